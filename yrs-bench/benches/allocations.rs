@@ -0,0 +1,150 @@
+//! Measures a number of bytes allocated while loading multi-megabyte documents. Backends returning
+//! values in buffers they own (i.e. memory-mapped pages in case of LMDB) let loaded document states
+//! be decoded in place, while the in-memory store copies every value it returns and serves as
+//! a baseline.
+
+use criterion::measurement::{Measurement, ValueFormatter};
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use yrs::{Doc, Transact};
+use yrs_bench::{backends_with_blob_threshold, large_state, next_name};
+
+/// Approximate sizes of loaded document states in bytes.
+const DOC_SIZES: [usize; 3] = [1 << 20, 4 << 20, 16 << 20];
+
+/// Blob threshold used by the `load_doc_blob` group: every benchmarked state is stored as a blob.
+const BLOB_THRESHOLD: usize = 64 * 1024;
+
+static ALLOCATED: AtomicUsize = AtomicUsize::new(0);
+
+/// System allocator counting all bytes allocated by this process.
+struct CountingAlloc;
+
+unsafe impl GlobalAlloc for CountingAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATED.fetch_add(layout.size(), Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATED.fetch_add(new_size.saturating_sub(layout.size()), Ordering::Relaxed);
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAlloc = CountingAlloc;
+
+/// Criterion measurement reporting bytes allocated instead of wall time.
+struct Allocated;
+
+impl Measurement for Allocated {
+    type Intermediate = usize;
+    type Value = usize;
+
+    fn start(&self) -> Self::Intermediate {
+        ALLOCATED.load(Ordering::SeqCst)
+    }
+
+    fn end(&self, start: Self::Intermediate) -> Self::Value {
+        ALLOCATED.load(Ordering::SeqCst) - start
+    }
+
+    fn add(&self, v1: &Self::Value, v2: &Self::Value) -> Self::Value {
+        v1 + v2
+    }
+
+    fn zero(&self) -> Self::Value {
+        0
+    }
+
+    fn to_f64(&self, value: &Self::Value) -> f64 {
+        *value as f64
+    }
+
+    fn formatter(&self) -> &dyn ValueFormatter {
+        &BytesFormatter
+    }
+}
+
+struct BytesFormatter;
+
+impl ValueFormatter for BytesFormatter {
+    fn scale_values(&self, typical_value: f64, values: &mut [f64]) -> &'static str {
+        let (factor, unit) = if typical_value < 1024.0 {
+            (1.0, "B")
+        } else if typical_value < 1024.0 * 1024.0 {
+            (1024.0, "KiB")
+        } else {
+            (1024.0 * 1024.0, "MiB")
+        };
+        for value in values.iter_mut() {
+            *value /= factor;
+        }
+        unit
+    }
+
+    fn scale_throughputs(
+        &self,
+        _typical_value: f64,
+        throughput: &Throughput,
+        values: &mut [f64],
+    ) -> &'static str {
+        // bytes allocated per byte of a loaded document state
+        if let Throughput::Bytes(bytes) = throughput {
+            for value in values.iter_mut() {
+                *value /= *bytes as f64;
+            }
+            "B/B"
+        } else {
+            "B"
+        }
+    }
+
+    fn scale_for_machines(&self, _values: &mut [f64]) -> &'static str {
+        "B"
+    }
+}
+
+fn bench(c: &mut Criterion<Allocated>) {
+    load_doc(c, "load_doc_inline", 0);
+    load_doc(c, "load_doc_blob", BLOB_THRESHOLD);
+}
+
+fn load_doc(c: &mut Criterion<Allocated>, group_name: &str, blob_threshold: usize) {
+    let mut group = c.benchmark_group(group_name);
+    for &size in DOC_SIZES.iter() {
+        let state = large_state(size);
+        group.throughput(Throughput::Bytes(state.len() as u64));
+        for backend in backends_with_blob_threshold(blob_threshold) {
+            let name = next_name();
+            backend.with_store(&mut |store| {
+                store.push_update(name.as_bytes(), &state).unwrap();
+                store.flush_doc(name.as_bytes()).unwrap();
+            });
+            let id = BenchmarkId::new(backend.name(), size >> 20);
+            group.bench_with_input(id, &name, |b, name| {
+                b.iter(|| {
+                    let doc = Doc::new();
+                    backend.with_store(&mut |store| {
+                        let mut txn = doc.transact_mut();
+                        assert!(store.load_doc(name.as_bytes(), &mut txn).unwrap());
+                    });
+                });
+            });
+        }
+    }
+    group.finish();
+}
+
+criterion_group! {
+    name = benches;
+    config = Criterion::default().with_measurement(Allocated).sample_size(10);
+    targets = bench,
+}
+criterion_main!(benches);
@@ -0,0 +1,146 @@
+use criterion::{criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion, Throughput};
+use yrs::{Doc, Transact};
+use yrs_bench::{
+    backends, next_name, read_trace, trace_state, trace_state_vector, trace_updates, TextOp,
+};
+
+/// Number of updates pushed by a single iteration of the push_update benchmark.
+const PUSHED_UPDATES: usize = 100;
+
+/// Numbers of pending updates of loaded documents.
+const PENDING_UPDATES: [usize; 4] = [0, 10, 100, 1000];
+
+/// Numbers of editing trace operations used to build documents of different sizes.
+const DOC_SIZES: [usize; 3] = [1_000, 10_000, 100_000];
+
+fn bench(c: &mut Criterion) {
+    let ops = read_trace();
+    push_update(c, &ops);
+    load_doc(c, &ops);
+    flush_doc(c, &ops);
+    get_diff(c, &ops);
+}
+
+fn push_update(c: &mut Criterion, ops: &[TextOp]) {
+    let updates = trace_updates(&Doc::new(), &ops[..PUSHED_UPDATES]);
+
+    let mut group = c.benchmark_group("push_update");
+    group.throughput(Throughput::Elements(updates.len() as u64));
+    for backend in backends() {
+        group.bench_function(backend.name(), |b| {
+            b.iter(|| {
+                let name = next_name();
+                for update in updates.iter() {
+                    backend.with_store(&mut |store| {
+                        store.push_update(name.as_bytes(), update).unwrap();
+                    });
+                }
+            });
+        });
+    }
+    group.finish();
+}
+
+fn load_doc(c: &mut Criterion, ops: &[TextOp]) {
+    // document state is built from the first part of the trace, pending updates from the rest
+    let base = DOC_SIZES[0];
+    let state = trace_state(&ops[..base]);
+    let max_pending = PENDING_UPDATES[PENDING_UPDATES.len() - 1];
+    let doc = Doc::new();
+    apply_state(&doc, &state);
+    let updates = trace_updates(&doc, &ops[base..(base + max_pending)]);
+
+    let mut group = c.benchmark_group("load_doc");
+    for backend in backends() {
+        for &pending in PENDING_UPDATES.iter() {
+            let name = next_name();
+            backend.with_store(&mut |store| {
+                store.push_update(name.as_bytes(), &state).unwrap();
+                store.flush_doc(name.as_bytes()).unwrap();
+                for update in updates[..pending].iter() {
+                    store.push_update(name.as_bytes(), update).unwrap();
+                }
+            });
+            let id = BenchmarkId::new(backend.name(), pending);
+            group.bench_with_input(id, &name, |b, name| {
+                b.iter(|| {
+                    let doc = Doc::new();
+                    backend.with_store(&mut |store| {
+                        let mut txn = doc.transact_mut();
+                        assert!(store.load_doc(name.as_bytes(), &mut txn).unwrap());
+                    });
+                });
+            });
+        }
+    }
+    group.finish();
+}
+
+fn flush_doc(c: &mut Criterion, ops: &[TextOp]) {
+    let mut group = c.benchmark_group("flush_doc");
+    for &size in DOC_SIZES.iter() {
+        // flushed document has its whole state stored as a single pending update
+        let state = trace_state(&ops[..size]);
+        for backend in backends() {
+            let id = BenchmarkId::new(backend.name(), size);
+            group.bench_with_input(id, &state, |b, state| {
+                b.iter_batched(
+                    || {
+                        let name = next_name();
+                        backend.with_store(&mut |store| {
+                            store.push_update(name.as_bytes(), state).unwrap();
+                        });
+                        name
+                    },
+                    |name| {
+                        backend.with_store(&mut |store| {
+                            store.flush_doc(name.as_bytes()).unwrap();
+                        });
+                    },
+                    BatchSize::PerIteration,
+                );
+            });
+        }
+    }
+    group.finish();
+}
+
+fn get_diff(c: &mut Criterion, ops: &[TextOp]) {
+    let mut group = c.benchmark_group("get_diff");
+    for &size in DOC_SIZES.iter() {
+        // remote peer has seen the first half of the document changes
+        let state = trace_state(&ops[..size]);
+        let sv = trace_state_vector(&ops[..(size / 2)]);
+        for backend in backends() {
+            let name = next_name();
+            backend.with_store(&mut |store| {
+                store.push_update(name.as_bytes(), &state).unwrap();
+                store.flush_doc(name.as_bytes()).unwrap();
+            });
+            let id = BenchmarkId::new(backend.name(), size);
+            group.bench_with_input(id, &name, |b, name| {
+                b.iter(|| {
+                    backend.with_store(&mut |store| {
+                        store.get_diff(name.as_bytes(), &sv).unwrap().unwrap();
+                    });
+                });
+            });
+        }
+    }
+    group.finish();
+}
+
+fn apply_state(doc: &Doc, state: &[u8]) {
+    use yrs::updates::decoder::Decode;
+    use yrs::Update;
+
+    let mut txn = doc.transact_mut();
+    txn.apply_update(Update::decode_v1(state).unwrap()).unwrap();
+}
+
+criterion_group! {
+    name = benches;
+    config = Criterion::default().sample_size(10);
+    targets = bench,
+}
+criterion_main!(benches);
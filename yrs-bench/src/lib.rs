@@ -0,0 +1,348 @@
+//! Shared harness of benchmarks comparing persistence backends. Every backend enabled with cargo
+//! features (`fs` and `sled` by default, `rocksdb` and `lmdb` optionally) together with the
+//! in-memory store is exposed as a [Backend], so that the same operations can be measured over
+//! all of them:
+//!
+//! ```text
+//! cargo bench -p yrs-bench --features rocksdb,lmdb
+//! ```
+//!
+//! Documents are built by replaying an editing trace (see: [read_trace]).
+//!
+//! Besides time, the `allocations` benchmark measures bytes allocated while loading multi-megabyte
+//! documents (see: [large_state]), comparing backends which let stored values be decoded in place
+//! with the in-memory store, which copies them.
+
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use yrs::encoding::read::{Cursor, Read};
+use yrs::updates::decoder::DecoderV1;
+use yrs::{Doc, ReadTxn, StateVector, Text, Transact};
+use yrs_kvstore::dynamic::DynDocStore;
+use yrs_kvstore::memory::MemKVStore;
+
+/// Path to the editing trace used to build benchmarked documents.
+pub const TRACE_PATH: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/../editing-trace.bin");
+
+/// A persistence backend, which benchmarked operations are executed over.
+pub trait Backend {
+    /// Name of the backend, used to identify benchmarks.
+    fn name(&self) -> &'static str;
+
+    /// Opens a store, calls a given function `f` with it and commits all changes it has made.
+    fn with_store(&self, f: &mut dyn FnMut(&dyn DynDocStore<'_>));
+}
+
+/// Returns all backends enabled with cargo features. Backends persisting data on disk use
+/// dedicated directories within a temporary directory, which are removed once they're dropped,
+/// so only a single set of backends can be used at the time.
+pub fn backends() -> Vec<Box<dyn Backend>> {
+    backends_with_blob_threshold(0)
+}
+
+/// Returns all backends enabled with cargo features just like [backends] does, storing document
+/// states of at least `threshold` bytes in a blob keyspace (see: [yrs_kvstore::blob]).
+pub fn backends_with_blob_threshold(threshold: usize) -> Vec<Box<dyn Backend>> {
+    let store = MemKVStore::new().with_blob_threshold(threshold);
+    let mut backends: Vec<Box<dyn Backend>> = vec![Box::new(Memory(store))];
+    #[cfg(feature = "fs")]
+    backends.push(Box::new(fs_store::Fs::open(threshold)));
+    #[cfg(feature = "sled")]
+    backends.push(Box::new(sled_store::Sled::open(threshold)));
+    #[cfg(feature = "rocksdb")]
+    backends.push(Box::new(rocksdb_store::RocksDB::open(threshold)));
+    #[cfg(feature = "lmdb")]
+    backends.push(Box::new(lmdb_store::Lmdb::open(threshold)));
+    backends
+}
+
+/// In-memory store, used as a baseline for other backends.
+pub struct Memory(MemKVStore);
+
+impl Backend for Memory {
+    fn name(&self) -> &'static str {
+        "memory"
+    }
+
+    fn with_store(&self, f: &mut dyn FnMut(&dyn DynDocStore<'_>)) {
+        f(&self.0)
+    }
+}
+
+/// Directory removed once dropped.
+struct TempDir(PathBuf);
+
+impl TempDir {
+    fn new(name: &str) -> Self {
+        let path = std::env::temp_dir().join(format!("yrs-bench-{}", name));
+        Self::cleanup(&path);
+        TempDir(path)
+    }
+
+    fn path(&self) -> &Path {
+        &self.0
+    }
+
+    fn cleanup(path: &Path) {
+        // if dir doesn't exists, ignore
+        let _ = std::fs::remove_dir_all(path);
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        Self::cleanup(&self.0);
+    }
+}
+
+#[cfg(feature = "fs")]
+mod fs_store {
+    use crate::{Backend, TempDir};
+    use yrs_fs::FsStore;
+    use yrs_kvstore::dynamic::DynDocStore;
+
+    pub struct Fs {
+        store: FsStore,
+        _dir: TempDir,
+    }
+
+    impl Fs {
+        pub fn open(blob_threshold: usize) -> Self {
+            let dir = TempDir::new("fs");
+            let store = FsStore::open(dir.path())
+                .unwrap()
+                .with_blob_threshold(blob_threshold);
+            Fs { store, _dir: dir }
+        }
+    }
+
+    impl Backend for Fs {
+        fn name(&self) -> &'static str {
+            "fs"
+        }
+
+        fn with_store(&self, f: &mut dyn FnMut(&dyn DynDocStore<'_>)) {
+            f(&self.store)
+        }
+    }
+}
+
+#[cfg(feature = "sled")]
+mod sled_store {
+    use crate::{Backend, TempDir};
+    use yrs_kvstore::dynamic::DynDocStore;
+    use yrs_sled::SledStore;
+
+    pub struct Sled {
+        store: SledStore,
+        _db: sled::Db,
+        _dir: TempDir,
+    }
+
+    impl Sled {
+        pub fn open(blob_threshold: usize) -> Self {
+            let dir = TempDir::new("sled");
+            let db = sled::open(dir.path()).unwrap();
+            let store =
+                SledStore::from(db.open_tree("yrs").unwrap()).with_blob_threshold(blob_threshold);
+            Sled {
+                store,
+                _db: db,
+                _dir: dir,
+            }
+        }
+    }
+
+    impl Backend for Sled {
+        fn name(&self) -> &'static str {
+            "sled"
+        }
+
+        fn with_store(&self, f: &mut dyn FnMut(&dyn DynDocStore<'_>)) {
+            f(&self.store)
+        }
+    }
+}
+
+#[cfg(feature = "rocksdb")]
+mod rocksdb_store {
+    use crate::{Backend, TempDir};
+    use rocksdb::TransactionDB;
+    use yrs_kvstore::dynamic::DynDocStore;
+    use yrs_rocksdb::RocksDBStore;
+
+    pub struct RocksDB {
+        db: TransactionDB,
+        blob_threshold: usize,
+        _dir: TempDir,
+    }
+
+    impl RocksDB {
+        pub fn open(blob_threshold: usize) -> Self {
+            let dir = TempDir::new("rocksdb");
+            let db = TransactionDB::open_default(dir.path()).unwrap();
+            RocksDB {
+                db,
+                blob_threshold,
+                _dir: dir,
+            }
+        }
+    }
+
+    impl Backend for RocksDB {
+        fn name(&self) -> &'static str {
+            "rocksdb"
+        }
+
+        fn with_store(&self, f: &mut dyn FnMut(&dyn DynDocStore<'_>)) {
+            let store =
+                RocksDBStore::from(self.db.transaction()).with_blob_threshold(self.blob_threshold);
+            f(&store);
+            store.commit().unwrap();
+        }
+    }
+}
+
+#[cfg(feature = "lmdb")]
+mod lmdb_store {
+    use crate::{Backend, TempDir};
+    use lmdb_rs::core::DbCreate;
+    use lmdb_rs::{DbHandle, Environment};
+    use yrs_kvstore::dynamic::DynDocStore;
+    use yrs_lmdb::LmdbStore;
+
+    pub struct Lmdb {
+        env: Environment,
+        handle: DbHandle,
+        blob_threshold: usize,
+        _dir: TempDir,
+    }
+
+    impl Lmdb {
+        pub fn open(blob_threshold: usize) -> Self {
+            let dir = TempDir::new("lmdb");
+            let env = Environment::new()
+                .autocreate_dir(true)
+                .map_size(4 * 1024 * 1024 * 1024)
+                .max_dbs(4)
+                .open(dir.path(), 0o777)
+                .unwrap();
+            let handle = env.create_db("yrs", DbCreate).unwrap();
+            Lmdb {
+                env,
+                handle,
+                blob_threshold,
+                _dir: dir,
+            }
+        }
+    }
+
+    impl Backend for Lmdb {
+        fn name(&self) -> &'static str {
+            "lmdb"
+        }
+
+        fn with_store(&self, f: &mut dyn FnMut(&dyn DynDocStore<'_>)) {
+            let txn = self.env.new_transaction().unwrap();
+            {
+                let store = LmdbStore::from(txn.bind(&self.handle))
+                    .with_blob_threshold(self.blob_threshold);
+                f(&store);
+            }
+            txn.commit().unwrap();
+        }
+    }
+}
+
+/// Returns a unique document name.
+pub fn next_name() -> String {
+    static NEXT: AtomicU64 = AtomicU64::new(0);
+    format!("doc-{}", NEXT.fetch_add(1, Ordering::Relaxed))
+}
+
+/// A single operation of the editing trace.
+#[derive(Debug, Clone)]
+pub enum TextOp {
+    Insert(u32, String),
+    Delete(u32, u32),
+}
+
+/// Reads the editing trace stored under [TRACE_PATH].
+pub fn read_trace() -> Vec<TextOp> {
+    let buf = std::fs::read(TRACE_PATH).unwrap();
+    let mut decoder = DecoderV1::new(Cursor::new(buf.as_slice()));
+    let len: usize = decoder.read_var().unwrap();
+    let mut result = Vec::with_capacity(len);
+    for _ in 0..len {
+        let op = match decoder.read_var().unwrap() {
+            1u32 => {
+                let idx = decoder.read_var().unwrap();
+                let chunk = decoder.read_string().unwrap();
+                TextOp::Insert(idx, chunk.to_string())
+            }
+            2u32 => {
+                let idx = decoder.read_var().unwrap();
+                let len = decoder.read_var().unwrap();
+                TextOp::Delete(idx, len)
+            }
+            other => panic!("unrecognized TextOp tag type: {}", other),
+        };
+        result.push(op);
+    }
+    result
+}
+
+/// Applies given `ops` to a document, one transaction per operation.
+pub fn apply_ops(doc: &Doc, ops: &[TextOp]) {
+    let text = doc.get_or_insert_text("text");
+    for op in ops.iter() {
+        let mut txn = doc.transact_mut();
+        match op {
+            TextOp::Insert(idx, txt) => text.insert(&mut txn, *idx, txt),
+            TextOp::Delete(idx, len) => text.remove_range(&mut txn, *idx, *len),
+        }
+    }
+}
+
+/// Applies given `ops` to a document and returns an update (lib0 v1 encoding) produced by every
+/// one of them.
+pub fn trace_updates(doc: &Doc, ops: &[TextOp]) -> Vec<Vec<u8>> {
+    let mut updates = Vec::with_capacity(ops.len());
+    for op in ops.iter() {
+        let sv = doc.transact().state_vector();
+        apply_ops(doc, std::slice::from_ref(op));
+        updates.push(doc.transact().encode_diff_v1(&sv));
+    }
+    updates
+}
+
+/// Returns a state (lib0 v1 encoding) of a document built from given `ops`.
+pub fn trace_state(ops: &[TextOp]) -> Vec<u8> {
+    let doc = Doc::new();
+    apply_ops(&doc, ops);
+    let state = doc.transact().encode_diff_v1(&StateVector::default());
+    state
+}
+
+/// Returns a state (lib0 v1 encoding) of a document containing a text of roughly `size` bytes.
+pub fn large_state(size: usize) -> Vec<u8> {
+    const PARAGRAPH: &str = "Lorem ipsum dolor sit amet, consectetur adipiscing elit. ";
+    let doc = Doc::new();
+    let text = doc.get_or_insert_text("text");
+    {
+        let mut txn = doc.transact_mut();
+        for _ in 0..(size / PARAGRAPH.len()) {
+            text.push(&mut txn, PARAGRAPH);
+        }
+    }
+    let state = doc.transact().encode_diff_v1(&StateVector::default());
+    state
+}
+
+/// Returns a state vector of a document built from given `ops`.
+pub fn trace_state_vector(ops: &[TextOp]) -> StateVector {
+    let doc = Doc::new();
+    apply_ops(&doc, ops);
+    let sv = doc.transact().state_vector();
+    sv
+}
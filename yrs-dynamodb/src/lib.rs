@@ -0,0 +1,440 @@
+use async_trait::async_trait;
+use aws_sdk_dynamodb::primitives::Blob;
+use aws_sdk_dynamodb::types::AttributeValue;
+use aws_sdk_dynamodb::{Client, Error};
+use std::collections::HashMap;
+use yrs_kvstore::asynchronous::{AsyncDocOps, AsyncKVStore};
+use yrs_kvstore::keys::{KEYSPACE_DOC, SUB_KEY_POS, V1};
+use yrs_kvstore::KVEntry;
+
+/// Name of the partition key attribute.
+pub const ATTR_PARTITION_KEY: &str = "pk";
+/// Name of the sort key attribute.
+pub const ATTR_SORT_KEY: &str = "sk";
+/// Name of the attribute storing entry value.
+pub const ATTR_VALUE: &str = "v";
+
+/// Default maximum size of a document state stored in a single item. DynamoDB limits the size of
+/// an item to 400KiB, including names and values of its attributes, so larger document states are
+/// split into chunks (see: [AsyncDocOps::doc_chunk_size]).
+pub const DEFAULT_DOC_CHUNK_SIZE: usize = 384 * 1024;
+
+/// DynamoDB doesn't accept empty key attributes, so every sort key starts with this byte.
+const SORT_KEY_MARKER: u8 = 1;
+
+type Item = HashMap<String, AttributeValue>;
+
+/// Persistence layer for Yrs documents build on top of DynamoDB table. Table must have a binary
+/// partition key named [ATTR_PARTITION_KEY] and a binary sort key named [ATTR_SORT_KEY].
+///
+/// Keys of document entries are split so that document OID becomes a partition key, while
+/// the remaining bytes become a sort key: this way all entries of a single document live in the
+/// same partition and can be range-queried in order. Other keyspaces (i.e. document name to OID
+/// mapping) are partitioned by their keyspace prefix. Keys outside of [V1] schema are partitioned
+/// by their first byte.
+///
+/// DynamoDB only keeps entries ordered within a partition, therefore:
+/// - [AsyncKVStore::iter_range] and [AsyncKVStore::remove_range] over a range spanning many
+///   partitions fall back to scanning a whole table.
+/// - [AsyncKVStore::peek_back] only looks for entries within a partition of a given key.
+///
+/// None of these limitations affect [AsyncDocOps], which never reaches outside of a single
+/// partition in those cases. Also keep in mind that DynamoDB has no multi-request transactions,
+/// so compound [AsyncDocOps] operations are not atomic.
+///
+/// ```rust,no_run
+/// use yrs::{Doc, Transact};
+/// use yrs_dynamodb::DynamoStore;
+/// use yrs_kvstore::asynchronous::AsyncDocOps;
+///
+/// async fn save(
+///     client: aws_sdk_dynamodb::Client,
+///     doc: &Doc,
+/// ) -> Result<(), yrs_kvstore::error::Error> {
+///     let store = DynamoStore::new(client, "yrs-docs");
+///     store.insert_doc("doc", &doc.transact()).await
+/// }
+/// ```
+#[derive(Debug, Clone)]
+pub struct DynamoStore {
+    client: Client,
+    table: String,
+    doc_chunk_size: usize,
+}
+
+impl DynamoStore {
+    /// Creates a new store persisting entries in a given DynamoDB `table`.
+    pub fn new<S: Into<String>>(client: Client, table: S) -> Self {
+        DynamoStore {
+            client,
+            table: table.into(),
+            doc_chunk_size: DEFAULT_DOC_CHUNK_SIZE,
+        }
+    }
+
+    /// Sets a maximum size in bytes of a document state stored in a single item, which defaults to
+    /// [DEFAULT_DOC_CHUNK_SIZE]. Larger states are split into chunks stored in separate items (see:
+    /// [AsyncDocOps::doc_chunk_size]). Chunking is disabled when set to 0.
+    pub fn with_doc_chunk_size(mut self, size: usize) -> Self {
+        self.doc_chunk_size = size;
+        self
+    }
+
+    /// Returns a reference to the underlying DynamoDB client.
+    pub fn client(&self) -> &Client {
+        &self.client
+    }
+
+    /// Returns the name of a table used by current store.
+    pub fn table(&self) -> &str {
+        &self.table
+    }
+
+    async fn query_partition(
+        &self,
+        pk: &[u8],
+        from: &[u8],
+        to: &[u8],
+    ) -> Result<Vec<DynamoEntry>, Error> {
+        let mut entries = Vec::new();
+        let mut start = None;
+        loop {
+            let output = self
+                .client
+                .query()
+                .table_name(&self.table)
+                .key_condition_expression("pk = :pk AND sk BETWEEN :from AND :to")
+                .expression_attribute_values(":pk", binary(pk))
+                .expression_attribute_values(":from", sort_key(from))
+                .expression_attribute_values(":to", sort_key(to))
+                .consistent_read(true)
+                .set_exclusive_start_key(start)
+                .send()
+                .await?;
+            entries.extend(output.items().iter().filter_map(DynamoEntry::from_item));
+            match output.last_evaluated_key() {
+                Some(key) => start = Some(key.clone()),
+                None => return Ok(entries),
+            }
+        }
+    }
+
+    async fn scan_table(&self, from: &[u8], to: &[u8]) -> Result<Vec<DynamoEntry>, Error> {
+        let mut entries = Vec::new();
+        let mut start = None;
+        loop {
+            let output = self
+                .client
+                .scan()
+                .table_name(&self.table)
+                .consistent_read(true)
+                .set_exclusive_start_key(start)
+                .send()
+                .await?;
+            entries.extend(
+                output
+                    .items()
+                    .iter()
+                    .filter_map(DynamoEntry::from_item)
+                    .filter(|e| e.key.as_slice() >= from && e.key.as_slice() <= to),
+            );
+            match output.last_evaluated_key() {
+                Some(key) => start = Some(key.clone()),
+                None => break,
+            }
+        }
+        entries.sort_by(|a, b| a.key.cmp(&b.key));
+        Ok(entries)
+    }
+}
+
+/// Splits a given key into partition and sort key parts.
+fn split_key(key: &[u8]) -> (&[u8], &[u8]) {
+    let pos = match key {
+        // document entries are partitioned by document OID
+        [V1, KEYSPACE_DOC, ..] if key.len() >= SUB_KEY_POS => SUB_KEY_POS,
+        // other entries are partitioned by their keyspace
+        [V1, _, ..] => 2,
+        _ => key.len().min(1),
+    };
+    key.split_at(pos)
+}
+
+fn binary(value: &[u8]) -> AttributeValue {
+    AttributeValue::B(Blob::new(value))
+}
+
+fn sort_key(rest: &[u8]) -> AttributeValue {
+    let mut sk = Vec::with_capacity(rest.len() + 1);
+    sk.push(SORT_KEY_MARKER);
+    sk.extend_from_slice(rest);
+    AttributeValue::B(Blob::new(sk))
+}
+
+fn attr<'i>(item: &'i Item, name: &str) -> Option<&'i [u8]> {
+    match item.get(name) {
+        Some(AttributeValue::B(blob)) => Some(blob.as_ref()),
+        _ => None,
+    }
+}
+
+#[async_trait(?Send)]
+impl<'a> AsyncKVStore<'a> for DynamoStore {
+    type Error = Error;
+    type Cursor = std::vec::IntoIter<DynamoEntry>;
+    type Entry = DynamoEntry;
+    type Return = Vec<u8>;
+
+    async fn get(&self, key: &[u8]) -> Result<Option<Self::Return>, Self::Error> {
+        let (pk, sk) = split_key(key);
+        let output = self
+            .client
+            .get_item()
+            .table_name(&self.table)
+            .key(ATTR_PARTITION_KEY, binary(pk))
+            .key(ATTR_SORT_KEY, sort_key(sk))
+            .consistent_read(true)
+            .send()
+            .await?;
+        Ok(output
+            .item()
+            .and_then(|item| attr(item, ATTR_VALUE))
+            .map(|value| value.to_vec()))
+    }
+
+    async fn upsert(&self, key: &[u8], value: &[u8]) -> Result<(), Self::Error> {
+        let (pk, sk) = split_key(key);
+        self.client
+            .put_item()
+            .table_name(&self.table)
+            .item(ATTR_PARTITION_KEY, binary(pk))
+            .item(ATTR_SORT_KEY, sort_key(sk))
+            .item(ATTR_VALUE, binary(value))
+            .send()
+            .await?;
+        Ok(())
+    }
+
+    async fn remove(&self, key: &[u8]) -> Result<(), Self::Error> {
+        let (pk, sk) = split_key(key);
+        self.client
+            .delete_item()
+            .table_name(&self.table)
+            .key(ATTR_PARTITION_KEY, binary(pk))
+            .key(ATTR_SORT_KEY, sort_key(sk))
+            .send()
+            .await?;
+        Ok(())
+    }
+
+    async fn remove_range(&self, from: &[u8], to: &[u8]) -> Result<(), Self::Error> {
+        for entry in self.iter_range(from, to).await? {
+            self.remove(&entry.key).await?;
+        }
+        Ok(())
+    }
+
+    async fn iter_range(&self, from: &[u8], to: &[u8]) -> Result<Self::Cursor, Self::Error> {
+        if from > to {
+            return Ok(Vec::new().into_iter());
+        }
+        let (from_pk, from_sk) = split_key(from);
+        let (to_pk, to_sk) = split_key(to);
+        let entries = if from_pk == to_pk {
+            self.query_partition(from_pk, from_sk, to_sk).await?
+        } else {
+            self.scan_table(from, to).await?
+        };
+        Ok(entries.into_iter())
+    }
+
+    async fn peek_back(&self, key: &[u8]) -> Result<Option<Self::Entry>, Self::Error> {
+        let (pk, sk) = split_key(key);
+        let output = self
+            .client
+            .query()
+            .table_name(&self.table)
+            .key_condition_expression("pk = :pk AND sk < :sk")
+            .expression_attribute_values(":pk", binary(pk))
+            .expression_attribute_values(":sk", sort_key(sk))
+            .scan_index_forward(false)
+            .limit(1)
+            .consistent_read(true)
+            .send()
+            .await?;
+        Ok(output.items().iter().find_map(DynamoEntry::from_item))
+    }
+}
+
+impl<'a> AsyncDocOps<'a> for DynamoStore {
+    fn doc_chunk_size(&self) -> usize {
+        self.doc_chunk_size
+    }
+}
+
+pub struct DynamoEntry {
+    key: Vec<u8>,
+    value: Vec<u8>,
+}
+
+impl DynamoEntry {
+    fn from_item(item: &Item) -> Option<Self> {
+        let pk = attr(item, ATTR_PARTITION_KEY)?;
+        let sk = attr(item, ATTR_SORT_KEY)?;
+        let value = attr(item, ATTR_VALUE)?;
+        let mut key = Vec::with_capacity(pk.len() + sk.len());
+        key.extend_from_slice(pk);
+        key.extend_from_slice(sk.get(1..)?);
+        Some(DynamoEntry {
+            key,
+            value: value.to_vec(),
+        })
+    }
+}
+
+impl Into<(Vec<u8>, Vec<u8>)> for DynamoEntry {
+    fn into(self) -> (Vec<u8>, Vec<u8>) {
+        (self.key, self.value)
+    }
+}
+
+impl KVEntry for DynamoEntry {
+    fn key(&self) -> &[u8] {
+        &self.key
+    }
+
+    fn value(&self) -> &[u8] {
+        &self.value
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{split_key, DynamoStore, ATTR_PARTITION_KEY, ATTR_SORT_KEY};
+    use aws_sdk_dynamodb::config::{BehaviorVersion, Credentials, Region};
+    use aws_sdk_dynamodb::types::{
+        AttributeDefinition, BillingMode, KeySchemaElement, KeyType, ScalarAttributeType,
+    };
+    use aws_sdk_dynamodb::Client;
+    use yrs::{Doc, GetString, ReadTxn, Text, Transact};
+    use yrs_kvstore::asynchronous::{AsyncDocOps, AsyncKVStore};
+    use yrs_kvstore::keys::{key_doc, key_meta_start, key_oid, KEY_OID_COUNTER};
+    use yrs_kvstore::KVEntry;
+
+    /// Tests require a running DynamoDB (or DynamoDB Local) instance. Its address can be provided
+    /// using `DYNAMODB_ENDPOINT` environment variable.
+    async fn init_store(table: &str) -> DynamoStore {
+        let endpoint = std::env::var("DYNAMODB_ENDPOINT")
+            .unwrap_or_else(|_| "http://127.0.0.1:8000".to_string());
+        let config = aws_sdk_dynamodb::Config::builder()
+            .behavior_version(BehaviorVersion::latest())
+            .endpoint_url(endpoint)
+            .region(Region::new("us-east-1"))
+            .credentials_provider(Credentials::new("test", "test", None, None, "test"))
+            .build();
+        let client = Client::from_conf(config);
+        let _ = client.delete_table().table_name(table).send().await;
+        let attr = |name: &str| {
+            AttributeDefinition::builder()
+                .attribute_name(name)
+                .attribute_type(ScalarAttributeType::B)
+                .build()
+                .unwrap()
+        };
+        let key = |name: &str, key_type: KeyType| {
+            KeySchemaElement::builder()
+                .attribute_name(name)
+                .key_type(key_type)
+                .build()
+                .unwrap()
+        };
+        client
+            .create_table()
+            .table_name(table)
+            .attribute_definitions(attr(ATTR_PARTITION_KEY))
+            .attribute_definitions(attr(ATTR_SORT_KEY))
+            .key_schema(key(ATTR_PARTITION_KEY, KeyType::Hash))
+            .key_schema(key(ATTR_SORT_KEY, KeyType::Range))
+            .billing_mode(BillingMode::PayPerRequest)
+            .send()
+            .await
+            .unwrap();
+        DynamoStore::new(client, table)
+    }
+
+    #[test]
+    fn key_partitioning() {
+        let oid_key = key_oid(b"doc");
+        assert_eq!(split_key(&oid_key).0, &oid_key[..2]);
+        assert_eq!(split_key(&KEY_OID_COUNTER).0, &KEY_OID_COUNTER[..2]);
+        // all entries of the same document share the same partition
+        let doc_key = key_doc(1);
+        let (pk, sk) = split_key(&doc_key);
+        assert_eq!(pk, split_key(&key_meta_start(1)).0);
+        assert_ne!(pk, split_key(&key_doc(2)).0);
+        assert_eq!([pk, sk].concat(), doc_key.as_ref());
+    }
+
+    #[tokio::test]
+    #[ignore]
+    async fn range_queries() {
+        let store = init_store("yrs-range-queries").await;
+        for i in 0u8..10 {
+            store.upsert(&[1, i], &[i]).await.unwrap();
+        }
+        store.upsert(&[2], &[10]).await.unwrap();
+
+        let values: Vec<_> = store
+            .iter_range(&[1, 2], &[1, 5])
+            .await
+            .unwrap()
+            .map(|e| e.value().to_vec())
+            .collect();
+        assert_eq!(values, vec![vec![2], vec![3], vec![4], vec![5]]);
+
+        // range spanning many partitions
+        let values: Vec<_> = store
+            .iter_range(&[1, 8], &[2])
+            .await
+            .unwrap()
+            .map(|e| e.value().to_vec())
+            .collect();
+        assert_eq!(values, vec![vec![8], vec![9], vec![10]]);
+
+        let last = store.peek_back(&[1, 7]).await.unwrap().unwrap();
+        assert_eq!(last.key(), &[1, 6]);
+
+        store.remove_range(&[1, 0], &[1, 8]).await.unwrap();
+        let remaining = store.iter_range(&[1], &[2]).await.unwrap().count();
+        assert_eq!(remaining, 2);
+    }
+
+    #[tokio::test]
+    #[ignore]
+    async fn create_get_remove() {
+        let store = init_store("yrs-create-get-remove").await;
+        let doc = Doc::new();
+        let text = doc.get_or_insert_text("text");
+        text.push(&mut doc.transact_mut(), "hello");
+        store.insert_doc("doc", &doc.transact()).await.unwrap();
+
+        let sv = doc.transact().state_vector();
+        text.push(&mut doc.transact_mut(), " world");
+        let update = doc.transact().encode_diff_v1(&sv);
+        let clock = store.push_update("doc", &update).await.unwrap();
+        assert_eq!(clock, 1);
+
+        let loaded = Doc::new();
+        let loaded_text = loaded.get_or_insert_text("text");
+        let found = store
+            .load_doc("doc", &mut loaded.transact_mut())
+            .await
+            .unwrap();
+        assert!(found);
+        assert_eq!(loaded_text.get_string(&loaded.transact()), "hello world");
+
+        store.clear_doc("doc").await.unwrap();
+        let (sv, _) = store.get_state_vector("doc").await.unwrap();
+        assert!(sv.is_none());
+    }
+}
@@ -0,0 +1,277 @@
+use async_trait::async_trait;
+use foundationdb::options::StreamingMode;
+use foundationdb::{
+    Database, FdbBindingError, FdbError, KeySelector, RangeOption, RetryableTransaction,
+};
+use futures::TryStreamExt;
+use std::cell::RefCell;
+use std::future::Future;
+use yrs_kvstore::asynchronous::{AsyncDocOps, AsyncKVStore};
+use yrs_kvstore::error::{find, Error};
+use yrs_kvstore::KVEntry;
+
+/// Persistence layer for Yrs documents build on top of FoundationDB transaction. All reads are
+/// serializable, so that concurrent modifications of the same document made by other transactions
+/// cause a conflict on commit.
+///
+/// Since [AsyncDocOps] operations read and write many keys, they should be executed within
+/// a retry loop (see: [run]), which retries the whole operation on conflicts and other transient
+/// FoundationDB errors.
+#[derive(Clone)]
+pub struct FdbStore {
+    txn: RetryableTransaction,
+}
+
+impl FdbStore {
+    /// Returns a handle to the underlying FoundationDB transaction.
+    pub fn transaction(&self) -> &RetryableTransaction {
+        &self.txn
+    }
+}
+
+impl From<RetryableTransaction> for FdbStore {
+    fn from(txn: RetryableTransaction) -> Self {
+        FdbStore { txn }
+    }
+}
+
+/// Runs a given closure over [FdbStore] within FoundationDB retryable transaction. Transaction is
+/// committed once the closure has completed successfully. If closure (or commit) failed with
+/// a retryable [FdbError], i.e. because of a conflict with another transaction, the closure is
+/// called again over a new transaction. Other errors are returned as they are.
+///
+/// Since the closure may be called many times, it should not have side effects other than the
+/// ones made through provided store. Keep in mind that a transaction, which failed to commit, may
+/// still have been committed (i.e. in case of network failure), so non-idempotent operations like
+/// [AsyncDocOps::push_update] may be applied twice.
+///
+/// ```rust,no_run
+/// use yrs::{Doc, Transact};
+/// use yrs_kvstore::asynchronous::AsyncDocOps;
+///
+/// async fn save(db: &foundationdb::Database, doc: &Doc) -> Result<(), yrs_kvstore::error::Error> {
+///     yrs_foundationdb::run(db, |store| async move {
+///         store.insert_doc("doc", &doc.transact()).await
+///     })
+///     .await
+/// }
+/// ```
+pub async fn run<F, Fut, T>(db: &Database, f: F) -> Result<T, Error>
+where
+    F: Fn(FdbStore) -> Fut,
+    Fut: Future<Output = Result<T, Error>>,
+{
+    // non-FDB errors are not retried: they are stashed here, so that they can be returned as they
+    // are once the retry loop has been aborted
+    let failed: RefCell<Option<Error>> = RefCell::new(None);
+    let result = db
+        .run(|txn, _maybe_committed| {
+            let fut = f(FdbStore::from(txn));
+            let failed = &failed;
+            async move {
+                match fut.await {
+                    Ok(value) => Ok(value),
+                    Err(e) => match find::<FdbError>(&e) {
+                        Some(fdb) => Err(FdbBindingError::from(*fdb)),
+                        None => {
+                            let message = e.to_string();
+                            failed.replace(Some(e));
+                            Err(FdbBindingError::CustomError(message.into()))
+                        }
+                    },
+                }
+            }
+        })
+        .await;
+    match result {
+        Ok(value) => Ok(value),
+        Err(e) => match failed.into_inner() {
+            Some(e) => Err(e),
+            None => Err(e.into()),
+        },
+    }
+}
+
+/// Returns the first key following a given `key` in lexicographical order.
+fn key_after(key: &[u8]) -> Vec<u8> {
+    let mut next = Vec::with_capacity(key.len() + 1);
+    next.extend_from_slice(key);
+    next.push(0);
+    next
+}
+
+#[async_trait(?Send)]
+impl<'a> AsyncKVStore<'a> for FdbStore {
+    type Error = FdbError;
+    type Cursor = std::vec::IntoIter<FdbEntry>;
+    type Entry = FdbEntry;
+    type Return = Vec<u8>;
+
+    async fn get(&self, key: &[u8]) -> Result<Option<Self::Return>, Self::Error> {
+        let value = self.txn.get(key, false).await?;
+        Ok(value.map(|value| value.to_vec()))
+    }
+
+    async fn upsert(&self, key: &[u8], value: &[u8]) -> Result<(), Self::Error> {
+        self.txn.set(key, value);
+        Ok(())
+    }
+
+    async fn remove(&self, key: &[u8]) -> Result<(), Self::Error> {
+        self.txn.clear(key);
+        Ok(())
+    }
+
+    async fn remove_range(&self, from: &[u8], to: &[u8]) -> Result<(), Self::Error> {
+        // FoundationDB range end is exclusive
+        self.txn.clear_range(from, &key_after(to));
+        Ok(())
+    }
+
+    async fn iter_range(&self, from: &[u8], to: &[u8]) -> Result<Self::Cursor, Self::Error> {
+        let mut opt = RangeOption::from((
+            KeySelector::first_greater_or_equal(from.to_vec()),
+            KeySelector::first_greater_than(to.to_vec()),
+        ));
+        opt.mode = StreamingMode::WantAll;
+        let entries: Vec<FdbEntry> = self
+            .txn
+            .get_ranges_keyvalues(opt, false)
+            .map_ok(|kv| FdbEntry::new(kv.key().to_vec(), kv.value().to_vec()))
+            .try_collect()
+            .await?;
+        Ok(entries.into_iter())
+    }
+
+    async fn peek_back(&self, key: &[u8]) -> Result<Option<Self::Entry>, Self::Error> {
+        let mut opt = RangeOption::from((
+            KeySelector::first_greater_or_equal(Vec::new()),
+            KeySelector::first_greater_or_equal(key.to_vec()),
+        ));
+        opt.limit = Some(1);
+        opt.reverse = true;
+        let values = self.txn.get_range(&opt, 1, false).await?;
+        Ok(values
+            .iter()
+            .next()
+            .map(|kv| FdbEntry::new(kv.key().to_vec(), kv.value().to_vec())))
+    }
+}
+
+impl<'a> AsyncDocOps<'a> for FdbStore {}
+
+pub struct FdbEntry {
+    key: Vec<u8>,
+    value: Vec<u8>,
+}
+
+impl FdbEntry {
+    fn new(key: Vec<u8>, value: Vec<u8>) -> Self {
+        FdbEntry { key, value }
+    }
+}
+
+impl Into<(Vec<u8>, Vec<u8>)> for FdbEntry {
+    fn into(self) -> (Vec<u8>, Vec<u8>) {
+        (self.key, self.value)
+    }
+}
+
+impl KVEntry for FdbEntry {
+    fn key(&self) -> &[u8] {
+        &self.key
+    }
+
+    fn value(&self) -> &[u8] {
+        &self.value
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::run;
+    use foundationdb::Database;
+    use futures::executor::block_on;
+    use std::sync::Once;
+    use yrs::{Doc, GetString, ReadTxn, Text, Transact};
+    use yrs_kvstore::asynchronous::{AsyncDocOps, AsyncKVStore};
+
+    static NETWORK: Once = Once::new();
+
+    /// Tests require a running FoundationDB cluster. Path to its cluster file can be provided
+    /// using `FDB_CLUSTER_FILE` environment variable.
+    fn init_db() -> Database {
+        NETWORK.call_once(|| {
+            // network thread must be started only once and live until the process ends
+            let network = unsafe { foundationdb::boot() };
+            std::mem::forget(network);
+        });
+        let cluster_file = std::env::var("FDB_CLUSTER_FILE").ok();
+        let db = Database::new(cluster_file.as_deref()).unwrap();
+        // clear all entries, leaving the system keyspace (`\xff` prefix) untouched
+        block_on(run(&db, |store| async move {
+            store.remove_range(&[0], &[254]).await?;
+            Ok(())
+        }))
+        .unwrap();
+        db
+    }
+
+    #[test]
+    #[ignore]
+    fn create_get_remove() {
+        let db = init_db();
+        let doc = Doc::new();
+        let text = doc.get_or_insert_text("text");
+        text.push(&mut doc.transact_mut(), "hello");
+        block_on(run(&db, |store| {
+            let doc = &doc;
+            async move { store.insert_doc("doc", &doc.transact()).await }
+        }))
+        .unwrap();
+
+        let sv = doc.transact().state_vector();
+        text.push(&mut doc.transact_mut(), " world");
+        let update = doc.transact().encode_diff_v1(&sv);
+        let clock = block_on(run(&db, |store| {
+            let update = &update;
+            async move { store.push_update("doc", update).await }
+        }))
+        .unwrap();
+        assert_eq!(clock, 1);
+
+        let loaded = Doc::new();
+        let loaded_text = loaded.get_or_insert_text("text");
+        let found = block_on(run(&db, |store| {
+            let loaded = &loaded;
+            async move { store.load_doc("doc", &mut loaded.transact_mut()).await }
+        }))
+        .unwrap();
+        assert!(found);
+        assert_eq!(loaded_text.get_string(&loaded.transact()), "hello world");
+
+        block_on(run(
+            &db,
+            |store| async move { store.clear_doc("doc").await },
+        ))
+        .unwrap();
+        let found = block_on(run(&db, |store| async move {
+            Ok(store.get_state_vector("doc").await?.0.is_some())
+        }))
+        .unwrap();
+        assert!(!found);
+    }
+
+    #[test]
+    #[ignore]
+    fn custom_errors_are_not_retried() {
+        let db = init_db();
+        let attempts = std::cell::Cell::new(0);
+        let result: Result<(), _> = block_on(run(&db, |_| {
+            attempts.set(attempts.get() + 1);
+            async move { Err("aborted".into()) }
+        }));
+        assert_eq!(result.unwrap_err().to_string(), "aborted");
+        assert_eq!(attempts.get(), 1);
+    }
+}
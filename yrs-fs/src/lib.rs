@@ -0,0 +1,617 @@
+use std::fs::File;
+use std::io::{ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use yrs_kvstore::codec::{CodecChain, ValueCodec};
+use yrs_kvstore::compression::Compression;
+use yrs_kvstore::export::JsonExport;
+use yrs_kvstore::keys::{
+    update_clock, ENCODING_V1, ENCODING_V2, KEYSPACE_DOC, OID_LEN, SUB_DOC, SUB_KEY_POS, SUB_META,
+    SUB_STATE_VEC, SUB_UPDATE, TERMINATOR, V1,
+};
+use yrs_kvstore::metrics::{Metrics, StoreMetrics};
+use yrs_kvstore::observer::{UpdateObservers, WriteHook};
+use yrs_kvstore::options::StoreOptions;
+use yrs_kvstore::validation::UpdateValidator;
+use yrs_kvstore::{
+    CompactionPolicy, DocOps, KVEntry, KVStore, OidAllocation, RetentionPolicy, UpdateLimits,
+};
+
+const DIR_DOCS: &str = "docs";
+const DIR_KEYS: &str = "keys";
+const DIR_UPDATES: &str = "updates";
+const DIR_META: &str = "meta";
+const DIR_OTHER: &str = "other";
+const FILE_STATE: &str = "state";
+const FILE_STATE_VECTOR: &str = "state_vector";
+const SUFFIX_V2: &str = ".v2";
+
+/// Persistence layer for Yrs documents build on top of plain files. It's meant for simple
+/// single-node deployments and for debugging, as persisted data can be inspected directly.
+///
+/// Every entry is stored in a separate file. Entries of each document live in their own directory
+/// named after document OID:
+///
+/// ```text
+/// {root}/docs/{oid}/state             document state
+/// {root}/docs/{oid}/state_vector      document state vector
+/// {root}/docs/{oid}/updates/{clock}   pending updates, `.v2` suffix marks lib0 v2 encoding
+/// {root}/docs/{oid}/meta/{name}       metadata entries
+/// {root}/docs/{oid}/other/{hex}       other document entries (i.e. snapshots)
+/// {root}/keys/{hex}                   entries of other keyspaces (i.e. document names)
+/// ```
+///
+/// Metadata names keep lowercase ASCII letters, digits, `-` and `_` as they are, while other
+/// bytes are escaped as `%XX`. Files are written atomically by renaming a temporary file in place
+/// of the target one, so a crash never leaves a partially written entry behind. However, there's
+/// no locking: a directory must not be used by many stores at the same time, and write batches
+/// (see: [KVStore::commit_batch]) are applied one entry at the time.
+pub struct FsStore {
+    root: PathBuf,
+    options: StoreOptions,
+    update_observers: UpdateObservers,
+    metrics: Metrics,
+}
+
+impl FsStore {
+    /// Opens a store persisting its entries inside of a given `root` directory. Directory is
+    /// created if it didn't exist.
+    pub fn open<P: AsRef<Path>>(root: P) -> std::io::Result<Self> {
+        let root = root.as_ref().to_path_buf();
+        std::fs::create_dir_all(&root)?;
+        Ok(FsStore {
+            root,
+            options: StoreOptions::default(),
+            update_observers: UpdateObservers::default(),
+            metrics: Metrics::default(),
+        })
+    }
+
+    /// Sets [StoreOptions] used to configure current store, replacing all settings set so far.
+    ///
+    /// # Panics
+    ///
+    /// Panics if options can't be applied without wrapping the store with
+    /// [yrs_kvstore::options::ConfiguredStore] (see: [StoreOptions::assert_unscoped]).
+    pub fn with_options(mut self, options: StoreOptions) -> Self {
+        options.assert_unscoped();
+        self.options = options;
+        self
+    }
+
+    /// Sets a [CompactionPolicy] used to automatically flush documents, which have accumulated
+    /// too many pending updates.
+    pub fn with_compaction_policy(mut self, policy: CompactionPolicy) -> Self {
+        self.options.compaction_policy = policy;
+        self
+    }
+
+    /// Sets an [UpdateObservers] registry notified about every persisted document update (see:
+    /// [DocOps::observe_updates]). The same registry can be shared by multiple stores.
+    pub fn with_update_observers(mut self, observers: UpdateObservers) -> Self {
+        self.update_observers = observers;
+        self
+    }
+
+    /// Sets a [Compression] applied to document states and updates written by current store.
+    pub fn with_compression(mut self, compression: Compression) -> Self {
+        self.options.compression = compression;
+        self
+    }
+
+    /// Sets an [OidAllocation] strategy used to assign OIDs to documents persisted for the first
+    /// time by current store.
+    pub fn with_oid_allocation(mut self, allocation: OidAllocation) -> Self {
+        self.options.oid_allocation = allocation;
+        self
+    }
+
+    /// Enables or disables recording document changes in a global change log (see:
+    /// [DocOps::iter_changes]).
+    pub fn with_changelog(mut self, enabled: bool) -> Self {
+        self.options.changelog = enabled;
+        self
+    }
+
+    /// Sets a [Metrics] receiver, which current store reports counts and latencies of its
+    /// operations into (see: [DocOps::metrics]).
+    pub fn with_metrics(mut self, metrics: Metrics) -> Self {
+        self.metrics = metrics;
+        self
+    }
+
+    /// Sets a number of recently pushed updates of each document, which are remembered in order to
+    /// skip persisting byte-identical updates pushed again, i.e. by retrying clients (see:
+    /// [StoreOptions::update_dedup_window]). Deduplication is disabled when set to 0.
+    pub fn with_update_dedup(mut self, window: usize) -> Self {
+        self.options.update_dedup_window = window;
+        self
+    }
+
+    /// Sets a size in bytes, starting from which document states are stored only once in
+    /// a content addressed blob keyspace and referenced from their documents (see:
+    /// [StoreOptions::blob_threshold]). Blob storage is disabled when set to 0.
+    pub fn with_blob_threshold(mut self, threshold: usize) -> Self {
+        self.options.blob_threshold = threshold;
+        self
+    }
+
+    /// Sets a [ValueCodec] used to encode document states, updates and metadata persisted by
+    /// current store (see: [StoreOptions::codecs]). It replaces a [Compression] set on the store.
+    pub fn with_value_codec(mut self, codec: Arc<dyn ValueCodec>) -> Self {
+        self.options.codecs = CodecChain::from(codec);
+        self
+    }
+
+    /// Enables or disables moving documents removed by [DocOps::clear_doc] into a trash, from
+    /// which they can be restored (see: [StoreOptions::soft_delete]).
+    pub fn with_soft_delete(mut self, enabled: bool) -> Self {
+        self.options.soft_delete = enabled;
+        self
+    }
+
+    /// Enables or disables recording timestamps of pushed updates (see:
+    /// [StoreOptions::update_timestamps]).
+    pub fn with_update_timestamps(mut self, enabled: bool) -> Self {
+        self.options.update_timestamps = enabled;
+        self
+    }
+
+    /// Sets a [RetentionPolicy] used to determine which pending updates are merged into the
+    /// document state by [DocOps::enforce_retention].
+    pub fn with_retention_policy(mut self, policy: RetentionPolicy) -> Self {
+        self.options.retention_policy = policy;
+        self
+    }
+
+    /// Sets [UpdateLimits] used to reject oversized updates before they are written (see:
+    /// [StoreOptions::update_limits]).
+    pub fn with_update_limits(mut self, limits: UpdateLimits) -> Self {
+        self.options.update_limits = limits;
+        self
+    }
+
+    /// Sets an [UpdateValidator] called with every update and document state before it's
+    /// persisted, which can veto the write (see: [StoreOptions::update_validator]).
+    pub fn with_update_validator(mut self, validator: Arc<dyn UpdateValidator>) -> Self {
+        self.options.update_validator = Some(validator);
+        self
+    }
+
+    /// Sets a [WriteHook] called with a decoded summary of every change written by current store
+    /// (see: [StoreOptions::write_hook]).
+    pub fn with_write_hook(mut self, hook: Arc<dyn WriteHook>) -> Self {
+        self.options.write_hook = Some(hook);
+        self
+    }
+
+    /// Sets a [JsonExport] used to maintain a cached JSON rendering of documents refreshed every
+    /// time they are flushed (see: [StoreOptions::json_export]).
+    pub fn with_json_export(mut self, export: JsonExport) -> Self {
+        self.options.json_export = Some(Arc::new(export));
+        self
+    }
+
+    /// Returns a root directory of current store.
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Returns a path of the file, which stores an entry with a given `key`. File may not exist.
+    pub fn entry_path(&self, key: &[u8]) -> PathBuf {
+        self.root.join(key_path(key))
+    }
+
+    fn read(&self, key: &[u8]) -> std::io::Result<Option<Vec<u8>>> {
+        match std::fs::read(self.entry_path(key)) {
+            Ok(value) => Ok(Some(value)),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Returns keys of all entries stored within a given directory (relative to the store root).
+    fn keys_within(&self, dir: &Path) -> std::io::Result<Vec<Vec<u8>>> {
+        let mut files = Vec::new();
+        self.walk(dir, &mut files)?;
+        Ok(files.iter().filter_map(|path| path_key(path)).collect())
+    }
+
+    fn walk(&self, dir: &Path, files: &mut Vec<PathBuf>) -> std::io::Result<()> {
+        let entries = match std::fs::read_dir(self.root.join(dir)) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(()),
+            Err(e) => return Err(e),
+        };
+        for entry in entries {
+            let entry = entry?;
+            let name = entry.file_name();
+            if name.to_string_lossy().starts_with('.') {
+                // skip temporary files
+                continue;
+            }
+            let path = dir.join(name);
+            if entry.file_type()?.is_dir() {
+                self.walk(&path, files)?;
+            } else {
+                files.push(path);
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns ordered keys of all entries within `from`..=`to` range.
+    fn keys_in_range(&self, from: &[u8], to: &[u8]) -> std::io::Result<Vec<Vec<u8>>> {
+        let dir = match (doc_prefix(from), doc_prefix(to)) {
+            // all keys between two keys of the same document belong to that document as well
+            (Some(a), Some(b)) if a == b => doc_dir(a),
+            _ => PathBuf::new(),
+        };
+        let mut keys: Vec<_> = self
+            .keys_within(&dir)?
+            .into_iter()
+            .filter(|key| key.as_slice() >= from && key.as_slice() <= to)
+            .collect();
+        keys.sort();
+        Ok(keys)
+    }
+
+    /// Removes empty directories left after removing a file under a given `path`.
+    fn prune(&self, path: &Path) {
+        for dir in path.ancestors().skip(1) {
+            if dir == self.root
+                || dir == self.root.join(DIR_DOCS)
+                || dir == self.root.join(DIR_KEYS)
+            {
+                break;
+            }
+            if std::fs::remove_dir(dir).is_err() {
+                // directory is not empty
+                break;
+            }
+        }
+    }
+}
+
+/// Returns a prefix shared by all keys of the same document, if a given `key` belongs to
+/// a document keyspace.
+fn doc_prefix(key: &[u8]) -> Option<&[u8]> {
+    if key.len() > SUB_KEY_POS && key[0] == V1 && key[1] == KEYSPACE_DOC {
+        Some(&key[..SUB_KEY_POS])
+    } else {
+        None
+    }
+}
+
+fn doc_dir(prefix: &[u8]) -> PathBuf {
+    Path::new(DIR_DOCS).join(to_hex(&prefix[2..]))
+}
+
+/// Maps a given `key` onto a path of a file (relative to the store root), which stores its value.
+fn key_path(key: &[u8]) -> PathBuf {
+    let prefix = match doc_prefix(key) {
+        Some(prefix) => prefix,
+        None => return Path::new(DIR_KEYS).join(to_hex(key)),
+    };
+    let dir = doc_dir(prefix);
+    let sub = &key[SUB_KEY_POS..];
+    match sub {
+        [SUB_DOC] => dir.join(FILE_STATE),
+        [SUB_STATE_VEC] => dir.join(FILE_STATE_VECTOR),
+        [SUB_UPDATE, _, _, _, _, ENCODING_V1] => dir
+            .join(DIR_UPDATES)
+            .join(format!("{:010}", update_clock(key))),
+        [SUB_UPDATE, _, _, _, _, ENCODING_V2] => {
+            let clock = update_clock(key);
+            dir.join(DIR_UPDATES)
+                .join(format!("{:010}{}", clock, SUFFIX_V2))
+        }
+        [SUB_META, name @ .., TERMINATOR] if !name.is_empty() => {
+            dir.join(DIR_META).join(escape(name))
+        }
+        _ => dir.join(DIR_OTHER).join(to_hex(sub)),
+    }
+}
+
+/// Maps a path of a file (relative to the store root) back onto a key it stores value of.
+/// Returns `None` if the path doesn't belong to any key.
+fn path_key(path: &Path) -> Option<Vec<u8>> {
+    let parts = path
+        .iter()
+        .map(|part| part.to_str())
+        .collect::<Option<Vec<_>>>()?;
+    match parts.as_slice() {
+        [DIR_KEYS, hex] => from_hex(hex),
+        [DIR_DOCS, oid, rest @ ..] => {
+            let oid = from_hex(oid).filter(|oid| oid.len() == OID_LEN)?;
+            let mut key = vec![V1, KEYSPACE_DOC];
+            key.extend_from_slice(&oid);
+            match rest {
+                [FILE_STATE] => key.push(SUB_DOC),
+                [FILE_STATE_VECTOR] => key.push(SUB_STATE_VEC),
+                [DIR_UPDATES, name] => {
+                    let (clock, encoding) = match name.strip_suffix(SUFFIX_V2) {
+                        Some(clock) => (clock, ENCODING_V2),
+                        None => (*name, ENCODING_V1),
+                    };
+                    if clock.len() != 10 {
+                        return None;
+                    }
+                    let clock: u32 = clock.parse().ok()?;
+                    key.push(SUB_UPDATE);
+                    key.extend_from_slice(&clock.to_be_bytes());
+                    key.push(encoding);
+                }
+                [DIR_META, name] => {
+                    key.push(SUB_META);
+                    key.extend_from_slice(&unescape(name)?);
+                    key.push(TERMINATOR);
+                }
+                [DIR_OTHER, hex] => key.extend_from_slice(&from_hex(hex)?),
+                _ => return None,
+            }
+            Some(key)
+        }
+        _ => None,
+    }
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn from_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) || !hex.is_ascii() {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).ok())
+        .collect()
+}
+
+/// Escapes a metadata name, so that it can be safely used as a file name, also on case
+/// insensitive file systems.
+fn escape(name: &[u8]) -> String {
+    let mut escaped = String::with_capacity(name.len());
+    for &b in name {
+        if b.is_ascii_lowercase() || b.is_ascii_digit() || b == b'-' || b == b'_' {
+            escaped.push(b as char);
+        } else {
+            escaped.push_str(&format!("%{:02X}", b));
+        }
+    }
+    escaped
+}
+
+fn unescape(name: &str) -> Option<Vec<u8>> {
+    let bytes = name.as_bytes();
+    let mut result = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            let hex = name.get(i + 1..i + 3)?;
+            result.push(u8::from_str_radix(hex, 16).ok()?);
+            i += 3;
+        } else {
+            result.push(bytes[i]);
+            i += 1;
+        }
+    }
+    Some(result)
+}
+
+impl<'a> DocOps<'a> for FsStore {
+    fn options(&self) -> &StoreOptions {
+        &self.options
+    }
+
+    fn update_observers(&self) -> Option<&UpdateObservers> {
+        Some(&self.update_observers)
+    }
+
+    fn metrics(&self) -> Option<&dyn StoreMetrics> {
+        self.metrics.get()
+    }
+}
+
+impl<'a> KVStore<'a> for FsStore {
+    type Error = std::io::Error;
+    type Cursor = std::vec::IntoIter<FsEntry>;
+    type Entry = FsEntry;
+    type Return = Vec<u8>;
+
+    fn get(&self, key: &[u8]) -> Result<Option<Self::Return>, Self::Error> {
+        self.read(key)
+    }
+
+    fn upsert(&self, key: &[u8], value: &[u8]) -> Result<(), Self::Error> {
+        let path = self.entry_path(key);
+        let dir = path.parent().unwrap();
+        std::fs::create_dir_all(dir)?;
+        let name = path.file_name().unwrap().to_string_lossy();
+        let tmp = dir.join(format!(".{}.tmp", name));
+        let mut file = File::create(&tmp)?;
+        file.write_all(value)?;
+        file.sync_all()?;
+        std::fs::rename(&tmp, &path)
+    }
+
+    fn remove(&self, key: &[u8]) -> Result<(), Self::Error> {
+        let path = self.entry_path(key);
+        match std::fs::remove_file(&path) {
+            Ok(()) => {
+                self.prune(&path);
+                Ok(())
+            }
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(()),
+            Err(e) => Err(e),
+        }
+    }
+
+    fn remove_range(&self, from: &[u8], to: &[u8]) -> Result<(), Self::Error> {
+        for key in self.keys_in_range(from, to)? {
+            self.remove(&key)?;
+        }
+        Ok(())
+    }
+
+    fn iter_range(&self, from: &[u8], to: &[u8]) -> Result<Self::Cursor, Self::Error> {
+        let mut entries = Vec::new();
+        for key in self.keys_in_range(from, to)? {
+            if let Some(value) = self.read(&key)? {
+                entries.push(FsEntry::new(key, value));
+            }
+        }
+        Ok(entries.into_iter())
+    }
+
+    fn peek_back(&self, key: &[u8]) -> Result<Option<Self::Entry>, Self::Error> {
+        let last_before =
+            |keys: Vec<Vec<u8>>| keys.into_iter().filter(|k| k.as_slice() < key).max();
+        let mut last = None;
+        if let Some(prefix) = doc_prefix(key) {
+            // if there's a preceding key of the same document, there's no need to look further
+            last = last_before(self.keys_within(&doc_dir(prefix))?);
+        }
+        if last.is_none() {
+            last = last_before(self.keys_within(Path::new(""))?);
+        }
+        match last {
+            Some(key) => Ok(self.read(&key)?.map(|value| FsEntry::new(key, value))),
+            None => Ok(None),
+        }
+    }
+}
+
+pub struct FsEntry {
+    key: Vec<u8>,
+    value: Vec<u8>,
+}
+
+impl FsEntry {
+    fn new(key: Vec<u8>, value: Vec<u8>) -> Self {
+        FsEntry { key, value }
+    }
+}
+
+impl Into<(Vec<u8>, Vec<u8>)> for FsEntry {
+    fn into(self) -> (Vec<u8>, Vec<u8>) {
+        (self.key, self.value)
+    }
+}
+
+impl KVEntry for FsEntry {
+    fn key(&self) -> &[u8] {
+        &self.key
+    }
+
+    fn value(&self) -> &[u8] {
+        &self.value
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{key_path, path_key, FsStore};
+    use yrs::{Doc, GetString, ReadTxn, Text, Transact};
+    use yrs_kvstore::keys::{
+        key_doc, key_doc_v2, key_meta, key_oid, key_state_vector, key_update, key_update_v2,
+        KEY_OID_COUNTER,
+    };
+    use yrs_kvstore::testing::verify_kvstore_contract;
+    use yrs_kvstore::DocOps;
+
+    struct Cleaner(&'static str);
+
+    impl Cleaner {
+        fn new(dir: &'static str) -> Self {
+            Self::cleanup(dir);
+            Cleaner(dir)
+        }
+
+        fn dir(&self) -> &str {
+            self.0
+        }
+
+        fn cleanup(dir: &str) {
+            if let Err(_) = std::fs::remove_dir_all(dir) {
+                // if dir doesn't exists, ignore
+            }
+        }
+    }
+
+    impl Drop for Cleaner {
+        fn drop(&mut self) {
+            Self::cleanup(self.dir());
+        }
+    }
+
+    #[test]
+    fn key_paths() {
+        let keys = [
+            key_oid(b"doc").to_vec(),
+            KEY_OID_COUNTER.to_vec(),
+            key_doc(1).to_vec(),
+            key_doc_v2(1).to_vec(),
+            key_state_vector(1).to_vec(),
+            key_update(1, 12).to_vec(),
+            key_update_v2(1, 13).to_vec(),
+            key_meta(1, b"Title: a/b").to_vec(),
+            key_meta(1, b"").to_vec(),
+        ];
+        for key in keys.iter() {
+            let path = key_path(key);
+            assert_eq!(path_key(&path).as_ref(), Some(key), "{:?}", path);
+        }
+        assert!(key_path(&key_update(1, 12)).ends_with("updates/0000000012"));
+        assert!(key_path(&key_update_v2(1, 13)).ends_with("updates/0000000013.v2"));
+        assert!(key_path(&key_meta(1, b"Title")).ends_with("meta/%54itle"));
+    }
+
+    #[test]
+    fn kvstore_contract() {
+        let cleaner = Cleaner::new("fs-kvstore_contract");
+        let store = FsStore::open(cleaner.dir()).unwrap();
+        verify_kvstore_contract(&store);
+    }
+
+    #[test]
+    fn create_get_remove() {
+        let cleaner = Cleaner::new("fs-create_get_remove");
+        let store = FsStore::open(cleaner.dir()).unwrap();
+        let doc = Doc::new();
+        let text = doc.get_or_insert_text("text");
+        text.push(&mut doc.transact_mut(), "hello");
+        store.insert_doc("doc", &doc.transact()).unwrap();
+        store.insert_meta("doc", "author", b"me").unwrap();
+
+        let sv = doc.transact().state_vector();
+        text.push(&mut doc.transact_mut(), " world");
+        store
+            .push_update("doc", &doc.transact().encode_diff_v1(&sv))
+            .unwrap();
+
+        // persisted entries are laid out in a document directory
+        let doc_dirs: Vec<_> = std::fs::read_dir(store.root().join("docs"))
+            .unwrap()
+            .map(|e| e.unwrap().path())
+            .collect();
+        assert_eq!(doc_dirs.len(), 1);
+        let doc_dir = &doc_dirs[0];
+        assert!(doc_dir.join("state").is_file());
+        assert!(doc_dir.join("state_vector").is_file());
+        assert!(doc_dir.join("updates/0000000001").is_file());
+        assert_eq!(std::fs::read(doc_dir.join("meta/author")).unwrap(), b"me");
+
+        let loaded = Doc::new();
+        let loaded_text = loaded.get_or_insert_text("text");
+        assert!(store.load_doc("doc", &mut loaded.transact_mut()).unwrap());
+        assert_eq!(loaded_text.get_string(&loaded.transact()), "hello world");
+
+        store.clear_doc("doc").unwrap();
+        assert!(!doc_dir.exists());
+        let loaded = Doc::new();
+        assert!(!store.load_doc("doc", &mut loaded.transact_mut()).unwrap());
+    }
+}
@@ -0,0 +1,342 @@
+//! Remote persistence layer for Yrs documents served over gRPC. It lets a single store run as
+//! a standalone persistence daemon (see: [server::GrpcService]) shared by multiple application
+//! nodes, which access it using [GrpcStore].
+
+// tonic::Status is an error type returned by all gRPC calls
+#![allow(clippy::result_large_err)]
+
+pub mod server;
+
+/// Protocol types and service definitions generated from `proto/docstore.proto`. Generated code
+/// is checked in, so that building this crate doesn't require `protoc`: it has to be regenerated
+/// with `tonic-build` whenever the protocol definition changes.
+pub mod proto {
+    include!("generated/yrs.kvstore.rs");
+}
+
+use crate::proto::batch_op::Op;
+use crate::proto::doc_store_client::DocStoreClient;
+use std::convert::TryInto;
+use std::future::Future;
+use std::sync::Arc;
+use tokio::runtime::Runtime;
+use tonic::transport::{Channel, Endpoint};
+use tonic::{Response, Status};
+use yrs::updates::decoder::Decode;
+use yrs::updates::encoder::Encode;
+use yrs::{StateVector, TransactionMut, Update};
+use yrs_kvstore::batch::{BatchOp, WriteBatch};
+use yrs_kvstore::error::Error;
+use yrs_kvstore::{DocOps, KVEntry, KVStore};
+
+/// Persistence layer for Yrs documents, which forwards all operations to a remote store served
+/// by [server::GrpcService].
+///
+/// Loading documents, pushing updates, computing diffs and metadata operations are executed by
+/// the remote store as a whole, using its own settings (i.e. compaction policy or update
+/// validator). All other [DocOps] operations are executed locally on top of the key-value
+/// operations of the remote store, so the remote store should keep values in their default
+/// encoding.
+///
+/// Calls are blocking: they are executed on a [Runtime] owned by the store, which means that
+/// they can't be made from within an asynchronous context of another runtime.
+///
+/// ```rust,no_run
+/// use yrs::{Doc, Transact};
+/// use yrs_grpc::GrpcStore;
+/// use yrs_kvstore::DocOps;
+///
+/// let store = GrpcStore::connect("http://127.0.0.1:50051").unwrap();
+/// let doc = Doc::new();
+/// store.insert_doc("doc", &doc.transact()).unwrap();
+/// ```
+#[derive(Debug, Clone)]
+pub struct GrpcStore {
+    client: DocStoreClient<Channel>,
+    runtime: Arc<Runtime>,
+}
+
+impl GrpcStore {
+    /// Connects to a remote store served under a given `endpoint` address.
+    pub fn connect<D>(endpoint: D) -> Result<Self, Error>
+    where
+        D: TryInto<Endpoint>,
+        D::Error: std::error::Error + 'static,
+    {
+        let endpoint = endpoint.try_into()?;
+        let runtime = Runtime::new()?;
+        let channel = runtime.block_on(endpoint.connect())?;
+        Ok(Self::new(DocStoreClient::new(channel), Arc::new(runtime)))
+    }
+
+    /// Creates a new store over an already connected gRPC `client`, which calls are executed
+    /// using a given `runtime`.
+    pub fn new(client: DocStoreClient<Channel>, runtime: Arc<Runtime>) -> Self {
+        GrpcStore { client, runtime }
+    }
+
+    /// Returns a reference to the underlying gRPC client.
+    pub fn client(&self) -> &DocStoreClient<Channel> {
+        &self.client
+    }
+
+    /// Blocks until a given remote `call` is completed.
+    fn call<R, F>(&self, call: F) -> Result<R, Status>
+    where
+        F: Future<Output = Result<Response<R>, Status>>,
+    {
+        let response = self.runtime.block_on(call)?;
+        Ok(response.into_inner())
+    }
+}
+
+impl<'a> DocOps<'a> for GrpcStore {
+    fn load_doc<K: AsRef<[u8]> + ?Sized>(
+        &self,
+        name: &K,
+        txn: &mut TransactionMut,
+    ) -> Result<bool, Error> {
+        let name = name.as_ref().to_vec();
+        let state = self.call(self.client.clone().load_doc(proto::DocName { name }))?;
+        if state.found {
+            txn.apply_update(Update::decode_v1(&state.update)?)?;
+        }
+        Ok(state.found)
+    }
+
+    fn push_update<K: AsRef<[u8]> + ?Sized>(&self, name: &K, update: &[u8]) -> Result<u32, Error> {
+        let req = proto::PushUpdateRequest {
+            name: name.as_ref().to_vec(),
+            update: update.to_vec(),
+        };
+        let reply = self.call(self.client.clone().push_update(req))?;
+        Ok(reply.seq)
+    }
+
+    fn get_diff<K: AsRef<[u8]> + ?Sized>(
+        &self,
+        name: &K,
+        sv: &StateVector,
+    ) -> Result<Option<Vec<u8>>, Error> {
+        let req = proto::GetDiffRequest {
+            name: name.as_ref().to_vec(),
+            state_vector: sv.encode_v1(),
+        };
+        let diff = self.call(self.client.clone().get_diff(req))?;
+        Ok(if diff.found { Some(diff.update) } else { None })
+    }
+
+    fn get_meta<K1: AsRef<[u8]> + ?Sized, K2: AsRef<[u8]> + ?Sized>(
+        &self,
+        name: &K1,
+        meta_key: &K2,
+    ) -> Result<Option<Vec<u8>>, Error> {
+        let req = proto::MetaKey {
+            name: name.as_ref().to_vec(),
+            meta_key: meta_key.as_ref().to_vec(),
+        };
+        Ok(self.call(self.client.clone().get_meta(req))?.value)
+    }
+
+    fn insert_meta<K1: AsRef<[u8]> + ?Sized, K2: AsRef<[u8]> + ?Sized>(
+        &self,
+        name: &K1,
+        meta_key: &K2,
+        meta: &[u8],
+    ) -> Result<(), Error> {
+        let req = proto::MetaEntry {
+            name: name.as_ref().to_vec(),
+            meta_key: meta_key.as_ref().to_vec(),
+            meta: meta.to_vec(),
+        };
+        self.call(self.client.clone().insert_meta(req))?;
+        Ok(())
+    }
+
+    fn remove_meta<K1: AsRef<[u8]> + ?Sized, K2: AsRef<[u8]> + ?Sized>(
+        &self,
+        name: &K1,
+        meta_key: &K2,
+    ) -> Result<(), Error> {
+        let req = proto::MetaKey {
+            name: name.as_ref().to_vec(),
+            meta_key: meta_key.as_ref().to_vec(),
+        };
+        self.call(self.client.clone().remove_meta(req))?;
+        Ok(())
+    }
+}
+
+impl<'a> KVStore<'a> for GrpcStore {
+    type Error = Status;
+    type Cursor = std::vec::IntoIter<GrpcEntry>;
+    type Entry = GrpcEntry;
+    type Return = Vec<u8>;
+
+    fn get(&self, key: &[u8]) -> Result<Option<Self::Return>, Self::Error> {
+        let key = key.to_vec();
+        Ok(self
+            .call(self.client.clone().get(proto::Key { key }))?
+            .value)
+    }
+
+    fn upsert(&self, key: &[u8], value: &[u8]) -> Result<(), Self::Error> {
+        let entry = proto::Entry {
+            key: key.to_vec(),
+            value: value.to_vec(),
+        };
+        self.call(self.client.clone().upsert(entry))?;
+        Ok(())
+    }
+
+    fn remove(&self, key: &[u8]) -> Result<(), Self::Error> {
+        let key = key.to_vec();
+        self.call(self.client.clone().remove(proto::Key { key }))?;
+        Ok(())
+    }
+
+    fn remove_range(&self, from: &[u8], to: &[u8]) -> Result<(), Self::Error> {
+        let range = proto::KeyRange {
+            from: from.to_vec(),
+            to: to.to_vec(),
+        };
+        self.call(self.client.clone().remove_range(range))?;
+        Ok(())
+    }
+
+    fn iter_range(&self, from: &[u8], to: &[u8]) -> Result<Self::Cursor, Self::Error> {
+        let range = proto::KeyRange {
+            from: from.to_vec(),
+            to: to.to_vec(),
+        };
+        let mut stream = self.call(self.client.clone().iter_range(range))?;
+        // entries are fetched eagerly, so that errors can be reported before iteration starts
+        let entries = self.runtime.block_on(async move {
+            let mut entries = Vec::new();
+            while let Some(entry) = stream.message().await? {
+                entries.push(GrpcEntry(entry));
+            }
+            Ok::<_, Status>(entries)
+        })?;
+        Ok(entries.into_iter())
+    }
+
+    fn peek_back(&self, key: &[u8]) -> Result<Option<Self::Entry>, Self::Error> {
+        let key = key.to_vec();
+        let reply = self.call(self.client.clone().peek_back(proto::Key { key }))?;
+        Ok(reply.entry.map(GrpcEntry))
+    }
+
+    fn commit_batch(&self, batch: WriteBatch) -> Result<(), Self::Error> {
+        let ops = batch
+            .into_iter()
+            .map(|op| {
+                let op = match op {
+                    BatchOp::Upsert(key, value) => Op::Upsert(proto::Entry { key, value }),
+                    BatchOp::Remove(key) => Op::Remove(proto::Key { key }),
+                    BatchOp::RemoveRange(from, to) => Op::RemoveRange(proto::KeyRange { from, to }),
+                };
+                proto::BatchOp { op: Some(op) }
+            })
+            .collect();
+        self.call(self.client.clone().commit_batch(proto::Batch { ops }))?;
+        Ok(())
+    }
+}
+
+/// Key-value entry returned by [GrpcStore] cursors.
+#[derive(Debug, Clone, PartialEq)]
+pub struct GrpcEntry(proto::Entry);
+
+impl KVEntry for GrpcEntry {
+    fn key(&self) -> &[u8] {
+        &self.0.key
+    }
+
+    fn value(&self) -> &[u8] {
+        &self.0.value
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::server::GrpcService;
+    use crate::GrpcStore;
+    use std::convert::TryFrom;
+    use std::sync::Arc;
+    use tokio::runtime::Runtime;
+    use tonic::transport::Server;
+    use yrs::{Doc, GetString, ReadTxn, StateVector, Text, Transact};
+    use yrs_kvstore::memory::MemKVStore;
+    use yrs_kvstore::testing::verify_kvstore_contract;
+    use yrs_kvstore::DocOps;
+
+    fn init_store() -> GrpcStore {
+        let runtime = Arc::new(Runtime::new().unwrap());
+        let listener = runtime
+            .block_on(tokio::net::TcpListener::bind("127.0.0.1:0"))
+            .unwrap();
+        let addr = listener.local_addr().unwrap();
+        let incoming = tokio_stream::wrappers::TcpListenerStream::new(listener);
+        let service = GrpcService::new(MemKVStore::new()).into_server();
+        runtime.spawn(
+            Server::builder()
+                .add_service(service)
+                .serve_with_incoming(incoming),
+        );
+        let channel = runtime
+            .block_on(
+                tonic::transport::Endpoint::try_from(format!("http://{}", addr))
+                    .unwrap()
+                    .connect(),
+            )
+            .unwrap();
+        GrpcStore::new(
+            crate::proto::doc_store_client::DocStoreClient::new(channel),
+            runtime,
+        )
+    }
+
+    #[test]
+    fn kvstore_contract() {
+        let store = init_store();
+        verify_kvstore_contract(&store);
+    }
+
+    #[test]
+    fn remote_doc_ops() {
+        let store = init_store();
+        let doc = Doc::new();
+        let text = doc.get_or_insert_text("text");
+        text.push(&mut doc.transact_mut(), "hello");
+        store.insert_doc("doc", &doc.transact()).unwrap();
+        let sv = doc.transact().state_vector();
+        text.push(&mut doc.transact_mut(), " world");
+        store
+            .push_update("doc", &doc.transact().encode_diff_v1(&sv))
+            .unwrap();
+        store.insert_meta("doc", "owner", b"alice").unwrap();
+
+        let loaded = Doc::new();
+        let loaded_text = loaded.get_or_insert_text("text");
+        assert!(store.load_doc("doc", &mut loaded.transact_mut()).unwrap());
+        assert_eq!(loaded_text.get_string(&loaded.transact()), "hello world");
+        assert!(!store
+            .load_doc("missing", &mut loaded.transact_mut())
+            .unwrap());
+
+        let diff = store.get_diff("doc", &StateVector::default()).unwrap();
+        assert!(diff.is_some());
+        assert!(store
+            .get_diff("missing", &StateVector::default())
+            .unwrap()
+            .is_none());
+
+        assert_eq!(
+            store.get_meta("doc", "owner").unwrap(),
+            Some(b"alice".to_vec())
+        );
+        store.remove_meta("doc", "owner").unwrap();
+        assert!(store.get_meta("doc", "owner").unwrap().is_none());
+    }
+}
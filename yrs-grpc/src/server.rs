@@ -0,0 +1,271 @@
+//! gRPC service exposing any [DocOps] store to remote [crate::GrpcStore] clients.
+
+use crate::proto;
+use crate::proto::batch_op::Op;
+use crate::proto::doc_store_server::{DocStore, DocStoreServer};
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use tokio_stream::Stream;
+use tonic::{Request, Response, Status};
+use yrs::updates::decoder::Decode;
+use yrs::StateVector;
+use yrs_kvstore::batch::WriteBatch;
+use yrs_kvstore::error::{find, Error, StoreError};
+use yrs_kvstore::{DocOps, KVEntry, KVStore};
+
+/// gRPC service, which serves a single [DocOps] store. Requests are processed one at a time on
+/// a dedicated thread pool (see: [tokio::task::spawn_blocking]), since store operations are
+/// blocking.
+///
+/// ```rust,no_run
+/// use tonic::transport::Server;
+/// use yrs_grpc::server::GrpcService;
+/// use yrs_kvstore::memory::MemKVStore;
+///
+/// # async fn run() -> Result<(), Box<dyn std::error::Error>> {
+/// let service = GrpcService::new(MemKVStore::new());
+/// Server::builder()
+///     .add_service(service.into_server())
+///     .serve("127.0.0.1:50051".parse()?)
+///     .await?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct GrpcService<S> {
+    store: Arc<Mutex<S>>,
+}
+
+impl<S> GrpcService<S>
+where
+    S: DocOps<'static> + Send + 'static,
+    Error: From<<S as KVStore<'static>>::Error>,
+{
+    /// Creates a new service serving a given `store`.
+    pub fn new(store: S) -> Self {
+        GrpcService {
+            store: Arc::new(Mutex::new(store)),
+        }
+    }
+
+    /// Wraps current service into a tonic server, which can be registered in
+    /// [tonic::transport::Server].
+    pub fn into_server(self) -> DocStoreServer<Self> {
+        DocStoreServer::new(self)
+    }
+
+    /// Executes a given function `f` over the served store on a blocking thread pool.
+    async fn run<R, F>(&self, f: F) -> Result<R, Status>
+    where
+        R: Send + 'static,
+        F: FnOnce(&S) -> Result<R, Error> + Send + 'static,
+    {
+        let store = self.store.clone();
+        let result = tokio::task::spawn_blocking(move || {
+            let store = store.lock().unwrap_or_else(|e| e.into_inner());
+            // errors are not Send, they must be converted before leaving the blocking thread
+            f(&store).map_err(|e| status(&e))
+        })
+        .await;
+        match result {
+            Ok(result) => result,
+            Err(e) => Err(Status::internal(e.to_string())),
+        }
+    }
+}
+
+impl<S> Clone for GrpcService<S> {
+    fn clone(&self) -> Self {
+        GrpcService {
+            store: self.store.clone(),
+        }
+    }
+}
+
+fn status(err: &Error) -> Status {
+    match find::<StoreError>(err) {
+        Some(StoreError::UpdateTooLarge { .. }) | Some(StoreError::UpdateLogTooLarge { .. }) => {
+            Status::resource_exhausted(err.to_string())
+        }
+        Some(StoreError::UpdateRejected(_)) => Status::invalid_argument(err.to_string()),
+        _ => Status::internal(err.to_string()),
+    }
+}
+
+fn doc_state(diff: Option<Vec<u8>>) -> proto::DocState {
+    match diff {
+        Some(update) => proto::DocState {
+            found: true,
+            update,
+        },
+        None => proto::DocState::default(),
+    }
+}
+
+#[tonic::async_trait]
+impl<S> DocStore for GrpcService<S>
+where
+    S: DocOps<'static> + Send + 'static,
+    Error: From<<S as KVStore<'static>>::Error>,
+{
+    async fn get(
+        &self,
+        request: Request<proto::Key>,
+    ) -> Result<Response<proto::OptionalValue>, Status> {
+        let key = request.into_inner().key;
+        let value = self
+            .run(move |store| Ok(store.get(&key)?.map(|v| v.as_ref().to_vec())))
+            .await?;
+        Ok(Response::new(proto::OptionalValue { value }))
+    }
+
+    async fn upsert(
+        &self,
+        request: Request<proto::Entry>,
+    ) -> Result<Response<proto::Empty>, Status> {
+        let entry = request.into_inner();
+        self.run(move |store| Ok(store.upsert(&entry.key, &entry.value)?))
+            .await?;
+        Ok(Response::new(proto::Empty {}))
+    }
+
+    async fn remove(&self, request: Request<proto::Key>) -> Result<Response<proto::Empty>, Status> {
+        let key = request.into_inner().key;
+        self.run(move |store| Ok(store.remove(&key)?)).await?;
+        Ok(Response::new(proto::Empty {}))
+    }
+
+    async fn remove_range(
+        &self,
+        request: Request<proto::KeyRange>,
+    ) -> Result<Response<proto::Empty>, Status> {
+        let range = request.into_inner();
+        self.run(move |store| Ok(store.remove_range(&range.from, &range.to)?))
+            .await?;
+        Ok(Response::new(proto::Empty {}))
+    }
+
+    type IterRangeStream = Pin<Box<dyn Stream<Item = Result<proto::Entry, Status>> + Send>>;
+
+    async fn iter_range(
+        &self,
+        request: Request<proto::KeyRange>,
+    ) -> Result<Response<Self::IterRangeStream>, Status> {
+        let range = request.into_inner();
+        let entries = self
+            .run(move |store| {
+                let entries: Vec<_> = store
+                    .iter_range(&range.from, &range.to)?
+                    .map(|e| {
+                        Ok::<_, Status>(proto::Entry {
+                            key: e.key().to_vec(),
+                            value: e.value().to_vec(),
+                        })
+                    })
+                    .collect();
+                Ok(entries)
+            })
+            .await?;
+        Ok(Response::new(Box::pin(tokio_stream::iter(entries))))
+    }
+
+    async fn peek_back(
+        &self,
+        request: Request<proto::Key>,
+    ) -> Result<Response<proto::OptionalEntry>, Status> {
+        let key = request.into_inner().key;
+        let entry = self
+            .run(move |store| {
+                Ok(store.peek_back(&key)?.map(|e| proto::Entry {
+                    key: e.key().to_vec(),
+                    value: e.value().to_vec(),
+                }))
+            })
+            .await?;
+        Ok(Response::new(proto::OptionalEntry { entry }))
+    }
+
+    async fn commit_batch(
+        &self,
+        request: Request<proto::Batch>,
+    ) -> Result<Response<proto::Empty>, Status> {
+        let mut batch = WriteBatch::new();
+        for op in request.into_inner().ops {
+            match op.op {
+                Some(Op::Upsert(entry)) => batch.upsert(&entry.key, &entry.value),
+                Some(Op::Remove(key)) => batch.remove(&key.key),
+                Some(Op::RemoveRange(range)) => batch.remove_range(&range.from, &range.to),
+                None => return Err(Status::invalid_argument("empty batch operation")),
+            }
+        }
+        self.run(move |store| Ok(store.commit_batch(batch)?))
+            .await?;
+        Ok(Response::new(proto::Empty {}))
+    }
+
+    async fn load_doc(
+        &self,
+        request: Request<proto::DocName>,
+    ) -> Result<Response<proto::DocState>, Status> {
+        let name = request.into_inner().name;
+        let diff = self
+            .run(move |store| store.get_diff(&name, &StateVector::default()))
+            .await?;
+        Ok(Response::new(doc_state(diff)))
+    }
+
+    async fn push_update(
+        &self,
+        request: Request<proto::PushUpdateRequest>,
+    ) -> Result<Response<proto::PushUpdateReply>, Status> {
+        let req = request.into_inner();
+        let seq = self
+            .run(move |store| store.push_update(&req.name, &req.update))
+            .await?;
+        Ok(Response::new(proto::PushUpdateReply { seq }))
+    }
+
+    async fn get_diff(
+        &self,
+        request: Request<proto::GetDiffRequest>,
+    ) -> Result<Response<proto::DocState>, Status> {
+        let req = request.into_inner();
+        let sv = StateVector::decode_v1(&req.state_vector)
+            .map_err(|e| Status::invalid_argument(e.to_string()))?;
+        let diff = self
+            .run(move |store| store.get_diff(&req.name, &sv))
+            .await?;
+        Ok(Response::new(doc_state(diff)))
+    }
+
+    async fn get_meta(
+        &self,
+        request: Request<proto::MetaKey>,
+    ) -> Result<Response<proto::OptionalValue>, Status> {
+        let req = request.into_inner();
+        let value = self
+            .run(move |store| store.get_meta(&req.name, &req.meta_key))
+            .await?;
+        Ok(Response::new(proto::OptionalValue { value }))
+    }
+
+    async fn insert_meta(
+        &self,
+        request: Request<proto::MetaEntry>,
+    ) -> Result<Response<proto::Empty>, Status> {
+        let req = request.into_inner();
+        self.run(move |store| store.insert_meta(&req.name, &req.meta_key, &req.meta))
+            .await?;
+        Ok(Response::new(proto::Empty {}))
+    }
+
+    async fn remove_meta(
+        &self,
+        request: Request<proto::MetaKey>,
+    ) -> Result<Response<proto::Empty>, Status> {
+        let req = request.into_inner();
+        self.run(move |store| store.remove_meta(&req.name, &req.meta_key))
+            .await?;
+        Ok(Response::new(proto::Empty {}))
+    }
+}
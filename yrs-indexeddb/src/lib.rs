@@ -0,0 +1,354 @@
+use async_trait::async_trait;
+use js_sys::{Array, Function, Promise, Reflect, Uint8Array};
+use wasm_bindgen::closure::Closure;
+use wasm_bindgen::{JsCast, JsValue};
+use wasm_bindgen_futures::JsFuture;
+use web_sys::{
+    IdbCursorDirection, IdbCursorWithValue, IdbDatabase, IdbFactory, IdbKeyRange, IdbObjectStore,
+    IdbOpenDbRequest, IdbRequest, IdbTransaction, IdbTransactionMode,
+};
+use yrs_kvstore::asynchronous::{AsyncDocOps, AsyncKVStore};
+use yrs_kvstore::KVEntry;
+
+/// Persistence layer for Yrs documents build on top of IndexedDB, meant to be used by browser
+/// applications compiled to WebAssembly. All entries are kept in a single object store, using
+/// binary keys, which IndexedDB orders lexicographically. This way documents are persisted using
+/// the same keyspace layout as by the server side stores.
+///
+/// Every operation is executed within its own IndexedDB transaction. Since IndexedDB transactions
+/// are committed automatically once they have no pending requests, they can't be kept open for
+/// the duration of compound [AsyncDocOps] operations.
+#[derive(Debug, Clone)]
+pub struct IndexedDbStore {
+    db: IdbDatabase,
+    store_name: String,
+}
+
+impl IndexedDbStore {
+    /// Opens (or creates) an IndexedDB database with a given `db_name` and returns a store, which
+    /// keeps its entries in an object store called `store_name`, creating it if necessary.
+    pub async fn open(db_name: &str, store_name: &str) -> Result<Self, IdbError> {
+        let factory: IdbFactory = Reflect::get(&js_sys::global(), &JsValue::from("indexedDB"))?
+            .dyn_into()
+            .map_err(|_| IdbError::new("IndexedDB is not supported in current environment"))?;
+        let db = open_db(&factory, db_name, store_name, None).await?;
+        if db.object_store_names().contains(store_name) {
+            return Ok(Self::new(db, store_name));
+        }
+        // object stores can only be created while database version is being upgraded
+        let version = db.version() as u32 + 1;
+        db.close();
+        let db = open_db(&factory, db_name, store_name, Some(version)).await?;
+        Ok(Self::new(db, store_name))
+    }
+
+    /// Creates a new store over an already opened IndexedDB database. Object store called
+    /// `store_name` must already exist within that database.
+    pub fn new(db: IdbDatabase, store_name: &str) -> Self {
+        IndexedDbStore {
+            db,
+            store_name: store_name.to_string(),
+        }
+    }
+
+    /// Returns a handle to the underlying IndexedDB database.
+    pub fn database(&self) -> &IdbDatabase {
+        &self.db
+    }
+
+    fn object_store(
+        &self,
+        mode: IdbTransactionMode,
+    ) -> Result<(IdbTransaction, IdbObjectStore), IdbError> {
+        let tx = self
+            .db
+            .transaction_with_str_and_mode(&self.store_name, mode)?;
+        let store = tx.object_store(&self.store_name)?;
+        Ok((tx, store))
+    }
+}
+
+/// Opens a database with a given `name`, creating an object store called `store_name` if
+/// database is upgraded to a given `version`.
+async fn open_db(
+    factory: &IdbFactory,
+    name: &str,
+    store_name: &str,
+    version: Option<u32>,
+) -> Result<IdbDatabase, IdbError> {
+    let req: IdbOpenDbRequest = match version {
+        Some(version) => factory.open_with_u32(name, version)?,
+        None => factory.open(name)?,
+    };
+    let upgraded = req.clone();
+    let store_name = store_name.to_string();
+    let on_upgrade = Closure::once_into_js(move || {
+        if let Ok(db) = upgraded.result() {
+            let db: IdbDatabase = db.unchecked_into();
+            if !db.object_store_names().contains(&store_name) {
+                let _ = db.create_object_store(&store_name);
+            }
+        }
+    });
+    req.set_onupgradeneeded(Some(on_upgrade.unchecked_ref()));
+    let db = request(&req).await?;
+    Ok(db.unchecked_into())
+}
+
+/// Waits for a given IndexedDB request to complete, returning its result.
+async fn request(req: &IdbRequest) -> Result<JsValue, IdbError> {
+    let promise = Promise::new(&mut |resolve: Function, reject: Function| {
+        let succeeded = req.clone();
+        let on_success = Closure::once_into_js(move || {
+            let result = succeeded.result().unwrap_or(JsValue::UNDEFINED);
+            let _ = resolve.call1(&JsValue::UNDEFINED, &result);
+        });
+        let failed = req.clone();
+        let on_error = Closure::once_into_js(move || {
+            let error = match failed.error() {
+                Ok(Some(e)) => e.into(),
+                _ => JsValue::UNDEFINED,
+            };
+            let _ = reject.call1(&JsValue::UNDEFINED, &error);
+        });
+        req.set_onsuccess(Some(on_success.unchecked_ref()));
+        req.set_onerror(Some(on_error.unchecked_ref()));
+    });
+    Ok(JsFuture::from(promise).await?)
+}
+
+/// Waits for a given IndexedDB transaction to be committed.
+async fn committed(tx: &IdbTransaction) -> Result<(), IdbError> {
+    let promise = Promise::new(&mut |resolve: Function, reject: Function| {
+        let on_complete = Closure::once_into_js(move || {
+            let _ = resolve.call0(&JsValue::UNDEFINED);
+        });
+        let failed = tx.clone();
+        let on_error = Closure::once_into_js(move || {
+            let error = match failed.error() {
+                Some(e) => e.into(),
+                None => JsValue::UNDEFINED,
+            };
+            let _ = reject.call1(&JsValue::UNDEFINED, &error);
+        });
+        tx.set_oncomplete(Some(on_complete.unchecked_ref()));
+        // failed requests abort the transaction, so its abort event is raised in both cases
+        tx.set_onabort(Some(on_error.unchecked_ref()));
+    });
+    JsFuture::from(promise).await?;
+    Ok(())
+}
+
+fn key_value(key: &[u8]) -> JsValue {
+    Uint8Array::from(key).into()
+}
+
+fn to_bytes(value: &JsValue) -> Vec<u8> {
+    Uint8Array::new(value).to_vec()
+}
+
+fn key_range(from: &[u8], to: &[u8]) -> Result<IdbKeyRange, IdbError> {
+    Ok(IdbKeyRange::bound(&key_value(from), &key_value(to))?)
+}
+
+#[async_trait(?Send)]
+impl<'a> AsyncKVStore<'a> for IndexedDbStore {
+    type Error = IdbError;
+    type Cursor = std::vec::IntoIter<IdbEntry>;
+    type Entry = IdbEntry;
+    type Return = Vec<u8>;
+
+    async fn get(&self, key: &[u8]) -> Result<Option<Self::Return>, Self::Error> {
+        let (_, store) = self.object_store(IdbTransactionMode::Readonly)?;
+        let value = request(&store.get(&key_value(key))?).await?;
+        if value.is_undefined() {
+            Ok(None)
+        } else {
+            Ok(Some(to_bytes(&value)))
+        }
+    }
+
+    async fn upsert(&self, key: &[u8], value: &[u8]) -> Result<(), Self::Error> {
+        let (tx, store) = self.object_store(IdbTransactionMode::Readwrite)?;
+        let value: JsValue = Uint8Array::from(value).into();
+        store.put_with_key(&value, &key_value(key))?;
+        committed(&tx).await
+    }
+
+    async fn remove(&self, key: &[u8]) -> Result<(), Self::Error> {
+        let (tx, store) = self.object_store(IdbTransactionMode::Readwrite)?;
+        store.delete(&key_value(key))?;
+        committed(&tx).await
+    }
+
+    async fn remove_range(&self, from: &[u8], to: &[u8]) -> Result<(), Self::Error> {
+        let (tx, store) = self.object_store(IdbTransactionMode::Readwrite)?;
+        let range = key_range(from, to)?;
+        store.delete(&range)?;
+        committed(&tx).await
+    }
+
+    async fn iter_range(&self, from: &[u8], to: &[u8]) -> Result<Self::Cursor, Self::Error> {
+        let (_, store) = self.object_store(IdbTransactionMode::Readonly)?;
+        let range = key_range(from, to)?;
+        // both requests are served from the same transaction, so they see the same entries
+        let keys = store.get_all_keys_with_key(&range)?;
+        let values = store.get_all_with_key(&range)?;
+        let keys: Array = request(&keys).await?.unchecked_into();
+        let values: Array = request(&values).await?.unchecked_into();
+        let entries: Vec<_> = keys
+            .iter()
+            .zip(values.iter())
+            .map(|(key, value)| IdbEntry::new(to_bytes(&key), to_bytes(&value)))
+            .collect();
+        Ok(entries.into_iter())
+    }
+
+    async fn peek_back(&self, key: &[u8]) -> Result<Option<Self::Entry>, Self::Error> {
+        let (_, store) = self.object_store(IdbTransactionMode::Readonly)?;
+        let range = IdbKeyRange::upper_bound_with_open(&key_value(key), true)?;
+        let req = store.open_cursor_with_range_and_direction(&range, IdbCursorDirection::Prev)?;
+        let cursor = request(&req).await?;
+        if cursor.is_null() || cursor.is_undefined() {
+            return Ok(None);
+        }
+        let cursor: IdbCursorWithValue = cursor.unchecked_into();
+        Ok(Some(IdbEntry::new(
+            to_bytes(&cursor.key()?),
+            to_bytes(&cursor.value()?),
+        )))
+    }
+}
+
+impl<'a> AsyncDocOps<'a> for IndexedDbStore {}
+
+pub struct IdbEntry {
+    key: Vec<u8>,
+    value: Vec<u8>,
+}
+
+impl IdbEntry {
+    fn new(key: Vec<u8>, value: Vec<u8>) -> Self {
+        IdbEntry { key, value }
+    }
+}
+
+impl Into<(Vec<u8>, Vec<u8>)> for IdbEntry {
+    fn into(self) -> (Vec<u8>, Vec<u8>) {
+        (self.key, self.value)
+    }
+}
+
+impl KVEntry for IdbEntry {
+    fn key(&self) -> &[u8] {
+        &self.key
+    }
+
+    fn value(&self) -> &[u8] {
+        &self.value
+    }
+}
+
+/// Error returned by IndexedDB or JavaScript runtime.
+#[derive(Debug, Clone)]
+pub struct IdbError(String);
+
+impl IdbError {
+    fn new<S: Into<String>>(message: S) -> Self {
+        IdbError(message.into())
+    }
+}
+
+impl From<JsValue> for IdbError {
+    fn from(value: JsValue) -> Self {
+        let message = match value.dyn_ref::<js_sys::Error>() {
+            Some(e) => String::from(e.message()),
+            None => match value.as_string() {
+                Some(message) => message,
+                None => format!("{:?}", value),
+            },
+        };
+        IdbError(message)
+    }
+}
+
+impl std::fmt::Display for IdbError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "IndexedDB error: {}", self.0)
+    }
+}
+
+impl std::error::Error for IdbError {}
+
+#[cfg(all(test, target_arch = "wasm32"))]
+mod test {
+    use crate::IndexedDbStore;
+    use wasm_bindgen_test::{wasm_bindgen_test, wasm_bindgen_test_configure};
+    use yrs::{Doc, GetString, ReadTxn, StateVector, Text, Transact};
+    use yrs_kvstore::asynchronous::{AsyncDocOps, AsyncKVStore};
+
+    wasm_bindgen_test_configure!(run_in_browser);
+
+    #[wasm_bindgen_test]
+    async fn kvstore_ops() {
+        let db = IndexedDbStore::open("yrs-kvstore-ops", "entries")
+            .await
+            .unwrap();
+        db.remove_range(&[0], &[255]).await.unwrap();
+        db.upsert(&[0, 1], b"a").await.unwrap();
+        db.upsert(&[0, 2], b"b").await.unwrap();
+        db.upsert(&[0, 4], b"c").await.unwrap();
+
+        assert_eq!(db.get(&[0, 2]).await.unwrap(), Some(b"b".to_vec()));
+        assert_eq!(db.get(&[0, 3]).await.unwrap(), None);
+        let entries: Vec<(Vec<u8>, Vec<u8>)> = db
+            .iter_range(&[0, 2], &[0, 4])
+            .await
+            .unwrap()
+            .map(Into::into)
+            .collect();
+        assert_eq!(
+            entries,
+            vec![(vec![0, 2], b"b".to_vec()), (vec![0, 4], b"c".to_vec())]
+        );
+        let prev: (Vec<u8>, Vec<u8>) = db.peek_back(&[0, 4]).await.unwrap().unwrap().into();
+        assert_eq!(prev, (vec![0, 2], b"b".to_vec()));
+        assert!(db.peek_back(&[0, 1]).await.unwrap().is_none());
+
+        db.remove_range(&[0, 2], &[0, 4]).await.unwrap();
+        assert_eq!(db.iter_range(&[0], &[255]).await.unwrap().count(), 1);
+        db.remove(&[0, 1]).await.unwrap();
+        assert!(db.get(&[0, 1]).await.unwrap().is_none());
+    }
+
+    #[wasm_bindgen_test]
+    async fn load_doc() {
+        let db = IndexedDbStore::open("yrs-kvstore-docs", "entries")
+            .await
+            .unwrap();
+        db.clear_doc("doc").await.unwrap();
+        let doc = Doc::new();
+        let text = doc.get_or_insert_text("text");
+        text.push(&mut doc.transact_mut(), "hello");
+        db.insert_doc("doc", &doc.transact()).await.unwrap();
+        let sv = doc.transact().state_vector();
+        text.push(&mut doc.transact_mut(), " world");
+        let update = doc.transact().encode_diff_v1(&sv);
+        db.push_update("doc", &update).await.unwrap();
+
+        let loaded = Doc::new();
+        let loaded_text = loaded.get_or_insert_text("text");
+        assert!(db
+            .load_doc("doc", &mut loaded.transact_mut())
+            .await
+            .unwrap());
+        assert_eq!(loaded_text.get_string(&loaded.transact()), "hello world");
+
+        let diff = db
+            .get_diff("doc", &StateVector::default())
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(!diff.is_empty());
+    }
+}
@@ -0,0 +1,223 @@
+use clap::Subcommand;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Write};
+use std::path::PathBuf;
+use yrs_kvstore::error::Error;
+use yrs_kvstore::{DocOps, FlushAllOptions, KVStore};
+
+/// Operations, which can be executed over a store.
+#[derive(Debug, Clone, Subcommand)]
+pub enum Command {
+    /// List names of all documents.
+    List,
+    /// Show statistics of a single document or a number of documents in the whole store.
+    Stats {
+        /// Name of the document.
+        name: Option<String>,
+    },
+    /// Print content of a document as JSON.
+    Dump {
+        /// Name of the document.
+        name: String,
+    },
+    /// Merge pending updates of a single document or all documents into their states.
+    Flush {
+        /// Name of the document. All documents with pending updates are flushed if not set.
+        name: Option<String>,
+    },
+    /// Remove entries left behind by orphaned documents.
+    Compact,
+    /// Write all documents into a dump file.
+    Export {
+        /// Path to the dump file.
+        file: PathBuf,
+    },
+    /// Import all documents from a dump file produced by `export` command.
+    Import {
+        /// Path to the dump file.
+        file: PathBuf,
+    },
+    /// Check integrity of the store.
+    Verify,
+}
+
+impl Command {
+    /// Checks if current command requires a write capabilities from the database transaction.
+    /// Only transactional backends need to know it in order to commit their changes.
+    #[cfg(any(feature = "rocksdb", feature = "lmdb"))]
+    pub fn is_write(&self) -> bool {
+        matches!(
+            self,
+            Command::Flush { .. } | Command::Compact | Command::Import { .. }
+        )
+    }
+}
+
+/// Executes a given `command` over a `store`, writing its output into `out`. Returns `false` if
+/// command has found problems, which should be reported with a non-zero exit code.
+pub fn execute<'a, S, W>(store: &S, command: &Command, out: &mut W) -> Result<bool, Error>
+where
+    S: DocOps<'a>,
+    W: Write,
+    Error: From<<S as KVStore<'a>>::Error>,
+{
+    match command {
+        Command::List => {
+            for name in store.iter_docs()? {
+                writeln!(out, "{}", String::from_utf8_lossy(&name))?;
+            }
+        }
+        Command::Stats { name: None } => {
+            writeln!(out, "documents: {}", store.doc_count()?)?;
+            writeln!(out, "trashed documents: {}", store.trashed_docs()?.len())?;
+        }
+        Command::Stats { name: Some(name) } => match store.doc_stats(name)? {
+            Some(stats) => {
+                writeln!(out, "document state: {} bytes", stats.doc_state_bytes)?;
+                writeln!(
+                    out,
+                    "pending updates: {} ({} bytes)",
+                    stats.pending_updates, stats.pending_update_bytes
+                )?;
+                writeln!(out, "metadata entries: {}", stats.meta_entries)?;
+                if let Some(seq) = stats.last_update_seq {
+                    writeln!(out, "last update: {}", seq)?;
+                }
+                if let Some(seq) = stats.last_flush_seq {
+                    writeln!(out, "last flush: {}", seq)?;
+                }
+            }
+            None => return not_found(name),
+        },
+        Command::Dump { name } => match store.export_json(name)? {
+            Some(json) => writeln!(out, "{}", serde_json::to_string_pretty(&json)?)?,
+            None => return not_found(name),
+        },
+        Command::Flush { name: Some(name) } => {
+            if !store.doc_exists(name)? {
+                return not_found(name);
+            }
+            store.flush_doc(name)?;
+            writeln!(out, "flushed: {}", name)?;
+        }
+        Command::Flush { name: None } => {
+            let summary = store.flush_all(FlushAllOptions::default())?;
+            writeln!(out, "flushed: {}", summary.flushed)?;
+            for (name, e) in summary.failed.iter() {
+                writeln!(out, "failed: {}: {}", String::from_utf8_lossy(name), e)?;
+            }
+            return Ok(summary.failed.is_empty());
+        }
+        Command::Compact => {
+            let stats = store.vacuum()?;
+            writeln!(out, "orphaned documents: {}", stats.oids.len())?;
+            writeln!(out, "removed entries: {}", stats.total())?;
+        }
+        Command::Export { file } => {
+            let mut writer = BufWriter::new(File::create(file)?);
+            let count = store.export_all(&mut writer)?;
+            writer.flush()?;
+            writeln!(out, "exported: {}", count)?;
+        }
+        Command::Import { file } => {
+            let mut reader = BufReader::new(File::open(file)?);
+            let count = store.import_all(&mut reader)?;
+            writeln!(out, "imported: {}", count)?;
+        }
+        Command::Verify => {
+            let report = store.verify_store()?;
+            writeln!(out, "documents: {}", report.docs)?;
+            for (oid, names) in report.duplicate_oids.iter() {
+                let names: Vec<_> = names.iter().map(|n| String::from_utf8_lossy(n)).collect();
+                writeln!(out, "duplicate OID {}: {}", oid, names.join(", "))?;
+            }
+            for (oid, count) in report.orphaned.iter() {
+                writeln!(out, "orphaned OID {}: {} entries", oid, count)?;
+            }
+            for key in report.undecodable.iter() {
+                writeln!(out, "undecodable entry: {:?}", key)?;
+            }
+            for name in report.state_vector_mismatches.iter() {
+                let name = String::from_utf8_lossy(name);
+                writeln!(out, "state vector mismatch: {}", name)?;
+            }
+            return Ok(report.is_ok());
+        }
+    }
+    Ok(true)
+}
+
+fn not_found(name: &str) -> Result<bool, Error> {
+    Err(format!("document not found: {}", name).into())
+}
+
+#[cfg(test)]
+mod test {
+    use crate::commands::{execute, Command};
+    use yrs::{Doc, Text, Transact};
+    use yrs_kvstore::export::JsonExport;
+    use yrs_kvstore::memory::MemKVStore;
+    use yrs_kvstore::DocOps;
+
+    fn run(store: &MemKVStore, command: Command) -> (bool, String) {
+        let mut out = Vec::new();
+        let ok = execute(store, &command, &mut out).unwrap();
+        (ok, String::from_utf8(out).unwrap())
+    }
+
+    #[test]
+    fn inspect_store() {
+        let store = MemKVStore::new().with_json_export(JsonExport::new().text("text"));
+        for name in ["a", "b"].iter() {
+            let doc = Doc::new();
+            let text = doc.get_or_insert_text("text");
+            text.push(&mut doc.transact_mut(), name);
+            store.insert_doc(name, &doc.transact()).unwrap();
+        }
+
+        let (ok, out) = run(&store, Command::List);
+        assert!(ok);
+        assert_eq!(out, "a\nb\n");
+
+        let (_, out) = run(&store, Command::Stats { name: None });
+        assert!(out.starts_with("documents: 2\n"));
+
+        let (_, out) = run(
+            &store,
+            Command::Dump {
+                name: "b".to_string(),
+            },
+        );
+        let json: serde_json::Value = serde_json::from_str(&out).unwrap();
+        assert_eq!(json, serde_json::json!({ "text": "b" }));
+
+        let (ok, out) = run(&store, Command::Verify);
+        assert!(ok);
+        assert_eq!(out, "documents: 2\n");
+
+        let mut out = Vec::new();
+        let command = Command::Dump {
+            name: "missing".to_string(),
+        };
+        assert!(execute(&store, &command, &mut out).is_err());
+    }
+
+    #[test]
+    fn export_import() {
+        let path = std::env::temp_dir().join("yrs-kv-export_import.bin");
+        let source = MemKVStore::new();
+        let doc = Doc::new();
+        let text = doc.get_or_insert_text("text");
+        text.push(&mut doc.transact_mut(), "hello");
+        source.insert_doc("doc", &doc.transact()).unwrap();
+
+        let (_, out) = run(&source, Command::Export { file: path.clone() });
+        assert_eq!(out, "exported: 1\n");
+
+        let target = MemKVStore::new();
+        let (_, out) = run(&target, Command::Import { file: path.clone() });
+        assert_eq!(out, "imported: 1\n");
+        assert!(target.doc_exists("doc").unwrap());
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,105 @@
+//! Command line tool for inspecting and managing Yrs document stores. It can list persisted
+//! documents, show their statistics, dump their content as JSON, flush pending updates, remove
+//! orphaned entries, move documents between stores and verify store integrity.
+//!
+//! ```text
+//! yrs-kv --backend sled --path ./data list
+//! yrs-kv --backend sled --path ./data dump my-doc
+//! yrs-kv --backend sled --path ./data export dump.bin
+//! yrs-kv --backend rocksdb --path ./other import dump.bin
+//! ```
+//!
+//! Supported backends are selected with cargo features: `fs` and `sled` (enabled by default),
+//! `rocksdb` and `lmdb`.
+
+mod commands;
+
+use crate::commands::{execute, Command};
+use clap::{Parser, ValueEnum};
+use std::path::PathBuf;
+use yrs_kvstore::error::Error;
+
+#[derive(Debug, Parser)]
+#[command(name = "yrs-kv", version, about)]
+struct Cli {
+    /// Backend used to open the store.
+    #[arg(short, long, value_enum)]
+    backend: Backend,
+    /// Path to the database directory.
+    #[arg(short, long)]
+    path: PathBuf,
+    /// Name of the sled tree or LMDB database holding documents.
+    #[arg(long, default_value = "yrs")]
+    table: String,
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum Backend {
+    #[cfg(feature = "fs")]
+    Fs,
+    #[cfg(feature = "sled")]
+    Sled,
+    #[cfg(feature = "rocksdb")]
+    Rocksdb,
+    #[cfg(feature = "lmdb")]
+    Lmdb,
+}
+
+fn main() {
+    let cli = Cli::parse();
+    match run(&cli) {
+        Ok(true) => {}
+        Ok(false) => std::process::exit(1),
+        Err(e) => {
+            eprintln!("error: {}", e);
+            std::process::exit(2);
+        }
+    }
+}
+
+fn run(cli: &Cli) -> Result<bool, Error> {
+    let out = &mut std::io::stdout();
+    match cli.backend {
+        #[cfg(feature = "fs")]
+        Backend::Fs => {
+            let store = yrs_fs::FsStore::open(&cli.path)?;
+            execute(&store, &cli.command, out)
+        }
+        #[cfg(feature = "sled")]
+        Backend::Sled => {
+            let db = sled::open(&cli.path)?;
+            let store = yrs_sled::SledStore::from(db.open_tree(&cli.table)?);
+            let ok = execute(&store, &cli.command, out)?;
+            db.flush()?;
+            Ok(ok)
+        }
+        #[cfg(feature = "rocksdb")]
+        Backend::Rocksdb => {
+            let db = rocksdb::TransactionDB::open_default(&cli.path)?;
+            let store = yrs_rocksdb::RocksDBStore::from(db.transaction());
+            let ok = execute(&store, &cli.command, out)?;
+            if cli.command.is_write() {
+                store.commit()?;
+            }
+            Ok(ok)
+        }
+        #[cfg(feature = "lmdb")]
+        Backend::Lmdb => {
+            let env = lmdb_rs::Environment::new()
+                .max_dbs(4)
+                .open(&cli.path, 0o777)?;
+            let handle = yrs_lmdb::LmdbStore::open_db(&env, Some(&cli.table))?;
+            let txn = env.new_transaction()?;
+            let ok = {
+                let store = yrs_lmdb::LmdbStore::from(txn.bind(&handle));
+                execute(&store, &cli.command, out)?
+            };
+            if cli.command.is_write() {
+                txn.commit()?;
+            }
+            Ok(ok)
+        }
+    }
+}
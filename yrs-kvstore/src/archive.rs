@@ -0,0 +1,318 @@
+//! Portable archives of a single document (see: [crate::DocOps::export_doc]), which can be used
+//! for offline backups or to move documents between databases using different backends.
+//!
+//! Archive is stored using `{version:1}{flags:1}[{state:buf}][{sv:buf}]{updates}{meta}{times}`
+//! format, where `updates` is a var-int length prefixed list of
+//! `{clock:var}{flags:1}{update:buf}[{origin:buf}]` entries, `meta` is a var-int length prefixed
+//! list of `{key:buf}{value:buf}` entries and `times` is a var-int length prefixed list of
+//! `{clock:var}{timestamp:var}` entries. All values are stored decompressed and without
+//! checksums, so archives don't depend on the compression settings of the store they were
+//! exported from. Archives of version 1 didn't contain `times`, and update origins were added in
+//! version 3.
+//!
+//! Whole database dumps (see: [crate::DocOps::export_all]) are streams of
+//! `{name_len:4}{name}{archive_len:4}{archive}` entries - one per document - with lengths stored
+//! as big endian integers.
+
+use crate::error::{Error, StoreError};
+use crate::{MetaEntry, PendingUpdate};
+use lib0::decoding::{Cursor, Read};
+use lib0::encoding::Write;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Current version of the [DocArchive] binary format.
+pub const ARCHIVE_VERSION: u8 = 3;
+
+const FLAG_STATE: u8 = 0b001;
+const FLAG_STATE_V2: u8 = 0b010;
+const FLAG_STATE_VECTOR: u8 = 0b100;
+
+const FLAG_UPDATE_V2: u8 = 0b01;
+const FLAG_UPDATE_ORIGIN: u8 = 0b10;
+
+/// Snapshot of all data persisted for a single document: its state, state vector, pending
+/// updates and metadata. Snapshots, history, subdocument links, expiry and awareness state are
+/// not part of an archive.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DocArchive {
+    /// Document state, if it has been stored.
+    pub state: Option<Box<[u8]>>,
+    /// Flag determining if document state has been encoded using lib0 v2 encoding.
+    pub state_v2: bool,
+    /// Document state vector (lib0 v1 encoding), if it has been stored.
+    pub state_vector: Option<Box<[u8]>>,
+    /// Pending updates, which have not been merged into the document state yet.
+    pub updates: Vec<PendingUpdate>,
+    /// Metadata entries as `(key, value)` pairs.
+    pub meta: Vec<MetaEntry>,
+    /// Timestamps of pending updates as `(clock, timestamp)` pairs (see:
+    /// [crate::options::StoreOptions::update_timestamps]).
+    pub update_timestamps: Vec<(u32, u64)>,
+}
+
+impl DocArchive {
+    /// Serializes current archive into a binary blob.
+    pub fn encode(&self) -> Vec<u8> {
+        let mut buf = Vec::new();
+        let mut flags = 0;
+        if self.state.is_some() {
+            flags |= FLAG_STATE;
+        }
+        if self.state_v2 {
+            flags |= FLAG_STATE_V2;
+        }
+        if self.state_vector.is_some() {
+            flags |= FLAG_STATE_VECTOR;
+        }
+        buf.write_u8(ARCHIVE_VERSION);
+        buf.write_u8(flags);
+        if let Some(state) = &self.state {
+            buf.write_buf(state);
+        }
+        if let Some(sv) = &self.state_vector {
+            buf.write_buf(sv);
+        }
+        buf.write_var(self.updates.len());
+        for u in self.updates.iter() {
+            let mut flags = 0;
+            if u.v2 {
+                flags |= FLAG_UPDATE_V2;
+            }
+            if u.origin.is_some() {
+                flags |= FLAG_UPDATE_ORIGIN;
+            }
+            buf.write_var(u.clock);
+            buf.write_u8(flags);
+            buf.write_buf(&u.update);
+            if let Some(origin) = &u.origin {
+                buf.write_buf(origin);
+            }
+        }
+        buf.write_var(self.meta.len());
+        for (key, value) in self.meta.iter() {
+            buf.write_buf(key);
+            buf.write_buf(value);
+        }
+        buf.write_var(self.update_timestamps.len());
+        for (clock, timestamp) in self.update_timestamps.iter() {
+            buf.write_var(*clock);
+            buf.write_var(*timestamp);
+        }
+        buf
+    }
+
+    /// Deserializes an archive from a binary blob produced by [DocArchive::encode].
+    ///
+    /// Returns [StoreError::UnsupportedArchiveVersion] if archive has been produced using
+    /// a newer version of the archive format.
+    pub fn decode(data: &[u8]) -> Result<Self, Error> {
+        let mut cursor = Cursor::new(data);
+        let version = cursor.read_u8()?;
+        if version == 0 || version > ARCHIVE_VERSION {
+            return Err(StoreError::UnsupportedArchiveVersion(version).into());
+        }
+        let flags = cursor.read_u8()?;
+        let state = if flags & FLAG_STATE != 0 {
+            Some(cursor.read_buf()?.into())
+        } else {
+            None
+        };
+        let state_vector = if flags & FLAG_STATE_VECTOR != 0 {
+            Some(cursor.read_buf()?.into())
+        } else {
+            None
+        };
+        let len: usize = cursor.read_var()?;
+        let mut updates = Vec::with_capacity(len);
+        for _ in 0..len {
+            let clock = cursor.read_var()?;
+            let flags = cursor.read_u8()?;
+            let update = cursor.read_buf()?.into();
+            let origin = if flags & FLAG_UPDATE_ORIGIN != 0 {
+                Some(cursor.read_buf()?.into())
+            } else {
+                None
+            };
+            updates.push(PendingUpdate {
+                clock,
+                update,
+                v2: flags & FLAG_UPDATE_V2 != 0,
+                origin,
+            });
+        }
+        let len: usize = cursor.read_var()?;
+        let mut meta = Vec::with_capacity(len);
+        for _ in 0..len {
+            let key = cursor.read_buf()?.into();
+            let value = cursor.read_buf()?.into();
+            meta.push((key, value));
+        }
+        let mut update_timestamps = Vec::new();
+        if version >= 2 {
+            let len: usize = cursor.read_var()?;
+            for _ in 0..len {
+                let clock = cursor.read_var()?;
+                let timestamp = cursor.read_var()?;
+                update_timestamps.push((clock, timestamp));
+            }
+        }
+        Ok(DocArchive {
+            state,
+            state_v2: flags & FLAG_STATE_V2 != 0,
+            state_vector,
+            updates,
+            meta,
+            update_timestamps,
+        })
+    }
+}
+
+/// Destination of documents archived using [crate::DocOps::archive_doc], i.e. a directory or an
+/// object storage bucket. Every archive is identified by a location returned from
+/// [ArchiveSink::store], which is kept in a tombstone of an archived document.
+pub trait ArchiveSink {
+    /// Stores an `archive` of a document with a given `name` and returns its location.
+    fn store(&self, name: &[u8], archive: &DocArchive) -> Result<Vec<u8>, Error>;
+
+    /// Returns an archive stored under a given `location` or `None` if it was not found.
+    fn load(&self, location: &[u8]) -> Result<Option<DocArchive>, Error>;
+
+    /// Removes an archive stored under a given `location`, once its document has been restored.
+    fn remove(&self, location: &[u8]) -> Result<(), Error>;
+}
+
+/// [ArchiveSink] which stores archives as files within a given directory. Location of an archive
+/// is its file name, which is a hex-encoded document name.
+#[derive(Debug, Clone)]
+pub struct DirArchiveSink {
+    dir: PathBuf,
+}
+
+impl DirArchiveSink {
+    /// Creates a new sink, which stores archives in a given `dir`. Directory is created if it
+    /// didn't exist.
+    pub fn new<P: AsRef<Path>>(dir: P) -> io::Result<Self> {
+        let dir = dir.as_ref().to_path_buf();
+        std::fs::create_dir_all(&dir)?;
+        Ok(DirArchiveSink { dir })
+    }
+
+    fn path(&self, location: &[u8]) -> Result<PathBuf, Error> {
+        let file_name = std::str::from_utf8(location)?;
+        Ok(self.dir.join(file_name))
+    }
+}
+
+impl ArchiveSink for DirArchiveSink {
+    fn store(&self, name: &[u8], archive: &DocArchive) -> Result<Vec<u8>, Error> {
+        let mut file_name: String = name.iter().map(|b| format!("{:02x}", b)).collect();
+        file_name.push_str(".ydoc");
+        let path = self.dir.join(&file_name);
+        // write to a temporary file first, so that a crash never leaves a partial archive behind
+        let tmp = self.dir.join(format!(".{}.tmp", file_name));
+        std::fs::write(&tmp, archive.encode())?;
+        std::fs::rename(&tmp, &path)?;
+        Ok(file_name.into_bytes())
+    }
+
+    fn load(&self, location: &[u8]) -> Result<Option<DocArchive>, Error> {
+        match std::fs::read(self.path(location)?) {
+            Ok(data) => Ok(Some(DocArchive::decode(&data)?)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn remove(&self, location: &[u8]) -> Result<(), Error> {
+        match std::fs::remove_file(self.path(location)?) {
+            Ok(()) => Ok(()),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
+/// Writes an entry of a document with a given `name` into a database dump.
+pub(crate) fn write_dump_entry<W: io::Write>(
+    writer: &mut W,
+    name: &[u8],
+    archive: &DocArchive,
+) -> Result<(), Error> {
+    let archive = archive.encode();
+    writer.write_all(&(name.len() as u32).to_be_bytes())?;
+    writer.write_all(name)?;
+    writer.write_all(&(archive.len() as u32).to_be_bytes())?;
+    writer.write_all(&archive)?;
+    Ok(())
+}
+
+/// Reads the next document entry of a database dump. Returns `None` once the end of the stream
+/// has been reached.
+pub(crate) fn read_dump_entry<R: io::Read>(
+    reader: &mut R,
+) -> Result<Option<(Vec<u8>, DocArchive)>, Error> {
+    let mut len = [0u8; 4];
+    let mut read = 0;
+    while read < len.len() {
+        match reader.read(&mut len[read..]) {
+            // stream is allowed to end only between entries
+            Ok(0) if read == 0 => return Ok(None),
+            Ok(0) => return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into()),
+            Ok(n) => read += n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e.into()),
+        }
+    }
+    let mut name = vec![0u8; u32::from_be_bytes(len) as usize];
+    reader.read_exact(&mut name)?;
+    reader.read_exact(&mut len)?;
+    let mut archive = vec![0u8; u32::from_be_bytes(len) as usize];
+    reader.read_exact(&mut archive)?;
+    Ok(Some((name, DocArchive::decode(&archive)?)))
+}
+
+#[cfg(test)]
+mod test {
+    use crate::archive::DocArchive;
+    use crate::PendingUpdate;
+
+    #[test]
+    fn archive_roundtrip() {
+        let archive = DocArchive {
+            state: Some(vec![1, 2, 3].into()),
+            state_v2: true,
+            state_vector: None,
+            updates: vec![
+                PendingUpdate {
+                    clock: 1,
+                    update: vec![4, 5].into(),
+                    v2: false,
+                    origin: None,
+                },
+                PendingUpdate {
+                    clock: 300,
+                    update: vec![6].into(),
+                    v2: true,
+                    origin: Some(b"alice".to_vec().into()),
+                },
+            ],
+            meta: vec![(b"key".to_vec().into(), b"value".to_vec().into())],
+            update_timestamps: vec![(1, 1_700_000_000_000)],
+        };
+        let decoded = DocArchive::decode(&archive.encode()).unwrap();
+        assert_eq!(decoded, archive);
+
+        let mut data = archive.encode();
+        data[0] = 4;
+        assert!(DocArchive::decode(&data).is_err());
+
+        // version 1 archives end right after metadata entries
+        let mut data = archive.encode();
+        data[0] = 1;
+        data.truncate(data.len() - 8);
+        let decoded = DocArchive::decode(&data).unwrap();
+        assert!(decoded.update_timestamps.is_empty());
+        assert_eq!(decoded.meta, archive.meta);
+    }
+}
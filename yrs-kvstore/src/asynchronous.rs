@@ -0,0 +1,660 @@
+use crate::batch::{BatchOp, WriteBatch};
+use crate::chunk::{self, Manifest};
+use crate::error::{Error, StoreError};
+use crate::keys::{
+    key_doc, key_doc_end, key_doc_start, key_doc_v2, key_flush_marker, key_meta, key_meta_end,
+    key_meta_start, key_oid, key_state_vector, key_state_vector_clock, key_update, key_update_v2,
+    oid_from_bytes, update_clock, update_encoding, Key, ENCODING_V2, KEYSPACE_DOC, KEYSPACE_FLUSH,
+    KEYSPACE_OID, KEY_OID_COUNTER, OID, SUB_KEY_POS, SUB_UPDATE, V1,
+};
+use crate::{
+    decode_oid, decode_value, flush_marker, parse_flush_marker, DocsNameIter, KVEntry, MetadataIter,
+};
+use async_trait::async_trait;
+use std::borrow::Cow;
+use yrs::updates::decoder::Decode;
+use yrs::updates::encoder::Encode;
+use yrs::{Doc, ReadTxn, StateVector, Transact, TransactionMut, Update};
+
+/// An asynchronous equivalent of [crate::KVStore] trait. It should be implemented by key-value
+/// stores which expose non-blocking API (i.e. tikv, FoundationDB or async SQL drivers) in order
+/// to auto-implement features provided by [AsyncDocOps] trait.
+#[async_trait(?Send)]
+pub trait AsyncKVStore<'a> {
+    /// Error type returned from the implementation.
+    type Error: std::error::Error;
+    /// Cursor type used to iterate over the ordered range of key-value entries.
+    type Cursor: Iterator<Item = Self::Entry>;
+    /// Entry type returned by cursor.
+    type Entry: KVEntry;
+    /// Type returned from the implementation. Different key-value stores have different
+    /// abstractions over the binary data they use.
+    type Return: AsRef<[u8]>;
+
+    /// Return a value stored under given `key` or `None` if key was not found.
+    async fn get(&self, key: &[u8]) -> Result<Option<Self::Return>, Self::Error>;
+
+    /// Insert a new `value` under given `key` or replace an existing value with new one if
+    /// entry with that `key` already existed.
+    async fn upsert(&self, key: &[u8], value: &[u8]) -> Result<(), Self::Error>;
+
+    /// Return a value stored under the given `key` if it exists.
+    async fn remove(&self, key: &[u8]) -> Result<(), Self::Error>;
+
+    /// Remove all keys between `from`..=`to` range of keys.
+    async fn remove_range(&self, from: &[u8], to: &[u8]) -> Result<(), Self::Error>;
+
+    /// Return an iterator over all entries between `from`..=`to` range of keys.
+    async fn iter_range(&self, from: &[u8], to: &[u8]) -> Result<Self::Cursor, Self::Error>;
+
+    /// Looks into the last entry value prior to a given key. The provided key parameter may not
+    /// exist and it's used only to establish cursor position in ordered key collection.
+    ///
+    /// In example: in a key collection of `{1,2,5,7}`, this method with the key parameter of `4`
+    /// should return value of `2`.
+    async fn peek_back(&self, key: &[u8]) -> Result<Option<Self::Entry>, Self::Error>;
+}
+
+/// An asynchronous equivalent of [crate::DocOps] trait, automatically implementing core operations
+/// over the Yrs document on top of [AsyncKVStore]. It uses the same key layout as its synchronous
+/// counterpart.
+///
+/// Store settings (see: [crate::options::StoreOptions]) don't apply to asynchronous stores. Values
+/// are written without compression or codecs, and features like automatic compaction are not
+/// available.
+#[async_trait(?Send)]
+pub trait AsyncDocOps<'a>: AsyncKVStore<'a> + Sized
+where
+    Error: From<<Self as AsyncKVStore<'a>>::Error>,
+{
+    /// Called before a new OID is allocated for a document, which has not been persisted before.
+    /// See: [crate::DocOps::lock_oid_allocation].
+    ///
+    /// By default this method does nothing.
+    async fn lock_oid_allocation(&self) -> Result<(), Error> {
+        Ok(())
+    }
+
+    /// Returns a maximum size in bytes of a document state stored under a single key. Larger
+    /// document states are split into chunks. See: [crate::options::StoreOptions::doc_chunk_size].
+    ///
+    /// By default chunking is disabled and 0 is returned.
+    fn doc_chunk_size(&self) -> usize {
+        0
+    }
+
+    /// Inserts or updates a document given it's read transaction and name. lib0 v1 encoding is
+    /// used for storing the document.
+    ///
+    /// This feature requires a write capabilities from the database transaction.
+    async fn insert_doc<K: AsRef<[u8]> + ?Sized, T: ReadTxn>(
+        &self,
+        name: &K,
+        txn: &T,
+    ) -> Result<(), Error> {
+        let doc_state = txn.encode_diff_v1(&StateVector::default());
+        let state_vector = txn.state_vector().encode_v1();
+        self.insert_doc_raw_v1(name.as_ref(), &doc_state, &state_vector)
+            .await
+    }
+
+    /// Inserts or updates a document given it's binary update and state vector. lib0 v1 encoding is
+    /// assumed as a format for storing the document.
+    ///
+    /// This feature requires a write capabilities from the database transaction.
+    async fn insert_doc_raw_v1(
+        &self,
+        name: &[u8],
+        doc_state_v1: &[u8],
+        doc_sv_v1: &[u8],
+    ) -> Result<(), Error> {
+        let oid = get_or_create_oid(self, name).await?;
+        insert_inner_v1(self, oid, doc_state_v1, doc_sv_v1).await
+    }
+
+    /// Inserts or updates a document given it's binary update and state vector. lib0 v2 encoding is
+    /// assumed as a format for storing the document.
+    ///
+    /// This feature requires a write capabilities from the database transaction.
+    async fn insert_doc_raw_v2(
+        &self,
+        name: &[u8],
+        doc_state_v2: &[u8],
+        doc_sv_v2: &[u8],
+    ) -> Result<(), Error> {
+        let oid = get_or_create_oid(self, name).await?;
+        insert_inner_v2(self, oid, doc_state_v2, doc_sv_v2).await
+    }
+
+    /// Loads the document state stored in current database under given document `name` into
+    /// in-memory Yrs document using provided [TransactionMut]. This includes potential update
+    /// entries that may not have been merged with the main document state yet.
+    ///
+    /// This feature requires only a read capabilities from the database transaction.
+    async fn load_doc<K: AsRef<[u8]> + ?Sized>(
+        &self,
+        name: &K,
+        txn: &mut TransactionMut,
+    ) -> Result<bool, Error> {
+        if let Some(oid) = get_oid(self, name.as_ref()).await? {
+            let loaded = load_doc(self, oid, txn).await?;
+            Ok(loaded != 0)
+        } else {
+            Ok(false)
+        }
+    }
+
+    /// Merges all updates stored via [Self::push_update] that were detached from the main document
+    /// state, updates the document and its state vector and finally prunes the updates that have
+    /// been integrated this way. Returns the [Doc] with the most recent state produced this way.
+    ///
+    /// This feature requires a write capabilities from the database transaction.
+    async fn flush_doc<K: AsRef<[u8]> + ?Sized>(&self, name: &K) -> Result<Option<Doc>, Error> {
+        self.flush_doc_with(name, yrs::Options::default()).await
+    }
+
+    /// Merges all updates stored via [Self::push_update] that were detached from the main document
+    /// state, updates the document and its state vector and finally prunes the updates that have
+    /// been integrated this way. `options` are used to drive the details of integration process.
+    ///
+    /// This feature requires a write capabilities from the database transaction.
+    async fn flush_doc_with<K: AsRef<[u8]> + ?Sized>(
+        &self,
+        name: &K,
+        options: yrs::Options,
+    ) -> Result<Option<Doc>, Error> {
+        if let Some(oid) = get_oid(self, name.as_ref()).await? {
+            flush_doc(self, oid, options).await
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Returns the [StateVector] stored directly for the document with a given `name`.
+    /// Returns `None` if the state vector was not stored. A second tuple parameter boolean informs
+    /// if returned value is up to date.
+    ///
+    /// This feature requires only the read capabilities from the database transaction.
+    async fn get_state_vector<K: AsRef<[u8]> + ?Sized>(
+        &self,
+        name: &K,
+    ) -> Result<(Option<StateVector>, bool), Error> {
+        if let Some(oid) = get_oid(self, name.as_ref()).await? {
+            let key = key_state_vector(oid);
+            let data = self.get(&key).await?;
+            let sv = if let Some(data) = data {
+                let state_vector = StateVector::decode_v1(data.as_ref())?;
+                Some(state_vector)
+            } else {
+                None
+            };
+            let update_range_start = key_update(oid, 0);
+            let update_range_end = key_update(oid, u32::MAX);
+            let mut iter = self
+                .iter_range(&update_range_start, &update_range_end)
+                .await?;
+            let up_to_date = iter.next().is_none();
+            Ok((sv, up_to_date))
+        } else {
+            Ok((None, true))
+        }
+    }
+
+    /// Appends new update without integrating it directly into document store. Updates are
+    /// assumed to be serialized using lib0 v1 encoding.
+    ///
+    /// This feature requires a write capabilities from the database transaction.
+    async fn push_update<K: AsRef<[u8]> + ?Sized>(
+        &self,
+        name: &K,
+        update: &[u8],
+    ) -> Result<u32, Error> {
+        let oid = get_or_create_oid(self, name.as_ref()).await?;
+        let clock = next_clock(self, oid).await?;
+        let update_key = key_update(oid, clock);
+        self.upsert(&update_key, update).await?;
+        Ok(clock)
+    }
+
+    /// Appends new update without integrating it directly into document store. Updates are
+    /// assumed to be serialized using lib0 v2 encoding.
+    ///
+    /// This feature requires a write capabilities from the database transaction.
+    async fn push_update_v2<K: AsRef<[u8]> + ?Sized>(
+        &self,
+        name: &K,
+        update: &[u8],
+    ) -> Result<u32, Error> {
+        let oid = get_or_create_oid(self, name.as_ref()).await?;
+        let clock = next_clock(self, oid).await?;
+        let update_key = key_update_v2(oid, clock);
+        self.upsert(&update_key, update).await?;
+        Ok(clock)
+    }
+
+    /// Returns an update (encoded using lib0 v1 encoding) which contains all new changes that
+    /// happened since provided state vector for a given document.
+    ///
+    /// This feature requires only the read capabilities from the database transaction.
+    async fn get_diff<K: AsRef<[u8]> + ?Sized>(
+        &self,
+        name: &K,
+        sv: &StateVector,
+    ) -> Result<Option<Vec<u8>>, Error> {
+        let doc = Doc::new();
+        let found = {
+            let mut txn = doc.transact_mut();
+            self.load_doc(name, &mut txn).await?
+        };
+        if found {
+            Ok(Some(doc.transact().encode_diff_v1(sv)))
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Returns an update (encoded using lib0 v2 encoding) which contains all new changes that
+    /// happened since provided state vector for a given document.
+    ///
+    /// This feature requires only the read capabilities from the database transaction.
+    async fn get_diff_v2<K: AsRef<[u8]> + ?Sized>(
+        &self,
+        name: &K,
+        sv: &StateVector,
+    ) -> Result<Option<Vec<u8>>, Error> {
+        let doc = Doc::new();
+        let found = {
+            let mut txn = doc.transact_mut();
+            self.load_doc(name, &mut txn).await?
+        };
+        if found {
+            Ok(Some(doc.transact().encode_diff_v2(sv)))
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Removes all data associated with the current document (including its updates and metadata).
+    ///
+    /// This feature requires a write capabilities from the database transaction.
+    async fn clear_doc<K: AsRef<[u8]> + ?Sized>(&self, name: &K) -> Result<(), Error> {
+        if let Some(oid) = get_oid(self, name.as_ref()).await? {
+            let oid_key = key_oid(name.as_ref());
+            self.remove(&oid_key).await?;
+            let start = key_doc_start(oid);
+            let end = key_doc_end(oid);
+            self.remove_range(&start, &end).await?;
+            self.remove(&key_flush_marker(oid)).await?;
+        }
+        Ok(())
+    }
+
+    /// Returns a metadata value stored under its metadata `key` for a document with given `name`.
+    ///
+    /// This feature requires only the read capabilities from the database transaction.
+    async fn get_meta<K1: AsRef<[u8]> + ?Sized, K2: AsRef<[u8]> + ?Sized>(
+        &self,
+        name: &K1,
+        meta_key: &K2,
+    ) -> Result<Option<Self::Return>, Error> {
+        if let Some(oid) = get_oid(self, name.as_ref()).await? {
+            let key = key_meta(oid, meta_key.as_ref());
+            Ok(self.get(&key).await?)
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Inserts or updates new `meta` value stored under its metadata `key` for a document with
+    /// given `name`.
+    ///
+    /// This feature requires write capabilities from the database transaction.
+    async fn insert_meta<K1: AsRef<[u8]> + ?Sized, K2: AsRef<[u8]> + ?Sized>(
+        &self,
+        name: &K1,
+        meta_key: &K2,
+        meta: &[u8],
+    ) -> Result<(), Error> {
+        let oid = get_or_create_oid(self, name.as_ref()).await?;
+        let key = key_meta(oid, meta_key.as_ref());
+        self.upsert(&key, meta).await?;
+        Ok(())
+    }
+
+    /// Removes an metadata entry stored under given metadata `key` for a document with provided `name`.
+    ///
+    /// This feature requires write capabilities from the database transaction.
+    async fn remove_meta<K1: AsRef<[u8]> + ?Sized, K2: AsRef<[u8]> + ?Sized>(
+        &self,
+        name: &K1,
+        meta_key: &K2,
+    ) -> Result<(), Error> {
+        if let Some(oid) = get_oid(self, name.as_ref()).await? {
+            let key = key_meta(oid, meta_key.as_ref());
+            self.remove(&key).await?;
+        }
+        Ok(())
+    }
+
+    /// Finishes document flushes (see: [Self::flush_doc]), which have been interrupted i.e. by
+    /// a process crash. It should be called right after the database is opened, before any other
+    /// writes take place. Returns a number of recovered documents.
+    ///
+    /// This feature requires a write capabilities from the database transaction.
+    async fn recover(&self) -> Result<usize, Error> {
+        let start = Key::from_const([V1, KEYSPACE_FLUSH]);
+        let end = Key::from_const([V1, KEYSPACE_FLUSH + 1]);
+        let mut interrupted = Vec::new();
+        for e in self.iter_range(&start, &end).await? {
+            let oid = oid_from_bytes(&e.key()[2..]);
+            let (up_to, _) = parse_flush_marker(e.key(), e.value())?;
+            interrupted.push((oid, up_to));
+        }
+        for &(oid, up_to) in interrupted.iter() {
+            let doc = Doc::new();
+            let found = {
+                let mut txn = doc.transact_mut();
+                load_doc(self, oid, &mut txn).await?
+            };
+            if found == 0 {
+                // document has been removed in the meantime
+                self.remove(&key_flush_marker(oid)).await?;
+            } else {
+                write_flushed(self, oid, &doc, up_to).await?;
+            }
+        }
+        Ok(interrupted.len())
+    }
+
+    /// Returns an iterator over all document names stored in current database.
+    async fn iter_docs(&self) -> Result<DocsNameIter<Self::Cursor, Self::Entry>, Error> {
+        let start = Key::from_const([V1, KEYSPACE_OID]);
+        let end = Key::from_const([V1, KEYSPACE_DOC]);
+        let cursor = self.iter_range(&start, &end).await?;
+        Ok(DocsNameIter { cursor, start, end })
+    }
+
+    /// Returns an iterator over all metadata entries stored for a given document.
+    async fn iter_meta<K: AsRef<[u8]> + ?Sized>(
+        &self,
+        doc_name: &K,
+    ) -> Result<MetadataIter<Self::Cursor, Self::Entry>, Error> {
+        if let Some(oid) = get_oid(self, doc_name.as_ref()).await? {
+            let start = key_meta_start(oid).to_vec();
+            let end = key_meta_end(oid).to_vec();
+            let cursor = self.iter_range(&start, &end).await?;
+            Ok(MetadataIter(Some((cursor, start, end)), None))
+        } else {
+            Ok(MetadataIter(None, None))
+        }
+    }
+}
+
+async fn get_oid<'a, DB: AsyncDocOps<'a>>(db: &DB, name: &[u8]) -> Result<Option<OID>, Error>
+where
+    Error: From<<DB as AsyncKVStore<'a>>::Error>,
+{
+    let key = key_oid(name);
+    let value = db.get(&key).await?;
+    if let Some(value) = value {
+        Ok(Some(oid_from_bytes(value.as_ref())))
+    } else {
+        Ok(None)
+    }
+}
+
+async fn get_or_create_oid<'a, DB: AsyncDocOps<'a>>(db: &DB, name: &[u8]) -> Result<OID, Error>
+where
+    Error: From<<DB as AsyncKVStore<'a>>::Error>,
+{
+    if let Some(oid) = get_oid(db, name).await? {
+        Ok(oid)
+    } else {
+        db.lock_oid_allocation().await?;
+        if let Some(oid) = get_oid(db, name).await? {
+            // OID has been allocated by concurrent writer in the meantime
+            return Ok(oid);
+        }
+        // OIDs are always allocated using crate::OidAllocation::Counter strategy
+        let last_oid = if let Some(value) = db.get(&KEY_OID_COUNTER).await? {
+            decode_oid(&KEY_OID_COUNTER, value.as_ref())?
+        } else {
+            // see: crate::last_allocated_oid
+            let start = Key::from_const([V1, KEYSPACE_OID]);
+            let end = Key::from_const([V1, KEYSPACE_DOC]);
+            let mut last_oid = 0;
+            for e in db.iter_range(&start, &end).await? {
+                last_oid = last_oid.max(decode_oid(e.key(), e.value())?);
+            }
+            last_oid
+        };
+        let new_oid = last_oid
+            .checked_add(1)
+            .ok_or(StoreError::OidSpaceExhausted)?;
+        db.upsert(&KEY_OID_COUNTER, new_oid.to_be_bytes().as_ref())
+            .await?;
+        let key = key_oid(name);
+        db.upsert(&key, new_oid.to_be_bytes().as_ref()).await?;
+        Ok(new_oid)
+    }
+}
+
+async fn next_clock<'a, DB: AsyncDocOps<'a>>(db: &DB, oid: OID) -> Result<u32, Error>
+where
+    Error: From<<DB as AsyncKVStore<'a>>::Error>,
+{
+    let mut last = last_clock(db, oid).await?;
+    if last == u32::MAX {
+        // see: crate::reserve_clocks
+        flush_doc(db, oid, yrs::Options::default()).await?;
+        last = last_clock(db, oid).await?;
+        if last == u32::MAX {
+            return Err(StoreError::UpdateLogFull { oid }.into());
+        }
+    }
+    Ok(last + 1)
+}
+
+async fn last_clock<'a, DB: AsyncDocOps<'a>>(db: &DB, oid: OID) -> Result<u32, Error>
+where
+    Error: From<<DB as AsyncKVStore<'a>>::Error>,
+{
+    // metadata keys are placed right after update keys
+    let end = key_meta_start(oid);
+    match db.peek_back(&end).await? {
+        // peek back may return a key of a different kind (i.e. state vector key), make sure
+        // that it's an update key of the same document
+        Some(e)
+            if e.key().len() == SUB_KEY_POS + 6
+                && e.key().starts_with(&end[..SUB_KEY_POS])
+                && e.key()[SUB_KEY_POS] == SUB_UPDATE =>
+        {
+            Ok(update_clock(e.key()))
+        }
+        _ => Ok(0),
+    }
+}
+
+async fn load_doc<'a, DB: AsyncDocOps<'a>>(
+    db: &DB,
+    oid: OID,
+    txn: &mut TransactionMut<'_>,
+) -> Result<u32, Error>
+where
+    Error: From<<DB as AsyncKVStore<'a>>::Error>,
+{
+    let mut found = false;
+    if let Some(doc_state) = db.get(&key_doc(oid)).await? {
+        let doc_state = resolve_state(db, oid, &key_doc(oid), doc_state.as_ref()).await?;
+        let update = Update::decode_v1(&decode_value(None, &key_doc(oid), &doc_state)?)?;
+        txn.apply_update(update)?;
+        found = true;
+    } else if let Some(doc_state) = db.get(&key_doc_v2(oid)).await? {
+        let doc_state = resolve_state(db, oid, &key_doc_v2(oid), doc_state.as_ref()).await?;
+        let update = Update::decode_v2(&decode_value(None, &key_doc_v2(oid), &doc_state)?)?;
+        txn.apply_update(update)?;
+        found = true;
+    }
+    let mut update_count = 0;
+    {
+        let update_key_start = key_update(oid, 0);
+        let update_key_end = key_update(oid, u32::MAX);
+        let iter = db.iter_range(&update_key_start, &update_key_end).await?;
+        for e in iter {
+            let value = decode_value(None, e.key(), e.value())?;
+            let update = if update_encoding(e.key()) == ENCODING_V2 {
+                Update::decode_v2(&value)?
+            } else {
+                Update::decode_v1(&value)?
+            };
+            txn.apply_update(update)?;
+            update_count += 1;
+        }
+    }
+    if found {
+        update_count |= 1 << 31; // mark hi bit to note that document core state was used
+    }
+    Ok(update_count)
+}
+
+async fn flush_doc<'a, DB: AsyncDocOps<'a>>(
+    db: &DB,
+    oid: OID,
+    options: yrs::Options,
+) -> Result<Option<Doc>, Error>
+where
+    Error: From<<DB as AsyncKVStore<'a>>::Error>,
+{
+    // updates pushed after this point are not pruned, even if they happen to be loaded
+    let up_to = last_clock(db, oid).await?;
+    let doc = Doc::with_options(options);
+    let found = {
+        let mut txn = doc.transact_mut();
+        load_doc(db, oid, &mut txn).await?
+    };
+    if found & !(1 << 31) != 0 {
+        // loaded doc was generated from updates
+        write_flushed(db, oid, &doc, up_to).await?;
+        Ok(Some(doc))
+    } else {
+        Ok(None)
+    }
+}
+
+/// Stores the state of a given `doc` as the new document state, pruning its pending updates up to
+/// `up_to` clock (inclusive). Flush marker is kept for the duration of this operation, so that it
+/// can be finished by [AsyncDocOps::recover] when interrupted (see: [crate::DocOps::recover]).
+async fn write_flushed<'a, DB: AsyncDocOps<'a>>(
+    db: &DB,
+    oid: OID,
+    doc: &Doc,
+    up_to: u32,
+) -> Result<(), Error>
+where
+    Error: From<<DB as AsyncKVStore<'a>>::Error>,
+{
+    let (doc_state, state_vec) = {
+        let txn = doc.transact();
+        let doc_state = txn.encode_state_as_update_v1(&StateVector::default());
+        let state_vec = txn.state_vector().encode_v1();
+        (doc_state, state_vec)
+    };
+
+    let marker = key_flush_marker(oid);
+    db.upsert(&marker, &flush_marker(up_to, None)).await?;
+    insert_inner_v1(db, oid, &doc_state, &state_vec).await?;
+    let start = key_update(oid, 0);
+    let end = key_update_v2(oid, up_to);
+    db.remove_range(&start, &end).await?;
+    db.remove(&marker).await?;
+    Ok(())
+}
+
+/// Returns a document state `value` of a document with a given `oid` stored under a given `key`,
+/// reassembling it from chunks if necessary (see: [crate::chunk]).
+async fn resolve_state<'a, 'v, DB: AsyncDocOps<'a>>(
+    db: &DB,
+    oid: OID,
+    key: &[u8],
+    value: &'v [u8],
+) -> Result<Cow<'v, [u8]>, Error>
+where
+    Error: From<<DB as AsyncKVStore<'a>>::Error>,
+{
+    match Manifest::parse(value) {
+        Some(manifest) => {
+            let (start, end) = chunk::chunk_range(oid, &manifest);
+            let chunks = db.iter_range(&start, &end).await?;
+            Ok(Cow::Owned(manifest.assemble(key, chunks)?))
+        }
+        None => Ok(Cow::Borrowed(value)),
+    }
+}
+
+/// Splits a document state of a document with a given `oid` into chunks if it's too large to be
+/// stored under a single key (see: [AsyncDocOps::doc_chunk_size]). Chunks of the current document
+/// state are removed. Returns a value, which should be stored under the document state key.
+async fn split_state<'a, DB: AsyncDocOps<'a>>(
+    db: &DB,
+    oid: OID,
+    doc_state: &[u8],
+) -> Result<Vec<u8>, Error>
+where
+    Error: From<<DB as AsyncKVStore<'a>>::Error>,
+{
+    let mut batch = WriteBatch::new();
+    for key in [key_doc(oid), key_doc_v2(oid)] {
+        if let Some(value) = db.get(&key).await? {
+            if chunk::is_manifest(value.as_ref()) {
+                chunk::remove(&mut batch, oid);
+                break;
+            }
+        }
+    }
+    let doc_state = chunk::split(&mut batch, oid, doc_state.to_vec(), db.doc_chunk_size());
+    for op in batch {
+        match op {
+            BatchOp::Upsert(key, value) => db.upsert(&key, &value).await?,
+            BatchOp::Remove(key) => db.remove(&key).await?,
+            BatchOp::RemoveRange(from, to) => db.remove_range(&from, &to).await?,
+        }
+    }
+    Ok(doc_state)
+}
+
+async fn insert_inner_v1<'a, DB: AsyncDocOps<'a>>(
+    db: &DB,
+    oid: OID,
+    doc_state_v1: &[u8],
+    doc_sv_v1: &[u8],
+) -> Result<(), Error>
+where
+    Error: From<<DB as AsyncKVStore<'a>>::Error>,
+{
+    let doc_state_v1 = split_state(db, oid, doc_state_v1).await?;
+    db.upsert(&key_doc(oid), &doc_state_v1).await?;
+    db.upsert(&key_state_vector(oid), doc_sv_v1).await?;
+    db.remove(&key_doc_v2(oid)).await?;
+    // new state vector doesn't include remaining pending updates (see:
+    // [crate::options::StoreOptions::track_state_vector])
+    db.remove(&key_state_vector_clock(oid)).await?;
+    Ok(())
+}
+
+async fn insert_inner_v2<'a, DB: AsyncDocOps<'a>>(
+    db: &DB,
+    oid: OID,
+    doc_state_v2: &[u8],
+    doc_sv_v2: &[u8],
+) -> Result<(), Error>
+where
+    Error: From<<DB as AsyncKVStore<'a>>::Error>,
+{
+    let doc_sv_v1 = StateVector::decode_v2(doc_sv_v2)?.encode_v1();
+    let doc_state_v2 = split_state(db, oid, doc_state_v2).await?;
+    db.upsert(&key_doc_v2(oid), &doc_state_v2).await?;
+    db.upsert(&key_state_vector(oid), &doc_sv_v1).await?;
+    db.remove(&key_doc(oid)).await?;
+    db.remove(&key_state_vector_clock(oid)).await?;
+    Ok(())
+}
@@ -0,0 +1,190 @@
+/// A single write operation recorded by the [WriteBatch].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BatchOp {
+    /// Insert or replace a value stored under a given key.
+    Upsert(Vec<u8>, Vec<u8>),
+    /// Remove a value stored under a given key.
+    Remove(Vec<u8>),
+    /// Remove all entries between `from`..=`to` range of keys.
+    RemoveRange(Vec<u8>, Vec<u8>),
+}
+
+/// An ordered collection of write operations, which are meant to be applied together using
+/// [crate::KVStore::commit_batch]. Multi-key operations of [crate::DocOps] (like inserting or
+/// flushing a document) are gathered into a single batch, so that backends supporting atomic batch
+/// writes never persist partial state of these operations.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct WriteBatch {
+    ops: Vec<BatchOp>,
+}
+
+impl WriteBatch {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records an insert or update of a `value` under given `key`.
+    pub fn upsert(&mut self, key: &[u8], value: &[u8]) {
+        self.ops.push(BatchOp::Upsert(key.to_vec(), value.to_vec()));
+    }
+
+    /// Records a removal of a value stored under given `key`.
+    pub fn remove(&mut self, key: &[u8]) {
+        self.ops.push(BatchOp::Remove(key.to_vec()));
+    }
+
+    /// Records a removal of all entries between `from`..=`to` range of keys.
+    pub fn remove_range(&mut self, from: &[u8], to: &[u8]) {
+        self.ops
+            .push(BatchOp::RemoveRange(from.to_vec(), to.to_vec()));
+    }
+
+    /// Returns a number of recorded operations.
+    pub fn len(&self) -> usize {
+        self.ops.len()
+    }
+
+    /// Checks if current batch has no operations recorded.
+    pub fn is_empty(&self) -> bool {
+        self.ops.is_empty()
+    }
+
+    /// Returns an iterator over recorded operations, in order they were recorded.
+    pub fn iter(&self) -> std::slice::Iter<'_, BatchOp> {
+        self.ops.iter()
+    }
+}
+
+impl IntoIterator for WriteBatch {
+    type Item = BatchOp;
+    type IntoIter = std::vec::IntoIter<BatchOp>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.ops.into_iter()
+    }
+}
+
+/// A single document operation recorded by the [MultiDocBatch].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DocOp {
+    /// Append a new pending update to a document (see: [crate::DocOps::push_update]).
+    PushUpdate {
+        name: Vec<u8>,
+        update: Vec<u8>,
+        v2: bool,
+        origin: Option<Vec<u8>>,
+    },
+    /// Insert or update a document metadata entry (see: [crate::DocOps::insert_meta]).
+    InsertMeta {
+        name: Vec<u8>,
+        meta_key: Vec<u8>,
+        meta: Vec<u8>,
+    },
+    /// Remove a document metadata entry (see: [crate::DocOps::remove_meta]).
+    RemoveMeta { name: Vec<u8>, meta_key: Vec<u8> },
+}
+
+/// Builder used to gather updates and metadata changes of multiple documents, which are then
+/// written together using [crate::DocOps::commit_docs]. All of them are applied as a single
+/// [WriteBatch], which makes it possible i.e. to atomically maintain cross-document indexes.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MultiDocBatch {
+    ops: Vec<DocOp>,
+}
+
+impl MultiDocBatch {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records a new update (serialized using lib0 v1 encoding) of a document with given `name`.
+    pub fn push_update<K: AsRef<[u8]> + ?Sized>(&mut self, name: &K, update: &[u8]) -> &mut Self {
+        self.ops.push(DocOp::PushUpdate {
+            name: name.as_ref().to_vec(),
+            update: update.to_vec(),
+            v2: false,
+            origin: None,
+        });
+        self
+    }
+
+    /// Records a new update (serialized using lib0 v1 encoding) of a document with given `name`
+    /// together with its `origin` (see: [crate::DocOps::push_update_with_origin]).
+    pub fn push_update_with_origin<K: AsRef<[u8]> + ?Sized>(
+        &mut self,
+        name: &K,
+        update: &[u8],
+        origin: &[u8],
+    ) -> &mut Self {
+        self.ops.push(DocOp::PushUpdate {
+            name: name.as_ref().to_vec(),
+            update: update.to_vec(),
+            v2: false,
+            origin: Some(origin.to_vec()),
+        });
+        self
+    }
+
+    /// Records a new update (serialized using lib0 v2 encoding) of a document with given `name`.
+    pub fn push_update_v2<K: AsRef<[u8]> + ?Sized>(
+        &mut self,
+        name: &K,
+        update: &[u8],
+    ) -> &mut Self {
+        self.ops.push(DocOp::PushUpdate {
+            name: name.as_ref().to_vec(),
+            update: update.to_vec(),
+            v2: true,
+            origin: None,
+        });
+        self
+    }
+
+    /// Records an insert or update of `meta` value under its metadata key for a document with
+    /// given `name`.
+    pub fn insert_meta<K1: AsRef<[u8]> + ?Sized, K2: AsRef<[u8]> + ?Sized>(
+        &mut self,
+        name: &K1,
+        meta_key: &K2,
+        meta: &[u8],
+    ) -> &mut Self {
+        self.ops.push(DocOp::InsertMeta {
+            name: name.as_ref().to_vec(),
+            meta_key: meta_key.as_ref().to_vec(),
+            meta: meta.to_vec(),
+        });
+        self
+    }
+
+    /// Records a removal of metadata entry for a document with given `name`.
+    pub fn remove_meta<K1: AsRef<[u8]> + ?Sized, K2: AsRef<[u8]> + ?Sized>(
+        &mut self,
+        name: &K1,
+        meta_key: &K2,
+    ) -> &mut Self {
+        self.ops.push(DocOp::RemoveMeta {
+            name: name.as_ref().to_vec(),
+            meta_key: meta_key.as_ref().to_vec(),
+        });
+        self
+    }
+
+    /// Returns a number of recorded operations.
+    pub fn len(&self) -> usize {
+        self.ops.len()
+    }
+
+    /// Checks if current batch has no operations recorded.
+    pub fn is_empty(&self) -> bool {
+        self.ops.is_empty()
+    }
+}
+
+impl IntoIterator for MultiDocBatch {
+    type Item = DocOp;
+    type IntoIter = std::vec::IntoIter<DocOp>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.ops.into_iter()
+    }
+}
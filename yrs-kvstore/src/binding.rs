@@ -0,0 +1,317 @@
+use crate::error::Error;
+use crate::{DocOps, KVStore};
+use std::sync::{Arc, Mutex, Weak};
+use yrs::{Doc, Origin, Transact, TransactionMut, UpdateEvent};
+
+/// Options of a [PersistenceBinding], which determine how often updates of a bound document are
+/// pushed into the store. Updates are buffered in memory until any of the set thresholds is
+/// reached.
+///
+/// Default options don't buffer anything: every update is pushed as soon as its transaction has
+/// been committed.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct BindingOptions {
+    /// Maximum number of updates buffered in memory. Once reached, all of them are pushed into
+    /// the store together (see: [DocOps::push_updates]).
+    pub max_buffered_updates: Option<usize>,
+    /// Maximum number of bytes of all updates buffered in memory. Once reached, all of them are
+    /// pushed into the store together (see: [DocOps::push_updates]).
+    pub max_buffered_bytes: Option<usize>,
+    /// If set, closing the binding (see: [PersistenceBinding::close]) merges all pending updates
+    /// of the document into its state (see: [DocOps::flush_doc]), so that documents detached
+    /// from memory are left in their compacted form.
+    pub flush_on_close: bool,
+}
+
+/// Write-behind persistence of a live [Doc]. Binding subscribes to the document updates and pushes
+/// them into a store as pending updates (see: [DocOps::push_updates]), so that applications don't
+/// need to do so manually after every transaction. Compaction of pushed updates is driven by the
+/// store's [crate::CompactionPolicy].
+///
+/// Since updates are pushed from within the document observer, the store must be owned by the
+/// binding. Stores working over borrowed database transactions can't be bound this way.
+///
+/// Updates, which failed to be pushed, are kept in the buffer and retried together with the next
+/// update. The error itself can be observed by calling [PersistenceBinding::persist]. Dropping the
+/// binding closes it (see: [PersistenceBinding::close]), ignoring any errors.
+pub struct PersistenceBinding<S>
+where
+    S: DocOps<'static> + Send + 'static,
+    Error: From<<S as KVStore<'static>>::Error>,
+{
+    doc: Doc,
+    origin: Origin,
+    state: Arc<Mutex<BindingState<S>>>,
+    closed: bool,
+}
+
+impl<S> PersistenceBinding<S>
+where
+    S: DocOps<'static> + Send + 'static,
+    Error: From<<S as KVStore<'static>>::Error>,
+{
+    /// Binds a given `doc` to a `store` under a given `name`, using default [BindingOptions]. See
+    /// [PersistenceBinding::bind_with] for details.
+    pub fn bind<K: AsRef<[u8]> + ?Sized>(store: S, name: &K, doc: &Doc) -> Result<Self, Error> {
+        Self::bind_with(store, name, doc, BindingOptions::default())
+    }
+
+    /// Binds a given `doc` to a `store` under a given `name`. Document state already persisted
+    /// under that `name` is loaded into the `doc` first (see: [DocOps::load_doc]), then every
+    /// subsequent update of the `doc` is pushed into the store, according to given `options`.
+    pub fn bind_with<K: AsRef<[u8]> + ?Sized>(
+        store: S,
+        name: &K,
+        doc: &Doc,
+        options: BindingOptions,
+    ) -> Result<Self, Error> {
+        let name: Box<[u8]> = name.as_ref().into();
+        store.load_doc(&name, &mut doc.transact_mut())?;
+        let state = Arc::new(Mutex::new(BindingState {
+            store,
+            name,
+            options,
+            buffered: Vec::new(),
+            buffered_bytes: 0,
+        }));
+        let origin = Origin::from(Arc::as_ptr(&state) as usize);
+        let weak: Weak<Mutex<BindingState<S>>> = Arc::downgrade(&state);
+        doc.observe_update_v1(
+            origin.clone(),
+            move |_: &TransactionMut, e: &UpdateEvent| {
+                if let Some(state) = weak.upgrade() {
+                    let mut state = state.lock().unwrap();
+                    state.buffered_bytes += e.update.len();
+                    state.buffered.push(e.update.clone());
+                    if state.is_full() {
+                        // on failure updates stay buffered and are retried with the next one
+                        let _ = state.push_buffered();
+                    }
+                }
+            },
+        )?;
+        Ok(PersistenceBinding {
+            doc: doc.clone(),
+            origin,
+            state,
+            closed: false,
+        })
+    }
+
+    /// Returns a document bound to the store.
+    pub fn doc(&self) -> &Doc {
+        &self.doc
+    }
+
+    /// Returns a number of updates buffered in memory, which have not been pushed into the store
+    /// yet.
+    pub fn buffered_updates(&self) -> usize {
+        self.state.lock().unwrap().buffered.len()
+    }
+
+    /// Pushes all buffered updates into the store right away, regardless of [BindingOptions].
+    pub fn persist(&self) -> Result<(), Error> {
+        self.state.lock().unwrap().push_buffered()
+    }
+
+    /// Calls a given function `f` with a reference to the underlying store. Keep in mind that
+    /// updates of the bound document can't be persisted until `f` returns.
+    pub fn with_store<F, R>(&self, f: F) -> R
+    where
+        F: FnOnce(&S) -> R,
+    {
+        f(&self.state.lock().unwrap().store)
+    }
+
+    /// Unsubscribes from the bound document and pushes all buffered updates into the store. If
+    /// [BindingOptions::flush_on_close] was set, pending updates of the document are flushed
+    /// afterwards.
+    ///
+    /// This method is called automatically when the binding is dropped. Call it explicitly in
+    /// order to handle errors.
+    pub fn close(mut self) -> Result<(), Error> {
+        self.close_mut()
+    }
+
+    fn close_mut(&mut self) -> Result<(), Error> {
+        self.closed = true;
+        // if the document is in the middle of a transaction, callback stays registered, but it
+        // does nothing once the binding state is gone
+        let _ = self.doc.unobserve_update_v1(self.origin.clone());
+        let mut state = self.state.lock().unwrap();
+        state.push_buffered()?;
+        if state.options.flush_on_close {
+            state.store.flush_doc(&state.name)?;
+        }
+        Ok(())
+    }
+}
+
+impl<S> Drop for PersistenceBinding<S>
+where
+    S: DocOps<'static> + Send + 'static,
+    Error: From<<S as KVStore<'static>>::Error>,
+{
+    fn drop(&mut self) {
+        if !self.closed {
+            let _ = self.close_mut();
+        }
+    }
+}
+
+impl<S> std::fmt::Debug for PersistenceBinding<S>
+where
+    S: DocOps<'static> + Send + 'static,
+    Error: From<<S as KVStore<'static>>::Error>,
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let state = self.state.lock().unwrap();
+        f.debug_struct("PersistenceBinding")
+            .field("name", &String::from_utf8_lossy(&state.name))
+            .field("options", &state.options)
+            .field("buffered_updates", &state.buffered.len())
+            .finish()
+    }
+}
+
+struct BindingState<S> {
+    store: S,
+    name: Box<[u8]>,
+    options: BindingOptions,
+    buffered: Vec<Vec<u8>>,
+    buffered_bytes: usize,
+}
+
+impl<S> BindingState<S>
+where
+    S: DocOps<'static>,
+    Error: From<<S as KVStore<'static>>::Error>,
+{
+    fn is_full(&self) -> bool {
+        let BindingOptions {
+            max_buffered_updates,
+            max_buffered_bytes,
+            // flushing is done once the binding is closed (see: [PersistenceBinding::close])
+            flush_on_close: _,
+        } = self.options;
+        match (max_buffered_updates, max_buffered_bytes) {
+            (None, None) => true,
+            (max_updates, max_bytes) => {
+                max_updates.is_some_and(|max| self.buffered.len() >= max)
+                    || max_bytes.is_some_and(|max| self.buffered_bytes >= max)
+            }
+        }
+    }
+
+    fn push_buffered(&mut self) -> Result<(), Error> {
+        if !self.buffered.is_empty() {
+            let updates = self.buffered.iter().map(Vec::as_slice);
+            self.store.push_updates(&self.name, updates)?;
+            self.buffered.clear();
+            self.buffered_bytes = 0;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::binding::{BindingOptions, PersistenceBinding};
+    use crate::memory::MemKVStore;
+    use crate::metrics::{Metrics, StoreMetrics};
+    use crate::{DocOps, KVStore};
+    use std::sync::atomic::{AtomicU32, Ordering};
+    use std::sync::Arc;
+    use std::time::Duration;
+    use yrs::{Doc, GetString, Text, Transact};
+
+    #[test]
+    fn persist_doc_updates() {
+        let doc = Doc::new();
+        let text = doc.get_or_insert_text("text");
+        let binding = PersistenceBinding::bind(MemKVStore::new(), "doc", &doc).unwrap();
+        text.push(&mut doc.transact_mut(), "hello");
+        text.push(&mut doc.transact_mut(), " world");
+        assert_eq!(binding.buffered_updates(), 0);
+
+        binding.with_store(|store| {
+            let loaded = Doc::new();
+            let text = loaded.get_or_insert_text("text");
+            store.load_doc("doc", &mut loaded.transact_mut()).unwrap();
+            let txn = loaded.transact();
+            assert_eq!(text.get_string(&txn), "hello world");
+        });
+    }
+
+    #[test]
+    fn buffer_doc_updates() {
+        let doc = Doc::new();
+        let text = doc.get_or_insert_text("text");
+        let options = BindingOptions {
+            max_buffered_updates: Some(3),
+            ..BindingOptions::default()
+        };
+        let binding =
+            PersistenceBinding::bind_with(MemKVStore::new(), "doc", &doc, options).unwrap();
+        text.push(&mut doc.transact_mut(), "a");
+        text.push(&mut doc.transact_mut(), "b");
+        assert_eq!(binding.buffered_updates(), 2);
+        assert!(!binding.with_store(|store| store.doc_exists("doc").unwrap()));
+
+        text.push(&mut doc.transact_mut(), "c");
+        assert_eq!(binding.buffered_updates(), 0);
+        text.push(&mut doc.transact_mut(), "d");
+        binding.persist().unwrap();
+        assert_eq!(binding.buffered_updates(), 0);
+
+        // binding a new document loads persisted state
+        let loaded = Doc::new();
+        let text = loaded.get_or_insert_text("text");
+        let store = MemKVStore::new();
+        for (key, value) in binding.with_store(MemKVStore::entries) {
+            store.upsert(&key, &value).unwrap();
+        }
+        let _binding = PersistenceBinding::bind(store, "doc", &loaded).unwrap();
+        let txn = loaded.transact();
+        assert_eq!(text.get_string(&txn), "abcd");
+    }
+
+    #[test]
+    fn flush_on_close() {
+        #[derive(Default)]
+        struct Flushes(AtomicU32);
+        impl StoreMetrics for Flushes {
+            fn doc_flushed(&self, _name: &[u8], _bytes_written: u64, _elapsed: Duration) {
+                self.0.fetch_add(1, Ordering::SeqCst);
+            }
+        }
+
+        let flushes = Arc::new(Flushes::default());
+        let metrics = Metrics::from(flushes.clone() as Arc<dyn StoreMetrics>);
+        let doc = Doc::new();
+        let text = doc.get_or_insert_text("text");
+        let options = BindingOptions {
+            max_buffered_updates: Some(10),
+            flush_on_close: true,
+            ..BindingOptions::default()
+        };
+        let store = MemKVStore::new().with_metrics(metrics.clone());
+        let binding = PersistenceBinding::bind_with(store, "doc", &doc, options).unwrap();
+        text.push(&mut doc.transact_mut(), "hello");
+        binding.close().unwrap();
+        assert_eq!(flushes.0.load(Ordering::SeqCst), 1);
+
+        // dropping a binding closes it as well
+        let store = MemKVStore::new().with_metrics(metrics.clone());
+        let binding = PersistenceBinding::bind_with(store, "doc", &doc, options).unwrap();
+        text.push(&mut doc.transact_mut(), " world");
+        drop(binding);
+        assert_eq!(flushes.0.load(Ordering::SeqCst), 2);
+
+        let store = MemKVStore::new().with_metrics(metrics);
+        let binding = PersistenceBinding::bind(store, "doc", &doc).unwrap();
+        text.push(&mut doc.transact_mut(), "!");
+        binding.close().unwrap();
+        assert_eq!(flushes.0.load(Ordering::SeqCst), 2);
+    }
+}
@@ -0,0 +1,188 @@
+//! Content-addressed storage of large document states. When enabled (see:
+//! [crate::options::StoreOptions::blob_threshold]), document states of at least a given size are stored only once
+//! in a separate blob keyspace, addressed by a digest of their content, while the document keyspace
+//! keeps only a reference to them. This way documents sharing the same state, i.e. ones created
+//! from the same template (see: [crate::DocOps::copy_doc]) or imported many times, don't occupy
+//! the storage over and over again.
+//!
+//! References are stored using `{0xFF}{0x00}{0x04}{crc32:4}{len:4}` format, which shares its header
+//! with compressed values (see: [crate::compression]). Blobs keep values in the same format in
+//! which they would be stored inline, together with a number of documents referencing them. Blob
+//! is removed once the last of these documents has been flushed with a different state or cleared
+//! (see: [crate::DocOps::flush_doc] and [crate::DocOps::clear_doc]). A value, which digest collides
+//! with a different blob already stored, is stored inline instead.
+
+use crate::batch::WriteBatch;
+use crate::compression::HEADER;
+use crate::error::{Error, StoreError};
+use crate::keys::{key_blob, key_blob_refs};
+use crate::{DocOps, KVStore};
+use std::collections::HashMap;
+use std::convert::TryInto;
+
+/// Format identifier of values referencing a blob.
+pub const FORMAT_BLOB: u8 = 4;
+
+/// Length of a blob digest: `{crc32:4}{len:4}` of its content.
+const DIGEST_LEN: usize = 8;
+
+/// Length of a value referencing a blob.
+pub const BLOB_REF_LEN: usize = HEADER.len() + 1 + DIGEST_LEN;
+
+/// Checks if a given stored `value` is a reference to a blob.
+pub fn is_blob_ref(value: &[u8]) -> bool {
+    value.len() == BLOB_REF_LEN
+        && value[..HEADER.len()] == HEADER
+        && value[HEADER.len()] == FORMAT_BLOB
+}
+
+/// Returns a digest of a blob referenced by a given stored `value` or `None` if value is not
+/// a blob reference.
+fn referenced_digest(value: &[u8]) -> Option<[u8; DIGEST_LEN]> {
+    if is_blob_ref(value) {
+        value[HEADER.len() + 1..].try_into().ok()
+    } else {
+        None
+    }
+}
+
+fn digest(value: &[u8]) -> [u8; DIGEST_LEN] {
+    let mut digest = [0u8; DIGEST_LEN];
+    digest[..4].copy_from_slice(&crc32fast::hash(value).to_be_bytes());
+    digest[4..].copy_from_slice(&(value.len() as u32).to_be_bytes());
+    digest
+}
+
+fn blob_ref(digest: &[u8; DIGEST_LEN]) -> Vec<u8> {
+    let mut value = Vec::with_capacity(BLOB_REF_LEN);
+    value.extend_from_slice(&HEADER);
+    value.push(FORMAT_BLOB);
+    value.extend_from_slice(digest);
+    value
+}
+
+/// Returns a blob referenced by a document state `value` stored under a given `key` or `None` if
+/// value is not a blob reference. Blob is returned in a buffer owned by the store, so it's not
+/// copied when a backend returns borrowed or memory-mapped values.
+///
+/// Returns [StoreError::Corrupted] if referenced blob doesn't exist.
+pub(crate) fn resolve<'a, DB: DocOps<'a>>(
+    db: &DB,
+    key: &[u8],
+    value: &[u8],
+) -> Result<Option<<DB as KVStore<'a>>::Return>, Error>
+where
+    Error: From<<DB as KVStore<'a>>::Error>,
+{
+    if let Some(digest) = referenced_digest(value) {
+        match db.get(&key_blob(&digest))? {
+            Some(blob) => Ok(Some(blob)),
+            None => Err(StoreError::Corrupted { key: key.to_vec() }.into()),
+        }
+    } else {
+        Ok(None)
+    }
+}
+
+/// Changes of blob reference counts, gathered while document states are written or removed and
+/// applied together (see: [BlobRefs::commit]), so that the same blob can be referenced by many
+/// entries of a single write batch.
+#[derive(Debug, Default)]
+pub(crate) struct BlobRefs {
+    changes: HashMap<[u8; DIGEST_LEN], BlobChange>,
+}
+
+#[derive(Debug, Default)]
+struct BlobChange {
+    delta: i64,
+    /// Content of a blob, which has not been stored yet.
+    value: Option<Vec<u8>>,
+}
+
+impl BlobRefs {
+    /// Prepares an encoded document state `value` to be stored: if it's at least `threshold` bytes
+    /// long, it's moved into the blob keyspace and a reference to it is returned instead. Values
+    /// are always stored inline when `threshold` is 0.
+    pub fn share<'a, DB: DocOps<'a>>(
+        &mut self,
+        db: &DB,
+        value: Vec<u8>,
+        threshold: usize,
+    ) -> Result<Vec<u8>, Error>
+    where
+        Error: From<<DB as KVStore<'a>>::Error>,
+    {
+        if threshold == 0 || value.len() < threshold {
+            return Ok(value);
+        }
+        let digest = digest(&value);
+        let change = self.changes.entry(digest).or_default();
+        let same = match &change.value {
+            Some(pending) => Some(*pending == value),
+            None => db
+                .get(&key_blob(&digest))?
+                .map(|blob| blob.as_ref() == value.as_slice()),
+        };
+        match same {
+            // digest collision: different content is already stored as this blob
+            Some(false) => return Ok(value),
+            Some(true) => {}
+            None => change.value = Some(value),
+        }
+        change.delta += 1;
+        Ok(blob_ref(&digest))
+    }
+
+    /// Adds a reference to a blob referenced by a given stored `value`, i.e. when the value is
+    /// copied into another document. Does nothing if value is not a blob reference.
+    pub fn retain(&mut self, value: &[u8]) {
+        if let Some(digest) = referenced_digest(value) {
+            self.changes.entry(digest).or_default().delta += 1;
+        }
+    }
+
+    /// Removes a reference to a blob referenced by a given stored `value`, i.e. when the value is
+    /// removed or overwritten. Does nothing if value is not a blob reference.
+    pub fn release(&mut self, value: &[u8]) {
+        if let Some(digest) = referenced_digest(value) {
+            self.changes.entry(digest).or_default().delta -= 1;
+        }
+    }
+
+    /// Writes gathered changes into a given `batch`. Blobs, which are no longer referenced by any
+    /// document, are removed.
+    pub fn commit<'a, DB: DocOps<'a>>(self, db: &DB, batch: &mut WriteBatch) -> Result<(), Error>
+    where
+        Error: From<<DB as KVStore<'a>>::Error>,
+    {
+        for (digest, change) in self.changes {
+            if change.delta == 0 {
+                continue;
+            }
+            let refs_key = key_blob_refs(&digest);
+            let refs = match db.get(&refs_key)? {
+                Some(refs) => {
+                    let refs: [u8; 4] = match refs.as_ref().try_into() {
+                        Ok(refs) => refs,
+                        Err(_) => {
+                            let key = refs_key.to_vec();
+                            return Err(StoreError::Corrupted { key }.into());
+                        }
+                    };
+                    u32::from_be_bytes(refs) as i64
+                }
+                None => 0,
+            } + change.delta;
+            if refs > 0 {
+                if let Some(value) = change.value {
+                    batch.upsert(&key_blob(&digest), &value);
+                }
+                batch.upsert(&refs_key, &(refs as u32).to_be_bytes());
+            } else {
+                batch.remove(&key_blob(&digest));
+                batch.remove(&refs_key);
+            }
+        }
+        Ok(())
+    }
+}
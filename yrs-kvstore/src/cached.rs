@@ -0,0 +1,336 @@
+use crate::batch::{BatchOp, WriteBatch};
+use crate::error::Error;
+use crate::keys::{doc_oid_name, oid_from_bytes, KEYSPACE_DOC, KEYSPACE_OID, OID, SUB_KEY_POS, V1};
+use crate::metrics::StoreMetrics;
+use crate::observer::UpdateObservers;
+use crate::options::StoreOptions;
+use crate::{diff_merged, get_oid, merge_stored, DocOps, KVStore};
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use yrs::updates::decoder::Decode;
+use yrs::StateVector;
+
+/// Decorator over any [DocOps] store, which keeps the states of recently read documents in
+/// memory. States are kept as binary updates merged together with all pending updates of a
+/// document, alongside their state vectors, in a least recently used cache of a given capacity.
+/// This way [DocOps::get_diff], [DocOps::sync_step] and [DocOps::get_state_vector] called over and
+/// over again by busy sync endpoints don't need to read and merge the whole document every time.
+///
+/// Cached states are invalidated by every write made through this store. Writes made directly
+/// to the inner store or by other processes are not visible to the cache, so it's up to the
+/// caller to [CachedStore::invalidate] affected documents in such case. For the same reason,
+/// cache should not outlive a database transaction, which may be rolled back.
+#[derive(Debug)]
+pub struct CachedStore<S> {
+    inner: S,
+    cache: Mutex<DocCache>,
+}
+
+impl<S> CachedStore<S> {
+    /// Creates a new store, which caches states of at most `capacity` recently read documents of
+    /// an `inner` store.
+    pub fn new(inner: S, capacity: usize) -> Self {
+        CachedStore {
+            inner,
+            cache: Mutex::new(DocCache {
+                capacity,
+                entries: VecDeque::with_capacity(capacity),
+            }),
+        }
+    }
+
+    /// Returns a reference to the wrapped store.
+    pub fn inner(&self) -> &S {
+        &self.inner
+    }
+
+    /// Unwraps the underlying store.
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+
+    /// Returns a number of documents, which states are currently cached.
+    pub fn cached_docs(&self) -> usize {
+        self.cache.lock().unwrap().entries.len()
+    }
+
+    /// Removes a cached state of a document with a given `name`.
+    pub fn invalidate<K: AsRef<[u8]> + ?Sized>(&self, name: &K) {
+        let name = name.as_ref();
+        self.cache
+            .lock()
+            .unwrap()
+            .entries
+            .retain(|e| e.name.as_ref() != name);
+    }
+
+    /// Removes cached states of all documents.
+    pub fn invalidate_all(&self) {
+        self.cache.lock().unwrap().entries.clear();
+    }
+
+    /// Invalidates cached documents affected by a write of a given `key`.
+    fn invalidate_key(&self, key: &[u8]) {
+        let mut cache = self.cache.lock().unwrap();
+        match doc_key_oid(key) {
+            Some(oid) => cache.entries.retain(|e| e.oid != oid),
+            None if key.starts_with(&[V1, KEYSPACE_OID]) && key.len() > 2 => {
+                let name = doc_oid_name(key);
+                cache.entries.retain(|e| e.name.as_ref() != name);
+            }
+            None => {}
+        }
+    }
+
+    /// Invalidates cached documents affected by a removal of `from`..=`to` range of keys.
+    fn invalidate_range(&self, from: &[u8], to: &[u8]) {
+        match (doc_key_oid(from), doc_key_oid(to)) {
+            (Some(a), Some(b)) if a == b => self.invalidate_key(from),
+            _ => self.invalidate_all(),
+        }
+    }
+}
+
+impl<'a, S> CachedStore<S>
+where
+    S: DocOps<'a>,
+    Error: From<<S as KVStore<'a>>::Error>,
+{
+    /// Returns a merged state of a document with a given `name` together with its state vector,
+    /// reading it from the inner store if it was not cached.
+    fn cached_state(&self, name: &[u8]) -> Result<Option<(Vec<u8>, StateVector)>, Error> {
+        if let Some(hit) = self.cache.lock().unwrap().get(name) {
+            return Ok(Some(hit));
+        }
+        if let Some(oid) = get_oid(self, name)? {
+            if let Some(merged) = merge_stored(self, oid, false)? {
+                let sv = yrs::encode_state_vector_from_update_v1(&merged)?;
+                let sv = StateVector::decode_v1(&sv)?;
+                let mut cache = self.cache.lock().unwrap();
+                cache.insert(CachedDoc {
+                    name: name.into(),
+                    oid,
+                    merged: merged.clone(),
+                    state_vector: sv.clone(),
+                });
+                return Ok(Some((merged, sv)));
+            }
+        }
+        Ok(None)
+    }
+}
+
+impl<'a, S> DocOps<'a> for CachedStore<S>
+where
+    S: DocOps<'a>,
+    Error: From<<S as KVStore<'a>>::Error>,
+{
+    fn options(&self) -> &StoreOptions {
+        self.inner.options()
+    }
+
+    fn lock_oid_allocation(&self) -> Result<(), Error> {
+        self.inner.lock_oid_allocation()
+    }
+
+    fn update_observers(&self) -> Option<&UpdateObservers> {
+        self.inner.update_observers()
+    }
+
+    fn metrics(&self) -> Option<&dyn StoreMetrics> {
+        self.inner.metrics()
+    }
+
+    /// Returns a state vector of a document with a given `name`. Unlike the inner store, cached
+    /// state vector always includes pending updates of the document. Whether they have been
+    /// merged into the stored state vector is still reported after the inner store.
+    fn get_state_vector<K: AsRef<[u8]> + ?Sized>(
+        &self,
+        name: &K,
+    ) -> Result<(Option<StateVector>, bool), Error> {
+        match self.cached_state(name.as_ref())? {
+            Some((_, sv)) => {
+                let (_, up_to_date) = self.inner.get_state_vector(name)?;
+                Ok((Some(sv), up_to_date))
+            }
+            None => Ok((None, true)),
+        }
+    }
+
+    fn get_diff<K: AsRef<[u8]> + ?Sized>(
+        &self,
+        name: &K,
+        sv: &StateVector,
+    ) -> Result<Option<Vec<u8>>, Error> {
+        match self.cached_state(name.as_ref())? {
+            Some((merged, _)) => Ok(Some(diff_merged(&merged, sv, false)?)),
+            None => Ok(None),
+        }
+    }
+
+    fn sync_step<K: AsRef<[u8]> + ?Sized>(
+        &self,
+        name: &K,
+        remote_sv: &StateVector,
+    ) -> Result<Option<(Vec<u8>, StateVector)>, Error> {
+        match self.cached_state(name.as_ref())? {
+            Some((merged, sv)) => Ok(Some((diff_merged(&merged, remote_sv, false)?, sv))),
+            None => Ok(None),
+        }
+    }
+}
+
+impl<'a, S: KVStore<'a>> KVStore<'a> for CachedStore<S> {
+    type Error = S::Error;
+    type Cursor = S::Cursor;
+    type Entry = S::Entry;
+    type Return = S::Return;
+
+    fn get(&self, key: &[u8]) -> Result<Option<Self::Return>, Self::Error> {
+        self.inner.get(key)
+    }
+
+    fn upsert(&self, key: &[u8], value: &[u8]) -> Result<(), Self::Error> {
+        self.invalidate_key(key);
+        self.inner.upsert(key, value)
+    }
+
+    fn remove(&self, key: &[u8]) -> Result<(), Self::Error> {
+        self.invalidate_key(key);
+        self.inner.remove(key)
+    }
+
+    fn remove_range(&self, from: &[u8], to: &[u8]) -> Result<(), Self::Error> {
+        self.invalidate_range(from, to);
+        self.inner.remove_range(from, to)
+    }
+
+    fn iter_range(&self, from: &[u8], to: &[u8]) -> Result<Self::Cursor, Self::Error> {
+        self.inner.iter_range(from, to)
+    }
+
+    fn contains_range(&self, from: &[u8], to: &[u8]) -> Result<bool, Self::Error> {
+        self.inner.contains_range(from, to)
+    }
+
+    fn peek_back(&self, key: &[u8]) -> Result<Option<Self::Entry>, Self::Error> {
+        self.inner.peek_back(key)
+    }
+
+    fn batch(&self) -> WriteBatch {
+        self.inner.batch()
+    }
+
+    fn commit_batch(&self, batch: WriteBatch) -> Result<(), Self::Error> {
+        for op in batch.iter() {
+            match op {
+                BatchOp::Upsert(key, _) | BatchOp::Remove(key) => self.invalidate_key(key),
+                BatchOp::RemoveRange(from, to) => self.invalidate_range(from, to),
+            }
+        }
+        self.inner.commit_batch(batch)
+    }
+}
+
+/// Returns an OID of a document, which keyspace contains a given `key`.
+fn doc_key_oid(key: &[u8]) -> Option<OID> {
+    if key.len() >= SUB_KEY_POS && key.starts_with(&[V1, KEYSPACE_DOC]) {
+        Some(oid_from_bytes(&key[2..SUB_KEY_POS]))
+    } else {
+        None
+    }
+}
+
+#[derive(Debug)]
+struct DocCache {
+    capacity: usize,
+    /// Cached documents ordered from the most to the least recently used one.
+    entries: VecDeque<CachedDoc>,
+}
+
+impl DocCache {
+    fn get(&mut self, name: &[u8]) -> Option<(Vec<u8>, StateVector)> {
+        let pos = self.entries.iter().position(|e| e.name.as_ref() == name)?;
+        let entry = self.entries.remove(pos)?;
+        let hit = (entry.merged.clone(), entry.state_vector.clone());
+        self.entries.push_front(entry);
+        Some(hit)
+    }
+
+    fn insert(&mut self, entry: CachedDoc) {
+        if self.capacity == 0 {
+            return;
+        }
+        self.entries.retain(|e| e.name != entry.name);
+        self.entries.truncate(self.capacity - 1);
+        self.entries.push_front(entry);
+    }
+}
+
+#[derive(Debug)]
+struct CachedDoc {
+    name: Box<[u8]>,
+    oid: OID,
+    /// Document state merged together with its pending updates, encoded using lib0 v1 encoding.
+    merged: Vec<u8>,
+    state_vector: StateVector,
+}
+
+#[cfg(test)]
+mod test {
+    use crate::cached::CachedStore;
+    use crate::memory::MemKVStore;
+    use crate::testing::verify_kvstore_contract;
+    use crate::DocOps;
+    use yrs::updates::decoder::Decode;
+    use yrs::{Doc, GetString, ReadTxn, StateVector, Text, Transact, Update};
+
+    #[test]
+    fn kvstore_contract() {
+        let db = CachedStore::new(MemKVStore::new(), 4);
+        verify_kvstore_contract(&db);
+    }
+
+    #[test]
+    fn invalidate_on_write() {
+        let db = CachedStore::new(MemKVStore::new(), 1);
+        let doc = Doc::new();
+        let text = doc.get_or_insert_text("text");
+        text.push(&mut doc.transact_mut(), "hello");
+        db.insert_doc("a", &doc.transact()).unwrap();
+        db.insert_doc("b", &doc.transact()).unwrap();
+
+        let (sv, up_to_date) = db.get_state_vector("a").unwrap();
+        assert_eq!(sv, Some(doc.transact().state_vector()));
+        assert!(up_to_date);
+        assert_eq!(db.cached_docs(), 1);
+
+        let sv = doc.transact().state_vector();
+        text.push(&mut doc.transact_mut(), " world");
+        db.push_update("a", &doc.transact().encode_diff_v1(&sv))
+            .unwrap();
+        assert_eq!(db.cached_docs(), 0);
+
+        let diff = db.get_diff("a", &StateVector::default()).unwrap().unwrap();
+        let loaded = Doc::new();
+        let text = loaded.get_or_insert_text("text");
+        loaded
+            .transact_mut()
+            .apply_update(Update::decode_v1(&diff).unwrap())
+            .unwrap();
+        let txn = loaded.transact();
+        assert_eq!(text.get_string(&txn), "hello world");
+
+        // least recently used document is evicted
+        assert!(db
+            .sync_step("b", &StateVector::default())
+            .unwrap()
+            .is_some());
+        assert_eq!(db.cached_docs(), 1);
+        db.clear_doc("a").unwrap();
+        assert_eq!(db.cached_docs(), 1);
+        db.clear_doc("b").unwrap();
+        assert_eq!(db.cached_docs(), 0);
+        assert!(db.get_diff("b", &StateVector::default()).unwrap().is_none());
+    }
+}
@@ -0,0 +1,64 @@
+use crate::error::StoreError;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// Token used to abort long-running [crate::DocOps] operations, i.e. when a request, which
+/// triggered them, has already timed out (see: [crate::DocOps::load_doc_cancellable]). Operation
+/// is aborted once the token has been cancelled explicitly or its deadline has passed, whichever
+/// comes first. Aborted operations return [StoreError::Cancelled] error.
+///
+/// Token is cheap to clone, and all clones share the same cancellation state, so that it can be
+/// cancelled from another thread.
+#[derive(Debug, Clone, Default)]
+pub struct Cancel {
+    cancelled: Arc<AtomicBool>,
+    deadline: Option<Instant>,
+}
+
+impl Cancel {
+    /// Creates a new token, which is cancelled only explicitly (see: [Cancel::cancel]).
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates a new token, which is cancelled automatically once a given `deadline` has passed.
+    pub fn with_deadline(deadline: Instant) -> Self {
+        Cancel {
+            cancelled: Arc::default(),
+            deadline: Some(deadline),
+        }
+    }
+
+    /// Creates a new token, which is cancelled automatically after a given `timeout`.
+    pub fn with_timeout(timeout: Duration) -> Self {
+        Self::with_deadline(Instant::now() + timeout)
+    }
+
+    /// Returns a deadline of the current token, if any was set.
+    pub fn deadline(&self) -> Option<Instant> {
+        self.deadline
+    }
+
+    /// Cancels the current token and all of its clones.
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Release);
+    }
+
+    /// Checks if the current token has been cancelled or its deadline has passed.
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Acquire)
+            || self
+                .deadline
+                .is_some_and(|deadline| Instant::now() >= deadline)
+    }
+
+    /// Returns [StoreError::Cancelled] error if the current token has been cancelled.
+    pub fn check(&self) -> Result<(), StoreError> {
+        if self.is_cancelled() {
+            Err(StoreError::Cancelled)
+        } else {
+            Ok(())
+        }
+    }
+}
@@ -0,0 +1,81 @@
+//! Integrity checksums of persisted document states and updates. Every value is sealed with
+//! a CRC32 checksum, which is verified when the value is read back, so that bit rot or truncated
+//! writes are reported as [StoreError::Corrupted] instead of producing lib0 decoding errors.
+//!
+//! Sealed values are stored using `{0xFF}{0x00}{0x03}{crc32:4}{payload}` format, which shares its
+//! header with compressed values (see: [crate::compression]). Values written without a checksum
+//! are still accepted and returned unchanged.
+
+use crate::compression::HEADER;
+use crate::error::{Error, StoreError};
+
+/// Format identifier of values sealed with a checksum.
+pub const FORMAT_CHECKSUM: u8 = 3;
+
+/// Number of bytes added to every value sealed with a checksum.
+pub const SEALED_HEADER_LEN: usize = HEADER.len() + 1 + 4;
+
+/// Seals a given `value` with a CRC32 checksum.
+pub fn seal(value: &[u8]) -> Vec<u8> {
+    let mut sealed = Vec::with_capacity(SEALED_HEADER_LEN + value.len());
+    sealed.extend_from_slice(&HEADER);
+    sealed.push(FORMAT_CHECKSUM);
+    sealed.extend_from_slice(&crc32fast::hash(value).to_be_bytes());
+    sealed.extend_from_slice(value);
+    sealed
+}
+
+/// Checks if a given stored `value` has been sealed with a checksum.
+pub fn is_sealed(value: &[u8]) -> bool {
+    value.len() >= SEALED_HEADER_LEN
+        && value[..HEADER.len()] == HEADER
+        && value[HEADER.len()] == FORMAT_CHECKSUM
+}
+
+/// Verifies the checksum of a `value` stored under a given `key` and returns its payload. Values
+/// which were not sealed are returned unchanged.
+///
+/// Returns [StoreError::Corrupted] if checksum doesn't match.
+pub fn verify<'v>(key: &[u8], value: &'v [u8]) -> Result<&'v [u8], Error> {
+    if !is_sealed(value) {
+        return Ok(value);
+    }
+    let mut checksum = [0u8; 4];
+    checksum.copy_from_slice(&value[HEADER.len() + 1..SEALED_HEADER_LEN]);
+    let payload = &value[SEALED_HEADER_LEN..];
+    if crc32fast::hash(payload) != u32::from_be_bytes(checksum) {
+        return Err(StoreError::Corrupted { key: key.to_vec() }.into());
+    }
+    Ok(payload)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::checksum::{is_sealed, seal, verify};
+    use crate::error::StoreError;
+
+    #[test]
+    fn seal_and_verify() {
+        let value = b"hello world";
+        let sealed = seal(value);
+        assert!(is_sealed(&sealed));
+        assert_eq!(verify(b"key", &sealed).unwrap(), value);
+        // values without checksum are passed through
+        assert_eq!(verify(b"key", value).unwrap(), value);
+    }
+
+    #[test]
+    fn detect_corruption() {
+        let mut sealed = seal(b"hello world");
+        let last = sealed.len() - 1;
+        sealed[last] ^= 1;
+        let err = verify(b"key", &sealed).unwrap_err();
+        match err.downcast_ref::<StoreError>() {
+            Some(StoreError::Corrupted { key }) => assert_eq!(key, b"key"),
+            other => panic!("expected corruption error, got: {:?}", other),
+        }
+
+        let truncated = &seal(b"hello world")[..10];
+        assert!(verify(b"key", truncated).is_err());
+    }
+}
@@ -0,0 +1,174 @@
+//! Chunked storage of large document states. Some backends limit the size of a single value (i.e.
+//! DynamoDB items can't exceed 400KiB), so when enabled (see:
+//! [crate::options::StoreOptions::doc_chunk_size]), document states larger than a given size are
+//! split into chunks stored under consecutive keys (see: [crate::keys::key_doc_chunk]), while the
+//! document state key keeps only a manifest describing them. Chunks are transparently reassembled
+//! whenever the document state is read.
+//!
+//! Manifests are stored using `{0xFF}{0x00}{0x06}{count:4}{len:4}{crc32:4}` format, which shares
+//! its header with compressed values (see: [crate::compression]). Chunks keep a value in the same
+//! format in which it would be stored inline, so chunking can be freely combined with compression
+//! and value codecs. Chunks of a previous document state are removed once a new state is written
+//! and together with the document itself (see: [crate::DocOps::clear_doc]).
+
+use crate::batch::WriteBatch;
+use crate::compression::HEADER;
+use crate::error::{Error, StoreError};
+use crate::keys::{doc_chunk_index, key_doc_chunk, oid_from_bytes, OID, SUB_KEY_POS};
+use crate::{DocOps, KVEntry, KVStore};
+use std::borrow::Cow;
+use std::convert::TryInto;
+
+/// Format identifier of values describing a chunked document state.
+pub const FORMAT_CHUNKED: u8 = 6;
+
+/// Length of a value describing a chunked document state.
+pub const MANIFEST_LEN: usize = HEADER.len() + 1 + 12;
+
+/// Description of chunks, which a document state has been split into.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Manifest {
+    /// Number of chunks.
+    pub count: u32,
+    /// Total length of all chunks in bytes.
+    pub len: u32,
+    /// CRC32 checksum of a reassembled value.
+    pub crc: u32,
+}
+
+impl Manifest {
+    /// Parses a manifest from a given stored `value`. Returns `None` if value is not a manifest.
+    pub fn parse(value: &[u8]) -> Option<Self> {
+        if value.len() != MANIFEST_LEN
+            || value[..HEADER.len()] != HEADER
+            || value[HEADER.len()] != FORMAT_CHUNKED
+        {
+            return None;
+        }
+        let field = |i: usize| {
+            let start = HEADER.len() + 1 + i * 4;
+            u32::from_be_bytes(value[start..(start + 4)].try_into().unwrap())
+        };
+        Some(Manifest {
+            count: field(0),
+            len: field(1),
+            crc: field(2),
+        })
+    }
+
+    fn encode(&self) -> Vec<u8> {
+        let mut value = Vec::with_capacity(MANIFEST_LEN);
+        value.extend_from_slice(&HEADER);
+        value.push(FORMAT_CHUNKED);
+        value.extend_from_slice(&self.count.to_be_bytes());
+        value.extend_from_slice(&self.len.to_be_bytes());
+        value.extend_from_slice(&self.crc.to_be_bytes());
+        value
+    }
+
+    /// Reassembles a document state stored under a given `key` from `chunks` ordered by their
+    /// indexes.
+    ///
+    /// Returns [StoreError::Corrupted] if any chunk is missing or reassembled value doesn't match
+    /// current manifest.
+    pub(crate) fn assemble<E, I>(&self, key: &[u8], chunks: I) -> Result<Vec<u8>, Error>
+    where
+        E: KVEntry,
+        I: Iterator<Item = E>,
+    {
+        let mut value = Vec::with_capacity(self.len as usize);
+        let mut next = 0;
+        for e in chunks.take(self.count as usize) {
+            if doc_chunk_index(e.key()) != next {
+                break;
+            }
+            value.extend_from_slice(e.value());
+            next += 1;
+        }
+        if next != self.count
+            || value.len() != self.len as usize
+            || crc32fast::hash(&value) != self.crc
+        {
+            return Err(StoreError::Corrupted { key: key.to_vec() }.into());
+        }
+        Ok(value)
+    }
+}
+
+/// Checks if a given stored `value` describes a chunked document state.
+pub fn is_manifest(value: &[u8]) -> bool {
+    Manifest::parse(value).is_some()
+}
+
+/// Returns a length of a document state stored as a given `value`. In case of chunked document
+/// states this is a total length of all of their chunks.
+pub fn stored_len(value: &[u8]) -> usize {
+    match Manifest::parse(value) {
+        Some(manifest) => manifest.len as usize,
+        None => value.len(),
+    }
+}
+
+/// Returns a range of keys (inclusive), under which chunks described by a given `manifest` of
+/// a document with a given `oid` are stored.
+pub(crate) fn chunk_range(oid: OID, manifest: &Manifest) -> (Vec<u8>, Vec<u8>) {
+    let last = manifest.count.saturating_sub(1);
+    (
+        key_doc_chunk(oid, 0).to_vec(),
+        key_doc_chunk(oid, last).to_vec(),
+    )
+}
+
+/// Returns a document state `value` stored under a given document state `key`, reassembling it
+/// from chunks if it's a manifest. Other values are returned unchanged.
+///
+/// Returns [StoreError::Corrupted] if any chunk is missing or doesn't match the manifest.
+pub(crate) fn resolve<'a, 'v, DB: DocOps<'a>>(
+    db: &DB,
+    key: &[u8],
+    value: &'v [u8],
+) -> Result<Cow<'v, [u8]>, Error>
+where
+    Error: From<<DB as KVStore<'a>>::Error>,
+{
+    match Manifest::parse(value) {
+        Some(manifest) => {
+            // document state key scheme: 01{oid}{sub:1}
+            let oid = oid_from_bytes(&key[2..SUB_KEY_POS]);
+            let (start, end) = chunk_range(oid, &manifest);
+            let chunks = db.iter_range(&start, &end)?;
+            Ok(Cow::Owned(manifest.assemble(key, chunks)?))
+        }
+        None => Ok(Cow::Borrowed(value)),
+    }
+}
+
+/// Prepares an encoded document state `value` of a document with a given `oid` to be stored: if
+/// it's longer than `chunk_size`, it's split into chunks written into a given `batch` and
+/// a manifest describing them is returned instead. Values are never split when `chunk_size` is 0.
+pub(crate) fn split(
+    batch: &mut WriteBatch,
+    oid: OID,
+    value: Vec<u8>,
+    chunk_size: usize,
+) -> Vec<u8> {
+    if chunk_size == 0 || value.len() <= chunk_size {
+        return value;
+    }
+    let mut count = 0;
+    for chunk in value.chunks(chunk_size) {
+        batch.upsert(&key_doc_chunk(oid, count), chunk);
+        count += 1;
+    }
+    let manifest = Manifest {
+        count,
+        len: value.len() as u32,
+        crc: crc32fast::hash(&value),
+    };
+    manifest.encode()
+}
+
+/// Removes all chunks of a document with a given `oid` as a part of a write `batch`.
+pub(crate) fn remove(batch: &mut WriteBatch, oid: OID) {
+    batch.remove_range(&key_doc_chunk(oid, 0), &key_doc_chunk(oid, u32::MAX));
+}
@@ -0,0 +1,178 @@
+//! Pluggable transformations of persisted values. A [ValueCodec] is applied to every document
+//! state, update and metadata value written by [crate::DocOps] (see: [crate::options::StoreOptions::codecs]),
+//! and reversed when they are read back, so that backends don't need to implement compression,
+//! integrity checks or encryption on their own.
+//!
+//! Codecs are combined using [CodecChain]: values are encoded by codecs in order in which they were
+//! added to the chain and decoded in reverse order, i.e. a chain of [Compressed], [Checksummed] and
+//! [crate::encryption::EncryptionCodec] compresses values first, then seals and finally encrypts
+//! them.
+//!
+//! Values are encoded independently of keys they are stored under, since document states may be
+//! copied between documents or shared by many of them (see: [crate::blob]). Keys are passed to
+//! [ValueCodec::decode] only in order to report them in errors.
+
+use crate::checksum::{seal, verify};
+use crate::compression::{decompress, Compression};
+use crate::error::{Error, ErrorContext};
+use std::borrow::Cow;
+use std::sync::Arc;
+
+/// Reversible transformation of values persisted by [crate::DocOps].
+pub trait ValueCodec: std::fmt::Debug + Send + Sync {
+    /// Encodes a given `value` into a format in which it's persisted.
+    fn encode<'v>(&self, value: &'v [u8]) -> Result<Cow<'v, [u8]>, Error>;
+
+    /// Decodes a persisted `value` stored under a given `key` back into its original form.
+    fn decode<'v>(&self, key: &[u8], value: &'v [u8]) -> Result<Cow<'v, [u8]>, Error>;
+}
+
+/// Codec, which persists values as they are.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Identity;
+
+impl ValueCodec for Identity {
+    fn encode<'v>(&self, value: &'v [u8]) -> Result<Cow<'v, [u8]>, Error> {
+        Ok(Cow::Borrowed(value))
+    }
+
+    fn decode<'v>(&self, _key: &[u8], value: &'v [u8]) -> Result<Cow<'v, [u8]>, Error> {
+        Ok(Cow::Borrowed(value))
+    }
+}
+
+/// Codec, which compresses values using a given [Compression]. Values, which were not compressed,
+/// are decoded unchanged.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Compressed(pub Compression);
+
+impl ValueCodec for Compressed {
+    fn encode<'v>(&self, value: &'v [u8]) -> Result<Cow<'v, [u8]>, Error> {
+        self.0.compress(value)
+    }
+
+    fn decode<'v>(&self, key: &[u8], value: &'v [u8]) -> Result<Cow<'v, [u8]>, Error> {
+        decompress(value).with_key(key)
+    }
+}
+
+/// Codec, which seals values with an integrity checksum (see: [crate::checksum]). Values, which
+/// were not sealed, are decoded unchanged.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Checksummed;
+
+impl ValueCodec for Checksummed {
+    fn encode<'v>(&self, value: &'v [u8]) -> Result<Cow<'v, [u8]>, Error> {
+        Ok(Cow::Owned(seal(value)))
+    }
+
+    fn decode<'v>(&self, key: &[u8], value: &'v [u8]) -> Result<Cow<'v, [u8]>, Error> {
+        Ok(Cow::Borrowed(verify(key, value)?))
+    }
+}
+
+/// Sequence of codecs applied one after another. Values are encoded by codecs in order in which
+/// they were added and decoded in reverse order. Empty chain works just like [Identity].
+#[derive(Debug, Clone, Default)]
+pub struct CodecChain(Vec<Arc<dyn ValueCodec>>);
+
+impl CodecChain {
+    /// Creates a new empty chain.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Appends a given `codec` at the end of the current chain.
+    pub fn then<C: ValueCodec + 'static>(mut self, codec: C) -> Self {
+        self.0.push(Arc::new(codec));
+        self
+    }
+
+    /// Returns a number of codecs in the current chain.
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    /// Checks if current chain has no codecs.
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Returns the first codec of the current chain, if any.
+    pub(crate) fn first(&self) -> Option<Arc<dyn ValueCodec>> {
+        self.0.first().cloned()
+    }
+}
+
+impl From<Arc<dyn ValueCodec>> for CodecChain {
+    fn from(codec: Arc<dyn ValueCodec>) -> Self {
+        CodecChain(vec![codec])
+    }
+}
+
+impl ValueCodec for CodecChain {
+    fn encode<'v>(&self, value: &'v [u8]) -> Result<Cow<'v, [u8]>, Error> {
+        let mut current = Cow::Borrowed(value);
+        for codec in self.0.iter() {
+            current = match current {
+                Cow::Borrowed(value) => codec.encode(value)?,
+                Cow::Owned(value) => Cow::Owned(codec.encode(&value)?.into_owned()),
+            };
+        }
+        Ok(current)
+    }
+
+    fn decode<'v>(&self, key: &[u8], value: &'v [u8]) -> Result<Cow<'v, [u8]>, Error> {
+        let mut current = Cow::Borrowed(value);
+        for codec in self.0.iter().rev() {
+            current = match current {
+                Cow::Borrowed(value) => codec.decode(key, value)?,
+                Cow::Owned(value) => Cow::Owned(codec.decode(key, &value)?.into_owned()),
+            };
+        }
+        Ok(current)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::checksum::is_sealed;
+    use crate::codec::{Checksummed, CodecChain, Compressed, Identity, ValueCodec};
+    use crate::compression::Compression;
+    use crate::error::{find, StoreError};
+
+    #[test]
+    fn chain_roundtrip() {
+        let value = b"lorem ipsum dolor sit amet";
+        let chain = CodecChain::new()
+            .then(Compressed(Compression::default()))
+            .then(Identity)
+            .then(Checksummed);
+        assert_eq!(chain.len(), 3);
+        let encoded = chain.encode(value).unwrap();
+        assert!(is_sealed(&encoded));
+        let decoded = chain.decode(b"key", &encoded).unwrap();
+        assert_eq!(decoded.as_ref(), value);
+
+        // values which were not encoded are passed through
+        let decoded = chain.decode(b"key", value).unwrap();
+        assert_eq!(decoded.as_ref(), value);
+
+        let mut corrupted = encoded.into_owned();
+        let last = corrupted.len() - 1;
+        corrupted[last] ^= 0xFF;
+        let err = chain.decode(b"key", &corrupted).unwrap_err();
+        assert!(matches!(
+            find::<StoreError>(&err),
+            Some(StoreError::Corrupted { .. })
+        ));
+    }
+
+    #[test]
+    fn empty_chain() {
+        let chain = CodecChain::new();
+        assert!(chain.is_empty());
+        assert_eq!(chain.encode(b"value").unwrap().as_ref(), b"value");
+        assert_eq!(chain.decode(b"key", b"value").unwrap().as_ref(), b"value");
+    }
+}
@@ -0,0 +1,148 @@
+//! Optional transparent compression of persisted document states and updates. Compression codecs
+//! are enabled using `zstd` and `lz4` cargo features.
+//!
+//! Compressed values are stored using `{0xFF}{0x00}{codec:1}{payload}` format. This header is never
+//! produced at the beginning of lib0 encoded updates: in v1 encoding it would be a non-canonical
+//! variable length integer, while v2 encoding always starts with a `0` byte. Thanks to that,
+//! compressed and uncompressed entries can be freely mixed within the same database and turning
+//! compression on or off doesn't require migrating existing data.
+
+use crate::error::{Error, StoreError};
+use std::borrow::Cow;
+
+/// Header shared by all values stored in non-raw formats, followed by a format identifier byte.
+pub(crate) const HEADER: [u8; 2] = [0xFF, 0x00];
+const HEADER_LEN: usize = HEADER.len() + 1;
+
+/// Codec identifier of values compressed using zstd.
+pub const CODEC_ZSTD: u8 = 1;
+/// Codec identifier of values compressed using lz4.
+pub const CODEC_LZ4: u8 = 2;
+
+/// Compression applied to document states and updates before they are written into the store
+/// (see: [crate::options::StoreOptions::compression]). Values are stored compressed only if this makes them
+/// smaller, so small incremental updates usually stay uncompressed.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Compression {
+    /// Values are stored as they are.
+    #[default]
+    None,
+    /// Values are compressed using zstd with a given compression level.
+    #[cfg(feature = "zstd")]
+    Zstd(i32),
+    /// Values are compressed using lz4.
+    #[cfg(feature = "lz4")]
+    Lz4,
+}
+
+impl Compression {
+    /// Compresses a given `value`. Returns the value unchanged if compression is disabled or if it
+    /// wouldn't make the value any smaller.
+    pub fn compress<'v>(&self, value: &'v [u8]) -> Result<Cow<'v, [u8]>, Error> {
+        match self {
+            Compression::None => Ok(Cow::Borrowed(value)),
+            #[cfg(feature = "zstd")]
+            Compression::Zstd(level) => {
+                let payload = zstd::bulk::compress(value, *level)?;
+                Ok(with_header(CODEC_ZSTD, value, payload))
+            }
+            #[cfg(feature = "lz4")]
+            Compression::Lz4 => {
+                let payload = lz4_flex::compress_prepend_size(value);
+                Ok(with_header(CODEC_LZ4, value, payload))
+            }
+        }
+    }
+}
+
+#[cfg(any(feature = "zstd", feature = "lz4"))]
+fn with_header<'v>(codec: u8, value: &'v [u8], payload: Vec<u8>) -> Cow<'v, [u8]> {
+    if payload.len() + HEADER_LEN >= value.len() {
+        // compression didn't pay off
+        return Cow::Borrowed(value);
+    }
+    let mut compressed = Vec::with_capacity(payload.len() + HEADER_LEN);
+    compressed.extend_from_slice(&HEADER);
+    compressed.push(codec);
+    compressed.extend_from_slice(&payload);
+    Cow::Owned(compressed)
+}
+
+/// Checks if a given stored `value` has been compressed.
+pub fn is_compressed(value: &[u8]) -> bool {
+    value.len() >= HEADER_LEN
+        && value[..HEADER.len()] == HEADER
+        && matches!(value[HEADER.len()], CODEC_ZSTD | CODEC_LZ4)
+}
+
+/// Decompresses a given stored `value`. Values which were not compressed are returned unchanged.
+///
+/// Returns [StoreError::UnsupportedCompression] if value was compressed using a codec, which
+/// cargo feature is not enabled.
+pub fn decompress(value: &[u8]) -> Result<Cow<'_, [u8]>, Error> {
+    if !is_compressed(value) {
+        return Ok(Cow::Borrowed(value));
+    }
+    match value[HEADER.len()] {
+        #[cfg(feature = "zstd")]
+        CODEC_ZSTD => Ok(Cow::Owned(zstd::stream::decode_all(&value[HEADER_LEN..])?)),
+        #[cfg(feature = "lz4")]
+        CODEC_LZ4 => Ok(Cow::Owned(lz4_flex::decompress_size_prepended(
+            &value[HEADER_LEN..],
+        )?)),
+        codec => Err(StoreError::UnsupportedCompression(codec).into()),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::compression::{decompress, is_compressed, Compression};
+    use yrs::updates::decoder::Decode;
+    use yrs::updates::encoder::Encode;
+    use yrs::{Doc, ReadTxn, StateVector, Text, Transact, Update};
+
+    fn large_update() -> Vec<u8> {
+        let doc = Doc::new();
+        let text = doc.get_or_insert_text("text");
+        for _ in 0..100 {
+            text.push(&mut doc.transact_mut(), "lorem ipsum dolor sit amet ");
+        }
+        let update = doc
+            .transact()
+            .encode_state_as_update_v1(&StateVector::default());
+        update
+    }
+
+    #[test]
+    fn uncompressed_values_are_not_detected() {
+        let update = large_update();
+        assert!(!is_compressed(&update));
+        let update_v2 = Update::decode_v1(&update).unwrap().encode_v2();
+        assert!(!is_compressed(&update_v2));
+        assert!(!is_compressed(&[0, 0]));
+
+        let value = Compression::None.compress(&update).unwrap();
+        assert_eq!(value.as_ref(), update.as_slice());
+        assert_eq!(decompress(&value).unwrap().as_ref(), update.as_slice());
+    }
+
+    #[cfg(feature = "zstd")]
+    #[test]
+    fn zstd_roundtrip() {
+        let update = large_update();
+        let compressed = Compression::Zstd(3).compress(&update).unwrap();
+        assert!(is_compressed(&compressed));
+        assert!(compressed.len() < update.len());
+        assert_eq!(decompress(&compressed).unwrap().as_ref(), update.as_slice());
+    }
+
+    #[cfg(feature = "lz4")]
+    #[test]
+    fn lz4_roundtrip() {
+        let update = large_update();
+        let compressed = Compression::Lz4.compress(&update).unwrap();
+        assert!(is_compressed(&compressed));
+        assert!(compressed.len() < update.len());
+        assert_eq!(decompress(&compressed).unwrap().as_ref(), update.as_slice());
+    }
+}
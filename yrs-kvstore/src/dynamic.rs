@@ -0,0 +1,203 @@
+use crate::error::Error;
+use crate::{DocOps, KVStore, MetaEntry, PendingUpdate};
+use yrs::{Doc, StateVector, Transaction, TransactionMut};
+
+/// Boxed iterator returned by [DynDocStore] methods.
+pub type DynIter<'a, T> = Box<dyn Iterator<Item = T> + 'a>;
+
+/// Object-safe facade over the most commonly used [DocOps] operations. Generic parameters and
+/// associated types of [DocOps] make it impossible to box, so this trait erases them: document
+/// names and keys are passed as byte slices, values are returned as owned `Vec<u8>` and cursors
+/// are returned as boxed iterators.
+///
+/// It's implemented by every [DocOps] store, which makes it possible to keep a
+/// `Box<dyn DynDocStore>` in the application state and choose a backend at runtime.
+///
+/// ```rust
+/// use yrs_kvstore::dynamic::DynDocStore;
+/// use yrs_kvstore::memory::MemKVStore;
+///
+/// let store: Box<dyn DynDocStore> = Box::new(MemKVStore::new());
+/// assert!(!store.doc_exists(b"doc").unwrap());
+/// ```
+pub trait DynDocStore<'a> {
+    /// See: [DocOps::insert_doc].
+    fn insert_doc(&self, name: &[u8], txn: &Transaction) -> Result<(), Error>;
+
+    /// See: [DocOps::load_doc].
+    fn load_doc(&self, name: &[u8], txn: &mut TransactionMut) -> Result<bool, Error>;
+
+    /// See: [DocOps::flush_doc].
+    fn flush_doc(&self, name: &[u8]) -> Result<Option<Doc>, Error>;
+
+    /// See: [DocOps::get_state_vector].
+    fn get_state_vector(&self, name: &[u8]) -> Result<(Option<StateVector>, bool), Error>;
+
+    /// See: [DocOps::get_diff].
+    fn get_diff(&self, name: &[u8], sv: &StateVector) -> Result<Option<Vec<u8>>, Error>;
+
+    /// See: [DocOps::sync_step].
+    fn sync_step(
+        &self,
+        name: &[u8],
+        remote_sv: &StateVector,
+    ) -> Result<Option<(Vec<u8>, StateVector)>, Error>;
+
+    /// See: [DocOps::push_update].
+    fn push_update(&self, name: &[u8], update: &[u8]) -> Result<u32, Error>;
+
+    /// See: [DocOps::iter_updates].
+    fn iter_updates(&self, name: &[u8]) -> Result<DynIter<'a, PendingUpdate>, Error>;
+
+    /// See: [DocOps::clear_doc].
+    fn clear_doc(&self, name: &[u8]) -> Result<(), Error>;
+
+    /// See: [DocOps::doc_exists].
+    fn doc_exists(&self, name: &[u8]) -> Result<bool, Error>;
+
+    /// See: [DocOps::iter_docs].
+    fn iter_docs(&self) -> Result<DynIter<'a, Box<[u8]>>, Error>;
+
+    /// See: [DocOps::get_meta].
+    fn get_meta(&self, name: &[u8], meta_key: &[u8]) -> Result<Option<Vec<u8>>, Error>;
+
+    /// See: [DocOps::insert_meta].
+    fn insert_meta(&self, name: &[u8], meta_key: &[u8], meta: &[u8]) -> Result<(), Error>;
+
+    /// See: [DocOps::remove_meta].
+    fn remove_meta(&self, name: &[u8], meta_key: &[u8]) -> Result<(), Error>;
+
+    /// See: [DocOps::iter_meta].
+    fn iter_meta(&self, name: &[u8]) -> Result<DynIter<'a, MetaEntry>, Error>;
+
+    /// See: [DocOps::get_awareness].
+    fn get_awareness(&self, name: &[u8]) -> Result<Option<Vec<u8>>, Error>;
+
+    /// See: [DocOps::insert_awareness].
+    fn insert_awareness(&self, name: &[u8], awareness: &[u8]) -> Result<(), Error>;
+}
+
+impl<'a, S> DynDocStore<'a> for S
+where
+    S: DocOps<'a>,
+    <S as KVStore<'a>>::Cursor: 'a,
+    <S as KVStore<'a>>::Entry: 'a,
+    Error: From<<S as KVStore<'a>>::Error>,
+{
+    fn insert_doc(&self, name: &[u8], txn: &Transaction) -> Result<(), Error> {
+        DocOps::insert_doc(self, name, txn)
+    }
+
+    fn load_doc(&self, name: &[u8], txn: &mut TransactionMut) -> Result<bool, Error> {
+        DocOps::load_doc(self, name, txn)
+    }
+
+    fn flush_doc(&self, name: &[u8]) -> Result<Option<Doc>, Error> {
+        DocOps::flush_doc(self, name)
+    }
+
+    fn get_state_vector(&self, name: &[u8]) -> Result<(Option<StateVector>, bool), Error> {
+        DocOps::get_state_vector(self, name)
+    }
+
+    fn get_diff(&self, name: &[u8], sv: &StateVector) -> Result<Option<Vec<u8>>, Error> {
+        DocOps::get_diff(self, name, sv)
+    }
+
+    fn sync_step(
+        &self,
+        name: &[u8],
+        remote_sv: &StateVector,
+    ) -> Result<Option<(Vec<u8>, StateVector)>, Error> {
+        DocOps::sync_step(self, name, remote_sv)
+    }
+
+    fn push_update(&self, name: &[u8], update: &[u8]) -> Result<u32, Error> {
+        DocOps::push_update(self, name, update)
+    }
+
+    fn iter_updates(&self, name: &[u8]) -> Result<DynIter<'a, PendingUpdate>, Error> {
+        Ok(Box::new(DocOps::iter_updates(self, name)?))
+    }
+
+    fn clear_doc(&self, name: &[u8]) -> Result<(), Error> {
+        DocOps::clear_doc(self, name)
+    }
+
+    fn doc_exists(&self, name: &[u8]) -> Result<bool, Error> {
+        DocOps::doc_exists(self, name)
+    }
+
+    fn iter_docs(&self) -> Result<DynIter<'a, Box<[u8]>>, Error> {
+        Ok(Box::new(DocOps::iter_docs(self)?))
+    }
+
+    fn get_meta(&self, name: &[u8], meta_key: &[u8]) -> Result<Option<Vec<u8>>, Error> {
+        DocOps::get_meta(self, name, meta_key)
+    }
+
+    fn insert_meta(&self, name: &[u8], meta_key: &[u8], meta: &[u8]) -> Result<(), Error> {
+        DocOps::insert_meta(self, name, meta_key, meta)
+    }
+
+    fn remove_meta(&self, name: &[u8], meta_key: &[u8]) -> Result<(), Error> {
+        DocOps::remove_meta(self, name, meta_key)
+    }
+
+    fn iter_meta(&self, name: &[u8]) -> Result<DynIter<'a, MetaEntry>, Error> {
+        Ok(Box::new(DocOps::iter_meta(self, name)?))
+    }
+
+    fn get_awareness(&self, name: &[u8]) -> Result<Option<Vec<u8>>, Error> {
+        let awareness = DocOps::get_awareness(self, name)?;
+        Ok(awareness.map(|value| value.as_ref().to_vec()))
+    }
+
+    fn insert_awareness(&self, name: &[u8], awareness: &[u8]) -> Result<(), Error> {
+        DocOps::insert_awareness(self, name, awareness)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::dynamic::DynDocStore;
+    use crate::memory::MemKVStore;
+    use crate::scoped::ScopedStore;
+    use yrs::{Doc, GetString, ReadTxn, Text, Transact};
+
+    #[test]
+    fn swap_backends() {
+        let stores: Vec<Box<dyn DynDocStore>> = vec![
+            Box::new(MemKVStore::new()),
+            Box::new(ScopedStore::new(MemKVStore::new(), "tenant")),
+        ];
+        for store in stores.iter() {
+            let doc = Doc::new();
+            let text = doc.get_or_insert_text("text");
+            text.push(&mut doc.transact_mut(), "hello");
+            store.insert_doc(b"doc", &doc.transact()).unwrap();
+            let sv = doc.transact().state_vector();
+            text.push(&mut doc.transact_mut(), " world");
+            store
+                .push_update(b"doc", &doc.transact().encode_diff_v1(&sv))
+                .unwrap();
+            store.insert_meta(b"doc", b"key", b"value").unwrap();
+
+            let docs: Vec<_> = store.iter_docs().unwrap().collect();
+            assert_eq!(docs, vec![b"doc".as_ref().into()]);
+            assert_eq!(store.iter_updates(b"doc").unwrap().count(), 1);
+            assert_eq!(
+                store.get_meta(b"doc", b"key").unwrap(),
+                Some(b"value".to_vec())
+            );
+
+            let loaded = Doc::new();
+            let loaded_text = loaded.get_or_insert_text("text");
+            assert!(store.load_doc(b"doc", &mut loaded.transact_mut()).unwrap());
+            assert_eq!(loaded_text.get_string(&loaded.transact()), "hello world");
+
+            store.clear_doc(b"doc").unwrap();
+            assert!(!store.doc_exists(b"doc").unwrap());
+        }
+    }
+}
@@ -0,0 +1,334 @@
+use crate::batch::{BatchOp, WriteBatch};
+use crate::codec::ValueCodec;
+use crate::compression::HEADER;
+use crate::error::{Error, StoreError};
+use crate::metrics::StoreMetrics;
+use crate::observer::UpdateObservers;
+use crate::options::StoreOptions;
+use crate::{DocOps, KVEntry, KVStore};
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng, Payload};
+use chacha20poly1305::{Key, XChaCha20Poly1305, XNonce};
+use std::borrow::Cow;
+
+/// Length of the encryption key in bytes.
+pub const KEY_LEN: usize = 32;
+
+const NONCE_LEN: usize = 24;
+
+/// Format identifier of values encrypted by [EncryptionCodec].
+pub const FORMAT_ENCRYPTED: u8 = 5;
+
+const ENCRYPTED_HEADER_LEN: usize = HEADER.len() + 1 + NONCE_LEN;
+
+/// Adapter over any [KVStore], which encrypts all values with XChaCha20-Poly1305 AEAD cipher
+/// before passing them to the inner store, and decrypts them when they are read back. This way
+/// persisted CRDT content stays protected even when the database itself is not encrypted.
+///
+/// Values are stored as `{nonce:24}{ciphertext}`, where nonce is randomly generated on every
+/// write. Entry key is used as an associated data, so encrypted values can't be moved between
+/// keys without being detected. Keys themselves are stored in plain text, as they define the
+/// order of entries: this means that document names and metadata keys remain readable.
+///
+/// Since values must be decrypted before they are returned, [KVStore::iter_range] decrypts the
+/// whole range of entries upfront.
+#[derive(Clone)]
+pub struct EncryptedStore<S> {
+    inner: S,
+    cipher: XChaCha20Poly1305,
+}
+
+impl<S> EncryptedStore<S> {
+    /// Creates a new store, which encrypts values written to an `inner` store using a given
+    /// 256-bit encryption `key`.
+    pub fn new(inner: S, key: &[u8; KEY_LEN]) -> Self {
+        EncryptedStore {
+            inner,
+            cipher: XChaCha20Poly1305::new(Key::from_slice(key)),
+        }
+    }
+
+    /// Returns a reference to the wrapped store.
+    pub fn inner(&self) -> &S {
+        &self.inner
+    }
+
+    /// Unwraps the underlying store.
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+
+    fn encrypt(&self, key: &[u8], value: &[u8]) -> Vec<u8> {
+        let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let payload = Payload {
+            msg: value,
+            aad: key,
+        };
+        let ciphertext = self
+            .cipher
+            .encrypt(&nonce, payload)
+            .expect("XChaCha20-Poly1305 encryption cannot fail");
+        let mut encrypted = Vec::with_capacity(NONCE_LEN + ciphertext.len());
+        encrypted.extend_from_slice(&nonce);
+        encrypted.extend_from_slice(&ciphertext);
+        encrypted
+    }
+
+    fn decrypt<E>(&self, key: &[u8], value: &[u8]) -> Result<Vec<u8>, EncryptedStoreError<E>>
+    where
+        E: std::error::Error + 'static,
+    {
+        if value.len() < NONCE_LEN {
+            return Err(EncryptedStoreError::Decryption(key.to_vec()));
+        }
+        let (nonce, ciphertext) = value.split_at(NONCE_LEN);
+        let payload = Payload {
+            msg: ciphertext,
+            aad: key,
+        };
+        self.cipher
+            .decrypt(XNonce::from_slice(nonce), payload)
+            .map_err(|_| EncryptedStoreError::Decryption(key.to_vec()))
+    }
+}
+
+impl<S: std::fmt::Debug> std::fmt::Debug for EncryptedStore<S> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // never print the encryption key
+        f.debug_struct("EncryptedStore")
+            .field("inner", &self.inner)
+            .finish()
+    }
+}
+
+/// Error returned by [EncryptedStore].
+#[derive(Debug, thiserror::Error)]
+pub enum EncryptedStoreError<E: std::error::Error + 'static> {
+    /// Error returned by the inner store.
+    #[error(transparent)]
+    Store(#[from] E),
+    /// Value stored under a given key could not be decrypted, either because it was encrypted
+    /// using a different key or because it has been tampered with.
+    #[error("failed to decrypt value stored under key {0:?}")]
+    Decryption(Vec<u8>),
+}
+
+impl<'a, S> DocOps<'a> for EncryptedStore<S>
+where
+    S: DocOps<'a>,
+    S::Error: 'static,
+    Error: From<<S as KVStore<'a>>::Error>,
+{
+    fn options(&self) -> &StoreOptions {
+        // values are compressed before they are encrypted
+        self.inner.options()
+    }
+
+    fn lock_oid_allocation(&self) -> Result<(), Error> {
+        self.inner.lock_oid_allocation()
+    }
+
+    fn update_observers(&self) -> Option<&UpdateObservers> {
+        self.inner.update_observers()
+    }
+
+    fn metrics(&self) -> Option<&dyn StoreMetrics> {
+        self.inner.metrics()
+    }
+}
+
+impl<'a, S> KVStore<'a> for EncryptedStore<S>
+where
+    S: KVStore<'a>,
+    S::Error: 'static,
+{
+    type Error = EncryptedStoreError<S::Error>;
+    type Cursor = std::vec::IntoIter<EncryptedEntry>;
+    type Entry = EncryptedEntry;
+    type Return = Vec<u8>;
+
+    fn get(&self, key: &[u8]) -> Result<Option<Self::Return>, Self::Error> {
+        if let Some(value) = self.inner.get(key)? {
+            Ok(Some(self.decrypt(key, value.as_ref())?))
+        } else {
+            Ok(None)
+        }
+    }
+
+    fn upsert(&self, key: &[u8], value: &[u8]) -> Result<(), Self::Error> {
+        let encrypted = self.encrypt(key, value);
+        Ok(self.inner.upsert(key, &encrypted)?)
+    }
+
+    fn remove(&self, key: &[u8]) -> Result<(), Self::Error> {
+        Ok(self.inner.remove(key)?)
+    }
+
+    fn remove_range(&self, from: &[u8], to: &[u8]) -> Result<(), Self::Error> {
+        Ok(self.inner.remove_range(from, to)?)
+    }
+
+    fn iter_range(&self, from: &[u8], to: &[u8]) -> Result<Self::Cursor, Self::Error> {
+        let mut entries = Vec::new();
+        for e in self.inner.iter_range(from, to)? {
+            let value = self.decrypt(e.key(), e.value())?;
+            entries.push(EncryptedEntry {
+                key: e.key().to_vec(),
+                value,
+            });
+        }
+        Ok(entries.into_iter())
+    }
+
+    fn contains_range(&self, from: &[u8], to: &[u8]) -> Result<bool, Self::Error> {
+        // keys are not encrypted, so there's no need to decrypt any values
+        Ok(self.inner.contains_range(from, to)?)
+    }
+
+    fn peek_back(&self, key: &[u8]) -> Result<Option<Self::Entry>, Self::Error> {
+        if let Some(e) = self.inner.peek_back(key)? {
+            let value = self.decrypt(e.key(), e.value())?;
+            Ok(Some(EncryptedEntry {
+                key: e.key().to_vec(),
+                value,
+            }))
+        } else {
+            Ok(None)
+        }
+    }
+
+    fn commit_batch(&self, batch: WriteBatch) -> Result<(), Self::Error> {
+        let mut encrypted = self.inner.batch();
+        for op in batch {
+            match op {
+                BatchOp::Upsert(key, value) => encrypted.upsert(&key, &self.encrypt(&key, &value)),
+                BatchOp::Remove(key) => encrypted.remove(&key),
+                BatchOp::RemoveRange(from, to) => encrypted.remove_range(&from, &to),
+            }
+        }
+        Ok(self.inner.commit_batch(encrypted)?)
+    }
+}
+
+/// Entry returned by [EncryptedStore], containing already decrypted value.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EncryptedEntry {
+    key: Vec<u8>,
+    value: Vec<u8>,
+}
+
+impl KVEntry for EncryptedEntry {
+    fn key(&self) -> &[u8] {
+        &self.key
+    }
+
+    fn value(&self) -> &[u8] {
+        &self.value
+    }
+}
+
+/// [ValueCodec] encrypting values with XChaCha20-Poly1305 AEAD cipher. Unlike [EncryptedStore],
+/// it only encrypts values written by [DocOps] (document states, updates and metadata) and it can
+/// be combined with other codecs (see: [crate::codec::CodecChain]). Since codecs must be placed
+/// after compression in order for it to be effective, this is the preferred way of combining
+/// both.
+///
+/// Values are stored as `{0xFF}{0x00}{0x05}{nonce:24}{ciphertext}`, where nonce is randomly
+/// generated on every write. Values are not bound to their keys, as document states can be copied
+/// between documents. Values, which were not encrypted, are rejected with [StoreError::Corrupted]
+/// error rather than passed through, so that plain text can't be injected into the database.
+#[derive(Clone)]
+pub struct EncryptionCodec {
+    cipher: XChaCha20Poly1305,
+}
+
+impl EncryptionCodec {
+    /// Creates a new codec, which encrypts values using a given 256-bit encryption `key`.
+    pub fn new(key: &[u8; KEY_LEN]) -> Self {
+        EncryptionCodec {
+            cipher: XChaCha20Poly1305::new(Key::from_slice(key)),
+        }
+    }
+}
+
+impl std::fmt::Debug for EncryptionCodec {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // never print the encryption key
+        f.write_str("EncryptionCodec")
+    }
+}
+
+impl ValueCodec for EncryptionCodec {
+    fn encode<'v>(&self, value: &'v [u8]) -> Result<Cow<'v, [u8]>, Error> {
+        let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let ciphertext = self
+            .cipher
+            .encrypt(&nonce, value)
+            .expect("XChaCha20-Poly1305 encryption cannot fail");
+        let mut encrypted = Vec::with_capacity(ENCRYPTED_HEADER_LEN + ciphertext.len());
+        encrypted.extend_from_slice(&HEADER);
+        encrypted.push(FORMAT_ENCRYPTED);
+        encrypted.extend_from_slice(&nonce);
+        encrypted.extend_from_slice(&ciphertext);
+        Ok(Cow::Owned(encrypted))
+    }
+
+    fn decode<'v>(&self, key: &[u8], value: &'v [u8]) -> Result<Cow<'v, [u8]>, Error> {
+        let corrupted = || StoreError::Corrupted { key: key.to_vec() };
+        if value.len() < ENCRYPTED_HEADER_LEN
+            || value[..HEADER.len()] != HEADER
+            || value[HEADER.len()] != FORMAT_ENCRYPTED
+        {
+            return Err(corrupted().into());
+        }
+        let nonce = &value[HEADER.len() + 1..ENCRYPTED_HEADER_LEN];
+        let ciphertext = &value[ENCRYPTED_HEADER_LEN..];
+        let decrypted = self
+            .cipher
+            .decrypt(XNonce::from_slice(nonce), ciphertext)
+            .map_err(|_| corrupted())?;
+        Ok(Cow::Owned(decrypted))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::encryption::EncryptedStore;
+    use crate::memory::MemKVStore;
+    use crate::testing::verify_kvstore_contract;
+    use crate::{DocOps, KVStore};
+    use yrs::{Doc, GetString, Text, Transact};
+
+    const KEY: [u8; 32] = [7; 32];
+
+    #[test]
+    fn kvstore_contract() {
+        let db = EncryptedStore::new(MemKVStore::new(), &KEY);
+        verify_kvstore_contract(&db);
+        assert!(db.into_inner().is_empty());
+    }
+
+    #[test]
+    fn values_are_encrypted() {
+        let db = EncryptedStore::new(MemKVStore::new(), &KEY);
+        let doc = Doc::new();
+        let text = doc.get_or_insert_text("text");
+        text.push(&mut doc.transact_mut(), "top secret");
+        db.insert_doc("doc", &doc.transact()).unwrap();
+
+        for value in db.inner().entries().values() {
+            let found = value.windows(b"secret".len()).any(|w| w == b"secret");
+            assert!(!found, "plain text content found in the inner store");
+        }
+
+        let doc = Doc::new();
+        let text = doc.get_or_insert_text("text");
+        assert!(db.load_doc("doc", &mut doc.transact_mut()).unwrap());
+        assert_eq!(text.get_string(&doc.transact()), "top secret");
+
+        // values can't be read using a different key
+        let db = EncryptedStore::new(db.into_inner(), &[8; 32]);
+        let doc = Doc::new();
+        assert!(db.load_doc("doc", &mut doc.transact_mut()).is_err());
+        assert!(db.iter_range(&[0], &[255]).is_err());
+    }
+}
@@ -1 +1,185 @@
+use crate::keys::OID;
+use std::fmt::Formatter;
+
 pub type Error = Box<dyn std::error::Error>;
+
+/// Errors raised by [crate::DocOps] operations themselves, as opposed to the errors returned by
+/// an underlying key-value store.
+#[derive(Debug, thiserror::Error)]
+pub enum StoreError {
+    /// Some entries of a purged document were still present after its removal.
+    #[error("{count} orphaned entries of document (OID: {oid}) left after purge")]
+    OrphanedEntries { oid: OID, count: usize },
+    /// Document with a given name already exists.
+    #[error("document '{0}' already exists")]
+    DocumentExists(String),
+    /// Document with a given name has been modified concurrently: its stored state vector is
+    /// different than expected.
+    #[error("document '{0}' has been modified concurrently")]
+    Conflict(String),
+    /// Stored value has been compressed using a codec, which cargo feature is not enabled.
+    #[error("unsupported compression codec: {0}")]
+    UnsupportedCompression(u8),
+    /// Value stored under a given key is malformed or its checksum doesn't match its contents.
+    #[error("corrupted entry stored under key {key:?}")]
+    Corrupted { key: Vec<u8> },
+    /// Migration was requested from a schema version other than the one used by the database.
+    #[error("database uses schema version {actual}, but migration from {expected} was requested")]
+    SchemaVersionMismatch { expected: u8, actual: u8 },
+    /// Update clocks of a document have been exhausted and can't be reset by flushing its
+    /// pending updates.
+    #[error("update log of document (OID: {oid}) is full")]
+    UpdateLogFull { oid: OID },
+    /// All available OIDs have already been allocated.
+    #[error("no more OIDs left to allocate")]
+    OidSpaceExhausted,
+    /// Document archive has been produced using an unsupported version of the archive format.
+    #[error("unsupported document archive version: {0}")]
+    UnsupportedArchiveVersion(u8),
+    /// Operation has been aborted, as its [crate::cancel::Cancel] token has been cancelled or its
+    /// deadline has passed.
+    #[error("operation has been cancelled")]
+    Cancelled,
+    /// There's no migration path between given schema versions.
+    #[error("unsupported schema migration from version {from} to {to}")]
+    UnsupportedMigration { from: u8, to: u8 },
+    /// Archive of a document could not be found in a location stored in its tombstone.
+    #[error("archive of document '{0}' not found")]
+    ArchiveNotFound(String),
+    /// Version tag has been created without a snapshot, which was required by an operation.
+    #[error("version tag '{0}' has no snapshot")]
+    MissingSnapshot(String),
+    /// Pushed update exceeds [crate::UpdateLimits::max_update_bytes].
+    #[error("update of {size} bytes exceeds the limit of {max} bytes")]
+    UpdateTooLarge { size: usize, max: usize },
+    /// Pending updates of a document would exceed [crate::UpdateLimits::max_pending_bytes].
+    #[error("pending updates of document (OID: {oid}) would exceed the limit of {max} bytes")]
+    UpdateLogTooLarge { oid: OID, max: u64 },
+    /// Update has been vetoed by [crate::validation::UpdateValidator] for a given reason.
+    #[error("update rejected: {0}")]
+    UpdateRejected(String),
+}
+
+/// Error enriched with the context of a document, which operation has failed: its name, OID and
+/// the key of an entry that caused the failure, as far as they are known. This way a decoding
+/// error raised while loading thousands of documents points to the one that is corrupted.
+///
+/// Since context wraps the original error, use [find] instead of downcasting errors directly in
+/// order to inspect them. Context can be attached to any result using [ErrorContext] methods.
+#[derive(Debug)]
+pub struct DocError {
+    /// Name of the document.
+    pub name: Option<Box<[u8]>>,
+    /// OID of the document.
+    pub oid: Option<OID>,
+    /// Key of the entry, which caused the failure.
+    pub key: Option<Vec<u8>>,
+    source: Error,
+}
+
+impl DocError {
+    /// Wraps a given `source` error with an empty context.
+    pub fn new(source: Error) -> Self {
+        DocError {
+            name: None,
+            oid: None,
+            key: None,
+            source,
+        }
+    }
+
+    /// Returns a reference to the original error.
+    pub fn inner(&self) -> &Error {
+        &self.source
+    }
+
+    /// Unwraps the original error, discarding its context.
+    pub fn into_inner(self) -> Error {
+        self.source
+    }
+
+    /// Wraps a given `error` unless it already carries a context, which is returned instead.
+    fn wrap(error: Error) -> Box<DocError> {
+        match error.downcast::<DocError>() {
+            Ok(error) => error,
+            Err(error) => Box::new(DocError::new(error)),
+        }
+    }
+}
+
+impl std::fmt::Display for DocError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.source)?;
+        if let Some(name) = &self.name {
+            write!(f, " (document: '{}'", String::from_utf8_lossy(name))?;
+        } else {
+            write!(f, " (document: <unknown>")?;
+        }
+        if let Some(oid) = self.oid {
+            write!(f, ", OID: {}", oid)?;
+        }
+        if let Some(key) = &self.key {
+            write!(f, ", key: {:?}", key)?;
+        }
+        write!(f, ")")
+    }
+}
+
+impl std::error::Error for DocError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(self.source.as_ref())
+    }
+}
+
+/// Extension methods attaching document context to errors of failed results (see: [DocError]).
+/// Context set by the innermost call is never overridden, as it's the most specific one.
+pub trait ErrorContext<T> {
+    /// Attaches a document `name` to the error.
+    fn with_doc(self, name: &[u8]) -> Result<T, Error>;
+
+    /// Attaches a document `oid` to the error.
+    fn with_oid(self, oid: OID) -> Result<T, Error>;
+
+    /// Attaches a `key` of an entry, which caused the failure, to the error.
+    fn with_key(self, key: &[u8]) -> Result<T, Error>;
+}
+
+impl<T, E: Into<Error>> ErrorContext<T> for Result<T, E> {
+    fn with_doc(self, name: &[u8]) -> Result<T, Error> {
+        self.map_err(|e| {
+            let mut e = DocError::wrap(e.into());
+            e.name.get_or_insert_with(|| name.into());
+            e as Error
+        })
+    }
+
+    fn with_oid(self, oid: OID) -> Result<T, Error> {
+        self.map_err(|e| {
+            let mut e = DocError::wrap(e.into());
+            e.oid.get_or_insert(oid);
+            e as Error
+        })
+    }
+
+    fn with_key(self, key: &[u8]) -> Result<T, Error> {
+        self.map_err(|e| {
+            let mut e = DocError::wrap(e.into());
+            e.key.get_or_insert_with(|| key.to_vec());
+            e as Error
+        })
+    }
+}
+
+/// Returns a reference to an error of a given type `E`, looking through the [DocError] context if
+/// the `error` carries one.
+pub fn find<E: std::error::Error + 'static>(error: &Error) -> Option<&E> {
+    match error.downcast_ref::<DocError>() {
+        Some(e) => e.source.downcast_ref::<E>(),
+        None => error.downcast_ref::<E>(),
+    }
+}
+
+/// Returns the document context of a given `error`, if it carries one.
+pub fn context(error: &Error) -> Option<&DocError> {
+    error.downcast_ref::<DocError>()
+}
@@ -0,0 +1,108 @@
+//! Materialized JSON renderings of document content. They allow consumers, which don't use
+//! the yrs runtime (i.e. analytics pipelines or REST endpoints), to read document content.
+//!
+//! Root-level shared types are not typed in the encoded document state, so a [JsonExport] must
+//! declare their types in order to render their content. When a [JsonExport] is set on a store
+//! (see: [crate::options::StoreOptions::json_export]), the JSON rendering of a document is
+//! refreshed every time it's flushed and can be read using [crate::DocOps::get_json].
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use yrs::types::ToJson;
+use yrs::{Any, Doc, GetString, Transact};
+
+/// Type of a root-level shared type rendered by a [JsonExport].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RootKind {
+    /// [yrs::TextRef], rendered as a string.
+    Text,
+    /// [yrs::MapRef], rendered as a JSON object.
+    Map,
+    /// [yrs::ArrayRef], rendered as a JSON array.
+    Array,
+    /// [yrs::XmlFragmentRef], rendered as a string with its XML representation.
+    XmlFragment,
+}
+
+/// Definition of root-level shared types included in a JSON rendering of a document. Document is
+/// rendered as a JSON object with a field for every declared root-level type.
+///
+/// If no root-level types were declared, all root-level types of a document are rendered. Since
+/// their types are unknown, only types which have been already used by a document instance are
+/// rendered, while others are rendered as `null`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct JsonExport {
+    roots: Vec<(Arc<str>, RootKind)>,
+}
+
+impl JsonExport {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Includes a root-level text with a given `name` in the rendering.
+    pub fn text<N: Into<Arc<str>>>(self, name: N) -> Self {
+        self.root(name, RootKind::Text)
+    }
+
+    /// Includes a root-level map with a given `name` in the rendering.
+    pub fn map<N: Into<Arc<str>>>(self, name: N) -> Self {
+        self.root(name, RootKind::Map)
+    }
+
+    /// Includes a root-level array with a given `name` in the rendering.
+    pub fn array<N: Into<Arc<str>>>(self, name: N) -> Self {
+        self.root(name, RootKind::Array)
+    }
+
+    /// Includes a root-level XML fragment with a given `name` in the rendering.
+    pub fn xml_fragment<N: Into<Arc<str>>>(self, name: N) -> Self {
+        self.root(name, RootKind::XmlFragment)
+    }
+
+    /// Includes a root-level shared type of a given `kind` and `name` in the rendering.
+    pub fn root<N: Into<Arc<str>>>(mut self, name: N, kind: RootKind) -> Self {
+        self.roots.push((name.into(), kind));
+        self
+    }
+
+    /// Returns a JSON-like representation of a given `doc` content.
+    pub fn to_any(&self, doc: &Doc) -> Any {
+        if self.roots.is_empty() {
+            return doc.to_json(&doc.transact());
+        }
+        let mut fields = HashMap::with_capacity(self.roots.len());
+        for (name, kind) in self.roots.iter() {
+            // root-level types must be defined before document is being read
+            let value = match kind {
+                RootKind::Text => {
+                    let text = doc.get_or_insert_text(name.clone());
+                    let txn = doc.transact();
+                    Any::from(text.get_string(&txn))
+                }
+                RootKind::Map => {
+                    let map = doc.get_or_insert_map(name.clone());
+                    map.to_json(&doc.transact())
+                }
+                RootKind::Array => {
+                    let array = doc.get_or_insert_array(name.clone());
+                    array.to_json(&doc.transact())
+                }
+                RootKind::XmlFragment => {
+                    let fragment = doc.get_or_insert_xml_fragment(name.clone());
+                    let txn = doc.transact();
+                    Any::from(fragment.get_string(&txn))
+                }
+            };
+            fields.insert(name.to_string(), value);
+        }
+        Any::from(fields)
+    }
+
+    /// Returns a JSON string rendering of a given `doc` content.
+    pub fn render(&self, doc: &Doc) -> String {
+        let mut json = String::new();
+        self.to_any(doc).to_json(&mut json);
+        json
+    }
+}
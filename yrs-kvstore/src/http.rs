@@ -0,0 +1,396 @@
+//! HTTP and WebSocket endpoints exposing documents persisted by any [crate::DocOps] store, built
+//! on top of [axum]. They turn a store into a persistence-backed sync service (see:
+//! [SyncService::router]):
+//!
+//! - `GET /docs/{name}` returns a full document state (lib0 v1 encoding).
+//! - `POST /docs/{name}/diff` takes an encoded state vector (lib0 v1 encoding) and returns all
+//!   changes missing on the remote side.
+//! - `POST /docs/{name}/updates` takes an update (lib0 v1 encoding) and persists it using
+//!   [crate::DocOps::push_update].
+//! - `GET /docs/{name}/sync` upgrades the connection to a WebSocket speaking y-sync protocol
+//!   (see: [crate::sync]). Updates and awareness changes received from one peer are broadcast to
+//!   all other peers connected to the same document.
+//!
+//! Document states and diffs are streamed in chunks, which are produced only as fast as the
+//! client consumes them. Store operations are blocking, so they are executed on a dedicated
+//! thread pool (see: [tokio::task::spawn_blocking]).
+
+use crate::dynamic::DynDocStore;
+use crate::error::{find, Error, StoreError};
+use crate::sync::{handle_message, start_sync};
+use axum::body::{Body, Bytes};
+use axum::extract::ws::{Message as WsMessage, WebSocket, WebSocketUpgrade};
+use axum::extract::{Path, State};
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use axum::routing::{get, post};
+use axum::Router;
+use futures_util::{stream, SinkExt, StreamExt};
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use tokio::sync::broadcast;
+use tokio::sync::broadcast::error::RecvError;
+use yrs::sync::{Message, MessageReader, SyncMessage};
+use yrs::updates::decoder::{Decode, DecoderV1};
+use yrs::updates::encoder::Encode;
+use yrs::{StateVector, Update};
+
+/// Default size of chunks, in which document states and diffs are streamed.
+pub const DEFAULT_CHUNK_SIZE: usize = 64 * 1024;
+
+/// Default number of messages buffered for every peer connected to a document, before it's
+/// considered to be lagging behind.
+pub const DEFAULT_ROOM_CAPACITY: usize = 1024;
+
+/// Source of a store used to serve requests of a [SyncService]. Most of the stores are bound to
+/// a database transaction, so a provider decides how a store is opened for every operation.
+pub trait StoreProvider: Send + Sync + 'static {
+    /// Opens a store and calls a given function `f` with it, returning its result.
+    fn with_store<R, F>(&self, f: F) -> Result<R, Error>
+    where
+        F: FnOnce(&dyn DynDocStore<'static>) -> Result<R, Error>;
+}
+
+/// A single store shared by all requests, which are processed one at a time.
+impl<S> StoreProvider for Mutex<S>
+where
+    S: DynDocStore<'static> + Send + 'static,
+{
+    fn with_store<R, F>(&self, f: F) -> Result<R, Error>
+    where
+        F: FnOnce(&dyn DynDocStore<'static>) -> Result<R, Error>,
+    {
+        // store operations don't leave it in an inconsistent state when they panic
+        let store = self.lock().unwrap_or_else(|e| e.into_inner());
+        f(&*store)
+    }
+}
+
+/// Error response returned from [SyncService] endpoints.
+type HttpError = (StatusCode, String);
+
+/// Frame broadcast to peers connected to the same document, tagged with an identifier of
+/// a connection it originated from.
+type Frame = (u64, Arc<Vec<u8>>);
+
+/// Encoded messages produced by handling a single frame: replies for the sender and messages,
+/// which should be broadcast to other peers.
+type FrameOutput = (Vec<Vec<u8>>, Vec<Vec<u8>>);
+
+/// Persistence-backed sync service exposing documents of a store available through
+/// a [StoreProvider] over HTTP and WebSocket endpoints (see: [crate::http]).
+pub struct SyncService<P> {
+    provider: Arc<P>,
+    rooms: Mutex<HashMap<String, broadcast::Sender<Frame>>>,
+    next_peer: AtomicU64,
+    chunk_size: usize,
+    room_capacity: usize,
+}
+
+impl<P: StoreProvider> SyncService<P> {
+    pub fn new(provider: P) -> Self {
+        SyncService {
+            provider: Arc::new(provider),
+            rooms: Mutex::new(HashMap::new()),
+            next_peer: AtomicU64::new(0),
+            chunk_size: DEFAULT_CHUNK_SIZE,
+            room_capacity: DEFAULT_ROOM_CAPACITY,
+        }
+    }
+
+    /// Sets a size of chunks, in which document states and diffs are streamed to clients.
+    pub fn with_chunk_size(mut self, chunk_size: usize) -> Self {
+        self.chunk_size = chunk_size.max(1);
+        self
+    }
+
+    /// Sets a number of messages buffered for every WebSocket peer. Peers lagging further behind
+    /// receive a full document state once they catch up.
+    pub fn with_room_capacity(mut self, room_capacity: usize) -> Self {
+        self.room_capacity = room_capacity.max(1);
+        self
+    }
+
+    /// Returns a [Router] serving endpoints of current service.
+    pub fn router(self) -> Router {
+        Router::new()
+            .route("/docs/:name", get(load_doc::<P>))
+            .route("/docs/:name/diff", post(get_diff::<P>))
+            .route("/docs/:name/updates", post(push_update::<P>))
+            .route("/docs/:name/sync", get(sync::<P>))
+            .with_state(Arc::new(self))
+    }
+
+    /// Runs a given function `f` over a store on a blocking thread pool.
+    async fn run<R, F>(&self, f: F) -> Result<R, HttpError>
+    where
+        F: FnOnce(&dyn DynDocStore<'static>) -> Result<R, Error> + Send + 'static,
+        R: Send + 'static,
+    {
+        let provider = self.provider.clone();
+        let result = tokio::task::spawn_blocking(move || {
+            provider
+                .with_store(f)
+                .map_err(|e| (status_code(&e), e.to_string()))
+        })
+        .await;
+        match result {
+            Ok(result) => result,
+            Err(e) => Err((StatusCode::INTERNAL_SERVER_ERROR, e.to_string())),
+        }
+    }
+
+    /// Returns a response streaming all changes of a document with given `name`, which are not
+    /// included in a given state vector `sv`.
+    async fn diff(&self, name: String, sv: StateVector) -> Response {
+        let result = self
+            .run(move |store| store.get_diff(name.as_bytes(), &sv))
+            .await;
+        match result {
+            Ok(Some(diff)) => self.stream(diff),
+            Ok(None) => StatusCode::NOT_FOUND.into_response(),
+            Err(e) => e.into_response(),
+        }
+    }
+
+    /// Streams a given `data` in chunks, each one produced once the previous one has been
+    /// consumed by the client.
+    fn stream(&self, data: Vec<u8>) -> Response {
+        let data = Bytes::from(data);
+        let chunk_size = self.chunk_size;
+        let chunks = (0..data.len()).step_by(chunk_size).map(move |start| {
+            let end = (start + chunk_size).min(data.len());
+            Ok::<_, Infallible>(data.slice(start..end))
+        });
+        Body::from_stream(stream::iter(chunks)).into_response()
+    }
+
+    /// Returns a broadcast channel shared by all peers connected to a document with given `name`.
+    fn room(&self, name: &str) -> broadcast::Sender<Frame> {
+        let mut rooms = self.rooms.lock().unwrap_or_else(|e| e.into_inner());
+        rooms
+            .entry(name.to_string())
+            .or_insert_with(|| broadcast::channel(self.room_capacity).0)
+            .clone()
+    }
+
+    /// Broadcasts a given `frame` to peers connected to a document with given `name`, if there
+    /// are any.
+    fn broadcast(&self, name: &str, frame: Frame) {
+        let rooms = self.rooms.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(room) = rooms.get(name) {
+            // it only fails if there are no connected peers
+            let _ = room.send(frame);
+        }
+    }
+
+    /// Removes a broadcast channel of a document with given `name` once the last peer has left.
+    fn leave(&self, name: &str) {
+        let mut rooms = self.rooms.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(room) = rooms.get(name) {
+            if room.receiver_count() == 0 {
+                rooms.remove(name);
+            }
+        }
+    }
+
+    /// Runs y-sync protocol over a given WebSocket connection until it's closed.
+    async fn serve(self: Arc<Self>, name: String, socket: WebSocket) {
+        let peer = self.next_peer.fetch_add(1, Ordering::Relaxed);
+        let mut peers = self.room(&name).subscribe();
+        let (mut sink, mut socket) = socket.split();
+
+        let doc = name.clone();
+        let hello = self
+            .run(move |store| start_sync(store, doc.as_bytes()))
+            .await;
+        let mut connected = match hello {
+            Ok(msg) => sink.send(WsMessage::Binary(msg.encode_v1())).await.is_ok(),
+            Err(_) => false,
+        };
+        while connected {
+            tokio::select! {
+                frame = socket.next() => match frame {
+                    Some(Ok(WsMessage::Binary(data))) => {
+                        let doc = name.clone();
+                        let result = self
+                            .run(move |store| handle_frame(store, doc.as_bytes(), &data))
+                            .await;
+                        match result {
+                            Ok((replies, broadcasts)) => {
+                                for frame in broadcasts {
+                                    self.broadcast(&name, (peer, Arc::new(frame)));
+                                }
+                                for reply in replies {
+                                    if sink.send(WsMessage::Binary(reply)).await.is_err() {
+                                        connected = false;
+                                        break;
+                                    }
+                                }
+                            }
+                            Err(_) => connected = false,
+                        }
+                    }
+                    Some(Ok(WsMessage::Close(_))) | Some(Err(_)) | None => connected = false,
+                    Some(Ok(_)) => { /* ignore text and control frames */ }
+                },
+                frame = peers.recv() => match frame {
+                    Ok((sender, frame)) if sender != peer => {
+                        let frame = frame.as_ref().clone();
+                        connected = sink.send(WsMessage::Binary(frame)).await.is_ok();
+                    }
+                    Ok(_) => { /* frame sent by current peer */ }
+                    Err(RecvError::Lagged(_)) => {
+                        // some updates were missed, send the full document state instead
+                        let doc = name.clone();
+                        let sv = StateVector::default();
+                        let result = self
+                            .run(move |store| store.get_diff(doc.as_bytes(), &sv))
+                            .await;
+                        connected = match result {
+                            Ok(Some(state)) => {
+                                let msg = Message::Sync(SyncMessage::Update(state));
+                                sink.send(WsMessage::Binary(msg.encode_v1())).await.is_ok()
+                            }
+                            Ok(None) => true,
+                            Err(_) => false,
+                        };
+                    }
+                    Err(RecvError::Closed) => connected = false,
+                },
+            }
+        }
+        drop(peers);
+        self.leave(&name);
+    }
+}
+
+/// Handles all y-sync protocol messages of a given `data` frame using [handle_message]. Returns
+/// encoded replies for the sender and messages, which should be broadcast to other peers.
+fn handle_frame(
+    store: &dyn DynDocStore<'static>,
+    name: &[u8],
+    data: &[u8],
+) -> Result<FrameOutput, Error> {
+    let mut decoder = DecoderV1::from(data);
+    let mut replies = Vec::new();
+    let mut broadcasts = Vec::new();
+    for msg in MessageReader::new(&mut decoder) {
+        let msg = msg?;
+        let broadcast = match &msg {
+            Message::Sync(SyncMessage::SyncStep2(update))
+            | Message::Sync(SyncMessage::Update(update)) => {
+                if update != Update::EMPTY_V1 {
+                    Some(Message::Sync(SyncMessage::Update(update.clone())).encode_v1())
+                } else {
+                    None
+                }
+            }
+            Message::Awareness(_) => Some(msg.encode_v1()),
+            _ => None,
+        };
+        if let Some(reply) = handle_message(store, name, msg)? {
+            replies.push(reply.encode_v1());
+        }
+        broadcasts.extend(broadcast);
+    }
+    Ok((replies, broadcasts))
+}
+
+/// Returns an HTTP status code describing a given store error.
+fn status_code(err: &Error) -> StatusCode {
+    match find::<StoreError>(err) {
+        Some(StoreError::UpdateTooLarge { .. }) | Some(StoreError::UpdateLogTooLarge { .. }) => {
+            StatusCode::PAYLOAD_TOO_LARGE
+        }
+        Some(StoreError::UpdateRejected(_)) => StatusCode::UNPROCESSABLE_ENTITY,
+        _ => StatusCode::INTERNAL_SERVER_ERROR,
+    }
+}
+
+async fn load_doc<P: StoreProvider>(
+    State(service): State<Arc<SyncService<P>>>,
+    Path(name): Path<String>,
+) -> Response {
+    service.diff(name, StateVector::default()).await
+}
+
+async fn get_diff<P: StoreProvider>(
+    State(service): State<Arc<SyncService<P>>>,
+    Path(name): Path<String>,
+    body: Bytes,
+) -> Response {
+    match StateVector::decode_v1(&body) {
+        Ok(sv) => service.diff(name, sv).await,
+        Err(e) => (StatusCode::BAD_REQUEST, e.to_string()).into_response(),
+    }
+}
+
+async fn push_update<P: StoreProvider>(
+    State(service): State<Arc<SyncService<P>>>,
+    Path(name): Path<String>,
+    body: Bytes,
+) -> Response {
+    if let Err(e) = Update::decode_v1(&body) {
+        return (StatusCode::BAD_REQUEST, e.to_string()).into_response();
+    }
+    let update = body.to_vec();
+    let doc = name.clone();
+    let result = service
+        .run(move |store| store.push_update(doc.as_bytes(), &update))
+        .await;
+    match result {
+        Ok(seq) => {
+            // let connected WebSocket peers know about the change
+            let msg = Message::Sync(SyncMessage::Update(body.to_vec()));
+            service.broadcast(&name, (u64::MAX, Arc::new(msg.encode_v1())));
+            seq.to_string().into_response()
+        }
+        Err(e) => e.into_response(),
+    }
+}
+
+async fn sync<P: StoreProvider>(
+    State(service): State<Arc<SyncService<P>>>,
+    Path(name): Path<String>,
+    ws: WebSocketUpgrade,
+) -> Response {
+    ws.on_upgrade(move |socket| service.serve(name, socket))
+}
+
+#[cfg(test)]
+mod test {
+    use crate::http::{handle_frame, StoreProvider};
+    use crate::memory::MemKVStore;
+    use std::sync::Mutex;
+    use yrs::sync::{Message, SyncMessage};
+    use yrs::updates::encoder::Encode;
+    use yrs::{Doc, ReadTxn, StateVector, Text, Transact};
+
+    #[test]
+    fn frame_replies_and_broadcasts() {
+        let provider = Mutex::new(MemKVStore::new());
+        let doc = Doc::new();
+        let text = doc.get_or_insert_text("text");
+        text.push(&mut doc.transact_mut(), "hello");
+        let update = doc.transact().encode_diff_v1(&StateVector::default());
+
+        let mut frame = Message::Sync(SyncMessage::Update(update.clone())).encode_v1();
+        frame.extend(Message::Sync(SyncMessage::SyncStep1(StateVector::default())).encode_v1());
+        let (replies, broadcasts) = provider
+            .with_store(|store| handle_frame(store, b"doc", &frame))
+            .unwrap();
+        assert_eq!(replies.len(), 1);
+        assert_eq!(
+            broadcasts,
+            vec![Message::Sync(SyncMessage::Update(update)).encode_v1()]
+        );
+
+        let exists = provider
+            .with_store(|store| store.doc_exists(b"doc"))
+            .unwrap();
+        assert!(exists);
+    }
+}
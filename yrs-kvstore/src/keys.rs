@@ -1,32 +1,127 @@
 use smallvec::{smallvec, SmallVec};
+use std::convert::TryInto;
 use std::io::Write;
 use std::ops::Deref;
 
 pub const V1: u8 = 0;
 
+/// Version of the key schema used by current version of the library.
+pub const SCHEMA_VERSION: u8 = V1;
+
 /*
    00{doc_name:n}0      - OID key pattern
-   01{oid:4}0           - document key pattern
+   01{oid:4}0           - document key pattern (lib0 v1 encoding)
    01{oid:4}1           - state vector key pattern
-   01{oid:4}2{clock:4}0 - document update key pattern
+   01{oid:4}2{clock:4}0 - document update key pattern (lib0 v1 encoding)
+   01{oid:4}2{clock:4}1 - document update key pattern (lib0 v2 encoding)
    01{oid:4}3{name:m}0  - document meta key pattern
+   01{oid:4}4           - document key pattern (lib0 v2 encoding)
+   01{oid:4}5{name:m}0  - document snapshot key pattern
+   01{oid:4}6{seq:4}0   - document history update key pattern (lib0 v1 encoding)
+   01{oid:4}6{seq:4}1   - document history update key pattern (lib0 v2 encoding)
+   01{oid:4}7{guid:m}0  - subdocument key pattern
+   01{oid:4}8           - document expiry key pattern
+   01{oid:4}9           - document awareness key pattern
+   01{oid:4}A           - document lease key pattern
+   01{oid:4}B           - document update deduplication window key pattern
+   01{oid:4}C{key:n}    - quarantined document entry key pattern
+   01{oid:4}D           - document last access key pattern
+   01{oid:4}E{tag:m}0   - document version tag key pattern
+   01{oid:4}F{clock:4}  - pending update timestamp key pattern
+   01{oid:4}10{clock:4} - pending update origin key pattern
+   01{oid:4}11          - indexed search terms key pattern
+   01{oid:4}12          - cached JSON rendering key pattern
+   01{oid:4}13{n:4}     - document state chunk key pattern
+   01{oid:4}14          - tracked state vector clock key pattern
+   01{oid:4}15          - pending updates summary key pattern
+   02{expiry:8}{oid:4}  - document expiry index key pattern
+   030                  - OID counter key
+   04{oid:4}            - flush-in-progress marker key pattern
+   05{seq:8}            - change log entry key pattern
+   06{digest:8}0        - content-addressed blob key pattern
+   06{digest:8}1        - blob reference count key pattern
+   07{doc_name:N}0      - archived document tombstone key pattern
+   08{doc_name:N}0      - trashed document key pattern
+   09{term:n}0{oid:4}   - search index posting key pattern
+   FFFF                 - schema version key
+
+  OIDs are 4 bytes long by default or 8 bytes long when `oid64` feature is enabled.
 
-  First 0 byte is marker for current version of records stored.
-  Second 0|1 byte is used to differentiate oid index and document key spaces.
+  First 0 byte is marker for current version of records stored. Schema version key is placed
+  outside of versioned key spaces, so that it can be read regardless of the schema version.
+  Second 0|1|2|3|4|5|6|7|8|9 byte is used to differentiate oid index, document, expiry index,
+  counter, flush marker, change log, blob, archived document, trash and search index key spaces.
 */
 
 pub const KEYSPACE_OID: u8 = 0;
 pub const KEYSPACE_DOC: u8 = 1;
+pub const KEYSPACE_EXPIRY: u8 = 2;
+pub const KEYSPACE_COUNTER: u8 = 3;
+pub const KEYSPACE_FLUSH: u8 = 4;
+pub const KEYSPACE_CHANGELOG: u8 = 5;
+pub const KEYSPACE_BLOB: u8 = 6;
+pub const KEYSPACE_ARCHIVED: u8 = 7;
+pub const KEYSPACE_TRASH: u8 = 8;
+pub const KEYSPACE_SEARCH: u8 = 9;
 
 pub const SUB_DOC: u8 = 0;
 pub const SUB_STATE_VEC: u8 = 1;
 pub const SUB_UPDATE: u8 = 2;
 pub const SUB_META: u8 = 3;
+pub const SUB_DOC_V2: u8 = 4;
+pub const SUB_SNAPSHOT: u8 = 5;
+pub const SUB_HISTORY: u8 = 6;
+pub const SUB_SUBDOC: u8 = 7;
+pub const SUB_EXPIRY: u8 = 8;
+pub const SUB_AWARENESS: u8 = 9;
+pub const SUB_LEASE: u8 = 10;
+pub const SUB_DEDUP: u8 = 11;
+pub const SUB_QUARANTINE: u8 = 12;
+pub const SUB_ACCESS: u8 = 13;
+pub const SUB_TAG: u8 = 14;
+pub const SUB_UPDATE_TIME: u8 = 15;
+pub const SUB_UPDATE_ORIGIN: u8 = 16;
+pub const SUB_SEARCH_TERMS: u8 = 17;
+pub const SUB_JSON: u8 = 18;
+pub const SUB_DOC_CHUNK: u8 = 19;
+pub const SUB_SV_CLOCK: u8 = 20;
+pub const SUB_PENDING: u8 = 21;
+
+/// Key under which the last OID allocated using [crate::OidAllocation::Counter] is stored.
+pub const KEY_OID_COUNTER: [u8; 3] = [V1, KEYSPACE_COUNTER, 0];
+
+/// Key under which a version of the key schema used by the database is stored. If it's missing,
+/// database is assumed to use [V1] schema.
+pub const KEY_SCHEMA_VERSION: [u8; 2] = [0xFF, 0xFF];
 
 pub const TERMINATOR: u8 = 0;
 pub const TERMINATOR_HI_WATERMARK: u8 = 255;
 
+/// Last byte of an update key, marking that the update has been encoded using lib0 v1 encoding.
+pub const ENCODING_V1: u8 = TERMINATOR;
+/// Last byte of an update key, marking that the update has been encoded using lib0 v2 encoding.
+pub const ENCODING_V2: u8 = 1;
+
+/// Identifier of a document, used to address all of its entries in the document keyspace. By
+/// default OIDs are 4 bytes long, which can be widened to 8 bytes using `oid64` cargo feature for
+/// deployments hosting hundreds of millions of documents. Both key layouts are incompatible with
+/// each other, so the feature must be chosen before the database is created.
+#[cfg(not(feature = "oid64"))]
 pub type OID = u32;
+/// Identifier of a document, used to address all of its entries in the document keyspace.
+#[cfg(feature = "oid64")]
+pub type OID = u64;
+
+/// Length of the [OID] in bytes.
+pub const OID_LEN: usize = std::mem::size_of::<OID>();
+
+/// Position of a sub-key byte in the document keyspace keys: `{version:1}{keyspace:1}{oid}{sub:1}`.
+pub const SUB_KEY_POS: usize = 2 + OID_LEN;
+
+/// Decodes an [OID] from its big endian binary representation.
+pub fn oid_from_bytes(bytes: &[u8]) -> OID {
+    OID::from_be_bytes(bytes.try_into().unwrap())
+}
 
 pub fn key_oid(doc_name: &[u8]) -> Key<20> {
     let mut v: SmallVec<[u8; 20]> = smallvec![V1, KEYSPACE_OID];
@@ -42,6 +137,13 @@ pub fn key_doc(oid: OID) -> Key<8> {
     Key(v)
 }
 
+pub fn key_doc_v2(oid: OID) -> Key<8> {
+    let mut v: SmallVec<[u8; 8]> = smallvec![V1, KEYSPACE_DOC];
+    v.write_all(&oid.to_be_bytes()).unwrap();
+    v.push(SUB_DOC_V2);
+    Key(v)
+}
+
 pub fn key_doc_start(oid: OID) -> Key<8> {
     key_doc(oid)
 }
@@ -60,6 +162,15 @@ pub fn key_state_vector(oid: OID) -> Key<8> {
     Key(v)
 }
 
+/// Key under which a number and a total size of pending updates of a document with a given `oid`
+/// are kept, so that compaction limits can be checked without reading them all.
+pub fn key_pending_summary(oid: OID) -> Key<8> {
+    let mut v: SmallVec<[u8; 8]> = smallvec![V1, KEYSPACE_DOC];
+    v.write_all(&oid.to_be_bytes()).unwrap();
+    v.push(SUB_PENDING);
+    Key(v)
+}
+
 pub fn key_update(oid: OID, clock: u32) -> Key<12> {
     let mut v: SmallVec<[u8; 12]> = smallvec![V1, KEYSPACE_DOC];
     v.write_all(&oid.to_be_bytes()).unwrap();
@@ -69,8 +180,28 @@ pub fn key_update(oid: OID, clock: u32) -> Key<12> {
     Key(v)
 }
 
+pub fn key_update_v2(oid: OID, clock: u32) -> Key<12> {
+    let mut v: SmallVec<[u8; 12]> = smallvec![V1, KEYSPACE_DOC];
+    v.write_all(&oid.to_be_bytes()).unwrap();
+    v.push(SUB_UPDATE);
+    v.write_all(&clock.to_be_bytes()).unwrap();
+    v.push(ENCODING_V2);
+    Key(v)
+}
+
+/// Returns a clock (sequence number) of a given update key.
+pub fn update_clock(key: &[u8]) -> u32 {
+    let len = key.len();
+    u32::from_be_bytes(key[(len - 5)..(len - 1)].try_into().unwrap())
+}
+
+/// Returns lib0 encoding version marker of a given update key.
+pub fn update_encoding(key: &[u8]) -> u8 {
+    key[key.len() - 1]
+}
+
 pub fn doc_meta_name(key: &[u8]) -> &[u8] {
-    &key[7..(key.len() - 1)]
+    &key[(SUB_KEY_POS + 1)..(key.len() - 1)]
 }
 
 pub fn doc_oid_name(key: &[u8]) -> &[u8] {
@@ -101,6 +232,382 @@ pub fn key_meta_end(oid: OID) -> Key<8> {
     Key(v)
 }
 
+/// Returns the lowest possible metadata key of a document with given `oid`, which name starts with
+/// a given `prefix`.
+pub fn key_meta_prefix_start(oid: OID, prefix: &[u8]) -> Key<20> {
+    let mut v: SmallVec<[u8; 20]> = smallvec![V1, KEYSPACE_DOC];
+    v.write_all(&oid.to_be_bytes()).unwrap();
+    v.push(SUB_META);
+    v.write_all(prefix).unwrap();
+    Key(v)
+}
+
+/// Returns a key greater than all metadata keys of a document with given `oid`, which names start
+/// with a given `prefix`.
+pub fn key_meta_prefix_end(oid: OID, prefix: &[u8]) -> Key<20> {
+    // metadata names are followed by a terminator byte, so no metadata key is equal to the
+    // incremented prefix itself
+    let mut v: SmallVec<[u8; 20]> = smallvec![V1, KEYSPACE_DOC];
+    v.write_all(&oid.to_be_bytes()).unwrap();
+    match prefix.iter().rposition(|b| *b != u8::MAX) {
+        Some(i) => {
+            v.push(SUB_META);
+            v.write_all(&prefix[..i]).unwrap();
+            v.push(prefix[i] + 1);
+        }
+        None => v.push(SUB_META + 1),
+    }
+    Key(v)
+}
+
+pub fn doc_snapshot_id(key: &[u8]) -> &[u8] {
+    &key[(SUB_KEY_POS + 1)..(key.len() - 1)]
+}
+
+pub fn key_snapshot(oid: OID, snapshot_id: &[u8]) -> Key<20> {
+    let mut v: SmallVec<[u8; 20]> = smallvec![V1, KEYSPACE_DOC];
+    v.write_all(&oid.to_be_bytes()).unwrap();
+    v.push(SUB_SNAPSHOT);
+    v.write_all(snapshot_id).unwrap();
+    v.push(TERMINATOR);
+    Key(v)
+}
+
+pub fn key_snapshot_start(oid: OID) -> Key<8> {
+    let mut v: SmallVec<[u8; 8]> = smallvec![V1, KEYSPACE_DOC];
+    v.write_all(&oid.to_be_bytes()).unwrap();
+    v.push(SUB_SNAPSHOT);
+    v.push(TERMINATOR);
+    Key(v)
+}
+
+pub fn key_snapshot_end(oid: OID) -> Key<8> {
+    let mut v: SmallVec<[u8; 8]> = smallvec![V1, KEYSPACE_DOC];
+    v.write_all(&oid.to_be_bytes()).unwrap();
+    v.push(SUB_SNAPSHOT + 1);
+    Key(v)
+}
+
+pub fn doc_tag_name(key: &[u8]) -> &[u8] {
+    &key[(SUB_KEY_POS + 1)..(key.len() - 1)]
+}
+
+pub fn key_tag(oid: OID, tag: &[u8]) -> Key<20> {
+    let mut v: SmallVec<[u8; 20]> = smallvec![V1, KEYSPACE_DOC];
+    v.write_all(&oid.to_be_bytes()).unwrap();
+    v.push(SUB_TAG);
+    v.write_all(tag).unwrap();
+    v.push(TERMINATOR);
+    Key(v)
+}
+
+pub fn key_tag_start(oid: OID) -> Key<8> {
+    let mut v: SmallVec<[u8; 8]> = smallvec![V1, KEYSPACE_DOC];
+    v.write_all(&oid.to_be_bytes()).unwrap();
+    v.push(SUB_TAG);
+    v.push(TERMINATOR);
+    Key(v)
+}
+
+pub fn key_tag_end(oid: OID) -> Key<8> {
+    let mut v: SmallVec<[u8; 8]> = smallvec![V1, KEYSPACE_DOC];
+    v.write_all(&oid.to_be_bytes()).unwrap();
+    v.push(SUB_TAG + 1);
+    Key(v)
+}
+
+pub fn key_history(oid: OID, seq: u32) -> Key<12> {
+    let mut v: SmallVec<[u8; 12]> = smallvec![V1, KEYSPACE_DOC];
+    v.write_all(&oid.to_be_bytes()).unwrap();
+    v.push(SUB_HISTORY);
+    v.write_all(&seq.to_be_bytes()).unwrap();
+    v.push(TERMINATOR);
+    Key(v)
+}
+
+pub fn key_history_v2(oid: OID, seq: u32) -> Key<12> {
+    let mut v: SmallVec<[u8; 12]> = smallvec![V1, KEYSPACE_DOC];
+    v.write_all(&oid.to_be_bytes()).unwrap();
+    v.push(SUB_HISTORY);
+    v.write_all(&seq.to_be_bytes()).unwrap();
+    v.push(ENCODING_V2);
+    Key(v)
+}
+
+/// Returns a sequence number of a given document history key.
+pub fn history_seq(key: &[u8]) -> u32 {
+    let len = key.len();
+    u32::from_be_bytes(key[(len - 5)..(len - 1)].try_into().unwrap())
+}
+
+pub fn subdoc_guid(key: &[u8]) -> &[u8] {
+    &key[(SUB_KEY_POS + 1)..(key.len() - 1)]
+}
+
+pub fn key_subdoc(oid: OID, guid: &[u8]) -> Key<48> {
+    let mut v: SmallVec<[u8; 48]> = smallvec![V1, KEYSPACE_DOC];
+    v.write_all(&oid.to_be_bytes()).unwrap();
+    v.push(SUB_SUBDOC);
+    v.write_all(guid).unwrap();
+    v.push(TERMINATOR);
+    Key(v)
+}
+
+pub fn key_subdoc_start(oid: OID) -> Key<8> {
+    let mut v: SmallVec<[u8; 8]> = smallvec![V1, KEYSPACE_DOC];
+    v.write_all(&oid.to_be_bytes()).unwrap();
+    v.push(SUB_SUBDOC);
+    v.push(TERMINATOR);
+    Key(v)
+}
+
+pub fn key_subdoc_end(oid: OID) -> Key<8> {
+    let mut v: SmallVec<[u8; 8]> = smallvec![V1, KEYSPACE_DOC];
+    v.write_all(&oid.to_be_bytes()).unwrap();
+    v.push(SUB_SUBDOC);
+    v.push(TERMINATOR_HI_WATERMARK);
+    Key(v)
+}
+
+pub fn key_expiry(oid: OID) -> Key<8> {
+    let mut v: SmallVec<[u8; 8]> = smallvec![V1, KEYSPACE_DOC];
+    v.write_all(&oid.to_be_bytes()).unwrap();
+    v.push(SUB_EXPIRY);
+    Key(v)
+}
+
+pub fn key_awareness(oid: OID) -> Key<8> {
+    let mut v: SmallVec<[u8; 8]> = smallvec![V1, KEYSPACE_DOC];
+    v.write_all(&oid.to_be_bytes()).unwrap();
+    v.push(SUB_AWARENESS);
+    Key(v)
+}
+
+pub fn key_lease(oid: OID) -> Key<8> {
+    let mut v: SmallVec<[u8; 8]> = smallvec![V1, KEYSPACE_DOC];
+    v.write_all(&oid.to_be_bytes()).unwrap();
+    v.push(SUB_LEASE);
+    Key(v)
+}
+
+pub fn key_dedup(oid: OID) -> Key<8> {
+    let mut v: SmallVec<[u8; 8]> = smallvec![V1, KEYSPACE_DOC];
+    v.write_all(&oid.to_be_bytes()).unwrap();
+    v.push(SUB_DEDUP);
+    Key(v)
+}
+
+/// Key of a timestamp of the last access to a document (see: [crate::tiered::TieredStore]).
+pub fn key_access(oid: OID) -> Key<8> {
+    let mut v: SmallVec<[u8; 8]> = smallvec![V1, KEYSPACE_DOC];
+    v.write_all(&oid.to_be_bytes()).unwrap();
+    v.push(SUB_ACCESS);
+    Key(v)
+}
+
+/// Key of a timestamp at which a pending update with a given `clock` has been pushed (see:
+/// [crate::DocOps::update_timestamps]).
+pub fn key_update_time(oid: OID, clock: u32) -> Key<12> {
+    let mut v: SmallVec<[u8; 12]> = smallvec![V1, KEYSPACE_DOC];
+    v.write_all(&oid.to_be_bytes()).unwrap();
+    v.push(SUB_UPDATE_TIME);
+    v.write_all(&clock.to_be_bytes()).unwrap();
+    Key(v)
+}
+
+/// Returns a clock of a pending update, which timestamp is stored under a given `key`.
+pub fn update_time_clock(key: &[u8]) -> u32 {
+    u32::from_be_bytes(
+        key[(SUB_KEY_POS + 1)..(SUB_KEY_POS + 5)]
+            .try_into()
+            .unwrap(),
+    )
+}
+
+/// Key of an origin of a pending update with a given `clock` (see:
+/// [crate::DocOps::push_update_with_origin]).
+pub fn key_update_origin(oid: OID, clock: u32) -> Key<12> {
+    let mut v: SmallVec<[u8; 12]> = smallvec![V1, KEYSPACE_DOC];
+    v.write_all(&oid.to_be_bytes()).unwrap();
+    v.push(SUB_UPDATE_ORIGIN);
+    v.write_all(&clock.to_be_bytes()).unwrap();
+    Key(v)
+}
+
+/// Returns a clock of a pending update, which origin is stored under a given `key`.
+pub fn update_origin_clock(key: &[u8]) -> u32 {
+    u32::from_be_bytes(
+        key[(SUB_KEY_POS + 1)..(SUB_KEY_POS + 5)]
+            .try_into()
+            .unwrap(),
+    )
+}
+
+/// Key of a quarantined document entry, originally stored under a `sub_key` of a document keyspace
+/// (everything following document OID).
+pub fn key_quarantine(oid: OID, sub_key: &[u8]) -> Key<20> {
+    let mut v: SmallVec<[u8; 20]> = smallvec![V1, KEYSPACE_DOC];
+    v.write_all(&oid.to_be_bytes()).unwrap();
+    v.push(SUB_QUARANTINE);
+    v.write_all(sub_key).unwrap();
+    Key(v)
+}
+
+pub fn key_quarantine_start(oid: OID) -> Key<8> {
+    let mut v: SmallVec<[u8; 8]> = smallvec![V1, KEYSPACE_DOC];
+    v.write_all(&oid.to_be_bytes()).unwrap();
+    v.push(SUB_QUARANTINE);
+    Key(v)
+}
+
+pub fn key_quarantine_end(oid: OID) -> Key<8> {
+    let mut v: SmallVec<[u8; 8]> = smallvec![V1, KEYSPACE_DOC];
+    v.write_all(&oid.to_be_bytes()).unwrap();
+    v.push(SUB_QUARANTINE + 1);
+    Key(v)
+}
+
+pub fn key_flush_marker(oid: OID) -> Key<8> {
+    let mut v: SmallVec<[u8; 8]> = smallvec![V1, KEYSPACE_FLUSH];
+    v.write_all(&oid.to_be_bytes()).unwrap();
+    Key(v)
+}
+
+pub fn key_expiry_index(expiry: u64, oid: OID) -> Key<14> {
+    let mut v: SmallVec<[u8; 14]> = smallvec![V1, KEYSPACE_EXPIRY];
+    v.write_all(&expiry.to_be_bytes()).unwrap();
+    v.write_all(&oid.to_be_bytes()).unwrap();
+    Key(v)
+}
+
+/// Returns a key of the change log entry with a given sequence number.
+pub fn key_changelog(seq: u64) -> Key<10> {
+    let mut v: SmallVec<[u8; 10]> = smallvec![V1, KEYSPACE_CHANGELOG];
+    v.write_all(&seq.to_be_bytes()).unwrap();
+    Key(v)
+}
+
+pub fn changelog_seq(key: &[u8]) -> u64 {
+    u64::from_be_bytes(key[2..10].try_into().unwrap())
+}
+
+/// Returns a key of a tombstone left after a document with a given `name` has been archived (see:
+/// [crate::DocOps::archive_doc]). Its value is a location of the document archive.
+pub fn key_archived(doc_name: &[u8]) -> Key<20> {
+    let mut v: SmallVec<[u8; 20]> = smallvec![V1, KEYSPACE_ARCHIVED];
+    v.write_all(doc_name).unwrap();
+    v.push(TERMINATOR);
+    Key(v)
+}
+
+/// Returns a key under which a document with a given `name` is kept, once it has been moved into
+/// a trash (see: [crate::DocOps::trash_doc]).
+pub fn key_trash(doc_name: &[u8]) -> Key<20> {
+    let mut v: SmallVec<[u8; 20]> = smallvec![V1, KEYSPACE_TRASH];
+    v.write_all(doc_name).unwrap();
+    v.push(TERMINATOR);
+    Key(v)
+}
+
+pub fn trashed_doc_name(key: &[u8]) -> &[u8] {
+    &key[2..(key.len() - 1)]
+}
+
+/// Returns a key of a search index posting, which marks a document with a given `oid` as
+/// containing a given `term` (see: [crate::search]).
+pub fn key_posting(term: &[u8], oid: OID) -> Key<24> {
+    let mut v: SmallVec<[u8; 24]> = smallvec![V1, KEYSPACE_SEARCH];
+    v.write_all(term).unwrap();
+    v.push(TERMINATOR);
+    v.write_all(&oid.to_be_bytes()).unwrap();
+    Key(v)
+}
+
+pub fn key_posting_start(term: &[u8]) -> Key<20> {
+    let mut v: SmallVec<[u8; 20]> = smallvec![V1, KEYSPACE_SEARCH];
+    v.write_all(term).unwrap();
+    v.push(TERMINATOR);
+    Key(v)
+}
+
+pub fn key_posting_end(term: &[u8]) -> Key<20> {
+    let mut v: SmallVec<[u8; 20]> = smallvec![V1, KEYSPACE_SEARCH];
+    v.write_all(term).unwrap();
+    v.push(TERMINATOR + 1);
+    Key(v)
+}
+
+/// Returns an OID of a document, which search index posting is stored under a given `key`.
+pub fn posting_oid(key: &[u8]) -> OID {
+    oid_from_bytes(&key[(key.len() - OID_LEN)..])
+}
+
+/// Key under which search terms indexed for a document with a given `oid` are stored, so that
+/// its postings can be removed once it's indexed again (see: [crate::search]).
+pub fn key_search_terms(oid: OID) -> Key<8> {
+    let mut v: SmallVec<[u8; 8]> = smallvec![V1, KEYSPACE_DOC];
+    v.write_all(&oid.to_be_bytes()).unwrap();
+    v.push(SUB_SEARCH_TERMS);
+    Key(v)
+}
+
+/// Key under which a JSON rendering of a document with a given `oid` is cached (see:
+/// [crate::export]).
+pub fn key_json(oid: OID) -> Key<8> {
+    let mut v: SmallVec<[u8; 8]> = smallvec![V1, KEYSPACE_DOC];
+    v.write_all(&oid.to_be_bytes()).unwrap();
+    v.push(SUB_JSON);
+    Key(v)
+}
+
+/// Key under which a clock of the last pending update of a document with a given `oid`, which has
+/// been merged into its stored state vector, is kept (see:
+/// [crate::options::StoreOptions::track_state_vector]).
+pub fn key_state_vector_clock(oid: OID) -> Key<8> {
+    let mut v: SmallVec<[u8; 8]> = smallvec![V1, KEYSPACE_DOC];
+    v.write_all(&oid.to_be_bytes()).unwrap();
+    v.push(SUB_SV_CLOCK);
+    Key(v)
+}
+
+/// Returns a key of the `n`-th chunk of a state of a document with a given `oid` (see:
+/// [crate::chunk]).
+pub fn key_doc_chunk(oid: OID, n: u32) -> Key<12> {
+    let mut v: SmallVec<[u8; 12]> = smallvec![V1, KEYSPACE_DOC];
+    v.write_all(&oid.to_be_bytes()).unwrap();
+    v.push(SUB_DOC_CHUNK);
+    v.write_all(&n.to_be_bytes()).unwrap();
+    Key(v)
+}
+
+/// Returns an index of a document state chunk stored under a given `key`.
+pub fn doc_chunk_index(key: &[u8]) -> u32 {
+    u32::from_be_bytes(key[(key.len() - 4)..].try_into().unwrap())
+}
+
+/// Returns a key of a blob with a given `digest` (`{crc32:4}{len:4}` of its content).
+pub fn key_blob(digest: &[u8]) -> Key<11> {
+    let mut v: SmallVec<[u8; 11]> = smallvec![V1, KEYSPACE_BLOB];
+    v.write_all(digest).unwrap();
+    v.push(0);
+    Key(v)
+}
+
+/// Returns a key, under which a number of references to a blob with a given `digest` is stored.
+pub fn key_blob_refs(digest: &[u8]) -> Key<11> {
+    let mut v: SmallVec<[u8; 11]> = smallvec![V1, KEYSPACE_BLOB];
+    v.write_all(digest).unwrap();
+    v.push(1);
+    Key(v)
+}
+
+/// Returns an expiry timestamp and OID of a given document expiry index key.
+pub fn expiry_index_entry(key: &[u8]) -> (u64, OID) {
+    let expiry = u64::from_be_bytes(key[2..10].try_into().unwrap());
+    let oid = oid_from_bytes(&key[10..(10 + OID_LEN)]);
+    (expiry, oid)
+}
+
 #[repr(transparent)]
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct Key<const N: usize>(SmallVec<[u8; N]>);
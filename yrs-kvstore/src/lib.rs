@@ -1,15 +1,89 @@
+/// Records a value of a given field of the current tracing span. Does nothing unless `tracing`
+/// feature is enabled.
+macro_rules! trace_record {
+    ($field:literal, $value:expr) => {
+        #[cfg(feature = "tracing")]
+        tracing::Span::current().record($field, $value);
+    };
+}
+
+pub mod archive;
+#[cfg(feature = "async")]
+pub mod asynchronous;
+pub mod batch;
+pub mod binding;
+pub mod blob;
+pub mod cached;
+pub mod cancel;
+pub mod checksum;
+pub mod chunk;
+pub mod codec;
+pub mod compression;
+pub mod dynamic;
+#[cfg(feature = "encryption")]
+pub mod encryption;
 pub mod error;
+pub mod export;
+#[cfg(feature = "http")]
+pub mod http;
 pub mod keys;
+pub mod memory;
+pub mod metrics;
+pub mod observer;
+pub mod options;
+#[cfg(feature = "async")]
+pub mod range_kv;
+pub mod read_only;
+pub mod replication;
+pub mod scoped;
+#[cfg(feature = "search")]
+pub mod search;
+pub mod sync;
+pub mod testing;
+pub mod tiered;
+pub mod validation;
 
-use crate::error::Error;
+use crate::archive::{read_dump_entry, write_dump_entry, ArchiveSink, DocArchive};
+use crate::batch::{BatchOp, DocOp, MultiDocBatch, WriteBatch};
+use crate::blob::BlobRefs;
+use crate::cancel::Cancel;
+use crate::codec::{Checksummed, Compressed, ValueCodec};
+use crate::error::{Error, ErrorContext, StoreError};
 use crate::keys::{
-    doc_oid_name, key_doc, key_doc_end, key_doc_start, key_meta, key_meta_end, key_meta_start,
-    key_oid, key_state_vector, key_update, Key, KEYSPACE_DOC, KEYSPACE_OID, OID, V1,
+    changelog_seq, doc_oid_name, doc_snapshot_id, doc_tag_name, expiry_index_entry, history_seq,
+    key_archived, key_awareness, key_changelog, key_dedup, key_doc, key_doc_end, key_doc_start,
+    key_doc_v2, key_expiry, key_expiry_index, key_flush_marker, key_history, key_history_v2,
+    key_json, key_lease, key_meta, key_meta_end, key_meta_prefix_end, key_meta_prefix_start,
+    key_meta_start, key_oid, key_pending_summary, key_posting, key_quarantine, key_quarantine_end,
+    key_quarantine_start, key_search_terms, key_snapshot, key_snapshot_end, key_snapshot_start,
+    key_state_vector, key_state_vector_clock, key_subdoc, key_subdoc_end, key_subdoc_start,
+    key_tag, key_tag_end, key_tag_start, key_trash, key_update, key_update_origin, key_update_time,
+    key_update_v2, oid_from_bytes, subdoc_guid, trashed_doc_name, update_clock, update_encoding,
+    update_origin_clock, update_time_clock, Key, ENCODING_V2, KEYSPACE_CHANGELOG, KEYSPACE_DOC,
+    KEYSPACE_EXPIRY, KEYSPACE_FLUSH, KEYSPACE_OID, KEYSPACE_TRASH, KEY_OID_COUNTER,
+    KEY_SCHEMA_VERSION, OID, OID_LEN, SCHEMA_VERSION, SUB_ACCESS, SUB_AWARENESS, SUB_DEDUP,
+    SUB_DOC, SUB_DOC_CHUNK, SUB_DOC_V2, SUB_EXPIRY, SUB_HISTORY, SUB_JSON, SUB_KEY_POS, SUB_LEASE,
+    SUB_META, SUB_QUARANTINE, SUB_SEARCH_TERMS, SUB_SNAPSHOT, SUB_STATE_VEC, SUB_SUBDOC,
+    SUB_SV_CLOCK, SUB_TAG, SUB_UPDATE, SUB_UPDATE_ORIGIN, SUB_UPDATE_TIME, TERMINATOR, V1,
 };
+use crate::metrics::StoreMetrics;
+use crate::observer::{ChangeSummary, UpdateEvent, UpdateObservers, UpdateSubscription};
+use crate::options::StoreOptions;
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
 use std::convert::TryInto;
+use std::ops::Deref;
+use std::sync::{Arc, OnceLock};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+#[cfg(feature = "tracing")]
+use tracing::field::Empty;
+use yrs::types::text::YChange;
 use yrs::updates::decoder::Decode;
 use yrs::updates::encoder::Encode;
-use yrs::{Doc, ReadTxn, StateVector, Transact, TransactionMut, Update};
+use yrs::updates::encoder::{Encoder, EncoderV1};
+use yrs::{
+    Doc, Origin, ReadTxn, Snapshot, StateVector, Text, Transact, TransactionMut, Update, ID,
+};
 
 /// A trait to be implemented by the specific key-value store transaction equivalent in order to
 /// auto-implement features provided by [DocOps] trait.
@@ -40,12 +114,129 @@ pub trait KVStore<'a> {
     /// Return an iterator over all entries between `from`..=`to` range of keys.
     fn iter_range(&self, from: &[u8], to: &[u8]) -> Result<Self::Cursor, Self::Error>;
 
+    /// Checks if there's any entry within `from`..=`to` range of keys.
+    ///
+    /// By default it opens a cursor using [Self::iter_range]. Backends, which are able to check it
+    /// cheaper (i.e. with a single seek), should override this method, as it's used on hot paths
+    /// like [DocOps::has_pending_updates].
+    fn contains_range(&self, from: &[u8], to: &[u8]) -> Result<bool, Self::Error> {
+        Ok(self.iter_range(from, to)?.next().is_some())
+    }
+
     /// Looks into the last entry value prior to a given key. The provided key parameter may not
     /// exist and it's used only to establish cursor position in ordered key collection.
     ///
     /// In example: in a key collection of `{1,2,5,7}`, this method with the key parameter of `4`
     /// should return value of `2`.
     fn peek_back(&self, key: &[u8]) -> Result<Option<Self::Entry>, Self::Error>;
+
+    /// Returns a new, empty [WriteBatch], which can be used to gather multiple write operations
+    /// and apply them together using [Self::commit_batch].
+    fn batch(&self) -> WriteBatch {
+        WriteBatch::new()
+    }
+
+    /// Applies all operations recorded in a given `batch` in order they were recorded.
+    ///
+    /// By default operations are applied one by one, which is sufficient for implementations
+    /// working on top of database transactions. Backends which support atomic batch writes
+    /// should override this method, so that a crash never leaves partially applied batch behind.
+    fn commit_batch(&self, batch: WriteBatch) -> Result<(), Self::Error> {
+        for op in batch {
+            match op {
+                BatchOp::Upsert(key, value) => self.upsert(&key, &value)?,
+                BatchOp::Remove(key) => self.remove(&key)?,
+                BatchOp::RemoveRange(from, to) => self.remove_range(&from, &to)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Policy used to determine when pending updates of a document should be automatically merged
+/// into the document state (see: [DocOps::flush_doc]) as part of [DocOps::push_update] call. This
+/// prevents long-lived documents from accumulating thousands of unmerged updates.
+///
+/// Default policy never triggers automatic compaction.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CompactionPolicy {
+    /// Maximum number of pending updates stored for a single document. Once exceeded, document
+    /// updates will be compacted.
+    pub max_pending_updates: Option<u32>,
+    /// Maximum number of bytes of all pending updates stored for a single document. Once exceeded,
+    /// document updates will be compacted.
+    pub max_pending_bytes: Option<u64>,
+    /// If set, updates merged into the document state by [DocOps::flush_doc] are not deleted, but
+    /// moved into the document history instead (see: [DocOps::iter_history]). This way
+    /// applications can reconstruct intermediate document states or audit edits.
+    pub retain_history: bool,
+}
+
+/// Policy determining which pending updates of a document are retained in their original form,
+/// when [DocOps::enforce_retention] is called. Updates falling outside of all configured limits
+/// are merged into the document state, just like [DocOps::flush_doc] does. Since updates are
+/// merged in order, the first retained update keeps all updates pushed after it as well.
+///
+/// Default policy has no limits configured and retains all updates.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RetentionPolicy {
+    /// Number of the most recent pending updates, which are always retained.
+    pub keep_updates: Option<u32>,
+    /// Period for which pending updates are retained since they have been pushed. Update age is
+    /// known only if it was pushed while [DocOps::update_timestamps] were enabled - updates
+    /// without timestamps are always retained.
+    pub keep_for: Option<Duration>,
+    /// If set, pending updates are retained until all of their changes have been captured by one
+    /// of the document snapshots (see: [DocOps::create_snapshot]).
+    pub keep_since_snapshot: bool,
+}
+
+impl RetentionPolicy {
+    fn is_empty(&self) -> bool {
+        self.keep_updates.is_none() && self.keep_for.is_none() && !self.keep_since_snapshot
+    }
+}
+
+/// Limits protecting the store from malicious or buggy clients pushing gigantic updates (see:
+/// [DocOps::push_update]). Limits are checked before an update is written, so rejected updates
+/// are never persisted.
+///
+/// Default limits accept updates of any size.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct UpdateLimits {
+    /// Maximum size (in bytes) of a single pushed update. Larger updates are rejected with
+    /// [StoreError::UpdateTooLarge] error.
+    pub max_update_bytes: Option<usize>,
+    /// Maximum number of bytes of all pending updates stored for a single document, including the
+    /// pushed ones. Updates are measured after they have been encoded (see:
+    /// [StoreOptions::codecs]). Once exceeded, pushed updates are rejected with
+    /// [StoreError::UpdateLogTooLarge] error, unless [Self::flush_on_overflow] is set.
+    pub max_pending_bytes: Option<u64>,
+    /// If set, pending updates are merged into the document state (see: [DocOps::flush_doc])
+    /// when pushed updates would exceed [Self::max_pending_bytes], instead of rejecting them.
+    pub flush_on_overflow: bool,
+}
+
+/// Strategy used to allocate OIDs for documents, which are persisted for the first time (see:
+/// [StoreOptions::oid_allocation]). Switching between strategies doesn't require migrating existing
+/// data.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OidAllocation {
+    /// OIDs are assigned from a monotonic counter stored under [keys::KEY_OID_COUNTER], which is
+    /// incremented in the same write batch that persists the OID of a new document. Concurrent
+    /// writers are serialized by [DocOps::lock_oid_allocation] and isolation of the underlying
+    /// transaction.
+    ///
+    /// Databases created before the counter was introduced have it initialized from the highest
+    /// OID in use, when the first new document is persisted.
+    #[default]
+    Counter,
+    /// OIDs are picked at random, so that multiple writers don't need to coordinate over a shared
+    /// counter. Candidates already used by other documents are rejected. This strategy requires
+    /// 8-byte OIDs enabled by `oid64` feature, as the 4-byte OID space is too small to make random
+    /// collisions unlikely.
+    #[cfg(feature = "oid64")]
+    Random,
 }
 
 pub trait KVEntry {
@@ -58,6 +249,51 @@ pub trait DocOps<'a>: KVStore<'a> + Sized
 where
     Error: From<<Self as KVStore<'a>>::Error>,
 {
+    /// Called before a new OID is allocated for a document, which has not been persisted before.
+    /// Backends allowing for concurrent writers can use it to serialize OID allocation, i.e. by
+    /// acquiring a lock for the duration of the current transaction. After this method returns,
+    /// the OID mapping of a document is checked again before allocating a new one.
+    ///
+    /// By default this method does nothing.
+    fn lock_oid_allocation(&self) -> Result<(), Error> {
+        Ok(())
+    }
+
+    /// Returns [StoreOptions] used to configure current store. By default all settings have
+    /// their default values (see: [StoreOptions::default]).
+    fn options(&self) -> &StoreOptions {
+        static DEFAULT: OnceLock<StoreOptions> = OnceLock::new();
+        DEFAULT.get_or_init(StoreOptions::default)
+    }
+
+    /// Returns an [UpdateObservers] registry used by current store to notify about persisted
+    /// document updates. By default stores don't support observing updates and `None` is returned.
+    fn update_observers(&self) -> Option<&UpdateObservers> {
+        None
+    }
+
+    /// Returns a [StoreMetrics] receiver, which current store reports counts and latencies of its
+    /// operations into (see: [crate::metrics::Metrics]). By default no metrics are reported.
+    fn metrics(&self) -> Option<&dyn StoreMetrics> {
+        None
+    }
+
+    /// Registers a `callback` called whenever an update of a document with a given `name` is
+    /// persisted via [Self::push_update] (and its variants) or [Self::insert_doc] (and its
+    /// variants). Callback receives raw update bytes together with their sequence number, which
+    /// makes it useful i.e. for broadcasting persisted changes to connected peers.
+    ///
+    /// Callback stays registered for as long as returned subscription is alive. Returns `None` if
+    /// current store doesn't support observing updates (see: [Self::update_observers]).
+    fn observe_updates<K, F>(&self, name: &K, callback: F) -> Option<UpdateSubscription>
+    where
+        K: AsRef<[u8]> + ?Sized,
+        F: Fn(&UpdateEvent) + Send + Sync + 'static,
+    {
+        let observers = self.update_observers()?;
+        Some(observers.subscribe(name.as_ref(), callback))
+    }
+
     /// Inserts or updates a document given it's read transaction and name. lib0 v1 encoding is
     /// used for storing the document.
     ///
@@ -72,6 +308,35 @@ where
         self.insert_doc_raw_v1(name.as_ref(), &doc_state, &state_vector)
     }
 
+    /// Inserts or updates a document given it's read transaction and name, just like
+    /// [Self::insert_doc] does, but only if the current state vector of a stored document
+    /// (including its pending updates) is equal to `expected_sv`. Document which doesn't exist
+    /// is considered to have an empty state vector. This enables compare-and-set semantics for
+    /// services caching documents in memory, which need to detect concurrent writers.
+    ///
+    /// Returns [StoreError::Conflict] if state vectors differ, in which case document is not
+    /// modified.
+    ///
+    /// This feature requires a write capabilities from the database transaction.
+    fn insert_doc_if<K: AsRef<[u8]> + ?Sized, T: ReadTxn>(
+        &self,
+        name: &K,
+        txn: &T,
+        expected_sv: &StateVector,
+    ) -> Result<(), Error> {
+        let name = name.as_ref();
+        let current_sv = if let Some(oid) = get_oid(self, name)? {
+            current_state_vector(self, oid)?
+        } else {
+            StateVector::default()
+        };
+        if &current_sv != expected_sv {
+            let name = String::from_utf8_lossy(name).into_owned();
+            return Err(StoreError::Conflict(name).into());
+        }
+        self.insert_doc(name, txn)
+    }
+
     /// Inserts or updates a document given it's binary update and state vector. lib0 v1 encoding is
     /// assumed as a format for storing the document.
     ///
@@ -85,27 +350,338 @@ where
         doc_state_v1: &[u8],
         doc_sv_v1: &[u8],
     ) -> Result<(), Error> {
+        let start = Instant::now();
+        validate_update(self, name, doc_state_v1, false)?;
+        let oid = get_or_create_oid(self, name)?;
+        let doc_state = encode_value(self, doc_state_v1)?;
+        let mut batch = self.batch();
+        let doc_state = share_state(self, &mut batch, oid, doc_state)?;
+        insert_inner_v1(&mut batch, oid, &doc_state, doc_sv_v1);
+        log_changes(self, &mut batch, [(name, oid, ChangeKind::Insert)])?;
+        self.commit_batch(batch)?;
+        publish_update(self, name, doc_state_v1, None, false);
+        if let Some(metrics) = self.metrics() {
+            let bytes = (doc_state.len() + doc_sv_v1.len()) as u64;
+            metrics.doc_inserted(name, bytes, start.elapsed());
+        }
+        Ok(())
+    }
+
+    /// Inserts or updates a document given it's binary update and state vector. lib0 v2 encoding is
+    /// assumed as a format for storing the document. v2 encoding is usually more compact than v1,
+    /// at the cost of slightly slower encoding/decoding.
+    ///
+    /// This feature requires a write capabilities from the database transaction.
+    fn insert_doc_raw_v2(
+        &self,
+        name: &[u8],
+        doc_state_v2: &[u8],
+        doc_sv_v2: &[u8],
+    ) -> Result<(), Error> {
+        let start = Instant::now();
+        validate_update(self, name, doc_state_v2, true)?;
         let oid = get_or_create_oid(self, name)?;
-        insert_inner_v1(self, oid, doc_state_v1, doc_sv_v1)?;
+        let doc_state = encode_value(self, doc_state_v2)?;
+        let mut batch = self.batch();
+        let doc_state = share_state(self, &mut batch, oid, doc_state)?;
+        insert_inner_v2(&mut batch, oid, &doc_state, doc_sv_v2)?;
+        log_changes(self, &mut batch, [(name, oid, ChangeKind::Insert)])?;
+        self.commit_batch(batch)?;
+        publish_update(self, name, doc_state_v2, None, true);
+        if let Some(metrics) = self.metrics() {
+            let bytes = (doc_state.len() + doc_sv_v2.len()) as u64;
+            metrics.doc_inserted(name, bytes, start.elapsed());
+        }
         Ok(())
     }
 
     /// Loads the document state stored in current database under given document `name` into
     /// in-memory Yrs document using provided [TransactionMut]. This includes potential update
-    /// entries that may not have been merged with the main document state yet.
+    /// entries that may not have been merged with the main document state yet. States of stored
+    /// subdocuments (see: [Self::insert_subdoc]) are loaded lazily, once a subdocument requests
+    /// to be loaded (see: [Doc::load]).
     ///
     /// This feature requires only a read capabilities from the database transaction.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            level = "debug",
+            skip_all,
+            fields(name = %String::from_utf8_lossy(name.as_ref()), oid = Empty, updates = Empty)
+        )
+    )]
     fn load_doc<K: AsRef<[u8]> + ?Sized>(
         &self,
         name: &K,
         txn: &mut TransactionMut,
     ) -> Result<bool, Error> {
+        load_named_doc(self, name.as_ref(), txn, None)
+    }
+
+    /// Loads the document state stored in current database under given document `name` into
+    /// in-memory Yrs document using provided [TransactionMut], just like [Self::load_doc] does.
+    /// Loading is aborted with [StoreError::Cancelled] error as soon as a given `cancel` token has
+    /// been cancelled, in which case some of the pending updates may already have been applied
+    /// to the transaction.
+    ///
+    /// This feature requires only a read capabilities from the database transaction.
+    fn load_doc_cancellable<K: AsRef<[u8]> + ?Sized>(
+        &self,
+        name: &K,
+        txn: &mut TransactionMut,
+        cancel: &Cancel,
+    ) -> Result<bool, Error> {
+        load_named_doc(self, name.as_ref(), txn, Some(cancel))
+    }
+
+    /// Loads the document state stored in current database under given document `name` into
+    /// provided in-memory Yrs `doc`, just like [Self::load_doc] does. Loaded changes are applied
+    /// within a transaction tagged with a given `origin`, which lets document observers distinguish
+    /// changes loaded from the persistence layer from live user edits.
+    ///
+    /// This feature requires only a read capabilities from the database transaction.
+    fn load_doc_with_origin<K: AsRef<[u8]> + ?Sized, O: Into<Origin>>(
+        &self,
+        name: &K,
+        doc: &Doc,
+        origin: O,
+    ) -> Result<bool, Error> {
+        let mut txn = doc.transact_mut_with(origin);
+        self.load_doc(name, &mut txn)
+    }
+
+    /// Loads the document state stored in current database under given document `name` into
+    /// in-memory Yrs document using provided [TransactionMut], just like [Self::load_doc] does.
+    /// However entries which can't be decoded or integrated (i.e. because they are corrupted) are
+    /// skipped instead of aborting the whole load, so that a single bad update doesn't make the
+    /// document unloadable. Keys of skipped entries are recorded in returned [LoadReport] and can
+    /// be moved out of the way using [Self::quarantine].
+    ///
+    /// Errors of the underlying store are still returned.
+    ///
+    /// This feature requires only a read capabilities from the database transaction.
+    fn load_doc_lenient<K: AsRef<[u8]> + ?Sized>(
+        &self,
+        name: &K,
+        txn: &mut TransactionMut,
+    ) -> Result<LoadReport, Error> {
+        let mut report = LoadReport::default();
+        if let Some(oid) = get_oid(self, name.as_ref())? {
+            let doc_key = key_doc(oid);
+            let doc_key_v2 = key_doc_v2(oid);
+            for (key, v2) in [(doc_key.as_ref(), false), (doc_key_v2.as_ref(), true)] {
+                if let Some(value) = self.get(key)? {
+                    report.found = true;
+                    let applied = match decode_state(self, key, value.as_ref(), v2) {
+                        Ok(update) => txn.apply_update(update).is_ok(),
+                        Err(_) => false,
+                    };
+                    if !applied {
+                        report.skipped.push(key.into());
+                    }
+                    break;
+                }
+            }
+            let start = key_update(oid, 0);
+            let end = key_update(oid, u32::MAX);
+            let codec = self.options().value_codec();
+            for e in self.iter_range(&start, &end)? {
+                report.found = true;
+                let applied = match decode_update(codec.as_deref(), &e) {
+                    Ok(update) => txn.apply_update(update).is_ok(),
+                    Err(_) => false,
+                };
+                if applied {
+                    report.applied_updates += 1;
+                } else {
+                    report.skipped.push(e.key().into());
+                }
+            }
+        }
+        Ok(report)
+    }
+
+    /// Moves entries of a document with a given `name`, which were skipped by
+    /// [Self::load_doc_lenient] (see: [LoadReport::skipped]), into a quarantine keyspace of that
+    /// document. This way they no longer affect loading of the document, while they can still be
+    /// inspected or restored manually (see: [Self::get_quarantined]). If the document state
+    /// itself was quarantined, its state vector is removed as well. Returns a number of
+    /// quarantined entries.
+    ///
+    /// Quarantined entries are removed together with the document (see: [Self::clear_doc]).
+    ///
+    /// This feature requires a write capabilities from the database transaction.
+    fn quarantine<K: AsRef<[u8]> + ?Sized>(
+        &self,
+        name: &K,
+        report: &LoadReport,
+    ) -> Result<usize, Error> {
+        let oid = match get_oid(self, name.as_ref())? {
+            Some(oid) => oid,
+            None => return Ok(0),
+        };
+        let doc_prefix = key_doc_start(oid);
+        let mut batch = self.batch();
+        let mut count = 0;
+        for key in report.skipped.iter() {
+            // only entries of the same document can be quarantined
+            if key.len() <= SUB_KEY_POS || !key.starts_with(&doc_prefix[..SUB_KEY_POS]) {
+                continue;
+            }
+            if let Some(value) = self.get(key)? {
+                batch.upsert(&key_quarantine(oid, &key[SUB_KEY_POS..]), value.as_ref());
+                batch.remove(key);
+                match key[SUB_KEY_POS] {
+                    SUB_DOC | SUB_DOC_V2 => batch.remove(&key_state_vector(oid)),
+                    // summary no longer matches pending updates, it's rebuilt once needed
+                    SUB_UPDATE => batch.remove(&key_pending_summary(oid)),
+                    _ => {}
+                }
+                // changes of a quarantined update may have been merged into the state vector
+                batch.remove(&key_state_vector_clock(oid));
+                count += 1;
+            }
+        }
+        self.commit_batch(batch)?;
+        Ok(count)
+    }
+
+    /// Returns all quarantined entries of a document with a given `name` (see: [Self::quarantine])
+    /// as pairs of their original keys and raw stored values.
+    ///
+    /// This feature requires only a read capabilities from the database transaction.
+    fn get_quarantined<K: AsRef<[u8]> + ?Sized>(
+        &self,
+        name: &K,
+    ) -> Result<Vec<QuarantinedEntry>, Error> {
+        let mut result = Vec::new();
+        if let Some(oid) = get_oid(self, name.as_ref())? {
+            let start = key_quarantine_start(oid);
+            let end = key_quarantine_end(oid);
+            for e in self.iter_range(&start, &end)? {
+                let key = e.key();
+                if key.len() <= SUB_KEY_POS + 1 {
+                    continue;
+                }
+                let mut original = key[..SUB_KEY_POS].to_vec();
+                original.extend_from_slice(&key[SUB_KEY_POS + 1..]);
+                result.push((original, e.value().into()));
+            }
+        }
+        Ok(result)
+    }
+
+    /// Loads documents stored under given `names` into new in-memory Yrs documents. Missing
+    /// documents are skipped. Returns loaded documents together with their names, in order in
+    /// which they were requested.
+    ///
+    /// Entries of all documents are read first, then states and pending updates of each
+    /// document are merged together before being applied. When `rayon` feature is enabled,
+    /// merging is performed in parallel, which significantly reduces the time needed to warm up
+    /// many documents i.e. when a server boots.
+    ///
+    /// This feature requires only a read capabilities from the database transaction.
+    fn load_docs<K: AsRef<[u8]>>(&self, names: &[K]) -> Result<Vec<NamedDoc>, Error> {
+        let mut stored = Vec::with_capacity(names.len());
+        for name in names {
+            let name = name.as_ref();
+            if let Some(oid) = get_oid(self, name)? {
+                let blobs = read_stored(self, oid).with_oid(oid).with_doc(name)?;
+                stored.push((name, oid, blobs));
+            }
+        }
+
+        #[cfg(feature = "rayon")]
+        let merged: Vec<_> = {
+            use rayon::prelude::*;
+            stored
+                .into_par_iter()
+                .map(|(name, oid, blobs)| (name, oid, merge_blobs(blobs, false)))
+                .collect()
+        };
+        #[cfg(not(feature = "rayon"))]
+        let merged: Vec<_> = stored
+            .into_iter()
+            .map(|(name, oid, blobs)| (name, oid, merge_blobs(blobs, false)))
+            .collect();
+
+        let mut docs = Vec::with_capacity(merged.len());
+        for (name, oid, merged) in merged {
+            let merged = merged
+                .map_err(|e| e as Error)
+                .with_oid(oid)
+                .with_doc(name)?;
+            if let Some(merged) = merged {
+                let update = Update::decode_v1(&merged).with_oid(oid).with_doc(name)?;
+                let doc = Doc::with_options(self.options().new_doc_options());
+                doc.transact_mut()
+                    .apply_update(update)
+                    .with_oid(oid)
+                    .with_doc(name)?;
+                docs.push((name.into(), doc));
+            }
+        }
+        Ok(docs)
+    }
+
+    /// Starts loading the document stored under given document `name` in chunks. This loads the
+    /// document state and at most `max_updates_per_step` of its pending updates using provided
+    /// [TransactionMut], returning a [LoadCursor] which should be passed to
+    /// [Self::load_doc_resume] in order to load the remaining updates. This way services loading
+    /// documents with a very long update log can yield in between steps, instead of blocking for
+    /// the whole time of loading. Returns `None` if document was not found.
+    ///
+    /// Document should not be flushed until it's fully loaded, as flushing resets sequence numbers
+    /// of pending updates.
+    ///
+    /// This feature requires only a read capabilities from the database transaction.
+    fn load_doc_chunked<K: AsRef<[u8]> + ?Sized>(
+        &self,
+        name: &K,
+        txn: &mut TransactionMut,
+        max_updates_per_step: usize,
+    ) -> Result<Option<LoadCursor>, Error> {
         if let Some(oid) = get_oid(self, name.as_ref())? {
-            let loaded = load_doc(self, oid, txn)?;
-            Ok(loaded != 0)
+            load_doc_state(self, oid, txn)?;
+            let mut cursor = LoadCursor {
+                oid,
+                next_clock: Some(0),
+                max_updates: max_updates_per_step.max(1),
+            };
+            self.load_doc_resume(&mut cursor, txn)?;
+            Ok(Some(cursor))
         } else {
-            Ok(false)
+            Ok(None)
+        }
+    }
+
+    /// Loads the next chunk of pending updates of a document loaded via [Self::load_doc_chunked]
+    /// using provided [TransactionMut], advancing a given `cursor`. Returns `true` once all updates
+    /// have been loaded (see: [LoadCursor::is_done]).
+    ///
+    /// This feature requires only a read capabilities from the database transaction.
+    fn load_doc_resume(
+        &self,
+        cursor: &mut LoadCursor,
+        txn: &mut TransactionMut,
+    ) -> Result<bool, Error> {
+        if let Some(clock) = cursor.next_clock {
+            let start = key_update(cursor.oid, clock);
+            let end = key_update_v2(cursor.oid, u32::MAX);
+            let mut iter = self.iter_range(&start, &end)?;
+            let mut last = None;
+            let codec = self.options().value_codec();
+            for e in iter.by_ref().take(cursor.max_updates) {
+                txn.apply_update(decode_update(codec.as_deref(), &e)?)
+                    .with_key(e.key())?;
+                last = Some(update_clock(e.key()));
+            }
+            cursor.next_clock = match last {
+                Some(last) if iter.next().is_some() => last.checked_add(1),
+                _ => None,
+            };
         }
+        Ok(cursor.is_done())
     }
 
     /// Merges all updates stored via [Self::push_update] that were detached from the main document
@@ -114,7 +690,32 @@ where
     ///
     /// This feature requires a write capabilities from the database transaction.
     fn flush_doc<K: AsRef<[u8]> + ?Sized>(&self, name: &K) -> Result<Option<Doc>, Error> {
-        self.flush_doc_with(name, yrs::Options::default())
+        self.flush_doc_with(name, self.options().new_doc_options())
+    }
+
+    /// Merges all updates stored via [Self::push_update] into the document state, just like
+    /// [Self::flush_doc] does. Flush is aborted with [StoreError::Cancelled] error as soon as a
+    /// given `cancel` token has been cancelled while pending updates are being merged, leaving
+    /// the stored document untouched.
+    ///
+    /// This feature requires a write capabilities from the database transaction.
+    fn flush_doc_cancellable<K: AsRef<[u8]> + ?Sized>(
+        &self,
+        name: &K,
+        cancel: &Cancel,
+    ) -> Result<Option<Doc>, Error> {
+        let start = Instant::now();
+        if let Some(oid) = get_oid(self, name.as_ref())? {
+            let flushed = flush_doc(self, oid, self.options().new_doc_options(), Some(cancel))
+                .with_oid(oid)
+                .with_doc(name.as_ref())?;
+            if let (Some(metrics), Some((_, bytes))) = (self.metrics(), &flushed) {
+                metrics.doc_flushed(name.as_ref(), *bytes, start.elapsed());
+            }
+            Ok(flushed.map(|(doc, _)| doc))
+        } else {
+            Ok(None)
+        }
     }
 
     /// Merges all updates stored via [Self::push_update] that were detached from the main document
@@ -124,19 +725,200 @@ where
     /// `options` parameter.
     ///
     /// This feature requires a write capabilities from the database transaction.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            level = "debug",
+            skip_all,
+            fields(name = %String::from_utf8_lossy(name.as_ref()), oid = Empty, bytes = Empty)
+        )
+    )]
     fn flush_doc_with<K: AsRef<[u8]> + ?Sized>(
         &self,
         name: &K,
         options: yrs::Options,
     ) -> Result<Option<Doc>, Error> {
+        let start = Instant::now();
+        if let Some(oid) = get_oid(self, name.as_ref())? {
+            trace_record!("oid", oid);
+            let flushed = flush_doc(self, oid, options, None)
+                .with_oid(oid)
+                .with_doc(name.as_ref())?;
+            trace_record!("bytes", flushed.as_ref().map(|(_, bytes)| *bytes));
+            if let (Some(metrics), Some((_, bytes))) = (self.metrics(), &flushed) {
+                metrics.doc_flushed(name.as_ref(), *bytes, start.elapsed());
+            }
+            Ok(flushed.map(|(doc, _)| doc))
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Rewrites the state of a document with a given `name`, merging all of its pending updates
+    /// and garbage collecting the content of deleted blocks (tombstones). Unlike [Self::flush_doc],
+    /// document state is rewritten even if there were no pending updates. This way long-lived
+    /// documents, which have been persisted with garbage collection disabled, don't grow unbounded
+    /// on disk. Returns `None` if document was not found.
+    ///
+    /// Keep in mind that content removed this way can no longer be restored from snapshots.
+    ///
+    /// This feature requires a write capabilities from the database transaction.
+    fn flush_doc_gc<K: AsRef<[u8]> + ?Sized>(&self, name: &K) -> Result<Option<FlushStats>, Error> {
+        let start = Instant::now();
         if let Some(oid) = get_oid(self, name.as_ref())? {
-            let doc = flush_doc(self, oid, options)?;
-            Ok(doc)
+            let bytes_before = stored_doc_size(self, oid)?;
+            let up_to = last_clock(self, oid)?;
+            let doc = Doc::with_options(yrs::Options {
+                skip_gc: false,
+                ..self.options().new_doc_options()
+            });
+            let found = load_doc(self, oid, &mut doc.transact_mut(), None)?;
+            if found == 0 {
+                return Ok(None);
+            }
+            let bytes_after = write_flushed(self, oid, &doc, up_to)?;
+            if let Some(metrics) = self.metrics() {
+                metrics.doc_flushed(name.as_ref(), bytes_after, start.elapsed());
+            }
+            Ok(Some(FlushStats {
+                bytes_before,
+                bytes_after,
+            }))
         } else {
             Ok(None)
         }
     }
 
+    /// Merges all updates stored via [Self::push_update] into the document state, just like
+    /// [Self::flush_doc] does, but without instantiating a [Doc]: persisted document state and its
+    /// pending updates are merged together as binary updates (see: [yrs::merge_updates_v1]). This
+    /// is significantly faster and allocates far less for documents with many small updates.
+    /// Returns `false` if document was not found or it had no pending updates.
+    ///
+    /// Unlike [Self::flush_doc], merged state is not garbage collected.
+    ///
+    /// This feature requires a write capabilities from the database transaction.
+    fn flush_doc_squash<K: AsRef<[u8]> + ?Sized>(&self, name: &K) -> Result<bool, Error> {
+        let start = Instant::now();
+        if let Some(oid) = get_oid(self, name.as_ref())? {
+            // updates pushed after this point are not pruned, even if they happen to be merged
+            let up_to = last_clock(self, oid)?;
+            if up_to == 0 {
+                return Ok(false);
+            }
+            if let Some(merged) = merge_stored(self, oid, false)? {
+                let state_vec = yrs::encode_state_vector_from_update_v1(&merged)?;
+                let first_seq = flush_history_seq(self, oid)?;
+                let bytes = write_state_with(self, oid, &merged, &state_vec, up_to, first_seq)?;
+                if let Some(metrics) = self.metrics() {
+                    metrics.doc_flushed(name.as_ref(), bytes, start.elapsed());
+                }
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+
+    /// Flushes all documents, which have pending updates (see: [Self::flush_doc]). This is meant
+    /// to be called periodically, i.e. by a maintenance job of a server hosting thousands of
+    /// documents, which are not flushed by the clients themselves.
+    ///
+    /// When [FlushAllOptions::max_docs] limit is set, at most that many documents are flushed per
+    /// call and [FlushSummary::remaining] informs if there are more to flush. Since flushed
+    /// documents no longer have pending updates, consecutive calls always make progress.
+    ///
+    /// Failure to flush a single document (i.e. a corrupted one or one with malformed OID) doesn't
+    /// stop the others from being flushed. Such failures are reported in [FlushSummary::failed]
+    /// instead.
+    ///
+    /// This feature requires a write capabilities from the database transaction.
+    fn flush_all(&self, options: FlushAllOptions) -> Result<FlushSummary, Error> {
+        let limit = options.max_docs.unwrap_or(usize::MAX);
+        let min_pending_updates = options.min_pending_updates.max(1);
+        let mut summary = FlushSummary::default();
+        let mut pending = Vec::new();
+        let start = Key::from_const([V1, KEYSPACE_OID]);
+        let end = Key::from_const([V1, KEYSPACE_DOC]);
+        for e in self.iter_range(&start, &end)? {
+            let name: Box<[u8]> = doc_oid_name(e.key()).into();
+            let oid = match decode_oid(e.key(), e.value()) {
+                Ok(oid) => oid,
+                Err(e) => {
+                    summary.failed.push((name, e));
+                    continue;
+                }
+            };
+            if pending_summary(self, oid)?.count >= min_pending_updates {
+                if pending.len() == limit {
+                    summary.remaining = true;
+                    break;
+                }
+                pending.push(name);
+            }
+        }
+        for name in pending {
+            match self.flush_doc_with(&name, options.doc_options.clone()) {
+                Ok(_) => summary.flushed += 1,
+                Err(e) => summary.failed.push((name, e)),
+            }
+        }
+        Ok(summary)
+    }
+
+    /// Merges pending updates of a document with given `name`, which fall outside of the
+    /// [StoreOptions::retention_policy], into the document state. Unlike [Self::flush_doc], updates
+    /// retained by the policy are left untouched. Returns the number of merged updates.
+    ///
+    /// This feature requires a write capabilities from the database transaction.
+    fn enforce_retention<K: AsRef<[u8]> + ?Sized>(&self, name: &K) -> Result<u32, Error> {
+        let policy = self.options().retention_policy;
+        if policy.is_empty() {
+            return Ok(0);
+        }
+        if let Some(oid) = get_oid(self, name.as_ref())? {
+            let merged = enforce_retention(self, oid, &policy, unix_millis())
+                .with_oid(oid)
+                .with_doc(name.as_ref())?;
+            return Ok(merged);
+        }
+        Ok(0)
+    }
+
+    /// Enforces [StoreOptions::retention_policy] over all documents, which have pending updates
+    /// (see: [Self::enforce_retention]). Returns the number of documents, which had any of their
+    /// updates merged.
+    ///
+    /// This method is meant to be called periodically, i.e. by a background maintenance job.
+    ///
+    /// This feature requires a write capabilities from the database transaction.
+    fn enforce_retention_all(&self) -> Result<usize, Error> {
+        let policy = self.options().retention_policy;
+        if policy.is_empty() {
+            return Ok(0);
+        }
+        let mut pending = Vec::new();
+        let start = Key::from_const([V1, KEYSPACE_OID]);
+        let end = Key::from_const([V1, KEYSPACE_DOC]);
+        for e in self.iter_range(&start, &end)? {
+            if e.value().len() != OID_LEN {
+                let key = e.key().to_vec();
+                return Err(StoreError::Corrupted { key }.into());
+            }
+            let oid = oid_from_bytes(e.value());
+            if has_updates(self, oid)? {
+                pending.push(oid);
+            }
+        }
+        let now = unix_millis();
+        let mut compacted = 0;
+        for oid in pending {
+            if enforce_retention(self, oid, &policy, now).with_oid(oid)? > 0 {
+                compacted += 1;
+            }
+        }
+        Ok(compacted)
+    }
+
     /// Returns the [StateVector] stored directly for the document with a given `name`.
     /// Returns `None` if the state vector was not stored.
     ///
@@ -144,6 +926,8 @@ where
     /// tuple parameter boolean informs if returned value is up to date. If that's not the case, it
     /// means that state vector exists but must be recalculated from the collection of persisted
     /// updates using either [Self::load_doc] (read-only) or [Self::flush_doc] (read-write).
+    /// Stored state vector stays up to date despite pending updates, when they have been merged
+    /// into it (see: [StoreOptions::track_state_vector]).
     ///
     /// This feature requires only the read capabilities from the database transaction.
     fn get_state_vector<K: AsRef<[u8]> + ?Sized>(
@@ -159,289 +943,3925 @@ where
             } else {
                 None
             };
-            let update_range_start = key_update(oid, 0);
-            let update_range_end = key_update(oid, u32::MAX);
-            let mut iter = self.iter_range(&update_range_start, &update_range_end)?;
-            let up_to_date = iter.next().is_none();
+            let up_to_date = state_vector_up_to_date(self, oid)?;
             Ok((sv, up_to_date))
         } else {
             Ok((None, true))
         }
     }
 
+    /// Checks if a document with a given `name` has any pending updates, which have not been
+    /// merged into its state yet (see: [Self::flush_doc]). Returns `false` if document was not
+    /// found.
+    ///
+    /// This feature requires only the read capabilities from the database transaction.
+    fn has_pending_updates<K: AsRef<[u8]> + ?Sized>(&self, name: &K) -> Result<bool, Error> {
+        if let Some(oid) = get_oid(self, name.as_ref())? {
+            has_updates(self, oid)
+        } else {
+            Ok(false)
+        }
+    }
+
     /// Appends new update without integrating it directly into document store (which is faster
     /// than persisting full document state on every update). Updates are assumed to be serialized
     /// using lib0 v1 encoding.
     ///
     /// Returns a sequence number of a stored update. Once updates are integrated into document and
-    /// pruned (using [Self::flush_doc] method), sequence number is reset.
+    /// pruned (using [Self::flush_doc] method), sequence number is reset. Updates may also be
+    /// integrated automatically, when a [CompactionPolicy] threshold has been exceeded or when
+    /// sequence numbers have reached [u32::MAX].
     ///
     /// This feature requires a write capabilities from the database transaction.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            level = "debug",
+            skip_all,
+            fields(
+                name = %String::from_utf8_lossy(name.as_ref()),
+                oid = Empty,
+                clock = Empty,
+                bytes = Empty,
+            )
+        )
+    )]
     fn push_update<K: AsRef<[u8]> + ?Sized>(&self, name: &K, update: &[u8]) -> Result<u32, Error> {
+        push_update(self, name.as_ref(), update, false, None)
+    }
+
+    /// Appends new update without integrating it directly into document store, just like
+    /// [Self::push_update] does, storing an `origin` (i.e. identifier of a user or session, which
+    /// made the change) alongside of it. Origin is returned together with an update by
+    /// [Self::iter_updates] and removed once update has been merged into the document state.
+    ///
+    /// If the same update is still pending (see: [StoreOptions::update_dedup_window]), its origin
+    /// is left unchanged.
+    ///
+    /// This feature requires a write capabilities from the database transaction.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            level = "debug",
+            skip_all,
+            fields(
+                name = %String::from_utf8_lossy(name.as_ref()),
+                oid = Empty,
+                clock = Empty,
+                bytes = Empty,
+            )
+        )
+    )]
+    fn push_update_with_origin<K: AsRef<[u8]> + ?Sized>(
+        &self,
+        name: &K,
+        update: &[u8],
+        origin: &[u8],
+    ) -> Result<u32, Error> {
+        push_update(self, name.as_ref(), update, false, Some(origin))
+    }
+
+    /// Appends a batch of new updates without integrating them directly into document store.
+    /// Updates are assumed to be serialized using lib0 v1 encoding. Sequence numbers are assigned
+    /// to consecutive updates in order, using a single lookup for the last stored sequence number.
+    /// This makes it cheaper than calling [Self::push_update] for every buffered update.
+    ///
+    /// Returns a sequence number of the last stored update.
+    ///
+    /// This feature requires a write capabilities from the database transaction.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            level = "debug",
+            skip_all,
+            fields(
+                name = %String::from_utf8_lossy(name.as_ref()),
+                oid = Empty,
+                updates = Empty,
+                bytes = Empty,
+            )
+        )
+    )]
+    fn push_updates<'u, K, I>(&self, name: &K, updates: I) -> Result<u32, Error>
+    where
+        K: AsRef<[u8]> + ?Sized,
+        I: IntoIterator<Item = &'u [u8]>,
+    {
+        let start = Instant::now();
+        let updates: Vec<_> = updates.into_iter().collect();
+        for update in updates.iter() {
+            check_update_size(self, update)?;
+            validate_update(self, name.as_ref(), update, false)?;
+        }
         let oid = get_or_create_oid(self, name.as_ref())?;
-        let last_clock = {
-            let end = key_update(oid, u32::MAX);
-            if let Some(e) = self.peek_back(&end)? {
-                let last_key = e.key();
-                let len = last_key.len();
-                let last_clock = &last_key[(len - 5)..(len - 1)]; // update key scheme: 01{name:n}1{clock:4}0
-                u32::from_be_bytes(last_clock.try_into().unwrap())
-            } else {
-                0
+        trace_record!("oid", oid);
+        let count: u32 = updates
+            .len()
+            .try_into()
+            .map_err(|_| StoreError::UpdateLogFull { oid })?;
+        trace_record!("updates", count);
+        let mut values = Vec::with_capacity(updates.len());
+        for update in updates.iter() {
+            values.push(encode_value(self, update)?);
+        }
+        let bytes = values.iter().map(|value| value.len() as u64).sum();
+        reserve_pending_bytes(self, oid, bytes)?;
+        let first = reserve_clocks(self, oid, count)?;
+        let now = unix_millis();
+        let mut batch = self.batch();
+        for (value, clock) in values.into_iter().zip(first..) {
+            let update_key = key_update(oid, clock);
+            batch.upsert(&update_key, &value);
+            record_update_time(self, &mut batch, oid, clock, now);
+        }
+        let last = first + count - 1;
+        if count != 0 {
+            track_pending(self, &mut batch, oid, last, count, bytes)?;
+            let tracked = updates
+                .iter()
+                .zip(first..)
+                .map(|(u, clock)| (*u, clock, false));
+            track_state_vector(self, &mut batch, oid, tracked)?;
+            log_changes(self, &mut batch, [(name.as_ref(), oid, ChangeKind::Update)])?;
+            self.commit_batch(batch)?;
+            trace_record!("bytes", bytes);
+            for (update, clock) in updates.into_iter().zip(first..) {
+                publish_update(self, name.as_ref(), update, Some(clock), false);
             }
-        };
-        let clock = last_clock + 1;
-        let update_key = key_update(oid, clock);
-        self.upsert(&update_key, &update)?;
-        Ok(clock)
+            if let Some(metrics) = self.metrics() {
+                metrics.updates_pushed(name.as_ref(), count, bytes, start.elapsed());
+            }
+            compact_if_needed(self, oid)?;
+        }
+        Ok(last)
+    }
+
+    /// Appends new update without integrating it directly into document store. Works just like
+    /// [Self::push_update], except updates are assumed to be serialized using lib0 v2 encoding.
+    /// Updates using different encodings can be freely mixed within the same document.
+    ///
+    /// This feature requires a write capabilities from the database transaction.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            level = "debug",
+            skip_all,
+            fields(
+                name = %String::from_utf8_lossy(name.as_ref()),
+                oid = Empty,
+                clock = Empty,
+                bytes = Empty,
+            )
+        )
+    )]
+    fn push_update_v2<K: AsRef<[u8]> + ?Sized>(
+        &self,
+        name: &K,
+        update: &[u8],
+    ) -> Result<u32, Error> {
+        push_update(self, name.as_ref(), update, true, None)
     }
 
     /// Returns an update (encoded using lib0 v1 encoding) which contains all new changes that
     /// happened since provided state vector for a given document.
     ///
+    /// Diff is computed directly from the persisted document state and its pending updates
+    /// without reconstructing the document in memory. If a given state vector already covers
+    /// the stored one, only deletions are taken from the document state, so that the diff is
+    /// mostly built from pending updates, which makes incremental sync of large documents cheap.
+    ///
     /// This feature requires only the read capabilities from the database transaction.
     fn get_diff<K: AsRef<[u8]> + ?Sized>(
         &self,
         name: &K,
         sv: &StateVector,
     ) -> Result<Option<Vec<u8>>, Error> {
-        let doc = Doc::new();
-        let found = {
-            let mut txn = doc.transact_mut();
-            self.load_doc(name, &mut txn)?
-        };
-        if found {
-            Ok(Some(doc.transact().encode_diff_v1(sv)))
+        if let Some(oid) = get_oid(self, name.as_ref())? {
+            diff_stored(self, oid, sv, false)
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Returns an update (encoded using lib0 v2 encoding) which contains all new changes that
+    /// happened since provided state vector for a given document.
+    ///
+    /// Diff is computed directly from the persisted document state and its pending updates
+    /// without reconstructing the document in memory. If a given state vector already covers
+    /// the stored one, only deletions are taken from the document state, so that the diff is
+    /// mostly built from pending updates, which makes incremental sync of large documents cheap.
+    ///
+    /// This feature requires only the read capabilities from the database transaction.
+    fn get_diff_v2<K: AsRef<[u8]> + ?Sized>(
+        &self,
+        name: &K,
+        sv: &StateVector,
+    ) -> Result<Option<Vec<u8>>, Error> {
+        if let Some(oid) = get_oid(self, name.as_ref())? {
+            diff_stored(self, oid, sv, true)
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Computes a reply to the sync step 1 message of y-sync protocol: an update (encoded using
+    /// lib0 v1 encoding) containing all changes, which happened since provided `remote_sv`,
+    /// together with a state vector of a locally stored document, which remote peer can use to
+    /// send back the changes missing on our side. Both are computed from a single pass over the
+    /// persisted document state and its pending updates, without reconstructing the document in
+    /// memory.
+    ///
+    /// Returns `None` if document was not found.
+    ///
+    /// This feature requires only the read capabilities from the database transaction.
+    fn sync_step<K: AsRef<[u8]> + ?Sized>(
+        &self,
+        name: &K,
+        remote_sv: &StateVector,
+    ) -> Result<Option<(Vec<u8>, StateVector)>, Error> {
+        if let Some(oid) = get_oid(self, name.as_ref())? {
+            sync_stored(self, oid, remote_sv, false)
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Works just like [Self::sync_step], except returned update is encoded using lib0 v2
+    /// encoding.
+    ///
+    /// This feature requires only the read capabilities from the database transaction.
+    fn sync_step_v2<K: AsRef<[u8]> + ?Sized>(
+        &self,
+        name: &K,
+        remote_sv: &StateVector,
+    ) -> Result<Option<(Vec<u8>, StateVector)>, Error> {
+        if let Some(oid) = get_oid(self, name.as_ref())? {
+            sync_stored(self, oid, remote_sv, true)
         } else {
             Ok(None)
         }
     }
 
     /// Removes all data associated with the current document (including its updates and metadata).
+    /// If soft delete has been enabled (see: [StoreOptions::soft_delete]), document is moved into a
+    /// trash instead, just like [Self::trash_doc] does.
     ///
     /// This feature requires a write capabilities from the database transaction.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            level = "debug",
+            skip_all,
+            fields(name = %String::from_utf8_lossy(name.as_ref()), oid = Empty)
+        )
+    )]
     fn clear_doc<K: AsRef<[u8]> + ?Sized>(&self, name: &K) -> Result<(), Error> {
-        let oid_key = key_oid(name.as_ref());
-        if let Some(oid) = self.get(&oid_key)? {
-            // all document related elements are stored within bounds [0,1,..oid,0]..[0,1,..oid,255]
-            let oid: [u8; 4] = oid.as_ref().try_into().unwrap();
-            let oid = OID::from_be_bytes(oid);
-            self.remove(&oid_key)?;
-            let start = key_doc_start(oid);
-            let end = key_doc_end(oid);
-            for v in self.iter_range(&start, &end)? {
-                let key: &[u8] = v.key();
-                if key > &end {
-                    break; //TODO: for some reason key range doesn't always work
-                }
-                self.remove(&key)?;
-            }
+        if self.options().soft_delete {
+            self.trash_doc(name)?;
+        } else if let Some(oid) = get_oid(self, name.as_ref())? {
+            trace_record!("oid", oid);
+            let mut batch = self.batch();
+            clear_doc(self, &mut batch, name.as_ref(), oid)?;
+            self.commit_batch(batch)?;
+            notify_cleared(self, name.as_ref());
         }
         Ok(())
     }
 
-    /// Returns a metadata value stored under its metadata `key` for a document with given `name`.
+    /// Moves a document with given `name` into a trash, from which it can be brought back using
+    /// [Self::restore_trashed] until it's permanently removed by [Self::purge_trash]. Trashed
+    /// document is stored as a [DocArchive] (see: [Self::export_doc]), so its snapshots, history
+    /// and subdocument links are not preserved. Returns `false` if document was not found.
+    ///
+    /// Trash keeps only the last removed document of a given name: if a document recreated under
+    /// the same name is trashed again, previously trashed one is permanently removed.
+    ///
+    /// This feature requires a write capabilities from the database transaction.
+    fn trash_doc<K: AsRef<[u8]> + ?Sized>(&self, name: &K) -> Result<bool, Error> {
+        let name = name.as_ref();
+        if let Some(oid) = get_oid(self, name)? {
+            let archive = export_doc(self, oid)?;
+            // trash entry: {deleted_at:8}{archive}
+            let mut value = unix_millis().to_be_bytes().to_vec();
+            value.extend_from_slice(&encode_value(self, &archive.encode())?);
+            let mut batch = self.batch();
+            batch.upsert(&key_trash(name), &value);
+            clear_doc(self, &mut batch, name, oid)?;
+            self.commit_batch(batch)?;
+            notify_cleared(self, name);
+            Ok(true)
+        } else {
+            Ok(false)
+        }
+    }
+
+    /// Restores a document with given `name` previously moved into a trash by [Self::trash_doc]
+    /// or [Self::clear_doc]. Returns `false` if there was no trashed document with such `name`.
+    ///
+    /// Returns [StoreError::DocumentExists] error if a new document with the same `name` has been
+    /// created in the meantime.
+    ///
+    /// This feature requires a write capabilities from the database transaction.
+    fn restore_trashed<K: AsRef<[u8]> + ?Sized>(&self, name: &K) -> Result<bool, Error> {
+        let name = name.as_ref();
+        let key = key_trash(name);
+        let value = match self.get(&key)? {
+            Some(value) => value,
+            None => return Ok(false),
+        };
+        if get_oid(self, name)?.is_some() {
+            let name = String::from_utf8_lossy(name).into_owned();
+            return Err(StoreError::DocumentExists(name).into());
+        }
+        let value = value.as_ref().get(8..).unwrap_or_default();
+        let data = decode_value(self.options().value_codec().as_deref(), &key, value)?;
+        let archive = DocArchive::decode(&data)?;
+        let oid = get_or_create_oid(self, name)?;
+        let mut batch = self.batch();
+        import_doc(self, &mut batch, oid, &archive)?;
+        batch.remove(&key);
+        self.commit_batch(batch)?;
+        Ok(true)
+    }
+
+    /// Returns names of all documents currently kept in a trash (see: [Self::trash_doc]) together
+    /// with timestamps (in milliseconds since Unix epoch) of when they were removed.
     ///
     /// This feature requires only the read capabilities from the database transaction.
-    fn get_meta<K1: AsRef<[u8]> + ?Sized, K2: AsRef<[u8]> + ?Sized>(
+    fn trashed_docs(&self) -> Result<Vec<TrashedDoc>, Error> {
+        let start = Key::from_const([V1, KEYSPACE_TRASH]);
+        let end = Key::from_const([V1, KEYSPACE_TRASH + 1]);
+        let mut docs = Vec::new();
+        for e in self.iter_range(&start, &end)? {
+            if let Some(deleted_at) = trash_timestamp(e.value()) {
+                docs.push((trashed_doc_name(e.key()).into(), deleted_at));
+            }
+        }
+        Ok(docs)
+    }
+
+    /// Permanently removes all documents, which have been moved into a trash (see:
+    /// [Self::trash_doc]) at or before `older_than` timestamp (in milliseconds since Unix epoch).
+    /// Returns a number of removed documents.
+    ///
+    /// This method is meant to be called periodically, i.e. by a background job enforcing trash
+    /// retention window.
+    ///
+    /// This feature requires a write capabilities from the database transaction.
+    fn purge_trash(&self, older_than: u64) -> Result<usize, Error> {
+        let start = Key::from_const([V1, KEYSPACE_TRASH]);
+        let end = Key::from_const([V1, KEYSPACE_TRASH + 1]);
+        let mut batch = self.batch();
+        let mut removed = 0;
+        for e in self.iter_range(&start, &end)? {
+            match trash_timestamp(e.value()) {
+                Some(deleted_at) if deleted_at > older_than => {}
+                _ => {
+                    batch.remove(e.key());
+                    removed += 1;
+                }
+            }
+        }
+        self.commit_batch(batch)?;
+        Ok(removed)
+    }
+
+    /// Renames a document stored under `old_name` to `new_name`. Only the document name to OID
+    /// mapping is changed, while document state, updates and metadata are left untouched, which
+    /// makes this operation cheap regardless of the document size. Returns `false` if document
+    /// with `old_name` was not found.
+    ///
+    /// Change log (see: [Self::iter_changes]) records rename as a removal of `old_name` followed
+    /// by an insertion of `new_name`.
+    ///
+    /// Returns [StoreError::DocumentExists] error if document with `new_name` already exists.
+    ///
+    /// This feature requires a write capabilities from the database transaction.
+    fn rename_doc<K1: AsRef<[u8]> + ?Sized, K2: AsRef<[u8]> + ?Sized>(
         &self,
-        name: &K1,
-        meta_key: &K2,
-    ) -> Result<Option<Self::Return>, Error> {
+        old_name: &K1,
+        new_name: &K2,
+    ) -> Result<bool, Error> {
+        let old_name = old_name.as_ref();
+        let new_name = new_name.as_ref();
+        if let Some(oid) = get_oid(self, old_name)? {
+            if old_name == new_name {
+                return Ok(true);
+            }
+            if get_oid(self, new_name)?.is_some() {
+                let name = String::from_utf8_lossy(new_name).into_owned();
+                return Err(StoreError::DocumentExists(name).into());
+            }
+            let mut batch = self.batch();
+            batch.remove(&key_oid(old_name));
+            batch.upsert(&key_oid(new_name), &oid.to_be_bytes());
+            if let Some(expiry) = get_expiry(self, oid)? {
+                // expiry index refers to documents by their names
+                batch.upsert(&key_expiry_index(expiry, oid), new_name);
+            }
+            let changes = [
+                (old_name, oid, ChangeKind::Clear),
+                (new_name, oid, ChangeKind::Insert),
+            ];
+            log_changes(self, &mut batch, changes)?;
+            self.commit_batch(batch)?;
+            Ok(true)
+        } else {
+            Ok(false)
+        }
+    }
+
+    /// Copies a document stored under `src_name` into a new document named `dst_name`. Copy
+    /// receives a fresh OID and contains the document state, its pending updates and
+    /// subdocuments. Metadata entries are copied only if `with_meta` flag is set. Other entries
+    /// (i.e. snapshots or update history) are never copied. Returns `false` if document with
+    /// `src_name` was not found.
+    ///
+    /// This can be used i.e. to create new documents from templates or to fork existing ones.
+    /// Returns [StoreError::DocumentExists] error if document with `dst_name` already exists.
+    ///
+    /// This feature requires a write capabilities from the database transaction.
+    fn copy_doc<K1: AsRef<[u8]> + ?Sized, K2: AsRef<[u8]> + ?Sized>(
+        &self,
+        src_name: &K1,
+        dst_name: &K2,
+        with_meta: bool,
+    ) -> Result<bool, Error> {
+        let dst_name = dst_name.as_ref();
+        if let Some(src_oid) = get_oid(self, src_name.as_ref())? {
+            if get_oid(self, dst_name)?.is_some() {
+                let name = String::from_utf8_lossy(dst_name).into_owned();
+                return Err(StoreError::DocumentExists(name).into());
+            }
+            self.lock_oid_allocation()?;
+            if get_oid(self, dst_name)?.is_some() {
+                // document has been created by concurrent writer in the meantime
+                let name = String::from_utf8_lossy(dst_name).into_owned();
+                return Err(StoreError::DocumentExists(name).into());
+            }
+            let mut batch = self.batch();
+            let dst_oid = allocate_oid(self, &mut batch, dst_name)?;
+            let mut blobs = BlobRefs::default();
+            let start = key_doc_start(src_oid);
+            let end = key_doc_end(src_oid);
+            for e in self.iter_range(&start, &end)? {
+                let key = e.key();
+                let copy = match key[SUB_KEY_POS] {
+                    SUB_DOC | SUB_DOC_V2 | SUB_DOC_CHUNK | SUB_STATE_VEC | SUB_UPDATE
+                    | SUB_UPDATE_TIME | SUB_UPDATE_ORIGIN | SUB_SUBDOC => true,
+                    SUB_META => with_meta,
+                    _ => false,
+                };
+                if copy {
+                    if matches!(key[SUB_KEY_POS], SUB_DOC | SUB_DOC_V2) {
+                        // copied document state shares the same blob
+                        blobs.retain(e.value());
+                    }
+                    // document key scheme: 01{oid:4}{sub:1}...
+                    let mut dst_key = key.to_vec();
+                    dst_key[2..SUB_KEY_POS].copy_from_slice(&dst_oid.to_be_bytes());
+                    batch.upsert(&dst_key, e.value());
+                }
+            }
+            blobs.commit(self, &mut batch)?;
+            log_changes(self, &mut batch, [(dst_name, dst_oid, ChangeKind::Insert)])?;
+            self.commit_batch(batch)?;
+            Ok(true)
+        } else {
+            Ok(false)
+        }
+    }
+
+    /// Exports all data persisted for a document with given `name` - its state, state vector,
+    /// pending updates and metadata - into a portable [DocArchive], which can be imported back
+    /// using [Self::import_doc], possibly into a database using a different backend. Returns `None`
+    /// if document was not found.
+    ///
+    /// This feature requires only the read capabilities from the database transaction.
+    fn export_doc<K: AsRef<[u8]> + ?Sized>(&self, name: &K) -> Result<Option<DocArchive>, Error> {
         if let Some(oid) = get_oid(self, name.as_ref())? {
-            let key = key_meta(oid, meta_key.as_ref());
-            Ok(self.get(&key)?)
+            Ok(Some(export_doc(self, oid)?))
         } else {
             Ok(None)
         }
     }
 
-    /// Inserts or updates new `meta` value stored under its metadata `key` for a document with
-    /// given `name`.
+    /// Imports a document from an `archive` produced by [Self::export_doc] and stores it under
+    /// a given `name`. Document state and pending updates are stored using the compression
+    /// settings of the current store.
     ///
-    /// This feature requires write capabilities from the database transaction.
-    fn insert_meta<K1: AsRef<[u8]> + ?Sized, K2: AsRef<[u8]> + ?Sized>(
+    /// Returns [StoreError::DocumentExists] error if document with `name` already exists.
+    ///
+    /// This feature requires a write capabilities from the database transaction.
+    fn import_doc<K: AsRef<[u8]> + ?Sized>(
         &self,
-        name: &K1,
-        meta_key: &K2,
-        meta: &[u8],
+        name: &K,
+        archive: &DocArchive,
     ) -> Result<(), Error> {
-        let oid = get_or_create_oid(self, name.as_ref())?;
-        let key = key_meta(oid, meta_key.as_ref());
-        self.upsert(&key, meta)?;
+        let name = name.as_ref();
+        if get_oid(self, name)?.is_some() {
+            let name = String::from_utf8_lossy(name).into_owned();
+            return Err(StoreError::DocumentExists(name).into());
+        }
+        let oid = get_or_create_oid(self, name)?;
+        let mut batch = self.batch();
+        import_doc(self, &mut batch, oid, archive)?;
+        log_changes(self, &mut batch, [(name, oid, ChangeKind::Insert)])?;
+        self.commit_batch(batch)?;
         Ok(())
     }
 
-    /// Removes an metadata entry stored under given metadata `key` for a document with provided `name`.
+    /// Moves a document with given `name` out of the current database into a given archive `sink`.
+    /// Pending updates are merged into the document state first, then the document is exported
+    /// (see: [Self::export_doc]) and all of its entries are removed. Only a small tombstone holding
+    /// the archive location is kept, so that the document can be brought back using
+    /// [Self::restore_doc]. Returns the archive location or `None` if document was not found.
+    ///
+    /// This feature requires a write capabilities from the database transaction.
+    fn archive_doc<K, S>(&self, name: &K, sink: &S) -> Result<Option<Vec<u8>>, Error>
+    where
+        K: AsRef<[u8]> + ?Sized,
+        S: ArchiveSink + ?Sized,
+    {
+        let name = name.as_ref();
+        let oid = match get_oid(self, name)? {
+            Some(oid) => oid,
+            None => return Ok(None),
+        };
+        self.flush_doc(name)?;
+        let archive = export_doc(self, oid)?;
+        let location = sink.store(name, &archive)?;
+        let mut batch = self.batch();
+        batch.upsert(&key_archived(name), &location);
+        // archived documents are removed permanently, even if soft delete is enabled
+        clear_doc(self, &mut batch, name, oid)?;
+        self.commit_batch(batch)?;
+        notify_cleared(self, name);
+        Ok(Some(location))
+    }
+
+    /// Restores a document with given `name` previously moved into a given archive `sink` using
+    /// [Self::archive_doc]. Once document has been imported back, its tombstone and archive are
+    /// removed. Returns `false` if there was no archived document with such `name`.
+    ///
+    /// Returns [StoreError::ArchiveNotFound] error if tombstone exists, but its archive couldn't be
+    /// found in a `sink`, and [StoreError::DocumentExists] if a new document with the same `name`
+    /// has been created in the meantime.
+    ///
+    /// This feature requires a write capabilities from the database transaction.
+    fn restore_doc<K, S>(&self, name: &K, sink: &S) -> Result<bool, Error>
+    where
+        K: AsRef<[u8]> + ?Sized,
+        S: ArchiveSink + ?Sized,
+    {
+        let name = name.as_ref();
+        let location = match self.get_archive_location(name)? {
+            Some(location) => location,
+            None => return Ok(false),
+        };
+        let archive = match sink.load(&location)? {
+            Some(archive) => archive,
+            None => {
+                let name = String::from_utf8_lossy(name).into_owned();
+                return Err(StoreError::ArchiveNotFound(name).into());
+            }
+        };
+        self.import_doc(name, &archive)?;
+        self.remove(&key_archived(name))?;
+        sink.remove(&location)?;
+        Ok(true)
+    }
+
+    /// Returns a location of an archive (see: [Self::archive_doc]) of a document with given
+    /// `name` or `None` if such document has not been archived.
+    ///
+    /// This feature requires only the read capabilities from the database transaction.
+    fn get_archive_location<K: AsRef<[u8]> + ?Sized>(
+        &self,
+        name: &K,
+    ) -> Result<Option<Vec<u8>>, Error> {
+        let location = self.get(&key_archived(name.as_ref()))?;
+        Ok(location.map(|v| v.as_ref().to_vec()))
+    }
+
+    /// Writes all documents stored in current database into a given `writer`, one [DocArchive]
+    /// (see: [Self::export_doc]) per document, together with its name. Such dump can be loaded
+    /// back using [Self::import_all], i.e. to migrate all documents to a database using a different
+    /// backend. Returns a number of exported documents.
+    ///
+    /// This feature requires only the read capabilities from the database transaction.
+    fn export_all<W: std::io::Write>(&self, writer: &mut W) -> Result<usize, Error> {
+        export_docs(self, writer, None)
+    }
+
+    /// Writes all documents stored in current database into a given `writer`, just like
+    /// [Self::export_all] does. Export is aborted with [StoreError::Cancelled] error as soon as a
+    /// given `cancel` token has been cancelled, in which case the `writer` is left with an
+    /// incomplete dump.
+    ///
+    /// This feature requires only the read capabilities from the database transaction.
+    fn export_all_cancellable<W: std::io::Write>(
+        &self,
+        writer: &mut W,
+        cancel: &Cancel,
+    ) -> Result<usize, Error> {
+        export_docs(self, writer, Some(cancel))
+    }
+
+    /// Imports all documents from a dump produced by [Self::export_all] and read from a given
+    /// `reader`. Returns a number of imported documents.
+    ///
+    /// Returns [StoreError::DocumentExists] error if any of the imported documents already exists.
+    /// Documents imported prior to that are not rolled back.
+    ///
+    /// This feature requires a write capabilities from the database transaction.
+    fn import_all<R: std::io::Read>(&self, reader: &mut R) -> Result<usize, Error> {
+        let mut count = 0;
+        while let Some((name, archive)) = read_dump_entry(reader)? {
+            self.import_doc(&name, &archive)?;
+            count += 1;
+        }
+        Ok(count)
+    }
+
+    /// Returns an iterator over all pending updates of a document with given `name`, which have
+    /// not been merged into the document state yet, ordered by their sequence numbers. Updates are
+    /// returned in their raw form, which makes it possible to replay or forward them (i.e. to
+    /// connected peers) without reconstructing the document.
+    ///
+    /// This feature requires only the read capabilities from the database transaction.
+    fn iter_updates<K: AsRef<[u8]> + ?Sized>(
+        &self,
+        name: &K,
+    ) -> Result<UpdatesIter<Self::Cursor, Self::Entry>, Error> {
+        if let Some(oid) = get_oid(self, name.as_ref())? {
+            let origins = update_origins(self, oid, 0)?;
+            let start = key_update(oid, 0);
+            let end = key_update(oid, u32::MAX);
+            let cursor = self.iter_range(&start, &end)?;
+            Ok(UpdatesIter(
+                Some(cursor),
+                self.options().value_codec(),
+                origins,
+            ))
+        } else {
+            Ok(UpdatesIter(None, None, HashMap::new()))
+        }
+    }
+
+    /// Removes pending updates of a document with given `name`, which sequence numbers are lower
+    /// than or equal to `up_to_clock`, without merging them into the document state. This is
+    /// useful when an external process has already integrated a prefix of the update log
+    /// elsewhere (i.e. using updates returned by [Self::iter_updates]).
+    ///
+    /// Keep in mind that if all pending updates have been removed this way, sequence numbers of
+    /// the updates pushed afterwards will start over, just like after [Self::flush_doc].
     ///
     /// This feature requires write capabilities from the database transaction.
-    fn remove_meta<K1: AsRef<[u8]> + ?Sized, K2: AsRef<[u8]> + ?Sized>(
+    fn trim_updates<K: AsRef<[u8]> + ?Sized>(
         &self,
-        name: &K1,
-        meta_key: &K2,
+        name: &K,
+        up_to_clock: u32,
     ) -> Result<(), Error> {
         if let Some(oid) = get_oid(self, name.as_ref())? {
-            let key = key_meta(oid, meta_key.as_ref());
-            self.remove(&key)?;
+            let mut batch = self.batch();
+            delete_updates(&mut batch, oid, up_to_clock);
+            if self.get(&key_state_vector_clock(oid))?.is_some() {
+                // tracked state vector includes changes of removed updates, restore the one
+                // describing the document state
+                let doc = Doc::with_options(self.options().new_doc_options());
+                load_doc_state(self, oid, &mut doc.transact_mut())?;
+                let sv = doc.transact().state_vector().encode_v1();
+                batch.upsert(&key_state_vector(oid), &sv);
+                batch.remove(&key_state_vector_clock(oid));
+            }
+            self.commit_batch(batch)?;
         }
         Ok(())
     }
 
-    /// Returns an iterator over all document names stored in current database.
-    fn iter_docs(&self) -> Result<DocsNameIter<Self::Cursor, Self::Entry>, Error> {
-        let start = Key::from_const([V1, KEYSPACE_OID]);
-        let end = Key::from_const([V1, KEYSPACE_DOC]);
-        let cursor = self.iter_range(&start, &end)?;
-        Ok(DocsNameIter { cursor, start, end })
-    }
-
-    /// Returns an iterator over all metadata entries stored for a given document.
-    fn iter_meta<K: AsRef<[u8]> + ?Sized>(
+    /// Returns pending updates of a document with given `name` starting from the first one, which
+    /// has been pushed at or after a given `timestamp` (in milliseconds since Unix epoch), ordered
+    /// by their sequence numbers. Only updates pushed while [StoreOptions::update_timestamps] was
+    /// enabled can be found by their timestamps.
+    ///
+    /// This feature requires only the read capabilities from the database transaction.
+    fn iter_updates_since<K: AsRef<[u8]> + ?Sized>(
         &self,
-        doc_name: &K,
-    ) -> Result<MetadataIter<Self::Cursor, Self::Entry>, Error> {
-        if let Some(oid) = get_oid(self, doc_name.as_ref())? {
-            let start = key_meta_start(oid).to_vec();
-            let end = key_meta_end(oid).to_vec();
-            let cursor = self.iter_range(&start, &end)?;
-            Ok(MetadataIter(Some((cursor, start, end))))
-        } else {
-            Ok(MetadataIter(None))
+        name: &K,
+        timestamp: u64,
+    ) -> Result<UpdatesIter<Self::Cursor, Self::Entry>, Error> {
+        if let Some(oid) = get_oid(self, name.as_ref())? {
+            let start = key_update_time(oid, 0);
+            let end = key_update_time(oid, u32::MAX);
+            let mut first = None;
+            for e in self.iter_range(&start, &end)? {
+                if update_timestamp(e.value()) >= Some(timestamp) {
+                    first = Some(update_time_clock(e.key()));
+                    break;
+                }
+            }
+            if let Some(clock) = first {
+                let origins = update_origins(self, oid, clock)?;
+                let start = key_update(oid, clock);
+                let end = key_update(oid, u32::MAX);
+                let cursor = self.iter_range(&start, &end)?;
+                return Ok(UpdatesIter(
+                    Some(cursor),
+                    self.options().value_codec(),
+                    origins,
+                ));
+            }
         }
+        Ok(UpdatesIter(None, None, HashMap::new()))
     }
-}
 
-fn get_oid<'a, DB: DocOps<'a> + ?Sized>(db: &DB, name: &[u8]) -> Result<Option<OID>, Error>
-where
-    Error: From<<DB as KVStore<'a>>::Error>,
-{
-    let key = key_oid(name);
-    let value = db.get(&key)?;
-    if let Some(value) = value {
-        let bytes: [u8; 4] = value.as_ref().try_into().unwrap();
-        let oid = OID::from_be_bytes(bytes);
-        Ok(Some(oid))
-    } else {
+    /// Returns a timestamp (in milliseconds since Unix epoch) at which a pending update with
+    /// a given `clock` has been pushed to a document with given `name`. Returns `None` if update
+    /// was not found or it was pushed while [StoreOptions::update_timestamps] was disabled.
+    ///
+    /// This feature requires only the read capabilities from the database transaction.
+    fn get_update_timestamp<K: AsRef<[u8]> + ?Sized>(
+        &self,
+        name: &K,
+        clock: u32,
+    ) -> Result<Option<u64>, Error> {
+        if let Some(oid) = get_oid(self, name.as_ref())? {
+            if let Some(value) = self.get(&key_update_time(oid, clock))? {
+                return Ok(update_timestamp(value.as_ref()));
+            }
+        }
         Ok(None)
     }
-}
-
-fn get_or_create_oid<'a, DB: DocOps<'a> + ?Sized>(db: &DB, name: &[u8]) -> Result<OID, Error>
-where
-    Error: From<<DB as KVStore<'a>>::Error>,
-{
-    if let Some(oid) = get_oid(db, name)? {
-        Ok(oid)
-    } else {
-        /*
-           Since pattern is:
 
-           00{doc_name:n}0      - OID key pattern
-           01{oid:4}0           - document key pattern
+    /// Returns storage statistics of a document with given `name` or `None` if document was not
+    /// found. They can be used to monitor storage usage and decide when the document should be
+    /// compacted (see: [Self::flush_doc]).
+    ///
+    /// This feature requires only the read capabilities from the database transaction.
+    fn doc_stats<K: AsRef<[u8]> + ?Sized>(&self, name: &K) -> Result<Option<DocStats>, Error> {
+        if let Some(oid) = get_oid(self, name.as_ref())? {
+            let start = key_doc_start(oid);
+            let end = key_doc_end(oid);
+            let mut stats = DocStats::default();
+            for e in self.iter_range(&start, &end)? {
+                let key = e.key();
+                let len = e.value().len() as u64;
+                match key[SUB_KEY_POS] {
+                    SUB_DOC | SUB_DOC_V2 | SUB_DOC_CHUNK => stats.doc_state_bytes += len,
+                    SUB_UPDATE => {
+                        stats.pending_updates += 1;
+                        stats.pending_update_bytes += len;
+                        stats.last_update_seq = Some(update_clock(key));
+                    }
+                    SUB_META => stats.meta_entries += 1,
+                    SUB_HISTORY => stats.last_flush_seq = Some(history_seq(key)),
+                    _ => {}
+                }
+            }
+            Ok(Some(stats))
+        } else {
+            Ok(None)
+        }
+    }
 
-           Use 00{0000}0 to try to move cursor to GTE first document, then move cursor 1 position
-           back to get the latest OID or not found.
-        */
-        let last_oid = if let Some(e) = db.peek_back([V1, KEYSPACE_DOC].as_ref())? {
-            let value = e.value();
-            let last_value = OID::from_be_bytes(value.try_into().unwrap());
-            last_value
+    /// Removes all data associated with the current document just like [Self::clear_doc], but
+    /// also returns the counts of removed entries grouped by their kind. Returns `None` if
+    /// document was not found. Document is removed permanently, even if soft delete is enabled
+    /// (see: [StoreOptions::soft_delete]).
+    ///
+    /// If `verify` is set, once removal is done, document keyspace is checked again and an
+    /// [StoreError::OrphanedEntries] error is returned if any of its entries still remained.
+    ///
+    /// This feature requires a write capabilities from the database transaction.
+    fn purge_doc<K: AsRef<[u8]> + ?Sized>(
+        &self,
+        name: &K,
+        verify: bool,
+    ) -> Result<Option<PurgeStats>, Error> {
+        if let Some(oid) = get_oid(self, name.as_ref())? {
+            let start = key_doc_start(oid);
+            let end = key_doc_end(oid);
+            let mut stats = PurgeStats::default();
+            for e in self.iter_range(&start, &end)? {
+                stats.count(e.key());
+            }
+            let mut batch = self.batch();
+            clear_doc(self, &mut batch, name.as_ref(), oid)?;
+            self.commit_batch(batch)?;
+            notify_cleared(self, name.as_ref());
+            if verify {
+                let count = self.iter_range(&start, &end)?.count();
+                if count != 0 {
+                    return Err(StoreError::OrphanedEntries { oid, count }.into());
+                }
+            }
+            Ok(Some(stats))
         } else {
-            0
+            Ok(None)
+        }
+    }
+
+    /// Returns a description of every raw key-value entry belonging to a document with given
+    /// `name`, in order in which they are returned by the underlying store: its OID mapping, all
+    /// entries of its keyspace and a flush marker (if flush has been interrupted). Returns `None`
+    /// if document was not found.
+    ///
+    /// It's meant for debugging, i.e. to verify that range iteration of a backend returns all
+    /// entries removed by [Self::clear_doc].
+    ///
+    /// This feature requires only the read capabilities from the database transaction.
+    fn debug_dump<K: AsRef<[u8]> + ?Sized>(&self, name: &K) -> Result<Option<Vec<KeyDump>>, Error> {
+        let name = name.as_ref();
+        let oid = match get_oid(self, name)? {
+            Some(oid) => oid,
+            None => return Ok(None),
         };
-        let new_oid = last_oid + 1;
+        let mut entries = Vec::new();
         let key = key_oid(name);
-        db.upsert(&key, new_oid.to_be_bytes().as_ref())?;
-        Ok(new_oid)
+        if let Some(value) = self.get(&key)? {
+            entries.push(KeyDump::new(&key, value.as_ref()));
+        }
+        for e in self.iter_range(&key_doc_start(oid), &key_doc_end(oid))? {
+            entries.push(KeyDump::new(e.key(), e.value()));
+        }
+        let key = key_flush_marker(oid);
+        if let Some(value) = self.get(&key)? {
+            entries.push(KeyDump::new(&key, value.as_ref()));
+        }
+        Ok(Some(entries))
+    }
+
+    /// Writes all updates and metadata changes of multiple documents gathered in a given `batch`
+    /// together, using a single [WriteBatch]. This way changes spanning across several documents
+    /// (i.e. cross-document indexes) can be persisted atomically on backends supporting atomic
+    /// batch writes. Update observers are notified only once all changes have been written.
+    ///
+    /// This feature requires a write capabilities from the database transaction.
+    fn commit_docs(&self, batch: MultiDocBatch) -> Result<(), Error> {
+        // document name -> (OID, last update clock)
+        let mut docs: HashMap<Vec<u8>, (OID, Option<u32>)> = HashMap::new();
+        let mut write_batch = self.batch();
+        let mut pushed = Vec::new();
+        // document name -> total size of updates pushed to it
+        let mut pending: HashMap<Vec<u8>, u64> = HashMap::new();
+        let mut changes = Vec::new();
+        let now = unix_millis();
+        let ops: Vec<_> = batch.into_iter().collect();
+        // encoded updates are checked against update limits before anything is written
+        let mut values = Vec::new();
+        // document name -> total size of encoded updates pushed to it
+        let mut incoming: HashMap<Vec<u8>, u64> = HashMap::new();
+        for op in ops.iter() {
+            if let DocOp::PushUpdate {
+                name, update, v2, ..
+            } = op
+            {
+                check_update_size(self, update)?;
+                validate_update(self, name, update, *v2)?;
+                let value = encode_value(self, update)?;
+                *incoming.entry(name.clone()).or_default() += value.len() as u64;
+                values.push(value);
+            }
+        }
+        let mut values = values.into_iter();
+        for op in ops {
+            match op {
+                DocOp::PushUpdate {
+                    name,
+                    update,
+                    v2,
+                    origin,
+                } => {
+                    let value = values.next().expect("encoded update");
+                    let (oid, clock) = resolve_doc(self, &mut docs, &name)?;
+                    let batched = self.options().update_dedup_window != 0
+                        && pushed
+                            .iter()
+                            .any(|(n, u, _, v)| *n == name && *u == update && *v == v2);
+                    if batched || find_duplicate(self, *oid, &update, v2)?.is_some() {
+                        // the same update is still pending or has been pushed in this batch
+                        continue;
+                    }
+                    let next = match clock {
+                        Some(clock) => clock
+                            .checked_add(1)
+                            .ok_or(StoreError::UpdateLogFull { oid: *oid })?,
+                        None => {
+                            reserve_pending_bytes(self, *oid, incoming[&name])?;
+                            next_clock(self, *oid)?
+                        }
+                    };
+                    *clock = Some(next);
+                    let key = if v2 {
+                        key_update_v2(*oid, next)
+                    } else {
+                        key_update(*oid, next)
+                    };
+                    *pending.entry(name.clone()).or_default() += value.len() as u64;
+                    write_batch.upsert(&key, &value);
+                    if let Some(origin) = origin {
+                        let origin = encode_meta(self, &origin)?;
+                        write_batch.upsert(&key_update_origin(*oid, next), &origin);
+                    }
+                    record_update_time(self, &mut write_batch, *oid, next, now);
+                    changes.push((name.clone(), *oid, ChangeKind::Update));
+                    pushed.push((name, update, next, v2));
+                }
+                DocOp::InsertMeta {
+                    name,
+                    meta_key,
+                    meta,
+                } => {
+                    let (oid, _) = resolve_doc(self, &mut docs, &name)?;
+                    write_batch.upsert(&key_meta(*oid, &meta_key), &encode_meta(self, &meta)?);
+                    changes.push((name, *oid, ChangeKind::Meta));
+                }
+                DocOp::RemoveMeta { name, meta_key } => {
+                    let (oid, _) = resolve_doc(self, &mut docs, &name)?;
+                    write_batch.remove(&key_meta(*oid, &meta_key));
+                    changes.push((name, *oid, ChangeKind::Meta));
+                }
+            }
+        }
+        // document name -> number of updates pushed to it
+        let mut counts: HashMap<&[u8], u32> = HashMap::new();
+        for (name, _, _, _) in pushed.iter() {
+            *counts.entry(name.as_slice()).or_default() += 1;
+        }
+        for (name, count) in counts {
+            if let (oid, Some(last)) = docs[name] {
+                track_pending(self, &mut write_batch, oid, last, count, pending[name])?;
+                let updates = pushed
+                    .iter()
+                    .filter(|(n, _, _, _)| n.as_slice() == name)
+                    .map(|(_, update, clock, v2)| (update.as_slice(), *clock, *v2));
+                track_state_vector(self, &mut write_batch, oid, updates.clone())?;
+                let updates = updates.map(|(update, clock, _)| (update, clock));
+                remember_updates(self, &mut write_batch, oid, updates)?;
+            }
+        }
+        let changes = changes
+            .iter()
+            .map(|(name, oid, kind)| (name.as_slice(), *oid, *kind));
+        log_changes(self, &mut write_batch, changes)?;
+        self.commit_batch(write_batch)?;
+        for (name, update, clock, v2) in pushed {
+            publish_update(self, &name, &update, Some(clock), v2);
+        }
+        for (oid, clock) in docs.values() {
+            if clock.is_some() {
+                compact_if_needed(self, *oid)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Sets an `expiry` timestamp of a document with given `name`, replacing the previous one.
+    /// Once expired, document will be removed by [Self::sweep_expired]. Timestamp units are up to
+    /// the application (i.e. milliseconds since Unix epoch), as long as they are consistent with
+    /// the values passed to [Self::sweep_expired].
+    ///
+    /// This feature requires a write capabilities from the database transaction.
+    fn set_doc_expiry<K: AsRef<[u8]> + ?Sized>(&self, name: &K, expiry: u64) -> Result<(), Error> {
+        let name = name.as_ref();
+        let oid = get_or_create_oid(self, name)?;
+        let mut batch = self.batch();
+        if let Some(prev) = get_expiry(self, oid)? {
+            batch.remove(&key_expiry_index(prev, oid));
+        }
+        batch.upsert(&key_expiry(oid), &expiry.to_be_bytes());
+        batch.upsert(&key_expiry_index(expiry, oid), name);
+        self.commit_batch(batch)?;
+        Ok(())
+    }
+
+    /// Returns an expiry timestamp of a document with given `name` or `None` if document was not
+    /// found or it has no expiry set.
+    ///
+    /// This feature requires only the read capabilities from the database transaction.
+    fn get_doc_expiry<K: AsRef<[u8]> + ?Sized>(&self, name: &K) -> Result<Option<u64>, Error> {
+        if let Some(oid) = get_oid(self, name.as_ref())? {
+            get_expiry(self, oid)
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Removes an expiry timestamp of a document with given `name`, if it was set.
+    ///
+    /// This feature requires a write capabilities from the database transaction.
+    fn remove_doc_expiry<K: AsRef<[u8]> + ?Sized>(&self, name: &K) -> Result<(), Error> {
+        if let Some(oid) = get_oid(self, name.as_ref())? {
+            if let Some(expiry) = get_expiry(self, oid)? {
+                let mut batch = self.batch();
+                batch.remove(&key_expiry(oid));
+                batch.remove(&key_expiry_index(expiry, oid));
+                self.commit_batch(batch)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Removes all documents (see: [Self::clear_doc]), which expiry timestamp is lower than or
+    /// equal to `now`. Returns a number of removed documents.
+    ///
+    /// This method is meant to be called periodically, i.e. by a background job cleaning up
+    /// ephemeral documents.
+    ///
+    /// This feature requires a write capabilities from the database transaction.
+    fn sweep_expired(&self, now: u64) -> Result<usize, Error> {
+        let start = key_expiry_index(0, 0);
+        let end = key_expiry_index(now, OID::MAX);
+        let expired: Vec<_> = self
+            .iter_range(&start, &end)?
+            .map(|e| (e.key().to_vec(), e.value().to_vec()))
+            .collect();
+        let mut removed = 0;
+        for (key, name) in expired {
+            let (expiry, oid) = expiry_index_entry(&key);
+            // index entry is stale if document has been removed or its expiry has changed
+            if get_oid(self, &name)? == Some(oid) && get_expiry(self, oid)? == Some(expiry) {
+                // index entry is removed together with the document
+                self.clear_doc(&name)?;
+                removed += 1;
+            } else {
+                self.remove(&key)?;
+            }
+        }
+        Ok(removed)
+    }
+
+    /// Returns a version of the key schema used by the database. Databases created before the
+    /// schema version has been tracked are using [keys::V1] schema.
+    ///
+    /// This feature requires only the read capabilities from the database transaction.
+    fn schema_version(&self) -> Result<u8, Error> {
+        if let Some(value) = self.get(&KEY_SCHEMA_VERSION)? {
+            Ok(value.as_ref()[0])
+        } else {
+            Ok(V1)
+        }
+    }
+
+    /// Upgrades the key schema used by the database in place, from `from_version` to
+    /// `to_version`, applying all intermediate migration steps in order. Once done, database is
+    /// marked as using `to_version` schema. Migrating to the same version is a no-op.
+    ///
+    /// Returns [StoreError::SchemaVersionMismatch] if database doesn't use `from_version` schema
+    /// and [StoreError::UnsupportedMigration] if there's no migration path between versions.
+    ///
+    /// This feature requires a write capabilities from the database transaction.
+    fn migrate(&self, from_version: u8, to_version: u8) -> Result<(), Error> {
+        let actual = self.schema_version()?;
+        if actual != from_version {
+            return Err(StoreError::SchemaVersionMismatch {
+                expected: from_version,
+                actual,
+            }
+            .into());
+        }
+        if to_version < from_version || to_version > SCHEMA_VERSION {
+            return Err(StoreError::UnsupportedMigration {
+                from: from_version,
+                to: to_version,
+            }
+            .into());
+        }
+        for version in from_version..to_version {
+            migrate_step(self, version)?;
+        }
+        self.upsert(&KEY_SCHEMA_VERSION, &[to_version])?;
+        Ok(())
+    }
+
+    /// Returns a metadata value stored under its metadata `key` for a document with given `name`.
+    ///
+    /// This feature requires only the read capabilities from the database transaction.
+    fn get_meta<K1: AsRef<[u8]> + ?Sized, K2: AsRef<[u8]> + ?Sized>(
+        &self,
+        name: &K1,
+        meta_key: &K2,
+    ) -> Result<Option<Vec<u8>>, Error> {
+        if let Some(oid) = get_oid(self, name.as_ref())? {
+            let key = key_meta(oid, meta_key.as_ref());
+            match self.get(&key)? {
+                Some(value) => {
+                    let codec = self.options().value_codec();
+                    Ok(Some(
+                        decode_meta(codec.as_deref(), &key, value.as_ref())?.into_owned(),
+                    ))
+                }
+                None => Ok(None),
+            }
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Inserts or updates new `meta` value stored under its metadata `key` for a document with
+    /// given `name`.
+    ///
+    /// This feature requires write capabilities from the database transaction.
+    fn insert_meta<K1: AsRef<[u8]> + ?Sized, K2: AsRef<[u8]> + ?Sized>(
+        &self,
+        name: &K1,
+        meta_key: &K2,
+        meta: &[u8],
+    ) -> Result<(), Error> {
+        let oid = get_or_create_oid(self, name.as_ref())?;
+        let mut batch = self.batch();
+        batch.upsert(&key_meta(oid, meta_key.as_ref()), &encode_meta(self, meta)?);
+        log_changes(self, &mut batch, [(name.as_ref(), oid, ChangeKind::Meta)])?;
+        self.commit_batch(batch)?;
+        Ok(())
+    }
+
+    /// Returns a metadata value stored under its metadata `key` for a document with given `name`,
+    /// deserialized from JSON. Returns an error if stored value is not a valid JSON representation
+    /// of `T`.
+    ///
+    /// This feature requires only the read capabilities from the database transaction.
+    #[cfg(feature = "json")]
+    fn get_meta_json<K1, K2, T>(&self, name: &K1, meta_key: &K2) -> Result<Option<T>, Error>
+    where
+        K1: AsRef<[u8]> + ?Sized,
+        K2: AsRef<[u8]> + ?Sized,
+        T: serde::de::DeserializeOwned,
+    {
+        if let Some(value) = self.get_meta(name, meta_key)? {
+            Ok(Some(serde_json::from_slice(value.as_ref())?))
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Inserts or updates `meta` value serialized as JSON under its metadata `key` for a document
+    /// with given `name`.
+    ///
+    /// This feature requires write capabilities from the database transaction.
+    #[cfg(feature = "json")]
+    fn insert_meta_json<K1, K2, T>(&self, name: &K1, meta_key: &K2, meta: &T) -> Result<(), Error>
+    where
+        K1: AsRef<[u8]> + ?Sized,
+        K2: AsRef<[u8]> + ?Sized,
+        T: serde::Serialize + ?Sized,
+    {
+        let json = serde_json::to_vec(meta)?;
+        self.insert_meta(name, meta_key, &json)
+    }
+
+    /// Removes an metadata entry stored under given metadata `key` for a document with provided
+    /// `name`.
+    ///
+    /// This feature requires write capabilities from the database transaction.
+    fn remove_meta<K1: AsRef<[u8]> + ?Sized, K2: AsRef<[u8]> + ?Sized>(
+        &self,
+        name: &K1,
+        meta_key: &K2,
+    ) -> Result<(), Error> {
+        if let Some(oid) = get_oid(self, name.as_ref())? {
+            let mut batch = self.batch();
+            batch.remove(&key_meta(oid, meta_key.as_ref()));
+            log_changes(self, &mut batch, [(name.as_ref(), oid, ChangeKind::Meta)])?;
+            self.commit_batch(batch)?;
+        }
+        Ok(())
+    }
+
+    /// Returns a JSON rendering of a document with given `name` cached on its last flush, if
+    /// current store maintains one (see: [StoreOptions::json_export]). Pending updates pushed since
+    /// then are not reflected. It lets consumers read document content without the yrs runtime.
+    ///
+    /// This feature requires only the read capabilities from the database transaction.
+    fn get_json<K: AsRef<[u8]> + ?Sized>(&self, name: &K) -> Result<Option<Vec<u8>>, Error> {
+        if let Some(oid) = get_oid(self, name.as_ref())? {
+            let key = key_json(oid);
+            if let Some(value) = self.get(&key)? {
+                let codec = self.options().value_codec();
+                let json = decode_meta(codec.as_deref(), &key, value.as_ref())?;
+                return Ok(Some(json.into_owned()));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Loads a document with given `name` together with all of its pending updates and returns
+    /// a JSON representation of its content rendered using [StoreOptions::json_export] or a default
+    /// [crate::export::JsonExport] if none was set. Returns `None` if document was not found.
+    ///
+    /// This feature requires only the read capabilities from the database transaction.
+    #[cfg(feature = "json")]
+    fn export_json<K: AsRef<[u8]> + ?Sized>(
+        &self,
+        name: &K,
+    ) -> Result<Option<serde_json::Value>, Error> {
+        let doc = Doc::with_options(self.options().new_doc_options());
+        if !self.load_doc(name, &mut doc.transact_mut())? {
+            return Ok(None);
+        }
+        let export = self.options().json_export.clone().unwrap_or_default();
+        Ok(Some(serde_json::to_value(export.to_any(&doc))?))
+    }
+
+    /// Returns the last known awareness state (i.e. user presence and cursor positions) stored for
+    /// a document with given `name`. Awareness state is stored as an opaque binary blob, which
+    /// usually is an awareness update encoded by y-sync protocol.
+    ///
+    /// This feature requires only the read capabilities from the database transaction.
+    fn get_awareness<K: AsRef<[u8]> + ?Sized>(
+        &self,
+        name: &K,
+    ) -> Result<Option<Self::Return>, Error> {
+        if let Some(oid) = get_oid(self, name.as_ref())? {
+            Ok(self.get(&key_awareness(oid))?)
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Inserts or replaces the last known `awareness` state of a document with given `name`, so
+    /// that reconnecting clients can be shown recent presence information even before other peers
+    /// rejoin. Awareness state is not copied by [Self::copy_doc].
+    ///
+    /// This feature requires write capabilities from the database transaction.
+    fn insert_awareness<K: AsRef<[u8]> + ?Sized>(
+        &self,
+        name: &K,
+        awareness: &[u8],
+    ) -> Result<(), Error> {
+        let oid = get_or_create_oid(self, name.as_ref())?;
+        self.upsert(&key_awareness(oid), awareness)?;
+        Ok(())
+    }
+
+    /// Removes the awareness state stored for a document with given `name`.
+    ///
+    /// This feature requires write capabilities from the database transaction.
+    fn remove_awareness<K: AsRef<[u8]> + ?Sized>(&self, name: &K) -> Result<(), Error> {
+        if let Some(oid) = get_oid(self, name.as_ref())? {
+            self.remove(&key_awareness(oid))?;
+        }
+        Ok(())
+    }
+
+    /// Acquires a lease over a document with given `name` on behalf of an `owner` (i.e. a cluster
+    /// node identifier) for a `ttl` period starting at `now`, so that clustered servers can
+    /// coordinate which of them is allowed to flush or compact a given document. Timestamp units
+    /// are up to the application, as long as they are used consistently.
+    ///
+    /// Returns `true` if lease was acquired, which happens when document has no lease, its lease
+    /// has expired or is already held by the same `owner` (in which case it's renewed). Returns
+    /// `false` if lease is held by another owner.
+    ///
+    /// Leases rely on isolation of the database transaction: two competing transactions must not
+    /// be able to both observe a document without a lease and commit their own one.
+    ///
+    /// This feature requires write capabilities from the database transaction.
+    fn acquire_doc_lease<K: AsRef<[u8]> + ?Sized, O: AsRef<[u8]> + ?Sized>(
+        &self,
+        name: &K,
+        owner: &O,
+        now: u64,
+        ttl: u64,
+    ) -> Result<bool, Error> {
+        let owner = owner.as_ref();
+        let oid = get_or_create_oid(self, name.as_ref())?;
+        let key = key_lease(oid);
+        if let Some(lease) = self.get(&key)? {
+            let lease = DocLease::decode(&key, lease.as_ref())?;
+            if lease.owner.as_ref() != owner && lease.expires_at > now {
+                return Ok(false);
+            }
+        }
+        let lease = DocLease {
+            owner: owner.into(),
+            expires_at: now.saturating_add(ttl),
+        };
+        self.upsert(&key, &lease.encode())?;
+        Ok(true)
+    }
+
+    /// Releases a lease over a document with given `name` held by a given `owner`. Returns `false`
+    /// if document has no lease or it's held by another owner, in which case it's left untouched.
+    ///
+    /// This feature requires write capabilities from the database transaction.
+    fn release_doc_lease<K: AsRef<[u8]> + ?Sized, O: AsRef<[u8]> + ?Sized>(
+        &self,
+        name: &K,
+        owner: &O,
+    ) -> Result<bool, Error> {
+        if let Some(oid) = get_oid(self, name.as_ref())? {
+            let key = key_lease(oid);
+            if let Some(lease) = self.get(&key)? {
+                let lease = DocLease::decode(&key, lease.as_ref())?;
+                if lease.owner.as_ref() == owner.as_ref() {
+                    self.remove(&key)?;
+                    return Ok(true);
+                }
+            }
+        }
+        Ok(false)
+    }
+
+    /// Returns a lease over a document with given `name` (see: [Self::acquire_doc_lease]). Keep
+    /// in mind that returned lease may have already expired.
+    ///
+    /// This feature requires only the read capabilities from the database transaction.
+    fn get_doc_lease<K: AsRef<[u8]> + ?Sized>(&self, name: &K) -> Result<Option<DocLease>, Error> {
+        if let Some(oid) = get_oid(self, name.as_ref())? {
+            let key = key_lease(oid);
+            if let Some(lease) = self.get(&key)? {
+                return Ok(Some(DocLease::decode(&key, lease.as_ref())?));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Checks if a document with given `name` exists in current database. Only the OID mapping of
+    /// a document is looked up, without reading its contents.
+    ///
+    /// This feature requires only the read capabilities from the database transaction.
+    fn doc_exists<K: AsRef<[u8]> + ?Sized>(&self, name: &K) -> Result<bool, Error> {
+        Ok(get_oid(self, name.as_ref())?.is_some())
+    }
+
+    /// Returns a number of documents stored in current database. Documents are counted by
+    /// iterating over their OID mappings, without reading their contents.
+    ///
+    /// This feature requires only the read capabilities from the database transaction.
+    fn doc_count(&self) -> Result<u64, Error> {
+        let start = Key::from_const([V1, KEYSPACE_OID]);
+        let end = Key::from_const([V1, KEYSPACE_DOC]);
+        let mut count = 0;
+        for _ in self.iter_range(&start, &end)? {
+            count += 1;
+        }
+        Ok(count)
+    }
+
+    /// Finishes document flushes (see: [Self::flush_doc]), which have been interrupted i.e. by
+    /// a process crash. This is only necessary for backends, which don't apply write batches
+    /// atomically, and should be called right after the database is opened, before any other
+    /// writes take place. Returns a number of recovered documents.
+    ///
+    /// This feature requires a write capabilities from the database transaction.
+    fn recover(&self) -> Result<usize, Error> {
+        let start = Key::from_const([V1, KEYSPACE_FLUSH]);
+        let end = Key::from_const([V1, KEYSPACE_FLUSH + 1]);
+        let mut interrupted = Vec::new();
+        for e in self.iter_range(&start, &end)? {
+            let oid = oid_from_bytes(&e.key()[2..]);
+            let (up_to, first_seq) = parse_flush_marker(e.key(), e.value())?;
+            interrupted.push((oid, up_to, first_seq));
+        }
+        for &(oid, up_to, first_seq) in interrupted.iter() {
+            let doc = Doc::with_options(self.options().new_doc_options());
+            let found = load_doc(self, oid, &mut doc.transact_mut(), None)?;
+            if found == 0 {
+                // document has been removed in the meantime
+                self.remove(&key_flush_marker(oid))?;
+            } else {
+                write_flushed_with(self, oid, &doc, up_to, first_seq)?;
+            }
+        }
+        Ok(interrupted.len())
+    }
+
+    /// Walks over all key spaces of current database in order to check its integrity, i.e. before
+    /// or after a migration. It verifies that:
+    ///
+    /// - Every OID is mapped to a single document name.
+    /// - There are no document entries left behind by OIDs without a document name.
+    /// - Document states, their state vectors as well as pending and history updates can be
+    ///   decoded.
+    /// - Stored state vectors match the document states.
+    ///
+    /// Found problems are reported instead of being returned as errors. This operation reads every
+    /// entry of the database, so it may take a long time for large databases.
+    ///
+    /// This feature requires only the read capabilities from the database transaction.
+    fn verify_store(&self) -> Result<VerificationReport, Error> {
+        let mut report = VerificationReport::default();
+        let mut names: HashMap<OID, Vec<Box<[u8]>>> = HashMap::new();
+        let start = Key::from_const([V1, KEYSPACE_OID]);
+        let end = Key::from_const([V1, KEYSPACE_DOC]);
+        for e in self.iter_range(&start, &end)? {
+            if e.value().len() != OID_LEN {
+                report.undecodable.push(e.key().into());
+                continue;
+            }
+            let name = doc_oid_name(e.key());
+            names
+                .entry(oid_from_bytes(e.value()))
+                .or_default()
+                .push(name.into());
+        }
+        report.docs = names.values().map(Vec::len).sum();
+        for (oid, names) in names.iter() {
+            if names.len() > 1 {
+                report.duplicate_oids.push((*oid, names.clone()));
+            }
+        }
+        report.duplicate_oids.sort();
+
+        let start = Key::from_const([V1, KEYSPACE_DOC]);
+        let end = Key::from_const([V1, KEYSPACE_DOC + 1]);
+        let mut current: Option<VerifiedDoc> = None;
+        let codec = self.options().value_codec();
+        for e in self.iter_range(&start, &end)? {
+            let key = e.key();
+            if key.len() <= SUB_KEY_POS {
+                report.undecodable.push(key.into());
+                continue;
+            }
+            let oid = oid_from_bytes(&key[2..SUB_KEY_POS]);
+            if current.as_ref().map(|doc| doc.oid) != Some(oid) {
+                if let Some(doc) = current.take() {
+                    doc.finish(&names, &mut report);
+                }
+                current = Some(VerifiedDoc::new(oid));
+            }
+            let doc = current.as_mut().unwrap();
+            if !names.contains_key(&oid) {
+                doc.entries += 1;
+                continue;
+            }
+            let valid = match key[SUB_KEY_POS] {
+                SUB_DOC | SUB_DOC_V2 => {
+                    let v2 = key[SUB_KEY_POS] == SUB_DOC_V2;
+                    match decode_state(self, key, e.value(), v2) {
+                        Ok(update) => {
+                            doc.state_vector = Some(update.state_vector());
+                            true
+                        }
+                        Err(_) => false,
+                    }
+                }
+                SUB_STATE_VEC => match StateVector::decode_v1(e.value()) {
+                    Ok(sv) => {
+                        doc.stored_state_vector = Some(sv);
+                        true
+                    }
+                    Err(_) => false,
+                },
+                SUB_UPDATE | SUB_HISTORY => decode_update(codec.as_deref(), &e).is_ok(),
+                SUB_SV_CLOCK => {
+                    doc.tracked_state_vector = true;
+                    true
+                }
+                _ => true,
+            };
+            if !valid {
+                report.undecodable.push(key.into());
+            }
+        }
+        if let Some(doc) = current {
+            doc.finish(&names, &mut report);
+        }
+        Ok(report)
+    }
+
+    /// Removes entries of orphaned documents, i.e. ones which OID is no longer mapped to any
+    /// document name (see: [VerificationReport::orphaned]). Such entries may be left behind by
+    /// [Self::clear_doc] interrupted in the middle on backends, which don't apply write batches
+    /// atomically. Besides the document keyspace, expiry index entries and flush markers of
+    /// orphaned documents are removed as well. Returns counts of removed entries.
+    ///
+    /// Documents which names are being persisted by concurrent transactions may be seen as
+    /// orphaned, so vacuum should be run when no other writers are active.
+    ///
+    /// This feature requires a write capabilities from the database transaction.
+    fn vacuum(&self) -> Result<VacuumStats, Error> {
+        let mut oids = HashSet::new();
+        let start = Key::from_const([V1, KEYSPACE_OID]);
+        let end = Key::from_const([V1, KEYSPACE_DOC]);
+        for e in self.iter_range(&start, &end)? {
+            if e.value().len() == OID_LEN {
+                oids.insert(oid_from_bytes(e.value()));
+            }
+        }
+
+        let mut stats = VacuumStats::default();
+        let mut batch = self.batch();
+        let mut blobs = BlobRefs::default();
+        let start = Key::from_const([V1, KEYSPACE_DOC]);
+        let end = Key::from_const([V1, KEYSPACE_DOC + 1]);
+        for e in self.iter_range(&start, &end)? {
+            let key = e.key();
+            if key.len() <= SUB_KEY_POS {
+                continue;
+            }
+            let oid = oid_from_bytes(&key[2..SUB_KEY_POS]);
+            if oids.contains(&oid) {
+                continue;
+            }
+            if stats.oids.last() != Some(&oid) {
+                batch.remove_range(&key_doc_start(oid), &key_doc_end(oid));
+                stats.oids.push(oid);
+            }
+            if matches!(key[SUB_KEY_POS], SUB_DOC | SUB_DOC_V2 | SUB_QUARANTINE) {
+                blobs.release(e.value());
+            }
+            stats.entries.count(key);
+        }
+        blobs.commit(self, &mut batch)?;
+
+        let start = Key::from_const([V1, KEYSPACE_EXPIRY]);
+        let end = Key::from_const([V1, KEYSPACE_EXPIRY + 1]);
+        for e in self.iter_range(&start, &end)? {
+            if e.key().len() == 10 + OID_LEN {
+                let (_, oid) = expiry_index_entry(e.key());
+                if !oids.contains(&oid) {
+                    batch.remove(e.key());
+                    stats.index_entries += 1;
+                }
+            }
+        }
+        let start = Key::from_const([V1, KEYSPACE_FLUSH]);
+        let end = Key::from_const([V1, KEYSPACE_FLUSH + 1]);
+        for e in self.iter_range(&start, &end)? {
+            if e.key().len() == 2 + OID_LEN && !oids.contains(&oid_from_bytes(&e.key()[2..])) {
+                batch.remove(e.key());
+                stats.index_entries += 1;
+            }
+        }
+        self.commit_batch(batch)?;
+        Ok(stats)
+    }
+
+    /// Returns an iterator over the entries of a global change log, which sequence numbers are
+    /// greater than `since_seq`, ordered by their sequence numbers. Changes are recorded only by
+    /// stores with change log enabled (see: [StoreOptions::changelog]). Passing the sequence number
+    /// of the last processed change makes it possible to consume the change log incrementally, i.e.
+    /// to maintain search indexes or to fan out changes to connected clients.
+    ///
+    /// Sequence numbers are allocated from the last recorded change log entry, so concurrent
+    /// writers must be serialized by the isolation of the underlying transaction.
+    ///
+    /// This feature requires only the read capabilities from the database transaction.
+    fn iter_changes(
+        &self,
+        since_seq: u64,
+    ) -> Result<ChangesIter<Self::Cursor, Self::Entry>, Error> {
+        let start = key_changelog(since_seq.saturating_add(1));
+        let end = key_changelog(u64::MAX);
+        let cursor = self.iter_range(&start, &end)?;
+        Ok(ChangesIter(cursor))
+    }
+
+    /// Removes change log entries, which sequence numbers are lower than or equal to `up_to_seq`.
+    /// The most recent entry is always kept, so that sequence numbers of the changes recorded
+    /// afterwards never start over.
+    ///
+    /// This feature requires write capabilities from the database transaction.
+    fn trim_changes(&self, up_to_seq: u64) -> Result<(), Error> {
+        let up_to_seq = up_to_seq.min(last_change_seq(self)?.saturating_sub(1));
+        if up_to_seq > 0 {
+            self.remove_range(&key_changelog(0), &key_changelog(up_to_seq))?;
+        }
+        Ok(())
+    }
+
+    /// Returns an iterator over all document names stored in current database.
+    fn iter_docs(&self) -> Result<DocsNameIter<Self::Cursor, Self::Entry>, Error> {
+        let start = Key::from_const([V1, KEYSPACE_OID]);
+        let end = Key::from_const([V1, KEYSPACE_DOC]);
+        let cursor = self.iter_range(&start, &end)?;
+        Ok(DocsNameIter { cursor, start, end })
+    }
+
+    /// Returns a single page of up to `limit` documents stored in current database, ordered by
+    /// their names. Every entry contains a document name together with its OID. Page starts right
+    /// after a document named `start_after` (usually the last name of the previous page) or from
+    /// the first document if `None` was provided.
+    ///
+    /// Only the entries belonging to the requested page are read, which makes it suitable for
+    /// serving paginated document listings.
+    fn iter_docs_paged(&self, start_after: Option<&[u8]>, limit: usize) -> Result<DocsPage, Error> {
+        let start = match start_after {
+            Some(name) => key_oid(name).to_vec(),
+            None => vec![V1, KEYSPACE_OID],
+        };
+        let end = Key::from_const([V1, KEYSPACE_DOC]);
+        let mut page = Vec::new();
+        if limit == 0 {
+            return Ok(page);
+        }
+        for e in self.iter_range(&start, &end)? {
+            if start_after.is_some() && e.key() == start.as_slice() {
+                continue;
+            }
+            page.push((doc_oid_name(e.key()).into(), oid_from_bytes(e.value())));
+            if page.len() == limit {
+                break;
+            }
+        }
+        Ok(page)
+    }
+
+    /// Returns an iterator over all metadata entries stored for a given document.
+    fn iter_meta<K: AsRef<[u8]> + ?Sized>(
+        &self,
+        doc_name: &K,
+    ) -> Result<MetadataIter<Self::Cursor, Self::Entry>, Error> {
+        if let Some(oid) = get_oid(self, doc_name.as_ref())? {
+            let start = key_meta_start(oid).to_vec();
+            let end = key_meta_end(oid).to_vec();
+            let cursor = self.iter_range(&start, &end)?;
+            Ok(MetadataIter(
+                Some((cursor, start, end)),
+                self.options().value_codec(),
+            ))
+        } else {
+            Ok(MetadataIter(None, None))
+        }
+    }
+
+    /// Returns an iterator over metadata entries of a given document, which keys start with
+    /// provided `prefix`. Entries are returned in the order of their keys. Range is resolved by
+    /// the underlying store, so only the matching entries are ever read.
+    fn iter_meta_prefix<K1: AsRef<[u8]> + ?Sized, K2: AsRef<[u8]> + ?Sized>(
+        &self,
+        doc_name: &K1,
+        prefix: &K2,
+    ) -> Result<MetadataIter<Self::Cursor, Self::Entry>, Error> {
+        if let Some(oid) = get_oid(self, doc_name.as_ref())? {
+            let start = key_meta_prefix_start(oid, prefix.as_ref()).to_vec();
+            let end = key_meta_prefix_end(oid, prefix.as_ref()).to_vec();
+            let cursor = self.iter_range(&start, &end)?;
+            Ok(MetadataIter(
+                Some((cursor, start, end)),
+                self.options().value_codec(),
+            ))
+        } else {
+            Ok(MetadataIter(None, None))
+        }
+    }
+
+    /// Returns an iterator over metadata entries of a given document, which keys are within
+    /// `from`..=`to` range. Entries are returned in the order of their keys. Range is resolved by
+    /// the underlying store, so only the matching entries are ever read.
+    fn iter_meta_range<K1, K2, K3>(
+        &self,
+        doc_name: &K1,
+        from: &K2,
+        to: &K3,
+    ) -> Result<MetadataIter<Self::Cursor, Self::Entry>, Error>
+    where
+        K1: AsRef<[u8]> + ?Sized,
+        K2: AsRef<[u8]> + ?Sized,
+        K3: AsRef<[u8]> + ?Sized,
+    {
+        if let Some(oid) = get_oid(self, doc_name.as_ref())? {
+            let start = key_meta(oid, from.as_ref()).to_vec();
+            let end = key_meta(oid, to.as_ref()).to_vec();
+            let cursor = self.iter_range(&start, &end)?;
+            Ok(MetadataIter(
+                Some((cursor, start, end)),
+                self.options().value_codec(),
+            ))
+        } else {
+            Ok(MetadataIter(None, None))
+        }
+    }
+
+    /// Captures a [Snapshot] of the current state of a document with given `name` (including its
+    /// pending updates) and stores it under provided `snapshot_id`. If snapshot with the same id
+    /// already existed, it will be overridden. Returns `None` if document was not found.
+    ///
+    /// Keep in mind that restoring document state from snapshot requires the deleted content to be
+    /// present. For this reason documents, which are meant to be restored from snapshots, should
+    /// be flushed using [Self::flush_doc_with] and [yrs::Options::skip_gc] set to `true`.
+    ///
+    /// This feature requires write capabilities from the database transaction.
+    fn create_snapshot<K1: AsRef<[u8]> + ?Sized, K2: AsRef<[u8]> + ?Sized>(
+        &self,
+        name: &K1,
+        snapshot_id: &K2,
+    ) -> Result<Option<Snapshot>, Error> {
+        if let Some(oid) = get_oid(self, name.as_ref())? {
+            let doc = Doc::with_options(yrs::Options {
+                skip_gc: true,
+                ..self.options().new_doc_options()
+            });
+            let found = load_doc(self, oid, &mut doc.transact_mut(), None)?;
+            if found != 0 {
+                let snapshot = doc.transact().snapshot();
+                let key = key_snapshot(oid, snapshot_id.as_ref());
+                self.upsert(&key, &snapshot.encode_v1())?;
+                return Ok(Some(snapshot));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Returns a [Snapshot] stored under given `snapshot_id` for a document with provided `name`.
+    ///
+    /// This feature requires only the read capabilities from the database transaction.
+    fn get_snapshot<K1: AsRef<[u8]> + ?Sized, K2: AsRef<[u8]> + ?Sized>(
+        &self,
+        name: &K1,
+        snapshot_id: &K2,
+    ) -> Result<Option<Snapshot>, Error> {
+        if let Some(oid) = get_oid(self, name.as_ref())? {
+            let key = key_snapshot(oid, snapshot_id.as_ref());
+            if let Some(data) = self.get(&key)? {
+                let snapshot = Snapshot::decode_v1(data.as_ref())?;
+                return Ok(Some(snapshot));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Loads the historical state of a document with given `name`, as it was captured by the
+    /// snapshot stored under `snapshot_id`, into in-memory Yrs document using provided
+    /// [TransactionMut]. Returns `false` if either document or snapshot were not found.
+    ///
+    /// This feature requires only the read capabilities from the database transaction.
+    fn load_snapshot<K1: AsRef<[u8]> + ?Sized, K2: AsRef<[u8]> + ?Sized>(
+        &self,
+        name: &K1,
+        snapshot_id: &K2,
+        txn: &mut TransactionMut,
+    ) -> Result<bool, Error> {
+        if let Some(snapshot) = self.get_snapshot(name, snapshot_id)? {
+            let doc = Doc::with_options(yrs::Options {
+                skip_gc: true,
+                ..self.options().new_doc_options()
+            });
+            if self.load_doc(name, &mut doc.transact_mut())? {
+                let update = encode_state_from_snapshot(&doc, &snapshot)?;
+                txn.apply_update(Update::decode_v1(&update)?)?;
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+
+    /// Removes a snapshot stored under given `snapshot_id` for a document with provided `name`.
+    ///
+    /// This feature requires write capabilities from the database transaction.
+    fn remove_snapshot<K1: AsRef<[u8]> + ?Sized, K2: AsRef<[u8]> + ?Sized>(
+        &self,
+        name: &K1,
+        snapshot_id: &K2,
+    ) -> Result<(), Error> {
+        if let Some(oid) = get_oid(self, name.as_ref())? {
+            let key = key_snapshot(oid, snapshot_id.as_ref());
+            self.remove(&key)?;
+        }
+        Ok(())
+    }
+
+    /// Returns an iterator over identifiers of all snapshots stored for a given document.
+    fn iter_snapshots<K: AsRef<[u8]> + ?Sized>(
+        &self,
+        doc_name: &K,
+    ) -> Result<SnapshotIter<Self::Cursor, Self::Entry>, Error> {
+        if let Some(oid) = get_oid(self, doc_name.as_ref())? {
+            let start = key_snapshot_start(oid).to_vec();
+            let end = key_snapshot_end(oid).to_vec();
+            let cursor = self.iter_range(&start, &end)?;
+            Ok(SnapshotIter(Some((cursor, start, end))))
+        } else {
+            Ok(SnapshotIter(None))
+        }
+    }
+
+    /// Tags the current version of a document with given `name` (including its pending updates)
+    /// with a human-readable `tag`, which can later be used as a save point, i.e. to compute
+    /// changes made since (see: [Self::diff_since_tag]). Tag stores the current state vector of
+    /// a document and, if `with_snapshot` is set, a [Snapshot] of its state. If tag with the same
+    /// name already existed, it will be overridden. Returns `None` if document was not found.
+    ///
+    /// Just like in case of [Self::create_snapshot], restoring document state from a tagged
+    /// snapshot requires the deleted content to be present.
+    ///
+    /// This feature requires write capabilities from the database transaction.
+    fn tag_version<K1: AsRef<[u8]> + ?Sized, K2: AsRef<[u8]> + ?Sized>(
+        &self,
+        name: &K1,
+        tag: &K2,
+        with_snapshot: bool,
+    ) -> Result<Option<VersionTag>, Error> {
+        if let Some(oid) = get_oid(self, name.as_ref())? {
+            let doc = Doc::with_options(yrs::Options {
+                skip_gc: true,
+                ..self.options().new_doc_options()
+            });
+            let found = load_doc(self, oid, &mut doc.transact_mut(), None)?;
+            if found != 0 {
+                let txn = doc.transact();
+                let tag_value = VersionTag {
+                    state_vector: txn.state_vector(),
+                    snapshot: if with_snapshot {
+                        Some(txn.snapshot())
+                    } else {
+                        None
+                    },
+                };
+                let key = key_tag(oid, tag.as_ref());
+                self.upsert(&key, &tag_value.encode())?;
+                return Ok(Some(tag_value));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Returns a [VersionTag] stored under given `tag` for a document with provided `name`.
+    ///
+    /// This feature requires only the read capabilities from the database transaction.
+    fn get_tag<K1: AsRef<[u8]> + ?Sized, K2: AsRef<[u8]> + ?Sized>(
+        &self,
+        name: &K1,
+        tag: &K2,
+    ) -> Result<Option<VersionTag>, Error> {
+        if let Some(oid) = get_oid(self, name.as_ref())? {
+            let key = key_tag(oid, tag.as_ref());
+            if let Some(data) = self.get(&key)? {
+                return Ok(Some(VersionTag::decode(&key, data.as_ref())?));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Returns names of all tags (see: [Self::tag_version]) of a given document, ordered
+    /// lexicographically.
+    ///
+    /// This feature requires only the read capabilities from the database transaction.
+    fn list_tags<K: AsRef<[u8]> + ?Sized>(&self, name: &K) -> Result<Vec<Box<[u8]>>, Error> {
+        let mut tags = Vec::new();
+        if let Some(oid) = get_oid(self, name.as_ref())? {
+            let start = key_tag_start(oid);
+            let end = key_tag_end(oid);
+            for e in self.iter_range(&start, &end)? {
+                tags.push(doc_tag_name(e.key()).into());
+            }
+        }
+        Ok(tags)
+    }
+
+    /// Removes a tag stored under given `tag` name for a document with provided `name`.
+    ///
+    /// This feature requires write capabilities from the database transaction.
+    fn remove_tag<K1: AsRef<[u8]> + ?Sized, K2: AsRef<[u8]> + ?Sized>(
+        &self,
+        name: &K1,
+        tag: &K2,
+    ) -> Result<(), Error> {
+        if let Some(oid) = get_oid(self, name.as_ref())? {
+            self.remove(&key_tag(oid, tag.as_ref()))?;
+        }
+        Ok(())
+    }
+
+    /// Returns an update (encoded using lib0 v1 encoding) containing all changes made to
+    /// a document with given `name` since its version has been tagged with `tag` (see:
+    /// [Self::tag_version]), including its pending updates. Returns `None` if either document or
+    /// tag were not found.
+    ///
+    /// This feature requires only the read capabilities from the database transaction.
+    fn diff_since_tag<K1: AsRef<[u8]> + ?Sized, K2: AsRef<[u8]> + ?Sized>(
+        &self,
+        name: &K1,
+        tag: &K2,
+    ) -> Result<Option<Vec<u8>>, Error> {
+        if let Some(tag) = self.get_tag(name, tag)? {
+            let doc = Doc::with_options(self.options().new_doc_options());
+            if self.load_doc(name, &mut doc.transact_mut())? {
+                return Ok(Some(doc.transact().encode_diff_v1(&tag.state_vector)));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Returns an update (encoded using lib0 v1 encoding) containing all changes made to
+    /// a document with given `name` between two of its tagged versions (see: [Self::tag_version]).
+    /// Applying it to a document state as of `from_tag` version, brings it to the state as of
+    /// `to_tag` version. Returns `None` if either document or any of the tags were not found.
+    ///
+    /// Returns [StoreError::MissingSnapshot] error if `to_tag` version has been tagged without
+    /// a snapshot, as document state as of that version can't be recreated. Just like in case of
+    /// [Self::load_snapshot], document must have been flushed without garbage collecting deleted
+    /// content.
+    ///
+    /// This feature requires only the read capabilities from the database transaction.
+    fn diff_snapshots<K1, K2, K3>(
+        &self,
+        name: &K1,
+        from_tag: &K2,
+        to_tag: &K3,
+    ) -> Result<Option<Vec<u8>>, Error>
+    where
+        K1: AsRef<[u8]> + ?Sized,
+        K2: AsRef<[u8]> + ?Sized,
+        K3: AsRef<[u8]> + ?Sized,
+    {
+        let (from, to) = match (self.get_tag(name, from_tag)?, self.get_tag(name, to_tag)?) {
+            (Some(from), Some(to)) => (from, to),
+            _ => return Ok(None),
+        };
+        let snapshot = match to.snapshot {
+            Some(snapshot) => snapshot,
+            None => {
+                let tag = String::from_utf8_lossy(to_tag.as_ref()).into_owned();
+                return Err(StoreError::MissingSnapshot(tag).into());
+            }
+        };
+        let options = yrs::Options {
+            skip_gc: true,
+            ..self.options().new_doc_options()
+        };
+        let doc = Doc::with_options(options.clone());
+        if !self.load_doc(name, &mut doc.transact_mut())? {
+            return Ok(None);
+        }
+        // recreate document as of `to_tag` version, then diff it with `from_tag` version
+        let update = encode_state_from_snapshot(&doc, &snapshot)?;
+        let to_doc = Doc::with_options(options);
+        to_doc
+            .transact_mut()
+            .apply_update(Update::decode_v1(&update)?)?;
+        let diff = to_doc.transact().encode_diff_v1(&from.state_vector);
+        Ok(Some(diff))
+    }
+
+    /// Persists the current state of a `subdoc` under the document with given `parent_name`.
+    /// Subdocuments are identified by their GUIDs, and their state is stored separately from the
+    /// state of the parent document, which only keeps a reference to them. Removing the parent
+    /// document via [Self::clear_doc] removes all of its subdocuments as well.
+    ///
+    /// This feature requires write capabilities from the database transaction.
+    fn insert_subdoc<K: AsRef<[u8]> + ?Sized>(
+        &self,
+        parent_name: &K,
+        subdoc: &Doc,
+    ) -> Result<(), Error> {
+        let oid = get_or_create_oid(self, parent_name.as_ref())?;
+        let guid = subdoc.guid();
+        let doc_state = subdoc
+            .transact()
+            .encode_state_as_update_v1(&StateVector::default());
+        let key = key_subdoc(oid, guid.as_bytes());
+        self.upsert(&key, &encode_value(self, &doc_state)?)?;
+        Ok(())
+    }
+
+    /// Loads the state of a subdocument identified by its `guid`, stored under the document with
+    /// given `parent_name`, using provided [TransactionMut] of that subdocument. Returns `false`
+    /// if subdocument state was not found.
+    ///
+    /// Subdocuments of documents loaded via [Self::load_doc] are loaded automatically, once they
+    /// request it. This method can be used to load them explicitly instead.
+    ///
+    /// This feature requires only the read capabilities from the database transaction.
+    fn load_subdoc<K: AsRef<[u8]> + ?Sized>(
+        &self,
+        parent_name: &K,
+        guid: &str,
+        txn: &mut TransactionMut,
+    ) -> Result<bool, Error> {
+        if let Some(oid) = get_oid(self, parent_name.as_ref())? {
+            load_subdoc(self, oid, guid, txn)
+        } else {
+            Ok(false)
+        }
+    }
+
+    /// Loads the state of all subdocuments referenced by a parent document, which has been
+    /// previously loaded (i.e. via [Self::load_doc]) and can be read using `parent_txn`. Returns
+    /// a number of subdocuments, which state has been found and loaded.
+    ///
+    /// This feature requires only the read capabilities from the database transaction.
+    fn load_subdocs<K: AsRef<[u8]> + ?Sized, T: ReadTxn>(
+        &self,
+        parent_name: &K,
+        parent_txn: &T,
+    ) -> Result<usize, Error> {
+        let mut loaded = 0;
+        if let Some(oid) = get_oid(self, parent_name.as_ref())? {
+            for subdoc in parent_txn.subdocs() {
+                if load_subdoc(self, oid, &subdoc.guid(), &mut subdoc.transact_mut())? {
+                    loaded += 1;
+                }
+            }
+        }
+        Ok(loaded)
+    }
+
+    /// Removes the state of a subdocument identified by its `guid`, stored under the document with
+    /// given `parent_name`.
+    ///
+    /// This feature requires write capabilities from the database transaction.
+    fn remove_subdoc<K: AsRef<[u8]> + ?Sized>(
+        &self,
+        parent_name: &K,
+        guid: &str,
+    ) -> Result<(), Error> {
+        if let Some(oid) = get_oid(self, parent_name.as_ref())? {
+            let key = key_subdoc(oid, guid.as_bytes());
+            self.remove(&key)?;
+        }
+        Ok(())
+    }
+
+    /// Returns an iterator over GUIDs of all subdocuments stored for a given document.
+    fn iter_subdocs<K: AsRef<[u8]> + ?Sized>(
+        &self,
+        parent_name: &K,
+    ) -> Result<SubdocIter<Self::Cursor, Self::Entry>, Error> {
+        if let Some(oid) = get_oid(self, parent_name.as_ref())? {
+            let start = key_subdoc_start(oid).to_vec();
+            let end = key_subdoc_end(oid).to_vec();
+            let cursor = self.iter_range(&start, &end)?;
+            Ok(SubdocIter(Some((cursor, start, end))))
+        } else {
+            Ok(SubdocIter(None))
+        }
+    }
+
+    /// Returns an iterator over all updates, which have been merged into the state of a document
+    /// with given `name` while [CompactionPolicy::retain_history] was enabled. Updates are ordered
+    /// by their history sequence numbers, which (unlike sequence numbers returned by
+    /// [Self::push_update]) are never reset.
+    ///
+    /// This feature requires only the read capabilities from the database transaction.
+    fn iter_history<K: AsRef<[u8]> + ?Sized>(
+        &self,
+        doc_name: &K,
+    ) -> Result<HistoryIter<Self::Cursor, Self::Entry>, Error> {
+        if let Some(oid) = get_oid(self, doc_name.as_ref())? {
+            let start = key_history(oid, 0);
+            let end = key_history_v2(oid, u32::MAX);
+            let cursor = self.iter_range(&start, &end)?;
+            Ok(HistoryIter(Some(cursor), self.options().value_codec()))
+        } else {
+            Ok(HistoryIter(None, None))
+        }
+    }
+
+    /// Removes all history updates of a document with given `name`, which sequence numbers are
+    /// lower than or equal to `up_to_seq`.
+    ///
+    /// This feature requires write capabilities from the database transaction.
+    fn trim_history<K: AsRef<[u8]> + ?Sized>(&self, name: &K, up_to_seq: u32) -> Result<(), Error> {
+        if let Some(oid) = get_oid(self, name.as_ref())? {
+            let start = key_history(oid, 0);
+            let end = key_history_v2(oid, up_to_seq);
+            self.remove_range(&start, &end)?;
+        }
+        Ok(())
+    }
+}
+
+fn get_oid<'a, DB: DocOps<'a> + ?Sized>(db: &DB, name: &[u8]) -> Result<Option<OID>, Error>
+where
+    Error: From<<DB as KVStore<'a>>::Error>,
+{
+    let key = key_oid(name);
+    let value = db.get(&key)?;
+    if let Some(value) = value {
+        Ok(Some(oid_from_bytes(value.as_ref())))
+    } else {
+        Ok(None)
+    }
+}
+
+fn get_or_create_oid<'a, DB: DocOps<'a> + ?Sized>(db: &DB, name: &[u8]) -> Result<OID, Error>
+where
+    Error: From<<DB as KVStore<'a>>::Error>,
+{
+    if let Some(oid) = get_oid(db, name)? {
+        Ok(oid)
+    } else {
+        db.lock_oid_allocation()?;
+        if let Some(oid) = get_oid(db, name)? {
+            // OID has been allocated by concurrent writer in the meantime
+            return Ok(oid);
+        }
+        let mut batch = db.batch();
+        let new_oid = allocate_oid(db, &mut batch, name)?;
+        db.commit_batch(batch)?;
+        Ok(new_oid)
+    }
+}
+
+/// Allocates a new OID for a document with a given `name` using [StoreOptions::oid_allocation]
+/// strategy and records its OID mapping entry in a given `batch`. Caller is responsible for holding
+/// [DocOps::lock_oid_allocation] and for checking that the document didn't exist before.
+fn allocate_oid<'a, DB: DocOps<'a>>(
+    db: &DB,
+    batch: &mut WriteBatch,
+    name: &[u8],
+) -> Result<OID, Error>
+where
+    Error: From<<DB as KVStore<'a>>::Error>,
+{
+    let new_oid = match db.options().oid_allocation {
+        OidAllocation::Counter => {
+            let new_oid = last_allocated_oid(db)?
+                .checked_add(1)
+                .ok_or(StoreError::OidSpaceExhausted)?;
+            batch.upsert(&KEY_OID_COUNTER, new_oid.to_be_bytes().as_ref());
+            new_oid
+        }
+        #[cfg(feature = "oid64")]
+        OidAllocation::Random => random_oid(db)?,
+    };
+    let key = key_oid(name);
+    batch.upsert(&key, new_oid.to_be_bytes().as_ref());
+    Ok(new_oid)
+}
+
+/// Decodes an [OID] stored under a given `key`, returning [StoreError::Corrupted] if `value` has
+/// unexpected length.
+pub(crate) fn decode_oid(key: &[u8], value: &[u8]) -> Result<OID, Error> {
+    match value.try_into() {
+        Ok(bytes) => Ok(OID::from_be_bytes(bytes)),
+        Err(_) => Err(StoreError::Corrupted { key: key.to_vec() }.into()),
+    }
+}
+
+/// Returns the last OID allocated using [OidAllocation::Counter].
+fn last_allocated_oid<'a, DB: DocOps<'a>>(db: &DB) -> Result<OID, Error>
+where
+    Error: From<<DB as KVStore<'a>>::Error>,
+{
+    if let Some(value) = db.get(&KEY_OID_COUNTER)? {
+        return decode_oid(&KEY_OID_COUNTER, value.as_ref());
+    }
+    // counter is missing in databases which have not used it before: since OID entries are
+    // ordered by document names, not by their OIDs, all of them must be checked
+    let start = Key::from_const([V1, KEYSPACE_OID]);
+    let end = Key::from_const([V1, KEYSPACE_DOC]);
+    let mut last_oid = 0;
+    for e in db.iter_range(&start, &end)? {
+        last_oid = last_oid.max(decode_oid(e.key(), e.value())?);
+    }
+    Ok(last_oid)
+}
+
+/// Picks a random OID, which is neither mapped to any document name nor used by any existing
+/// document entries.
+#[cfg(feature = "oid64")]
+fn random_oid<'a, DB: DocOps<'a>>(db: &DB) -> Result<OID, Error>
+where
+    Error: From<<DB as KVStore<'a>>::Error>,
+{
+    loop {
+        let mut bytes = [0u8; keys::OID_LEN];
+        getrandom::getrandom(&mut bytes)?;
+        let oid = OID::from_be_bytes(bytes);
+        if oid == 0 {
+            continue;
+        }
+        let start = key_doc_start(oid);
+        let end = key_doc_end(oid);
+        if db.iter_range(&start, &end)?.next().is_some() {
+            continue;
+        }
+        // OID mappings are committed before any document entries are written, so the document
+        // keyspace alone is not enough to tell if an OID is free
+        let start = Key::from_const([V1, KEYSPACE_OID]);
+        let end = Key::from_const([V1, KEYSPACE_DOC]);
+        let mut mapped = false;
+        for e in db.iter_range(&start, &end)? {
+            if decode_oid(e.key(), e.value())? == oid {
+                mapped = true;
+                break;
+            }
+        }
+        if !mapped {
+            return Ok(oid);
+        }
+    }
+}
+
+/// Migrates database from a given schema `version` to the next one.
+fn migrate_step<'a, DB: DocOps<'a>>(_db: &DB, version: u8) -> Result<(), Error>
+where
+    Error: From<<DB as KVStore<'a>>::Error>,
+{
+    // steps are added here whenever a new schema version is introduced: there are none yet, as
+    // V1 is the only schema version
+    Err(StoreError::UnsupportedMigration {
+        from: version,
+        to: version + 1,
+    }
+    .into())
+}
+
+fn get_expiry<'a, DB: DocOps<'a>>(db: &DB, oid: OID) -> Result<Option<u64>, Error>
+where
+    Error: From<<DB as KVStore<'a>>::Error>,
+{
+    if let Some(value) = db.get(&key_expiry(oid))? {
+        let bytes: [u8; 8] = value.as_ref().try_into().unwrap();
+        Ok(Some(u64::from_be_bytes(bytes)))
+    } else {
+        Ok(None)
+    }
+}
+
+/// Returns a cached OID and last update clock of a document with a given `name`, allocating a new
+/// OID if necessary.
+fn resolve_doc<'a, 'd, DB: DocOps<'a>>(
+    db: &DB,
+    docs: &'d mut HashMap<Vec<u8>, (OID, Option<u32>)>,
+    name: &[u8],
+) -> Result<&'d mut (OID, Option<u32>), Error>
+where
+    Error: From<<DB as KVStore<'a>>::Error>,
+{
+    if !docs.contains_key(name) {
+        let oid = get_or_create_oid(db, name)?;
+        docs.insert(name.to_vec(), (oid, None));
+    }
+    Ok(docs.get_mut(name).unwrap())
+}
+
+fn next_clock<'a, DB: DocOps<'a>>(db: &DB, oid: OID) -> Result<u32, Error>
+where
+    Error: From<<DB as KVStore<'a>>::Error>,
+{
+    reserve_clocks(db, oid, 1)
+}
+
+/// Returns the first of `count` consecutive update clocks, which can be assigned to new updates of
+/// a document with a given `oid`. If there are not enough clocks left before reaching [u32::MAX],
+/// pending updates are flushed first, which makes clocks start over.
+fn reserve_clocks<'a, DB: DocOps<'a>>(db: &DB, oid: OID, count: u32) -> Result<u32, Error>
+where
+    Error: From<<DB as KVStore<'a>>::Error>,
+{
+    let mut last = last_clock(db, oid)?;
+    if u32::MAX - last < count.max(1) {
+        flush_doc(db, oid, db.options().new_doc_options(), None)?;
+        last = last_clock(db, oid)?;
+        if u32::MAX - last < count.max(1) {
+            return Err(StoreError::UpdateLogFull { oid }.into());
+        }
+    }
+    Ok(last + 1)
+}
+
+/// Returns a clock of the last pending update of a document with a given `oid` or 0 if there are
+/// no pending updates.
+fn last_clock<'a, DB: DocOps<'a>>(db: &DB, oid: OID) -> Result<u32, Error>
+where
+    Error: From<<DB as KVStore<'a>>::Error>,
+{
+    // metadata keys are placed right after update keys
+    let end = key_meta_start(oid);
+    match db.peek_back(&end)? {
+        // peek back may return a key of a different kind (i.e. state vector key), make sure
+        // that it's an update key of the same document
+        Some(e)
+            if e.key().len() == SUB_KEY_POS + 6
+                && e.key().starts_with(&end[..SUB_KEY_POS])
+                && e.key()[SUB_KEY_POS] == SUB_UPDATE =>
+        {
+            Ok(update_clock(e.key()))
+        }
+        _ => Ok(0),
+    }
+}
+
+/// Returns the state vector of a document with a given `oid`, including the changes of its
+/// pending updates. Stored state vector is used as long as it's up to date (see:
+/// [state_vector_up_to_date]).
+fn current_state_vector<'a, DB: DocOps<'a>>(db: &DB, oid: OID) -> Result<StateVector, Error>
+where
+    Error: From<<DB as KVStore<'a>>::Error>,
+{
+    if state_vector_up_to_date(db, oid)? {
+        if let Some(sv) = db.get(&key_state_vector(oid))? {
+            return Ok(StateVector::decode_v1(sv.as_ref())?);
+        }
+    }
+    match merge_stored(db, oid, false)? {
+        Some(merged) => {
+            let sv = yrs::encode_state_vector_from_update_v1(&merged)?;
+            Ok(StateVector::decode_v1(&sv)?)
+        }
+        None => Ok(StateVector::default()),
+    }
+}
+
+/// Returns a sequence number of a pending update identical to a given `update`, if it was pushed
+/// within the deduplication window of a document with a given `oid` (see:
+/// [StoreOptions::update_dedup_window]). Deduplication window is stored as a list of
+/// `{hash:4}{clock:4}` entries, ordered from the oldest to the newest one.
+fn find_duplicate<'a, DB: DocOps<'a>>(
+    db: &DB,
+    oid: OID,
+    update: &[u8],
+    v2: bool,
+) -> Result<Option<u32>, Error>
+where
+    Error: From<<DB as KVStore<'a>>::Error>,
+{
+    if db.options().update_dedup_window == 0 {
+        return Ok(None);
+    }
+    if let Some(window) = db.get(&key_dedup(oid))? {
+        let codec = db.options().value_codec();
+        let hash = crc32fast::hash(update).to_be_bytes();
+        for entry in window.as_ref().chunks_exact(8).rev() {
+            if entry[..4] != hash {
+                continue;
+            }
+            // hashes may collide and clocks start over after flush, compare the update itself
+            let clock = u32::from_be_bytes(entry[4..].try_into().unwrap());
+            let key = if v2 {
+                key_update_v2(oid, clock)
+            } else {
+                key_update(oid, clock)
+            };
+            if let Some(value) = db.get(&key)? {
+                if decode_value(codec.as_deref(), &key, value.as_ref())?.as_ref() == update {
+                    return Ok(Some(clock));
+                }
+            }
+        }
+    }
+    Ok(None)
+}
+
+/// Appends `(update, clock)` pairs of updates stored under given clocks to the deduplication
+/// window of a document with a given `oid`, evicting the oldest entries which no longer fit into
+/// it.
+fn remember_updates<'a, 'u, DB, I>(
+    db: &DB,
+    batch: &mut WriteBatch,
+    oid: OID,
+    updates: I,
+) -> Result<(), Error>
+where
+    DB: DocOps<'a>,
+    I: IntoIterator<Item = (&'u [u8], u32)>,
+    Error: From<<DB as KVStore<'a>>::Error>,
+{
+    let window = db.options().update_dedup_window;
+    if window == 0 {
+        return Ok(());
+    }
+    let key = key_dedup(oid);
+    let mut entries = match db.get(&key)? {
+        Some(entries) => entries.as_ref().to_vec(),
+        None => Vec::with_capacity(8),
+    };
+    for (update, clock) in updates {
+        entries.extend_from_slice(&crc32fast::hash(update).to_be_bytes());
+        entries.extend_from_slice(&clock.to_be_bytes());
+    }
+    let max_len = window.saturating_mul(8);
+    if entries.len() > max_len {
+        entries.drain(..entries.len() - max_len);
+    }
+    batch.upsert(&key, &entries);
+    Ok(())
+}
+
+/// Document entries gathered by [DocOps::verify_store].
+struct VerifiedDoc {
+    oid: OID,
+    /// Number of entries of an orphaned document.
+    entries: usize,
+    /// State vector of a decoded document state.
+    state_vector: Option<StateVector>,
+    /// State vector stored next to the document state.
+    stored_state_vector: Option<StateVector>,
+    /// Whether stored state vector includes changes of pending updates (see:
+    /// [StoreOptions::track_state_vector]).
+    tracked_state_vector: bool,
+}
+
+impl VerifiedDoc {
+    fn new(oid: OID) -> Self {
+        VerifiedDoc {
+            oid,
+            entries: 0,
+            state_vector: None,
+            stored_state_vector: None,
+            tracked_state_vector: false,
+        }
+    }
+
+    fn finish(self, names: &HashMap<OID, Vec<Box<[u8]>>>, report: &mut VerificationReport) {
+        match names.get(&self.oid) {
+            None => report.orphaned.push((self.oid, self.entries)),
+            Some(names) => {
+                // undecodable document states are reported separately
+                if let (Some(sv), false) = (self.state_vector, self.tracked_state_vector) {
+                    if self.stored_state_vector.as_ref() != Some(&sv) {
+                        report.state_vector_mismatches.extend(names.iter().cloned());
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Checks if a document with a given `oid` has any pending updates.
+fn has_updates<'a, DB: DocOps<'a>>(db: &DB, oid: OID) -> Result<bool, Error>
+where
+    Error: From<<DB as KVStore<'a>>::Error>,
+{
+    let start = key_update(oid, 0);
+    let end = key_update(oid, u32::MAX);
+    Ok(db.contains_range(&start, &end)?)
+}
+
+/// Checks if the stored state vector of a document with a given `oid` covers all of its pending
+/// updates: either there are none or all of them have been merged into it (see:
+/// [StoreOptions::track_state_vector]).
+fn state_vector_up_to_date<'a, DB: DocOps<'a>>(db: &DB, oid: OID) -> Result<bool, Error>
+where
+    Error: From<<DB as KVStore<'a>>::Error>,
+{
+    if !has_updates(db, oid)? {
+        return Ok(true);
+    }
+    let clock_key = key_state_vector_clock(oid);
+    match db.get(&clock_key)? {
+        Some(clock) => Ok(decode_clock(&clock_key, clock.as_ref())? == last_clock(db, oid)?),
+        None => Ok(false),
+    }
+}
+
+/// Decodes a `{clock:4}` value stored under a given `key`.
+///
+/// Returns [StoreError::Corrupted] if value has a different length.
+fn decode_clock(key: &[u8], value: &[u8]) -> Result<u32, Error> {
+    match value.try_into() {
+        Ok(clock) => Ok(u32::from_be_bytes(clock)),
+        Err(_) => Err(StoreError::Corrupted { key: key.to_vec() }.into()),
+    }
+}
+
+/// Merges insertions of pending `updates` (together with their clocks and encoding flags), stored
+/// under consecutive clocks, into the stored state vector of a document with a given `oid` as a
+/// part of a write `batch`, if state vector tracking has been enabled (see:
+/// [StoreOptions::track_state_vector]). It's only possible while the stored state vector covers
+/// all preceding pending updates. Merging stops at the first update, which depends on changes
+/// unknown to the state vector, so that it never claims changes missing from the document.
+fn track_state_vector<'a, 'u, DB, I>(
+    db: &DB,
+    batch: &mut WriteBatch,
+    oid: OID,
+    updates: I,
+) -> Result<(), Error>
+where
+    DB: DocOps<'a>,
+    I: IntoIterator<Item = (&'u [u8], u32, bool)>,
+    Error: From<<DB as KVStore<'a>>::Error>,
+{
+    if !db.options().track_state_vector {
+        return Ok(());
+    }
+    let mut updates = updates.into_iter().peekable();
+    let first = match updates.peek() {
+        Some((_, clock, _)) => *clock,
+        None => return Ok(()),
+    };
+    let clock_key = key_state_vector_clock(oid);
+    let tracked = match db.get(&clock_key)? {
+        Some(clock) => decode_clock(&clock_key, clock.as_ref())? == first - 1,
+        // tracking starts once all pending updates have been flushed
+        None => !has_updates(db, oid)?,
+    };
+    if !tracked {
+        return Ok(());
+    }
+    let sv_key = key_state_vector(oid);
+    let mut sv = match db.get(&sv_key)? {
+        Some(sv) => StateVector::decode_v1(sv.as_ref())?,
+        None => StateVector::default(),
+    };
+    let mut merged = None;
+    for (update, clock, v2) in updates {
+        let update = if v2 {
+            Update::decode_v2(update)?
+        } else {
+            Update::decode_v1(update)?
+        };
+        match merge_insertions(&sv, &update) {
+            Some(next) => sv = next,
+            None => break,
+        }
+        merged = Some(clock);
+    }
+    if let Some(clock) = merged {
+        batch.upsert(&sv_key, &sv.encode_v1());
+        batch.upsert(&clock_key, &clock.to_be_bytes());
+    }
+    Ok(())
+}
+
+/// Returns a given state vector `sv` advanced by all blocks inserted by an `update` (including
+/// deleted ones) or `None` if update contains blocks, which are not contiguous with clocks
+/// described by the state vector.
+fn merge_insertions(sv: &StateVector, update: &Update) -> Option<StateVector> {
+    let mut sv = sv.clone();
+    for (client, ranges) in update.insertions(true).iter() {
+        let mut clock = sv.get(client);
+        for range in ranges.iter() {
+            if range.start > clock {
+                return None;
+            }
+            clock = clock.max(range.end);
+        }
+        sv.set_max(*client, clock);
+    }
+    Some(sv)
+}
+
+fn compact_if_needed<'a, DB: DocOps<'a>>(db: &DB, oid: OID) -> Result<(), Error>
+where
+    Error: From<<DB as KVStore<'a>>::Error>,
+{
+    let policy = db.options().compaction_policy;
+    if policy.max_pending_updates.is_none() && policy.max_pending_bytes.is_none() {
+        return Ok(());
+    }
+    let summary = pending_summary(db, oid)?;
+    let exceeded = policy
+        .max_pending_updates
+        .is_some_and(|max| summary.count > max)
+        || policy
+            .max_pending_bytes
+            .is_some_and(|max| summary.bytes > max);
+    if exceeded {
+        flush_doc(db, oid, db.options().new_doc_options(), None)?;
+    }
+    Ok(())
+}
+
+/// Number and total size of pending updates of a document, which have been stored under clocks up
+/// to a given `clock`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+struct PendingSummary {
+    clock: u32,
+    count: u32,
+    bytes: u64,
+}
+
+impl PendingSummary {
+    fn encode(&self) -> [u8; 16] {
+        let mut buf = [0u8; 16];
+        buf[..4].copy_from_slice(&self.clock.to_be_bytes());
+        buf[4..8].copy_from_slice(&self.count.to_be_bytes());
+        buf[8..].copy_from_slice(&self.bytes.to_be_bytes());
+        buf
+    }
+
+    fn decode(key: &[u8], value: &[u8]) -> Result<Self, Error> {
+        if value.len() != 16 {
+            return Err(StoreError::Corrupted { key: key.to_vec() }.into());
+        }
+        Ok(PendingSummary {
+            clock: u32::from_be_bytes(value[..4].try_into().unwrap()),
+            count: u32::from_be_bytes(value[4..8].try_into().unwrap()),
+            bytes: u64::from_be_bytes(value[8..].try_into().unwrap()),
+        })
+    }
+}
+
+/// Checks if pending updates of documents need to be summarized (see: [pending_summary]), which
+/// is the case when [CompactionPolicy] puts a limit on them.
+fn summarize_pending<'a, DB: DocOps<'a>>(db: &DB) -> bool
+where
+    Error: From<<DB as KVStore<'a>>::Error>,
+{
+    let policy = db.options().compaction_policy;
+    policy.max_pending_updates.is_some() || policy.max_pending_bytes.is_some()
+}
+
+/// Returns a number and a total size of all pending updates of a document with a given `oid`.
+/// Stored summary is used as long as it covers the last pending update. Otherwise (i.e. updates
+/// have been pushed while no limits were configured) pending updates are read once and the
+/// summary is stored again, so that it can be kept up to date by [track_pending].
+fn pending_summary<'a, DB: DocOps<'a>>(db: &DB, oid: OID) -> Result<PendingSummary, Error>
+where
+    Error: From<<DB as KVStore<'a>>::Error>,
+{
+    let key = key_pending_summary(oid);
+    let last = next_clock(db, oid)? - 1;
+    if let Some(value) = db.get(&key)? {
+        let summary = PendingSummary::decode(&key, value.as_ref())?;
+        if summary.clock == last {
+            return Ok(summary);
+        }
+    }
+    let mut summary = PendingSummary {
+        clock: last,
+        ..PendingSummary::default()
+    };
+    let start = key_update(oid, 0);
+    let end = key_update(oid, u32::MAX);
+    for e in db.iter_range(&start, &end)? {
+        summary.count += 1;
+        summary.bytes += e.value().len() as u64;
+    }
+    db.upsert(&key, &summary.encode())?;
+    Ok(summary)
+}
+
+/// Adds `count` pending updates of a total size of `bytes`, stored under consecutive clocks up to
+/// `last`, to the summary of pending updates of a document with a given `oid` (see:
+/// [pending_summary]) as a part of a write `batch`. It's only possible while the stored summary
+/// covers all preceding pending updates, otherwise it's rebuilt once it's needed.
+fn track_pending<'a, DB: DocOps<'a>>(
+    db: &DB,
+    batch: &mut WriteBatch,
+    oid: OID,
+    last: u32,
+    count: u32,
+    bytes: u64,
+) -> Result<(), Error>
+where
+    Error: From<<DB as KVStore<'a>>::Error>,
+{
+    if !summarize_pending(db) {
+        return Ok(());
+    }
+    let key = key_pending_summary(oid);
+    let mut summary = match db.get(&key)? {
+        Some(value) => PendingSummary::decode(&key, value.as_ref())?,
+        // summary starts over once all pending updates have been flushed
+        None if last == count => PendingSummary::default(),
+        None => return Ok(()),
+    };
+    if summary.clock != last - count {
+        return Ok(());
+    }
+    summary.clock = last;
+    summary.count += count;
+    summary.bytes += bytes;
+    batch.upsert(&key, &summary.encode());
+    Ok(())
+}
+
+/// Origin used to register subdocument observer responsible for loading subdocuments of documents
+/// loaded via [DocOps::load_doc].
+const SUBDOC_LOADER: &str = "yrs-kvstore/subdocs";
+
+/// Makes subdocuments of a document with a given `oid`, which is being loaded using provided
+/// `txn`, load their stored state once they are requested, i.e. because they have
+/// [Doc::auto_load] option enabled or because [Doc::load] has been called on them.
+fn load_subdocs_lazily<'a, DB: DocOps<'a>>(
+    db: &DB,
+    oid: OID,
+    txn: &mut TransactionMut,
+) -> Result<(), Error>
+where
+    Error: From<<DB as KVStore<'a>>::Error>,
+{
+    let start = key_subdoc_start(oid);
+    let end = key_subdoc_end(oid);
+    let codec = db.options().value_codec();
+    let mut states = HashMap::new();
+    for e in db.iter_range(&start, &end)? {
+        let guid = String::from_utf8_lossy(subdoc_guid(e.key())).into_owned();
+        let state = decode_value(codec.as_deref(), e.key(), e.value())?;
+        states.insert(guid, Update::decode_v1(&state)?);
+    }
+    if !states.is_empty() {
+        txn.observe_subdocs(SUBDOC_LOADER, move |_, e| {
+            for subdoc in e.loaded() {
+                if let Some(update) = states.remove(subdoc.guid().as_ref()) {
+                    // stored state has been decoded already, there's no way to report errors
+                    // of its integration from within subdocument observer
+                    let _ = subdoc.transact_mut().apply_update(update);
+                }
+            }
+        });
+    }
+    Ok(())
+}
+
+/// Returns the total number of bytes of all pending updates stored for a document with a given
+/// `oid`.
+fn pending_bytes<'a, DB: DocOps<'a>>(db: &DB, oid: OID) -> Result<u64, Error>
+where
+    Error: From<<DB as KVStore<'a>>::Error>,
+{
+    let start = key_update(oid, 0);
+    let end = key_update(oid, u32::MAX);
+    let mut total = 0u64;
+    for e in db.iter_range(&start, &end)? {
+        total += e.value().len() as u64;
+    }
+    Ok(total)
+}
+
+/// Checks if a given `update` doesn't exceed [UpdateLimits::max_update_bytes].
+fn check_update_size<'a, DB: DocOps<'a>>(db: &DB, update: &[u8]) -> Result<(), Error>
+where
+    Error: From<<DB as KVStore<'a>>::Error>,
+{
+    if let Some(max) = db.options().update_limits.max_update_bytes {
+        if update.len() > max {
+            let size = update.len();
+            return Err(StoreError::UpdateTooLarge { size, max }.into());
+        }
+    }
+    Ok(())
+}
+
+/// Passes a given `update` (using lib0 v2 encoding if `v2` is set) about to be persisted for
+/// a document with a given `name` to [StoreOptions::update_validator], if there's any.
+fn validate_update<'a, DB: DocOps<'a>>(
+    db: &DB,
+    name: &[u8],
+    update: &[u8],
+    v2: bool,
+) -> Result<(), Error>
+where
+    Error: From<<DB as KVStore<'a>>::Error>,
+{
+    if let Some(validator) = &db.options().update_validator {
+        let update = if v2 {
+            Update::decode_v2(update)?
+        } else {
+            Update::decode_v1(update)?
+        };
+        validator.validate(name, &update).with_doc(name)?;
+    }
+    Ok(())
+}
+
+/// Makes room for `incoming` bytes of updates about to be pushed to a document with a given `oid`,
+/// so that its pending updates don't exceed [UpdateLimits::max_pending_bytes]. Depending on
+/// [UpdateLimits::flush_on_overflow], pending updates are either flushed or the push is rejected.
+fn reserve_pending_bytes<'a, DB: DocOps<'a>>(db: &DB, oid: OID, incoming: u64) -> Result<(), Error>
+where
+    Error: From<<DB as KVStore<'a>>::Error>,
+{
+    let limits = db.options().update_limits;
+    if let Some(max) = limits.max_pending_bytes {
+        if incoming > max {
+            // flushing wouldn't help, pushed updates alone exceed the limit
+            return Err(StoreError::UpdateLogTooLarge { oid, max }.into());
+        }
+        if pending_bytes(db, oid)? + incoming > max {
+            if limits.flush_on_overflow {
+                flush_doc(db, oid, db.options().new_doc_options(), None)?;
+            } else {
+                return Err(StoreError::UpdateLogTooLarge { oid, max }.into());
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Appends given document changes to the change log as a part of a write `batch`, if change log
+/// has been enabled (see: [StoreOptions::changelog]).
+fn log_changes<'a, 'n, DB, I>(db: &DB, batch: &mut WriteBatch, changes: I) -> Result<(), Error>
+where
+    DB: DocOps<'a>,
+    I: IntoIterator<Item = (&'n [u8], OID, ChangeKind)>,
+    Error: From<<DB as KVStore<'a>>::Error>,
+{
+    if !db.options().changelog {
+        return Ok(());
+    }
+    let mut seq = last_change_seq(db)?;
+    for (name, oid, kind) in changes {
+        seq += 1;
+        // change log entry: {kind:1}{oid}{name}
+        let mut entry = Vec::with_capacity(1 + OID_LEN + name.len());
+        entry.push(kind as u8);
+        entry.extend_from_slice(&oid.to_be_bytes());
+        entry.extend_from_slice(name);
+        batch.upsert(&key_changelog(seq), &entry);
+    }
+    Ok(())
+}
+
+fn load_subdoc<'a, DB: DocOps<'a>>(
+    db: &DB,
+    oid: OID,
+    guid: &str,
+    txn: &mut TransactionMut,
+) -> Result<bool, Error>
+where
+    Error: From<<DB as KVStore<'a>>::Error>,
+{
+    let key = key_subdoc(oid, guid.as_bytes());
+    if let Some(data) = db.get(&key)? {
+        let codec = db.options().value_codec();
+        let update = Update::decode_v1(&decode_value(codec.as_deref(), &key, data.as_ref())?)?;
+        txn.apply_update(update)?;
+        Ok(true)
+    } else {
+        Ok(false)
+    }
+}
+
+/// Returns a sequence number of the last change log entry or 0 if change log is empty.
+fn last_change_seq<'a, DB: DocOps<'a>>(db: &DB) -> Result<u64, Error>
+where
+    Error: From<<DB as KVStore<'a>>::Error>,
+{
+    let end = Key::from_const([V1, KEYSPACE_CHANGELOG + 1]);
+    match db.peek_back(&end)? {
+        Some(e) if e.key().len() == 10 && e.key()[..2] == [V1, KEYSPACE_CHANGELOG] => {
+            Ok(changelog_seq(e.key()))
+        }
+        _ => Ok(0),
+    }
+}
+
+fn publish_update<'a, DB: DocOps<'a>>(
+    db: &DB,
+    name: &[u8],
+    update: &[u8],
+    seq: Option<u32>,
+    v2: bool,
+) where
+    Error: From<<DB as KVStore<'a>>::Error>,
+{
+    if let Some(observers) = db.update_observers() {
+        observers.publish(&UpdateEvent {
+            name,
+            update,
+            seq,
+            v2,
+        });
+    }
+    if let Some(hook) = &db.options().write_hook {
+        let decoded = if v2 {
+            Update::decode_v2(update)
+        } else {
+            Update::decode_v1(update)
+        };
+        // update has already been persisted, malformed ones simply have nothing to summarize
+        if let Ok(decoded) = decoded {
+            let kind = if seq.is_some() {
+                ChangeKind::Update
+            } else {
+                ChangeKind::Insert
+            };
+            hook.on_after_write(name, &ChangeSummary::new(kind, seq, &decoded));
+        }
+    }
+}
+
+/// Notifies [StoreOptions::write_hook] (if there's any) that a document with a given `name` has
+/// been removed.
+fn notify_cleared<'a, DB: DocOps<'a>>(db: &DB, name: &[u8])
+where
+    Error: From<<DB as KVStore<'a>>::Error>,
+{
+    if let Some(hook) = &db.options().write_hook {
+        hook.on_after_write(name, &ChangeSummary::cleared());
+    }
+}
+
+/// Loads a document with a given `name` (see: [DocOps::load_doc]), reporting its metrics.
+fn load_named_doc<'a, DB: DocOps<'a>>(
+    db: &DB,
+    name: &[u8],
+    txn: &mut TransactionMut,
+    cancel: Option<&Cancel>,
+) -> Result<bool, Error>
+where
+    Error: From<<DB as KVStore<'a>>::Error>,
+{
+    let start = Instant::now();
+    if let Some(oid) = get_oid(db, name)? {
+        trace_record!("oid", oid);
+        let loaded = load_doc(db, oid, txn, cancel)
+            .with_oid(oid)
+            .with_doc(name)?;
+        load_subdocs_lazily(db, oid, txn)
+            .with_oid(oid)
+            .with_doc(name)?;
+        trace_record!("updates", loaded & !(1 << 31));
+        if let Some(metrics) = db.metrics() {
+            if loaded != 0 {
+                let pending_updates = loaded & !(1 << 31);
+                metrics.doc_loaded(name, pending_updates, start.elapsed());
+            }
+        }
+        Ok(loaded != 0)
+    } else {
+        Ok(false)
+    }
+}
+
+fn load_doc<'a, DB: DocOps<'a> + ?Sized>(
+    db: &DB,
+    oid: OID,
+    txn: &mut TransactionMut,
+    cancel: Option<&Cancel>,
+) -> Result<u32, Error>
+where
+    Error: From<<DB as KVStore<'a>>::Error>,
+{
+    if let Some(cancel) = cancel {
+        cancel.check()?;
+    }
+    let found = load_doc_state(db, oid, txn)?;
+    let mut update_count = 0;
+    {
+        let update_key_start = key_update(oid, 0);
+        let update_key_end = key_update(oid, u32::MAX);
+        let mut iter = db.iter_range(&update_key_start, &update_key_end)?;
+        let codec = db.options().value_codec();
+        while let Some(e) = iter.next() {
+            if let Some(cancel) = cancel {
+                cancel.check()?;
+            }
+            txn.apply_update(decode_update(codec.as_deref(), &e)?)
+                .with_key(e.key())?;
+            update_count += 1;
+        }
+    }
+    if found {
+        update_count |= 1 << 31; // mark hi bit to note that document core state was used
+    }
+    Ok(update_count)
+}
+
+/// Name of a root text type used only to split blocks of a document by a snapshot. Empty root
+/// types are never encoded, so it doesn't show up in the encoded document state.
+const SNAPSHOT_SPLIT_ROOT: &str = "__yrs_kvstore_snapshot";
+
+/// Encodes the state of a given `doc` as of provided `snapshot` using lib0 v1 encoding. Document
+/// must have been created with `skip_gc` option.
+fn encode_state_from_snapshot(doc: &Doc, snapshot: &Snapshot) -> Result<Vec<u8>, Error> {
+    // yrs doesn't trim a block cut by the snapshot right after its first element, which would
+    // leak content inserted after the snapshot: split blocks by the snapshot upfront and encode
+    // the state before the transaction is committed and the blocks are squashed back together
+    let text = doc.get_or_insert_text(SNAPSHOT_SPLIT_ROOT);
+    let mut txn = doc.transact_mut();
+    text.diff_range(&mut txn, Some(snapshot), None, YChange::identity);
+    let mut encoder = EncoderV1::new();
+    txn.encode_state_from_snapshot(snapshot, &mut encoder)?;
+    Ok(encoder.to_vec())
+}
+
+/// Applies the stored state of a document with a given `oid` (if any). Returns `true` if the
+/// document state was found.
+fn load_doc_state<'a, DB: DocOps<'a>>(
+    db: &DB,
+    oid: OID,
+    txn: &mut TransactionMut,
+) -> Result<bool, Error>
+where
+    Error: From<<DB as KVStore<'a>>::Error>,
+{
+    let doc_key = key_doc(oid);
+    if let Some(doc_state) = db.get(&doc_key)? {
+        txn.apply_update(decode_state(db, &doc_key, doc_state.as_ref(), false)?)
+            .with_key(&doc_key)?;
+        return Ok(true);
+    }
+    let doc_key = key_doc_v2(oid);
+    if let Some(doc_state) = db.get(&doc_key)? {
+        txn.apply_update(decode_state(db, &doc_key, doc_state.as_ref(), true)?)
+            .with_key(&doc_key)?;
+        return Ok(true);
+    }
+    Ok(false)
+}
+
+/// Buffer holding a stored document state: either borrowed from a value already read from the
+/// store, returned by the store itself (see: [KVStore::Return]) or reassembled from many values.
+/// This way document states can be decoded directly from buffers owned by backends (i.e.
+/// memory-mapped pages in case of LMDB) without copying them first.
+enum StateBuf<'v, R> {
+    Borrowed(&'v [u8]),
+    Returned(R),
+    Owned(Vec<u8>),
+}
+
+impl<'v, R: AsRef<[u8]>> Deref for StateBuf<'v, R> {
+    type Target = [u8];
+
+    fn deref(&self) -> &Self::Target {
+        match self {
+            StateBuf::Borrowed(value) => value,
+            StateBuf::Returned(value) => value.as_ref(),
+            StateBuf::Owned(value) => value,
+        }
+    }
+}
+
+/// Returns a document state `value` stored under a given `key`, reassembling it from chunks (see:
+/// [crate::chunk]) or reading it from the blob keyspace (see: [crate::blob]) if necessary.
+fn resolve_state<'a, 'v, DB: DocOps<'a>>(
+    db: &DB,
+    key: &[u8],
+    value: &'v [u8],
+) -> Result<StateBuf<'v, <DB as KVStore<'a>>::Return>, Error>
+where
+    Error: From<<DB as KVStore<'a>>::Error>,
+{
+    // blob references are small enough to never be split into chunks
+    if let Some(blob) = blob::resolve(db, key, value)? {
+        return Ok(StateBuf::Returned(blob));
+    }
+    match chunk::resolve(db, key, value)? {
+        Cow::Borrowed(value) => Ok(StateBuf::Borrowed(value)),
+        Cow::Owned(value) => Ok(StateBuf::Owned(value)),
+    }
+}
+
+/// Decodes a document state stored under a given `key`, using lib0 v2 encoding if `v2` is set.
+fn decode_state<'a, DB: DocOps<'a>>(
+    db: &DB,
+    key: &[u8],
+    value: &[u8],
+    v2: bool,
+) -> Result<Update, Error>
+where
+    Error: From<<DB as KVStore<'a>>::Error>,
+{
+    let value = resolve_state(db, key, value)?;
+    let value = decode_value(db.options().value_codec().as_deref(), key, &value)?;
+    if v2 {
+        Update::decode_v2(&value).with_key(key)
+    } else {
+        Update::decode_v1(&value).with_key(key)
+    }
+}
+
+/// Decodes a pending update entry, using encoding determined by its key.
+fn decode_update<E: KVEntry>(codec: Option<&dyn ValueCodec>, e: &E) -> Result<Update, Error> {
+    let value = decode_value(codec, e.key(), e.value())?;
+    let update = if update_encoding(e.key()) == ENCODING_V2 {
+        Update::decode_v2(&value).with_key(e.key())?
+    } else {
+        Update::decode_v1(&value).with_key(e.key())?
+    };
+    Ok(update)
+}
+
+/// Computes a diff between a given state vector and persisted document state using binary update
+/// operations only. Document state and all pending updates are merged together (after being
+/// re-encoded if their encoding differs from the requested one) and the result is trimmed down to
+/// the changes missing from `sv`.
+fn diff_stored<'a, DB: DocOps<'a>>(
+    db: &DB,
+    oid: OID,
+    sv: &StateVector,
+    v2: bool,
+) -> Result<Option<Vec<u8>>, Error>
+where
+    Error: From<<DB as KVStore<'a>>::Error>,
+{
+    if let Some(diff) = diff_pending(db, oid, sv, v2)? {
+        return Ok(Some(diff));
+    }
+    if let Some(merged) = merge_stored(db, oid, v2)? {
+        Ok(Some(diff_merged(&merged, sv, v2)?))
+    } else {
+        Ok(None)
+    }
+}
+
+/// Returns a diff between a document with a given `oid` and a remote state vector `sv`, which
+/// covers the stored state vector of that document, i.e. remote peer has already seen all blocks
+/// inserted by the persisted document state. In such case the document state is not merged with
+/// pending updates: only its deletions are, as they don't advance state vectors and could have
+/// been missed by the remote peer. Returns `None` if the fast path can't be used.
+fn diff_pending<'a, DB: DocOps<'a>>(
+    db: &DB,
+    oid: OID,
+    sv: &StateVector,
+    v2: bool,
+) -> Result<Option<Vec<u8>>, Error>
+where
+    Error: From<<DB as KVStore<'a>>::Error>,
+{
+    let stored_sv = match db.get(&key_state_vector(oid))? {
+        Some(stored_sv) => StateVector::decode_v1(stored_sv.as_ref())?,
+        None => return Ok(None),
+    };
+    let covered = stored_sv
+        .iter()
+        .all(|(client, clock)| sv.get(client) >= *clock);
+    if !covered {
+        return Ok(None);
+    }
+    let (state_v2, doc_state) = match read_state(db, oid)? {
+        Some(state) => state,
+        None => return Ok(None),
+    };
+    let deletions = diff_merged(&doc_state, sv, state_v2)?;
+    let mut blobs = vec![(state_v2, deletions)];
+    blobs.extend(read_updates(db, oid)?);
+    match merge_blobs(blobs, v2).map_err(|e| e as Error)? {
+        Some(merged) => Ok(Some(diff_merged(&merged, sv, v2)?)),
+        None => Ok(None),
+    }
+}
+
+fn sync_stored<'a, DB: DocOps<'a>>(
+    db: &DB,
+    oid: OID,
+    remote_sv: &StateVector,
+    v2: bool,
+) -> Result<Option<(Vec<u8>, StateVector)>, Error>
+where
+    Error: From<<DB as KVStore<'a>>::Error>,
+{
+    if let Some(merged) = merge_stored(db, oid, v2)? {
+        let diff = diff_merged(&merged, remote_sv, v2)?;
+        let local_sv = if v2 {
+            StateVector::decode_v2(&yrs::encode_state_vector_from_update_v2(&merged)?)?
+        } else {
+            StateVector::decode_v1(&yrs::encode_state_vector_from_update_v1(&merged)?)?
+        };
+        Ok(Some((diff, local_sv)))
+    } else {
+        Ok(None)
+    }
+}
+
+fn diff_merged(merged: &[u8], sv: &StateVector, v2: bool) -> Result<Vec<u8>, Error> {
+    let diff = if v2 {
+        yrs::diff_updates_v2(merged, &sv.encode_v2())?
+    } else {
+        yrs::diff_updates_v1(merged, &sv.encode_v1())?
+    };
+    Ok(diff)
+}
+
+/// Merges the persisted state of a document with a given `oid` together with all of its pending
+/// updates into a single update, encoded using lib0 v2 encoding if `v2` is set or v1 otherwise.
+fn merge_stored<'a, DB: DocOps<'a>>(db: &DB, oid: OID, v2: bool) -> Result<Option<Vec<u8>>, Error>
+where
+    Error: From<<DB as KVStore<'a>>::Error>,
+{
+    let blobs = read_stored(db, oid)?;
+    merge_blobs(blobs, v2).map_err(|e| e as Error)
+}
+
+/// Reads the persisted state of a document with a given `oid` together with all of its pending
+/// updates as `(is_v2, update)` pairs.
+fn read_stored<'a, DB: DocOps<'a>>(db: &DB, oid: OID) -> Result<Vec<(bool, Vec<u8>)>, Error>
+where
+    Error: From<<DB as KVStore<'a>>::Error>,
+{
+    let mut blobs: Vec<(bool, Vec<u8>)> = Vec::new();
+    blobs.extend(read_state(db, oid)?);
+    blobs.extend(read_updates(db, oid)?);
+    Ok(blobs)
+}
+
+/// Reads the persisted state of a document with a given `oid` as an `(is_v2, state)` pair.
+fn read_state<'a, DB: DocOps<'a>>(db: &DB, oid: OID) -> Result<Option<(bool, Vec<u8>)>, Error>
+where
+    Error: From<<DB as KVStore<'a>>::Error>,
+{
+    let codec = db.options().value_codec();
+    let codec = codec.as_deref();
+    if let Some(doc_state) = db.get(&key_doc(oid))? {
+        let doc_state = resolve_state(db, &key_doc(oid), doc_state.as_ref())?;
+        let doc_state = decode_value(codec, &key_doc(oid), &doc_state)?;
+        Ok(Some((false, doc_state.into_owned())))
+    } else if let Some(doc_state) = db.get(&key_doc_v2(oid))? {
+        let doc_state = resolve_state(db, &key_doc_v2(oid), doc_state.as_ref())?;
+        let doc_state = decode_value(codec, &key_doc_v2(oid), &doc_state)?;
+        Ok(Some((true, doc_state.into_owned())))
+    } else {
+        Ok(None)
+    }
+}
+
+/// Reads all pending updates of a document with a given `oid` as `(is_v2, update)` pairs.
+fn read_updates<'a, DB: DocOps<'a>>(db: &DB, oid: OID) -> Result<Vec<(bool, Vec<u8>)>, Error>
+where
+    Error: From<<DB as KVStore<'a>>::Error>,
+{
+    let mut blobs: Vec<(bool, Vec<u8>)> = Vec::new();
+    let codec = db.options().value_codec();
+    let codec = codec.as_deref();
+    let update_key_start = key_update(oid, 0);
+    let update_key_end = key_update(oid, u32::MAX);
+    for e in db.iter_range(&update_key_start, &update_key_end)? {
+        let is_v2 = update_encoding(e.key()) == ENCODING_V2;
+        blobs.push((is_v2, decode_value(codec, e.key(), e.value())?.into_owned()));
+    }
+    Ok(blobs)
+}
+
+/// Merges `(is_v2, update)` pairs returned by [read_stored] into a single update, encoded using
+/// lib0 v2 encoding if `v2` is set or v1 otherwise. It doesn't touch the store, so it can be
+/// called from other threads.
+fn merge_blobs(
+    blobs: Vec<(bool, Vec<u8>)>,
+    v2: bool,
+) -> Result<Option<Vec<u8>>, Box<dyn std::error::Error + Send + Sync>> {
+    if blobs.is_empty() {
+        return Ok(None);
+    }
+
+    let mut updates = Vec::with_capacity(blobs.len());
+    for (is_v2, blob) in blobs {
+        let blob = match (is_v2, v2) {
+            (false, true) => Update::decode_v1(&blob)?.encode_v2(),
+            (true, false) => Update::decode_v2(&blob)?.encode_v1(),
+            _ => blob,
+        };
+        updates.push(blob);
+    }
+    let merged = if updates.len() == 1 {
+        updates.pop().unwrap()
+    } else {
+        let updates: Vec<&[u8]> = updates.iter().map(Vec::as_slice).collect();
+        if v2 {
+            yrs::merge_updates_v2(&updates)?
+        } else {
+            yrs::merge_updates_v1(&updates)?
+        }
+    };
+    Ok(Some(merged))
+}
+
+fn delete_updates(batch: &mut WriteBatch, oid: OID, up_to: u32) {
+    let start = key_update(oid, 0);
+    let end = key_update_v2(oid, up_to);
+    batch.remove_range(&start, &end);
+    batch.remove(&key_pending_summary(oid));
+    batch.remove_range(&key_update_time(oid, 0), &key_update_time(oid, up_to));
+    batch.remove_range(&key_update_origin(oid, 0), &key_update_origin(oid, up_to));
+}
+
+/// Returns decoded origins (see: [DocOps::push_update_with_origin]) of all pending updates of
+/// a document with a given `oid`, starting from a given `clock`.
+fn update_origins<'a, DB: DocOps<'a>>(
+    db: &DB,
+    oid: OID,
+    clock: u32,
+) -> Result<HashMap<u32, Box<[u8]>>, Error>
+where
+    Error: From<<DB as KVStore<'a>>::Error>,
+{
+    let codec = db.options().value_codec();
+    let start = key_update_origin(oid, clock);
+    let end = key_update_origin(oid, u32::MAX);
+    let mut origins = HashMap::new();
+    for e in db.iter_range(&start, &end)? {
+        let origin = decode_meta(codec.as_deref(), e.key(), e.value())?;
+        origins.insert(update_origin_clock(e.key()), origin.into());
+    }
+    Ok(origins)
+}
+
+/// Appends a new `update` of a document with a given `name` (see: [DocOps::push_update]),
+/// optionally storing its `origin`.
+fn push_update<'a, DB: DocOps<'a>>(
+    db: &DB,
+    name: &[u8],
+    update: &[u8],
+    v2: bool,
+    origin: Option<&[u8]>,
+) -> Result<u32, Error>
+where
+    Error: From<<DB as KVStore<'a>>::Error>,
+{
+    let start = Instant::now();
+    check_update_size(db, update)?;
+    validate_update(db, name, update, v2)?;
+    let oid = get_or_create_oid(db, name)?;
+    trace_record!("oid", oid);
+    if let Some(clock) = find_duplicate(db, oid, update, v2)? {
+        // the same update is still pending, i.e. it has been replayed by a retrying client
+        return Ok(clock);
+    }
+    let value = encode_value(db, update)?;
+    reserve_pending_bytes(db, oid, value.len() as u64)?;
+    let clock = next_clock(db, oid)?;
+    trace_record!("clock", clock);
+    let mut batch = db.batch();
+    let update_key = if v2 {
+        key_update_v2(oid, clock)
+    } else {
+        key_update(oid, clock)
+    };
+    trace_record!("bytes", value.len() as u64);
+    batch.upsert(&update_key, &value);
+    if let Some(origin) = origin {
+        batch.upsert(&key_update_origin(oid, clock), &encode_meta(db, origin)?);
+    }
+    record_update_time(db, &mut batch, oid, clock, unix_millis());
+    track_pending(db, &mut batch, oid, clock, 1, value.len() as u64)?;
+    track_state_vector(db, &mut batch, oid, [(update, clock, v2)])?;
+    remember_updates(db, &mut batch, oid, [(update, clock)])?;
+    log_changes(db, &mut batch, [(name, oid, ChangeKind::Update)])?;
+    db.commit_batch(batch)?;
+    publish_update(db, name, update, Some(clock), v2);
+    if let Some(metrics) = db.metrics() {
+        metrics.updates_pushed(name, 1, value.len() as u64, start.elapsed());
+    }
+    compact_if_needed(db, oid)?;
+    Ok(clock)
+}
+
+/// Records a `timestamp` of a pending update with a given `clock` as a part of a write `batch`,
+/// if update timestamps have been enabled (see: [StoreOptions::update_timestamps]).
+fn record_update_time<'a, DB: DocOps<'a>>(
+    db: &DB,
+    batch: &mut WriteBatch,
+    oid: OID,
+    clock: u32,
+    timestamp: u64,
+) where
+    Error: From<<DB as KVStore<'a>>::Error>,
+{
+    if db.options().update_timestamps {
+        batch.upsert(&key_update_time(oid, clock), &timestamp.to_be_bytes());
+    }
+}
+
+/// Returns a sequence number, under which the next update of a document will be archived.
+fn next_history_seq<'a, DB: DocOps<'a>>(db: &DB, oid: OID) -> Result<u32, Error>
+where
+    Error: From<<DB as KVStore<'a>>::Error>,
+{
+    let end = key_history_v2(oid, u32::MAX);
+    match db.peek_back(&end)? {
+        Some(e) if e.key().len() == end.len() && e.key().starts_with(&end[..=SUB_KEY_POS]) => {
+            Ok(history_seq(e.key()) + 1)
+        }
+        _ => Ok(1),
+    }
+}
+
+/// Copies pending updates of a document up to a given clock (inclusive) into its history
+/// keyspace, starting from a given history sequence number.
+fn archive_updates<'a, DB: DocOps<'a>>(
+    db: &DB,
+    batch: &mut WriteBatch,
+    oid: OID,
+    up_to: u32,
+    seq: u32,
+) -> Result<(), Error>
+where
+    Error: From<<DB as KVStore<'a>>::Error>,
+{
+    let start = key_update(oid, 0);
+    let end = key_update_v2(oid, up_to);
+    for (seq, e) in (seq..).zip(db.iter_range(&start, &end)?) {
+        let key = if update_encoding(e.key()) == ENCODING_V2 {
+            key_history_v2(oid, seq)
+        } else {
+            key_history(oid, seq)
+        };
+        batch.upsert(&key, e.value());
+    }
+    Ok(())
+}
+
+fn flush_doc<'a, DB: DocOps<'a>>(
+    db: &DB,
+    oid: OID,
+    options: yrs::Options,
+    cancel: Option<&Cancel>,
+) -> Result<Option<(Doc, u64)>, Error>
+where
+    Error: From<<DB as KVStore<'a>>::Error>,
+{
+    // updates pushed after this point are not pruned, even if they happen to be loaded
+    let up_to = last_clock(db, oid)?;
+    let doc = Doc::with_options(options);
+    let found = load_doc(db, oid, &mut doc.transact_mut(), cancel)?;
+    if let Some(cancel) = cancel {
+        // this is the last chance to abort flush before anything has been written
+        cancel.check()?;
+    }
+    if found & !(1 << 31) != 0 {
+        // loaded doc was generated from updates
+        let bytes = write_flushed(db, oid, &doc, up_to)?;
+        Ok(Some((doc, bytes)))
+    } else {
+        Ok(None)
+    }
+}
+
+/// Merges pending updates of a document with a given `oid`, which are not retained by a given
+/// retention `policy` at `now` (in milliseconds since Unix epoch), into the document state.
+/// Returns the number of merged updates.
+fn enforce_retention<'a, DB: DocOps<'a>>(
+    db: &DB,
+    oid: OID,
+    policy: &RetentionPolicy,
+    now: u64,
+) -> Result<u32, Error>
+where
+    Error: From<<DB as KVStore<'a>>::Error>,
+{
+    let codec = db.options().value_codec();
+    let start = key_update(oid, 0);
+    let end = key_update(oid, u32::MAX);
+    let mut updates = Vec::new();
+    for e in db.iter_range(&start, &end)? {
+        updates.push((update_clock(e.key()), decode_update(codec.as_deref(), &e)?));
+    }
+    // every limit can only shorten the prefix of updates, which is going to be merged
+    let mut merge_count = updates.len();
+    if let Some(keep) = policy.keep_updates {
+        merge_count = merge_count.min(updates.len().saturating_sub(keep as usize));
+    }
+    if let Some(keep_for) = policy.keep_for {
+        let cutoff = now.saturating_sub(keep_for.as_millis() as u64);
+        let mut expired = 0;
+        for (clock, _) in updates.iter().take(merge_count) {
+            let pushed_at = match db.get(&key_update_time(oid, *clock))? {
+                Some(value) => update_timestamp(value.as_ref()),
+                None => None,
+            };
+            match pushed_at {
+                Some(pushed_at) if pushed_at <= cutoff => expired += 1,
+                _ => break,
+            }
+        }
+        merge_count = expired;
+    }
+    if policy.keep_since_snapshot {
+        let mut snapshots = Vec::new();
+        for e in db.iter_range(&key_snapshot_start(oid), &key_snapshot_end(oid))? {
+            snapshots.push(Snapshot::decode_v1(e.value())?);
+        }
+        merge_count = updates
+            .iter()
+            .take(merge_count)
+            .take_while(|(_, update)| snapshots.iter().any(|s| snapshot_covers(s, update)))
+            .count();
+    }
+    if merge_count == 0 {
+        return Ok(0);
+    }
+    let up_to = updates[merge_count - 1].0;
+    let doc = Doc::with_options(db.options().new_doc_options());
+    {
+        let mut txn = doc.transact_mut();
+        load_doc_state(db, oid, &mut txn)?;
+        for (_, update) in updates.into_iter().take(merge_count) {
+            txn.apply_update(update);
+        }
+    }
+    write_flushed(db, oid, &doc, up_to)?;
+    Ok(merge_count as u32)
+}
+
+/// Checks if all changes (both insertions and deletions) of a given `update` have been captured
+/// by a given `snapshot`.
+fn snapshot_covers(snapshot: &Snapshot, update: &Update) -> bool {
+    // update state vector skips clients, which blocks don't start at clock 0, so insertions are
+    // compared against the snapshot clocks directly
+    let inserted = update.insertions(true).iter().all(|(client, ranges)| {
+        let clock = snapshot.state_map.get(client);
+        ranges.iter().all(|range| range.end <= clock)
+    });
+    inserted
+        && update.delete_set().iter().all(|(client, ranges)| {
+            ranges.iter().all(|range| {
+                range
+                    .clone()
+                    .all(|clock| snapshot.delete_set.contains(&ID::new(*client, clock)))
+            })
+        })
+}
+
+/// Encodes a value of the flush marker: `{up_to:4}` clock of the last merged update optionally
+/// followed by `{first_seq:4}` - the first history sequence number used to archive merged
+/// updates, present until all of them have been archived. It makes archiving idempotent, so that
+/// updates archived by an interrupted flush are overridden instead of being duplicated when that
+/// flush is finished.
+pub(crate) fn flush_marker(up_to: u32, first_seq: Option<u32>) -> Vec<u8> {
+    let mut value = Vec::with_capacity(8);
+    value.extend_from_slice(&up_to.to_be_bytes());
+    if let Some(seq) = first_seq {
+        value.extend_from_slice(&seq.to_be_bytes());
+    }
+    value
+}
+
+/// Decodes a flush marker value (see: [flush_marker]) stored under a given `key`.
+pub(crate) fn parse_flush_marker(key: &[u8], value: &[u8]) -> Result<(u32, Option<u32>), Error> {
+    match value.len() {
+        4 => Ok((u32::from_be_bytes(value.try_into().unwrap()), None)),
+        8 => Ok((
+            u32::from_be_bytes(value[..4].try_into().unwrap()),
+            Some(u32::from_be_bytes(value[4..].try_into().unwrap())),
+        )),
+        _ => Err(StoreError::Corrupted { key: key.to_vec() }.into()),
+    }
+}
+
+/// Stores the state of a given `doc` as the new document state, pruning its pending updates up to
+/// `up_to` clock (inclusive). Returns the size of a stored document state in bytes.
+fn write_flushed<'a, DB: DocOps<'a>>(db: &DB, oid: OID, doc: &Doc, up_to: u32) -> Result<u64, Error>
+where
+    Error: From<<DB as KVStore<'a>>::Error>,
+{
+    let first_seq = flush_history_seq(db, oid)?;
+    write_flushed_with(db, oid, doc, up_to, first_seq)
+}
+
+/// Returns the first history sequence number, which should be used to archive updates merged by
+/// a flush, or `None` if history is not retained by a given store.
+fn flush_history_seq<'a, DB: DocOps<'a>>(db: &DB, oid: OID) -> Result<Option<u32>, Error>
+where
+    Error: From<<DB as KVStore<'a>>::Error>,
+{
+    if db.options().compaction_policy.retain_history {
+        Ok(Some(next_history_seq(db, oid)?))
+    } else {
+        Ok(None)
+    }
+}
+
+/// Stores the state of a given `doc` as the new document state, archiving pending updates up to
+/// `up_to` clock (inclusive) starting from `first_seq` history sequence number if it was provided,
+/// and finally pruning them. Returns the size of a stored document state in bytes.
+///
+/// Flush is done in phases guarded by a flush marker, so that it can be finished by
+/// [DocOps::recover] if the backend doesn't apply write batches atomically and the process was
+/// interrupted in between: new document state is fully written before any update is removed.
+fn write_flushed_with<'a, DB: DocOps<'a>>(
+    db: &DB,
+    oid: OID,
+    doc: &Doc,
+    up_to: u32,
+    first_seq: Option<u32>,
+) -> Result<u64, Error>
+where
+    Error: From<<DB as KVStore<'a>>::Error>,
+{
+    let txn = doc.transact();
+    let doc_state = txn.encode_state_as_update_v1(&StateVector::default());
+    let state_vec = txn.state_vector().encode_v1();
+    drop(txn);
+    let bytes = write_state_with(db, oid, &doc_state, &state_vec, up_to, first_seq)?;
+    if let Some(export) = &db.options().json_export {
+        let json = export.render(doc);
+        db.upsert(&key_json(oid), &encode_meta(db, json.as_bytes())?)?;
+    }
+    Ok(bytes)
+}
+
+/// Stores a given document state (lib0 v1 encoding) and its state vector just like
+/// [write_flushed_with] does. Returns the size of a stored document state in bytes.
+fn write_state_with<'a, DB: DocOps<'a>>(
+    db: &DB,
+    oid: OID,
+    doc_state: &[u8],
+    state_vec: &[u8],
+    up_to: u32,
+    first_seq: Option<u32>,
+) -> Result<u64, Error>
+where
+    Error: From<<DB as KVStore<'a>>::Error>,
+{
+    let doc_state = encode_value(db, doc_state)?;
+    let marker = key_flush_marker(oid);
+    db.upsert(&marker, &flush_marker(up_to, first_seq))?;
+
+    let mut batch = db.batch();
+    let doc_state = share_state(db, &mut batch, oid, doc_state)?;
+    let bytes = chunk::stored_len(&doc_state) as u64;
+    insert_inner_v1(&mut batch, oid, &doc_state, state_vec);
+    db.commit_batch(batch)?;
+
+    if let Some(seq) = first_seq {
+        // all updates are archived before any of them is removed, once it's done the marker no
+        // longer needs to point to a history sequence number
+        let mut batch = db.batch();
+        archive_updates(db, &mut batch, oid, up_to, seq)?;
+        batch.upsert(&marker, &flush_marker(up_to, None));
+        db.commit_batch(batch)?;
+    }
+
+    let mut batch = db.batch();
+    delete_updates(&mut batch, oid, up_to);
+    batch.remove(&marker);
+    db.commit_batch(batch)?;
+    Ok(bytes)
+}
+
+/// Returns a total size in bytes of the document state and all pending updates stored for
+/// a document with given `oid`.
+fn stored_doc_size<'a, DB: DocOps<'a>>(db: &DB, oid: OID) -> Result<u64, Error>
+where
+    Error: From<<DB as KVStore<'a>>::Error>,
+{
+    let mut size = 0u64;
+    if let Some(doc_state) = db.get(&key_doc(oid))? {
+        size += chunk::stored_len(doc_state.as_ref()) as u64;
+    } else if let Some(doc_state) = db.get(&key_doc_v2(oid))? {
+        size += chunk::stored_len(doc_state.as_ref()) as u64;
+    }
+    let start = key_update(oid, 0);
+    let end = key_update(oid, u32::MAX);
+    for e in db.iter_range(&start, &end)? {
+        size += e.value().len() as u64;
+    }
+    Ok(size)
+}
+
+/// Writes all stored documents into a given `writer` (see: [DocOps::export_all]).
+fn export_docs<'a, DB: DocOps<'a>, W: std::io::Write>(
+    db: &DB,
+    writer: &mut W,
+    cancel: Option<&Cancel>,
+) -> Result<usize, Error>
+where
+    Error: From<<DB as KVStore<'a>>::Error>,
+{
+    let start = Key::from_const([V1, KEYSPACE_OID]);
+    let end = Key::from_const([V1, KEYSPACE_DOC]);
+    let mut count = 0;
+    for e in db.iter_range(&start, &end)? {
+        if let Some(cancel) = cancel {
+            cancel.check()?;
+        }
+        let archive = export_doc(db, oid_from_bytes(e.value()))?;
+        write_dump_entry(writer, doc_oid_name(e.key()), &archive)?;
+        count += 1;
+    }
+    writer.flush()?;
+    Ok(count)
+}
+
+fn export_doc<'a, DB: DocOps<'a>>(db: &DB, oid: OID) -> Result<DocArchive, Error>
+where
+    Error: From<<DB as KVStore<'a>>::Error>,
+{
+    let mut archive = DocArchive::default();
+    let codec = db.options().value_codec();
+    let codec = codec.as_deref();
+    let doc_key = key_doc(oid);
+    if let Some(doc_state) = db.get(&doc_key)? {
+        let doc_state = resolve_state(db, &doc_key, doc_state.as_ref())?;
+        archive.state = Some(decode_value(codec, &doc_key, &doc_state)?.into());
+    } else {
+        let doc_key = key_doc_v2(oid);
+        if let Some(doc_state) = db.get(&doc_key)? {
+            let doc_state = resolve_state(db, &doc_key, doc_state.as_ref())?;
+            archive.state = Some(decode_value(codec, &doc_key, &doc_state)?.into());
+            archive.state_v2 = true;
+        }
+    }
+    if let Some(sv) = db.get(&key_state_vector(oid))? {
+        archive.state_vector = Some(sv.as_ref().into());
+    }
+    let mut origins = update_origins(db, oid, 0)?;
+    let start = key_update(oid, 0);
+    let end = key_update(oid, u32::MAX);
+    for e in db.iter_range(&start, &end)? {
+        let key = e.key();
+        let clock = update_clock(key);
+        archive.updates.push(PendingUpdate {
+            clock,
+            update: decode_value(codec, key, e.value())?.into(),
+            v2: update_encoding(key) == ENCODING_V2,
+            origin: origins.remove(&clock),
+        });
+    }
+    let start = key_meta_start(oid);
+    let end = key_meta_end(oid);
+    for e in db.iter_range(&start, &end)? {
+        let key = e.key();
+        let meta_key = &key[SUB_KEY_POS + 1..key.len() - 1];
+        let value = decode_meta(codec, key, e.value())?;
+        archive.meta.push((meta_key.into(), value.into()));
+    }
+    let start = key_update_time(oid, 0);
+    let end = key_update_time(oid, u32::MAX);
+    for e in db.iter_range(&start, &end)? {
+        if let Some(timestamp) = update_timestamp(e.value()) {
+            archive
+                .update_timestamps
+                .push((update_time_clock(e.key()), timestamp));
+        }
+    }
+    Ok(archive)
+}
+
+fn import_doc<'a, DB: DocOps<'a>>(
+    db: &DB,
+    batch: &mut WriteBatch,
+    oid: OID,
+    archive: &DocArchive,
+) -> Result<(), Error>
+where
+    Error: From<<DB as KVStore<'a>>::Error>,
+{
+    if let Some(doc_state) = &archive.state {
+        let doc_state = encode_value(db, doc_state)?;
+        let doc_state = share_state(db, batch, oid, doc_state)?;
+        if archive.state_v2 {
+            batch.upsert(&key_doc_v2(oid), &doc_state);
+        } else {
+            batch.upsert(&key_doc(oid), &doc_state);
+        }
+    }
+    if let Some(sv) = &archive.state_vector {
+        batch.upsert(&key_state_vector(oid), sv);
+    }
+    for u in archive.updates.iter() {
+        let key = if u.v2 {
+            key_update_v2(oid, u.clock)
+        } else {
+            key_update(oid, u.clock)
+        };
+        batch.upsert(&key, &encode_value(db, &u.update)?);
+        if let Some(origin) = &u.origin {
+            batch.upsert(&key_update_origin(oid, u.clock), &encode_meta(db, origin)?);
+        }
+    }
+    for (meta_key, value) in archive.meta.iter() {
+        batch.upsert(&key_meta(oid, meta_key), &encode_meta(db, value)?);
+    }
+    for (clock, timestamp) in archive.update_timestamps.iter() {
+        batch.upsert(&key_update_time(oid, *clock), &timestamp.to_be_bytes());
+    }
+    Ok(())
+}
+
+/// Prepares an encoded document state of a document with a given `oid` to be written into a given
+/// `batch`: state is moved into the blob keyspace if it's large enough (see:
+/// [StoreOptions::blob_threshold]) or otherwise split into chunks if it's too large to be stored
+/// under a single key (see: [StoreOptions::doc_chunk_size]). Blob or chunks of the current state
+/// of the document are released.
+fn share_state<'a, DB: DocOps<'a>>(
+    db: &DB,
+    batch: &mut WriteBatch,
+    oid: OID,
+    doc_state: Vec<u8>,
+) -> Result<Vec<u8>, Error>
+where
+    Error: From<<DB as KVStore<'a>>::Error>,
+{
+    let mut blobs = BlobRefs::default();
+    let mut chunked = false;
+    for key in [key_doc(oid), key_doc_v2(oid)] {
+        if let Some(value) = db.get(&key)? {
+            blobs.release(value.as_ref());
+            chunked |= chunk::is_manifest(value.as_ref());
+        }
+    }
+    let doc_state = blobs.share(db, doc_state, db.options().blob_threshold)?;
+    blobs.commit(db, batch)?;
+    if chunked {
+        chunk::remove(batch, oid);
     }
+    Ok(chunk::split(
+        batch,
+        oid,
+        doc_state,
+        db.options().doc_chunk_size,
+    ))
 }
 
-fn load_doc<'a, DB: DocOps<'a> + ?Sized>(
+/// Removes all entries of a document with a given `name` and `oid` as a part of a write `batch`.
+fn clear_doc<'a, DB: DocOps<'a>>(
     db: &DB,
+    batch: &mut WriteBatch,
+    name: &[u8],
     oid: OID,
-    txn: &mut TransactionMut,
-) -> Result<u32, Error>
+) -> Result<(), Error>
 where
     Error: From<<DB as KVStore<'a>>::Error>,
 {
-    let mut found = false;
-    {
-        let doc_key = key_doc(oid);
-        if let Some(doc_state) = db.get(&doc_key)? {
-            let update = Update::decode_v1(doc_state.as_ref())?;
-            txn.apply_update(update);
-            found = true;
-        }
-    }
-    let mut update_count = 0;
-    {
-        let update_key_start = key_update(oid, 0);
-        let update_key_end = key_update(oid, u32::MAX);
-        let mut iter = db.iter_range(&update_key_start, &update_key_end)?;
-        while let Some(e) = iter.next() {
-            let value = e.value();
-            let update = Update::decode_v1(value)?;
-            txn.apply_update(update);
-            update_count += 1;
-        }
+    // all document related elements are stored within bounds [0,1,..oid,0]..[0,1,..oid,255]
+    let mut blobs = BlobRefs::default();
+    release_doc_blobs(db, &mut blobs, oid)?;
+    blobs.commit(db, batch)?;
+    batch.remove(&key_oid(name));
+    remove_search_postings(db, batch, oid)?;
+    batch.remove_range(&key_doc_start(oid), &key_doc_end(oid));
+    batch.remove(&key_flush_marker(oid));
+    if let Some(expiry) = get_expiry(db, oid)? {
+        batch.remove(&key_expiry_index(expiry, oid));
     }
-    if found {
-        update_count |= 1 << 31; // mark hi bit to note that document core state was used
-    }
-    Ok(update_count)
+    log_changes(db, batch, [(name, oid, ChangeKind::Clear)])
 }
 
-fn delete_updates<'a, DB: DocOps<'a> + ?Sized>(db: &DB, oid: OID) -> Result<(), Error>
+/// Removes search index postings of all terms indexed for a document with a given `oid` (see:
+/// [crate::search]) as a part of a write `batch`. Postings live outside of the document keyspace,
+/// so they are not removed together with it.
+pub(crate) fn remove_search_postings<'a, DB: DocOps<'a>>(
+    db: &DB,
+    batch: &mut WriteBatch,
+    oid: OID,
+) -> Result<(), Error>
 where
     Error: From<<DB as KVStore<'a>>::Error>,
 {
-    let start = key_update(oid, 0);
-    let end = key_update(oid, u32::MAX);
-    db.remove_range(&start, &end)?;
+    let key = key_search_terms(oid);
+    if let Some(value) = db.get(&key)? {
+        // indexed terms: {term:n}0{term:n}0...
+        for term in value.as_ref().split(|b| *b == TERMINATOR) {
+            if !term.is_empty() {
+                batch.remove(&key_posting(term, oid));
+            }
+        }
+        batch.remove(&key);
+    }
     Ok(())
 }
 
-fn flush_doc<'a, DB: DocOps<'a> + ?Sized>(
+/// Returns a timestamp of when a document has been moved into a trash, stored in a given trash
+/// entry `value` (see: [DocOps::trash_doc]).
+fn trash_timestamp(value: &[u8]) -> Option<u64> {
+    let timestamp = value.get(..8)?;
+    Some(u64::from_be_bytes(timestamp.try_into().unwrap()))
+}
+
+/// Returns a timestamp of a pending update stored in a given update timestamp entry `value`.
+fn update_timestamp(value: &[u8]) -> Option<u64> {
+    let timestamp: [u8; 8] = value.try_into().ok()?;
+    Some(u64::from_be_bytes(timestamp))
+}
+
+/// Returns a current time in milliseconds since Unix epoch.
+pub(crate) fn unix_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or_default()
+}
+
+/// Releases a blob referenced by the current state of a document with a given `oid`, if any.
+fn release_state_blob<'a, DB: DocOps<'a>>(
     db: &DB,
+    blobs: &mut BlobRefs,
     oid: OID,
-    options: yrs::Options,
-) -> Result<Option<Doc>, Error>
+) -> Result<(), Error>
 where
     Error: From<<DB as KVStore<'a>>::Error>,
 {
-    let doc = Doc::with_options(options);
-    let found = load_doc(db, oid, &mut doc.transact_mut())?;
-    if found & !(1 << 31) != 0 {
-        // loaded doc was generated from updates
-        let txn = doc.transact();
-        let doc_state = txn.encode_state_as_update_v1(&StateVector::default());
-        let state_vec = txn.state_vector().encode_v1();
-        drop(txn);
-
-        insert_inner_v1(db, oid, &doc_state, &state_vec)?;
-        delete_updates(db, oid)?;
-        Ok(Some(doc))
-    } else {
-        Ok(None)
+    for key in [key_doc(oid), key_doc_v2(oid)] {
+        if let Some(value) = db.get(&key)? {
+            blobs.release(value.as_ref());
+        }
     }
+    Ok(())
 }
 
-fn insert_inner_v1<'a, DB: DocOps<'a> + ?Sized>(
+/// Releases all blobs referenced by a document with a given `oid`, including its quarantined
+/// entries.
+fn release_doc_blobs<'a, DB: DocOps<'a>>(
     db: &DB,
+    blobs: &mut BlobRefs,
     oid: OID,
-    doc_state_v1: &[u8],
-    doc_sv_v1: &[u8],
 ) -> Result<(), Error>
 where
-    error::Error: From<<DB as KVStore<'a>>::Error>,
+    Error: From<<DB as KVStore<'a>>::Error>,
 {
+    release_state_blob(db, blobs, oid)?;
+    for e in db.iter_range(&key_quarantine_start(oid), &key_quarantine_end(oid))? {
+        blobs.release(e.value());
+    }
+    Ok(())
+}
+
+fn insert_inner_v1(batch: &mut WriteBatch, oid: OID, doc_state_v1: &[u8], doc_sv_v1: &[u8]) {
     let key_doc = key_doc(oid);
     let key_sv = key_state_vector(oid);
-    db.upsert(&key_doc, doc_state_v1)?;
-    db.upsert(&key_sv, doc_sv_v1)?;
+    batch.upsert(&key_doc, doc_state_v1);
+    batch.upsert(&key_sv, doc_sv_v1);
+    batch.remove(&key_doc_v2(oid));
+    // new state vector doesn't include remaining pending updates
+    batch.remove(&key_state_vector_clock(oid));
+}
+
+fn insert_inner_v2(
+    batch: &mut WriteBatch,
+    oid: OID,
+    doc_state_v2: &[u8],
+    doc_sv_v2: &[u8],
+) -> Result<(), Error> {
+    // state vector is always stored using v1 encoding, so it can be read regardless of the
+    // encoding used by the document state itself
+    let doc_sv_v1 = StateVector::decode_v2(doc_sv_v2)?.encode_v1();
+    let key_doc_v1 = key_doc(oid);
+    let key_doc_v2 = key_doc_v2(oid);
+    let key_sv = key_state_vector(oid);
+    batch.upsert(&key_doc_v2, doc_state_v2);
+    batch.upsert(&key_sv, &doc_sv_v1);
+    batch.remove(&key_doc_v1);
+    batch.remove(&key_state_vector_clock(oid));
     Ok(())
 }
 
+/// Document loaded together with its name.
+pub type NamedDoc = (Box<[u8]>, Doc);
+
+/// A single page of document names together with their OIDs returned by
+/// [DocOps::iter_docs_paged].
+pub type DocsPage = Vec<(Box<[u8]>, OID)>;
+
 pub struct DocsNameIter<I, E>
 where
     I: Iterator<Item = E>,
@@ -465,7 +4885,10 @@ where
     }
 }
 
-pub struct MetadataIter<I, E>(Option<(I, Vec<u8>, Vec<u8>)>)
+/// Metadata entry of a document as a `(key, value)` pair.
+pub type MetaEntry = (Box<[u8]>, Box<[u8]>);
+
+pub struct MetadataIter<I, E>(Option<(I, Vec<u8>, Vec<u8>)>, Option<Arc<dyn ValueCodec>>)
 where
     I: Iterator<Item = E>,
     E: KVEntry;
@@ -482,7 +4905,677 @@ where
         let v = cursor.next()?;
         let key = v.key();
         let value = v.value();
-        let meta_key = &key[7..key.len() - 1];
-        Some((meta_key.into(), value.into()))
+        let meta_key = &key[SUB_KEY_POS + 1..key.len() - 1];
+        // values which can't be decoded are returned as they are stored
+        let value = match decode_meta(self.1.as_deref(), key, value) {
+            Ok(value) => value.into(),
+            Err(_) => value.into(),
+        };
+        Some((meta_key.into(), value))
+    }
+}
+
+pub struct SnapshotIter<I, E>(Option<(I, Vec<u8>, Vec<u8>)>)
+where
+    I: Iterator<Item = E>,
+    E: KVEntry;
+
+impl<I, E> Iterator for SnapshotIter<I, E>
+where
+    I: Iterator<Item = E>,
+    E: KVEntry,
+{
+    type Item = Box<[u8]>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (cursor, _, _) = self.0.as_mut()?;
+        let v = cursor.next()?;
+        Some(doc_snapshot_id(v.key()).into())
+    }
+}
+
+pub struct SubdocIter<I, E>(Option<(I, Vec<u8>, Vec<u8>)>)
+where
+    I: Iterator<Item = E>,
+    E: KVEntry;
+
+impl<I, E> Iterator for SubdocIter<I, E>
+where
+    I: Iterator<Item = E>,
+    E: KVEntry,
+{
+    type Item = Box<[u8]>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (cursor, _, _) = self.0.as_mut()?;
+        let v = cursor.next()?;
+        Some(subdoc_guid(v.key()).into())
+    }
+}
+
+/// Storage statistics of a single document returned by [DocOps::doc_stats].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DocStats {
+    /// Size in bytes of the persisted document state, excluding pending updates.
+    pub doc_state_bytes: u64,
+    /// Number of pending updates, which have not been merged into the document state yet.
+    pub pending_updates: u32,
+    /// Total size in bytes of all pending updates.
+    pub pending_update_bytes: u64,
+    /// Number of metadata entries.
+    pub meta_entries: usize,
+    /// Sequence number of the last pending update.
+    pub last_update_seq: Option<u32>,
+    /// History sequence number of the last update merged into the document state by a flush.
+    /// It's only tracked when [CompactionPolicy::retain_history] is enabled.
+    pub last_flush_seq: Option<u32>,
+}
+
+/// Kind of a raw key-value entry described by [KeyDump].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyKind {
+    /// Mapping of a document name to its OID.
+    Oid,
+    /// Document state (lib0 v1 encoding).
+    DocState,
+    /// Document state (lib0 v2 encoding).
+    DocStateV2,
+    /// State vector of a document state.
+    StateVector,
+    /// Pending update.
+    Update,
+    /// Metadata entry.
+    Meta,
+    /// Snapshot.
+    Snapshot,
+    /// Update kept in the document history.
+    History,
+    /// Subdocument link.
+    Subdoc,
+    /// Document expiry.
+    Expiry,
+    /// Awareness state.
+    Awareness,
+    /// Document lease.
+    Lease,
+    /// Update deduplication window.
+    Dedup,
+    /// Quarantined entry.
+    Quarantine,
+    /// Last access timestamp.
+    Access,
+    /// Version tag.
+    Tag,
+    /// Timestamp of a pending update.
+    UpdateTime,
+    /// Origin of a pending update.
+    UpdateOrigin,
+    /// Indexed search terms.
+    SearchTerms,
+    /// Cached JSON rendering.
+    Json,
+    /// Chunk of a document state.
+    DocChunk,
+    /// Clock of the last update merged into a tracked state vector.
+    StateVectorClock,
+    /// Marker of a flush in progress.
+    FlushMarker,
+    /// Entry of unknown kind.
+    Unknown,
+}
+
+/// Description of a single raw key-value entry belonging to a document (see: [DocOps::debug_dump]).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeyDump {
+    /// Raw key of the entry.
+    pub key: Box<[u8]>,
+    /// Kind of the entry recognized from its key.
+    pub kind: KeyKind,
+    /// Clock of a pending update or sequence number of a history update, if entry refers to one.
+    pub clock: Option<u32>,
+    /// Size of the value in bytes.
+    pub value_len: usize,
+    /// Hex encoded prefix of the value, up to [KeyDump::PREVIEW_LEN] bytes long.
+    pub preview: String,
+}
+
+impl KeyDump {
+    /// Maximum number of value bytes included in [KeyDump::preview].
+    pub const PREVIEW_LEN: usize = 16;
+
+    fn new(key: &[u8], value: &[u8]) -> Self {
+        let (kind, clock) = key_kind(key);
+        let len = value.len().min(Self::PREVIEW_LEN);
+        let mut preview = hex(&value[..len]);
+        if len < value.len() {
+            preview.push_str("..");
+        }
+        KeyDump {
+            key: key.into(),
+            kind,
+            clock,
+            value_len: value.len(),
+            preview,
+        }
+    }
+}
+
+impl std::fmt::Display for KeyDump {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?} key={}", self.kind, hex(&self.key))?;
+        if let Some(clock) = self.clock {
+            write!(f, " clock={}", clock)?;
+        }
+        write!(f, " value[{}]={}", self.value_len, self.preview)
+    }
+}
+
+/// Recognizes a kind of an entry stored under a given `key` together with a clock (or a sequence
+/// number) it refers to.
+fn key_kind(key: &[u8]) -> (KeyKind, Option<u32>) {
+    let clocked = key.len() >= SUB_KEY_POS + 5;
+    match key.get(1) {
+        Some(&KEYSPACE_OID) => (KeyKind::Oid, None),
+        Some(&KEYSPACE_FLUSH) => (KeyKind::FlushMarker, None),
+        Some(&KEYSPACE_DOC) => match key.get(SUB_KEY_POS) {
+            Some(&SUB_DOC) => (KeyKind::DocState, None),
+            Some(&SUB_DOC_V2) => (KeyKind::DocStateV2, None),
+            Some(&SUB_STATE_VEC) => (KeyKind::StateVector, None),
+            Some(&SUB_UPDATE) if clocked => (KeyKind::Update, Some(update_clock(key))),
+            Some(&SUB_META) => (KeyKind::Meta, None),
+            Some(&SUB_SNAPSHOT) => (KeyKind::Snapshot, None),
+            Some(&SUB_HISTORY) if clocked => (KeyKind::History, Some(history_seq(key))),
+            Some(&SUB_SUBDOC) => (KeyKind::Subdoc, None),
+            Some(&SUB_EXPIRY) => (KeyKind::Expiry, None),
+            Some(&SUB_AWARENESS) => (KeyKind::Awareness, None),
+            Some(&SUB_LEASE) => (KeyKind::Lease, None),
+            Some(&SUB_DEDUP) => (KeyKind::Dedup, None),
+            Some(&SUB_QUARANTINE) => (KeyKind::Quarantine, None),
+            Some(&SUB_ACCESS) => (KeyKind::Access, None),
+            Some(&SUB_TAG) => (KeyKind::Tag, None),
+            Some(&SUB_UPDATE_TIME) if clocked => {
+                (KeyKind::UpdateTime, Some(update_time_clock(key)))
+            }
+            Some(&SUB_UPDATE_ORIGIN) if clocked => {
+                (KeyKind::UpdateOrigin, Some(update_origin_clock(key)))
+            }
+            Some(&SUB_SEARCH_TERMS) => (KeyKind::SearchTerms, None),
+            Some(&SUB_JSON) => (KeyKind::Json, None),
+            Some(&SUB_DOC_CHUNK) => (KeyKind::DocChunk, None),
+            Some(&SUB_SV_CLOCK) => (KeyKind::StateVectorClock, None),
+            _ => (KeyKind::Unknown, None),
+        },
+        _ => (KeyKind::Unknown, None),
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    let mut s = String::with_capacity(bytes.len() * 2);
+    for b in bytes {
+        s.push_str(&format!("{:02x}", b));
+    }
+    s
+}
+
+/// Position of a document loaded in chunks (see: [DocOps::load_doc_chunked]), which can be used
+/// to resume loading of its remaining updates (see: [DocOps::load_doc_resume]).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LoadCursor {
+    oid: OID,
+    /// Sequence number of the next update to load or `None` if all updates have been loaded.
+    next_clock: Option<u32>,
+    max_updates: usize,
+}
+
+impl LoadCursor {
+    /// Checks if all pending updates of a document have been loaded.
+    pub fn is_done(&self) -> bool {
+        self.next_clock.is_none()
+    }
+}
+
+/// Name of a document kept in a trash together with a timestamp (in milliseconds since Unix
+/// epoch) of when it was removed.
+pub type TrashedDoc = (Box<[u8]>, u64);
+
+/// Quarantined entry of a document as a pair of its original key and raw stored value.
+pub type QuarantinedEntry = (Vec<u8>, Box<[u8]>);
+
+/// Outcome of a document loaded using [DocOps::load_doc_lenient].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LoadReport {
+    /// Flag determining if any entry of the document was found.
+    pub found: bool,
+    /// Number of pending updates successfully applied on top of the document state.
+    pub applied_updates: u32,
+    /// Keys of entries, which couldn't be decoded and were skipped (see: [DocOps::quarantine]).
+    pub skipped: Vec<Box<[u8]>>,
+}
+
+impl LoadReport {
+    /// Checks if all entries of the document have been loaded successfully.
+    pub fn is_complete(&self) -> bool {
+        self.skipped.is_empty()
+    }
+}
+
+/// Outcome of a database integrity check performed by [DocOps::verify_store].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct VerificationReport {
+    /// Number of document names found.
+    pub docs: usize,
+    /// OIDs mapped to more than one document name, together with those names.
+    pub duplicate_oids: Vec<(OID, Vec<Box<[u8]>>)>,
+    /// OIDs of document entries, which have no document name mapped to them, together with a
+    /// number of their entries.
+    pub orphaned: Vec<(OID, usize)>,
+    /// Keys of entries, which values couldn't be decoded.
+    pub undecodable: Vec<Box<[u8]>>,
+    /// Names of documents, which stored state vectors don't match their document states.
+    pub state_vector_mismatches: Vec<Box<[u8]>>,
+}
+
+impl VerificationReport {
+    /// Checks if no problems have been found.
+    pub fn is_ok(&self) -> bool {
+        self.duplicate_oids.is_empty()
+            && self.orphaned.is_empty()
+            && self.undecodable.is_empty()
+            && self.state_vector_mismatches.is_empty()
+    }
+}
+
+/// Lease over a document held by a single owner (see: [DocOps::acquire_doc_lease]). It's stored
+/// using `{expires_at:8}{owner}` format.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DocLease {
+    /// Identifier of the lease owner.
+    pub owner: Box<[u8]>,
+    /// Timestamp at which lease expires.
+    pub expires_at: u64,
+}
+
+impl DocLease {
+    fn encode(&self) -> Vec<u8> {
+        let mut value = Vec::with_capacity(8 + self.owner.len());
+        value.extend_from_slice(&self.expires_at.to_be_bytes());
+        value.extend_from_slice(&self.owner);
+        value
+    }
+
+    fn decode(key: &[u8], value: &[u8]) -> Result<Self, Error> {
+        if value.len() < 8 {
+            return Err(StoreError::Corrupted { key: key.to_vec() }.into());
+        }
+        let (expires_at, owner) = value.split_at(8);
+        Ok(DocLease {
+            owner: owner.into(),
+            expires_at: u64::from_be_bytes(expires_at.try_into().unwrap()),
+        })
+    }
+}
+
+/// Named version of a document (see: [DocOps::tag_version]). It's stored using
+/// `{has_snapshot:1}{state_vector|snapshot}` format, where both state vector and snapshot use
+/// lib0 v1 encoding.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VersionTag {
+    /// State vector of a document at the moment it was tagged.
+    pub state_vector: StateVector,
+    /// Snapshot of a document state at the moment it was tagged, if it was requested.
+    pub snapshot: Option<Snapshot>,
+}
+
+impl VersionTag {
+    fn encode(&self) -> Vec<u8> {
+        match &self.snapshot {
+            Some(snapshot) => {
+                let mut value = vec![1];
+                value.extend_from_slice(&snapshot.encode_v1());
+                value
+            }
+            None => {
+                let mut value = vec![0];
+                value.extend_from_slice(&self.state_vector.encode_v1());
+                value
+            }
+        }
+    }
+
+    fn decode(key: &[u8], value: &[u8]) -> Result<Self, Error> {
+        match value.split_first() {
+            Some((0, data)) => Ok(VersionTag {
+                state_vector: StateVector::decode_v1(data).with_key(key)?,
+                snapshot: None,
+            }),
+            Some((1, data)) => {
+                let snapshot = Snapshot::decode_v1(data).with_key(key)?;
+                Ok(VersionTag {
+                    state_vector: snapshot.state_map.clone(),
+                    snapshot: Some(snapshot),
+                })
+            }
+            _ => Err(StoreError::Corrupted { key: key.to_vec() }.into()),
+        }
+    }
+}
+
+/// Statistics of a document state rewrite performed by [DocOps::flush_doc_gc].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct FlushStats {
+    /// Size in bytes of the document state and all of its pending updates before the rewrite.
+    pub bytes_before: u64,
+    /// Size in bytes of the document state after the rewrite.
+    pub bytes_after: u64,
+}
+
+impl FlushStats {
+    /// Returns a number of bytes reclaimed by the rewrite.
+    pub fn reclaimed(&self) -> u64 {
+        self.bytes_before.saturating_sub(self.bytes_after)
+    }
+}
+
+/// Counts of entries removed by [DocOps::purge_doc], grouped by their kind. Document name to OID
+/// mapping entry is not included.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PurgeStats {
+    /// Number of entries storing document state and its state vector.
+    pub state: usize,
+    /// Number of pending updates.
+    pub updates: usize,
+    /// Number of metadata entries.
+    pub meta: usize,
+    /// Number of snapshots.
+    pub snapshots: usize,
+    /// Number of updates kept in the document history.
+    pub history: usize,
+    /// Number of subdocuments.
+    pub subdocs: usize,
+    /// Number of entries of unknown kind.
+    pub other: usize,
+}
+
+impl PurgeStats {
+    /// Returns a total number of removed entries.
+    pub fn total(&self) -> usize {
+        self.state
+            + self.updates
+            + self.meta
+            + self.snapshots
+            + self.history
+            + self.subdocs
+            + self.other
+    }
+
+    /// Counts an entry of a document keyspace stored under a given `key`.
+    fn count(&mut self, key: &[u8]) {
+        match key[SUB_KEY_POS] {
+            SUB_DOC | SUB_DOC_V2 | SUB_DOC_CHUNK | SUB_STATE_VEC | SUB_SV_CLOCK => self.state += 1,
+            SUB_UPDATE => self.updates += 1,
+            SUB_META => self.meta += 1,
+            SUB_SNAPSHOT => self.snapshots += 1,
+            SUB_HISTORY => self.history += 1,
+            SUB_SUBDOC => self.subdocs += 1,
+            _ => self.other += 1,
+        }
+    }
+}
+
+/// Options of [DocOps::flush_all].
+#[derive(Debug, Clone, Default)]
+pub struct FlushAllOptions {
+    /// Maximum number of documents flushed by a single call. All documents with pending updates
+    /// are flushed if not set.
+    pub max_docs: Option<usize>,
+    /// Minimum number of pending updates a document must have in order to be flushed. Documents
+    /// with fewer pending updates are skipped.
+    pub min_pending_updates: u32,
+    /// Options used to initialize flushed documents (see: [DocOps::flush_doc_with]).
+    pub doc_options: yrs::Options,
+}
+
+/// Outcome of [DocOps::flush_all].
+#[derive(Debug, Default)]
+pub struct FlushSummary {
+    /// Number of flushed documents.
+    pub flushed: usize,
+    /// Names of documents, which failed to be flushed, together with their errors.
+    pub failed: Vec<(Box<[u8]>, Error)>,
+    /// Flag determining if there are more documents left to flush, because of the
+    /// [FlushAllOptions::max_docs] limit.
+    pub remaining: bool,
+}
+
+/// Counts of entries removed by [DocOps::vacuum].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct VacuumStats {
+    /// OIDs of orphaned documents, which entries have been removed.
+    pub oids: Vec<OID>,
+    /// Counts of removed entries of orphaned documents, grouped by their kind.
+    pub entries: PurgeStats,
+    /// Number of removed expiry index entries and flush markers of orphaned documents.
+    pub index_entries: usize,
+}
+
+impl VacuumStats {
+    /// Returns a total number of removed entries.
+    pub fn total(&self) -> usize {
+        self.entries.total() + self.index_entries
+    }
+}
+
+/// Pending update of a document returned by [DocOps::iter_updates].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PendingUpdate {
+    /// Sequence number of the update, as returned by [DocOps::push_update].
+    pub clock: u32,
+    /// Update bytes, decoded using [Compression] or [StoreOptions::codecs] they were stored with.
+    pub update: Box<[u8]>,
+    /// Flag determining if update has been encoded using lib0 v2 encoding.
+    pub v2: bool,
+    /// Origin stored together with the update (see: [DocOps::push_update_with_origin]).
+    pub origin: Option<Box<[u8]>>,
+}
+
+/// Encodes a document state or update into a format in which it's persisted using a value codec
+/// of a given store (see: [StoreOptions::codecs]). If store has no codec, value is compressed
+/// using its [StoreOptions::compression] and sealed with an integrity checksum.
+fn encode_value<'a, DB: DocOps<'a>>(db: &DB, value: &[u8]) -> Result<Vec<u8>, Error>
+where
+    Error: From<<DB as KVStore<'a>>::Error>,
+{
+    match db.options().value_codec() {
+        Some(codec) => Ok(codec.encode(value)?.into_owned()),
+        None => {
+            let compressed = Compressed(db.options().compression).encode(value)?;
+            Ok(Checksummed.encode(&compressed)?.into_owned())
+        }
+    }
+}
+
+/// Decodes a persisted document state or update stored under a given `key` using a given value
+/// `codec`. If there's no codec, value checksum is verified and value is decompressed.
+pub(crate) fn decode_value<'v>(
+    codec: Option<&dyn ValueCodec>,
+    key: &[u8],
+    value: &'v [u8],
+) -> Result<Cow<'v, [u8]>, Error> {
+    match codec {
+        Some(codec) => codec.decode(key, value).with_key(key),
+        None => match Checksummed.decode(key, value)? {
+            Cow::Borrowed(verified) => Compressed::default().decode(key, verified),
+            Cow::Owned(verified) => Ok(Cow::Owned(
+                Compressed::default().decode(key, &verified)?.into_owned(),
+            )),
+        },
+    }
+}
+
+/// Encodes a metadata value into a format in which it's persisted using a value codec of a given
+/// store (see: [StoreOptions::codecs]). Without a codec metadata is stored as it is.
+fn encode_meta<'a, 'v, DB: DocOps<'a>>(db: &DB, value: &'v [u8]) -> Result<Cow<'v, [u8]>, Error>
+where
+    Error: From<<DB as KVStore<'a>>::Error>,
+{
+    match db.options().value_codec() {
+        Some(codec) => codec.encode(value),
+        None => Ok(Cow::Borrowed(value)),
+    }
+}
+
+/// Decodes a persisted metadata value stored under a given `key` using a given value `codec`.
+fn decode_meta<'v>(
+    codec: Option<&dyn ValueCodec>,
+    key: &[u8],
+    value: &'v [u8],
+) -> Result<Cow<'v, [u8]>, Error> {
+    match codec {
+        Some(codec) => codec.decode(key, value).with_key(key),
+        None => Ok(Cow::Borrowed(value)),
+    }
+}
+
+/// Returns decoded update bytes. Values which can't be decoded are returned as they are stored,
+/// so that the error is reported once they are deserialized.
+fn decoded(codec: Option<&dyn ValueCodec>, key: &[u8], value: &[u8]) -> Box<[u8]> {
+    match decode_value(codec, key, value) {
+        Ok(value) => value.into(),
+        Err(_) => value.into(),
+    }
+}
+
+pub struct UpdatesIter<I, E>(
+    Option<I>,
+    Option<Arc<dyn ValueCodec>>,
+    HashMap<u32, Box<[u8]>>,
+)
+where
+    I: Iterator<Item = E>,
+    E: KVEntry;
+
+impl<I, E> Iterator for UpdatesIter<I, E>
+where
+    I: Iterator<Item = E>,
+    E: KVEntry,
+{
+    type Item = PendingUpdate;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let cursor = self.0.as_mut()?;
+        let v = cursor.next()?;
+        let key = v.key();
+        let clock = update_clock(key);
+        Some(PendingUpdate {
+            clock,
+            update: decoded(self.1.as_deref(), key, v.value()),
+            v2: update_encoding(key) == ENCODING_V2,
+            origin: self.2.remove(&clock),
+        })
+    }
+}
+
+/// Update stored in the document history (see: [DocOps::iter_history]).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HistoryEntry {
+    /// History sequence number of the update.
+    pub seq: u32,
+    /// Update bytes, decoded using [Compression] or [StoreOptions::codecs] they were stored with.
+    pub update: Box<[u8]>,
+    /// Flag determining if update has been encoded using lib0 v2 encoding.
+    pub v2: bool,
+}
+
+pub struct HistoryIter<I, E>(Option<I>, Option<Arc<dyn ValueCodec>>)
+where
+    I: Iterator<Item = E>,
+    E: KVEntry;
+
+impl<I, E> Iterator for HistoryIter<I, E>
+where
+    I: Iterator<Item = E>,
+    E: KVEntry,
+{
+    type Item = HistoryEntry;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let cursor = self.0.as_mut()?;
+        let v = cursor.next()?;
+        let key = v.key();
+        Some(HistoryEntry {
+            seq: history_seq(key),
+            update: decoded(self.1.as_deref(), key, v.value()),
+            v2: update_encoding(key) == ENCODING_V2,
+        })
+    }
+}
+
+/// Kind of a document change recorded in the change log (see: [DocOps::iter_changes]).
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChangeKind {
+    /// New updates have been pushed (see: [DocOps::push_update]).
+    Update = 1,
+    /// Document state has been inserted or replaced (see: [DocOps::insert_doc]).
+    Insert = 2,
+    /// Document metadata entry has been inserted or removed (see: [DocOps::insert_meta]).
+    Meta = 3,
+    /// Document has been removed (see: [DocOps::clear_doc]).
+    Clear = 4,
+}
+
+impl ChangeKind {
+    fn from_u8(value: u8) -> Option<Self> {
+        match value {
+            1 => Some(ChangeKind::Update),
+            2 => Some(ChangeKind::Insert),
+            3 => Some(ChangeKind::Meta),
+            4 => Some(ChangeKind::Clear),
+            _ => None,
+        }
+    }
+}
+
+/// Entry of the change log returned by [DocOps::iter_changes].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Change {
+    /// Sequence number of the change log entry.
+    pub seq: u64,
+    /// OID of a changed document.
+    pub oid: OID,
+    /// Name of a changed document.
+    pub name: Box<[u8]>,
+    /// Kind of a change.
+    pub kind: ChangeKind,
+}
+
+pub struct ChangesIter<I, E>(I)
+where
+    I: Iterator<Item = E>,
+    E: KVEntry;
+
+impl<I, E> Iterator for ChangesIter<I, E>
+where
+    I: Iterator<Item = E>,
+    E: KVEntry,
+{
+    type Item = Change;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let v = self.0.next()?;
+            let value = v.value();
+            if value.len() > OID_LEN {
+                // entries of unknown kinds may have been recorded by newer versions, skip them
+                if let Some(kind) = ChangeKind::from_u8(value[0]) {
+                    return Some(Change {
+                        seq: changelog_seq(v.key()),
+                        oid: oid_from_bytes(&value[1..=OID_LEN]),
+                        name: value[1 + OID_LEN..].into(),
+                        kind,
+                    });
+                }
+            }
+        }
     }
 }
@@ -0,0 +1,2202 @@
+use crate::codec::{CodecChain, ValueCodec};
+use crate::compression::Compression;
+use crate::export::JsonExport;
+use crate::metrics::{Metrics, StoreMetrics};
+use crate::observer::{UpdateObservers, WriteHook};
+use crate::options::StoreOptions;
+use crate::validation::UpdateValidator;
+use crate::{
+    CompactionPolicy, DocOps, KVEntry, KVStore, OidAllocation, RetentionPolicy, UpdateLimits,
+};
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::convert::Infallible;
+use std::ops::Bound;
+use std::sync::Arc;
+
+/// In-memory reference implementation of [KVStore] trait, backed by an ordered [BTreeMap]. It
+/// follows the [KVStore] contract exactly, which makes it a good fit for unit tests of code
+/// using [DocOps] without a need to set up a real database.
+///
+/// All returned values are copied out of the store.
+#[derive(Debug, Default)]
+pub struct MemKVStore {
+    entries: RefCell<BTreeMap<Vec<u8>, Vec<u8>>>,
+    options: StoreOptions,
+    update_observers: UpdateObservers,
+    metrics: Metrics,
+}
+
+impl MemKVStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets [StoreOptions] used to configure current store, replacing all settings set so far.
+    ///
+    /// # Panics
+    ///
+    /// Panics if options can't be applied without wrapping the store with
+    /// [crate::options::ConfiguredStore] (see: [StoreOptions::assert_unscoped]).
+    pub fn with_options(mut self, options: StoreOptions) -> Self {
+        options.assert_unscoped();
+        self.options = options;
+        self
+    }
+
+    /// Sets a [CompactionPolicy] used to automatically flush documents, which have accumulated
+    /// too many pending updates.
+    pub fn with_compaction_policy(mut self, policy: CompactionPolicy) -> Self {
+        self.options.compaction_policy = policy;
+        self
+    }
+
+    /// Sets an [UpdateObservers] registry notified about every persisted document update (see:
+    /// [DocOps::observe_updates]). The same registry can be shared by multiple stores.
+    pub fn with_update_observers(mut self, observers: UpdateObservers) -> Self {
+        self.update_observers = observers;
+        self
+    }
+
+    /// Sets a [Compression] applied to document states and updates written by current store.
+    pub fn with_compression(mut self, compression: Compression) -> Self {
+        self.options.compression = compression;
+        self
+    }
+
+    /// Sets an [OidAllocation] strategy used to assign OIDs to documents persisted for the first
+    /// time by current store.
+    pub fn with_oid_allocation(mut self, allocation: OidAllocation) -> Self {
+        self.options.oid_allocation = allocation;
+        self
+    }
+
+    /// Enables or disables recording document changes in a global change log (see:
+    /// [DocOps::iter_changes]).
+    pub fn with_changelog(mut self, enabled: bool) -> Self {
+        self.options.changelog = enabled;
+        self
+    }
+
+    /// Sets a [Metrics] receiver, which current store reports counts and latencies of its
+    /// operations into (see: [DocOps::metrics]).
+    pub fn with_metrics(mut self, metrics: Metrics) -> Self {
+        self.metrics = metrics;
+        self
+    }
+
+    /// Sets a number of recently pushed updates of each document, which are remembered in order to
+    /// skip persisting byte-identical updates pushed again, i.e. by retrying clients (see:
+    /// [StoreOptions::update_dedup_window]). Deduplication is disabled when set to 0.
+    pub fn with_update_dedup(mut self, window: usize) -> Self {
+        self.options.update_dedup_window = window;
+        self
+    }
+
+    /// Sets a size in bytes, starting from which document states are stored only once in
+    /// a content addressed blob keyspace and referenced from their documents (see:
+    /// [StoreOptions::blob_threshold]). Blob storage is disabled when set to 0.
+    pub fn with_blob_threshold(mut self, threshold: usize) -> Self {
+        self.options.blob_threshold = threshold;
+        self
+    }
+
+    /// Sets a maximum size in bytes of a document state stored under a single key. Larger states
+    /// are split into chunks (see: [StoreOptions::doc_chunk_size]). Chunking is disabled when set
+    /// to 0.
+    pub fn with_doc_chunk_size(mut self, size: usize) -> Self {
+        self.options.doc_chunk_size = size;
+        self
+    }
+
+    /// Sets a [ValueCodec] used to encode document states, updates and metadata persisted by
+    /// current store (see: [StoreOptions::codecs]). It replaces a [Compression] set on the store.
+    pub fn with_value_codec(mut self, codec: Arc<dyn ValueCodec>) -> Self {
+        self.options.codecs = CodecChain::from(codec);
+        self
+    }
+
+    /// Enables or disables moving documents removed by [DocOps::clear_doc] into a trash, from
+    /// which they can be restored (see: [StoreOptions::soft_delete]).
+    pub fn with_soft_delete(mut self, enabled: bool) -> Self {
+        self.options.soft_delete = enabled;
+        self
+    }
+
+    /// Enables or disables recording timestamps of pushed updates (see:
+    /// [StoreOptions::update_timestamps]).
+    pub fn with_update_timestamps(mut self, enabled: bool) -> Self {
+        self.options.update_timestamps = enabled;
+        self
+    }
+
+    /// Enables or disables maintaining document state vectors on every pushed update (see:
+    /// [StoreOptions::track_state_vector]).
+    pub fn with_state_vector_tracking(mut self, enabled: bool) -> Self {
+        self.options.track_state_vector = enabled;
+        self
+    }
+
+    /// Sets a [RetentionPolicy] used to determine which pending updates are merged into the
+    /// document state by [DocOps::enforce_retention].
+    pub fn with_retention_policy(mut self, policy: RetentionPolicy) -> Self {
+        self.options.retention_policy = policy;
+        self
+    }
+
+    /// Sets [UpdateLimits] used to reject oversized updates before they are written (see:
+    /// [StoreOptions::update_limits]).
+    pub fn with_update_limits(mut self, limits: UpdateLimits) -> Self {
+        self.options.update_limits = limits;
+        self
+    }
+
+    /// Sets an [UpdateValidator] called with every update and document state before it's
+    /// persisted, which can veto the write (see: [StoreOptions::update_validator]).
+    pub fn with_update_validator(mut self, validator: Arc<dyn UpdateValidator>) -> Self {
+        self.options.update_validator = Some(validator);
+        self
+    }
+
+    /// Sets a [WriteHook] called with a decoded summary of every change written by current store
+    /// (see: [StoreOptions::write_hook]).
+    pub fn with_write_hook(mut self, hook: Arc<dyn WriteHook>) -> Self {
+        self.options.write_hook = Some(hook);
+        self
+    }
+
+    /// Sets a [JsonExport] used to maintain a cached JSON rendering of documents refreshed every
+    /// time they are flushed (see: [StoreOptions::json_export]).
+    pub fn with_json_export(mut self, export: JsonExport) -> Self {
+        self.options.json_export = Some(Arc::new(export));
+        self
+    }
+
+    /// Returns a number of all key-value entries stored.
+    pub fn len(&self) -> usize {
+        self.entries.borrow().len()
+    }
+
+    /// Checks if current store contains no entries.
+    pub fn is_empty(&self) -> bool {
+        self.entries.borrow().is_empty()
+    }
+
+    /// Returns a copy of all key-value entries stored.
+    pub fn entries(&self) -> BTreeMap<Vec<u8>, Vec<u8>> {
+        self.entries.borrow().clone()
+    }
+}
+
+impl From<BTreeMap<Vec<u8>, Vec<u8>>> for MemKVStore {
+    fn from(entries: BTreeMap<Vec<u8>, Vec<u8>>) -> Self {
+        MemKVStore {
+            entries: RefCell::new(entries),
+            options: StoreOptions::default(),
+            update_observers: UpdateObservers::default(),
+            metrics: Metrics::default(),
+        }
+    }
+}
+
+impl<'a> DocOps<'a> for MemKVStore {
+    fn options(&self) -> &StoreOptions {
+        &self.options
+    }
+
+    fn update_observers(&self) -> Option<&UpdateObservers> {
+        Some(&self.update_observers)
+    }
+
+    fn metrics(&self) -> Option<&dyn StoreMetrics> {
+        self.metrics.get()
+    }
+}
+
+impl<'a> KVStore<'a> for MemKVStore {
+    type Error = Infallible;
+    type Cursor = std::vec::IntoIter<MemEntry>;
+    type Entry = MemEntry;
+    type Return = Vec<u8>;
+
+    fn get(&self, key: &[u8]) -> Result<Option<Self::Return>, Self::Error> {
+        Ok(self.entries.borrow().get(key).cloned())
+    }
+
+    fn upsert(&self, key: &[u8], value: &[u8]) -> Result<(), Self::Error> {
+        self.entries
+            .borrow_mut()
+            .insert(key.to_vec(), value.to_vec());
+        Ok(())
+    }
+
+    fn remove(&self, key: &[u8]) -> Result<(), Self::Error> {
+        self.entries.borrow_mut().remove(key);
+        Ok(())
+    }
+
+    fn remove_range(&self, from: &[u8], to: &[u8]) -> Result<(), Self::Error> {
+        if from > to {
+            return Ok(());
+        }
+        let mut entries = self.entries.borrow_mut();
+        let keys: Vec<_> = entries
+            .range::<[u8], _>((Bound::Included(from), Bound::Included(to)))
+            .map(|(key, _)| key.clone())
+            .collect();
+        for key in keys {
+            entries.remove(&key);
+        }
+        Ok(())
+    }
+
+    fn iter_range(&self, from: &[u8], to: &[u8]) -> Result<Self::Cursor, Self::Error> {
+        if from > to {
+            return Ok(Vec::new().into_iter());
+        }
+        let entries: Vec<_> = self
+            .entries
+            .borrow()
+            .range::<[u8], _>((Bound::Included(from), Bound::Included(to)))
+            .map(|(key, value)| MemEntry::new(key.clone(), value.clone()))
+            .collect();
+        Ok(entries.into_iter())
+    }
+
+    fn contains_range(&self, from: &[u8], to: &[u8]) -> Result<bool, Self::Error> {
+        if from > to {
+            return Ok(false);
+        }
+        let entries = self.entries.borrow();
+        let mut range = entries.range::<[u8], _>((Bound::Included(from), Bound::Included(to)));
+        Ok(range.next().is_some())
+    }
+
+    fn peek_back(&self, key: &[u8]) -> Result<Option<Self::Entry>, Self::Error> {
+        let entries = self.entries.borrow();
+        let last = entries
+            .range::<[u8], _>((Bound::Unbounded, Bound::Excluded(key)))
+            .next_back()
+            .map(|(key, value)| MemEntry::new(key.clone(), value.clone()));
+        Ok(last)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MemEntry {
+    key: Vec<u8>,
+    value: Vec<u8>,
+}
+
+impl MemEntry {
+    fn new(key: Vec<u8>, value: Vec<u8>) -> Self {
+        MemEntry { key, value }
+    }
+}
+
+impl From<MemEntry> for (Vec<u8>, Vec<u8>) {
+    fn from(entry: MemEntry) -> Self {
+        (entry.key, entry.value)
+    }
+}
+
+impl KVEntry for MemEntry {
+    fn key(&self) -> &[u8] {
+        &self.key
+    }
+
+    fn value(&self) -> &[u8] {
+        &self.value
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::archive::{ArchiveSink, DocArchive};
+    use crate::batch::MultiDocBatch;
+    use crate::blob::is_blob_ref;
+    use crate::cancel::Cancel;
+    use crate::checksum::is_sealed;
+    use crate::chunk::Manifest;
+    use crate::codec::{Checksummed, CodecChain, Compressed};
+    use crate::compression::Compression;
+    use crate::error::{context, find, Error, StoreError};
+    use crate::keys::{
+        key_doc, key_doc_chunk, key_flush_marker, key_meta, key_oid, key_state_vector, key_update,
+        oid_from_bytes, KEYSPACE_BLOB, KEY_OID_COUNTER, OID, SUB_DOC_CHUNK, SUB_KEY_POS, V1,
+    };
+    use crate::memory::MemKVStore;
+    use crate::metrics::{Metrics, StoreMetrics};
+    use crate::observer::{ChangeSummary, WriteHook};
+    use crate::testing::properties::{verify_key_layout, verify_key_ranges};
+    use crate::testing::verify_kvstore_contract;
+    use crate::validation::RejectDeletions;
+    use crate::{
+        flush_marker, get_oid, ChangeKind, CompactionPolicy, DocOps, FlushAllOptions, KVEntry,
+        KVStore, KeyKind, RetentionPolicy, UpdateLimits,
+    };
+    use std::cell::RefCell;
+    use std::collections::HashMap;
+    use std::sync::{Arc, Mutex};
+    use std::time::{Duration, Instant};
+    use yrs::updates::decoder::Decode;
+    use yrs::updates::encoder::Encode;
+    use yrs::{
+        Doc, GetString, Origin, ReadTxn, StateVector, Text, Transact, TransactionMut, Update,
+    };
+
+    #[test]
+    fn peek_back() {
+        let db = MemKVStore::new();
+        for key in [1u8, 2, 5, 7] {
+            db.upsert(&[key], &[key]).unwrap();
+        }
+        let e = db.peek_back(&[4]).unwrap().unwrap();
+        assert_eq!(e.key(), &[2]);
+        let e = db.peek_back(&[5]).unwrap().unwrap();
+        assert_eq!(e.key(), &[2]);
+        let e = db.peek_back(&[8]).unwrap().unwrap();
+        assert_eq!(e.key(), &[7]);
+        assert!(db.peek_back(&[1]).unwrap().is_none());
+    }
+
+    #[test]
+    fn ranges_are_inclusive() {
+        let db = MemKVStore::new();
+        for key in [1u8, 2, 5, 7] {
+            db.upsert(&[key], &[key]).unwrap();
+        }
+        let keys: Vec<_> = db
+            .iter_range(&[2], &[7])
+            .unwrap()
+            .map(|e| e.key().to_vec())
+            .collect();
+        assert_eq!(keys, vec![vec![2], vec![5], vec![7]]);
+
+        db.remove_range(&[2], &[5]).unwrap();
+        let keys: Vec<_> = db
+            .iter_range(&[0], &[255])
+            .unwrap()
+            .map(|e| e.key().to_vec())
+            .collect();
+        assert_eq!(keys, vec![vec![1], vec![7]]);
+    }
+
+    #[test]
+    fn doc_roundtrip() {
+        let db = MemKVStore::new();
+        let doc = Doc::new();
+        let text = doc.get_or_insert_text("text");
+        text.push(&mut doc.transact_mut(), "hello");
+        db.insert_doc("doc", &doc.transact()).unwrap();
+
+        let doc = Doc::new();
+        let text = doc.get_or_insert_text("text");
+        let mut txn = doc.transact_mut();
+        assert!(db.load_doc("doc", &mut txn).unwrap());
+        assert_eq!(text.get_string(&txn), "hello");
+
+        db.clear_doc("doc").unwrap();
+        let keys: Vec<_> = db.entries().into_keys().collect();
+        assert_eq!(keys, vec![KEY_OID_COUNTER.to_vec()]);
+    }
+
+    #[test]
+    fn load_doc_with_origin() {
+        let db = MemKVStore::new();
+        let doc = Doc::new();
+        let text = doc.get_or_insert_text("text");
+        text.push(&mut doc.transact_mut(), "hello");
+        db.insert_doc("doc", &doc.transact()).unwrap();
+
+        let doc = Doc::new();
+        let origins = Arc::new(Mutex::new(Vec::new()));
+        {
+            let origins = origins.clone();
+            doc.observe_update_v1("origins", move |txn: &TransactionMut, _| {
+                origins.lock().unwrap().push(txn.origin().cloned());
+            })
+            .unwrap();
+        }
+        assert!(db.load_doc_with_origin("doc", &doc, "storage").unwrap());
+        assert!(!db.load_doc_with_origin("none", &doc, "storage").unwrap());
+        let text = doc.get_or_insert_text("text");
+        text.push(&mut doc.transact_mut(), " world");
+
+        let origins = origins.lock().unwrap();
+        assert_eq!(*origins, vec![Some(Origin::from("storage")), None]);
+    }
+
+    #[test]
+    fn kvstore_contract() {
+        let db = MemKVStore::new();
+        verify_kvstore_contract(&db);
+        assert!(db.is_empty());
+    }
+
+    #[test]
+    fn key_properties() {
+        let db = MemKVStore::new();
+        verify_key_layout();
+        verify_key_ranges(&db);
+        assert!(db.is_empty());
+    }
+
+    #[test]
+    fn commit_batch() {
+        let db = MemKVStore::new();
+        db.upsert(&[1], &[1]).unwrap();
+        db.upsert(&[2], &[2]).unwrap();
+
+        let mut batch = db.batch();
+        batch.upsert(&[3], &[3]);
+        batch.remove(&[1]);
+        batch.upsert(&[4], &[4]);
+        batch.remove_range(&[4], &[5]);
+        assert_eq!(batch.len(), 4);
+        db.commit_batch(batch).unwrap();
+
+        let keys: Vec<_> = db.entries().into_keys().collect();
+        assert_eq!(keys, vec![vec![2], vec![3]]);
+    }
+
+    #[test]
+    fn compaction_counts_pending_updates() {
+        let doc = Doc::new();
+        let text = doc.get_or_insert_text("text");
+        let mut updates = Vec::new();
+        for chunk in ["a", "b", "c", "d", "e"] {
+            let sv = doc.transact().state_vector();
+            text.push(&mut doc.transact_mut(), chunk);
+            updates.push(doc.transact().encode_diff_v1(&sv));
+        }
+        let db = MemKVStore::new().with_compaction_policy(CompactionPolicy {
+            max_pending_updates: Some(3),
+            ..CompactionPolicy::default()
+        });
+        for update in updates[..3].iter() {
+            db.push_update("doc", update).unwrap();
+        }
+        assert_eq!(db.iter_updates("doc").unwrap().count(), 3);
+        db.trim_updates("doc", 2).unwrap();
+
+        // clocks keep growing after trimming, but only 2 updates are pending
+        assert_eq!(db.push_update("doc", &updates[3]).unwrap(), 4);
+        assert_eq!(db.iter_updates("doc").unwrap().count(), 2);
+        db.push_updates("doc", [updates[4].as_slice()]).unwrap();
+        assert_eq!(db.iter_updates("doc").unwrap().count(), 3);
+
+        // limit is exceeded once the update count exceeds it, not the clock
+        db.push_update("doc", &updates[0]).unwrap();
+        assert_eq!(db.iter_updates("doc").unwrap().count(), 0);
+    }
+
+    #[test]
+    fn oid_counter() {
+        fn oid(db: &MemKVStore, name: &str) -> OID {
+            let value = db.get(&key_oid(name.as_bytes())).unwrap().unwrap();
+            oid_from_bytes(&value)
+        }
+
+        let db = MemKVStore::new();
+        let doc = Doc::new();
+        // OIDs don't follow the order of document names
+        for name in ["c", "b", "a"] {
+            db.insert_doc(name, &doc.transact()).unwrap();
+        }
+        assert_eq!(oid(&db, "a"), 3);
+
+        // OIDs of removed documents are not reused
+        db.clear_doc("a").unwrap();
+        db.insert_doc("d", &doc.transact()).unwrap();
+        assert_eq!(oid(&db, "d"), 4);
+
+        // counter missing in databases which didn't use it is initialized from existing OIDs
+        db.remove(&KEY_OID_COUNTER).unwrap();
+        db.insert_doc("a", &doc.transact()).unwrap();
+        assert_eq!(oid(&db, "a"), 5);
+
+        // malformed counter is reported instead of being overwritten
+        db.upsert(&KEY_OID_COUNTER, &[1, 2, 3]).unwrap();
+        let err = db.insert_doc("e", &doc.transact()).unwrap_err();
+        match err.downcast_ref::<StoreError>() {
+            Some(StoreError::Corrupted { key }) => {
+                assert_eq!(key.as_slice(), KEY_OID_COUNTER.as_ref())
+            }
+            other => panic!("expected corruption error, got: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn update_clock_exhausted() {
+        let db = MemKVStore::new();
+        let doc = Doc::new();
+        let text = doc.get_or_insert_text("text");
+        text.push(&mut doc.transact_mut(), "hello");
+        let update = doc.transact().encode_diff_v1(&StateVector::default());
+        assert_eq!(db.push_update("doc", &update).unwrap(), 1);
+
+        // move pending update to the last available clock
+        let value = db.get(&key_update(1, 1)).unwrap().unwrap();
+        db.remove(&key_update(1, 1)).unwrap();
+        db.upsert(&key_update(1, u32::MAX), &value).unwrap();
+
+        // next update flushes pending ones, so that clocks start over
+        let sv = doc.transact().state_vector();
+        text.push(&mut doc.transact_mut(), " world");
+        let update = doc.transact().encode_diff_v1(&sv);
+        assert_eq!(db.push_update("doc", &update).unwrap(), 1);
+        assert!(db.get(&key_update(1, u32::MAX)).unwrap().is_none());
+
+        let doc = Doc::new();
+        let text = doc.get_or_insert_text("text");
+        assert!(db.load_doc("doc", &mut doc.transact_mut()).unwrap());
+        assert_eq!(text.get_string(&doc.transact()), "hello world");
+    }
+
+    #[test]
+    fn recover_interrupted_flush() {
+        let db = MemKVStore::new();
+        let doc = Doc::new();
+        let text = doc.get_or_insert_text("text");
+        for chunk in ["hello", " world", "!"] {
+            let sv = doc.transact().state_vector();
+            text.push(&mut doc.transact_mut(), chunk);
+            let update = doc.transact().encode_diff_v1(&sv);
+            db.push_update("doc", &update).unwrap();
+        }
+
+        // flush of the first two updates has been interrupted right after writing its marker
+        db.upsert(&key_flush_marker(1), &flush_marker(2, None))
+            .unwrap();
+
+        assert_eq!(db.recover().unwrap(), 1);
+        assert!(db.get(&key_flush_marker(1)).unwrap().is_none());
+        assert!(db.get(&key_update(1, 1)).unwrap().is_none());
+        assert!(db.get(&key_update(1, 2)).unwrap().is_none());
+        assert!(db.get(&key_update(1, 3)).unwrap().is_some());
+        assert_eq!(db.recover().unwrap(), 0);
+
+        let doc = Doc::new();
+        let text = doc.get_or_insert_text("text");
+        assert!(db.load_doc("doc", &mut doc.transact_mut()).unwrap());
+        assert_eq!(text.get_string(&doc.transact()), "hello world!");
+    }
+
+    #[test]
+    fn export_import_doc() {
+        let src = MemKVStore::new();
+        let doc = Doc::new();
+        let text = doc.get_or_insert_text("text");
+        text.push(&mut doc.transact_mut(), "hello");
+        src.insert_doc("doc", &doc.transact()).unwrap();
+        let sv = doc.transact().state_vector();
+        text.push(&mut doc.transact_mut(), " world");
+        let update = doc.transact().encode_diff_v2(&sv);
+        src.push_update_v2("doc", &update).unwrap();
+        src.insert_meta("doc", "key", &[1, 2, 3]).unwrap();
+
+        assert!(src.export_doc("missing").unwrap().is_none());
+        let archive = src.export_doc("doc").unwrap().unwrap();
+        assert_eq!(archive.updates.len(), 1);
+        let archive = DocArchive::decode(&archive.encode()).unwrap();
+
+        let dst = MemKVStore::new();
+        dst.import_doc("copy", &archive).unwrap();
+        let doc = Doc::new();
+        let text = doc.get_or_insert_text("text");
+        assert!(dst.load_doc("copy", &mut doc.transact_mut()).unwrap());
+        assert_eq!(text.get_string(&doc.transact()), "hello world");
+        assert_eq!(dst.iter_updates("copy").unwrap().count(), 1);
+        let meta = dst.get_meta("copy", "key").unwrap();
+        assert_eq!(meta, Some(vec![1, 2, 3]));
+        assert_eq!(dst.export_doc("copy").unwrap().unwrap(), archive);
+
+        let err = dst.import_doc("copy", &archive).unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<StoreError>(),
+            Some(StoreError::DocumentExists(_))
+        ));
+    }
+
+    #[test]
+    fn export_import_all() {
+        let src = MemKVStore::new();
+        for name in ["a", "b", "c"] {
+            let doc = Doc::new();
+            let text = doc.get_or_insert_text("text");
+            text.push(&mut doc.transact_mut(), name);
+            src.insert_doc(name, &doc.transact()).unwrap();
+            src.insert_meta(name, "name", name.as_bytes()).unwrap();
+        }
+
+        let mut dump = Vec::new();
+        assert_eq!(src.export_all(&mut dump).unwrap(), 3);
+
+        let dst = MemKVStore::new();
+        assert_eq!(dst.import_all(&mut dump.as_slice()).unwrap(), 3);
+        for name in ["a", "b", "c"] {
+            let doc = Doc::new();
+            let text = doc.get_or_insert_text("text");
+            assert!(dst.load_doc(name, &mut doc.transact_mut()).unwrap());
+            assert_eq!(text.get_string(&doc.transact()), name);
+            let meta = dst.get_meta(name, "name").unwrap();
+            assert_eq!(meta, Some(name.as_bytes().to_vec()));
+        }
+
+        // truncated dumps are rejected
+        let dst = MemKVStore::new();
+        let mut truncated = &dump[..dump.len() - 1];
+        assert!(dst.import_all(&mut truncated).is_err());
+    }
+
+    #[derive(Default)]
+    struct MemArchiveSink(RefCell<HashMap<Vec<u8>, Vec<u8>>>);
+
+    impl ArchiveSink for MemArchiveSink {
+        fn store(&self, name: &[u8], archive: &DocArchive) -> Result<Vec<u8>, Error> {
+            self.0.borrow_mut().insert(name.to_vec(), archive.encode());
+            Ok(name.to_vec())
+        }
+
+        fn load(&self, location: &[u8]) -> Result<Option<DocArchive>, Error> {
+            match self.0.borrow().get(location) {
+                Some(data) => Ok(Some(DocArchive::decode(data)?)),
+                None => Ok(None),
+            }
+        }
+
+        fn remove(&self, location: &[u8]) -> Result<(), Error> {
+            self.0.borrow_mut().remove(location);
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn archive_restore_doc() {
+        let db = MemKVStore::new();
+        let sink = MemArchiveSink::default();
+        let doc = Doc::new();
+        let text = doc.get_or_insert_text("text");
+        text.push(&mut doc.transact_mut(), "hello");
+        db.insert_doc("doc", &doc.transact()).unwrap();
+        let sv = doc.transact().state_vector();
+        text.push(&mut doc.transact_mut(), " world");
+        db.push_update("doc", &doc.transact().encode_diff_v1(&sv))
+            .unwrap();
+        db.insert_meta("doc", "key", &[1]).unwrap();
+
+        assert!(db.archive_doc("missing", &sink).unwrap().is_none());
+        let location = db.archive_doc("doc", &sink).unwrap().unwrap();
+        assert_eq!(db.get_archive_location("doc").unwrap(), Some(location));
+        assert!(!db.doc_exists("doc").unwrap());
+        // pending updates have been merged before archiving
+        let archived = sink.load(b"doc").unwrap().unwrap();
+        assert!(archived.updates.is_empty());
+
+        assert!(!db.restore_doc("missing", &sink).unwrap());
+        assert!(db.restore_doc("doc", &sink).unwrap());
+        assert!(db.get_archive_location("doc").unwrap().is_none());
+        assert!(sink.0.borrow().is_empty());
+        let doc = Doc::new();
+        let text = doc.get_or_insert_text("text");
+        assert!(db.load_doc("doc", &mut doc.transact_mut()).unwrap());
+        assert_eq!(text.get_string(&doc.transact()), "hello world");
+        assert_eq!(db.get_meta("doc", "key").unwrap(), Some(vec![1]));
+
+        // tombstone without an archive
+        db.archive_doc("doc", &sink).unwrap().unwrap();
+        sink.0.borrow_mut().clear();
+        let err = db.restore_doc("doc", &sink).unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<StoreError>(),
+            Some(StoreError::ArchiveNotFound(_))
+        ));
+    }
+
+    #[test]
+    fn trash_restore_doc() {
+        let db = MemKVStore::new().with_soft_delete(true);
+        let doc = Doc::new();
+        let text = doc.get_or_insert_text("text");
+        text.push(&mut doc.transact_mut(), "hello");
+        db.insert_doc("doc", &doc.transact()).unwrap();
+        db.insert_meta("doc", "key", &[1]).unwrap();
+
+        db.clear_doc("doc").unwrap();
+        assert!(!db.doc_exists("doc").unwrap());
+        let trashed = db.trashed_docs().unwrap();
+        assert_eq!(trashed.len(), 1);
+        assert_eq!(trashed[0].0.as_ref(), b"doc");
+
+        // trashed document can't be restored over a newly created one
+        db.insert_doc("doc", &Doc::new().transact()).unwrap();
+        let err = db.restore_trashed("doc").unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<StoreError>(),
+            Some(StoreError::DocumentExists(_))
+        ));
+        db.purge_doc("doc", true).unwrap();
+        assert_eq!(db.trashed_docs().unwrap().len(), 1);
+
+        assert!(!db.restore_trashed("missing").unwrap());
+        assert!(db.restore_trashed("doc").unwrap());
+        assert!(db.trashed_docs().unwrap().is_empty());
+        let doc = Doc::new();
+        let text = doc.get_or_insert_text("text");
+        assert!(db.load_doc("doc", &mut doc.transact_mut()).unwrap());
+        assert_eq!(text.get_string(&doc.transact()), "hello");
+        assert_eq!(db.get_meta("doc", "key").unwrap(), Some(vec![1]));
+
+        assert!(db.trash_doc("doc").unwrap());
+        let deleted_at = db.trashed_docs().unwrap()[0].1;
+        assert_eq!(db.purge_trash(deleted_at - 1).unwrap(), 0);
+        assert_eq!(db.purge_trash(deleted_at).unwrap(), 1);
+        assert!(!db.restore_trashed("doc").unwrap());
+    }
+
+    #[test]
+    fn version_tags() {
+        let db = MemKVStore::new();
+        assert!(db.tag_version("doc", "v1", false).unwrap().is_none());
+        let doc = Doc::new();
+        let text = doc.get_or_insert_text("text");
+        text.push(&mut doc.transact_mut(), "hello");
+        db.insert_doc("doc", &doc.transact()).unwrap();
+
+        let v1 = db.tag_version("doc", "v1", false).unwrap().unwrap();
+        assert_eq!(v1.state_vector, doc.transact().state_vector());
+        assert!(v1.snapshot.is_none());
+
+        let v1_state = doc
+            .transact()
+            .encode_state_as_update_v1(&StateVector::default());
+        text.push(&mut doc.transact_mut(), " world");
+        let update = doc.transact().encode_diff_v1(&v1.state_vector);
+        db.push_update("doc", &update).unwrap();
+        let v2 = db.tag_version("doc", "v2", true).unwrap().unwrap();
+        assert!(v2.snapshot.is_some());
+        assert_eq!(db.get_tag("doc", "v2").unwrap(), Some(v2));
+
+        let tags = db.list_tags("doc").unwrap();
+        assert_eq!(tags, vec![b"v1".to_vec().into(), b"v2".to_vec().into()]);
+
+        // changes made since a tag applied on top of a tagged version recreate current state
+        let diff = db.diff_since_tag("doc", "v1").unwrap().unwrap();
+        let restored = Doc::new();
+        let restored_text = restored.get_or_insert_text("text");
+        {
+            let mut txn = restored.transact_mut();
+            txn.apply_update(Update::decode_v1(&v1_state).unwrap())
+                .unwrap();
+            txn.apply_update(Update::decode_v1(&diff).unwrap()).unwrap();
+        }
+        assert_eq!(
+            restored_text.get_string(&restored.transact()),
+            "hello world"
+        );
+        assert!(db.diff_since_tag("doc", "missing").unwrap().is_none());
+
+        db.remove_tag("doc", "v1").unwrap();
+        assert_eq!(db.list_tags("doc").unwrap().len(), 1);
+    }
+
+    #[test]
+    fn diff_snapshots() {
+        let db = MemKVStore::new();
+        let doc = Doc::new();
+        let text = doc.get_or_insert_text("text");
+        text.push(&mut doc.transact_mut(), "hello world");
+        db.insert_doc("doc", &doc.transact()).unwrap();
+        db.tag_version("doc", "v1", false).unwrap().unwrap();
+        let v1_state = doc
+            .transact()
+            .encode_state_as_update_v1(&StateVector::default());
+
+        let sv = doc.transact().state_vector();
+        text.remove_range(&mut doc.transact_mut(), 5, 6);
+        text.push(&mut doc.transact_mut(), "!");
+        db.push_update("doc", &doc.transact().encode_diff_v1(&sv))
+            .unwrap();
+        db.tag_version("doc", "v2", true).unwrap().unwrap();
+
+        let sv = doc.transact().state_vector();
+        text.push(&mut doc.transact_mut(), "?");
+        db.push_update("doc", &doc.transact().encode_diff_v1(&sv))
+            .unwrap();
+
+        let diff = db.diff_snapshots("doc", "v1", "v2").unwrap().unwrap();
+        let restored = Doc::new();
+        let restored_text = restored.get_or_insert_text("text");
+        {
+            let mut txn = restored.transact_mut();
+            txn.apply_update(Update::decode_v1(&v1_state).unwrap())
+                .unwrap();
+            txn.apply_update(Update::decode_v1(&diff).unwrap()).unwrap();
+        }
+        assert_eq!(restored_text.get_string(&restored.transact()), "hello!");
+
+        assert!(db.diff_snapshots("doc", "v1", "missing").unwrap().is_none());
+        let err = db.diff_snapshots("doc", "v2", "v1").unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<StoreError>(),
+            Some(StoreError::MissingSnapshot(_))
+        ));
+    }
+
+    #[test]
+    fn update_timestamps() {
+        let db = MemKVStore::new().with_update_timestamps(true);
+        let doc = Doc::new();
+        let text = doc.get_or_insert_text("text");
+        let mut clocks = Vec::new();
+        for chunk in ["a", "b", "c"] {
+            let sv = doc.transact().state_vector();
+            text.push(&mut doc.transact_mut(), chunk);
+            let update = doc.transact().encode_diff_v1(&sv);
+            clocks.push(db.push_update("doc", &update).unwrap());
+        }
+        let first = db.get_update_timestamp("doc", clocks[0]).unwrap().unwrap();
+        let last = db.get_update_timestamp("doc", clocks[2]).unwrap().unwrap();
+        assert!(first <= last);
+
+        assert_eq!(db.iter_updates_since("doc", 0).unwrap().count(), 3);
+        let since_last: Vec<_> = db
+            .iter_updates_since("doc", last)
+            .unwrap()
+            .map(|u| u.clock)
+            .collect();
+        assert_eq!(since_last.last(), Some(&clocks[2]));
+        assert_eq!(db.iter_updates_since("doc", last + 1).unwrap().count(), 0);
+
+        // timestamps are exported and imported together with their updates
+        let archive = db.export_doc("doc").unwrap().unwrap();
+        assert_eq!(archive.update_timestamps.len(), 3);
+        let copy = MemKVStore::new();
+        copy.import_doc("doc", &archive).unwrap();
+        let timestamp = copy.get_update_timestamp("doc", clocks[2]).unwrap();
+        assert_eq!(timestamp, Some(last));
+
+        // updates pushed in multi-document batches get their timestamps too
+        let mut batch = MultiDocBatch::new();
+        batch.push_update(
+            "batched",
+            &doc.transact().encode_diff_v1(&StateVector::default()),
+        );
+        db.commit_docs(batch).unwrap();
+        assert_eq!(db.iter_updates_since("batched", last).unwrap().count(), 1);
+
+        // timestamps are removed together with their updates
+        db.trim_updates("doc", clocks[0]).unwrap();
+        assert!(db.get_update_timestamp("doc", clocks[0]).unwrap().is_none());
+        db.flush_doc("doc").unwrap();
+        assert!(db.get_update_timestamp("doc", clocks[2]).unwrap().is_none());
+
+        let db = MemKVStore::new();
+        let update = doc.transact().encode_diff_v1(&StateVector::default());
+        let clock = db.push_update("doc", &update).unwrap();
+        assert!(db.get_update_timestamp("doc", clock).unwrap().is_none());
+        assert_eq!(db.iter_updates_since("doc", 0).unwrap().count(), 0);
+    }
+
+    #[test]
+    fn get_diff_from_pending_updates() {
+        fn push(db: &MemKVStore, doc: &Doc, edit: impl FnOnce(&mut TransactionMut)) {
+            let sv = doc.transact().state_vector();
+            edit(&mut doc.transact_mut());
+            let update = doc.transact().encode_diff_v1(&sv);
+            db.push_update("doc", &update).unwrap();
+        }
+
+        let db = MemKVStore::new();
+        let doc = Doc::with_client_id(1);
+        let text = doc.get_or_insert_text("text");
+        push(&db, &doc, |txn| text.push(txn, "hello world"));
+        db.flush_doc("doc").unwrap();
+        let remote = Doc::with_client_id(2);
+        let remote_text = remote.get_or_insert_text("text");
+        let update = doc.transact().encode_diff_v1(&StateVector::default());
+        remote
+            .transact_mut()
+            .apply_update(Update::decode_v1(&update).unwrap())
+            .unwrap();
+        let sv = remote.transact().state_vector();
+
+        // deletion merged into the document state doesn't advance its state vector
+        push(&db, &doc, |txn| text.remove_range(txn, 5, 6));
+        db.flush_doc("doc").unwrap();
+        push(&db, &doc, |txn| text.push(txn, "!"));
+
+        let full = db
+            .get_diff("doc", &StateVector::default())
+            .unwrap()
+            .unwrap();
+        let diff = db.get_diff("doc", &sv).unwrap().unwrap();
+        assert!(diff.len() < full.len());
+        remote
+            .transact_mut()
+            .apply_update(Update::decode_v1(&diff).unwrap())
+            .unwrap();
+        assert_eq!(remote_text.get_string(&remote.transact()), "hello!");
+
+        let diff = db.get_diff_v2("doc", &sv).unwrap().unwrap();
+        let remote = Doc::with_client_id(3);
+        let remote_text = remote.get_or_insert_text("text");
+        {
+            let mut txn = remote.transact_mut();
+            txn.apply_update(Update::decode_v1(&update).unwrap())
+                .unwrap();
+            txn.apply_update(Update::decode_v2(&diff).unwrap()).unwrap();
+        }
+        assert_eq!(remote_text.get_string(&remote.transact()), "hello!");
+    }
+
+    #[test]
+    fn track_state_vector() {
+        fn push(db: &MemKVStore, name: &str, doc: &Doc, edit: impl FnOnce(&mut TransactionMut)) {
+            let sv = doc.transact().state_vector();
+            edit(&mut doc.transact_mut());
+            let update = doc.transact().encode_diff_v1(&sv);
+            db.push_update(name, &update).unwrap();
+        }
+
+        let db = MemKVStore::new().with_state_vector_tracking(true);
+        let doc = Doc::with_client_id(1);
+        let text = doc.get_or_insert_text("text");
+        for chunk in ["a", "b", "c"] {
+            push(&db, "doc", &doc, |txn| text.push(txn, chunk));
+        }
+        assert!(db.has_pending_updates("doc").unwrap());
+        let sv = doc.transact().state_vector();
+        assert_eq!(
+            db.get_state_vector("doc").unwrap(),
+            (Some(sv.clone()), true)
+        );
+
+        // deletions don't change the state vector
+        push(&db, "doc", &doc, |txn| text.remove_range(txn, 0, 1));
+        assert_eq!(
+            db.get_state_vector("doc").unwrap(),
+            (Some(sv.clone()), true)
+        );
+
+        db.flush_doc("doc").unwrap();
+        let flushed = doc.transact().state_vector();
+        push(&db, "doc", &doc, |txn| text.push(txn, "d"));
+        let sv = doc.transact().state_vector();
+        assert_eq!(db.get_state_vector("doc").unwrap(), (Some(sv), true));
+        assert!(db.verify_store().unwrap().is_ok());
+
+        // trimmed updates are no longer a part of the state vector
+        db.trim_updates("doc", u32::MAX).unwrap();
+        assert_eq!(db.get_state_vector("doc").unwrap(), (Some(flushed), true));
+
+        // update depending on changes, which were not pushed, can't be merged
+        let remote = Doc::with_client_id(2);
+        let remote_text = remote.get_or_insert_text("text");
+        remote_text.push(&mut remote.transact_mut(), "x");
+        let missing = remote.transact().encode_diff_v1(&StateVector::default());
+        push(&db, "gap", &remote, |txn| remote_text.push(txn, "y"));
+        assert_eq!(db.get_state_vector("gap").unwrap(), (None, false));
+        db.push_update("gap", &missing).unwrap();
+        assert_eq!(db.get_state_vector("gap").unwrap(), (None, false));
+        db.flush_doc("gap").unwrap();
+        let sv = remote.transact().state_vector();
+        assert_eq!(db.get_state_vector("gap").unwrap(), (Some(sv), true));
+
+        // updates pushed in multi-document batches are merged as well
+        let batched = Doc::with_client_id(3);
+        let batched_text = batched.get_or_insert_text("text");
+        let mut batch = MultiDocBatch::new();
+        for chunk in ["a", "b"] {
+            let sv = batched.transact().state_vector();
+            batched_text.push(&mut batched.transact_mut(), chunk);
+            batch.push_update_v2("batched", &batched.transact().encode_diff_v2(&sv));
+        }
+        db.commit_docs(batch).unwrap();
+        let sv = batched.transact().state_vector();
+        assert_eq!(db.get_state_vector("batched").unwrap(), (Some(sv), true));
+
+        // state vector is not tracked by default
+        let db = MemKVStore::new();
+        push(&db, "doc", &doc, |txn| text.push(txn, "e"));
+        assert_eq!(db.get_state_vector("doc").unwrap(), (None, false));
+    }
+
+    #[test]
+    fn update_origins() {
+        let db = MemKVStore::new();
+        let doc = Doc::new();
+        let text = doc.get_or_insert_text("text");
+        let mut updates = Vec::new();
+        for chunk in ["a", "b", "c"] {
+            let sv = doc.transact().state_vector();
+            text.push(&mut doc.transact_mut(), chunk);
+            updates.push(doc.transact().encode_diff_v1(&sv));
+        }
+        db.push_update_with_origin("doc", &updates[0], b"alice")
+            .unwrap();
+        db.push_update("doc", &updates[1]).unwrap();
+        db.push_update_with_origin("doc", &updates[2], b"bob")
+            .unwrap();
+
+        let origins: Vec<_> = db
+            .iter_updates("doc")
+            .unwrap()
+            .map(|u| u.origin.map(|o| o.to_vec()))
+            .collect();
+        assert_eq!(
+            origins,
+            vec![Some(b"alice".to_vec()), None, Some(b"bob".to_vec())]
+        );
+
+        // origins are exported and imported together with their updates
+        let archive = db.export_doc("doc").unwrap().unwrap();
+        assert_eq!(archive.updates[0].origin.as_deref(), Some(&b"alice"[..]));
+        let copy = MemKVStore::new();
+        copy.import_doc("doc", &archive).unwrap();
+        assert_eq!(copy.export_doc("doc").unwrap().unwrap(), archive);
+
+        // updates pushed in multi-document batches keep their origins too
+        let mut batch = MultiDocBatch::new();
+        batch.push_update_with_origin("batched", &updates[0], b"carol");
+        db.commit_docs(batch).unwrap();
+        let update = db.iter_updates("batched").unwrap().next().unwrap();
+        assert_eq!(update.origin.as_deref(), Some(&b"carol"[..]));
+
+        // origins are removed together with their updates
+        db.flush_doc("doc").unwrap();
+        db.push_update("doc", &updates[0]).unwrap();
+        let update = db.iter_updates("doc").unwrap().next().unwrap();
+        assert_eq!(update.clock, 1);
+        assert!(update.origin.is_none());
+    }
+
+    #[test]
+    fn retention_policy() {
+        fn push_chunks(db: &MemKVStore, doc: &Doc, chunks: &[&str]) {
+            let text = doc.get_or_insert_text("text");
+            for chunk in chunks {
+                let sv = doc.transact().state_vector();
+                text.push(&mut doc.transact_mut(), chunk);
+                let update = doc.transact().encode_diff_v1(&sv);
+                db.push_update("doc", &update).unwrap();
+            }
+        }
+        fn load_text(db: &MemKVStore) -> String {
+            let doc = Doc::new();
+            let text = doc.get_or_insert_text("text");
+            db.load_doc("doc", &mut doc.transact_mut()).unwrap();
+            let txn = doc.transact();
+            text.get_string(&txn)
+        }
+
+        // policy without limits retains all updates
+        let db = MemKVStore::new();
+        push_chunks(&db, &Doc::new(), &["a", "b"]);
+        assert_eq!(db.enforce_retention("doc").unwrap(), 0);
+        assert_eq!(db.iter_updates("doc").unwrap().count(), 2);
+
+        let db = MemKVStore::new().with_retention_policy(RetentionPolicy {
+            keep_updates: Some(2),
+            ..RetentionPolicy::default()
+        });
+        push_chunks(&db, &Doc::new(), &["a", "b", "c", "d", "e"]);
+        assert_eq!(db.enforce_retention("doc").unwrap(), 3);
+        assert_eq!(db.iter_updates("doc").unwrap().count(), 2);
+        assert_eq!(load_text(&db), "abcde");
+        assert_eq!(db.enforce_retention("doc").unwrap(), 0);
+        assert_eq!(db.enforce_retention("missing").unwrap(), 0);
+
+        // updates without timestamps are always retained
+        let db = MemKVStore::new().with_retention_policy(RetentionPolicy {
+            keep_for: Some(Duration::ZERO),
+            ..RetentionPolicy::default()
+        });
+        push_chunks(&db, &Doc::new(), &["a", "b"]);
+        assert_eq!(db.enforce_retention("doc").unwrap(), 0);
+        let db = db.with_update_timestamps(true);
+        push_chunks(&db, &Doc::new(), &["c"]);
+        assert_eq!(db.enforce_retention("doc").unwrap(), 0);
+
+        let db = MemKVStore::new()
+            .with_update_timestamps(true)
+            .with_retention_policy(RetentionPolicy {
+                keep_for: Some(Duration::from_secs(3600)),
+                ..RetentionPolicy::default()
+            });
+        push_chunks(&db, &Doc::new(), &["a", "b"]);
+        assert_eq!(db.enforce_retention("doc").unwrap(), 0);
+        let db = db.with_retention_policy(RetentionPolicy {
+            keep_for: Some(Duration::ZERO),
+            ..RetentionPolicy::default()
+        });
+        assert_eq!(db.enforce_retention("doc").unwrap(), 2);
+        assert!(!db.has_pending_updates("doc").unwrap());
+        assert_eq!(load_text(&db), "ab");
+
+        let db = MemKVStore::new().with_retention_policy(RetentionPolicy {
+            keep_since_snapshot: true,
+            ..RetentionPolicy::default()
+        });
+        let doc = Doc::new();
+        push_chunks(&db, &doc, &["a", "b"]);
+        assert_eq!(db.enforce_retention("doc").unwrap(), 0);
+        db.create_snapshot("doc", "v1").unwrap();
+        push_chunks(&db, &doc, &["c"]);
+        assert_eq!(db.enforce_retention("doc").unwrap(), 2);
+        assert_eq!(db.iter_updates("doc").unwrap().count(), 1);
+        assert_eq!(load_text(&db), "abc");
+
+        let db = MemKVStore::new().with_retention_policy(RetentionPolicy {
+            keep_updates: Some(1),
+            ..RetentionPolicy::default()
+        });
+        push_chunks(&db, &Doc::new(), &["a", "b"]);
+        let doc = Doc::new();
+        let text = doc.get_or_insert_text("text");
+        text.push(&mut doc.transact_mut(), "x");
+        let update = doc
+            .transact()
+            .encode_state_as_update_v1(&StateVector::default());
+        db.push_update("other", &update).unwrap();
+        db.push_update("flushed", &update).unwrap();
+        db.flush_doc("flushed").unwrap();
+        assert_eq!(db.enforce_retention_all().unwrap(), 1);
+        assert_eq!(db.iter_updates("doc").unwrap().count(), 1);
+        assert_eq!(db.iter_updates("other").unwrap().count(), 1);
+    }
+
+    #[test]
+    fn update_limits() {
+        let doc = Doc::new();
+        let text = doc.get_or_insert_text("text");
+        let mut updates = Vec::new();
+        for chunk in ["a", "b", "c", "d"] {
+            let sv = doc.transact().state_vector();
+            text.push(&mut doc.transact_mut(), chunk);
+            updates.push(doc.transact().encode_diff_v1(&sv));
+        }
+        let size = updates[0].len();
+        // limit of pending updates fitting exactly the first two updates, as they are stored
+        let db = MemKVStore::new();
+        db.push_updates("doc", updates[..2].iter().map(|u| u.as_slice()))
+            .unwrap();
+        let limit = db.doc_stats("doc").unwrap().unwrap().pending_update_bytes;
+
+        let db = MemKVStore::new().with_update_limits(UpdateLimits {
+            max_update_bytes: Some(size - 1),
+            ..UpdateLimits::default()
+        });
+        let err = db.push_update("doc", &updates[0]).unwrap_err();
+        assert!(matches!(
+            find::<StoreError>(&err),
+            Some(StoreError::UpdateTooLarge { .. })
+        ));
+        let err = db.push_updates("doc", [updates[0].as_slice()]).unwrap_err();
+        assert!(matches!(
+            find::<StoreError>(&err),
+            Some(StoreError::UpdateTooLarge { .. })
+        ));
+        // rejected updates don't create a document
+        assert!(get_oid(&db, b"doc").unwrap().is_none());
+
+        let db = MemKVStore::new().with_update_limits(UpdateLimits {
+            max_pending_bytes: Some(limit),
+            ..UpdateLimits::default()
+        });
+        db.push_update("doc", &updates[0]).unwrap();
+        db.push_update("doc", &updates[1]).unwrap();
+        let err = db.push_update("doc", &updates[2]).unwrap_err();
+        assert!(matches!(
+            find::<StoreError>(&err),
+            Some(StoreError::UpdateLogTooLarge { .. })
+        ));
+        assert_eq!(db.iter_updates("doc").unwrap().count(), 2);
+        db.flush_doc("doc").unwrap();
+        let err = db
+            .push_updates("doc", updates[1..].iter().map(|u| u.as_slice()))
+            .unwrap_err();
+        assert!(matches!(
+            find::<StoreError>(&err),
+            Some(StoreError::UpdateLogTooLarge { .. })
+        ));
+
+        let db = MemKVStore::new().with_update_limits(UpdateLimits {
+            max_pending_bytes: Some(limit),
+            flush_on_overflow: true,
+            ..UpdateLimits::default()
+        });
+        for update in updates.iter() {
+            db.push_update("doc", update).unwrap();
+        }
+        // pending updates have been flushed at least once to make room for the new ones
+        assert!(db.iter_updates("doc").unwrap().count() < updates.len());
+        let loaded = Doc::new();
+        let loaded_text = loaded.get_or_insert_text("text");
+        db.load_doc("doc", &mut loaded.transact_mut()).unwrap();
+        assert_eq!(loaded_text.get_string(&loaded.transact()), "abcd");
+    }
+
+    #[test]
+    fn update_validator() {
+        let db = MemKVStore::new().with_update_validator(Arc::new(RejectDeletions));
+        let doc = Doc::new();
+        let text = doc.get_or_insert_text("text");
+        text.push(&mut doc.transact_mut(), "hello");
+        let insert = doc.transact().encode_diff_v1(&StateVector::default());
+        let sv = doc.transact().state_vector();
+        text.remove_range(&mut doc.transact_mut(), 0, 1);
+        let delete = doc.transact().encode_diff_v1(&sv);
+
+        db.push_update("doc", &insert).unwrap();
+        let err = db.push_update("doc", &delete).unwrap_err();
+        assert!(matches!(
+            find::<StoreError>(&err),
+            Some(StoreError::UpdateRejected(_))
+        ));
+        assert_eq!(db.iter_updates("doc").unwrap().count(), 1);
+
+        // full document state containing deletions is vetoed as well
+        assert!(db.insert_doc("copy", &doc.transact()).is_err());
+        assert!(get_oid(&db, b"copy").unwrap().is_none());
+
+        // rejected update vetoes the whole batch
+        let mut batch = MultiDocBatch::new();
+        batch.push_update("a", &insert).push_update("b", &delete);
+        assert!(db.commit_docs(batch).is_err());
+        assert!(get_oid(&db, b"a").unwrap().is_none());
+    }
+
+    #[test]
+    fn write_hook() {
+        #[derive(Debug, Default)]
+        struct Recorder(Mutex<Vec<(Vec<u8>, ChangeSummary)>>);
+        impl WriteHook for Recorder {
+            fn on_after_write(&self, name: &[u8], changes: &ChangeSummary) {
+                self.0
+                    .lock()
+                    .unwrap()
+                    .push((name.to_vec(), changes.clone()));
+            }
+        }
+
+        let recorder = Arc::new(Recorder::default());
+        let db = MemKVStore::new().with_write_hook(recorder.clone());
+        let doc = Doc::new();
+        let text = doc.get_or_insert_text("text");
+        text.push(&mut doc.transact_mut(), "hello");
+        db.insert_doc("a", &doc.transact()).unwrap();
+        let sv = doc.transact().state_vector();
+        text.remove_range(&mut doc.transact_mut(), 0, 1);
+        let update = doc.transact().encode_diff_v1(&sv);
+        let clock = db.push_update("a", &update).unwrap();
+        db.clear_doc("a").unwrap();
+
+        let recorded = recorder.0.lock().unwrap();
+        assert_eq!(recorded.len(), 3);
+        assert!(recorded.iter().all(|(name, _)| name == b"a"));
+        let (_, insert) = &recorded[0];
+        assert_eq!(insert.kind, ChangeKind::Insert);
+        assert!(!insert.inserted.is_empty());
+        let (_, update) = &recorded[1];
+        assert_eq!(update.kind, ChangeKind::Update);
+        assert_eq!(update.seq, Some(clock));
+        assert!(update.inserted.is_empty());
+        assert!(!update.deleted.is_empty());
+        let (_, clear) = &recorded[2];
+        assert_eq!(clear, &ChangeSummary::cleared());
+    }
+
+    #[test]
+    fn changelog() {
+        let db = MemKVStore::new().with_changelog(true);
+        let doc = Doc::new();
+        let text = doc.get_or_insert_text("text");
+        text.push(&mut doc.transact_mut(), "hello");
+        db.insert_doc("a", &doc.transact()).unwrap();
+        let update = doc.transact().encode_diff_v1(&StateVector::default());
+        db.push_update("b", &update).unwrap();
+        db.insert_meta("a", "key", &[1]).unwrap();
+        db.clear_doc("b").unwrap();
+
+        let changes: Vec<_> = db
+            .iter_changes(0)
+            .unwrap()
+            .map(|c| (c.seq, c.name, c.kind))
+            .collect();
+        assert_eq!(
+            changes,
+            vec![
+                (1, "a".as_bytes().into(), ChangeKind::Insert),
+                (2, "b".as_bytes().into(), ChangeKind::Update),
+                (3, "a".as_bytes().into(), ChangeKind::Meta),
+                (4, "b".as_bytes().into(), ChangeKind::Clear),
+            ]
+        );
+        let seqs: Vec<_> = db.iter_changes(2).unwrap().map(|c| c.seq).collect();
+        assert_eq!(seqs, vec![3, 4]);
+
+        // the last entry is kept, so that sequence numbers never start over
+        db.trim_changes(u64::MAX).unwrap();
+        let seqs: Vec<_> = db.iter_changes(0).unwrap().map(|c| c.seq).collect();
+        assert_eq!(seqs, vec![4]);
+        db.remove_meta("a", "key").unwrap();
+        let seqs: Vec<_> = db.iter_changes(0).unwrap().map(|c| c.seq).collect();
+        assert_eq!(seqs, vec![4, 5]);
+
+        // rename is recorded as a removal of the old name and an insertion of the new one
+        db.rename_doc("a", "c").unwrap();
+        db.copy_doc("c", "d", false).unwrap();
+        let changes: Vec<_> = db
+            .iter_changes(5)
+            .unwrap()
+            .map(|c| (c.name, c.kind))
+            .collect();
+        assert_eq!(
+            changes,
+            vec![
+                ("a".as_bytes().into(), ChangeKind::Clear),
+                ("c".as_bytes().into(), ChangeKind::Insert),
+                ("d".as_bytes().into(), ChangeKind::Insert),
+            ]
+        );
+
+        // change log is disabled by default
+        let db = MemKVStore::new();
+        db.insert_doc("a", &doc.transact()).unwrap();
+        assert!(db.iter_changes(0).unwrap().next().is_none());
+    }
+
+    #[test]
+    fn report_metrics() {
+        #[derive(Default)]
+        struct Counters {
+            loaded: Mutex<Vec<u32>>,
+            flushed: Mutex<Vec<u64>>,
+            pushed: Mutex<Vec<(u32, u64)>>,
+            inserted: Mutex<u32>,
+        }
+
+        impl StoreMetrics for Arc<Counters> {
+            fn doc_loaded(&self, _: &[u8], pending_updates: u32, _: Duration) {
+                self.loaded.lock().unwrap().push(pending_updates);
+            }
+
+            fn doc_flushed(&self, _: &[u8], bytes_written: u64, _: Duration) {
+                self.flushed.lock().unwrap().push(bytes_written);
+            }
+
+            fn updates_pushed(&self, _: &[u8], count: u32, bytes_written: u64, _: Duration) {
+                self.pushed.lock().unwrap().push((count, bytes_written));
+            }
+
+            fn doc_inserted(&self, _: &[u8], _: u64, _: Duration) {
+                *self.inserted.lock().unwrap() += 1;
+            }
+        }
+
+        let counters = Arc::new(Counters::default());
+        let db = MemKVStore::new().with_metrics(Metrics::new(counters.clone()));
+        let doc = Doc::new();
+        let text = doc.get_or_insert_text("text");
+        text.push(&mut doc.transact_mut(), "a");
+        db.insert_doc("doc", &doc.transact()).unwrap();
+        let sv = doc.transact().state_vector();
+        text.push(&mut doc.transact_mut(), "b");
+        let u1 = doc.transact().encode_diff_v1(&sv);
+        let sv = doc.transact().state_vector();
+        text.push(&mut doc.transact_mut(), "c");
+        let u2 = doc.transact().encode_diff_v1(&sv);
+        db.push_update("doc", &u1).unwrap();
+        db.push_updates("doc", [u2.as_slice()]).unwrap();
+
+        let loaded = Doc::new();
+        assert!(db.load_doc("doc", &mut loaded.transact_mut()).unwrap());
+        assert!(!db.load_doc("missing", &mut loaded.transact_mut()).unwrap());
+        db.flush_doc("doc").unwrap().unwrap();
+
+        assert_eq!(*counters.inserted.lock().unwrap(), 1);
+        let pushed = counters.pushed.lock().unwrap().clone();
+        assert_eq!(pushed.len(), 2);
+        assert_eq!((pushed[0].0, pushed[1].0), (1, 1));
+        assert!(pushed[0].1 >= u1.len() as u64);
+        assert_eq!(*counters.loaded.lock().unwrap(), vec![2]);
+        let flushed = counters.flushed.lock().unwrap().clone();
+        assert_eq!(flushed.len(), 1);
+        assert!(flushed[0] > 0);
+    }
+
+    #[test]
+    fn load_doc_chunked() {
+        let db = MemKVStore::new();
+        let doc = Doc::new();
+        let text = doc.get_or_insert_text("text");
+        text.push(&mut doc.transact_mut(), "a");
+        db.insert_doc("doc", &doc.transact()).unwrap();
+        for c in ["b", "c", "d", "e", "f"] {
+            let sv = doc.transact().state_vector();
+            text.push(&mut doc.transact_mut(), c);
+            let update = doc.transact().encode_diff_v1(&sv);
+            db.push_update("doc", &update).unwrap();
+        }
+
+        let loaded = Doc::new();
+        let text = loaded.get_or_insert_text("text");
+        let read = |doc: &Doc| {
+            let txn = doc.transact();
+            text.get_string(&txn)
+        };
+        let mut cursor = db
+            .load_doc_chunked("doc", &mut loaded.transact_mut(), 2)
+            .unwrap()
+            .unwrap();
+        assert!(!cursor.is_done());
+        assert_eq!(read(&loaded), "abc");
+        assert!(!db
+            .load_doc_resume(&mut cursor, &mut loaded.transact_mut())
+            .unwrap());
+        assert_eq!(read(&loaded), "abcde");
+        assert!(db
+            .load_doc_resume(&mut cursor, &mut loaded.transact_mut())
+            .unwrap());
+        assert_eq!(read(&loaded), "abcdef");
+        // resuming finished cursor is a no-op
+        assert!(db
+            .load_doc_resume(&mut cursor, &mut loaded.transact_mut())
+            .unwrap());
+
+        let empty = Doc::new();
+        let cursor = db
+            .load_doc_chunked("missing", &mut empty.transact_mut(), 2)
+            .unwrap();
+        assert!(cursor.is_none());
+    }
+
+    #[test]
+    fn flush_doc_squash() {
+        let db = MemKVStore::new();
+        let doc = Doc::new();
+        let text = doc.get_or_insert_text("text");
+        text.push(&mut doc.transact_mut(), "a");
+        db.insert_doc("doc", &doc.transact()).unwrap();
+        assert!(!db.flush_doc_squash("doc").unwrap());
+        for c in ["b", "c", "d"] {
+            let sv = doc.transact().state_vector();
+            text.push(&mut doc.transact_mut(), c);
+            let update = doc.transact().encode_diff_v2(&sv);
+            db.push_update_v2("doc", &update).unwrap();
+        }
+
+        assert!(db.flush_doc_squash("doc").unwrap());
+        assert_eq!(db.iter_updates("doc").unwrap().count(), 0);
+        let (sv, up_to_date) = db.get_state_vector("doc").unwrap();
+        assert_eq!(sv, Some(doc.transact().state_vector()));
+        assert!(up_to_date);
+
+        let loaded = Doc::new();
+        let text = loaded.get_or_insert_text("text");
+        assert!(db.load_doc("doc", &mut loaded.transact_mut()).unwrap());
+        let txn = loaded.transact();
+        assert_eq!(text.get_string(&txn), "abcd");
+        assert!(!db.flush_doc_squash("missing").unwrap());
+    }
+
+    #[test]
+    fn doc_lease() {
+        let db = MemKVStore::new();
+        assert!(db.acquire_doc_lease("doc", "node-a", 100, 10).unwrap());
+        let lease = db.get_doc_lease("doc").unwrap().unwrap();
+        assert_eq!(lease.owner.as_ref(), b"node-a");
+        assert_eq!(lease.expires_at, 110);
+
+        // lease is held by another node until it expires
+        assert!(!db.acquire_doc_lease("doc", "node-b", 105, 10).unwrap());
+        assert!(!db.release_doc_lease("doc", "node-b").unwrap());
+        // owner can renew its lease
+        assert!(db.acquire_doc_lease("doc", "node-a", 105, 10).unwrap());
+        assert_eq!(db.get_doc_lease("doc").unwrap().unwrap().expires_at, 115);
+        assert!(db.acquire_doc_lease("doc", "node-b", 115, 10).unwrap());
+
+        assert!(db.release_doc_lease("doc", "node-b").unwrap());
+        assert!(db.get_doc_lease("doc").unwrap().is_none());
+        assert!(db.acquire_doc_lease("doc", "node-a", 120, 10).unwrap());
+
+        db.clear_doc("doc").unwrap();
+        assert!(db.get_doc_lease("doc").unwrap().is_none());
+    }
+
+    #[test]
+    fn insert_doc_if() {
+        let db = MemKVStore::new();
+        let doc = Doc::new();
+        let text = doc.get_or_insert_text("text");
+        text.push(&mut doc.transact_mut(), "a");
+        db.insert_doc_if("doc", &doc.transact(), &StateVector::default())
+            .unwrap();
+        let cached_sv = doc.transact().state_vector();
+
+        // concurrent writer
+        let other = Doc::new();
+        let other_text = other.get_or_insert_text("text");
+        other_text.push(&mut other.transact_mut(), "b");
+        let update = other.transact().encode_diff_v1(&StateVector::default());
+        db.push_update("doc", &update).unwrap();
+
+        text.push(&mut doc.transact_mut(), "c");
+        let err = db
+            .insert_doc_if("doc", &doc.transact(), &cached_sv)
+            .unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<StoreError>(),
+            Some(StoreError::Conflict(_))
+        ));
+
+        // reload document and retry
+        let doc = Doc::new();
+        let text = doc.get_or_insert_text("text");
+        db.load_doc("doc", &mut doc.transact_mut()).unwrap();
+        let sv = doc.transact().state_vector();
+        text.push(&mut doc.transact_mut(), "c");
+        db.insert_doc_if("doc", &doc.transact(), &sv).unwrap();
+        db.flush_doc("doc").unwrap();
+        let sv = doc.transact().state_vector();
+        db.insert_doc_if("doc", &doc.transact(), &sv).unwrap();
+    }
+
+    #[test]
+    fn dedup_updates() {
+        let db = MemKVStore::new().with_update_dedup(2);
+        let doc = Doc::new();
+        let text = doc.get_or_insert_text("text");
+        let mut updates = Vec::new();
+        for chunk in ["a", "b", "c"] {
+            let sv = doc.transact().state_vector();
+            text.push(&mut doc.transact_mut(), chunk);
+            updates.push(doc.transact().encode_diff_v1(&sv));
+        }
+        let a = db.push_update("doc", &updates[0]).unwrap();
+        let b = db.push_update("doc", &updates[1]).unwrap();
+        assert_eq!(db.push_update("doc", &updates[0]).unwrap(), a);
+        assert_eq!(db.push_update("doc", &updates[1]).unwrap(), b);
+        let c = db.push_update("doc", &updates[2]).unwrap();
+
+        // first update no longer fits into a window
+        assert!(db.push_update("doc", &updates[0]).unwrap() > c);
+        assert_eq!(db.push_update("doc", &updates[2]).unwrap(), c);
+
+        // updates pushed in a batch are deduplicated against stored and batched ones
+        let mut batch = MultiDocBatch::new();
+        batch.push_update("doc", &updates[2]);
+        batch.push_update("other", &updates[0]);
+        batch.push_update("other", &updates[0]);
+        db.commit_docs(batch).unwrap();
+        assert_eq!(db.iter_updates("other").unwrap().count(), 1);
+        let last = db.iter_updates("doc").unwrap().last().unwrap().clock;
+        assert_eq!(db.push_update("doc", &updates[0]).unwrap(), last);
+
+        let loaded = Doc::new();
+        let text = loaded.get_or_insert_text("text");
+        db.load_doc("doc", &mut loaded.transact_mut()).unwrap();
+        let txn = loaded.transact();
+        assert_eq!(text.get_string(&txn), "abc");
+    }
+
+    #[test]
+    fn cancel_operations() {
+        fn is_cancelled(err: crate::error::Error) -> bool {
+            matches!(find::<StoreError>(&err), Some(StoreError::Cancelled))
+        }
+
+        let db = MemKVStore::new();
+        let doc = Doc::new();
+        let text = doc.get_or_insert_text("text");
+        text.push(&mut doc.transact_mut(), "hello");
+        db.insert_doc("doc", &doc.transact()).unwrap();
+        let sv = doc.transact().state_vector();
+        text.push(&mut doc.transact_mut(), " world");
+        db.push_update("doc", &doc.transact().encode_diff_v1(&sv))
+            .unwrap();
+
+        let cancel = Cancel::new();
+        let loaded = Doc::new();
+        assert!(db
+            .load_doc_cancellable("doc", &mut loaded.transact_mut(), &cancel)
+            .unwrap());
+        let mut dump = Vec::new();
+        assert_eq!(db.export_all_cancellable(&mut dump, &cancel).unwrap(), 1);
+
+        cancel.cancel();
+        let err = db
+            .load_doc_cancellable("doc", &mut Doc::new().transact_mut(), &cancel)
+            .unwrap_err();
+        assert!(is_cancelled(err));
+        let err = db.flush_doc_cancellable("doc", &cancel).unwrap_err();
+        assert!(is_cancelled(err));
+        assert!(db.has_pending_updates("doc").unwrap());
+
+        // expired deadline cancels operations as well
+        let cancel = Cancel::with_deadline(Instant::now());
+        let err = db
+            .export_all_cancellable(&mut Vec::new(), &cancel)
+            .unwrap_err();
+        assert!(is_cancelled(err));
+
+        let cancel = Cancel::with_timeout(Duration::from_secs(60));
+        assert!(db.flush_doc_cancellable("doc", &cancel).unwrap().is_some());
+        assert!(!db.has_pending_updates("doc").unwrap());
+    }
+
+    #[test]
+    fn load_doc_lenient() {
+        let db = MemKVStore::new();
+        let doc = Doc::new();
+        let text = doc.get_or_insert_text("text");
+        text.push(&mut doc.transact_mut(), "hello");
+        db.insert_doc("doc", &doc.transact()).unwrap();
+        let bad = db.push_update("doc", &[0xff, 0xff, 0xff]).unwrap();
+        let sv = doc.transact().state_vector();
+        text.push(&mut doc.transact_mut(), " world");
+        db.push_update("doc", &doc.transact().encode_diff_v1(&sv))
+            .unwrap();
+        assert!(db.load_doc("doc", &mut Doc::new().transact_mut()).is_err());
+
+        let loaded = Doc::new();
+        let text = loaded.get_or_insert_text("text");
+        let report = db
+            .load_doc_lenient("doc", &mut loaded.transact_mut())
+            .unwrap();
+        assert!(report.found);
+        assert!(!report.is_complete());
+        assert_eq!(report.applied_updates, 1);
+        let bad_key: Box<[u8]> = key_update(1, bad).as_ref().into();
+        assert_eq!(report.skipped, vec![bad_key]);
+        assert_eq!(text.get_string(&loaded.transact()), "hello world");
+
+        assert_eq!(db.quarantine("doc", &report).unwrap(), 1);
+        let quarantined = db.get_quarantined("doc").unwrap();
+        assert_eq!(quarantined.len(), 1);
+        assert_eq!(quarantined[0].0, key_update(1, bad).as_ref());
+
+        let loaded = Doc::new();
+        let text = loaded.get_or_insert_text("text");
+        assert!(db.load_doc("doc", &mut loaded.transact_mut()).unwrap());
+        let txn = loaded.transact();
+        assert_eq!(text.get_string(&txn), "hello world");
+    }
+
+    #[test]
+    fn verify_store() {
+        let db = MemKVStore::new();
+        for name in ["a", "b"] {
+            let doc = Doc::new();
+            let text = doc.get_or_insert_text("text");
+            text.push(&mut doc.transact_mut(), name);
+            db.insert_doc(name, &doc.transact()).unwrap();
+            let sv = doc.transact().state_vector();
+            text.push(&mut doc.transact_mut(), "!");
+            db.push_update(name, &doc.transact().encode_diff_v1(&sv))
+                .unwrap();
+        }
+        let report = db.verify_store().unwrap();
+        assert!(report.is_ok(), "{:?}", report);
+        assert_eq!(report.docs, 2);
+
+        // alias mapped to the same OID as document "a"
+        let oid = db.get(&key_oid(b"a")).unwrap().unwrap();
+        db.upsert(&key_oid(b"alias"), &oid).unwrap();
+        // entries left behind by a document without a name
+        db.upsert(&key_doc(99), &[0]).unwrap();
+        db.upsert(&key_update(99, 1), &[0]).unwrap();
+        // undecodable update
+        let bad = db.push_update("b", &[0xff, 0xff, 0xff]).unwrap();
+        // outdated state vector
+        let sv = StateVector::default().encode_v1();
+        db.upsert(&key_state_vector(2), &sv).unwrap();
+
+        let report = db.verify_store().unwrap();
+        assert!(!report.is_ok());
+        assert_eq!(report.docs, 3);
+        assert_eq!(
+            report.duplicate_oids,
+            vec![(1, vec!["a".as_bytes().into(), "alias".as_bytes().into()])]
+        );
+        assert_eq!(report.orphaned, vec![(99, 2)]);
+        let bad_key: Box<[u8]> = key_update(2, bad).as_ref().into();
+        assert_eq!(report.undecodable, vec![bad_key]);
+        let name: Box<[u8]> = "b".as_bytes().into();
+        assert_eq!(report.state_vector_mismatches, vec![name]);
+    }
+
+    #[test]
+    fn vacuum() {
+        let db = MemKVStore::new();
+        for name in ["a", "b"] {
+            let doc = Doc::new();
+            let text = doc.get_or_insert_text("text");
+            text.push(&mut doc.transact_mut(), name);
+            db.insert_doc(name, &doc.transact()).unwrap();
+            let sv = doc.transact().state_vector();
+            text.push(&mut doc.transact_mut(), "!");
+            db.push_update(name, &doc.transact().encode_diff_v1(&sv))
+                .unwrap();
+            db.set_doc_expiry(name, 100).unwrap();
+        }
+        assert_eq!(db.vacuum().unwrap().total(), 0);
+
+        // simulate clear_doc interrupted right after removing the document name
+        db.remove(&key_oid(b"b")).unwrap();
+        let stats = db.vacuum().unwrap();
+        assert_eq!(stats.oids, vec![2]);
+        assert_eq!(stats.entries.state, 2);
+        assert_eq!(stats.entries.updates, 1);
+        assert_eq!(stats.index_entries, 1);
+        assert!(db.verify_store().unwrap().is_ok());
+
+        let doc = Doc::new();
+        let text = doc.get_or_insert_text("text");
+        assert!(db.load_doc("a", &mut doc.transact_mut()).unwrap());
+        let txn = doc.transact();
+        assert_eq!(text.get_string(&txn), "a!");
+    }
+
+    #[test]
+    fn debug_dump() {
+        let db = MemKVStore::new();
+        let doc = Doc::new();
+        let text = doc.get_or_insert_text("text");
+        text.push(&mut doc.transact_mut(), "hello");
+        db.insert_doc("doc", &doc.transact()).unwrap();
+        let sv = doc.transact().state_vector();
+        text.push(&mut doc.transact_mut(), " world");
+        let update = doc.transact().encode_diff_v1(&sv);
+        let seq = db.push_update("doc", &update).unwrap();
+        db.insert_meta("doc", "owner", &[0xab; 20]).unwrap();
+
+        let dump = db.debug_dump("doc").unwrap().unwrap();
+        let kinds: Vec<_> = dump.iter().map(|e| e.kind).collect();
+        assert_eq!(
+            kinds,
+            vec![
+                KeyKind::Oid,
+                KeyKind::DocState,
+                KeyKind::StateVector,
+                KeyKind::Update,
+                KeyKind::Meta
+            ]
+        );
+        assert_eq!(dump[3].clock, Some(seq));
+        assert_eq!(dump[3].key.as_ref(), key_update(1, seq).as_ref());
+        assert_eq!(dump[4].value_len, 20);
+        assert_eq!(dump[4].preview, format!("{}..", "ab".repeat(16)));
+        assert!(dump[3].to_string().starts_with("Update key="));
+        assert!(db.debug_dump("missing").unwrap().is_none());
+    }
+
+    #[test]
+    fn flush_all() {
+        let db = MemKVStore::new();
+        for (name, updates) in [("a", 1), ("b", 3), ("c", 2)] {
+            let doc = Doc::new();
+            let text = doc.get_or_insert_text("text");
+            for _ in 0..updates {
+                let sv = doc.transact().state_vector();
+                text.push(&mut doc.transact_mut(), name);
+                db.push_update(name, &doc.transact().encode_diff_v1(&sv))
+                    .unwrap();
+            }
+        }
+        let summary = db
+            .flush_all(FlushAllOptions {
+                min_pending_updates: 2,
+                max_docs: Some(1),
+                ..FlushAllOptions::default()
+            })
+            .unwrap();
+        assert_eq!(summary.flushed, 1);
+        assert!(summary.failed.is_empty());
+        assert!(summary.remaining);
+        assert!(!db.has_pending_updates("b").unwrap());
+
+        let summary = db.flush_all(FlushAllOptions::default()).unwrap();
+        assert_eq!(summary.flushed, 2);
+        assert!(!summary.remaining);
+        for name in ["a", "b", "c"] {
+            assert!(!db.has_pending_updates(name).unwrap());
+        }
+        let summary = db.flush_all(FlushAllOptions::default()).unwrap();
+        assert_eq!(summary.flushed, 0);
+
+        // flushed updates don't count towards the minimum
+        let doc = Doc::new();
+        db.load_doc("b", &mut doc.transact_mut()).unwrap();
+        let text = doc.get_or_insert_text("text");
+        let sv = doc.transact().state_vector();
+        text.push(&mut doc.transact_mut(), "b");
+        db.push_update("b", &doc.transact().encode_diff_v1(&sv))
+            .unwrap();
+        let summary = db
+            .flush_all(FlushAllOptions {
+                min_pending_updates: 2,
+                ..FlushAllOptions::default()
+            })
+            .unwrap();
+        assert_eq!(summary.flushed, 0);
+        assert!(db.has_pending_updates("b").unwrap());
+
+        // malformed OID is reported without stopping the others from being flushed
+        db.upsert(&key_oid(b"broken"), &[1, 2]).unwrap();
+        let summary = db.flush_all(FlushAllOptions::default()).unwrap();
+        assert_eq!(summary.flushed, 1);
+        assert_eq!(summary.failed.len(), 1);
+        assert_eq!(summary.failed[0].0.as_ref(), b"broken");
+        assert!(!db.has_pending_updates("b").unwrap());
+    }
+
+    #[test]
+    fn load_docs() {
+        let db = MemKVStore::new();
+        for name in ["a", "b", "c"] {
+            let doc = Doc::new();
+            let text = doc.get_or_insert_text("text");
+            text.push(&mut doc.transact_mut(), name);
+            db.insert_doc(name, &doc.transact()).unwrap();
+            let sv = doc.transact().state_vector();
+            text.push(&mut doc.transact_mut(), "!");
+            db.push_update(name, &doc.transact().encode_diff_v1(&sv))
+                .unwrap();
+        }
+
+        let docs = db.load_docs(&["c", "missing", "a"]).unwrap();
+        let names: Vec<_> = docs.iter().map(|(name, _)| name.as_ref()).collect();
+        assert_eq!(names, vec!["c".as_bytes(), "a".as_bytes()]);
+        for (name, doc) in docs.iter() {
+            let text = doc.get_or_insert_text("text");
+            let txn = doc.transact();
+            let expected = format!("{}!", String::from_utf8_lossy(name));
+            assert_eq!(text.get_string(&txn), expected);
+        }
+    }
+
+    #[test]
+    fn shared_blobs() {
+        let db = MemKVStore::new().with_blob_threshold(64);
+        let blobs = |db: &MemKVStore| {
+            db.entries()
+                .keys()
+                .filter(|key| key.starts_with(&[V1, KEYSPACE_BLOB]))
+                .count()
+                / 2
+        };
+        let doc = Doc::new();
+        let text = doc.get_or_insert_text("text");
+        text.push(&mut doc.transact_mut(), &"lorem ipsum ".repeat(20));
+        db.insert_doc("a", &doc.transact()).unwrap();
+        assert_eq!(blobs(&db), 1);
+
+        // copies share the same blob
+        assert!(db.copy_doc("a", "b", false).unwrap());
+        assert_eq!(blobs(&db), 1);
+        let loaded = Doc::new();
+        let loaded_text = loaded.get_or_insert_text("text");
+        db.load_doc("b", &mut loaded.transact_mut()).unwrap();
+        let txn = loaded.transact();
+        assert_eq!(
+            loaded_text.get_string(&txn),
+            text.get_string(&doc.transact())
+        );
+
+        // flushed document gets a new blob, while the old one is still referenced by the copy
+        let sv = doc.transact().state_vector();
+        text.push(&mut doc.transact_mut(), "dolor sit amet");
+        db.push_update("a", &doc.transact().encode_diff_v1(&sv))
+            .unwrap();
+        db.flush_doc("a").unwrap();
+        assert_eq!(blobs(&db), 2);
+        let exported = db.export_doc("a").unwrap().unwrap();
+        assert!(!is_blob_ref(exported.state.as_deref().unwrap()));
+
+        db.clear_doc("b").unwrap();
+        assert_eq!(blobs(&db), 1);
+        db.clear_doc("a").unwrap();
+        assert_eq!(blobs(&db), 0);
+    }
+
+    #[test]
+    fn chunked_doc_state() {
+        let db = MemKVStore::new().with_doc_chunk_size(64);
+        let chunks = |db: &MemKVStore| {
+            db.entries()
+                .keys()
+                .filter(|key| key.len() > SUB_KEY_POS && key[SUB_KEY_POS] == SUB_DOC_CHUNK)
+                .count() as u32
+        };
+        let load = |db: &MemKVStore| {
+            let doc = Doc::new();
+            let text = doc.get_or_insert_text("text");
+            db.load_doc("doc", &mut doc.transact_mut())?;
+            let txn = doc.transact();
+            Ok::<_, Error>(text.get_string(&txn))
+        };
+        let doc = Doc::new();
+        let text = doc.get_or_insert_text("text");
+        text.push(&mut doc.transact_mut(), &"lorem ipsum ".repeat(20));
+        db.insert_doc("doc", &doc.transact()).unwrap();
+
+        // document state key keeps only a manifest of its chunks
+        let oid = get_oid(&db, b"doc").unwrap().unwrap();
+        let manifest = Manifest::parse(&db.get(&key_doc(oid)).unwrap().unwrap()).unwrap();
+        assert!(manifest.count > 1);
+        assert_eq!(chunks(&db), manifest.count);
+        assert_eq!(load(&db).unwrap(), text.get_string(&doc.transact()));
+
+        // chunks of a flushed document state replace the previous ones
+        let sv = doc.transact().state_vector();
+        text.push(&mut doc.transact_mut(), &" dolor sit amet".repeat(10));
+        db.push_update("doc", &doc.transact().encode_diff_v1(&sv))
+            .unwrap();
+        db.flush_doc("doc").unwrap();
+        let manifest = Manifest::parse(&db.get(&key_doc(oid)).unwrap().unwrap()).unwrap();
+        assert_eq!(chunks(&db), manifest.count);
+        assert_eq!(load(&db).unwrap(), text.get_string(&doc.transact()));
+        let diff = db
+            .get_diff("doc", &StateVector::default())
+            .unwrap()
+            .unwrap();
+        let remote = Doc::new();
+        let remote_text = remote.get_or_insert_text("text");
+        remote
+            .transact_mut()
+            .apply_update(Update::decode_v1(&diff).unwrap())
+            .unwrap();
+        assert_eq!(
+            remote_text.get_string(&remote.transact()),
+            text.get_string(&doc.transact())
+        );
+        assert_eq!(db.verify_store().unwrap().undecodable.len(), 0);
+
+        // copied document gets its own chunks
+        assert!(db.copy_doc("doc", "copy", false).unwrap());
+        assert_eq!(chunks(&db), 2 * manifest.count);
+        let copy = Doc::new();
+        let copy_text = copy.get_or_insert_text("text");
+        db.load_doc("copy", &mut copy.transact_mut()).unwrap();
+        assert_eq!(
+            copy_text.get_string(&copy.transact()),
+            text.get_string(&doc.transact())
+        );
+        db.clear_doc("copy").unwrap();
+        assert_eq!(chunks(&db), manifest.count);
+
+        // missing chunk is reported as a corrupted document state
+        db.remove(&key_doc_chunk(oid, 1)).unwrap();
+        let err = load(&db).unwrap_err();
+        assert!(matches!(
+            find::<StoreError>(&err),
+            Some(StoreError::Corrupted { .. })
+        ));
+
+        db.clear_doc("doc").unwrap();
+        assert_eq!(chunks(&db), 0);
+    }
+
+    #[test]
+    fn value_codec() {
+        let codec = CodecChain::new()
+            .then(Compressed(Compression::default()))
+            .then(Checksummed);
+        let db = MemKVStore::new().with_value_codec(Arc::new(codec));
+        let doc = Doc::new();
+        let text = doc.get_or_insert_text("text");
+        text.push(&mut doc.transact_mut(), "hello");
+        db.insert_doc("doc", &doc.transact()).unwrap();
+        let sv = doc.transact().state_vector();
+        text.push(&mut doc.transact_mut(), " world");
+        db.push_update("doc", &doc.transact().encode_diff_v1(&sv))
+            .unwrap();
+        db.insert_meta("doc", "key", b"value").unwrap();
+
+        // metadata is persisted using the codec, but read back as it was written
+        let oid = get_oid(&db, b"doc").unwrap().unwrap();
+        let raw = db.get(&key_meta(oid, b"key")).unwrap().unwrap();
+        assert!(is_sealed(&raw));
+        assert_eq!(db.get_meta("doc", "key").unwrap(), Some(b"value".to_vec()));
+        let meta: Vec<_> = db.iter_meta("doc").unwrap().collect();
+        assert_eq!(
+            meta,
+            vec![(b"key".as_ref().into(), b"value".as_ref().into())]
+        );
+
+        let updates: Vec<_> = db.iter_updates("doc").unwrap().collect();
+        assert_eq!(updates.len(), 1);
+        assert_eq!(
+            updates[0].update.as_ref(),
+            doc.transact().encode_diff_v1(&sv).as_slice()
+        );
+
+        let loaded = Doc::new();
+        let loaded_text = loaded.get_or_insert_text("text");
+        db.load_doc("doc", &mut loaded.transact_mut()).unwrap();
+        assert_eq!(loaded_text.get_string(&loaded.transact()), "hello world");
+        db.flush_doc("doc").unwrap();
+        let loaded = Doc::new();
+        let loaded_text = loaded.get_or_insert_text("text");
+        db.load_doc("doc", &mut loaded.transact_mut()).unwrap();
+        assert_eq!(loaded_text.get_string(&loaded.transact()), "hello world");
+    }
+
+    #[test]
+    fn detect_corrupted_doc() {
+        let db = MemKVStore::new();
+        let doc = Doc::new();
+        let text = doc.get_or_insert_text("text");
+        text.push(&mut doc.transact_mut(), "hello");
+        db.insert_doc("doc", &doc.transact()).unwrap();
+
+        // flip a bit in the persisted document state
+        let (key, mut value) = db
+            .entries()
+            .into_iter()
+            .find(|(k, _)| k.as_slice() == key_doc(1).as_ref())
+            .unwrap();
+        let last = value.len() - 1;
+        value[last] ^= 1;
+        db.upsert(&key, &value).unwrap();
+
+        let doc = Doc::new();
+        let err = db.load_doc("doc", &mut doc.transact_mut()).unwrap_err();
+        match find::<StoreError>(&err) {
+            Some(StoreError::Corrupted { key: k }) => assert_eq!(k, &key),
+            other => panic!("expected corruption error, got: {:?}", other),
+        }
+        let ctx = context(&err).unwrap();
+        assert_eq!(ctx.name.as_deref(), Some("doc".as_bytes()));
+        assert_eq!(ctx.oid, Some(1));
+    }
+
+    #[test]
+    fn undecodable_update_context() {
+        let db = MemKVStore::new();
+        let doc = Doc::new();
+        let text = doc.get_or_insert_text("text");
+        text.push(&mut doc.transact_mut(), "hello");
+        db.insert_doc("doc", &doc.transact()).unwrap();
+        let clock = db.push_update("doc", &[0xff, 0xff, 0xff]).unwrap();
+
+        let err = db
+            .load_doc("doc", &mut Doc::new().transact_mut())
+            .unwrap_err();
+        let ctx = context(&err).unwrap();
+        assert_eq!(ctx.name.as_deref(), Some("doc".as_bytes()));
+        assert_eq!(ctx.oid, Some(1));
+        assert_eq!(ctx.key.as_deref(), Some(key_update(1, clock).as_ref()));
+        assert!(err.to_string().contains("document: 'doc', OID: 1"));
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn json_meta() {
+        use serde_json::{json, Value};
+
+        let db = MemKVStore::new();
+        let owner = json!({ "name": "alice", "acl": ["read", "write"] });
+        db.insert_meta_json("doc", "owner", &owner).unwrap();
+        db.insert_meta_json("doc", "title", "Untitled").unwrap();
+
+        let actual: Option<Value> = db.get_meta_json("doc", "owner").unwrap();
+        assert_eq!(actual, Some(owner));
+        let actual: Option<String> = db.get_meta_json("doc", "title").unwrap();
+        assert_eq!(actual.as_deref(), Some("Untitled"));
+        let actual: Option<Value> = db.get_meta_json("doc", "missing").unwrap();
+        assert!(actual.is_none());
+
+        // stored value doesn't match requested type
+        assert!(db.get_meta_json::<_, _, u32>("doc", "title").is_err());
+    }
+
+    #[test]
+    fn cached_json_rendering() {
+        use crate::export::JsonExport;
+        use yrs::Map;
+
+        let db = MemKVStore::new().with_json_export(JsonExport::new().text("text").map("map"));
+        let doc = Doc::new();
+        let text = doc.get_or_insert_text("text");
+        let map = doc.get_or_insert_map("map");
+        text.push(&mut doc.transact_mut(), "hello");
+        map.insert(&mut doc.transact_mut(), "count", 1);
+        db.push_update(
+            "doc",
+            &doc.transact().encode_diff_v1(&StateVector::default()),
+        )
+        .unwrap();
+
+        // JSON rendering is refreshed only on flush
+        assert!(db.get_json("doc").unwrap().is_none());
+        db.flush_doc("doc").unwrap();
+        let json = String::from_utf8(db.get_json("doc").unwrap().unwrap()).unwrap();
+        assert!(json.contains(r#""text":"hello""#), "{}", json);
+        assert!(json.contains(r#""map":{"count":1}"#), "{}", json);
+        assert!(db.get_json("missing").unwrap().is_none());
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn export_json() {
+        use crate::export::JsonExport;
+        use serde_json::json;
+        use yrs::Array;
+
+        let db = MemKVStore::new().with_json_export(JsonExport::new().text("text").array("list"));
+        let doc = Doc::new();
+        let text = doc.get_or_insert_text("text");
+        let list = doc.get_or_insert_array("list");
+        text.push(&mut doc.transact_mut(), "hello");
+        list.push_back(&mut doc.transact_mut(), "a");
+        db.insert_doc("doc", &doc.transact()).unwrap();
+        let sv = doc.transact().state_vector();
+        text.push(&mut doc.transact_mut(), " world");
+        db.push_update("doc", &doc.transact().encode_diff_v1(&sv))
+            .unwrap();
+
+        // exported JSON includes pending updates
+        let actual = db.export_json("doc").unwrap();
+        assert_eq!(
+            actual,
+            Some(json!({ "text": "hello world", "list": ["a"] }))
+        );
+        assert!(db.export_json("missing").unwrap().is_none());
+    }
+
+    #[cfg(feature = "lz4")]
+    #[test]
+    fn compressed_doc_roundtrip() {
+        use crate::checksum::verify;
+        use crate::compression::{is_compressed, Compression};
+
+        let db = MemKVStore::new().with_compression(Compression::Lz4);
+        let doc = Doc::new();
+        let text = doc.get_or_insert_text("text");
+        for _ in 0..100 {
+            text.push(&mut doc.transact_mut(), "lorem ipsum dolor sit amet ");
+        }
+        db.insert_doc("doc", &doc.transact()).unwrap();
+        db.push_update(
+            "doc",
+            &doc.transact().encode_diff_v1(&StateVector::default()),
+        )
+        .unwrap();
+        let compressed = db
+            .entries()
+            .iter()
+            .filter(|(k, v)| is_compressed(verify(k, v).unwrap()))
+            .count();
+        assert_eq!(compressed, 2);
+
+        let expected = text.get_string(&doc.transact());
+        let doc = Doc::new();
+        let text = doc.get_or_insert_text("text");
+        assert!(db.load_doc("doc", &mut doc.transact_mut()).unwrap());
+        assert_eq!(text.get_string(&doc.transact()), expected);
+    }
+}
@@ -0,0 +1,57 @@
+use std::fmt::Formatter;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Receiver of statistics reported by [crate::DocOps] operations, which can be used to export
+/// persistence metrics (i.e. counters and latency histograms) into an observability stack.
+/// All methods have empty default implementations, so that implementors can pick only the
+/// statistics they are interested in.
+///
+/// Methods are called synchronously, right after a given operation has finished successfully.
+pub trait StoreMetrics: Send + Sync {
+    /// Called when a document has been loaded (see: [crate::DocOps::load_doc]), together with
+    /// a number of pending updates applied on top of its state.
+    fn doc_loaded(&self, _name: &[u8], _pending_updates: u32, _elapsed: Duration) {}
+
+    /// Called when pending updates of a document have been merged into its state (see:
+    /// [crate::DocOps::flush_doc]), together with a number of bytes of a new document state.
+    fn doc_flushed(&self, _name: &[u8], _bytes_written: u64, _elapsed: Duration) {}
+
+    /// Called when new updates of a document have been pushed (see:
+    /// [crate::DocOps::push_update]), together with a number of bytes written.
+    fn updates_pushed(&self, _name: &[u8], _count: u32, _bytes_written: u64, _elapsed: Duration) {}
+
+    /// Called when a document state has been inserted (see: [crate::DocOps::insert_doc]),
+    /// together with a number of bytes written.
+    fn doc_inserted(&self, _name: &[u8], _bytes_written: u64, _elapsed: Duration) {}
+}
+
+/// A handle to the [StoreMetrics] receiver used by a store (see: [crate::DocOps::metrics]). It's
+/// cheap to clone, and all clones report to the same receiver. By default no metrics are
+/// reported.
+#[derive(Clone, Default)]
+pub struct Metrics(Option<Arc<dyn StoreMetrics>>);
+
+impl Metrics {
+    /// Creates a new handle reporting to a given `metrics` receiver.
+    pub fn new<M: StoreMetrics + 'static>(metrics: M) -> Self {
+        Metrics(Some(Arc::new(metrics)))
+    }
+
+    /// Returns a receiver of the reported statistics, if any was set.
+    pub fn get(&self) -> Option<&dyn StoreMetrics> {
+        self.0.as_deref()
+    }
+}
+
+impl From<Arc<dyn StoreMetrics>> for Metrics {
+    fn from(metrics: Arc<dyn StoreMetrics>) -> Self {
+        Metrics(Some(metrics))
+    }
+}
+
+impl std::fmt::Debug for Metrics {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("Metrics").field(&self.0.is_some()).finish()
+    }
+}
@@ -0,0 +1,188 @@
+use crate::ChangeKind;
+use std::collections::HashMap;
+use std::fmt::Formatter;
+use std::sync::{Arc, Mutex, Weak};
+use yrs::{IdSet, Update};
+
+/// Event passed to callbacks registered via [crate::DocOps::observe_updates], whenever a document
+/// update has been written into the store.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UpdateEvent<'a> {
+    /// Name of the updated document.
+    pub name: &'a [u8],
+    /// Raw update bytes, as they were passed to the store (before compression).
+    pub update: &'a [u8],
+    /// Sequence number of the persisted update or `None` if the update contains a full document
+    /// state written by [crate::DocOps::insert_doc].
+    pub seq: Option<u32>,
+    /// Flag determining if update has been encoded using lib0 v2 encoding.
+    pub v2: bool,
+}
+
+pub type UpdateCallback = Arc<dyn Fn(&UpdateEvent) + Send + Sync>;
+
+/// A registry of callbacks observing persisted document updates. It's cheap to clone, and all
+/// clones share the same set of callbacks. This way the same registry can be attached to many
+/// short-lived store instances (i.e. ones created over database transactions), while callbacks
+/// need to be registered only once.
+///
+/// Keep in mind that callbacks are called as soon as the update has been written using the store,
+/// which may happen before underlying database transaction has been committed.
+#[derive(Clone, Default)]
+pub struct UpdateObservers(Arc<Mutex<Callbacks>>);
+
+#[derive(Default)]
+struct Callbacks {
+    next_id: u32,
+    by_name: HashMap<Box<[u8]>, Vec<(u32, UpdateCallback)>>,
+}
+
+impl UpdateObservers {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registers a new callback called whenever an update of a document with a given `name` is
+    /// persisted. Callback stays registered for as long as returned [UpdateSubscription] is alive.
+    pub fn subscribe<F>(&self, name: &[u8], callback: F) -> UpdateSubscription
+    where
+        F: Fn(&UpdateEvent) + Send + Sync + 'static,
+    {
+        let mut callbacks = self.0.lock().unwrap();
+        let id = callbacks.next_id;
+        callbacks.next_id += 1;
+        callbacks
+            .by_name
+            .entry(name.into())
+            .or_default()
+            .push((id, Arc::new(callback)));
+        UpdateSubscription {
+            observers: Arc::downgrade(&self.0),
+            name: name.into(),
+            id,
+        }
+    }
+
+    /// Checks if there are any callbacks registered for a document with a given `name`.
+    pub fn has_subscribers(&self, name: &[u8]) -> bool {
+        self.0.lock().unwrap().by_name.contains_key(name)
+    }
+
+    /// Calls all callbacks registered for a document, which name was provided within `event`.
+    pub(crate) fn publish(&self, event: &UpdateEvent) {
+        // callbacks are cloned out, so that they are free to (un)subscribe without deadlocking
+        let callbacks: Vec<_> = match self.0.lock().unwrap().by_name.get(event.name) {
+            None => return,
+            Some(callbacks) => callbacks.iter().map(|(_, cb)| cb.clone()).collect(),
+        };
+        for callback in callbacks {
+            callback(event);
+        }
+    }
+}
+
+impl std::fmt::Debug for UpdateObservers {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let callbacks = self.0.lock().unwrap();
+        f.debug_struct("UpdateObservers")
+            .field("docs", &callbacks.by_name.len())
+            .finish()
+    }
+}
+
+/// Subscription handle returned by [crate::DocOps::observe_updates]. Dropping it unregisters
+/// the associated callback.
+#[derive(Debug)]
+pub struct UpdateSubscription {
+    observers: Weak<Mutex<Callbacks>>,
+    name: Box<[u8]>,
+    id: u32,
+}
+
+impl Drop for UpdateSubscription {
+    fn drop(&mut self) {
+        if let Some(observers) = self.observers.upgrade() {
+            let mut callbacks = observers.lock().unwrap();
+            if let Some(subscribers) = callbacks.by_name.get_mut(&self.name) {
+                subscribers.retain(|(id, _)| *id != self.id);
+                if subscribers.is_empty() {
+                    callbacks.by_name.remove(&self.name);
+                }
+            }
+        }
+    }
+}
+
+/// Decoded summary of a change persisted for a document, passed to [WriteHook]s.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChangeSummary {
+    /// Kind of the persisted change: [ChangeKind::Update] for pushed updates,
+    /// [ChangeKind::Insert] for document states written by [crate::DocOps::insert_doc] and
+    /// [ChangeKind::Clear] for removed documents.
+    pub kind: ChangeKind,
+    /// Sequence number of the persisted update or `None` if change was not a pushed update.
+    pub seq: Option<u32>,
+    /// Identifiers of all blocks inserted by the change.
+    pub inserted: IdSet,
+    /// Identifiers of all blocks deleted by the change.
+    pub deleted: IdSet,
+}
+
+impl ChangeSummary {
+    /// Creates a summary of a given decoded `update`.
+    pub fn new(kind: ChangeKind, seq: Option<u32>, update: &Update) -> Self {
+        ChangeSummary {
+            kind,
+            seq,
+            inserted: update.insertions(true),
+            deleted: update.delete_set().clone(),
+        }
+    }
+
+    /// Creates a summary of a removed document.
+    pub fn cleared() -> Self {
+        ChangeSummary {
+            kind: ChangeKind::Clear,
+            seq: None,
+            inserted: IdSet::default(),
+            deleted: IdSet::default(),
+        }
+    }
+}
+
+/// Hook called after changes of a document have been written using [crate::DocOps] (see:
+/// [crate::options::StoreOptions::write_hook]). Unlike [UpdateObservers], hooks are called for all
+/// documents and receive decoded changes, so that integrations like full-text indexing or
+/// notifications can react to persisted changes without parsing the key-value layer themselves.
+///
+/// Keep in mind that hooks are called as soon as changes have been written using the store, which
+/// may happen before underlying database transaction has been committed.
+pub trait WriteHook: std::fmt::Debug + Send + Sync {
+    /// Called after `changes` of a document with a given `name` have been written.
+    fn on_after_write(&self, name: &[u8], changes: &ChangeSummary);
+}
+
+/// Sequence of hooks called one after another, in order in which they were added.
+#[derive(Debug, Clone, Default)]
+pub struct WriteHooks(Vec<Arc<dyn WriteHook>>);
+
+impl WriteHooks {
+    /// Creates a new empty sequence of hooks.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Appends a given `hook` at the end of the current sequence.
+    pub fn then<H: WriteHook + 'static>(mut self, hook: H) -> Self {
+        self.0.push(Arc::new(hook));
+        self
+    }
+}
+
+impl WriteHook for WriteHooks {
+    fn on_after_write(&self, name: &[u8], changes: &ChangeSummary) {
+        for hook in self.0.iter() {
+            hook.on_after_write(name, changes);
+        }
+    }
+}
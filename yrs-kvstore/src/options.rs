@@ -0,0 +1,435 @@
+use crate::batch::{BatchOp, WriteBatch};
+use crate::codec::{CodecChain, ValueCodec};
+use crate::compression::Compression;
+use crate::error::Error;
+use crate::export::JsonExport;
+use crate::keys::{OID_LEN, V1};
+use crate::metrics::StoreMetrics;
+use crate::observer::{UpdateObservers, WriteHook};
+use crate::scoped::{ScopedCursor, ScopedEntry};
+use crate::validation::UpdateValidator;
+use crate::{
+    CompactionPolicy, DocOps, KVEntry, KVStore, OidAllocation, RetentionPolicy, UpdateLimits,
+};
+use std::borrow::Cow;
+use std::sync::Arc;
+
+/// Configuration of the persistence layer, read by [DocOps] through [DocOps::options]. The same
+/// options are used to configure backend stores shipped alongside this crate and applied to any
+/// other backend by wrapping it with [ConfiguredStore]. It gathers in a single place the settings
+/// which otherwise have to be passed to every call or are left implicit.
+#[derive(Debug, Clone)]
+pub struct StoreOptions {
+    /// Options used to initialize documents created by the store, i.e. ones returned by
+    /// [DocOps::flush_doc] and [DocOps::load_docs] or used internally to merge pending updates.
+    /// Options passed explicitly, like in case of [DocOps::flush_doc_with], take precedence.
+    /// Client identifier and guid are generated anew for every document.
+    pub doc_options: yrs::Options,
+    /// Policy used to determine if pending updates should be automatically merged into the
+    /// document state, whenever a new update is pushed. Automatic compaction is disabled by
+    /// default.
+    pub compaction_policy: CompactionPolicy,
+    /// Codecs applied to all document states, updates and metadata values written by the store
+    /// and reversed when they are read back (see: [crate::codec]). Custom codecs replace
+    /// [Self::compression], which can be included in the chain itself using
+    /// [crate::codec::Compressed].
+    ///
+    /// If empty, document states and updates are compressed using [Self::compression] and sealed
+    /// with a checksum, while metadata values are stored as they are.
+    pub codecs: CodecChain,
+    /// Raw prefix prepended to every key of the wrapped store, just like in case of
+    /// [crate::scoped::ScopedStore::with_prefix]. Keys are not prefixed if not set. Only
+    /// [ConfiguredStore] is able to apply it.
+    pub keyspace_prefix: Option<Vec<u8>>,
+    /// Width of document [crate::keys::OID] in bytes: 4 or 8 if `oid64` feature is enabled.
+    pub oid_width: usize,
+    /// Strategy used to assign OIDs to documents persisted for the first time. By default OIDs are
+    /// allocated from a monotonic counter.
+    pub oid_allocation: OidAllocation,
+    /// Compression applied to document states and updates. Compressed and uncompressed entries can
+    /// be mixed within the same database, so it's always safe to change it. Values are not
+    /// compressed by default.
+    pub compression: Compression,
+    /// Records changes of documents in a global change log (see: [DocOps::iter_changes]). Change
+    /// log is disabled by default.
+    pub changelog: bool,
+    /// Number of recently pushed updates of each document, which are remembered by
+    /// [DocOps::push_update], [DocOps::push_update_v2] and [DocOps::commit_docs] in order to skip
+    /// persisting byte-identical updates, which are still pending (i.e. ones replayed by clients
+    /// retrying after reconnect). In such case the sequence number of already stored update is
+    /// returned. Updates pushed using [DocOps::push_updates] are not deduplicated. Deduplication
+    /// is disabled when set to 0.
+    pub update_dedup_window: usize,
+    /// Size in bytes, starting from which document states are stored in a content addressed blob
+    /// keyspace and only referenced from their documents (see: [crate::blob]). Documents sharing
+    /// the same state, i.e. ones created using [DocOps::copy_doc], store it only once. Blobs are
+    /// reference counted and removed together with the last document using them. Blob storage is
+    /// disabled when set to 0.
+    pub blob_threshold: usize,
+    /// Moves documents removed by [DocOps::clear_doc] into a trash (see: [DocOps::trash_doc]),
+    /// from which they can be restored until the trash is purged, instead of removing them
+    /// permanently. Soft delete is disabled by default.
+    pub soft_delete: bool,
+    /// Records a timestamp (in milliseconds since Unix epoch) of every pending update when it's
+    /// pushed (see: [DocOps::iter_updates_since]). Timestamps are removed together with updates
+    /// they belong to. Timestamps are not recorded by default.
+    pub update_timestamps: bool,
+    /// Policy used to determine which pending updates should be merged into the document state by
+    /// [DocOps::enforce_retention]. All updates are retained by default.
+    pub retention_policy: RetentionPolicy,
+    /// Limits used to reject oversized updates before they are written. Updates of any size are
+    /// accepted by default.
+    pub update_limits: UpdateLimits,
+    /// Validator called with every update and document state before it's persisted, which can veto
+    /// the write (see: [crate::validation]). All updates are accepted by default.
+    pub update_validator: Option<Arc<dyn UpdateValidator>>,
+    /// Hook called with a decoded summary of every pushed update, inserted document state and
+    /// removed document, once it has been written. No hook is set by default.
+    pub write_hook: Option<Arc<dyn WriteHook>>,
+    /// Definition used to render a JSON representation of document content, which is cached every
+    /// time a document is flushed (see: [DocOps::get_json]). No JSON rendering is maintained by
+    /// default.
+    pub json_export: Option<Arc<JsonExport>>,
+    /// Maximum size in bytes of a document state stored under a single key. Larger document
+    /// states are split into chunks, which are transparently reassembled when the document is read
+    /// (see: [crate::chunk]), so that documents can be persisted by backends limiting the size of
+    /// a single value. Chunking is disabled (set to 0) by default.
+    pub doc_chunk_size: usize,
+    /// Determines if the stored state vector of a document is updated incrementally by every pushed
+    /// update, so that [DocOps::get_state_vector] stays accurate without flushing the document.
+    /// Only insertions are taken into account, as state vectors don't describe deletions. Updates
+    /// depending on changes, which have not been persisted yet, can't be merged: the state vector
+    /// is reported as outdated until the next [DocOps::flush_doc] in such case. Tracking costs
+    /// decoding every pushed update and rewriting the state vector. State vectors are not tracked
+    /// by default.
+    pub track_state_vector: bool,
+}
+
+impl StoreOptions {
+    /// Returns [Self::codecs] as a single [ValueCodec] or `None` if no codecs were configured.
+    pub fn value_codec(&self) -> Option<Arc<dyn ValueCodec>> {
+        match self.codecs.len() {
+            0 => None,
+            1 => self.codecs.first(),
+            _ => Some(Arc::new(self.codecs.clone())),
+        }
+    }
+
+    /// Returns [Self::doc_options] with client identifier and guid generated anew.
+    pub fn new_doc_options(&self) -> yrs::Options {
+        let unique = yrs::Options::default();
+        yrs::Options {
+            client_id: unique.client_id,
+            guid: unique.guid,
+            ..self.doc_options.clone()
+        }
+    }
+
+    /// Checks if current options can be used by a backend store directly, without wrapping it
+    /// with [ConfiguredStore].
+    ///
+    /// # Panics
+    ///
+    /// Panics if [StoreOptions::oid_width] doesn't match the width of OIDs this crate has been
+    /// compiled with, or if [StoreOptions::keyspace_prefix] is set.
+    pub fn assert_unscoped(&self) {
+        assert_eq!(
+            self.oid_width, OID_LEN,
+            "unsupported OID width (8-byte OIDs require `oid64` feature)"
+        );
+        assert!(
+            self.keyspace_prefix.is_none(),
+            "keyspace prefix can only be applied by ConfiguredStore"
+        );
+    }
+}
+
+impl Default for StoreOptions {
+    fn default() -> Self {
+        StoreOptions {
+            doc_options: yrs::Options::default(),
+            compaction_policy: CompactionPolicy::default(),
+            codecs: CodecChain::default(),
+            keyspace_prefix: None,
+            oid_width: OID_LEN,
+            oid_allocation: OidAllocation::default(),
+            compression: Compression::default(),
+            changelog: false,
+            update_dedup_window: 0,
+            blob_threshold: 0,
+            soft_delete: false,
+            update_timestamps: false,
+            retention_policy: RetentionPolicy::default(),
+            update_limits: UpdateLimits::default(),
+            update_validator: None,
+            write_hook: None,
+            json_export: None,
+            doc_chunk_size: 0,
+            track_state_vector: false,
+        }
+    }
+}
+
+/// Decorator over any [KVStore], which configures it using given [StoreOptions], overriding
+/// the options of the inner store. Registries not covered by the options, like
+/// [DocOps::update_observers] or [DocOps::metrics], are taken from the inner store.
+#[derive(Debug)]
+pub struct ConfiguredStore<S> {
+    inner: S,
+    options: StoreOptions,
+    prefix: Vec<u8>,
+}
+
+impl<S> ConfiguredStore<S> {
+    /// Creates a new store, which wraps an `inner` store and configures it using given `options`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if [StoreOptions::oid_width] doesn't match the width of OIDs this crate has been
+    /// compiled with, or if [StoreOptions::keyspace_prefix] is empty or starts with
+    /// [crate::keys::V1] byte.
+    pub fn new(inner: S, options: StoreOptions) -> Self {
+        assert_eq!(
+            options.oid_width, OID_LEN,
+            "unsupported OID width (8-byte OIDs require `oid64` feature)"
+        );
+        let prefix = match &options.keyspace_prefix {
+            Some(prefix) => {
+                assert!(
+                    !prefix.is_empty() && prefix[0] != V1,
+                    "prefix must be non-empty and can't start with a V1 byte"
+                );
+                prefix.clone()
+            }
+            None => Vec::new(),
+        };
+        ConfiguredStore {
+            inner,
+            options,
+            prefix,
+        }
+    }
+
+    /// Returns a reference to the wrapped store.
+    pub fn inner(&self) -> &S {
+        &self.inner
+    }
+
+    /// Unwraps the underlying store.
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+
+    fn prefixed<'k>(&self, key: &'k [u8]) -> Cow<'k, [u8]> {
+        if self.prefix.is_empty() {
+            Cow::Borrowed(key)
+        } else {
+            let mut prefixed = Vec::with_capacity(self.prefix.len() + key.len());
+            prefixed.extend_from_slice(&self.prefix);
+            prefixed.extend_from_slice(key);
+            Cow::Owned(prefixed)
+        }
+    }
+}
+
+impl<'a, S> DocOps<'a> for ConfiguredStore<S>
+where
+    S: DocOps<'a>,
+    Error: From<<S as KVStore<'a>>::Error>,
+{
+    fn options(&self) -> &StoreOptions {
+        &self.options
+    }
+
+    fn lock_oid_allocation(&self) -> Result<(), Error> {
+        self.inner.lock_oid_allocation()
+    }
+
+    fn update_observers(&self) -> Option<&UpdateObservers> {
+        self.inner.update_observers()
+    }
+
+    fn metrics(&self) -> Option<&dyn StoreMetrics> {
+        self.inner.metrics()
+    }
+}
+
+impl<'a, S: KVStore<'a>> KVStore<'a> for ConfiguredStore<S> {
+    type Error = S::Error;
+    type Cursor = ScopedCursor<S::Cursor>;
+    type Entry = ScopedEntry<S::Entry>;
+    type Return = S::Return;
+
+    fn get(&self, key: &[u8]) -> Result<Option<Self::Return>, Self::Error> {
+        self.inner.get(&self.prefixed(key))
+    }
+
+    fn upsert(&self, key: &[u8], value: &[u8]) -> Result<(), Self::Error> {
+        self.inner.upsert(&self.prefixed(key), value)
+    }
+
+    fn remove(&self, key: &[u8]) -> Result<(), Self::Error> {
+        self.inner.remove(&self.prefixed(key))
+    }
+
+    fn remove_range(&self, from: &[u8], to: &[u8]) -> Result<(), Self::Error> {
+        self.inner
+            .remove_range(&self.prefixed(from), &self.prefixed(to))
+    }
+
+    fn iter_range(&self, from: &[u8], to: &[u8]) -> Result<Self::Cursor, Self::Error> {
+        let cursor = self
+            .inner
+            .iter_range(&self.prefixed(from), &self.prefixed(to))?;
+        Ok(ScopedCursor {
+            cursor,
+            prefix_len: self.prefix.len(),
+        })
+    }
+
+    fn contains_range(&self, from: &[u8], to: &[u8]) -> Result<bool, Self::Error> {
+        self.inner
+            .contains_range(&self.prefixed(from), &self.prefixed(to))
+    }
+
+    fn peek_back(&self, key: &[u8]) -> Result<Option<Self::Entry>, Self::Error> {
+        if let Some(entry) = self.inner.peek_back(&self.prefixed(key))? {
+            // the entry prior to the first prefixed key belongs to some other keyspace
+            if entry.key().starts_with(&self.prefix) {
+                return Ok(Some(ScopedEntry {
+                    entry,
+                    prefix_len: self.prefix.len(),
+                }));
+            }
+        }
+        Ok(None)
+    }
+
+    fn batch(&self) -> WriteBatch {
+        self.inner.batch()
+    }
+
+    fn commit_batch(&self, batch: WriteBatch) -> Result<(), Self::Error> {
+        if self.prefix.is_empty() {
+            return self.inner.commit_batch(batch);
+        }
+        let mut prefixed = self.inner.batch();
+        for op in batch {
+            match op {
+                BatchOp::Upsert(key, value) => prefixed.upsert(&self.prefixed(&key), &value),
+                BatchOp::Remove(key) => prefixed.remove(&self.prefixed(&key)),
+                BatchOp::RemoveRange(from, to) => {
+                    prefixed.remove_range(&self.prefixed(&from), &self.prefixed(&to))
+                }
+            }
+        }
+        self.inner.commit_batch(prefixed)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::checksum::is_sealed;
+    use crate::codec::{Checksummed, CodecChain};
+    use crate::keys::key_doc;
+    use crate::memory::MemKVStore;
+    use crate::options::{ConfiguredStore, StoreOptions};
+    use crate::testing::verify_kvstore_contract;
+    use crate::{get_oid, CompactionPolicy, DocOps, KVStore};
+    use yrs::{Doc, GetString, ReadTxn, Text, Transact};
+
+    #[test]
+    fn kvstore_contract() {
+        let options = StoreOptions {
+            keyspace_prefix: Some(b"staging".to_vec()),
+            ..StoreOptions::default()
+        };
+        let db = ConfiguredStore::new(MemKVStore::new(), options);
+        verify_kvstore_contract(&db);
+        assert!(db.into_inner().is_empty());
+
+        let db = ConfiguredStore::new(MemKVStore::new(), StoreOptions::default());
+        verify_kvstore_contract(&db);
+    }
+
+    #[test]
+    fn configured_store() {
+        let options = StoreOptions {
+            doc_options: yrs::Options {
+                skip_gc: true,
+                ..yrs::Options::default()
+            },
+            compaction_policy: CompactionPolicy {
+                max_pending_updates: Some(2),
+                ..CompactionPolicy::default()
+            },
+            codecs: CodecChain::new().then(Checksummed),
+            keyspace_prefix: Some(b"staging".to_vec()),
+            ..StoreOptions::default()
+        };
+        let db = ConfiguredStore::new(MemKVStore::new(), options);
+        let doc = Doc::new();
+        let text = doc.get_or_insert_text("text");
+        db.insert_doc("doc", &doc.transact()).unwrap();
+        for chunk in ["a", "b", "c"] {
+            let sv = doc.transact().state_vector();
+            text.push(&mut doc.transact_mut(), chunk);
+            db.push_update("doc", &doc.transact().encode_diff_v1(&sv))
+                .unwrap();
+        }
+        // pending updates have been compacted once their limit was exceeded
+        assert_eq!(db.iter_updates("doc").unwrap().count(), 0);
+
+        // there's nothing left to flush after compaction
+        assert!(db.flush_doc("doc").unwrap().is_none());
+        assert!(db.options().new_doc_options().skip_gc);
+        let loaded = Doc::new();
+        let loaded_text = loaded.get_or_insert_text("text");
+        db.load_doc("doc", &mut loaded.transact_mut()).unwrap();
+        assert_eq!(loaded_text.get_string(&loaded.transact()), "abc");
+
+        // all entries are stored within a keyspace prefix, using configured codecs
+        let entries = db.inner().entries();
+        assert!(entries.keys().all(|key| key.starts_with(b"staging")));
+        let oid = get_oid(&db, b"doc").unwrap().unwrap();
+        let state = db.get(&key_doc(oid)).unwrap().unwrap();
+        assert!(is_sealed(&state));
+    }
+
+    #[test]
+    fn backend_options() {
+        let options = StoreOptions {
+            changelog: true,
+            blob_threshold: 1024,
+            ..StoreOptions::default()
+        };
+        let db = MemKVStore::new().with_options(options);
+        assert!(db.options().changelog);
+        assert_eq!(db.options().blob_threshold, 1024);
+
+        // options of the wrapped store are overridden
+        let db = ConfiguredStore::new(db, StoreOptions::default());
+        assert!(!db.options().changelog);
+        assert_eq!(db.options().blob_threshold, 0);
+    }
+
+    #[test]
+    #[should_panic]
+    fn backend_keyspace_prefix() {
+        let options = StoreOptions {
+            keyspace_prefix: Some(b"staging".to_vec()),
+            ..StoreOptions::default()
+        };
+        MemKVStore::new().with_options(options);
+    }
+
+    #[test]
+    #[should_panic]
+    fn unsupported_oid_width() {
+        let options = StoreOptions {
+            oid_width: 3,
+            ..StoreOptions::default()
+        };
+        ConfiguredStore::new(MemKVStore::new(), options);
+    }
+}
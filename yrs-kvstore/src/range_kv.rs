@@ -0,0 +1,141 @@
+//! Adapter implementing [AsyncKVStore] over distributed key-value stores, which expose a minimal
+//! range-oriented API, like the ones of TiKV or etcd. Such stores only need to implement
+//! [RangeKv] trait, while [RangeKvStore] maps [AsyncKVStore] operations on top of it.
+
+use crate::asynchronous::{AsyncDocOps, AsyncKVStore};
+use crate::error::Error;
+use crate::KVEntry;
+use async_trait::async_trait;
+
+/// Minimal interface of a remote, ordered key-value store with range scans. Keys are compared
+/// lexicographically. Ranges are half-open: they include `from` key, but exclude `to` key.
+#[async_trait(?Send)]
+pub trait RangeKv {
+    /// Error type returned by the client.
+    type Error: std::error::Error;
+
+    /// Returns a value stored under given `key` or `None` if key was not found.
+    async fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>, Self::Error>;
+
+    /// Inserts a new `value` under given `key`, replacing an existing one.
+    async fn put(&self, key: &[u8], value: &[u8]) -> Result<(), Self::Error>;
+
+    /// Returns key-value entries within `from`..`to` range, ordered by their keys in ascending
+    /// order or in descending order if `reverse` is set. At most `limit` entries are returned, or
+    /// all of them if `limit` is `None`.
+    async fn scan(
+        &self,
+        from: &[u8],
+        to: &[u8],
+        limit: Option<u32>,
+        reverse: bool,
+    ) -> Result<Vec<(Vec<u8>, Vec<u8>)>, Self::Error>;
+
+    /// Removes all entries within `from`..`to` range.
+    async fn delete_range(&self, from: &[u8], to: &[u8]) -> Result<(), Self::Error>;
+}
+
+/// Persistence layer for Yrs documents build on top of any [RangeKv] client.
+///
+/// Keep in mind that [RangeKv] doesn't expose transactions, so compound [AsyncDocOps] operations
+/// are not atomic, unless the client itself executes its requests within a transaction.
+#[derive(Debug, Clone)]
+pub struct RangeKvStore<C> {
+    client: C,
+}
+
+impl<C: RangeKv> RangeKvStore<C> {
+    /// Creates a new store over provided range key-value `client`.
+    pub fn new(client: C) -> Self {
+        RangeKvStore { client }
+    }
+
+    /// Returns a reference to the underlying client.
+    pub fn client(&self) -> &C {
+        &self.client
+    }
+
+    /// Unwraps the underlying client.
+    pub fn into_inner(self) -> C {
+        self.client
+    }
+}
+
+/// Returns the first key following a given `key` in lexicographical order. It's used to turn
+/// inclusive upper bounds into exclusive ones.
+fn key_after(key: &[u8]) -> Vec<u8> {
+    let mut next = Vec::with_capacity(key.len() + 1);
+    next.extend_from_slice(key);
+    next.push(0);
+    next
+}
+
+#[async_trait(?Send)]
+impl<'a, C: RangeKv> AsyncKVStore<'a> for RangeKvStore<C> {
+    type Error = C::Error;
+    type Cursor = std::vec::IntoIter<RangeKvEntry>;
+    type Entry = RangeKvEntry;
+    type Return = Vec<u8>;
+
+    async fn get(&self, key: &[u8]) -> Result<Option<Self::Return>, Self::Error> {
+        self.client.get(key).await
+    }
+
+    async fn upsert(&self, key: &[u8], value: &[u8]) -> Result<(), Self::Error> {
+        self.client.put(key, value).await
+    }
+
+    async fn remove(&self, key: &[u8]) -> Result<(), Self::Error> {
+        self.client.delete_range(key, &key_after(key)).await
+    }
+
+    async fn remove_range(&self, from: &[u8], to: &[u8]) -> Result<(), Self::Error> {
+        self.client.delete_range(from, &key_after(to)).await
+    }
+
+    async fn iter_range(&self, from: &[u8], to: &[u8]) -> Result<Self::Cursor, Self::Error> {
+        let entries = self.client.scan(from, &key_after(to), None, false).await?;
+        let entries: Vec<_> = entries
+            .into_iter()
+            .map(|(key, value)| RangeKvEntry { key, value })
+            .collect();
+        Ok(entries.into_iter())
+    }
+
+    async fn peek_back(&self, key: &[u8]) -> Result<Option<Self::Entry>, Self::Error> {
+        let entries = self.client.scan(&[], key, Some(1), true).await?;
+        Ok(entries
+            .into_iter()
+            .next()
+            .map(|(key, value)| RangeKvEntry { key, value }))
+    }
+}
+
+impl<'a, C> AsyncDocOps<'a> for RangeKvStore<C>
+where
+    C: RangeKv,
+    Error: From<C::Error>,
+{
+}
+
+/// Entry returned by [RangeKvStore].
+pub struct RangeKvEntry {
+    key: Vec<u8>,
+    value: Vec<u8>,
+}
+
+impl From<RangeKvEntry> for (Vec<u8>, Vec<u8>) {
+    fn from(entry: RangeKvEntry) -> Self {
+        (entry.key, entry.value)
+    }
+}
+
+impl KVEntry for RangeKvEntry {
+    fn key(&self) -> &[u8] {
+        &self.key
+    }
+
+    fn value(&self) -> &[u8] {
+        &self.value
+    }
+}
@@ -0,0 +1,433 @@
+use crate::archive::DocArchive;
+use crate::cancel::Cancel;
+use crate::error::Error;
+use crate::{
+    ChangesIter, DocLease, DocOps, DocStats, DocsNameIter, DocsPage, HistoryIter, KVStore, KeyDump,
+    LoadCursor, LoadReport, MetadataIter, NamedDoc, QuarantinedEntry, SnapshotIter, SubdocIter,
+    UpdatesIter, VerificationReport,
+};
+use yrs::{Doc, Origin, ReadTxn, Snapshot, StateVector, TransactionMut};
+
+/// Read-only view over any store implementing [DocOps]. It exposes only the operations, which
+/// require read capabilities from the database transaction, i.e. loading documents, computing
+/// diffs, reading metadata and iterating over stored entries. This makes it a good fit for
+/// replicas opened over read-only database snapshots, where calling any write operation would
+/// fail deep inside of the backend.
+///
+/// [ReadOnlyStore] intentionally implements neither [KVStore] nor [DocOps] trait, so that
+/// write operations are rejected at compile time.
+#[derive(Debug)]
+pub struct ReadOnlyStore<S> {
+    inner: S,
+}
+
+impl<S> ReadOnlyStore<S> {
+    /// Creates a new read-only view over a given `inner` store.
+    pub fn new(inner: S) -> Self {
+        ReadOnlyStore { inner }
+    }
+
+    /// Unwraps the underlying store, giving back access to its write operations.
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+}
+
+impl<S> From<S> for ReadOnlyStore<S> {
+    fn from(inner: S) -> Self {
+        ReadOnlyStore::new(inner)
+    }
+}
+
+impl<'a, S> ReadOnlyStore<S>
+where
+    S: DocOps<'a>,
+    Error: From<<S as KVStore<'a>>::Error>,
+{
+    /// See: [DocOps::load_doc].
+    pub fn load_doc<K: AsRef<[u8]> + ?Sized>(
+        &self,
+        name: &K,
+        txn: &mut TransactionMut,
+    ) -> Result<bool, Error> {
+        self.inner.load_doc(name, txn)
+    }
+
+    /// See: [DocOps::load_doc_cancellable].
+    pub fn load_doc_cancellable<K: AsRef<[u8]> + ?Sized>(
+        &self,
+        name: &K,
+        txn: &mut TransactionMut,
+        cancel: &Cancel,
+    ) -> Result<bool, Error> {
+        self.inner.load_doc_cancellable(name, txn, cancel)
+    }
+
+    /// See: [DocOps::load_docs].
+    pub fn load_docs<K: AsRef<[u8]>>(&self, names: &[K]) -> Result<Vec<NamedDoc>, Error> {
+        self.inner.load_docs(names)
+    }
+
+    /// See: [DocOps::load_doc_lenient].
+    pub fn load_doc_lenient<K: AsRef<[u8]> + ?Sized>(
+        &self,
+        name: &K,
+        txn: &mut TransactionMut,
+    ) -> Result<LoadReport, Error> {
+        self.inner.load_doc_lenient(name, txn)
+    }
+
+    /// See: [DocOps::get_quarantined].
+    pub fn get_quarantined<K: AsRef<[u8]> + ?Sized>(
+        &self,
+        name: &K,
+    ) -> Result<Vec<QuarantinedEntry>, Error> {
+        self.inner.get_quarantined(name)
+    }
+
+    /// See: [DocOps::load_doc_with_origin].
+    pub fn load_doc_with_origin<K: AsRef<[u8]> + ?Sized, O: Into<Origin>>(
+        &self,
+        name: &K,
+        doc: &Doc,
+        origin: O,
+    ) -> Result<bool, Error> {
+        self.inner.load_doc_with_origin(name, doc, origin)
+    }
+
+    /// See: [DocOps::load_doc_chunked].
+    pub fn load_doc_chunked<K: AsRef<[u8]> + ?Sized>(
+        &self,
+        name: &K,
+        txn: &mut TransactionMut,
+        max_updates_per_step: usize,
+    ) -> Result<Option<LoadCursor>, Error> {
+        self.inner.load_doc_chunked(name, txn, max_updates_per_step)
+    }
+
+    /// See: [DocOps::load_doc_resume].
+    pub fn load_doc_resume(
+        &self,
+        cursor: &mut LoadCursor,
+        txn: &mut TransactionMut,
+    ) -> Result<bool, Error> {
+        self.inner.load_doc_resume(cursor, txn)
+    }
+
+    /// See: [DocOps::get_state_vector].
+    pub fn get_state_vector<K: AsRef<[u8]> + ?Sized>(
+        &self,
+        name: &K,
+    ) -> Result<(Option<StateVector>, bool), Error> {
+        self.inner.get_state_vector(name)
+    }
+
+    /// See: [DocOps::has_pending_updates].
+    pub fn has_pending_updates<K: AsRef<[u8]> + ?Sized>(&self, name: &K) -> Result<bool, Error> {
+        self.inner.has_pending_updates(name)
+    }
+
+    /// See: [DocOps::get_diff].
+    pub fn get_diff<K: AsRef<[u8]> + ?Sized>(
+        &self,
+        name: &K,
+        sv: &StateVector,
+    ) -> Result<Option<Vec<u8>>, Error> {
+        self.inner.get_diff(name, sv)
+    }
+
+    /// See: [DocOps::get_diff_v2].
+    pub fn get_diff_v2<K: AsRef<[u8]> + ?Sized>(
+        &self,
+        name: &K,
+        sv: &StateVector,
+    ) -> Result<Option<Vec<u8>>, Error> {
+        self.inner.get_diff_v2(name, sv)
+    }
+
+    /// See: [DocOps::sync_step].
+    pub fn sync_step<K: AsRef<[u8]> + ?Sized>(
+        &self,
+        name: &K,
+        remote_sv: &StateVector,
+    ) -> Result<Option<(Vec<u8>, StateVector)>, Error> {
+        self.inner.sync_step(name, remote_sv)
+    }
+
+    /// See: [DocOps::sync_step_v2].
+    pub fn sync_step_v2<K: AsRef<[u8]> + ?Sized>(
+        &self,
+        name: &K,
+        remote_sv: &StateVector,
+    ) -> Result<Option<(Vec<u8>, StateVector)>, Error> {
+        self.inner.sync_step_v2(name, remote_sv)
+    }
+
+    /// See: [DocOps::export_doc].
+    pub fn export_doc<K: AsRef<[u8]> + ?Sized>(
+        &self,
+        name: &K,
+    ) -> Result<Option<DocArchive>, Error> {
+        self.inner.export_doc(name)
+    }
+
+    /// See: [DocOps::export_all].
+    pub fn export_all<W: std::io::Write>(&self, writer: &mut W) -> Result<usize, Error> {
+        self.inner.export_all(writer)
+    }
+
+    /// See: [DocOps::export_all_cancellable].
+    pub fn export_all_cancellable<W: std::io::Write>(
+        &self,
+        writer: &mut W,
+        cancel: &Cancel,
+    ) -> Result<usize, Error> {
+        self.inner.export_all_cancellable(writer, cancel)
+    }
+
+    /// See: [DocOps::iter_updates].
+    pub fn iter_updates<K: AsRef<[u8]> + ?Sized>(
+        &self,
+        name: &K,
+    ) -> Result<UpdatesIter<S::Cursor, S::Entry>, Error> {
+        self.inner.iter_updates(name)
+    }
+
+    /// See: [DocOps::doc_stats].
+    pub fn doc_stats<K: AsRef<[u8]> + ?Sized>(&self, name: &K) -> Result<Option<DocStats>, Error> {
+        self.inner.doc_stats(name)
+    }
+
+    /// See: [DocOps::debug_dump].
+    pub fn debug_dump<K: AsRef<[u8]> + ?Sized>(
+        &self,
+        name: &K,
+    ) -> Result<Option<Vec<KeyDump>>, Error> {
+        self.inner.debug_dump(name)
+    }
+
+    /// See: [DocOps::get_doc_expiry].
+    pub fn get_doc_expiry<K: AsRef<[u8]> + ?Sized>(&self, name: &K) -> Result<Option<u64>, Error> {
+        self.inner.get_doc_expiry(name)
+    }
+
+    /// See: [DocOps::schema_version].
+    pub fn schema_version(&self) -> Result<u8, Error> {
+        self.inner.schema_version()
+    }
+
+    /// See: [DocOps::get_meta].
+    pub fn get_meta<K1: AsRef<[u8]> + ?Sized, K2: AsRef<[u8]> + ?Sized>(
+        &self,
+        name: &K1,
+        meta_key: &K2,
+    ) -> Result<Option<Vec<u8>>, Error> {
+        self.inner.get_meta(name, meta_key)
+    }
+
+    /// See: [DocOps::get_meta_json].
+    #[cfg(feature = "json")]
+    pub fn get_meta_json<K1, K2, T>(&self, name: &K1, meta_key: &K2) -> Result<Option<T>, Error>
+    where
+        K1: AsRef<[u8]> + ?Sized,
+        K2: AsRef<[u8]> + ?Sized,
+        T: serde::de::DeserializeOwned,
+    {
+        self.inner.get_meta_json(name, meta_key)
+    }
+
+    /// See: [DocOps::get_json].
+    pub fn get_json<K: AsRef<[u8]> + ?Sized>(&self, name: &K) -> Result<Option<Vec<u8>>, Error> {
+        self.inner.get_json(name)
+    }
+
+    /// See: [DocOps::export_json].
+    #[cfg(feature = "json")]
+    pub fn export_json<K: AsRef<[u8]> + ?Sized>(
+        &self,
+        name: &K,
+    ) -> Result<Option<serde_json::Value>, Error> {
+        self.inner.export_json(name)
+    }
+
+    /// See: [DocOps::get_awareness].
+    pub fn get_awareness<K: AsRef<[u8]> + ?Sized>(
+        &self,
+        name: &K,
+    ) -> Result<Option<S::Return>, Error> {
+        self.inner.get_awareness(name)
+    }
+
+    /// See: [DocOps::get_doc_lease].
+    pub fn get_doc_lease<K: AsRef<[u8]> + ?Sized>(
+        &self,
+        name: &K,
+    ) -> Result<Option<DocLease>, Error> {
+        self.inner.get_doc_lease(name)
+    }
+
+    /// See: [DocOps::doc_exists].
+    pub fn doc_exists<K: AsRef<[u8]> + ?Sized>(&self, name: &K) -> Result<bool, Error> {
+        self.inner.doc_exists(name)
+    }
+
+    /// See: [DocOps::doc_count].
+    pub fn doc_count(&self) -> Result<u64, Error> {
+        self.inner.doc_count()
+    }
+
+    /// See: [DocOps::verify_store].
+    pub fn verify_store(&self) -> Result<VerificationReport, Error> {
+        self.inner.verify_store()
+    }
+
+    /// See: [DocOps::iter_changes].
+    pub fn iter_changes(&self, since_seq: u64) -> Result<ChangesIter<S::Cursor, S::Entry>, Error> {
+        self.inner.iter_changes(since_seq)
+    }
+
+    /// See: [DocOps::iter_docs].
+    pub fn iter_docs(&self) -> Result<DocsNameIter<S::Cursor, S::Entry>, Error> {
+        self.inner.iter_docs()
+    }
+
+    /// See: [DocOps::iter_docs_paged].
+    pub fn iter_docs_paged(
+        &self,
+        start_after: Option<&[u8]>,
+        limit: usize,
+    ) -> Result<DocsPage, Error> {
+        self.inner.iter_docs_paged(start_after, limit)
+    }
+
+    /// See: [DocOps::iter_meta].
+    pub fn iter_meta<K: AsRef<[u8]> + ?Sized>(
+        &self,
+        doc_name: &K,
+    ) -> Result<MetadataIter<S::Cursor, S::Entry>, Error> {
+        self.inner.iter_meta(doc_name)
+    }
+
+    /// See: [DocOps::iter_meta_prefix].
+    pub fn iter_meta_prefix<K1: AsRef<[u8]> + ?Sized, K2: AsRef<[u8]> + ?Sized>(
+        &self,
+        doc_name: &K1,
+        prefix: &K2,
+    ) -> Result<MetadataIter<S::Cursor, S::Entry>, Error> {
+        self.inner.iter_meta_prefix(doc_name, prefix)
+    }
+
+    /// See: [DocOps::iter_meta_range].
+    pub fn iter_meta_range<K1, K2, K3>(
+        &self,
+        doc_name: &K1,
+        from: &K2,
+        to: &K3,
+    ) -> Result<MetadataIter<S::Cursor, S::Entry>, Error>
+    where
+        K1: AsRef<[u8]> + ?Sized,
+        K2: AsRef<[u8]> + ?Sized,
+        K3: AsRef<[u8]> + ?Sized,
+    {
+        self.inner.iter_meta_range(doc_name, from, to)
+    }
+
+    /// See: [DocOps::get_snapshot].
+    pub fn get_snapshot<K1: AsRef<[u8]> + ?Sized, K2: AsRef<[u8]> + ?Sized>(
+        &self,
+        name: &K1,
+        snapshot_id: &K2,
+    ) -> Result<Option<Snapshot>, Error> {
+        self.inner.get_snapshot(name, snapshot_id)
+    }
+
+    /// See: [DocOps::load_snapshot].
+    pub fn load_snapshot<K1: AsRef<[u8]> + ?Sized, K2: AsRef<[u8]> + ?Sized>(
+        &self,
+        name: &K1,
+        snapshot_id: &K2,
+        txn: &mut TransactionMut,
+    ) -> Result<bool, Error> {
+        self.inner.load_snapshot(name, snapshot_id, txn)
+    }
+
+    /// See: [DocOps::iter_snapshots].
+    pub fn iter_snapshots<K: AsRef<[u8]> + ?Sized>(
+        &self,
+        doc_name: &K,
+    ) -> Result<SnapshotIter<S::Cursor, S::Entry>, Error> {
+        self.inner.iter_snapshots(doc_name)
+    }
+
+    /// See: [DocOps::load_subdoc].
+    pub fn load_subdoc<K: AsRef<[u8]> + ?Sized>(
+        &self,
+        parent_name: &K,
+        guid: &str,
+        txn: &mut TransactionMut,
+    ) -> Result<bool, Error> {
+        self.inner.load_subdoc(parent_name, guid, txn)
+    }
+
+    /// See: [DocOps::load_subdocs].
+    pub fn load_subdocs<K: AsRef<[u8]> + ?Sized, T: ReadTxn>(
+        &self,
+        parent_name: &K,
+        parent_txn: &T,
+    ) -> Result<usize, Error> {
+        self.inner.load_subdocs(parent_name, parent_txn)
+    }
+
+    /// See: [DocOps::iter_subdocs].
+    pub fn iter_subdocs<K: AsRef<[u8]> + ?Sized>(
+        &self,
+        parent_name: &K,
+    ) -> Result<SubdocIter<S::Cursor, S::Entry>, Error> {
+        self.inner.iter_subdocs(parent_name)
+    }
+
+    /// See: [DocOps::iter_history].
+    pub fn iter_history<K: AsRef<[u8]> + ?Sized>(
+        &self,
+        doc_name: &K,
+    ) -> Result<HistoryIter<S::Cursor, S::Entry>, Error> {
+        self.inner.iter_history(doc_name)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::memory::MemKVStore;
+    use crate::read_only::ReadOnlyStore;
+    use crate::DocOps;
+    use yrs::{Doc, GetString, ReadTxn, StateVector, Text, Transact};
+
+    #[test]
+    fn read_through_view() {
+        let db = MemKVStore::new();
+        let doc = Doc::new();
+        let text = doc.get_or_insert_text("text");
+        text.push(&mut doc.transact_mut(), "hello");
+        db.insert_doc("doc", &doc.transact()).unwrap();
+        db.insert_meta("doc", "key", b"value").unwrap();
+
+        let db = ReadOnlyStore::new(db);
+        assert!(db.doc_exists("doc").unwrap());
+        assert_eq!(db.doc_count().unwrap(), 1);
+        let docs: Vec<_> = db.iter_docs().unwrap().collect();
+        assert_eq!(docs, vec!["doc".as_bytes().into()]);
+        assert_eq!(db.get_meta("doc", "key").unwrap(), Some(b"value".to_vec()));
+
+        let (sv, up_to_date) = db.get_state_vector("doc").unwrap();
+        assert_eq!(sv, Some(doc.transact().state_vector()));
+        assert!(up_to_date);
+        let diff = db.get_diff("doc", &StateVector::default()).unwrap();
+        assert!(diff.is_some());
+
+        let loaded = Doc::new();
+        let text = loaded.get_or_insert_text("text");
+        assert!(db.load_doc("doc", &mut loaded.transact_mut()).unwrap());
+        let txn = loaded.transact();
+        assert_eq!(text.get_string(&txn), "hello");
+    }
+}
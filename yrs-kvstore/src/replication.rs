@@ -0,0 +1,223 @@
+//! Incremental replication of documents between two stores, i.e. to keep a secondary database
+//! in sync as a warm standby (see: [replicate]).
+
+use crate::error::Error;
+use crate::keys::{
+    doc_oid_name, key_doc, key_doc_v2, key_update_v2, oid_from_bytes, Key, KEYSPACE_DOC,
+    KEYSPACE_OID, OID, V1,
+};
+use crate::{DocOps, KVEntry, KVStore};
+use lib0::decoding::{Cursor, Read};
+use lib0::encoding::Write;
+use std::collections::HashMap;
+use yrs::updates::decoder::Decode;
+use yrs::updates::encoder::Encode;
+use yrs::StateVector;
+
+/// Position of the replication process (see: [replicate]). For every replicated document it
+/// remembers a digest of its persisted state and updates at the time it was replicated, together
+/// with the state vector which has been sent to the destination store. Cursor can be persisted
+/// using [ReplicationCursor::encode], so that replication can be resumed after restart.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ReplicationCursor {
+    docs: HashMap<Box<[u8]>, DocCursor>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct DocCursor {
+    digest: u32,
+    state_vector: StateVector,
+}
+
+impl ReplicationCursor {
+    /// Creates a new cursor pointing to the beginning of replication: first [replicate] call will
+    /// copy all documents.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns a number of documents tracked by current cursor.
+    pub fn len(&self) -> usize {
+        self.docs.len()
+    }
+
+    /// Checks if current cursor doesn't track any documents.
+    pub fn is_empty(&self) -> bool {
+        self.docs.is_empty()
+    }
+
+    /// Serializes current cursor into a binary blob.
+    pub fn encode(&self) -> Vec<u8> {
+        let mut buf = Vec::new();
+        buf.write_var(self.docs.len());
+        for (name, doc) in self.docs.iter() {
+            buf.write_buf(name);
+            buf.write_u32(doc.digest);
+            buf.write_buf(doc.state_vector.encode_v1());
+        }
+        buf
+    }
+
+    /// Deserializes a cursor from a binary blob produced by [ReplicationCursor::encode].
+    pub fn decode(data: &[u8]) -> Result<Self, Error> {
+        let mut cursor = Cursor::new(data);
+        let len: usize = cursor.read_var()?;
+        let mut docs = HashMap::with_capacity(len);
+        for _ in 0..len {
+            let name: Box<[u8]> = cursor.read_buf()?.into();
+            let digest = cursor.read_u32()?;
+            let state_vector = StateVector::decode_v1(cursor.read_buf()?)?;
+            docs.insert(
+                name,
+                DocCursor {
+                    digest,
+                    state_vector,
+                },
+            );
+        }
+        Ok(ReplicationCursor { docs })
+    }
+}
+
+/// Statistics of a single [replicate] call.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ReplicationStats {
+    /// Number of documents, which changes have been copied into the destination store.
+    pub replicated: usize,
+    /// Number of documents removed from the destination store, as they no longer exist in the
+    /// source store.
+    pub removed: usize,
+}
+
+/// Copies documents from `src` to `dst` store, which have changed since the position remembered
+/// by a given cursor, and returns the cursor advanced to the current state of `src`.
+///
+/// Documents are compared using digests of their persisted state and pending updates, so
+/// unchanged documents are skipped without being decoded. For changed documents only the changes
+/// missing from the last replicated state vector are pushed into `dst` as a new update (see:
+/// [DocOps::push_update]). Documents which no longer exist in `src` are removed from `dst`.
+/// Document metadata is not replicated.
+///
+/// Destination store is expected to be modified only by the replication process itself.
+pub fn replicate<'a, 'b, S, D>(
+    src: &S,
+    dst: &D,
+    since: ReplicationCursor,
+) -> Result<(ReplicationCursor, ReplicationStats), Error>
+where
+    S: DocOps<'a>,
+    D: DocOps<'b>,
+    Error: From<<S as KVStore<'a>>::Error> + From<<D as KVStore<'b>>::Error>,
+{
+    let mut prev = since.docs;
+    let mut next = HashMap::with_capacity(prev.len());
+    let mut stats = ReplicationStats::default();
+    let start = Key::from_const([V1, KEYSPACE_OID]);
+    let end = Key::from_const([V1, KEYSPACE_DOC]);
+    for e in src.iter_range(&start, &end)? {
+        let name: Box<[u8]> = doc_oid_name(e.key()).into();
+        let digest = doc_digest(src, oid_from_bytes(e.value()))?;
+        let doc = match prev.remove(&name) {
+            Some(doc) if doc.digest == digest => doc,
+            stale => {
+                let sv = stale.map(|doc| doc.state_vector).unwrap_or_default();
+                let state_vector = match src.sync_step(&name, &sv)? {
+                    Some((update, state_vector)) => {
+                        dst.push_update(&name, &update)?;
+                        stats.replicated += 1;
+                        state_vector
+                    }
+                    // document has no state nor updates
+                    None => sv,
+                };
+                DocCursor {
+                    digest,
+                    state_vector,
+                }
+            }
+        };
+        next.insert(name, doc);
+    }
+    // remaining documents have been removed from the source store
+    for name in prev.keys() {
+        dst.clear_doc(name)?;
+        stats.removed += 1;
+    }
+    Ok((ReplicationCursor { docs: next }, stats))
+}
+
+/// Computes a digest of persisted state and pending updates of a document with a given `oid`.
+fn doc_digest<'a, DB: DocOps<'a>>(db: &DB, oid: OID) -> Result<u32, Error>
+where
+    Error: From<<DB as KVStore<'a>>::Error>,
+{
+    let mut hasher = crc32fast::Hasher::new();
+    // document state (v1), state vector and pending updates are placed next to each other
+    let start = key_doc(oid);
+    let end = key_update_v2(oid, u32::MAX);
+    for e in db.iter_range(&start, &end)? {
+        hasher.update(e.key());
+        hasher.update(e.value());
+    }
+    if let Some(doc_state) = db.get(&key_doc_v2(oid))? {
+        hasher.update(doc_state.as_ref());
+    }
+    Ok(hasher.finalize())
+}
+
+#[cfg(test)]
+mod test {
+    use crate::memory::MemKVStore;
+    use crate::replication::{replicate, ReplicationCursor};
+    use crate::DocOps;
+    use yrs::{Doc, GetString, ReadTxn, Text, Transact};
+
+    fn load(db: &MemKVStore, name: &str) -> String {
+        let doc = Doc::new();
+        let text = doc.get_or_insert_text("text");
+        db.load_doc(name, &mut doc.transact_mut()).unwrap();
+        let txn = doc.transact();
+        text.get_string(&txn)
+    }
+
+    #[test]
+    fn replicate_changes() {
+        let src = MemKVStore::new();
+        let dst = MemKVStore::new();
+        let doc = Doc::new();
+        let text = doc.get_or_insert_text("text");
+        text.push(&mut doc.transact_mut(), "hello");
+        src.insert_doc("a", &doc.transact()).unwrap();
+        src.insert_doc("b", &doc.transact()).unwrap();
+
+        let (cursor, stats) = replicate(&src, &dst, ReplicationCursor::new()).unwrap();
+        assert_eq!(stats.replicated, 2);
+        assert_eq!(cursor.len(), 2);
+        assert_eq!(load(&dst, "a"), "hello");
+        assert_eq!(load(&dst, "b"), "hello");
+
+        // unchanged documents are skipped
+        let cursor = ReplicationCursor::decode(&cursor.encode()).unwrap();
+        let (cursor, stats) = replicate(&src, &dst, cursor).unwrap();
+        assert_eq!(stats.replicated, 0);
+
+        let sv = doc.transact().state_vector();
+        text.push(&mut doc.transact_mut(), " world");
+        let update = doc.transact().encode_diff_v1(&sv);
+        src.push_update("a", &update).unwrap();
+        src.clear_doc("b").unwrap();
+
+        let (cursor, stats) = replicate(&src, &dst, cursor).unwrap();
+        assert_eq!(stats.replicated, 1);
+        assert_eq!(stats.removed, 1);
+        assert_eq!(cursor.len(), 1);
+        assert_eq!(load(&dst, "a"), "hello world");
+        assert!(!dst.doc_exists("b").unwrap());
+
+        // flushing source document doesn't change its content
+        src.flush_doc("a").unwrap();
+        let (_, stats) = replicate(&src, &dst, cursor).unwrap();
+        assert_eq!(stats.replicated, 1);
+        assert_eq!(load(&dst, "a"), "hello world");
+    }
+}
@@ -0,0 +1,302 @@
+use crate::batch::{BatchOp, WriteBatch};
+use crate::error::Error;
+use crate::keys::V1;
+use crate::metrics::StoreMetrics;
+use crate::observer::UpdateObservers;
+use crate::options::StoreOptions;
+use crate::{DocOps, KVEntry, KVStore};
+
+/// Adapter over any [KVStore], which transparently prefixes every key with a scope identifier
+/// (i.e. tenant or workspace id). This way a single physical database can host many isolated
+/// namespaces of documents: two scoped stores with different scopes never see each other's
+/// documents, even when they use the same document names.
+///
+/// Scope is encoded as `{len:1}{scope:len}` key prefix, so that no scope is ever a prefix of
+/// another one. Since scopes can't be empty, scoped entries never collide with unscoped ones,
+/// which all start with a `0` version byte (see: [crate::keys::V1]). Alternatively a custom raw
+/// key prefix can be used instead (see: [ScopedStore::with_prefix]).
+///
+/// Update observers are not shared with the inner store, as document names of different scopes
+/// may overlap. Use [ScopedStore::with_update_observers] to observe updates within a scope.
+#[derive(Debug)]
+pub struct ScopedStore<S> {
+    inner: S,
+    prefix: Vec<u8>,
+    /// Position of the scope identifier within the `prefix`.
+    scope_pos: usize,
+    update_observers: UpdateObservers,
+}
+
+impl<S> ScopedStore<S> {
+    /// Creates a new store, which keeps all of its entries inside of a given `scope` of an
+    /// `inner` store.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `scope` is empty or longer than 255 bytes.
+    pub fn new<K: AsRef<[u8]> + ?Sized>(inner: S, scope: &K) -> Self {
+        let scope = scope.as_ref();
+        assert!(
+            !scope.is_empty() && scope.len() <= u8::MAX as usize,
+            "scope must be between 1 and 255 bytes long"
+        );
+        let mut prefix = Vec::with_capacity(scope.len() + 1);
+        prefix.push(scope.len() as u8);
+        prefix.extend_from_slice(scope);
+        ScopedStore {
+            inner,
+            prefix,
+            scope_pos: 1,
+            update_observers: UpdateObservers::default(),
+        }
+    }
+
+    /// Creates a new store, which prepends a given raw `prefix` in place of the [crate::keys::V1]
+    /// version byte of every key of an `inner` store. This way multiple independent datasets
+    /// (i.e. staging and production ones) can share a single physical database using prefixes of
+    /// their own choice.
+    ///
+    /// Unlike [ScopedStore::new], the `prefix` is used as is, so it's up to the caller to make sure
+    /// that none of the prefixes used within the same database is a prefix of another one.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `prefix` is empty or starts with [crate::keys::V1] byte, as it would collide with
+    /// unscoped entries.
+    pub fn with_prefix<K: AsRef<[u8]> + ?Sized>(inner: S, prefix: &K) -> Self {
+        let prefix = prefix.as_ref();
+        assert!(
+            !prefix.is_empty() && prefix[0] != V1,
+            "prefix must be non-empty and can't start with a V1 byte"
+        );
+        ScopedStore {
+            inner,
+            prefix: prefix.to_vec(),
+            scope_pos: 0,
+            update_observers: UpdateObservers::default(),
+        }
+    }
+
+    /// Sets an [UpdateObservers] registry notified about every persisted document update within
+    /// this scope (see: [DocOps::observe_updates]).
+    pub fn with_update_observers(mut self, observers: UpdateObservers) -> Self {
+        self.update_observers = observers;
+        self
+    }
+
+    /// Returns a scope identifier of the current store. For stores created using
+    /// [ScopedStore::with_prefix] it's the raw key prefix.
+    pub fn scope(&self) -> &[u8] {
+        &self.prefix[self.scope_pos..]
+    }
+
+    /// Returns a reference to the wrapped store.
+    pub fn inner(&self) -> &S {
+        &self.inner
+    }
+
+    /// Unwraps the underlying store.
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+
+    fn scoped(&self, key: &[u8]) -> Vec<u8> {
+        let mut scoped = Vec::with_capacity(self.prefix.len() + key.len());
+        scoped.extend_from_slice(&self.prefix);
+        scoped.extend_from_slice(key);
+        scoped
+    }
+}
+
+impl<'a, S> DocOps<'a> for ScopedStore<S>
+where
+    S: DocOps<'a>,
+    Error: From<<S as KVStore<'a>>::Error>,
+{
+    fn options(&self) -> &StoreOptions {
+        self.inner.options()
+    }
+
+    fn lock_oid_allocation(&self) -> Result<(), Error> {
+        self.inner.lock_oid_allocation()
+    }
+
+    fn update_observers(&self) -> Option<&UpdateObservers> {
+        Some(&self.update_observers)
+    }
+
+    fn metrics(&self) -> Option<&dyn StoreMetrics> {
+        self.inner.metrics()
+    }
+}
+
+impl<'a, S: KVStore<'a>> KVStore<'a> for ScopedStore<S> {
+    type Error = S::Error;
+    type Cursor = ScopedCursor<S::Cursor>;
+    type Entry = ScopedEntry<S::Entry>;
+    type Return = S::Return;
+
+    fn get(&self, key: &[u8]) -> Result<Option<Self::Return>, Self::Error> {
+        self.inner.get(&self.scoped(key))
+    }
+
+    fn upsert(&self, key: &[u8], value: &[u8]) -> Result<(), Self::Error> {
+        self.inner.upsert(&self.scoped(key), value)
+    }
+
+    fn remove(&self, key: &[u8]) -> Result<(), Self::Error> {
+        self.inner.remove(&self.scoped(key))
+    }
+
+    fn remove_range(&self, from: &[u8], to: &[u8]) -> Result<(), Self::Error> {
+        self.inner
+            .remove_range(&self.scoped(from), &self.scoped(to))
+    }
+
+    fn iter_range(&self, from: &[u8], to: &[u8]) -> Result<Self::Cursor, Self::Error> {
+        let cursor = self
+            .inner
+            .iter_range(&self.scoped(from), &self.scoped(to))?;
+        Ok(ScopedCursor {
+            cursor,
+            prefix_len: self.prefix.len(),
+        })
+    }
+
+    fn contains_range(&self, from: &[u8], to: &[u8]) -> Result<bool, Self::Error> {
+        self.inner
+            .contains_range(&self.scoped(from), &self.scoped(to))
+    }
+
+    fn peek_back(&self, key: &[u8]) -> Result<Option<Self::Entry>, Self::Error> {
+        if let Some(entry) = self.inner.peek_back(&self.scoped(key))? {
+            // the entry prior to the first key of the scope belongs to some other scope
+            if entry.key().starts_with(&self.prefix) {
+                return Ok(Some(ScopedEntry {
+                    entry,
+                    prefix_len: self.prefix.len(),
+                }));
+            }
+        }
+        Ok(None)
+    }
+
+    fn commit_batch(&self, batch: WriteBatch) -> Result<(), Self::Error> {
+        let mut scoped = self.inner.batch();
+        for op in batch {
+            match op {
+                BatchOp::Upsert(key, value) => scoped.upsert(&self.scoped(&key), &value),
+                BatchOp::Remove(key) => scoped.remove(&self.scoped(&key)),
+                BatchOp::RemoveRange(from, to) => {
+                    scoped.remove_range(&self.scoped(&from), &self.scoped(&to))
+                }
+            }
+        }
+        self.inner.commit_batch(scoped)
+    }
+}
+
+/// Cursor returned by [ScopedStore::iter_range].
+pub struct ScopedCursor<C> {
+    pub(crate) cursor: C,
+    pub(crate) prefix_len: usize,
+}
+
+impl<C: Iterator> Iterator for ScopedCursor<C> {
+    type Item = ScopedEntry<C::Item>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let entry = self.cursor.next()?;
+        Some(ScopedEntry {
+            entry,
+            prefix_len: self.prefix_len,
+        })
+    }
+}
+
+/// Entry returned by [ScopedStore]. Its key has the scope prefix stripped.
+pub struct ScopedEntry<E> {
+    pub(crate) entry: E,
+    pub(crate) prefix_len: usize,
+}
+
+impl<E: KVEntry> KVEntry for ScopedEntry<E> {
+    fn key(&self) -> &[u8] {
+        &self.entry.key()[self.prefix_len..]
+    }
+
+    fn value(&self) -> &[u8] {
+        self.entry.value()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::memory::MemKVStore;
+    use crate::scoped::ScopedStore;
+    use crate::testing::verify_kvstore_contract;
+    use crate::DocOps;
+    use yrs::{Doc, GetString, Text, Transact};
+
+    #[test]
+    fn kvstore_contract() {
+        let db = ScopedStore::new(MemKVStore::new(), "tenant");
+        verify_kvstore_contract(&db);
+        assert!(db.into_inner().is_empty());
+    }
+
+    #[test]
+    fn scopes_are_isolated() {
+        fn insert(store: &ScopedStore<MemKVStore>, content: &str) {
+            let doc = Doc::new();
+            let text = doc.get_or_insert_text("text");
+            text.push(&mut doc.transact_mut(), content);
+            store.insert_doc("doc", &doc.transact()).unwrap();
+        }
+
+        fn verify(store: &ScopedStore<MemKVStore>, content: &str) {
+            let docs: Vec<_> = store.iter_docs().unwrap().collect();
+            assert_eq!(docs, vec!["doc".as_bytes().into()]);
+
+            let doc = Doc::new();
+            let text = doc.get_or_insert_text("text");
+            assert!(store.load_doc("doc", &mut doc.transact_mut()).unwrap());
+            assert_eq!(text.get_string(&doc.transact()), content);
+        }
+
+        let a = ScopedStore::new(MemKVStore::new(), "a");
+        insert(&a, "hello");
+        let b = ScopedStore::new(a.into_inner(), "ab");
+        insert(&b, "world");
+        verify(&b, "world");
+        let a = ScopedStore::new(b.into_inner(), "a");
+        verify(&a, "hello");
+
+        a.clear_doc("doc").unwrap();
+        assert!(a.iter_docs().unwrap().next().is_none());
+        let b = ScopedStore::new(a.into_inner(), "ab");
+        verify(&b, "world");
+    }
+
+    #[test]
+    fn custom_prefixes() {
+        let db = ScopedStore::with_prefix(MemKVStore::new(), "staging/");
+        verify_kvstore_contract(&db);
+        assert_eq!(db.scope(), b"staging/");
+
+        let doc = Doc::new();
+        let text = doc.get_or_insert_text("text");
+        text.push(&mut doc.transact_mut(), "hello");
+        db.insert_doc("doc", &doc.transact()).unwrap();
+        let inner = db.into_inner();
+        assert!(inner
+            .entries()
+            .keys()
+            .all(|key| key.starts_with(b"staging/")));
+
+        let db = ScopedStore::with_prefix(inner, "prod/");
+        assert!(db.iter_docs().unwrap().next().is_none());
+        let db = ScopedStore::with_prefix(db.into_inner(), "staging/");
+        assert!(db.doc_exists("doc").unwrap());
+    }
+}
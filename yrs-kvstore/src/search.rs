@@ -0,0 +1,294 @@
+//! Basic full-text search over documents persisted by [DocOps]. Text content of selected root-level
+//! shared types (see: [SearchIndex]) is split into lowercase terms, which are written into
+//! a separate search index keyspace as postings pointing back to documents containing them.
+//! Documents are indexed when they are flushed (see: [flush_and_index]) and can be found by terms
+//! they contain (see: [search]).
+//!
+//! Postings are removed together with the document (see: [DocOps::clear_doc]) or explicitly using
+//! [remove_from_index]. Postings of documents, which have been renamed since they were indexed,
+//! are not returned by [search].
+
+use crate::error::Error;
+use crate::keys::{
+    key_posting, key_posting_end, key_posting_start, key_search_terms, posting_oid, OID, TERMINATOR,
+};
+use crate::{get_oid, remove_search_postings, DocOps, KVEntry, KVStore};
+use std::collections::{BTreeSet, HashMap};
+use std::sync::Arc;
+use yrs::{Doc, GetString, Transact, XmlFragment, XmlOut};
+
+/// Root-level shared type, which text content is indexed by a [SearchIndex].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum IndexedRoot {
+    /// Root-level [yrs::TextRef] with a given name.
+    Text(Arc<str>),
+    /// Root-level [yrs::XmlFragmentRef] with a given name. Content of all of its (possibly nested)
+    /// text nodes is indexed.
+    XmlFragment(Arc<str>),
+}
+
+/// Definition of a document content, which is indexed for search.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SearchIndex {
+    roots: Vec<IndexedRoot>,
+}
+
+impl SearchIndex {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Includes content of a root-level text with a given `name` in the index.
+    pub fn text<N: Into<Arc<str>>>(mut self, name: N) -> Self {
+        self.roots.push(IndexedRoot::Text(name.into()));
+        self
+    }
+
+    /// Includes content of a root-level XML fragment with a given `name` in the index.
+    pub fn xml_fragment<N: Into<Arc<str>>>(mut self, name: N) -> Self {
+        self.roots.push(IndexedRoot::XmlFragment(name.into()));
+        self
+    }
+
+    /// Returns a set of terms extracted from indexed root-level types of a given `doc`.
+    pub fn terms(&self, doc: &Doc) -> BTreeSet<String> {
+        let mut terms = BTreeSet::new();
+        for root in self.roots.iter() {
+            match root {
+                IndexedRoot::Text(name) => {
+                    let text = doc.get_or_insert_text(name.clone());
+                    let txn = doc.transact();
+                    terms.extend(tokenize(&text.get_string(&txn)));
+                }
+                IndexedRoot::XmlFragment(name) => {
+                    let fragment = doc.get_or_insert_xml_fragment(name.clone());
+                    let txn = doc.transact();
+                    for node in fragment.successors(&txn) {
+                        if let XmlOut::Text(text) = node {
+                            terms.extend(tokenize(&text.get_string(&txn)));
+                        }
+                    }
+                }
+            }
+        }
+        terms
+    }
+}
+
+/// Splits a given `text` into lowercase terms: continuous sequences of alphanumeric characters.
+pub fn tokenize(text: &str) -> impl Iterator<Item = String> + '_ {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|term| !term.is_empty())
+        .map(|term| term.to_lowercase())
+}
+
+/// Merges pending updates of a document with given `name` into its state (see:
+/// [DocOps::flush_doc]) and indexes the resulting document content using a given `index`.
+/// Returns the flushed document or `None` if document was not found.
+///
+/// This feature requires a write capabilities from the database transaction.
+pub fn flush_and_index<'a, S, K>(
+    store: &S,
+    name: &K,
+    index: &SearchIndex,
+) -> Result<Option<Doc>, Error>
+where
+    S: DocOps<'a>,
+    K: AsRef<[u8]> + ?Sized,
+    Error: From<<S as KVStore<'a>>::Error>,
+{
+    let doc = match store.flush_doc(name)? {
+        Some(doc) => doc,
+        None => {
+            // there were no pending updates to merge, stored document state is up to date
+            let doc = Doc::with_options(store.options().new_doc_options());
+            if !store.load_doc(name, &mut doc.transact_mut())? {
+                return Ok(None);
+            }
+            doc
+        }
+    };
+    index_doc(store, name, &doc, index)?;
+    Ok(Some(doc))
+}
+
+/// Replaces search index postings of a document with given `name` with terms extracted from
+/// a given `doc` using provided `index`. Returns `false` if document was not found.
+///
+/// This feature requires a write capabilities from the database transaction.
+pub fn index_doc<'a, S, K>(
+    store: &S,
+    name: &K,
+    doc: &Doc,
+    index: &SearchIndex,
+) -> Result<bool, Error>
+where
+    S: DocOps<'a>,
+    K: AsRef<[u8]> + ?Sized,
+    Error: From<<S as KVStore<'a>>::Error>,
+{
+    let name = name.as_ref();
+    let oid = match get_oid(store, name)? {
+        Some(oid) => oid,
+        None => return Ok(false),
+    };
+    let terms = index.terms(doc);
+    let mut batch = store.batch();
+    for term in indexed_terms(store, oid)? {
+        if !terms.contains(&term) {
+            batch.remove(&key_posting(term.as_bytes(), oid));
+        }
+    }
+    // indexed terms: {term:n}0{term:n}0...
+    let mut value = Vec::new();
+    for term in terms.iter() {
+        batch.upsert(&key_posting(term.as_bytes(), oid), name);
+        value.extend_from_slice(term.as_bytes());
+        value.push(TERMINATOR);
+    }
+    batch.upsert(&key_search_terms(oid), &value);
+    store.commit_batch(batch)?;
+    Ok(true)
+}
+
+/// Removes all search index postings of a document with given `name`. Returns `false` if
+/// document was not found.
+///
+/// This feature requires a write capabilities from the database transaction.
+pub fn remove_from_index<'a, S, K>(store: &S, name: &K) -> Result<bool, Error>
+where
+    S: DocOps<'a>,
+    K: AsRef<[u8]> + ?Sized,
+    Error: From<<S as KVStore<'a>>::Error>,
+{
+    let oid = match get_oid(store, name.as_ref())? {
+        Some(oid) => oid,
+        None => return Ok(false),
+    };
+    let mut batch = store.batch();
+    remove_search_postings(store, &mut batch, oid)?;
+    store.commit_batch(batch)?;
+    Ok(true)
+}
+
+/// Returns names of all indexed documents containing every term of a given `query` (see:
+/// [tokenize]), ordered by their names. Query without any terms matches no documents.
+///
+/// This feature requires only the read capabilities from the database transaction.
+pub fn search<'a, S>(store: &S, query: &str) -> Result<Vec<Box<[u8]>>, Error>
+where
+    S: DocOps<'a>,
+    Error: From<<S as KVStore<'a>>::Error>,
+{
+    let terms: BTreeSet<_> = tokenize(query).collect();
+    let mut matches: Option<HashMap<OID, Box<[u8]>>> = None;
+    for term in terms {
+        let start = key_posting_start(term.as_bytes());
+        let end = key_posting_end(term.as_bytes());
+        let mut found = HashMap::new();
+        for e in store.iter_range(&start, &end)? {
+            let oid = posting_oid(e.key());
+            if matches.as_ref().is_none_or(|m| m.contains_key(&oid)) {
+                found.insert(oid, e.value().into());
+            }
+        }
+        matches = Some(found);
+    }
+    let mut names = Vec::new();
+    for (oid, name) in matches.unwrap_or_default() {
+        // skip postings of documents removed or renamed since they have been indexed
+        if get_oid(store, &name)? == Some(oid) {
+            names.push(name);
+        }
+    }
+    names.sort();
+    Ok(names)
+}
+
+/// Returns terms indexed for a document with a given `oid`.
+fn indexed_terms<'a, S>(store: &S, oid: OID) -> Result<Vec<String>, Error>
+where
+    S: DocOps<'a>,
+    Error: From<<S as KVStore<'a>>::Error>,
+{
+    let mut terms = Vec::new();
+    if let Some(value) = store.get(&key_search_terms(oid))? {
+        for term in value.as_ref().split(|b| *b == TERMINATOR) {
+            if !term.is_empty() {
+                terms.push(String::from_utf8_lossy(term).into_owned());
+            }
+        }
+    }
+    Ok(terms)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::keys::{key_posting_end, key_posting_start};
+    use crate::memory::MemKVStore;
+    use crate::search::{flush_and_index, remove_from_index, search, tokenize, SearchIndex};
+    use crate::{DocOps, KVStore};
+    use yrs::{Doc, ReadTxn, StateVector, Text, Transact, XmlFragment, XmlTextPrelim};
+
+    fn names(found: Vec<Box<[u8]>>) -> Vec<String> {
+        found
+            .iter()
+            .map(|name| String::from_utf8_lossy(name).into_owned())
+            .collect()
+    }
+
+    #[test]
+    fn tokenize_text() {
+        let terms: Vec<_> = tokenize("Hello, World! hello-world 42").collect();
+        assert_eq!(terms, vec!["hello", "world", "hello", "world", "42"]);
+    }
+
+    #[test]
+    fn index_and_search() {
+        let db = MemKVStore::new();
+        let index = SearchIndex::new().text("title").xml_fragment("body");
+
+        let doc = Doc::new();
+        let title = doc.get_or_insert_text("title");
+        let body = doc.get_or_insert_xml_fragment("body");
+        title.push(&mut doc.transact_mut(), "Quarterly report");
+        body.push_back(&mut doc.transact_mut(), XmlTextPrelim::new("Revenue grew"));
+        let update = doc
+            .transact()
+            .encode_state_as_update_v1(&StateVector::default());
+        db.push_update("a", &update).unwrap();
+        assert!(flush_and_index(&db, "a", &index).unwrap().is_some());
+
+        let doc = Doc::new();
+        let title = doc.get_or_insert_text("title");
+        title.push(&mut doc.transact_mut(), "Annual report");
+        db.insert_doc("b", &doc.transact()).unwrap();
+        assert!(flush_and_index(&db, "b", &index).unwrap().is_some());
+        assert!(flush_and_index(&db, "missing", &index).unwrap().is_none());
+
+        assert_eq!(names(search(&db, "report").unwrap()), vec!["a", "b"]);
+        assert_eq!(names(search(&db, "REVENUE report").unwrap()), vec!["a"]);
+        assert!(search(&db, "revenue annual").unwrap().is_empty());
+        assert!(search(&db, "").unwrap().is_empty());
+
+        // reindexing replaces previous postings
+        let sv = doc.transact().state_vector();
+        title.remove_range(&mut doc.transact_mut(), 0, 7);
+        title.insert(&mut doc.transact_mut(), 0, "Final ");
+        db.push_update("b", &doc.transact().encode_diff_v1(&sv))
+            .unwrap();
+        flush_and_index(&db, "b", &index).unwrap();
+        assert!(search(&db, "annual").unwrap().is_empty());
+        assert_eq!(names(search(&db, "final").unwrap()), vec!["b"]);
+
+        // postings are removed together with the document
+        db.clear_doc("a").unwrap();
+        assert_eq!(names(search(&db, "report").unwrap()), vec!["b"]);
+        let start = key_posting_start(b"revenue");
+        let end = key_posting_end(b"revenue");
+        assert_eq!(db.iter_range(&start, &end).unwrap().count(), 0);
+
+        assert!(remove_from_index(&db, "b").unwrap());
+        assert!(search(&db, "report").unwrap().is_empty());
+    }
+}
@@ -0,0 +1,191 @@
+//! Server side of the y-sync protocol implemented directly over a [crate::DocOps] store (or any
+//! other [DynDocStore]). Documents are never loaded into memory: state vectors and diffs are
+//! computed from the persisted document state and its pending updates, while updates received
+//! from remote peers are persisted using [crate::DocOps::push_update].
+//!
+//! A typical websocket server sends [start_sync] message to every newly connected peer and then
+//! passes every binary frame it receives to [handle_messages], sending back all returned replies.
+//! Broadcasting updates to other connected peers is left to the caller.
+
+use crate::dynamic::DynDocStore;
+use crate::error::Error;
+use yrs::sync::{AwarenessUpdate, Message, MessageReader, SyncMessage};
+use yrs::updates::decoder::{Decode, DecoderV1};
+use yrs::updates::encoder::Encode;
+use yrs::{StateVector, Update};
+
+/// Returns a sync step 1 message, which should be sent to a newly connected peer. It contains
+/// a state vector of a document with given `name`, so that remote peer can reply with all
+/// changes missing on our side.
+///
+/// This feature requires only the read capabilities from the database transaction.
+pub fn start_sync<'a, S, K>(store: &S, name: &K) -> Result<Message, Error>
+where
+    S: DynDocStore<'a> + ?Sized,
+    K: AsRef<[u8]> + ?Sized,
+{
+    let name = name.as_ref();
+    let sv = match store.get_state_vector(name)? {
+        (Some(sv), true) => sv,
+        // stored state vector doesn't include pending updates, it must be computed
+        _ => match store.sync_step(name, &StateVector::default())? {
+            Some((_, sv)) => sv,
+            None => StateVector::default(),
+        },
+    };
+    Ok(Message::Sync(SyncMessage::SyncStep1(sv)))
+}
+
+/// Handles a sync protocol message received from a remote peer for a document with given `name`:
+///
+/// - [SyncMessage::SyncStep1] is answered with [SyncMessage::SyncStep2] containing all changes
+///   missing on the remote side.
+/// - Updates carried by [SyncMessage::SyncStep2] and [SyncMessage::Update] are persisted using
+///   [crate::DocOps::push_update]. No reply is returned for them.
+///
+/// This feature requires a write capabilities from the database transaction.
+pub fn handle_sync<'a, S, K>(
+    store: &S,
+    name: &K,
+    msg: SyncMessage,
+) -> Result<Option<SyncMessage>, Error>
+where
+    S: DynDocStore<'a> + ?Sized,
+    K: AsRef<[u8]> + ?Sized,
+{
+    let name = name.as_ref();
+    match msg {
+        SyncMessage::SyncStep1(sv) => {
+            let diff = match store.get_diff(name, &sv)? {
+                Some(diff) => diff,
+                None => Update::EMPTY_V1.to_vec(),
+            };
+            Ok(Some(SyncMessage::SyncStep2(diff)))
+        }
+        SyncMessage::SyncStep2(update) | SyncMessage::Update(update) => {
+            if update != Update::EMPTY_V1 {
+                store.push_update(name, &update)?;
+            }
+            Ok(None)
+        }
+    }
+}
+
+/// Handles a y-sync protocol message received from a remote peer for a document with given
+/// `name`. Sync messages are handled by [handle_sync]. Awareness updates are stored as the last
+/// known awareness state of a document (see: [crate::DocOps::insert_awareness]), which is sent back in
+/// reply to awareness queries. Other messages are ignored.
+///
+/// This feature requires a write capabilities from the database transaction.
+pub fn handle_message<'a, S, K>(store: &S, name: &K, msg: Message) -> Result<Option<Message>, Error>
+where
+    S: DynDocStore<'a> + ?Sized,
+    K: AsRef<[u8]> + ?Sized,
+{
+    let name = name.as_ref();
+    match msg {
+        Message::Sync(msg) => Ok(handle_sync(store, name, msg)?.map(Message::Sync)),
+        Message::Awareness(update) => {
+            store.insert_awareness(name, &update.encode_v1())?;
+            Ok(None)
+        }
+        Message::AwarenessQuery => match store.get_awareness(name)? {
+            Some(awareness) => {
+                let update = AwarenessUpdate::decode_v1(&awareness)?;
+                Ok(Some(Message::Awareness(update)))
+            }
+            None => Ok(None),
+        },
+        Message::Auth(_) | Message::Custom(_, _) => Ok(None),
+    }
+}
+
+/// Decodes all y-sync protocol messages (lib0 v1 encoding) from a given `data` frame received
+/// from a remote peer and handles them using [handle_message]. Returns encoded replies, which
+/// should be sent back to the remote peer in order.
+///
+/// This feature requires a write capabilities from the database transaction.
+pub fn handle_messages<'a, S, K>(store: &S, name: &K, data: &[u8]) -> Result<Vec<Vec<u8>>, Error>
+where
+    S: DynDocStore<'a> + ?Sized,
+    K: AsRef<[u8]> + ?Sized,
+{
+    let mut decoder = DecoderV1::from(data);
+    let mut replies = Vec::new();
+    for msg in MessageReader::new(&mut decoder) {
+        if let Some(reply) = handle_message(store, name, msg?)? {
+            replies.push(reply.encode_v1());
+        }
+    }
+    Ok(replies)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::memory::MemKVStore;
+    use crate::sync::{handle_messages, start_sync};
+    use crate::DocOps;
+    use yrs::sync::{Message, SyncMessage};
+    use yrs::updates::decoder::Decode;
+    use yrs::updates::encoder::Encode;
+    use yrs::{Doc, GetString, ReadTxn, StateVector, Text, Transact, Update};
+
+    #[test]
+    fn sync_with_peer() {
+        let db = MemKVStore::new();
+        let server = Doc::new();
+        let text = server.get_or_insert_text("text");
+        text.push(&mut server.transact_mut(), "hello");
+        db.insert_doc("doc", &server.transact()).unwrap();
+
+        let client = Doc::new();
+        let client_text = client.get_or_insert_text("text");
+        client_text.push(&mut client.transact_mut(), "world");
+
+        // server initiates the sync
+        let msg = start_sync(&db, "doc").unwrap();
+        let remote_sv = match msg {
+            Message::Sync(SyncMessage::SyncStep1(sv)) => sv,
+            other => panic!("unexpected message: {:?}", other),
+        };
+        assert_eq!(remote_sv, server.transact().state_vector());
+        let step2 = client.transact().encode_diff_v1(&remote_sv);
+
+        // client sends its own sync step 1 together with changes missing on the server side
+        let mut frame =
+            Message::Sync(SyncMessage::SyncStep1(client.transact().state_vector())).encode_v1();
+        frame.extend(Message::Sync(SyncMessage::SyncStep2(step2)).encode_v1());
+        let replies = handle_messages(&db, "doc", &frame).unwrap();
+        assert_eq!(replies.len(), 1);
+        match Message::decode_v1(&replies[0]).unwrap() {
+            Message::Sync(SyncMessage::SyncStep2(diff)) => {
+                let mut txn = client.transact_mut();
+                txn.apply_update(Update::decode_v1(&diff).unwrap()).unwrap();
+            }
+            other => panic!("unexpected message: {:?}", other),
+        }
+
+        // incremental update
+        let sv = client.transact().state_vector();
+        client_text.push(&mut client.transact_mut(), "!");
+        let update = client.transact().encode_diff_v1(&sv);
+        let frame = Message::Sync(SyncMessage::Update(update)).encode_v1();
+        assert!(handle_messages(&db, "doc", &frame).unwrap().is_empty());
+
+        let stored = Doc::new();
+        let stored_text = stored.get_or_insert_text("text");
+        db.load_doc("doc", &mut stored.transact_mut()).unwrap();
+        let expected = client_text.get_string(&client.transact());
+        assert_eq!(stored_text.get_string(&stored.transact()), expected);
+
+        // after sync there's nothing missing on either side
+        let msg = start_sync(&db, "doc").unwrap();
+        assert_eq!(
+            msg,
+            Message::Sync(SyncMessage::SyncStep1(client.transact().state_vector()))
+        );
+        let frame = Message::Sync(SyncMessage::SyncStep1(StateVector::default())).encode_v1();
+        assert!(!handle_messages(&db, "missing", &frame).unwrap().is_empty());
+        assert!(!db.doc_exists("missing").unwrap());
+    }
+}
@@ -0,0 +1,304 @@
+//! A reusable battery of tests, which can be used by [KVStore] implementors to validate if their
+//! implementation follows the trait contract. All of the functions in this module panic once
+//! a contract violation has been detected, so they can be called directly from within `#[test]`
+//! functions, i.e.:
+//!
+//! ```rust
+//! use yrs_kvstore::memory::MemKVStore;
+//! use yrs_kvstore::testing::verify_kvstore_contract;
+//!
+//! let store = MemKVStore::new();
+//! verify_kvstore_contract(&store);
+//! ```
+//!
+//! All functions expect to be called over an empty store and they clean up the entries they've
+//! created before returning.
+//!
+//! Property-based checks of the key layout and range boundaries can be found in the `properties`
+//! module, which requires `proptest` cargo feature.
+
+#[cfg(any(test, feature = "proptest"))]
+pub mod properties;
+
+use crate::error::Error;
+use crate::keys::{KEYSPACE_DOC, KEY_OID_COUNTER, TERMINATOR_HI_WATERMARK, V1};
+use crate::{DocOps, KVEntry, KVStore, OidAllocation};
+use yrs::updates::encoder::Encode;
+use yrs::{Doc, GetString, ReadTxn, StateVector, Text, Transact};
+
+/// Prefix of all keys created by raw key-value tests. It's placed after all keyspaces used by
+/// [DocOps].
+const PREFIX: u8 = 0xFE;
+
+fn key(k: u8) -> [u8; 2] {
+    [PREFIX, k]
+}
+
+fn collect_keys<'a, S: KVStore<'a>>(store: &S, from: &[u8], to: &[u8]) -> Vec<Vec<u8>> {
+    store
+        .iter_range(from, to)
+        .unwrap()
+        .map(|e| e.key().to_vec())
+        .collect()
+}
+
+fn fill<'a, S: KVStore<'a>>(store: &S) {
+    // key collection of {1,2,5,7}
+    for k in [1u8, 2, 5, 7] {
+        store.upsert(&key(k), &[k]).unwrap();
+    }
+}
+
+fn cleanup<'a, S: KVStore<'a>>(store: &S) {
+    store.remove_range(&key(0), &key(u8::MAX)).unwrap();
+    assert!(
+        collect_keys(store, &key(0), &key(u8::MAX)).is_empty(),
+        "remove_range didn't remove all keys"
+    );
+}
+
+/// Runs all contract checks defined in this module.
+pub fn verify_kvstore_contract<'a, S>(store: &S)
+where
+    S: DocOps<'a>,
+    Error: From<<S as KVStore<'a>>::Error>,
+{
+    verify_get_upsert_remove(store);
+    verify_iter_range(store);
+    verify_contains_range(store);
+    verify_remove_range(store);
+    verify_peek_back(store);
+    verify_doc_ops(store);
+}
+
+/// Verifies basic single key operations: [KVStore::get], [KVStore::upsert] and [KVStore::remove].
+pub fn verify_get_upsert_remove<'a, S: KVStore<'a>>(store: &S) {
+    let k = key(1);
+    assert!(
+        store.get(&k).unwrap().is_none(),
+        "get: found non-existing key"
+    );
+    store.upsert(&k, b"a").unwrap();
+    let value = store.get(&k).unwrap().map(|v| v.as_ref().to_vec());
+    assert_eq!(value.as_deref(), Some(b"a".as_ref()), "get after insert");
+    store.upsert(&k, b"b").unwrap();
+    let value = store.get(&k).unwrap().map(|v| v.as_ref().to_vec());
+    assert_eq!(value.as_deref(), Some(b"b".as_ref()), "get after update");
+    store.remove(&k).unwrap();
+    assert!(store.get(&k).unwrap().is_none(), "get after remove");
+    // removing non-existing key is not an error
+    store.remove(&k).unwrap();
+}
+
+/// Verifies that [KVStore::iter_range] returns ordered entries within `from..=to` range.
+pub fn verify_iter_range<'a, S: KVStore<'a>>(store: &S) {
+    fill(store);
+
+    let keys = collect_keys(store, &key(2), &key(7));
+    assert_eq!(
+        keys,
+        vec![key(2).to_vec(), key(5).to_vec(), key(7).to_vec()],
+        "iter_range: both bounds must be inclusive"
+    );
+    let keys = collect_keys(store, &key(0), &key(1));
+    assert_eq!(keys, vec![key(1).to_vec()], "iter_range: upper bound");
+    let keys = collect_keys(store, &key(3), &key(4));
+    assert!(keys.is_empty(), "iter_range: empty range");
+    let keys = collect_keys(store, &key(8), &key(u8::MAX));
+    assert!(keys.is_empty(), "iter_range: range past the last key");
+
+    let values: Vec<_> = store
+        .iter_range(&key(0), &key(u8::MAX))
+        .unwrap()
+        .map(|e| e.value().to_vec())
+        .collect();
+    assert_eq!(
+        values,
+        vec![vec![1], vec![2], vec![5], vec![7]],
+        "iter_range: values"
+    );
+
+    cleanup(store);
+}
+
+/// Verifies that [KVStore::contains_range] detects entries within `from..=to` range.
+pub fn verify_contains_range<'a, S: KVStore<'a>>(store: &S) {
+    let contains = |from: &[u8], to: &[u8]| store.contains_range(from, to).unwrap();
+    assert!(
+        !contains(&key(0), &key(u8::MAX)),
+        "contains_range: empty store"
+    );
+
+    fill(store);
+
+    assert!(contains(&key(0), &key(1)), "contains_range: upper bound");
+    assert!(
+        contains(&key(7), &key(u8::MAX)),
+        "contains_range: lower bound"
+    );
+    assert!(contains(&key(3), &key(5)), "contains_range: inner range");
+    assert!(!contains(&key(3), &key(4)), "contains_range: empty range");
+    assert!(
+        !contains(&key(8), &key(u8::MAX)),
+        "contains_range: range past the last key"
+    );
+
+    cleanup(store);
+}
+
+/// Verifies that [KVStore::remove_range] removes all entries within `from..=to` range.
+pub fn verify_remove_range<'a, S: KVStore<'a>>(store: &S) {
+    fill(store);
+
+    store.remove_range(&key(3), &key(4)).unwrap();
+    let keys = collect_keys(store, &key(0), &key(u8::MAX));
+    assert_eq!(keys.len(), 4, "remove_range: empty range removed entries");
+
+    store.remove_range(&key(2), &key(5)).unwrap();
+    let keys = collect_keys(store, &key(0), &key(u8::MAX));
+    assert_eq!(
+        keys,
+        vec![key(1).to_vec(), key(7).to_vec()],
+        "remove_range: both bounds must be inclusive"
+    );
+
+    cleanup(store);
+}
+
+/// Verifies that [KVStore::peek_back] returns the last entry strictly prior to a given key.
+pub fn verify_peek_back<'a, S: KVStore<'a>>(store: &S) {
+    fill(store);
+
+    let peek = |k: &[u8]| store.peek_back(k).unwrap().map(|e| e.key().to_vec());
+    assert_eq!(
+        peek(&key(4)),
+        Some(key(2).to_vec()),
+        "peek_back: missing key"
+    );
+    assert_eq!(
+        peek(&key(5)),
+        Some(key(2).to_vec()),
+        "peek_back: existing key must be excluded"
+    );
+    assert_eq!(
+        peek(&key(8)),
+        Some(key(7).to_vec()),
+        "peek_back: past last key"
+    );
+    assert_eq!(
+        peek(&[PREFIX, u8::MAX, u8::MAX]),
+        Some(key(7).to_vec()),
+        "peek_back: longer key"
+    );
+    assert_eq!(peek(&key(1)), None, "peek_back: before first key");
+
+    cleanup(store);
+}
+
+/// Verifies [DocOps] round-trips over the store: inserting and loading documents, pushing and
+/// flushing updates, metadata operations and document removal.
+pub fn verify_doc_ops<'a, S>(store: &S)
+where
+    S: DocOps<'a>,
+    Error: From<<S as KVStore<'a>>::Error>,
+{
+    const A: &str = "test-doc-a";
+    const B: &str = "test-doc-b";
+
+    let doc = Doc::new();
+    let text = doc.get_or_insert_text("text");
+    text.push(&mut doc.transact_mut(), "hello");
+    store.insert_doc(A, &doc.transact()).unwrap();
+
+    // push updates
+    let sv = doc.transact().state_vector();
+    text.push(&mut doc.transact_mut(), " world");
+    let update = doc.transact().encode_diff_v1(&sv);
+    assert_eq!(store.push_update(A, &update).unwrap(), 1);
+    let full = doc.transact().encode_diff_v1(&StateVector::default());
+    store.push_update(B, &full).unwrap();
+
+    let (sv, up_to_date) = store.get_state_vector(A).unwrap();
+    assert!(sv.is_some(), "get_state_vector: missing state vector");
+    assert!(
+        !up_to_date,
+        "get_state_vector: pending updates not detected"
+    );
+
+    for name in [A, B] {
+        let loaded = Doc::new();
+        let text = loaded.get_or_insert_text("text");
+        let mut txn = loaded.transact_mut();
+        assert!(
+            store.load_doc(name, &mut txn).unwrap(),
+            "load_doc: not found"
+        );
+        assert_eq!(text.get_string(&txn), "hello world", "load_doc: content");
+    }
+
+    // flush updates
+    assert!(
+        store.flush_doc(A).unwrap().is_some(),
+        "flush_doc: not flushed"
+    );
+    let (sv, up_to_date) = store.get_state_vector(A).unwrap();
+    assert_eq!(
+        sv.map(|sv| sv.encode_v1()),
+        Some(doc.transact().state_vector().encode_v1()),
+        "get_state_vector: state vector after flush"
+    );
+    assert!(up_to_date, "get_state_vector: pending updates after flush");
+
+    // metadata
+    store.insert_meta(A, "m1", b"1").unwrap();
+    store.insert_meta(A, "m2", b"2").unwrap();
+    store.insert_meta(B, "m3", b"3").unwrap();
+    let meta: Vec<_> = store.iter_meta(A).unwrap().collect();
+    assert_eq!(
+        meta,
+        vec![
+            (b"m1".to_vec().into(), b"1".to_vec().into()),
+            (b"m2".to_vec().into(), b"2".to_vec().into()),
+        ],
+        "iter_meta"
+    );
+    store.remove_meta(A, "m1").unwrap();
+    assert!(store.get_meta(A, "m1").unwrap().is_none(), "remove_meta");
+
+    let docs: Vec<_> = store.iter_docs().unwrap().collect();
+    assert_eq!(
+        docs,
+        vec![A.as_bytes().into(), B.as_bytes().into()],
+        "iter_docs"
+    );
+    assert!(store.doc_exists(A).unwrap(), "doc_exists");
+    assert!(
+        !store.doc_exists("C").unwrap(),
+        "doc_exists: non-existing document"
+    );
+    assert_eq!(store.doc_count().unwrap(), 2, "doc_count");
+
+    // clear documents
+    store.clear_doc(A).unwrap();
+    store.clear_doc(B).unwrap();
+    assert!(
+        store.iter_docs().unwrap().next().is_none(),
+        "clear_doc: document names left"
+    );
+    assert_eq!(store.doc_count().unwrap(), 0, "clear_doc: documents left");
+    let doc_keys = collect_keys(
+        store,
+        &[V1, KEYSPACE_DOC],
+        &[V1, KEYSPACE_DOC, TERMINATOR_HI_WATERMARK],
+    );
+    assert!(doc_keys.is_empty(), "clear_doc: document entries left");
+
+    // OID counter outlives cleared documents, so that their OIDs are not reused
+    if store.options().oid_allocation == OidAllocation::Counter {
+        assert!(
+            store.get(&KEY_OID_COUNTER).unwrap().is_some(),
+            "OID counter missing"
+        );
+        store.remove(&KEY_OID_COUNTER).unwrap();
+    }
+}
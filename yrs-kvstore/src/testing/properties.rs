@@ -0,0 +1,361 @@
+//! Property-based checks of the key layout used by [DocOps]. Keys are built and parsed using
+//! manual offset arithmetic, so these checks generate random document names, metadata keys, OIDs
+//! and clocks in order to verify that keys round-trip, sort in the same order as the values they
+//! encode and always fall within the range boundaries used to iterate over them.
+//!
+//! [verify_key_layout] checks the key functions alone, while [verify_key_ranges] checks that
+//! a [KVStore] implementation iterates over these ranges correctly:
+//!
+//! ```rust
+//! use yrs_kvstore::memory::MemKVStore;
+//! use yrs_kvstore::testing::properties::{verify_key_layout, verify_key_ranges};
+//!
+//! verify_key_layout();
+//! verify_key_ranges(&MemKVStore::new());
+//! ```
+//!
+//! Generated names never contain a [TERMINATOR] byte, since it's used to mark the end of
+//! variable length key segments.
+//!
+//! This module requires `proptest` cargo feature.
+
+use crate::error::Error;
+use crate::keys::{
+    changelog_seq, doc_meta_name, doc_oid_name, doc_snapshot_id, expiry_index_entry, history_seq,
+    key_changelog, key_doc_end, key_doc_start, key_expiry_index, key_history, key_meta,
+    key_meta_end, key_meta_prefix_end, key_meta_prefix_start, key_meta_start, key_oid, key_posting,
+    key_posting_end, key_posting_start, key_snapshot, key_snapshot_end, key_snapshot_start,
+    key_state_vector, key_trash, key_update, key_update_origin, key_update_time, key_update_v2,
+    oid_from_bytes, posting_oid, trashed_doc_name, update_clock, update_encoding,
+    update_origin_clock, update_time_clock, ENCODING_V1, ENCODING_V2, KEYSPACE_DOC, KEYSPACE_OID,
+    KEY_OID_COUNTER, OID, SUB_KEY_POS, TERMINATOR, V1,
+};
+use crate::{DocOps, KVEntry, KVStore};
+use proptest::collection::{btree_set, vec};
+use proptest::prelude::*;
+use proptest::test_runner::{Config, TestCaseError, TestRunner};
+use std::collections::BTreeSet;
+
+/// Number of cases generated by every check.
+const CASES: u32 = 256;
+
+/// Number of cases generated by checks running over a store, which are more expensive.
+const STORE_CASES: u32 = 32;
+
+/// Generates document names: non-empty byte strings without a [TERMINATOR] byte.
+pub fn doc_name() -> impl Strategy<Value = Vec<u8>> {
+    vec(TERMINATOR + 1..=u8::MAX, 1..24)
+}
+
+/// Generates metadata keys (also used as snapshot ids and search terms): possibly empty byte
+/// strings without a [TERMINATOR] byte.
+pub fn meta_key() -> impl Strategy<Value = Vec<u8>> {
+    vec(TERMINATOR + 1..=u8::MAX, 0..16)
+}
+
+/// Generates document OIDs.
+pub fn oid() -> impl Strategy<Value = OID> {
+    any::<OID>()
+}
+
+/// Generates update clocks.
+pub fn clock() -> impl Strategy<Value = u32> {
+    any::<u32>()
+}
+
+fn run<S, F>(name: &str, cases: u32, strategy: S, test: F)
+where
+    S: Strategy,
+    F: Fn(S::Value) -> Result<(), TestCaseError>,
+{
+    let mut runner = TestRunner::new(Config::with_cases(cases));
+    if let Err(e) = runner.run(&strategy, test) {
+        panic!("{}: {}", name, e);
+    }
+}
+
+fn within(key: &[u8], from: &[u8], to: &[u8]) -> bool {
+    from <= key && key <= to
+}
+
+/// Runs all key layout checks defined in this module, which don't require a store.
+pub fn verify_key_layout() {
+    verify_doc_name_keys();
+    verify_meta_keys();
+    verify_update_keys();
+    verify_index_keys();
+}
+
+/// Verifies that document names round-trip through their OID and trash keys and that OID keys
+/// are ordered by document names.
+pub fn verify_doc_name_keys() {
+    run(
+        "doc name keys",
+        CASES,
+        (doc_name(), doc_name()),
+        |(a, b)| {
+            let key_a = key_oid(&a);
+            let key_b = key_oid(&b);
+            prop_assert_eq!(doc_oid_name(&key_a), &a[..]);
+            let key_trashed = key_trash(&a);
+            prop_assert_eq!(trashed_doc_name(&key_trashed), &a[..]);
+            prop_assert_eq!(a.cmp(&b), key_a.cmp(&key_b), "OID keys mis-sorted");
+            prop_assert!(
+                within(&key_a, &[V1, KEYSPACE_OID], &[V1, KEYSPACE_DOC]),
+                "OID key outside of its keyspace"
+            );
+            Ok(())
+        },
+    );
+}
+
+/// Verifies that metadata keys and snapshot ids round-trip, are ordered by their names and fall
+/// within ranges used to iterate over all of them or over the ones sharing a common prefix.
+pub fn verify_meta_keys() {
+    run(
+        "meta keys",
+        CASES,
+        (oid(), oid(), meta_key(), meta_key(), 0..4usize),
+        |(oid, other_oid, a, b, prefix_len)| {
+            let key_a = key_meta(oid, &a);
+            let key_b = key_meta(oid, &b);
+            prop_assert_eq!(doc_meta_name(&key_a), &a[..]);
+            prop_assert_eq!(a.cmp(&b), key_a.cmp(&key_b), "meta keys mis-sorted");
+            prop_assert!(within(&key_a, &key_meta_start(oid), &key_meta_end(oid)));
+            prop_assert!(within(&key_a, &key_doc_start(oid), &key_doc_end(oid)));
+            if other_oid != oid {
+                let other = key_meta(other_oid, &a);
+                prop_assert!(!within(&other, &key_meta_start(oid), &key_meta_end(oid)));
+                prop_assert!(!within(&other, &key_doc_start(oid), &key_doc_end(oid)));
+            }
+            // updates and snapshots are placed right before and after metadata
+            let update = key_update_v2(oid, u32::MAX);
+            prop_assert!(!within(&update, &key_meta_start(oid), &key_meta_end(oid)));
+            let snapshot = key_snapshot(oid, &a);
+            prop_assert!(!within(&snapshot, &key_meta_start(oid), &key_meta_end(oid)));
+            prop_assert_eq!(doc_snapshot_id(&snapshot), &a[..]);
+            prop_assert!(within(
+                &snapshot,
+                &key_snapshot_start(oid),
+                &key_snapshot_end(oid)
+            ));
+
+            for prefix in [&a[..prefix_len.min(a.len())], &b[..prefix_len.min(b.len())]] {
+                let start = key_meta_prefix_start(oid, prefix);
+                let end = key_meta_prefix_end(oid, prefix);
+                prop_assert_eq!(
+                    a.starts_with(prefix),
+                    within(&key_a, &start, &end),
+                    "prefix range mismatch for {:?}",
+                    prefix
+                );
+            }
+            Ok(())
+        },
+    );
+}
+
+/// Verifies that clocks round-trip through update, history, update timestamp and update origin
+/// keys, that update keys are ordered by their clocks and that all of them fall within the range
+/// of their document.
+pub fn verify_update_keys() {
+    run(
+        "update keys",
+        CASES,
+        (oid(), oid(), clock(), clock()),
+        |(oid, other_oid, a, b)| {
+            let v1 = key_update(oid, a);
+            let v2 = key_update_v2(oid, a);
+            prop_assert_eq!(update_clock(&v1), a);
+            prop_assert_eq!(update_clock(&v2), a);
+            prop_assert_eq!(update_encoding(&v1), ENCODING_V1);
+            prop_assert_eq!(update_encoding(&v2), ENCODING_V2);
+            prop_assert_eq!(oid_from_bytes(&v1[2..SUB_KEY_POS]), oid);
+            prop_assert_eq!(history_seq(&key_history(oid, a)), a);
+            prop_assert_eq!(update_time_clock(&key_update_time(oid, a)), a);
+            prop_assert_eq!(update_origin_clock(&key_update_origin(oid, a)), a);
+
+            prop_assert_eq!(a.cmp(&b), v1.cmp(&key_update(oid, b)), "updates mis-sorted");
+            if a != b {
+                prop_assert_eq!(a.cmp(&b), v2.cmp(&key_update(oid, b)), "updates mis-sorted");
+            }
+            prop_assert!(v1 < v2);
+
+            let start = key_doc_start(oid);
+            let end = key_doc_end(oid);
+            for key in [&v1[..], &v2[..], &key_state_vector(oid)[..]] {
+                prop_assert!(within(key, &start, &end), "key outside of its document");
+            }
+            // update keys are placed between the state vector and metadata keys
+            prop_assert!(key_state_vector(oid)[..] < v1[..]);
+            prop_assert!(v2[..] < key_meta_start(oid)[..]);
+            if other_oid != oid {
+                let other = key_update(other_oid, a);
+                prop_assert!(
+                    !within(&other, &start, &end),
+                    "key of other document in range"
+                );
+            }
+            Ok(())
+        },
+    );
+}
+
+/// Verifies that expiry index, change log and search index keys round-trip and that search index
+/// postings fall only within the range of their own term.
+pub fn verify_index_keys() {
+    run(
+        "index keys",
+        CASES,
+        (oid(), any::<u64>(), meta_key(), meta_key()),
+        |(oid, n, term, other_term)| {
+            prop_assert_eq!(expiry_index_entry(&key_expiry_index(n, oid)), (n, oid));
+            prop_assert_eq!(changelog_seq(&key_changelog(n)), n);
+            let posting = key_posting(&term, oid);
+            prop_assert_eq!(posting_oid(&posting), oid);
+            prop_assert!(within(
+                &posting,
+                &key_posting_start(&term),
+                &key_posting_end(&term)
+            ));
+            prop_assert_eq!(
+                term == other_term,
+                within(
+                    &posting,
+                    &key_posting_start(&other_term),
+                    &key_posting_end(&other_term)
+                ),
+                "posting of {:?} in range of {:?}",
+                term,
+                other_term
+            );
+            Ok(())
+        },
+    );
+}
+
+/// Runs all checks defined in this module, which require a store. They expect to be called over
+/// an empty store and they clean up the entries they've created before returning.
+pub fn verify_key_ranges<'a, S>(store: &S)
+where
+    S: DocOps<'a>,
+    Error: From<<S as KVStore<'a>>::Error>,
+{
+    verify_doc_ranges(store);
+    verify_update_ranges(store);
+}
+
+/// Removes all entries of the OID and document keyspaces.
+fn clear_docs<'a, S: KVStore<'a>>(store: &S) -> Result<(), S::Error> {
+    store.remove_range(&[V1, KEYSPACE_OID], &[V1, KEYSPACE_DOC + 1])
+}
+
+/// Verifies that documents with random names and metadata keys are iterated in order of their
+/// names, that metadata prefix ranges return exactly the matching entries and that purging
+/// documents leaves no entries behind.
+pub fn verify_doc_ranges<'a, S>(store: &S)
+where
+    S: DocOps<'a>,
+    Error: From<<S as KVStore<'a>>::Error>,
+{
+    let strategy = (
+        btree_set(doc_name(), 1..6),
+        btree_set(meta_key(), 1..8),
+        0..3usize,
+    );
+    run(
+        "doc ranges",
+        STORE_CASES,
+        strategy,
+        |(names, keys, prefix_len): (BTreeSet<Vec<u8>>, BTreeSet<Vec<u8>>, usize)| {
+            // previous failed case may have left its entries behind
+            clear_docs(store).unwrap();
+            for name in names.iter() {
+                for key in keys.iter() {
+                    store.insert_meta(name, key, name).unwrap();
+                }
+            }
+
+            let docs: Vec<_> = store.iter_docs().unwrap().map(|n| n.to_vec()).collect();
+            let expected: Vec<_> = names.iter().cloned().collect();
+            prop_assert_eq!(docs, expected, "iter_docs");
+
+            for name in names.iter() {
+                let meta: Vec<_> = store
+                    .iter_meta(name)
+                    .unwrap()
+                    .map(|(k, v)| (k.to_vec(), v.to_vec()))
+                    .collect();
+                let expected: Vec<_> = keys.iter().map(|k| (k.clone(), name.clone())).collect();
+                prop_assert_eq!(meta, expected, "iter_meta");
+
+                for key in keys.iter() {
+                    let prefix = &key[..prefix_len.min(key.len())];
+                    let meta: Vec<_> = store
+                        .iter_meta_prefix(name, prefix)
+                        .unwrap()
+                        .map(|(k, _)| k.to_vec())
+                        .collect();
+                    let expected: Vec<_> = keys
+                        .iter()
+                        .filter(|k| k.starts_with(prefix))
+                        .cloned()
+                        .collect();
+                    prop_assert_eq!(meta, expected, "iter_meta_prefix: {:?}", prefix);
+                }
+            }
+
+            for name in names.iter() {
+                // purge verifies that no entries of a document are left behind
+                store.purge_doc(name, true).unwrap();
+            }
+            prop_assert!(store.iter_docs().unwrap().next().is_none(), "purge_doc");
+            Ok(())
+        },
+    );
+    clear_docs(store).unwrap();
+    store.remove(&KEY_OID_COUNTER).unwrap();
+}
+
+/// Verifies that update entries with random clocks are iterated in order of their clocks and
+/// that the last of them can be found by peeking back from the start of document metadata.
+pub fn verify_update_ranges<'a, S: KVStore<'a>>(store: &S) {
+    // OID far away from the ones allocated by the counter
+    const UPDATES_OID: OID = OID::MAX;
+
+    run(
+        "update ranges",
+        STORE_CASES,
+        btree_set(clock(), 1..16),
+        |clocks: BTreeSet<u32>| {
+            clear_docs(store).unwrap();
+            store.upsert(&key_state_vector(UPDATES_OID), &[0]).unwrap();
+            store.upsert(&key_meta(UPDATES_OID, b"meta"), &[0]).unwrap();
+            for &clock in clocks.iter() {
+                store.upsert(&key_update(UPDATES_OID, clock), &[0]).unwrap();
+            }
+
+            let start = key_update(UPDATES_OID, 0);
+            let end = key_update_v2(UPDATES_OID, u32::MAX);
+            let actual: Vec<_> = store
+                .iter_range(&start, &end)
+                .unwrap()
+                .map(|e| update_clock(e.key()))
+                .collect();
+            let expected: Vec<_> = clocks.iter().cloned().collect();
+            prop_assert_eq!(actual, expected, "iter_range over updates");
+
+            let last = store
+                .peek_back(&key_meta_start(UPDATES_OID))
+                .unwrap()
+                .map(|e| e.key().to_vec());
+            let expected = clocks
+                .iter()
+                .last()
+                .map(|&c| key_update(UPDATES_OID, c).to_vec());
+            prop_assert_eq!(last, expected, "peek_back from metadata");
+            Ok(())
+        },
+    );
+    clear_docs(store).unwrap();
+}
@@ -0,0 +1,313 @@
+//! Tiered storage, which keeps recently used documents in a primary (hot) store and moves dormant
+//! ones into a cheaper, secondary (cold) store (see: [TieredStore]).
+
+use crate::dynamic::{DynDocStore, DynIter};
+use crate::error::Error;
+use crate::keys::key_access;
+use crate::{get_oid, unix_millis, DocOps, KVStore, PendingUpdate};
+use std::convert::TryInto;
+use std::time::Duration;
+use yrs::{Doc, StateVector, Transaction, TransactionMut};
+
+/// Composition of two stores: a `hot` one, which all document operations are executed against,
+/// and a `cold` one, used as an archive of documents which have not been accessed for
+/// a configured idle period.
+///
+/// Documents are moved to the cold store by [TieredStore::offload_idle], which is meant to be
+/// called periodically, i.e. by a background job. Every offloaded document is flushed first, so
+/// that its pending updates are compacted into a document state. Offloaded documents are moved
+/// back into the hot store, once they are accessed again through [DynDocStore] methods or
+/// [TieredStore::rehydrate].
+///
+/// A document is always written into a cold store before being removed from the hot one, so that
+/// an interrupted offload may leave a document in both stores, but never loses it. In such case
+/// the hot store copy takes precedence.
+pub struct TieredStore<H, C> {
+    hot: H,
+    cold: C,
+    idle_period: u64,
+    clock: fn() -> u64,
+}
+
+impl<H, C> TieredStore<H, C> {
+    /// Creates a new tiered store, which moves documents not accessed for longer than
+    /// `idle_period` from the `hot` store to the `cold` one.
+    pub fn new(hot: H, cold: C, idle_period: Duration) -> Self {
+        TieredStore {
+            hot,
+            cold,
+            idle_period: idle_period.as_millis() as u64,
+            clock: unix_millis,
+        }
+    }
+
+    /// Sets a clock used to timestamp document accesses. It should return a number of milliseconds
+    /// since Unix epoch. By default a system clock is used.
+    pub fn with_clock(mut self, clock: fn() -> u64) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// Returns a reference to the hot store.
+    pub fn hot(&self) -> &H {
+        &self.hot
+    }
+
+    /// Returns a reference to the cold store.
+    pub fn cold(&self) -> &C {
+        &self.cold
+    }
+}
+
+impl<'a, H, C> TieredStore<H, C>
+where
+    H: DocOps<'a>,
+    C: DocOps<'a>,
+    Error: From<<H as KVStore<'a>>::Error> + From<<C as KVStore<'a>>::Error>,
+{
+    /// Makes sure that a document with a given `name` is present in the hot store, moving it from
+    /// the cold store if necessary, and records its access time. Returns `false` if document was
+    /// found in neither of the stores.
+    pub fn rehydrate<K: AsRef<[u8]> + ?Sized>(&self, name: &K) -> Result<bool, Error> {
+        let name = name.as_ref();
+        if !DocOps::doc_exists(&self.hot, name)? {
+            match DocOps::export_doc(&self.cold, name)? {
+                Some(archive) => {
+                    DocOps::import_doc(&self.hot, name, &archive)?;
+                    DocOps::clear_doc(&self.cold, name)?;
+                }
+                None => return Ok(false),
+            }
+        }
+        self.touch(name, (self.clock)())?;
+        Ok(true)
+    }
+
+    /// Returns a timestamp of the last recorded access to a document with given `name`, if it's
+    /// present in the hot store.
+    pub fn last_access<K: AsRef<[u8]> + ?Sized>(&self, name: &K) -> Result<Option<u64>, Error> {
+        if let Some(oid) = get_oid(&self.hot, name.as_ref())? {
+            if let Some(value) = self.hot.get(&key_access(oid))? {
+                if let Ok(bytes) = value.as_ref().try_into() {
+                    return Ok(Some(u64::from_be_bytes(bytes)));
+                }
+            }
+        }
+        Ok(None)
+    }
+
+    /// Flushes a document with given `name` and moves it from the hot store into the cold one,
+    /// replacing its previous cold copy. Returns `false` if document was not found in the hot store.
+    pub fn offload<K: AsRef<[u8]> + ?Sized>(&self, name: &K) -> Result<bool, Error> {
+        let name = name.as_ref();
+        DocOps::flush_doc(&self.hot, name)?;
+        let archive = match DocOps::export_doc(&self.hot, name)? {
+            Some(archive) => archive,
+            None => return Ok(false),
+        };
+        if DocOps::doc_exists(&self.cold, name)? {
+            DocOps::clear_doc(&self.cold, name)?;
+        }
+        DocOps::import_doc(&self.cold, name, &archive)?;
+        DocOps::clear_doc(&self.hot, name)?;
+        Ok(true)
+    }
+
+    /// Moves all documents, which have not been accessed for longer than configured idle period,
+    /// from the hot store into the cold one (see: [Self::offload]). Documents with no recorded
+    /// access time (i.e. written directly into the hot store) have it set to the current time.
+    /// Returns a number of offloaded documents.
+    pub fn offload_idle(&self) -> Result<usize, Error> {
+        let now = (self.clock)();
+        let names: Vec<_> = DocOps::iter_docs(&self.hot)?.collect();
+        let mut offloaded = 0;
+        for name in names {
+            match self.last_access(&name)? {
+                Some(last) if now.saturating_sub(last) >= self.idle_period => {
+                    if self.offload(&name)? {
+                        offloaded += 1;
+                    }
+                }
+                Some(_) => { /* document is still in use */ }
+                None => self.touch(&name, now)?,
+            }
+        }
+        Ok(offloaded)
+    }
+
+    fn touch(&self, name: &[u8], now: u64) -> Result<(), Error> {
+        if let Some(oid) = get_oid(&self.hot, name)? {
+            self.hot.upsert(&key_access(oid), &now.to_be_bytes())?;
+        }
+        Ok(())
+    }
+}
+
+impl<'a, H, C> DynDocStore<'a> for TieredStore<H, C>
+where
+    H: DocOps<'a>,
+    C: DocOps<'a>,
+    <H as KVStore<'a>>::Cursor: 'a,
+    <H as KVStore<'a>>::Entry: 'a,
+    <C as KVStore<'a>>::Cursor: 'a,
+    <C as KVStore<'a>>::Entry: 'a,
+    Error: From<<H as KVStore<'a>>::Error> + From<<C as KVStore<'a>>::Error>,
+{
+    fn insert_doc(&self, name: &[u8], txn: &Transaction) -> Result<(), Error> {
+        self.rehydrate(name)?;
+        DocOps::insert_doc(&self.hot, name, txn)
+    }
+
+    fn load_doc(&self, name: &[u8], txn: &mut TransactionMut) -> Result<bool, Error> {
+        self.rehydrate(name)?;
+        DocOps::load_doc(&self.hot, name, txn)
+    }
+
+    fn flush_doc(&self, name: &[u8]) -> Result<Option<Doc>, Error> {
+        self.rehydrate(name)?;
+        DocOps::flush_doc(&self.hot, name)
+    }
+
+    fn get_state_vector(&self, name: &[u8]) -> Result<(Option<StateVector>, bool), Error> {
+        self.rehydrate(name)?;
+        DocOps::get_state_vector(&self.hot, name)
+    }
+
+    fn get_diff(&self, name: &[u8], sv: &StateVector) -> Result<Option<Vec<u8>>, Error> {
+        self.rehydrate(name)?;
+        DocOps::get_diff(&self.hot, name, sv)
+    }
+
+    fn sync_step(
+        &self,
+        name: &[u8],
+        remote_sv: &StateVector,
+    ) -> Result<Option<(Vec<u8>, StateVector)>, Error> {
+        self.rehydrate(name)?;
+        DocOps::sync_step(&self.hot, name, remote_sv)
+    }
+
+    fn push_update(&self, name: &[u8], update: &[u8]) -> Result<u32, Error> {
+        self.rehydrate(name)?;
+        DocOps::push_update(&self.hot, name, update)
+    }
+
+    fn iter_updates(&self, name: &[u8]) -> Result<DynIter<'a, PendingUpdate>, Error> {
+        self.rehydrate(name)?;
+        DynDocStore::iter_updates(&self.hot, name)
+    }
+
+    fn clear_doc(&self, name: &[u8]) -> Result<(), Error> {
+        DocOps::clear_doc(&self.hot, name)?;
+        DocOps::clear_doc(&self.cold, name)
+    }
+
+    fn doc_exists(&self, name: &[u8]) -> Result<bool, Error> {
+        Ok(DocOps::doc_exists(&self.hot, name)? || DocOps::doc_exists(&self.cold, name)?)
+    }
+
+    fn iter_docs(&self) -> Result<DynIter<'a, Box<[u8]>>, Error> {
+        let mut names: Vec<_> = DocOps::iter_docs(&self.hot)?.collect();
+        names.extend(DocOps::iter_docs(&self.cold)?);
+        // after interrupted offload, a document may be present in both stores
+        names.sort();
+        names.dedup();
+        Ok(Box::new(names.into_iter()))
+    }
+
+    fn get_meta(&self, name: &[u8], meta_key: &[u8]) -> Result<Option<Vec<u8>>, Error> {
+        self.rehydrate(name)?;
+        DocOps::get_meta(&self.hot, name, meta_key)
+    }
+
+    fn insert_meta(&self, name: &[u8], meta_key: &[u8], meta: &[u8]) -> Result<(), Error> {
+        self.rehydrate(name)?;
+        DocOps::insert_meta(&self.hot, name, meta_key, meta)
+    }
+
+    fn remove_meta(&self, name: &[u8], meta_key: &[u8]) -> Result<(), Error> {
+        self.rehydrate(name)?;
+        DocOps::remove_meta(&self.hot, name, meta_key)
+    }
+
+    fn iter_meta(&self, name: &[u8]) -> Result<DynIter<'a, (Box<[u8]>, Box<[u8]>)>, Error> {
+        self.rehydrate(name)?;
+        DynDocStore::iter_meta(&self.hot, name)
+    }
+
+    fn get_awareness(&self, name: &[u8]) -> Result<Option<Vec<u8>>, Error> {
+        // awareness is ephemeral and it's not moved to the cold store
+        DynDocStore::get_awareness(&self.hot, name)
+    }
+
+    fn insert_awareness(&self, name: &[u8], awareness: &[u8]) -> Result<(), Error> {
+        self.rehydrate(name)?;
+        DocOps::insert_awareness(&self.hot, name, awareness)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::dynamic::DynDocStore;
+    use crate::memory::MemKVStore;
+    use crate::tiered::TieredStore;
+    use crate::DocOps;
+    use std::sync::atomic::{AtomicU64, Ordering};
+    use std::time::Duration;
+    use yrs::{Doc, GetString, ReadTxn, Text, Transact};
+
+    static NOW: AtomicU64 = AtomicU64::new(0);
+
+    fn now() -> u64 {
+        NOW.load(Ordering::SeqCst)
+    }
+
+    #[test]
+    fn offload_and_rehydrate() {
+        let store = TieredStore::new(
+            MemKVStore::new(),
+            MemKVStore::new(),
+            Duration::from_millis(1000),
+        )
+        .with_clock(now);
+        let doc = Doc::new();
+        let text = doc.get_or_insert_text("text");
+        text.push(&mut doc.transact_mut(), "hello");
+        store.insert_doc(b"a", &doc.transact()).unwrap();
+        store.insert_doc(b"b", &doc.transact()).unwrap();
+        let sv = doc.transact().state_vector();
+        text.push(&mut doc.transact_mut(), " world");
+        store
+            .push_update(b"a", &doc.transact().encode_diff_v1(&sv))
+            .unwrap();
+        store.insert_meta(b"a", b"key", b"value").unwrap();
+        // access time of documents is recorded on the first sweep
+        assert_eq!(store.offload_idle().unwrap(), 0);
+
+        NOW.store(600, Ordering::SeqCst);
+        store.get_state_vector(b"b").unwrap();
+        NOW.store(1200, Ordering::SeqCst);
+        assert_eq!(store.offload_idle().unwrap(), 1);
+        assert!(!DocOps::doc_exists(store.hot(), "a").unwrap());
+        assert!(DocOps::doc_exists(store.cold(), "a").unwrap());
+        // pending updates have been compacted before offload
+        assert_eq!(DocOps::iter_updates(store.cold(), "a").unwrap().count(), 0);
+        assert!(DocOps::doc_exists(store.hot(), "b").unwrap());
+        assert!(store.doc_exists(b"a").unwrap());
+        let docs: Vec<_> = store.iter_docs().unwrap().collect();
+        assert_eq!(docs, vec![b"a".as_ref().into(), b"b".as_ref().into()]);
+
+        // accessing offloaded document moves it back to the hot store
+        let loaded = Doc::new();
+        let loaded_text = loaded.get_or_insert_text("text");
+        assert!(store.load_doc(b"a", &mut loaded.transact_mut()).unwrap());
+        assert_eq!(loaded_text.get_string(&loaded.transact()), "hello world");
+        assert!(DocOps::doc_exists(store.hot(), "a").unwrap());
+        assert!(!DocOps::doc_exists(store.cold(), "a").unwrap());
+        assert_eq!(store.last_access("a").unwrap(), Some(1200));
+        assert_eq!(
+            store.get_meta(b"a", b"key").unwrap(),
+            Some(b"value".to_vec())
+        );
+    }
+}
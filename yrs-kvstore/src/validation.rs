@@ -0,0 +1,37 @@
+//! Validation of document updates before they are persisted. An [UpdateValidator] set on a store
+//! (see: [crate::options::StoreOptions::update_validator]) is called with every decoded update
+//! pushed via [crate::DocOps::push_update] and every document state written by
+//! [crate::DocOps::insert_doc], giving servers a single policy enforcement point at the storage
+//! boundary, i.e. to enforce schema constraints or reject deletions of protected content.
+//!
+//! Validation happens before anything is written, so vetoed updates are never persisted.
+
+use crate::error::{Error, StoreError};
+use yrs::Update;
+
+/// Policy deciding if a document update can be persisted.
+pub trait UpdateValidator: std::fmt::Debug + Send + Sync {
+    /// Inspects an `update` about to be persisted for a document with a given `name`. Returning an
+    /// error vetoes persistence: nothing is written and the error is returned to the caller.
+    /// Validators can use [crate::error::StoreError::UpdateRejected] to describe their reasons.
+    fn validate(&self, name: &[u8], update: &Update) -> Result<(), Error>;
+}
+
+/// Validator, which rejects updates deleting any content of a document. It's useful for
+/// append-only documents, i.e. audit logs.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RejectDeletions;
+
+impl UpdateValidator for RejectDeletions {
+    fn validate(&self, name: &[u8], update: &Update) -> Result<(), Error> {
+        if update.delete_set().is_empty() {
+            Ok(())
+        } else {
+            let reason = format!(
+                "document '{}' doesn't allow deletions",
+                String::from_utf8_lossy(name)
+            );
+            Err(StoreError::UpdateRejected(reason).into())
+        }
+    }
+}
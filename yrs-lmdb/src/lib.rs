@@ -1,9 +1,19 @@
-use lmdb_rs::core::{CursorIterator, MdbResult};
-use lmdb_rs::{CursorKeyRangeIter, Database, MdbError, ReadonlyTransaction};
+use lmdb_rs::core::{CursorIterator, DbCreate, MdbResult};
+use lmdb_rs::{CursorKeyRangeIter, Database, DbHandle, Environment, MdbError, ReadonlyTransaction};
 use std::ops::Deref;
+use std::sync::Arc;
+use yrs_kvstore::codec::{CodecChain, ValueCodec};
+use yrs_kvstore::compression::Compression;
 use yrs_kvstore::error::Error;
+use yrs_kvstore::export::JsonExport;
 use yrs_kvstore::keys::Key;
-use yrs_kvstore::{DocOps, KVEntry, KVStore};
+use yrs_kvstore::metrics::{Metrics, StoreMetrics};
+use yrs_kvstore::observer::{UpdateObservers, WriteHook};
+use yrs_kvstore::options::StoreOptions;
+use yrs_kvstore::validation::UpdateValidator;
+use yrs_kvstore::{
+    CompactionPolicy, DocOps, KVEntry, KVStore, OidAllocation, RetentionPolicy, UpdateLimits,
+};
 
 trait OptionalNotFound {
     type Return;
@@ -26,21 +36,189 @@ impl<T> OptionalNotFound for MdbResult<T> {
     }
 }
 
-#[repr(transparent)]
+/// [DocOps] implementation over a single LMDB database bound to a transaction. Store treats the
+/// whole key space of that database as its own, so when Yrs documents should coexist with other
+/// application data within the same LMDB environment, they should be kept in a dedicated named
+/// database (see: [LmdbStore::open_db]).
 #[derive(Debug)]
-pub struct LmdbStore<'db>(Database<'db>);
+pub struct LmdbStore<'db> {
+    db: Database<'db>,
+    options: StoreOptions,
+    update_observers: UpdateObservers,
+    metrics: Metrics,
+}
+
+impl<'db> LmdbStore<'db> {
+    /// Opens a handle to the LMDB database with a given `name` within an `env` environment,
+    /// creating that database if it doesn't exist yet. If `name` is `None`, the default (unnamed)
+    /// database of the environment is used instead.
+    ///
+    /// Named databases require the environment to be configured with a sufficient number of
+    /// maximum databases (see: [lmdb_rs::EnvBuilder::max_dbs]).
+    pub fn open_db(env: &Environment, name: Option<&str>) -> MdbResult<DbHandle> {
+        match name {
+            Some(name) => env.create_db(name, DbCreate),
+            None => env.get_default_db(DbCreate),
+        }
+    }
+
+    /// Sets [StoreOptions] used to configure current store, replacing all settings set so far.
+    ///
+    /// # Panics
+    ///
+    /// Panics if options can't be applied without wrapping the store with
+    /// [yrs_kvstore::options::ConfiguredStore] (see: [StoreOptions::assert_unscoped]).
+    pub fn with_options(mut self, options: StoreOptions) -> Self {
+        options.assert_unscoped();
+        self.options = options;
+        self
+    }
+
+    /// Sets a [CompactionPolicy] used to automatically flush documents, which have accumulated
+    /// too many pending updates.
+    pub fn with_compaction_policy(mut self, policy: CompactionPolicy) -> Self {
+        self.options.compaction_policy = policy;
+        self
+    }
+
+    /// Sets an [UpdateObservers] registry notified about every persisted document update (see:
+    /// [DocOps::observe_updates]). The same registry can be shared by multiple stores.
+    pub fn with_update_observers(mut self, observers: UpdateObservers) -> Self {
+        self.update_observers = observers;
+        self
+    }
+
+    /// Sets a [Compression] applied to document states and updates written by current store.
+    pub fn with_compression(mut self, compression: Compression) -> Self {
+        self.options.compression = compression;
+        self
+    }
+
+    /// Sets an [OidAllocation] strategy used to assign OIDs to documents persisted for the first
+    /// time by current store.
+    pub fn with_oid_allocation(mut self, allocation: OidAllocation) -> Self {
+        self.options.oid_allocation = allocation;
+        self
+    }
+
+    /// Enables or disables recording document changes in a global change log (see:
+    /// [DocOps::iter_changes]).
+    pub fn with_changelog(mut self, enabled: bool) -> Self {
+        self.options.changelog = enabled;
+        self
+    }
+
+    /// Sets a [Metrics] receiver, which current store reports counts and latencies of its
+    /// operations into (see: [DocOps::metrics]).
+    pub fn with_metrics(mut self, metrics: Metrics) -> Self {
+        self.metrics = metrics;
+        self
+    }
+
+    /// Sets a number of recently pushed updates of each document, which are remembered in order to
+    /// skip persisting byte-identical updates pushed again, i.e. by retrying clients (see:
+    /// [StoreOptions::update_dedup_window]). Deduplication is disabled when set to 0.
+    pub fn with_update_dedup(mut self, window: usize) -> Self {
+        self.options.update_dedup_window = window;
+        self
+    }
+
+    /// Sets a size in bytes, starting from which document states are stored only once in
+    /// a content addressed blob keyspace and referenced from their documents (see:
+    /// [StoreOptions::blob_threshold]). Blob storage is disabled when set to 0.
+    pub fn with_blob_threshold(mut self, threshold: usize) -> Self {
+        self.options.blob_threshold = threshold;
+        self
+    }
+
+    /// Sets a [ValueCodec] used to encode document states, updates and metadata persisted by
+    /// current store (see: [StoreOptions::codecs]). It replaces a [Compression] set on the store.
+    pub fn with_value_codec(mut self, codec: Arc<dyn ValueCodec>) -> Self {
+        self.options.codecs = CodecChain::from(codec);
+        self
+    }
+
+    /// Enables or disables moving documents removed by [DocOps::clear_doc] into a trash, from
+    /// which they can be restored (see: [StoreOptions::soft_delete]).
+    pub fn with_soft_delete(mut self, enabled: bool) -> Self {
+        self.options.soft_delete = enabled;
+        self
+    }
+
+    /// Enables or disables recording timestamps of pushed updates (see:
+    /// [StoreOptions::update_timestamps]).
+    pub fn with_update_timestamps(mut self, enabled: bool) -> Self {
+        self.options.update_timestamps = enabled;
+        self
+    }
+
+    /// Sets a [RetentionPolicy] used to determine which pending updates are merged into the
+    /// document state by [DocOps::enforce_retention].
+    pub fn with_retention_policy(mut self, policy: RetentionPolicy) -> Self {
+        self.options.retention_policy = policy;
+        self
+    }
+
+    /// Sets [UpdateLimits] used to reject oversized updates before they are written (see:
+    /// [StoreOptions::update_limits]).
+    pub fn with_update_limits(mut self, limits: UpdateLimits) -> Self {
+        self.options.update_limits = limits;
+        self
+    }
+
+    /// Sets an [UpdateValidator] called with every update and document state before it's
+    /// persisted, which can veto the write (see: [StoreOptions::update_validator]).
+    pub fn with_update_validator(mut self, validator: Arc<dyn UpdateValidator>) -> Self {
+        self.options.update_validator = Some(validator);
+        self
+    }
+
+    /// Sets a [WriteHook] called with a decoded summary of every change written by current store
+    /// (see: [StoreOptions::write_hook]).
+    pub fn with_write_hook(mut self, hook: Arc<dyn WriteHook>) -> Self {
+        self.options.write_hook = Some(hook);
+        self
+    }
+
+    /// Sets a [JsonExport] used to maintain a cached JSON rendering of documents refreshed every
+    /// time they are flushed (see: [StoreOptions::json_export]).
+    pub fn with_json_export(mut self, export: JsonExport) -> Self {
+        self.options.json_export = Some(Arc::new(export));
+        self
+    }
+
+    /// Sets a maximum size in bytes of a document state stored under a single key. Larger states
+    /// are split into chunks (see: [StoreOptions::doc_chunk_size]). Chunking is disabled when set
+    /// to 0.
+    pub fn with_doc_chunk_size(mut self, size: usize) -> Self {
+        self.options.doc_chunk_size = size;
+        self
+    }
+
+    /// Enables or disables maintaining document state vectors on every pushed update (see:
+    /// [StoreOptions::track_state_vector]).
+    pub fn with_state_vector_tracking(mut self, enabled: bool) -> Self {
+        self.options.track_state_vector = enabled;
+        self
+    }
+}
 
 impl<'db> From<Database<'db>> for LmdbStore<'db> {
     #[inline(always)]
     fn from(db: Database<'db>) -> Self {
-        LmdbStore(db)
+        LmdbStore {
+            db,
+            options: StoreOptions::default(),
+            update_observers: UpdateObservers::default(),
+            metrics: Metrics::default(),
+        }
     }
 }
 
 impl<'db> Into<Database<'db>> for LmdbStore<'db> {
     #[inline(always)]
     fn into(self) -> Database<'db> {
-        self.0
+        self.db
     }
 }
 
@@ -48,11 +226,23 @@ impl<'db> Deref for LmdbStore<'db> {
     type Target = Database<'db>;
 
     fn deref(&self) -> &Self::Target {
-        &self.0
+        &self.db
     }
 }
 
-impl<'db> DocOps<'db> for LmdbStore<'db> {}
+impl<'db> DocOps<'db> for LmdbStore<'db> {
+    fn options(&self) -> &StoreOptions {
+        &self.options
+    }
+
+    fn update_observers(&self) -> Option<&UpdateObservers> {
+        Some(&self.update_observers)
+    }
+
+    fn metrics(&self) -> Option<&dyn StoreMetrics> {
+        self.metrics.get()
+    }
+}
 
 impl<'db> KVStore<'db> for LmdbStore<'db> {
     type Error = MdbError;
@@ -61,25 +251,25 @@ impl<'db> KVStore<'db> for LmdbStore<'db> {
     type Return = &'db [u8];
 
     fn get(&self, key: &[u8]) -> Result<Option<Self::Return>, Self::Error> {
-        let value = self.0.get(&key).optional()?;
+        let value = self.db.get(&key).optional()?;
         Ok(value)
     }
 
     fn upsert(&self, key: &[u8], value: &[u8]) -> Result<(), Self::Error> {
-        self.0.set(&key, &value)?;
+        self.db.set(&key, &value)?;
         Ok(())
     }
 
     fn remove(&self, key: &[u8]) -> Result<(), Self::Error> {
-        let prev: Option<&[u8]> = self.0.get(&key).optional()?;
+        let prev: Option<&[u8]> = self.db.get(&key).optional()?;
         if prev.is_some() {
-            self.0.del(&key)?;
+            self.db.del(&key)?;
         }
         Ok(())
     }
 
     fn remove_range(&self, from: &[u8], to: &[u8]) -> Result<(), Self::Error> {
-        let mut c = self.0.new_cursor()?;
+        let mut c = self.db.new_cursor()?;
         if c.to_gte_key(&from).optional()?.is_some() {
             while c.get_key::<&[u8]>()? <= to {
                 c.del()?;
@@ -94,12 +284,21 @@ impl<'db> KVStore<'db> for LmdbStore<'db> {
     fn iter_range(&self, from: &[u8], to: &[u8]) -> Result<Self::Cursor, Self::Error> {
         let from = from.to_vec();
         let to = to.to_vec();
-        let cursor = unsafe { std::mem::transmute(self.0.keyrange(&from, &to)?) };
+        let cursor = unsafe { std::mem::transmute(self.db.keyrange(&from, &to)?) };
         Ok(LmdbRange { from, to, cursor })
     }
 
+    fn contains_range(&self, from: &[u8], to: &[u8]) -> Result<bool, Self::Error> {
+        let mut c = self.db.new_cursor()?;
+        if c.to_gte_key(&from).optional()?.is_some() {
+            Ok(c.get_key::<&[u8]>()? <= to)
+        } else {
+            Ok(false)
+        }
+    }
+
     fn peek_back(&self, key: &[u8]) -> Result<Option<Self::Entry>, Self::Error> {
-        let mut cursor = self.0.new_cursor()?;
+        let mut cursor = self.db.new_cursor()?;
         cursor.to_gte_key(&key).optional()?;
         if cursor.to_prev_key().optional()?.is_none() {
             return Ok(None);
@@ -192,8 +391,16 @@ mod test {
     use crate::{DocOps, LmdbStore};
     use lmdb_rs::core::DbCreate;
     use lmdb_rs::Environment;
-    use std::sync::Arc;
-    use yrs::{Doc, GetString, ReadTxn, Text, Transact};
+    use std::sync::{Arc, Mutex};
+    use yrs::updates::decoder::Decode;
+    use yrs::updates::encoder::Encode;
+    use yrs::{Doc, GetString, Map, ReadTxn, StateVector, Text, Transact, TransactionMut, Update};
+    use yrs_kvstore::batch::MultiDocBatch;
+    use yrs_kvstore::checksum::SEALED_HEADER_LEN;
+    use yrs_kvstore::keys::SCHEMA_VERSION;
+    use yrs_kvstore::observer::UpdateObservers;
+    use yrs_kvstore::testing::verify_kvstore_contract;
+    use yrs_kvstore::CompactionPolicy;
 
     struct Cleaner(&'static str);
 
@@ -338,12 +545,13 @@ mod test {
 
             let env = env.clone();
             let h = h.clone();
-            let _sub = doc.observe_update_v1(move |_, u| {
+            doc.observe_update_v1("persist", move |_: &TransactionMut, u| {
                 let db_txn = env.new_transaction().unwrap();
                 let db = LmdbStore::from(db_txn.bind(&h));
                 db.push_update(DOC_NAME, &u.update).unwrap();
                 db_txn.commit().unwrap();
-            });
+            })
+            .unwrap();
             // generate 3 updates
             text.push(&mut doc.transact_mut(), "a");
             text.push(&mut doc.transact_mut(), "b");
@@ -376,6 +584,155 @@ mod test {
         }
     }
 
+    #[test]
+    fn mixed_encoding_updates() {
+        const DOC_NAME: &str = "doc";
+        let cleaner = Cleaner::new("lmdb-mixed_encoding_updates");
+        let env = init_env(cleaner.dir());
+        let h = env.create_db("yrs", DbCreate).unwrap();
+
+        let (sv, expected) = {
+            let doc = Doc::new();
+            let text = doc.get_or_insert_text("text");
+            text.push(&mut doc.transact_mut(), "a");
+
+            let db_txn = env.new_transaction().unwrap();
+            let db = LmdbStore::from(db_txn.bind(&h));
+            {
+                let txn = doc.transact();
+                let doc_state = txn.encode_diff_v2(&StateVector::default());
+                let state_vector = txn.state_vector().encode_v2();
+                db.insert_doc_raw_v2(DOC_NAME.as_bytes(), &doc_state, &state_vector)
+                    .unwrap();
+            }
+
+            let sv = doc.transact().state_vector();
+            text.push(&mut doc.transact_mut(), "b");
+            db.push_update(DOC_NAME, &doc.transact().encode_diff_v1(&sv))
+                .unwrap();
+            let sv2 = doc.transact().state_vector();
+            text.push(&mut doc.transact_mut(), "c");
+            db.push_update_v2(DOC_NAME, &doc.transact().encode_diff_v2(&sv2))
+                .unwrap();
+            db_txn.commit().unwrap();
+
+            let update = doc.transact().encode_diff_v2(&sv);
+            (sv, update)
+        };
+
+        // load document
+        {
+            let doc = Doc::new();
+            let text = doc.get_or_insert_text("text");
+            let mut txn = doc.transact_mut();
+
+            let db_txn = env.get_reader().unwrap();
+            let db = LmdbStore::from(db_txn.bind(&h));
+            db.load_doc(DOC_NAME, &mut txn).unwrap();
+
+            assert_eq!(text.get_string(&txn), "abc");
+
+            // pending updates are merged without squashing their blocks, so updates are compared
+            // by the changes they carry instead of their binary representation
+            let actual = db.get_diff_v2(DOC_NAME, &sv).unwrap().unwrap();
+            let actual = Update::decode_v2(&actual).unwrap();
+            let expected = Update::decode_v2(&expected).unwrap();
+            assert_eq!(actual.insertions(true), expected.insertions(true));
+            assert_eq!(actual.delete_set(), expected.delete_set());
+        }
+
+        // flush document
+        {
+            let db_txn = env.new_transaction().unwrap();
+            let db = LmdbStore::from(db_txn.bind(&h));
+            let doc = db.flush_doc(DOC_NAME).unwrap().unwrap();
+            db_txn.commit().unwrap();
+
+            let text = doc.get_or_insert_text("text");
+            assert_eq!(text.get_string(&doc.transact()), "abc");
+        }
+    }
+
+    #[test]
+    fn batch_updates() {
+        const DOC_NAME: &str = "doc";
+        let cleaner = Cleaner::new("lmdb-batch_updates");
+        let env = init_env(cleaner.dir());
+        let h = env.create_db("yrs", DbCreate).unwrap();
+
+        // buffer document updates
+        let updates = {
+            let doc = Doc::new();
+            let text = doc.get_or_insert_text("text");
+            let mut updates = Vec::new();
+            for chunk in ["a", "b", "c"] {
+                let sv = doc.transact().state_vector();
+                text.push(&mut doc.transact_mut(), chunk);
+                updates.push(doc.transact().encode_diff_v1(&sv));
+            }
+            updates
+        };
+
+        {
+            let db_txn = env.new_transaction().unwrap();
+            let db = LmdbStore::from(db_txn.bind(&h));
+            let last = db
+                .push_updates(DOC_NAME, updates.iter().map(Vec::as_slice))
+                .unwrap();
+            assert_eq!(last, 3);
+            let next = db.push_update(DOC_NAME, &updates[0]).unwrap();
+            assert_eq!(next, 4);
+            db_txn.commit().unwrap();
+        }
+
+        {
+            let doc = Doc::new();
+            let text = doc.get_or_insert_text("text");
+            let mut txn = doc.transact_mut();
+
+            let db_txn = env.get_reader().unwrap();
+            let db = LmdbStore::from(db_txn.bind(&h));
+            db.load_doc(DOC_NAME, &mut txn).unwrap();
+
+            assert_eq!(text.get_string(&txn), "abc");
+        }
+    }
+
+    #[test]
+    fn auto_compaction() {
+        const DOC_NAME: &str = "doc";
+        let cleaner = Cleaner::new("lmdb-auto_compaction");
+        let env = init_env(cleaner.dir());
+        let h = env.create_db("yrs", DbCreate).unwrap();
+        let policy = CompactionPolicy {
+            max_pending_updates: Some(2),
+            ..CompactionPolicy::default()
+        };
+
+        let doc = Doc::new();
+        let text = doc.get_or_insert_text("text");
+        let db_txn = env.new_transaction().unwrap();
+        let db = LmdbStore::from(db_txn.bind(&h)).with_compaction_policy(policy);
+        for chunk in ["a", "b"] {
+            let sv = doc.transact().state_vector();
+            text.push(&mut doc.transact_mut(), chunk);
+            db.push_update(DOC_NAME, &doc.transact().encode_diff_v1(&sv))
+                .unwrap();
+        }
+        let (sv, completed) = db.get_state_vector(DOC_NAME).unwrap();
+        assert!(sv.is_none());
+        assert!(!completed);
+
+        // 3rd update exceeds the limit of pending updates
+        let sv = doc.transact().state_vector();
+        text.push(&mut doc.transact_mut(), "c");
+        db.push_update(DOC_NAME, &doc.transact().encode_diff_v1(&sv))
+            .unwrap();
+        let (sv, completed) = db.get_state_vector(DOC_NAME).unwrap();
+        assert_eq!(sv, Some(doc.transact().state_vector()));
+        assert!(completed);
+    }
+
     #[test]
     fn state_vector_updates_only() {
         const DOC_NAME: &str = "doc";
@@ -391,12 +748,13 @@ mod test {
             let text = doc.get_or_insert_text("text");
             let env = env.clone();
             let h = h.clone();
-            let _sub = doc.observe_update_v1(move |_, u| {
+            doc.observe_update_v1("persist", move |_: &TransactionMut, u| {
                 let db_txn = env.new_transaction().unwrap();
                 let db = LmdbStore::from(db_txn.bind(&h));
                 db.push_update(DOC_NAME, &u.update).unwrap();
                 db_txn.commit().unwrap();
-            });
+            })
+            .unwrap();
             // generate 3 updates
             text.push(&mut doc.transact_mut(), "a");
             text.push(&mut doc.transact_mut(), "b");
@@ -428,12 +786,13 @@ mod test {
 
             let env = env.clone();
             let h = h.clone();
-            let _sub = doc.observe_update_v1(move |_, u| {
+            doc.observe_update_v1("persist", move |_: &TransactionMut, u| {
                 let db_txn = env.new_transaction().unwrap();
                 let db = LmdbStore::from(db_txn.bind(&h));
                 db.push_update(DOC_NAME, &u.update).unwrap();
                 db_txn.commit().unwrap();
-            });
+            })
+            .unwrap();
 
             // generate 3 updates
             text.push(&mut doc.transact_mut(), "a");
@@ -498,7 +857,7 @@ mod test {
 
         let db_txn = env.new_transaction().unwrap();
         let db = LmdbStore::from(db_txn.bind(&h));
-        let prev = db.get_meta(DOC_NAME, "key").unwrap().map(Vec::from);
+        let prev = db.get_meta(DOC_NAME, "key").unwrap();
         db.insert_meta(DOC_NAME, "key", "value2".as_bytes())
             .unwrap();
         db_txn.commit().unwrap();
@@ -506,7 +865,7 @@ mod test {
 
         let db_txn = env.new_transaction().unwrap();
         let db = LmdbStore::from(db_txn.bind(&h));
-        let prev = db.get_meta(DOC_NAME, "key").unwrap().map(Vec::from);
+        let prev = db.get_meta(DOC_NAME, "key").unwrap();
         db.remove_meta(DOC_NAME, "key").unwrap();
         assert_eq!(prev.as_deref(), Some("value2".as_bytes()));
         let value = db.get_meta(DOC_NAME, "key").unwrap();
@@ -532,6 +891,800 @@ mod test {
         assert!(i.next().is_none());
     }
 
+    #[test]
+    fn doc_meta_range() {
+        let cleaner = Cleaner::new("lmdb-doc_meta_range");
+        let env = init_env(cleaner.dir());
+        let h = env.create_db("yrs", DbCreate).unwrap();
+        let db_txn = env.new_transaction().unwrap();
+        let db = LmdbStore::from(db_txn.bind(&h));
+
+        for key in [
+            "cursor:alice",
+            "cursor:bob",
+            "cursor:carol",
+            "owner",
+            "title",
+        ] {
+            db.insert_meta("A", key, key.as_bytes()).unwrap();
+        }
+        db.insert_meta("B", "cursor:dave", [1].as_ref()).unwrap();
+
+        let keys: Vec<_> = db
+            .iter_meta_prefix("A", "cursor:")
+            .unwrap()
+            .map(|(k, _)| k)
+            .collect();
+        let expected: Vec<Box<[u8]>> = vec![
+            "cursor:alice".as_bytes().into(),
+            "cursor:bob".as_bytes().into(),
+            "cursor:carol".as_bytes().into(),
+        ];
+        assert_eq!(keys, expected);
+
+        // range bounds are inclusive
+        let keys: Vec<_> = db
+            .iter_meta_range("A", "cursor:bob", "owner")
+            .unwrap()
+            .map(|(k, _)| k)
+            .collect();
+        let expected: Vec<Box<[u8]>> = vec![
+            "cursor:bob".as_bytes().into(),
+            "cursor:carol".as_bytes().into(),
+            "owner".as_bytes().into(),
+        ];
+        assert_eq!(keys, expected);
+
+        assert_eq!(db.iter_meta_prefix("A", "").unwrap().count(), 5);
+        assert!(db.iter_meta_prefix("A", "x").unwrap().next().is_none());
+        assert!(db.iter_meta_prefix("C", "").unwrap().next().is_none());
+    }
+
+    #[test]
+    fn doc_snapshots() {
+        const DOC_NAME: &str = "doc";
+        let cleaner = Cleaner::new("lmdb-doc_snapshots");
+        let env = init_env(cleaner.dir());
+        let h = env.create_db("yrs", DbCreate).unwrap();
+
+        {
+            let doc = Doc::new();
+            let text = doc.get_or_insert_text("text");
+            text.push(&mut doc.transact_mut(), "a");
+
+            let db_txn = env.new_transaction().unwrap();
+            let db = LmdbStore::from(db_txn.bind(&h));
+            db.insert_doc(DOC_NAME, &doc.transact()).unwrap();
+            let snapshot = db.create_snapshot(DOC_NAME, "v1").unwrap();
+            assert!(snapshot.is_some());
+
+            let sv = doc.transact().state_vector();
+            text.push(&mut doc.transact_mut(), "b");
+            db.push_update(DOC_NAME, &doc.transact().encode_diff_v1(&sv))
+                .unwrap();
+            db_txn.commit().unwrap();
+        }
+
+        {
+            let db_txn = env.get_reader().unwrap();
+            let db = LmdbStore::from(db_txn.bind(&h));
+
+            let doc = Doc::new();
+            let text = doc.get_or_insert_text("text");
+            let mut txn = doc.transact_mut();
+            assert!(db.load_snapshot(DOC_NAME, "v1", &mut txn).unwrap());
+            assert_eq!(text.get_string(&txn), "a");
+
+            let mut i = db.iter_snapshots(DOC_NAME).unwrap();
+            assert_eq!(i.next(), Some("v1".as_bytes().into()));
+            assert!(i.next().is_none());
+        }
+
+        {
+            let db_txn = env.new_transaction().unwrap();
+            let db = LmdbStore::from(db_txn.bind(&h));
+            db.remove_snapshot(DOC_NAME, "v1").unwrap();
+            assert!(db.get_snapshot(DOC_NAME, "v1").unwrap().is_none());
+
+            let doc = Doc::new();
+            let text = doc.get_or_insert_text("text");
+            let mut txn = doc.transact_mut();
+            assert!(!db.load_snapshot(DOC_NAME, "v1", &mut txn).unwrap());
+            db.load_doc(DOC_NAME, &mut txn).unwrap();
+            assert_eq!(text.get_string(&txn), "ab");
+        }
+    }
+
+    #[test]
+    fn kvstore_contract() {
+        let cleaner = Cleaner::new("lmdb-kvstore_contract");
+        let env = init_env(cleaner.dir());
+        let h = env.create_db("yrs", DbCreate).unwrap();
+        let db_txn = env.new_transaction().unwrap();
+        let db = LmdbStore::from(db_txn.bind(&h));
+        verify_kvstore_contract(&db);
+    }
+
+    #[test]
+    fn observe_updates() {
+        const DOC_NAME: &str = "doc";
+        let cleaner = Cleaner::new("lmdb-observe_updates");
+        let env = init_env(cleaner.dir());
+        let h = env.create_db("yrs", DbCreate).unwrap();
+        let observers = UpdateObservers::new();
+        let events = Arc::new(Mutex::new(Vec::new()));
+
+        let doc = Doc::new();
+        let text = doc.get_or_insert_text("text");
+        text.push(&mut doc.transact_mut(), "a");
+
+        // observers are shared between store instances
+        let _sub = {
+            let db_txn = env.get_reader().unwrap();
+            let db = LmdbStore::from(db_txn.bind(&h)).with_update_observers(observers.clone());
+            let events = events.clone();
+            db.observe_updates(DOC_NAME, move |e| {
+                events.lock().unwrap().push((e.seq, e.update.to_vec()));
+            })
+            .unwrap()
+        };
+
+        let mut expected = Vec::new();
+        {
+            let db_txn = env.new_transaction().unwrap();
+            let db = LmdbStore::from(db_txn.bind(&h)).with_update_observers(observers.clone());
+            db.insert_doc(DOC_NAME, &doc.transact()).unwrap();
+            expected.push((None, doc.transact().encode_diff_v1(&StateVector::default())));
+            for chunk in ["b", "c"] {
+                let sv = doc.transact().state_vector();
+                text.push(&mut doc.transact_mut(), chunk);
+                let update = doc.transact().encode_diff_v1(&sv);
+                let seq = db.push_update(DOC_NAME, &update).unwrap();
+                expected.push((Some(seq), update));
+            }
+            // updates of other documents are not observed
+            db.push_update("other", &expected[1].1).unwrap();
+            db_txn.commit().unwrap();
+        }
+
+        assert_eq!(*events.lock().unwrap(), expected);
+    }
+
+    #[test]
+    fn update_history() {
+        const DOC_NAME: &str = "doc";
+        let cleaner = Cleaner::new("lmdb-update_history");
+        let env = init_env(cleaner.dir());
+        let h = env.create_db("yrs", DbCreate).unwrap();
+        let policy = CompactionPolicy {
+            retain_history: true,
+            ..CompactionPolicy::default()
+        };
+
+        let doc = Doc::new();
+        let text = doc.get_or_insert_text("text");
+        for chunks in [["a", "b"], ["c", "d"]] {
+            let db_txn = env.new_transaction().unwrap();
+            let db = LmdbStore::from(db_txn.bind(&h)).with_compaction_policy(policy);
+            for chunk in chunks {
+                let sv = doc.transact().state_vector();
+                text.push(&mut doc.transact_mut(), chunk);
+                let update = doc.transact().encode_diff_v1(&sv);
+                db.push_update(DOC_NAME, &update).unwrap();
+            }
+            db.flush_doc(DOC_NAME).unwrap().unwrap();
+            db_txn.commit().unwrap();
+        }
+
+        let db_txn = env.new_transaction().unwrap();
+        let db = LmdbStore::from(db_txn.bind(&h)).with_compaction_policy(policy);
+        let history: Vec<_> = db.iter_history(DOC_NAME).unwrap().collect();
+        let seqs: Vec<_> = history.iter().map(|e| e.seq).collect();
+        assert_eq!(seqs, vec![1, 2, 3, 4]);
+
+        // reconstruct intermediate document state
+        let restored = Doc::new();
+        let restored_text = restored.get_or_insert_text("text");
+        for e in &history[..3] {
+            assert!(!e.v2);
+            let update = Update::decode_v1(&e.update).unwrap();
+            restored.transact_mut().apply_update(update).unwrap();
+        }
+        assert_eq!(restored_text.get_string(&restored.transact()), "abc");
+
+        db.trim_history(DOC_NAME, 2).unwrap();
+        let seqs: Vec<_> = db.iter_history(DOC_NAME).unwrap().map(|e| e.seq).collect();
+        assert_eq!(seqs, vec![3, 4]);
+    }
+
+    #[test]
+    fn subdocs() {
+        let cleaner = Cleaner::new("lmdb-subdocs");
+        let env = init_env(cleaner.dir());
+        let h = env.create_db("yrs", DbCreate).unwrap();
+
+        // insert parent document and its subdocument
+        let guid = {
+            let doc = Doc::new();
+            let map = doc.get_or_insert_map("map");
+            let subdoc = map.insert(&mut doc.transact_mut(), "sub", Doc::new());
+            let text = subdoc.get_or_insert_text("text");
+            text.push(&mut subdoc.transact_mut(), "hello");
+
+            let db_txn = env.new_transaction().unwrap();
+            let db = LmdbStore::from(db_txn.bind(&h));
+            db.insert_doc("parent", &doc.transact()).unwrap();
+            db.insert_subdoc("parent", &subdoc).unwrap();
+            db_txn.commit().unwrap();
+            subdoc.guid()
+        };
+
+        // load parent document together with its subdocuments
+        {
+            let doc = Doc::new();
+            let db_txn = env.get_reader().unwrap();
+            let db = LmdbStore::from(db_txn.bind(&h));
+            db.load_doc("parent", &mut doc.transact_mut()).unwrap();
+            let loaded = db.load_subdocs("parent", &doc.transact()).unwrap();
+            assert_eq!(loaded, 1);
+
+            let subdoc = doc.transact().subdocs().next().unwrap().clone();
+            assert_eq!(subdoc.guid(), guid);
+            let text = subdoc.get_or_insert_text("text");
+            assert_eq!(text.get_string(&subdoc.transact()), "hello");
+
+            let guids: Vec<_> = db.iter_subdocs("parent").unwrap().collect();
+            assert_eq!(guids, vec![guid.as_bytes().into()]);
+        }
+
+        // removing parent document removes its subdocuments
+        {
+            let db_txn = env.new_transaction().unwrap();
+            let db = LmdbStore::from(db_txn.bind(&h));
+            db.clear_doc("parent").unwrap();
+            let doc = Doc::new();
+            assert!(!db
+                .load_subdoc("parent", &guid, &mut doc.transact_mut())
+                .unwrap());
+        }
+    }
+
+    #[test]
+    fn subdocs_auto_load() {
+        let cleaner = Cleaner::new("lmdb-subdocs_auto_load");
+        let env = init_env(cleaner.dir());
+        let h = env.create_db("yrs", DbCreate).unwrap();
+
+        // insert parent document with one auto-loaded and one lazily loaded subdocument
+        let (auto_guid, lazy_guid) = {
+            let doc = Doc::new();
+            let map = doc.get_or_insert_map("map");
+            let options = yrs::Options {
+                auto_load: true,
+                ..yrs::Options::default()
+            };
+            let auto = map.insert(&mut doc.transact_mut(), "auto", Doc::with_options(options));
+            let lazy = map.insert(&mut doc.transact_mut(), "lazy", Doc::new());
+            for subdoc in [&auto, &lazy] {
+                let text = subdoc.get_or_insert_text("text");
+                text.push(&mut subdoc.transact_mut(), "hello");
+            }
+
+            let db_txn = env.new_transaction().unwrap();
+            let db = LmdbStore::from(db_txn.bind(&h));
+            db.insert_doc("parent", &doc.transact()).unwrap();
+            db.insert_subdoc("parent", &auto).unwrap();
+            db.insert_subdoc("parent", &lazy).unwrap();
+            db_txn.commit().unwrap();
+            (auto.guid(), lazy.guid())
+        };
+
+        let doc = Doc::new();
+        let db_txn = env.get_reader().unwrap();
+        let db = LmdbStore::from(db_txn.bind(&h));
+        db.load_doc("parent", &mut doc.transact_mut()).unwrap();
+
+        let subdocs: Vec<_> = doc.transact().subdocs().cloned().collect();
+        let auto = subdocs.iter().find(|d| d.guid() == auto_guid).unwrap();
+        let text = auto.get_or_insert_text("text");
+        assert_eq!(text.get_string(&auto.transact()), "hello");
+
+        // lazy subdocument is loaded only once requested
+        let lazy = subdocs.iter().find(|d| d.guid() == lazy_guid).unwrap();
+        let text = lazy.get_or_insert_text("text");
+        assert_eq!(text.get_string(&lazy.transact()), "");
+        lazy.load(&mut doc.transact_mut());
+        assert_eq!(text.get_string(&lazy.transact()), "hello");
+    }
+
+    #[test]
+    fn purge_doc() {
+        const DOC_NAME: &str = "doc";
+        let cleaner = Cleaner::new("lmdb-purge_doc");
+        let env = init_env(cleaner.dir());
+        let h = env.create_db("yrs", DbCreate).unwrap();
+
+        let db_txn = env.new_transaction().unwrap();
+        let db = LmdbStore::from(db_txn.bind(&h));
+        let doc = Doc::new();
+        let text = doc.get_or_insert_text("text");
+        text.push(&mut doc.transact_mut(), "a");
+        db.insert_doc(DOC_NAME, &doc.transact()).unwrap();
+        for chunk in ["b", "c"] {
+            let sv = doc.transact().state_vector();
+            text.push(&mut doc.transact_mut(), chunk);
+            let update = doc.transact().encode_diff_v1(&sv);
+            db.push_update(DOC_NAME, &update).unwrap();
+        }
+        db.insert_meta(DOC_NAME, "key", &[1]).unwrap();
+        db.create_snapshot(DOC_NAME, "s1").unwrap();
+
+        let stats = db.purge_doc(DOC_NAME, true).unwrap().unwrap();
+        assert_eq!(stats.state, 2);
+        assert_eq!(stats.updates, 2);
+        assert_eq!(stats.meta, 1);
+        assert_eq!(stats.snapshots, 1);
+        assert_eq!(stats.total(), 6);
+
+        assert!(db.iter_docs().unwrap().next().is_none());
+        assert!(db.purge_doc(DOC_NAME, true).unwrap().is_none());
+    }
+
+    #[test]
+    fn rename_doc() {
+        let cleaner = Cleaner::new("lmdb-rename_doc");
+        let env = init_env(cleaner.dir());
+        let h = env.create_db("yrs", DbCreate).unwrap();
+
+        let db_txn = env.new_transaction().unwrap();
+        let db = LmdbStore::from(db_txn.bind(&h));
+        let doc = Doc::new();
+        let text = doc.get_or_insert_text("text");
+        text.push(&mut doc.transact_mut(), "hello");
+        db.insert_doc("A", &doc.transact()).unwrap();
+        db.insert_meta("A", "key", &[1]).unwrap();
+        db.insert_meta("B", "key", &[2]).unwrap();
+
+        assert!(db.rename_doc("A", "C").unwrap());
+        assert!(!db.rename_doc("A", "D").unwrap());
+        assert!(db.rename_doc("C", "B").is_err());
+
+        let mut i = db.iter_docs().unwrap();
+        assert_eq!(i.next(), Some("B".as_bytes().into()));
+        assert_eq!(i.next(), Some("C".as_bytes().into()));
+        assert!(i.next().is_none());
+
+        let doc = Doc::new();
+        let text = doc.get_or_insert_text("text");
+        assert!(db.load_doc("C", &mut doc.transact_mut()).unwrap());
+        assert_eq!(text.get_string(&doc.transact()), "hello");
+        let meta = db.get_meta("C", "key").unwrap().unwrap();
+        assert_eq!(meta, &[1]);
+    }
+
+    #[test]
+    fn copy_doc() {
+        let cleaner = Cleaner::new("lmdb-copy_doc");
+        let env = init_env(cleaner.dir());
+        let h = env.create_db("yrs", DbCreate).unwrap();
+
+        let db_txn = env.new_transaction().unwrap();
+        let db = LmdbStore::from(db_txn.bind(&h));
+        let doc = Doc::new();
+        let text = doc.get_or_insert_text("text");
+        text.push(&mut doc.transact_mut(), "hello");
+        db.insert_doc("A", &doc.transact()).unwrap();
+        let sv = doc.transact().state_vector();
+        text.push(&mut doc.transact_mut(), " world");
+        let update = doc.transact().encode_diff_v1(&sv);
+        db.push_update("A", &update).unwrap();
+        db.insert_meta("A", "key", &[1]).unwrap();
+
+        assert!(db.copy_doc("A", "B", true).unwrap());
+        assert!(db.copy_doc("A", "C", false).unwrap());
+        assert!(!db.copy_doc("missing", "D", false).unwrap());
+        assert!(db.copy_doc("A", "C", false).is_err());
+
+        // copies are independent from the source document
+        db.clear_doc("A").unwrap();
+
+        for name in ["B", "C"] {
+            let doc = Doc::new();
+            let text = doc.get_or_insert_text("text");
+            assert!(db.load_doc(name, &mut doc.transact_mut()).unwrap());
+            assert_eq!(text.get_string(&doc.transact()), "hello world");
+        }
+        assert!(db.get_meta("B", "key").unwrap().is_some());
+        assert!(db.get_meta("C", "key").unwrap().is_none());
+    }
+
+    #[test]
+    fn flush_doc_gc() {
+        const DOC_NAME: &str = "doc";
+        let cleaner = Cleaner::new("lmdb-flush_doc_gc");
+        let env = init_env(cleaner.dir());
+        let h = env.create_db("yrs", DbCreate).unwrap();
+
+        let db_txn = env.new_transaction().unwrap();
+        let db = LmdbStore::from(db_txn.bind(&h));
+        let doc = Doc::with_options(yrs::Options {
+            skip_gc: true,
+            ..yrs::Options::default()
+        });
+        let text = doc.get_or_insert_text("text");
+        text.push(&mut doc.transact_mut(), "hello world, this will be removed");
+        text.remove_range(&mut doc.transact_mut(), 5, 28);
+        db.insert_doc(DOC_NAME, &doc.transact()).unwrap();
+
+        let stats = db.flush_doc_gc(DOC_NAME).unwrap().unwrap();
+        assert!(stats.reclaimed() > 0);
+
+        let doc = Doc::new();
+        let text = doc.get_or_insert_text("text");
+        db.load_doc(DOC_NAME, &mut doc.transact_mut()).unwrap();
+        assert_eq!(text.get_string(&doc.transact()), "hello");
+        assert!(db.flush_doc_gc("missing").unwrap().is_none());
+    }
+
+    #[test]
+    fn doc_stats() {
+        const DOC_NAME: &str = "doc";
+        let cleaner = Cleaner::new("lmdb-doc_stats");
+        let env = init_env(cleaner.dir());
+        let h = env.create_db("yrs", DbCreate).unwrap();
+
+        let db_txn = env.new_transaction().unwrap();
+        let db = LmdbStore::from(db_txn.bind(&h));
+        assert!(db.doc_stats(DOC_NAME).unwrap().is_none());
+
+        let doc = Doc::new();
+        let text = doc.get_or_insert_text("text");
+        text.push(&mut doc.transact_mut(), "hello");
+        let doc_state = doc.transact().encode_diff_v1(&StateVector::default());
+        db.insert_doc(DOC_NAME, &doc.transact()).unwrap();
+        let mut update_bytes = 0;
+        for chunk in ["a", "b"] {
+            let sv = doc.transact().state_vector();
+            text.push(&mut doc.transact_mut(), chunk);
+            let update = doc.transact().encode_diff_v1(&sv);
+            update_bytes += update.len() as u64;
+            db.push_update(DOC_NAME, &update).unwrap();
+        }
+        db.insert_meta(DOC_NAME, "key", &[1]).unwrap();
+
+        let stats = db.doc_stats(DOC_NAME).unwrap().unwrap();
+        // stored values are sealed with a checksum
+        let overhead = SEALED_HEADER_LEN as u64;
+        assert_eq!(stats.doc_state_bytes, doc_state.len() as u64 + overhead);
+        assert_eq!(stats.pending_updates, 2);
+        assert_eq!(stats.pending_update_bytes, update_bytes + 2 * overhead);
+        assert_eq!(stats.meta_entries, 1);
+        assert_eq!(stats.last_update_seq, Some(2));
+        assert_eq!(stats.last_flush_seq, None);
+    }
+
+    #[test]
+    fn iter_updates() {
+        const DOC_NAME: &str = "doc";
+        let cleaner = Cleaner::new("lmdb-iter_updates");
+        let env = init_env(cleaner.dir());
+        let h = env.create_db("yrs", DbCreate).unwrap();
+
+        let db_txn = env.new_transaction().unwrap();
+        let db = LmdbStore::from(db_txn.bind(&h));
+        let doc = Doc::new();
+        let text = doc.get_or_insert_text("text");
+        let mut expected = Vec::new();
+        for (i, chunk) in ["a", "b", "c"].iter().enumerate() {
+            let sv = doc.transact().state_vector();
+            text.push(&mut doc.transact_mut(), chunk);
+            let v2 = i == 1;
+            let update = if v2 {
+                let update = doc.transact().encode_diff_v2(&sv);
+                db.push_update_v2(DOC_NAME, &update).unwrap();
+                update
+            } else {
+                let update = doc.transact().encode_diff_v1(&sv);
+                db.push_update(DOC_NAME, &update).unwrap();
+                update
+            };
+            expected.push((i as u32 + 1, update, v2));
+        }
+
+        let actual: Vec<_> = db
+            .iter_updates(DOC_NAME)
+            .unwrap()
+            .map(|u| (u.clock, u.update.to_vec(), u.v2))
+            .collect();
+        assert_eq!(actual, expected);
+
+        db.flush_doc(DOC_NAME).unwrap();
+        assert!(db.iter_updates(DOC_NAME).unwrap().next().is_none());
+    }
+
+    #[test]
+    fn trim_updates() {
+        const DOC_NAME: &str = "doc";
+        let cleaner = Cleaner::new("lmdb-trim_updates");
+        let env = init_env(cleaner.dir());
+        let h = env.create_db("yrs", DbCreate).unwrap();
+
+        let db_txn = env.new_transaction().unwrap();
+        let db = LmdbStore::from(db_txn.bind(&h));
+        let doc = Doc::new();
+        let text = doc.get_or_insert_text("text");
+        for chunk in ["a", "b", "c"] {
+            let sv = doc.transact().state_vector();
+            text.push(&mut doc.transact_mut(), chunk);
+            let update = doc.transact().encode_diff_v1(&sv);
+            db.push_update(DOC_NAME, &update).unwrap();
+        }
+
+        db.trim_updates(DOC_NAME, 2).unwrap();
+        let clocks: Vec<_> = db
+            .iter_updates(DOC_NAME)
+            .unwrap()
+            .map(|u| u.clock)
+            .collect();
+        assert_eq!(clocks, vec![3]);
+
+        let update = doc.transact().encode_diff_v1(&StateVector::default());
+        assert_eq!(db.push_update(DOC_NAME, &update).unwrap(), 4);
+    }
+
+    #[test]
+    fn multi_doc_batch() {
+        let cleaner = Cleaner::new("lmdb-multi_doc_batch");
+        let env = init_env(cleaner.dir());
+        let h = env.create_db("yrs", DbCreate).unwrap();
+
+        let doc = Doc::new();
+        let text = doc.get_or_insert_text("text");
+        text.push(&mut doc.transact_mut(), "hello");
+        let update = doc.transact().encode_diff_v1(&StateVector::default());
+
+        let mut batch = MultiDocBatch::new();
+        batch
+            .push_update("A", &update)
+            .push_update("B", &update)
+            .push_update("A", &update)
+            .insert_meta("index", "A", &[1])
+            .insert_meta("index", "B", &[2]);
+        let db_txn = env.new_transaction().unwrap();
+        let db = LmdbStore::from(db_txn.bind(&h));
+        db.commit_docs(batch).unwrap();
+        db_txn.commit().unwrap();
+
+        let db_txn = env.get_reader().unwrap();
+        let db = LmdbStore::from(db_txn.bind(&h));
+        let clocks: Vec<_> = db.iter_updates("A").unwrap().map(|u| u.clock).collect();
+        assert_eq!(clocks, vec![1, 2]);
+        for name in ["A", "B"] {
+            let doc = Doc::new();
+            let text = doc.get_or_insert_text("text");
+            assert!(db.load_doc(name, &mut doc.transact_mut()).unwrap());
+            assert_eq!(text.get_string(&doc.transact()), "hello");
+        }
+        let index: Vec<_> = db.iter_meta("index").unwrap().collect();
+        assert_eq!(index.len(), 2);
+    }
+
+    #[test]
+    fn doc_expiry() {
+        let cleaner = Cleaner::new("lmdb-doc_expiry");
+        let env = init_env(cleaner.dir());
+        let h = env.create_db("yrs", DbCreate).unwrap();
+
+        let db_txn = env.new_transaction().unwrap();
+        let db = LmdbStore::from(db_txn.bind(&h));
+        let doc = Doc::new();
+        let text = doc.get_or_insert_text("text");
+        text.push(&mut doc.transact_mut(), "hello");
+        for name in ["A", "B", "C"] {
+            db.insert_doc(name, &doc.transact()).unwrap();
+        }
+        db.set_doc_expiry("A", 100).unwrap();
+        db.set_doc_expiry("B", 100).unwrap();
+        db.set_doc_expiry("C", 300).unwrap();
+        // expiry can be extended
+        db.set_doc_expiry("B", 200).unwrap();
+        assert_eq!(db.get_doc_expiry("B").unwrap(), Some(200));
+        // renamed documents still expire
+        db.rename_doc("C", "D").unwrap();
+
+        assert_eq!(db.sweep_expired(50).unwrap(), 0);
+        assert_eq!(db.sweep_expired(150).unwrap(), 1);
+        let docs: Vec<_> = db.iter_docs().unwrap().collect();
+        assert_eq!(docs, vec!["B".as_bytes().into(), "D".as_bytes().into()]);
+
+        db.remove_doc_expiry("B").unwrap();
+        assert_eq!(db.get_doc_expiry("B").unwrap(), None);
+        assert_eq!(db.sweep_expired(1000).unwrap(), 1);
+
+        let docs: Vec<_> = db.iter_docs().unwrap().collect();
+        assert_eq!(docs, vec!["B".as_bytes().into()]);
+        db_txn.commit().unwrap();
+    }
+
+    #[test]
+    fn schema_migration() {
+        let cleaner = Cleaner::new("lmdb-schema_migration");
+        let env = init_env(cleaner.dir());
+        let h = env.create_db("yrs", DbCreate).unwrap();
+
+        let db_txn = env.new_transaction().unwrap();
+        let db = LmdbStore::from(db_txn.bind(&h));
+        assert_eq!(db.schema_version().unwrap(), SCHEMA_VERSION);
+        db.migrate(SCHEMA_VERSION, SCHEMA_VERSION).unwrap();
+        assert_eq!(db.schema_version().unwrap(), SCHEMA_VERSION);
+
+        // there's no schema version newer than the current one
+        assert!(db.migrate(SCHEMA_VERSION, SCHEMA_VERSION + 1).is_err());
+        // database doesn't use requested schema version
+        assert!(db.migrate(SCHEMA_VERSION + 1, SCHEMA_VERSION + 2).is_err());
+        db_txn.commit().unwrap();
+    }
+
+    #[test]
+    fn sync_step() {
+        const DOC_NAME: &str = "doc";
+        let cleaner = Cleaner::new("lmdb-sync_step");
+
+        let doc = Doc::new();
+        let text = doc.get_or_insert_text("text");
+        text.push(&mut doc.transact_mut(), "a");
+        let env = init_env(cleaner.dir());
+        let h = env.create_db("yrs", DbCreate).unwrap();
+        let db_txn = env.new_transaction().unwrap();
+        let db = LmdbStore::from(db_txn.bind(&h));
+        db.insert_doc(DOC_NAME, &doc.transact()).unwrap();
+
+        // remote peer knows only about the stored document state
+        let remote = Doc::new();
+        let remote_text = remote.get_or_insert_text("text");
+        let state = doc.transact().encode_diff_v1(&StateVector::default());
+        remote
+            .transact_mut()
+            .apply_update(Update::decode_v1(&state).unwrap())
+            .unwrap();
+        let remote_sv = remote.transact().state_vector();
+
+        // local changes which were not merged into document state yet
+        text.push(&mut doc.transact_mut(), "b");
+        let update = doc.transact().encode_diff_v1(&remote_sv);
+        db.push_update(DOC_NAME, &update).unwrap();
+
+        let (diff, local_sv) = db.sync_step(DOC_NAME, &remote_sv).unwrap().unwrap();
+        assert_eq!(local_sv, doc.transact().state_vector());
+        remote
+            .transact_mut()
+            .apply_update(Update::decode_v1(&diff).unwrap())
+            .unwrap();
+        assert_eq!(remote_text.get_string(&remote.transact()), "ab");
+
+        let (diff_v2, local_sv_v2) = db.sync_step_v2(DOC_NAME, &remote_sv).unwrap().unwrap();
+        assert_eq!(local_sv_v2, local_sv);
+        assert_eq!(Update::decode_v2(&diff_v2).unwrap().encode_v1(), diff);
+
+        assert!(db.sync_step("none", &remote_sv).unwrap().is_none());
+        db_txn.commit().unwrap();
+    }
+
+    #[test]
+    fn doc_awareness() {
+        let cleaner = Cleaner::new("lmdb-doc_awareness");
+        let env = init_env(cleaner.dir());
+        let h = env.create_db("yrs", DbCreate).unwrap();
+        let db_txn = env.new_transaction().unwrap();
+        let db = LmdbStore::from(db_txn.bind(&h));
+
+        assert!(db.get_awareness("doc").unwrap().is_none());
+        let doc = Doc::new();
+        db.insert_doc("doc", &doc.transact()).unwrap();
+        db.insert_awareness("doc", &[1, 2, 3]).unwrap();
+        db.insert_awareness("doc", &[4, 5]).unwrap();
+        let awareness = db.get_awareness("doc").unwrap().map(Vec::from);
+        assert_eq!(awareness.as_deref(), Some(&[4u8, 5][..]));
+
+        // awareness state is not a part of document content
+        assert!(db.copy_doc("doc", "copy", true).unwrap());
+        assert!(db.get_awareness("copy").unwrap().is_none());
+
+        db.remove_awareness("doc").unwrap();
+        assert!(db.get_awareness("doc").unwrap().is_none());
+        db.insert_awareness("doc", &[6]).unwrap();
+        db.clear_doc("doc").unwrap();
+        assert!(db.get_awareness("doc").unwrap().is_none());
+        db_txn.commit().unwrap();
+    }
+
+    #[test]
+    fn doc_iter_paged() {
+        let cleaner = Cleaner::new("lmdb-doc_iter_paged");
+        let env = init_env(cleaner.dir());
+        let h = env.create_db("yrs", DbCreate).unwrap();
+        let db_txn = env.new_transaction().unwrap();
+        let db = LmdbStore::from(db_txn.bind(&h));
+        for name in ["A", "B", "C", "D", "E"] {
+            db.insert_meta(name, "key", [1].as_ref()).unwrap();
+        }
+
+        let names = |page: Vec<(Box<[u8]>, _)>| -> Vec<Box<[u8]>> {
+            page.into_iter().map(|(name, _)| name).collect()
+        };
+        let page = db.iter_docs_paged(None, 2).unwrap();
+        assert_eq!(page[0].1, 1);
+        assert_eq!(
+            names(page),
+            vec!["A".as_bytes().into(), "B".as_bytes().into()]
+        );
+        let page = db.iter_docs_paged(Some("B".as_bytes()), 2).unwrap();
+        assert_eq!(
+            names(page),
+            vec!["C".as_bytes().into(), "D".as_bytes().into()]
+        );
+        let page = db.iter_docs_paged(Some("D".as_bytes()), 2).unwrap();
+        assert_eq!(names(page), vec!["E".as_bytes().into()]);
+        assert!(db
+            .iter_docs_paged(Some("E".as_bytes()), 2)
+            .unwrap()
+            .is_empty());
+
+        // page may start after a document, which no longer exists
+        db.clear_doc("B").unwrap();
+        let page = db.iter_docs_paged(Some("B".as_bytes()), 10).unwrap();
+        assert_eq!(
+            names(page),
+            vec![
+                "C".as_bytes().into(),
+                "D".as_bytes().into(),
+                "E".as_bytes().into()
+            ]
+        );
+        assert!(db.iter_docs_paged(None, 0).unwrap().is_empty());
+        db_txn.commit().unwrap();
+    }
+
+    #[test]
+    fn named_databases() {
+        let cleaner = Cleaner::new("lmdb-named_databases");
+        let env = init_env(cleaner.dir());
+        let a = LmdbStore::open_db(&env, Some("yrs-a")).unwrap();
+        let b = LmdbStore::open_db(&env, Some("yrs-b")).unwrap();
+        let app = LmdbStore::open_db(&env, Some("app")).unwrap();
+
+        let db_txn = env.new_transaction().unwrap();
+        {
+            // other application data living next to documents
+            let app = db_txn.bind(&app);
+            app.set(&"doc".as_bytes(), &"app-value".as_bytes()).unwrap();
+        }
+        for (h, content) in [(&a, "hello"), (&b, "world")] {
+            let doc = Doc::new();
+            let text = doc.get_or_insert_text("text");
+            text.push(&mut doc.transact_mut(), content);
+            let db = LmdbStore::from(db_txn.bind(h));
+            db.insert_doc("doc", &doc.transact()).unwrap();
+        }
+        db_txn.commit().unwrap();
+
+        let db_txn = env.get_reader().unwrap();
+        for (h, content) in [(&a, "hello"), (&b, "world")] {
+            let db = LmdbStore::from(db_txn.bind(h));
+            let docs: Vec<_> = db.iter_docs().unwrap().collect();
+            assert_eq!(docs, vec!["doc".as_bytes().into()]);
+
+            let doc = Doc::new();
+            let text = doc.get_or_insert_text("text");
+            let mut txn = doc.transact_mut();
+            db.load_doc("doc", &mut txn).unwrap();
+            assert_eq!(text.get_string(&txn), content);
+        }
+        let app = db_txn.bind(&app);
+        let value: &[u8] = app.get(&"doc".as_bytes()).unwrap();
+        assert_eq!(value, "app-value".as_bytes());
+    }
+
     #[test]
     fn doc_iter() {
         let cleaner = Cleaner::new("lmdb-doc_iter");
@@ -565,12 +1718,13 @@ mod test {
             let doc = Doc::new();
             let env = env.clone();
             let h = h.clone();
-            let _sub = doc.observe_update_v1(move |_, u| {
+            doc.observe_update_v1("persist", move |_: &TransactionMut, u| {
                 let db_txn = env.new_transaction().unwrap();
                 let db = LmdbStore::from(db_txn.bind(&h));
                 db.push_update("C", &u.update).unwrap();
                 db_txn.commit().unwrap();
-            });
+            })
+            .unwrap();
             let text = doc.get_or_insert_text("text");
             let mut txn = doc.transact_mut();
             text.push(&mut txn, "hello world");
@@ -0,0 +1,408 @@
+use async_trait::async_trait;
+use futures::TryStreamExt;
+use object_store::path::{Path, PathPart};
+use object_store::{ObjectStore, PutPayload};
+use std::sync::Arc;
+use yrs_kvstore::asynchronous::{AsyncDocOps, AsyncKVStore};
+use yrs_kvstore::keys::{
+    update_clock, ENCODING_V1, ENCODING_V2, KEYSPACE_DOC, OID_LEN, SUB_DOC, SUB_KEY_POS,
+    SUB_UPDATE, V1,
+};
+use yrs_kvstore::KVEntry;
+
+const DIR_DOCS: &str = "docs";
+const DIR_KEYS: &str = "keys";
+const DIR_UPDATES: &str = "updates";
+const OBJ_STATE: &str = "state";
+const SUFFIX_V2: &str = ".v2";
+
+/// Persistence layer for Yrs documents build on top of object storage (i.e. Amazon S3, Google
+/// Cloud Storage or Azure Blob Storage) using [ObjectStore] abstraction. It's meant to be used as
+/// a cheap archive tier for dormant documents rather than a primary database: every entry is
+/// a separate object and range operations need to list objects first.
+///
+/// Objects are laid out as follows:
+///
+/// ```text
+/// {prefix}/docs/{oid}/state            document state
+/// {prefix}/docs/{oid}/updates/{clock}  append log of pending updates, one object per update
+/// {prefix}/docs/{oid}/{hex}            other document entries (i.e. metadata)
+/// {prefix}/keys/{hex}                  entries of other keyspaces (i.e. document names)
+/// ```
+///
+/// Object stores don't offer transactions, so compound [AsyncDocOps] operations are not atomic
+/// and a single prefix must not be written by many stores at the same time.
+///
+/// ```rust
+/// use std::sync::Arc;
+/// use object_store::memory::InMemory;
+/// use yrs_object_store::ObjectKVStore;
+///
+/// let store = ObjectKVStore::new(Arc::new(InMemory::new()), "archive");
+/// ```
+#[derive(Debug, Clone)]
+pub struct ObjectKVStore {
+    store: Arc<dyn ObjectStore>,
+    prefix: Path,
+}
+
+impl ObjectKVStore {
+    /// Creates a new store, which keeps its objects under a given `prefix`.
+    pub fn new<P: Into<Path>>(store: Arc<dyn ObjectStore>, prefix: P) -> Self {
+        ObjectKVStore {
+            store,
+            prefix: prefix.into(),
+        }
+    }
+
+    /// Returns a reference to the underlying object store.
+    pub fn object_store(&self) -> &Arc<dyn ObjectStore> {
+        &self.store
+    }
+
+    /// Returns a prefix of all objects created by current store.
+    pub fn prefix(&self) -> &Path {
+        &self.prefix
+    }
+
+    /// Returns a location of the object, which stores an entry with a given `key`.
+    pub fn object_path(&self, key: &[u8]) -> Path {
+        self.child(key_segments(key))
+    }
+
+    fn child(&self, segments: Vec<String>) -> Path {
+        let prefix = self.prefix.parts().map(|p| p.as_ref().to_string());
+        let parts: Vec<PathPart<'static>> = prefix.chain(segments).map(PathPart::from).collect();
+        parts.into_iter().collect()
+    }
+
+    async fn read(&self, key: &[u8]) -> Result<Option<Vec<u8>>, object_store::Error> {
+        match self.store.get(&self.object_path(key)).await {
+            Ok(result) => Ok(Some(result.bytes().await?.to_vec())),
+            Err(object_store::Error::NotFound { .. }) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Lists keys of all entries stored under a given `dir` (relative to the store prefix).
+    async fn keys_within(&self, dir: Vec<String>) -> Result<Vec<Vec<u8>>, object_store::Error> {
+        let dir = self.child(dir);
+        let objects: Vec<_> = self.store.list(Some(&dir)).try_collect().await?;
+        let keys = objects
+            .into_iter()
+            .filter_map(|meta| {
+                let parts = meta.location.prefix_match(&self.prefix)?;
+                let segments: Vec<String> = parts.map(|part| part.as_ref().to_string()).collect();
+                segments_key(&segments)
+            })
+            .collect();
+        Ok(keys)
+    }
+
+    /// Returns ordered keys of all entries within `from`..=`to` range.
+    async fn keys_in_range(
+        &self,
+        from: &[u8],
+        to: &[u8],
+    ) -> Result<Vec<Vec<u8>>, object_store::Error> {
+        let dir = match (doc_prefix(from), doc_prefix(to)) {
+            // all keys between two keys of the same document belong to that document as well
+            (Some(a), Some(b)) if a == b => doc_dir(a),
+            _ => Vec::new(),
+        };
+        let mut keys: Vec<_> = self
+            .keys_within(dir)
+            .await?
+            .into_iter()
+            .filter(|key| key.as_slice() >= from && key.as_slice() <= to)
+            .collect();
+        keys.sort();
+        Ok(keys)
+    }
+}
+
+/// Returns a prefix shared by all keys of the same document, if a given `key` belongs to
+/// a document keyspace.
+fn doc_prefix(key: &[u8]) -> Option<&[u8]> {
+    if key.len() > SUB_KEY_POS && key[0] == V1 && key[1] == KEYSPACE_DOC {
+        Some(&key[..SUB_KEY_POS])
+    } else {
+        None
+    }
+}
+
+fn doc_dir(prefix: &[u8]) -> Vec<String> {
+    vec![DIR_DOCS.to_string(), to_hex(&prefix[2..])]
+}
+
+/// Maps a given `key` onto segments of an object path (relative to the store prefix).
+fn key_segments(key: &[u8]) -> Vec<String> {
+    let prefix = match doc_prefix(key) {
+        Some(prefix) => prefix,
+        None => return vec![DIR_KEYS.to_string(), to_hex(key)],
+    };
+    let mut segments = doc_dir(prefix);
+    match &key[SUB_KEY_POS..] {
+        [SUB_DOC] => segments.push(OBJ_STATE.to_string()),
+        [SUB_UPDATE, _, _, _, _, ENCODING_V1] => {
+            segments.push(DIR_UPDATES.to_string());
+            segments.push(format!("{:010}", update_clock(key)));
+        }
+        [SUB_UPDATE, _, _, _, _, ENCODING_V2] => {
+            segments.push(DIR_UPDATES.to_string());
+            segments.push(format!("{:010}{}", update_clock(key), SUFFIX_V2));
+        }
+        sub => segments.push(to_hex(sub)),
+    }
+    segments
+}
+
+/// Maps segments of an object path (relative to the store prefix) back onto a key it stores value
+/// of. Returns `None` if the path doesn't belong to any key.
+fn segments_key(segments: &[String]) -> Option<Vec<u8>> {
+    let segments: Vec<&str> = segments.iter().map(String::as_str).collect();
+    match segments.as_slice() {
+        [DIR_KEYS, hex] => from_hex(hex),
+        [DIR_DOCS, oid, rest @ ..] => {
+            let oid = from_hex(oid).filter(|oid| oid.len() == OID_LEN)?;
+            let mut key = vec![V1, KEYSPACE_DOC];
+            key.extend_from_slice(&oid);
+            match rest {
+                [OBJ_STATE] => key.push(SUB_DOC),
+                [DIR_UPDATES, name] => {
+                    let (clock, encoding) = match name.strip_suffix(SUFFIX_V2) {
+                        Some(clock) => (clock, ENCODING_V2),
+                        None => (*name, ENCODING_V1),
+                    };
+                    if clock.len() != 10 {
+                        return None;
+                    }
+                    let clock: u32 = clock.parse().ok()?;
+                    key.push(SUB_UPDATE);
+                    key.extend_from_slice(&clock.to_be_bytes());
+                    key.push(encoding);
+                }
+                [hex] => key.extend_from_slice(&from_hex(hex)?),
+                _ => return None,
+            }
+            Some(key)
+        }
+        _ => None,
+    }
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn from_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) || !hex.is_ascii() {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).ok())
+        .collect()
+}
+
+#[async_trait(?Send)]
+impl<'a> AsyncKVStore<'a> for ObjectKVStore {
+    type Error = object_store::Error;
+    type Cursor = std::vec::IntoIter<ObjectEntry>;
+    type Entry = ObjectEntry;
+    type Return = Vec<u8>;
+
+    async fn get(&self, key: &[u8]) -> Result<Option<Self::Return>, Self::Error> {
+        self.read(key).await
+    }
+
+    async fn upsert(&self, key: &[u8], value: &[u8]) -> Result<(), Self::Error> {
+        let payload = PutPayload::from(value.to_vec());
+        self.store.put(&self.object_path(key), payload).await?;
+        Ok(())
+    }
+
+    async fn remove(&self, key: &[u8]) -> Result<(), Self::Error> {
+        match self.store.delete(&self.object_path(key)).await {
+            Ok(()) | Err(object_store::Error::NotFound { .. }) => Ok(()),
+            Err(e) => Err(e),
+        }
+    }
+
+    async fn remove_range(&self, from: &[u8], to: &[u8]) -> Result<(), Self::Error> {
+        for key in self.keys_in_range(from, to).await? {
+            self.remove(&key).await?;
+        }
+        Ok(())
+    }
+
+    async fn iter_range(&self, from: &[u8], to: &[u8]) -> Result<Self::Cursor, Self::Error> {
+        let mut entries = Vec::new();
+        for key in self.keys_in_range(from, to).await? {
+            if let Some(value) = self.read(&key).await? {
+                entries.push(ObjectEntry::new(key, value));
+            }
+        }
+        Ok(entries.into_iter())
+    }
+
+    async fn peek_back(&self, key: &[u8]) -> Result<Option<Self::Entry>, Self::Error> {
+        let last_before =
+            |keys: Vec<Vec<u8>>| keys.into_iter().filter(|k| k.as_slice() < key).max();
+        let mut last = None;
+        if let Some(prefix) = doc_prefix(key) {
+            // if there's a preceding key of the same document, there's no need to list others
+            last = last_before(self.keys_within(doc_dir(prefix)).await?);
+        }
+        if last.is_none() {
+            last = last_before(self.keys_within(Vec::new()).await?);
+        }
+        match last {
+            Some(key) => Ok(self
+                .read(&key)
+                .await?
+                .map(|value| ObjectEntry::new(key, value))),
+            None => Ok(None),
+        }
+    }
+}
+
+impl<'a> AsyncDocOps<'a> for ObjectKVStore {}
+
+pub struct ObjectEntry {
+    key: Vec<u8>,
+    value: Vec<u8>,
+}
+
+impl ObjectEntry {
+    fn new(key: Vec<u8>, value: Vec<u8>) -> Self {
+        ObjectEntry { key, value }
+    }
+}
+
+impl Into<(Vec<u8>, Vec<u8>)> for ObjectEntry {
+    fn into(self) -> (Vec<u8>, Vec<u8>) {
+        (self.key, self.value)
+    }
+}
+
+impl KVEntry for ObjectEntry {
+    fn key(&self) -> &[u8] {
+        &self.key
+    }
+
+    fn value(&self) -> &[u8] {
+        &self.value
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{key_segments, segments_key, ObjectKVStore};
+    use futures::executor::block_on;
+    use futures::TryStreamExt;
+    use object_store::memory::InMemory;
+    use object_store::ObjectStore;
+    use std::sync::Arc;
+    use yrs::{Doc, GetString, ReadTxn, Text, Transact};
+    use yrs_kvstore::asynchronous::{AsyncDocOps, AsyncKVStore};
+    use yrs_kvstore::keys::{key_doc, key_meta, key_oid, key_update, key_update_v2};
+    use yrs_kvstore::KVEntry;
+
+    #[test]
+    fn key_paths() {
+        let keys = [
+            key_oid(b"doc").to_vec(),
+            key_doc(1).to_vec(),
+            key_update(1, 12).to_vec(),
+            key_update_v2(1, 13).to_vec(),
+            key_meta(1, b"title").to_vec(),
+        ];
+        for key in keys.iter() {
+            let segments = key_segments(key);
+            assert_eq!(
+                segments_key(&segments).as_ref(),
+                Some(key),
+                "{:?}",
+                segments
+            );
+        }
+        assert_eq!(
+            key_segments(&key_update(1, 12)).last().unwrap(),
+            "0000000012"
+        );
+    }
+
+    #[test]
+    fn range_operations() {
+        block_on(async {
+            let store = ObjectKVStore::new(Arc::new(InMemory::new()), "archive");
+            for i in 0u8..10 {
+                store.upsert(&[1, i], &[i]).await.unwrap();
+            }
+            let values: Vec<_> = store
+                .iter_range(&[1, 2], &[1, 5])
+                .await
+                .unwrap()
+                .map(|e| e.value().to_vec())
+                .collect();
+            assert_eq!(values, vec![vec![2], vec![3], vec![4], vec![5]]);
+
+            let last = store.peek_back(&[1, 7]).await.unwrap().unwrap();
+            assert_eq!(last.key(), &[1, 6]);
+            assert!(store.peek_back(&[1, 0]).await.unwrap().is_none());
+
+            store.remove_range(&[1, 0], &[1, 8]).await.unwrap();
+            let keys: Vec<_> = store
+                .iter_range(&[0], &[255])
+                .await
+                .unwrap()
+                .map(|e| e.key().to_vec())
+                .collect();
+            assert_eq!(keys, vec![vec![1, 9]]);
+        });
+    }
+
+    #[test]
+    fn create_get_remove() {
+        block_on(async {
+            let objects = Arc::new(InMemory::new());
+            let store = ObjectKVStore::new(objects.clone(), "archive");
+            let doc = Doc::new();
+            let text = doc.get_or_insert_text("text");
+            text.push(&mut doc.transact_mut(), "hello");
+            store.insert_doc("doc", &doc.transact()).await.unwrap();
+
+            let sv = doc.transact().state_vector();
+            text.push(&mut doc.transact_mut(), " world");
+            let update = doc.transact().encode_diff_v1(&sv);
+            store.push_update("doc", &update).await.unwrap();
+
+            // pending update is stored as a separate object
+            let updates: Vec<_> = objects
+                .list(None)
+                .map_ok(|meta| meta.location.to_string())
+                .try_collect::<Vec<_>>()
+                .await
+                .unwrap()
+                .into_iter()
+                .filter(|path| path.contains("/updates/"))
+                .collect();
+            assert_eq!(updates.len(), 1);
+            assert!(updates[0].ends_with("/updates/0000000001"));
+
+            let loaded = Doc::new();
+            let loaded_text = loaded.get_or_insert_text("text");
+            let found = store
+                .load_doc("doc", &mut loaded.transact_mut())
+                .await
+                .unwrap();
+            assert!(found);
+            assert_eq!(loaded_text.get_string(&loaded.transact()), "hello world");
+
+            store.clear_doc("doc").await.unwrap();
+            let (sv, _) = store.get_state_vector("doc").await.unwrap();
+            assert!(sv.is_none());
+        });
+    }
+}
@@ -0,0 +1,460 @@
+use postgres::Transaction;
+use std::cell::RefCell;
+use std::sync::Arc;
+use yrs_kvstore::codec::{CodecChain, ValueCodec};
+use yrs_kvstore::compression::Compression;
+use yrs_kvstore::error::Error;
+use yrs_kvstore::export::JsonExport;
+use yrs_kvstore::metrics::{Metrics, StoreMetrics};
+use yrs_kvstore::observer::{UpdateObservers, WriteHook};
+use yrs_kvstore::options::StoreOptions;
+use yrs_kvstore::validation::UpdateValidator;
+use yrs_kvstore::{
+    CompactionPolicy, DocOps, KVEntry, KVStore, OidAllocation, RetentionPolicy, UpdateLimits,
+};
+
+/// Persistence layer for Yrs documents build on top of PostgreSQL transaction. All entries are
+/// stored in a single table with `key` and `value` columns of `bytea` type (see:
+/// [PostgresStore::create_table]). Since `bytea` values are compared byte-wise, the order of the
+/// keys is preserved.
+///
+/// OID allocation is serialized using transaction-scoped advisory lock, which makes it safe to
+/// use the same table by multiple concurrent writers.
+pub struct PostgresStore<'a> {
+    txn: RefCell<Transaction<'a>>,
+    table: String,
+    options: StoreOptions,
+    update_observers: UpdateObservers,
+    metrics: Metrics,
+}
+
+impl<'a> PostgresStore<'a> {
+    /// Creates a new store over provided transaction, which persists its entries in a given
+    /// `table`. Table name is used directly in SQL statements, so it must come from a trusted
+    /// source.
+    pub fn new(txn: Transaction<'a>, table: &str) -> Self {
+        PostgresStore {
+            txn: RefCell::new(txn),
+            table: table.to_string(),
+            options: StoreOptions::default(),
+            update_observers: UpdateObservers::default(),
+            metrics: Metrics::default(),
+        }
+    }
+
+    /// Sets [StoreOptions] used to configure current store, replacing all settings set so far.
+    ///
+    /// # Panics
+    ///
+    /// Panics if options can't be applied without wrapping the store with
+    /// [yrs_kvstore::options::ConfiguredStore] (see: [StoreOptions::assert_unscoped]).
+    pub fn with_options(mut self, options: StoreOptions) -> Self {
+        options.assert_unscoped();
+        self.options = options;
+        self
+    }
+
+    /// Sets a [CompactionPolicy] used to automatically flush documents, which have accumulated
+    /// too many pending updates.
+    pub fn with_compaction_policy(mut self, policy: CompactionPolicy) -> Self {
+        self.options.compaction_policy = policy;
+        self
+    }
+
+    /// Sets an [UpdateObservers] registry notified about every persisted document update (see:
+    /// [DocOps::observe_updates]). The same registry can be shared by multiple stores.
+    pub fn with_update_observers(mut self, observers: UpdateObservers) -> Self {
+        self.update_observers = observers;
+        self
+    }
+
+    /// Sets a [Compression] applied to document states and updates written by current store.
+    pub fn with_compression(mut self, compression: Compression) -> Self {
+        self.options.compression = compression;
+        self
+    }
+
+    /// Sets an [OidAllocation] strategy used to assign OIDs to documents persisted for the first
+    /// time by current store.
+    pub fn with_oid_allocation(mut self, allocation: OidAllocation) -> Self {
+        self.options.oid_allocation = allocation;
+        self
+    }
+
+    /// Enables or disables recording document changes in a global change log (see:
+    /// [DocOps::iter_changes]).
+    pub fn with_changelog(mut self, enabled: bool) -> Self {
+        self.options.changelog = enabled;
+        self
+    }
+
+    /// Sets a [Metrics] receiver, which current store reports counts and latencies of its
+    /// operations into (see: [DocOps::metrics]).
+    pub fn with_metrics(mut self, metrics: Metrics) -> Self {
+        self.metrics = metrics;
+        self
+    }
+
+    /// Sets a number of recently pushed updates of each document, which are remembered in order to
+    /// skip persisting byte-identical updates pushed again, i.e. by retrying clients (see:
+    /// [StoreOptions::update_dedup_window]). Deduplication is disabled when set to 0.
+    pub fn with_update_dedup(mut self, window: usize) -> Self {
+        self.options.update_dedup_window = window;
+        self
+    }
+
+    /// Sets a size in bytes, starting from which document states are stored only once in
+    /// a content addressed blob keyspace and referenced from their documents (see:
+    /// [StoreOptions::blob_threshold]). Blob storage is disabled when set to 0.
+    pub fn with_blob_threshold(mut self, threshold: usize) -> Self {
+        self.options.blob_threshold = threshold;
+        self
+    }
+
+    /// Sets a [ValueCodec] used to encode document states, updates and metadata persisted by
+    /// current store (see: [StoreOptions::codecs]). It replaces a [Compression] set on the store.
+    pub fn with_value_codec(mut self, codec: Arc<dyn ValueCodec>) -> Self {
+        self.options.codecs = CodecChain::from(codec);
+        self
+    }
+
+    /// Enables or disables moving documents removed by [DocOps::clear_doc] into a trash, from
+    /// which they can be restored (see: [StoreOptions::soft_delete]).
+    pub fn with_soft_delete(mut self, enabled: bool) -> Self {
+        self.options.soft_delete = enabled;
+        self
+    }
+
+    /// Enables or disables recording timestamps of pushed updates (see:
+    /// [StoreOptions::update_timestamps]).
+    pub fn with_update_timestamps(mut self, enabled: bool) -> Self {
+        self.options.update_timestamps = enabled;
+        self
+    }
+
+    /// Sets a [RetentionPolicy] used to determine which pending updates are merged into the
+    /// document state by [DocOps::enforce_retention].
+    pub fn with_retention_policy(mut self, policy: RetentionPolicy) -> Self {
+        self.options.retention_policy = policy;
+        self
+    }
+
+    /// Sets [UpdateLimits] used to reject oversized updates before they are written (see:
+    /// [StoreOptions::update_limits]).
+    pub fn with_update_limits(mut self, limits: UpdateLimits) -> Self {
+        self.options.update_limits = limits;
+        self
+    }
+
+    /// Sets an [UpdateValidator] called with every update and document state before it's
+    /// persisted, which can veto the write (see: [StoreOptions::update_validator]).
+    pub fn with_update_validator(mut self, validator: Arc<dyn UpdateValidator>) -> Self {
+        self.options.update_validator = Some(validator);
+        self
+    }
+
+    /// Sets a [WriteHook] called with a decoded summary of every change written by current store
+    /// (see: [StoreOptions::write_hook]).
+    pub fn with_write_hook(mut self, hook: Arc<dyn WriteHook>) -> Self {
+        self.options.write_hook = Some(hook);
+        self
+    }
+
+    /// Sets a [JsonExport] used to maintain a cached JSON rendering of documents refreshed every
+    /// time they are flushed (see: [StoreOptions::json_export]).
+    pub fn with_json_export(mut self, export: JsonExport) -> Self {
+        self.options.json_export = Some(Arc::new(export));
+        self
+    }
+
+    /// Creates a table used by current store, if it didn't exist before.
+    pub fn create_table(&self) -> Result<(), postgres::Error> {
+        let sql = format!(
+            "CREATE TABLE IF NOT EXISTS {} (key bytea PRIMARY KEY, value bytea NOT NULL)",
+            self.table
+        );
+        self.txn.borrow_mut().batch_execute(&sql)
+    }
+
+    #[inline(always)]
+    pub fn commit(self) -> Result<(), postgres::Error> {
+        self.txn.into_inner().commit()
+    }
+
+    /// Returns an identifier of advisory lock used for OID allocation. It's unique per table.
+    fn oid_lock_id(&self) -> i64 {
+        // FNV-1a hash of the table name
+        let mut hash: u64 = 0xcbf29ce484222325;
+        for b in self.table.bytes() {
+            hash ^= b as u64;
+            hash = hash.wrapping_mul(0x100000001b3);
+        }
+        hash as i64
+    }
+}
+
+impl<'a> DocOps<'a> for PostgresStore<'a> {
+    fn options(&self) -> &StoreOptions {
+        &self.options
+    }
+
+    fn update_observers(&self) -> Option<&UpdateObservers> {
+        Some(&self.update_observers)
+    }
+
+    fn metrics(&self) -> Option<&dyn StoreMetrics> {
+        self.metrics.get()
+    }
+
+    fn lock_oid_allocation(&self) -> Result<(), Error> {
+        // lock is released automatically once current transaction commits or rolls back
+        let lock_id = self.oid_lock_id();
+        self.txn
+            .borrow_mut()
+            .execute("SELECT pg_advisory_xact_lock($1)", &[&lock_id])?;
+        Ok(())
+    }
+}
+
+impl<'a> KVStore<'a> for PostgresStore<'a> {
+    type Error = postgres::Error;
+    type Cursor = std::vec::IntoIter<PostgresEntry>;
+    type Entry = PostgresEntry;
+    type Return = Vec<u8>;
+
+    fn get(&self, key: &[u8]) -> Result<Option<Self::Return>, Self::Error> {
+        let sql = format!("SELECT value FROM {} WHERE key = $1", self.table);
+        let row = self.txn.borrow_mut().query_opt(sql.as_str(), &[&key])?;
+        Ok(row.map(|row| row.get(0)))
+    }
+
+    fn upsert(&self, key: &[u8], value: &[u8]) -> Result<(), Self::Error> {
+        let sql = format!(
+            "INSERT INTO {} (key, value) VALUES ($1, $2) \
+             ON CONFLICT (key) DO UPDATE SET value = EXCLUDED.value",
+            self.table
+        );
+        self.txn
+            .borrow_mut()
+            .execute(sql.as_str(), &[&key, &value])?;
+        Ok(())
+    }
+
+    fn remove(&self, key: &[u8]) -> Result<(), Self::Error> {
+        let sql = format!("DELETE FROM {} WHERE key = $1", self.table);
+        self.txn.borrow_mut().execute(sql.as_str(), &[&key])?;
+        Ok(())
+    }
+
+    fn remove_range(&self, from: &[u8], to: &[u8]) -> Result<(), Self::Error> {
+        let sql = format!("DELETE FROM {} WHERE key >= $1 AND key <= $2", self.table);
+        self.txn.borrow_mut().execute(sql.as_str(), &[&from, &to])?;
+        Ok(())
+    }
+
+    fn iter_range(&self, from: &[u8], to: &[u8]) -> Result<Self::Cursor, Self::Error> {
+        let sql = format!(
+            "SELECT key, value FROM {} WHERE key >= $1 AND key <= $2 ORDER BY key",
+            self.table
+        );
+        let rows = self.txn.borrow_mut().query(sql.as_str(), &[&from, &to])?;
+        let entries: Vec<_> = rows
+            .into_iter()
+            .map(|row| PostgresEntry::new(row.get(0), row.get(1)))
+            .collect();
+        Ok(entries.into_iter())
+    }
+
+    fn contains_range(&self, from: &[u8], to: &[u8]) -> Result<bool, Self::Error> {
+        let sql = format!(
+            "SELECT 1 FROM {} WHERE key >= $1 AND key <= $2 LIMIT 1",
+            self.table
+        );
+        let row = self
+            .txn
+            .borrow_mut()
+            .query_opt(sql.as_str(), &[&from, &to])?;
+        Ok(row.is_some())
+    }
+
+    fn peek_back(&self, key: &[u8]) -> Result<Option<Self::Entry>, Self::Error> {
+        let sql = format!(
+            "SELECT key, value FROM {} WHERE key < $1 ORDER BY key DESC LIMIT 1",
+            self.table
+        );
+        let row = self.txn.borrow_mut().query_opt(sql.as_str(), &[&key])?;
+        Ok(row.map(|row| PostgresEntry::new(row.get(0), row.get(1))))
+    }
+}
+
+pub struct PostgresEntry {
+    key: Vec<u8>,
+    value: Vec<u8>,
+}
+
+impl PostgresEntry {
+    fn new(key: Vec<u8>, value: Vec<u8>) -> Self {
+        PostgresEntry { key, value }
+    }
+}
+
+impl Into<(Vec<u8>, Vec<u8>)> for PostgresEntry {
+    fn into(self) -> (Vec<u8>, Vec<u8>) {
+        (self.key, self.value)
+    }
+}
+
+impl KVEntry for PostgresEntry {
+    fn key(&self) -> &[u8] {
+        &self.key
+    }
+
+    fn value(&self) -> &[u8] {
+        &self.value
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::PostgresStore;
+    use postgres::{Client, NoTls};
+    use std::sync::{Arc, Barrier};
+    use yrs::{Doc, GetString, ReadTxn, Text, Transact};
+    use yrs_kvstore::DocOps;
+
+    /// Tests require a running PostgreSQL instance. Its connection string can be provided using
+    /// `POSTGRES_URL` environment variable.
+    fn connect() -> Client {
+        let url = std::env::var("POSTGRES_URL")
+            .unwrap_or_else(|_| "host=localhost user=postgres".to_string());
+        Client::connect(&url, NoTls).unwrap()
+    }
+
+    fn init_table(table: &str) -> Client {
+        let mut client = connect();
+        client
+            .batch_execute(&format!("DROP TABLE IF EXISTS {}", table))
+            .unwrap();
+        let db = PostgresStore::new(client.transaction().unwrap(), table);
+        db.create_table().unwrap();
+        db.commit().unwrap();
+        client
+    }
+
+    #[test]
+    #[ignore]
+    fn create_get_remove() {
+        const TABLE: &str = "pg_create_get_remove";
+        let mut client = init_table(TABLE);
+
+        // insert document
+        {
+            let doc = Doc::new();
+            let text = doc.get_or_insert_text("text");
+            let mut txn = doc.transact_mut();
+            text.insert(&mut txn, 0, "hello");
+
+            let db = PostgresStore::new(client.transaction().unwrap(), TABLE);
+            db.insert_doc("doc", &txn).unwrap();
+            db.commit().unwrap();
+        }
+
+        // retrieve document
+        {
+            let doc = Doc::new();
+            let text = doc.get_or_insert_text("text");
+            let mut txn = doc.transact_mut();
+            let db = PostgresStore::new(client.transaction().unwrap(), TABLE);
+            db.load_doc("doc", &mut txn).unwrap();
+
+            assert_eq!(text.get_string(&txn), "hello");
+
+            let (sv, completed) = db.get_state_vector("doc").unwrap();
+            assert_eq!(sv, Some(txn.state_vector()));
+            assert!(completed);
+        }
+
+        // remove document
+        {
+            let db = PostgresStore::new(client.transaction().unwrap(), TABLE);
+            db.clear_doc("doc").unwrap();
+
+            let doc = Doc::new();
+            let text = doc.get_or_insert_text("text");
+            let mut txn = doc.transact_mut();
+            db.load_doc("doc", &mut txn).unwrap();
+
+            assert_eq!(text.get_string(&txn), "");
+
+            let (sv, completed) = db.get_state_vector("doc").unwrap();
+            assert!(sv.is_none());
+            assert!(completed);
+        }
+    }
+
+    #[test]
+    #[ignore]
+    fn incremental_updates() {
+        const TABLE: &str = "pg_incremental_updates";
+        const DOC_NAME: &str = "doc";
+        let mut client = init_table(TABLE);
+
+        // store document updates
+        {
+            let doc = Doc::new();
+            let text = doc.get_or_insert_text("text");
+            for chunk in ["a", "b", "c"] {
+                let sv = doc.transact().state_vector();
+                text.push(&mut doc.transact_mut(), chunk);
+                let update = doc.transact().encode_diff_v1(&sv);
+                let db = PostgresStore::new(client.transaction().unwrap(), TABLE);
+                db.push_update(DOC_NAME, &update).unwrap();
+                db.commit().unwrap();
+            }
+        }
+
+        // flush document
+        {
+            let db = PostgresStore::new(client.transaction().unwrap(), TABLE);
+            let doc = db.flush_doc(DOC_NAME).unwrap().unwrap();
+            db.commit().unwrap();
+
+            let text = doc.get_or_insert_text("text");
+            assert_eq!(text.get_string(&doc.transact()), "abc");
+        }
+    }
+
+    #[test]
+    #[ignore]
+    fn concurrent_oid_allocation() {
+        const TABLE: &str = "pg_concurrent_oid_allocation";
+        const WRITERS: usize = 4;
+        init_table(TABLE);
+
+        let barrier = Arc::new(Barrier::new(WRITERS));
+        let handles: Vec<_> = (0..WRITERS)
+            .map(|i| {
+                let barrier = barrier.clone();
+                std::thread::spawn(move || {
+                    let mut client = connect();
+                    let db = PostgresStore::new(client.transaction().unwrap(), TABLE);
+                    barrier.wait();
+                    db.insert_meta(&format!("doc-{}", i), "key", &[i as u8])
+                        .unwrap();
+                    db.commit().unwrap();
+                })
+            })
+            .collect();
+        for h in handles {
+            h.join().unwrap();
+        }
+
+        // every document must have received its own OID
+        let mut client = connect();
+        let db = PostgresStore::new(client.transaction().unwrap(), TABLE);
+        for i in 0..WRITERS {
+            let value = db.get_meta(&format!("doc-{}", i), "key").unwrap();
+            assert_eq!(value, Some(vec![i as u8]));
+        }
+    }
+}
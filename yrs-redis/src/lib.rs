@@ -0,0 +1,541 @@
+use redis::{ConnectionLike, RedisError};
+use std::cell::RefCell;
+use std::sync::Arc;
+use yrs_kvstore::batch::{BatchOp, WriteBatch};
+use yrs_kvstore::codec::{CodecChain, ValueCodec};
+use yrs_kvstore::compression::Compression;
+use yrs_kvstore::export::JsonExport;
+use yrs_kvstore::metrics::{Metrics, StoreMetrics};
+use yrs_kvstore::observer::{UpdateObservers, WriteHook};
+use yrs_kvstore::options::StoreOptions;
+use yrs_kvstore::validation::UpdateValidator;
+use yrs_kvstore::{
+    CompactionPolicy, DocOps, KVEntry, KVStore, OidAllocation, RetentionPolicy, UpdateLimits,
+};
+
+/// Persistence layer for Yrs documents build on top of Redis. Since Redis doesn't offer ordered
+/// key-value collections, every store is represented by two Redis keys:
+///
+/// - `{namespace}:idx` is a sorted set, which members are the keys of the store. All of them use
+///   the same score, which makes them lexicographically ordered.
+/// - `{namespace}:val` is a hash containing the values of the store.
+///
+/// Every write operation updates both of them within a single `MULTI`/`EXEC` block. This also
+/// applies to write batches (see: [KVStore::commit_batch]).
+pub struct RedisStore<C> {
+    conn: RefCell<C>,
+    index_key: Vec<u8>,
+    values_key: Vec<u8>,
+    options: StoreOptions,
+    update_observers: UpdateObservers,
+    metrics: Metrics,
+}
+
+impl<C: ConnectionLike> RedisStore<C> {
+    /// Creates a new store over provided Redis connection, which keeps all of its entries under
+    /// provided `namespace`.
+    pub fn new<N: AsRef<[u8]> + ?Sized>(conn: C, namespace: &N) -> Self {
+        let namespace = namespace.as_ref();
+        let mut index_key = namespace.to_vec();
+        index_key.extend_from_slice(b":idx");
+        let mut values_key = namespace.to_vec();
+        values_key.extend_from_slice(b":val");
+        RedisStore {
+            conn: RefCell::new(conn),
+            index_key,
+            values_key,
+            options: StoreOptions::default(),
+            update_observers: UpdateObservers::default(),
+            metrics: Metrics::default(),
+        }
+    }
+
+    /// Sets [StoreOptions] used to configure current store, replacing all settings set so far.
+    ///
+    /// # Panics
+    ///
+    /// Panics if options can't be applied without wrapping the store with
+    /// [yrs_kvstore::options::ConfiguredStore] (see: [StoreOptions::assert_unscoped]).
+    pub fn with_options(mut self, options: StoreOptions) -> Self {
+        options.assert_unscoped();
+        self.options = options;
+        self
+    }
+
+    /// Sets a [CompactionPolicy] used to automatically flush documents, which have accumulated
+    /// too many pending updates.
+    pub fn with_compaction_policy(mut self, policy: CompactionPolicy) -> Self {
+        self.options.compaction_policy = policy;
+        self
+    }
+
+    /// Sets an [UpdateObservers] registry notified about every persisted document update (see:
+    /// [DocOps::observe_updates]). The same registry can be shared by multiple stores.
+    pub fn with_update_observers(mut self, observers: UpdateObservers) -> Self {
+        self.update_observers = observers;
+        self
+    }
+
+    /// Sets a [Compression] applied to document states and updates written by current store.
+    pub fn with_compression(mut self, compression: Compression) -> Self {
+        self.options.compression = compression;
+        self
+    }
+
+    /// Sets an [OidAllocation] strategy used to assign OIDs to documents persisted for the first
+    /// time by current store.
+    pub fn with_oid_allocation(mut self, allocation: OidAllocation) -> Self {
+        self.options.oid_allocation = allocation;
+        self
+    }
+
+    /// Enables or disables recording document changes in a global change log (see:
+    /// [DocOps::iter_changes]).
+    pub fn with_changelog(mut self, enabled: bool) -> Self {
+        self.options.changelog = enabled;
+        self
+    }
+
+    /// Sets a [Metrics] receiver, which current store reports counts and latencies of its
+    /// operations into (see: [DocOps::metrics]).
+    pub fn with_metrics(mut self, metrics: Metrics) -> Self {
+        self.metrics = metrics;
+        self
+    }
+
+    /// Sets a number of recently pushed updates of each document, which are remembered in order to
+    /// skip persisting byte-identical updates pushed again, i.e. by retrying clients (see:
+    /// [StoreOptions::update_dedup_window]). Deduplication is disabled when set to 0.
+    pub fn with_update_dedup(mut self, window: usize) -> Self {
+        self.options.update_dedup_window = window;
+        self
+    }
+
+    /// Sets a size in bytes, starting from which document states are stored only once in
+    /// a content addressed blob keyspace and referenced from their documents (see:
+    /// [StoreOptions::blob_threshold]). Blob storage is disabled when set to 0.
+    pub fn with_blob_threshold(mut self, threshold: usize) -> Self {
+        self.options.blob_threshold = threshold;
+        self
+    }
+
+    /// Sets a [ValueCodec] used to encode document states, updates and metadata persisted by
+    /// current store (see: [StoreOptions::codecs]). It replaces a [Compression] set on the store.
+    pub fn with_value_codec(mut self, codec: Arc<dyn ValueCodec>) -> Self {
+        self.options.codecs = CodecChain::from(codec);
+        self
+    }
+
+    /// Enables or disables moving documents removed by [DocOps::clear_doc] into a trash, from
+    /// which they can be restored (see: [StoreOptions::soft_delete]).
+    pub fn with_soft_delete(mut self, enabled: bool) -> Self {
+        self.options.soft_delete = enabled;
+        self
+    }
+
+    /// Enables or disables recording timestamps of pushed updates (see:
+    /// [StoreOptions::update_timestamps]).
+    pub fn with_update_timestamps(mut self, enabled: bool) -> Self {
+        self.options.update_timestamps = enabled;
+        self
+    }
+
+    /// Sets a [RetentionPolicy] used to determine which pending updates are merged into the
+    /// document state by [DocOps::enforce_retention].
+    pub fn with_retention_policy(mut self, policy: RetentionPolicy) -> Self {
+        self.options.retention_policy = policy;
+        self
+    }
+
+    /// Sets [UpdateLimits] used to reject oversized updates before they are written (see:
+    /// [StoreOptions::update_limits]).
+    pub fn with_update_limits(mut self, limits: UpdateLimits) -> Self {
+        self.options.update_limits = limits;
+        self
+    }
+
+    /// Sets an [UpdateValidator] called with every update and document state before it's
+    /// persisted, which can veto the write (see: [StoreOptions::update_validator]).
+    pub fn with_update_validator(mut self, validator: Arc<dyn UpdateValidator>) -> Self {
+        self.options.update_validator = Some(validator);
+        self
+    }
+
+    /// Sets a [WriteHook] called with a decoded summary of every change written by current store
+    /// (see: [StoreOptions::write_hook]).
+    pub fn with_write_hook(mut self, hook: Arc<dyn WriteHook>) -> Self {
+        self.options.write_hook = Some(hook);
+        self
+    }
+
+    /// Sets a [JsonExport] used to maintain a cached JSON rendering of documents refreshed every
+    /// time they are flushed (see: [StoreOptions::json_export]).
+    pub fn with_json_export(mut self, export: JsonExport) -> Self {
+        self.options.json_export = Some(Arc::new(export));
+        self
+    }
+
+    /// Returns an underlying Redis connection.
+    pub fn into_inner(self) -> C {
+        self.conn.into_inner()
+    }
+
+    fn keys_in_range(&self, from: &[u8], to: &[u8]) -> Result<Vec<Vec<u8>>, RedisError> {
+        redis::cmd("ZRANGEBYLEX")
+            .arg(&self.index_key)
+            .arg(lex_bound(b'[', from))
+            .arg(lex_bound(b'[', to))
+            .query(&mut *self.conn.borrow_mut())
+    }
+}
+
+impl<'a, C: ConnectionLike> DocOps<'a> for RedisStore<C> {
+    fn options(&self) -> &StoreOptions {
+        &self.options
+    }
+
+    fn update_observers(&self) -> Option<&UpdateObservers> {
+        Some(&self.update_observers)
+    }
+
+    fn metrics(&self) -> Option<&dyn StoreMetrics> {
+        self.metrics.get()
+    }
+}
+
+impl<'a, C: ConnectionLike> KVStore<'a> for RedisStore<C> {
+    type Error = RedisError;
+    type Cursor = std::vec::IntoIter<RedisEntry>;
+    type Entry = RedisEntry;
+    type Return = Vec<u8>;
+
+    fn get(&self, key: &[u8]) -> Result<Option<Self::Return>, Self::Error> {
+        redis::cmd("HGET")
+            .arg(&self.values_key)
+            .arg(key)
+            .query(&mut *self.conn.borrow_mut())
+    }
+
+    fn upsert(&self, key: &[u8], value: &[u8]) -> Result<(), Self::Error> {
+        redis::pipe()
+            .atomic()
+            .cmd("ZADD")
+            .arg(&self.index_key)
+            .arg(0)
+            .arg(key)
+            .ignore()
+            .cmd("HSET")
+            .arg(&self.values_key)
+            .arg(key)
+            .arg(value)
+            .ignore()
+            .query(&mut *self.conn.borrow_mut())
+    }
+
+    fn remove(&self, key: &[u8]) -> Result<(), Self::Error> {
+        redis::pipe()
+            .atomic()
+            .cmd("ZREM")
+            .arg(&self.index_key)
+            .arg(key)
+            .ignore()
+            .cmd("HDEL")
+            .arg(&self.values_key)
+            .arg(key)
+            .ignore()
+            .query(&mut *self.conn.borrow_mut())
+    }
+
+    fn remove_range(&self, from: &[u8], to: &[u8]) -> Result<(), Self::Error> {
+        let keys = self.keys_in_range(from, to)?;
+        if keys.is_empty() {
+            return Ok(());
+        }
+        redis::pipe()
+            .atomic()
+            .cmd("ZREMRANGEBYLEX")
+            .arg(&self.index_key)
+            .arg(lex_bound(b'[', from))
+            .arg(lex_bound(b'[', to))
+            .ignore()
+            .cmd("HDEL")
+            .arg(&self.values_key)
+            .arg(keys)
+            .ignore()
+            .query(&mut *self.conn.borrow_mut())
+    }
+
+    fn iter_range(&self, from: &[u8], to: &[u8]) -> Result<Self::Cursor, Self::Error> {
+        let keys = self.keys_in_range(from, to)?;
+        if keys.is_empty() {
+            return Ok(Vec::new().into_iter());
+        }
+        let values: Vec<Option<Vec<u8>>> = redis::cmd("HMGET")
+            .arg(&self.values_key)
+            .arg(&keys)
+            .query(&mut *self.conn.borrow_mut())?;
+        let entries: Vec<_> = keys
+            .into_iter()
+            .zip(values)
+            .filter_map(|(key, value)| Some(RedisEntry::new(key, value?)))
+            .collect();
+        Ok(entries.into_iter())
+    }
+
+    fn contains_range(&self, from: &[u8], to: &[u8]) -> Result<bool, Self::Error> {
+        let keys: Vec<Vec<u8>> = redis::cmd("ZRANGEBYLEX")
+            .arg(&self.index_key)
+            .arg(lex_bound(b'[', from))
+            .arg(lex_bound(b'[', to))
+            .arg("LIMIT")
+            .arg(0)
+            .arg(1)
+            .query(&mut *self.conn.borrow_mut())?;
+        Ok(!keys.is_empty())
+    }
+
+    fn peek_back(&self, key: &[u8]) -> Result<Option<Self::Entry>, Self::Error> {
+        let keys: Vec<Vec<u8>> = redis::cmd("ZREVRANGEBYLEX")
+            .arg(&self.index_key)
+            .arg(lex_bound(b'(', key))
+            .arg("-")
+            .arg("LIMIT")
+            .arg(0)
+            .arg(1)
+            .query(&mut *self.conn.borrow_mut())?;
+        if let Some(key) = keys.into_iter().next() {
+            if let Some(value) = self.get(&key)? {
+                return Ok(Some(RedisEntry::new(key, value)));
+            }
+        }
+        Ok(None)
+    }
+
+    fn commit_batch(&self, batch: WriteBatch) -> Result<(), Self::Error> {
+        let mut pipe = redis::pipe();
+        pipe.atomic();
+        // keys inserted by current batch, they are not visible to range queries yet
+        let mut inserted: Vec<Vec<u8>> = Vec::new();
+        for op in batch {
+            match op {
+                BatchOp::Upsert(key, value) => {
+                    pipe.cmd("ZADD")
+                        .arg(&self.index_key)
+                        .arg(0)
+                        .arg(&key)
+                        .ignore()
+                        .cmd("HSET")
+                        .arg(&self.values_key)
+                        .arg(&key)
+                        .arg(value)
+                        .ignore();
+                    inserted.push(key);
+                }
+                BatchOp::Remove(key) => {
+                    pipe.cmd("ZREM")
+                        .arg(&self.index_key)
+                        .arg(&key)
+                        .ignore()
+                        .cmd("HDEL")
+                        .arg(&self.values_key)
+                        .arg(&key)
+                        .ignore();
+                }
+                BatchOp::RemoveRange(from, to) => {
+                    let mut keys = self.keys_in_range(&from, &to)?;
+                    keys.extend(
+                        inserted
+                            .iter()
+                            .filter(|key| **key >= from && **key <= to)
+                            .cloned(),
+                    );
+                    if !keys.is_empty() {
+                        pipe.cmd("ZREMRANGEBYLEX")
+                            .arg(&self.index_key)
+                            .arg(lex_bound(b'[', &from))
+                            .arg(lex_bound(b'[', &to))
+                            .ignore()
+                            .cmd("HDEL")
+                            .arg(&self.values_key)
+                            .arg(keys)
+                            .ignore();
+                    }
+                }
+            }
+        }
+        pipe.query(&mut *self.conn.borrow_mut())
+    }
+}
+
+/// Constructs a boundary argument for Redis lexicographical range commands (i.e. `ZRANGEBYLEX`).
+/// `prefix` must be either `[` (inclusive) or `(` (exclusive).
+fn lex_bound(prefix: u8, key: &[u8]) -> Vec<u8> {
+    let mut bound = Vec::with_capacity(key.len() + 1);
+    bound.push(prefix);
+    bound.extend_from_slice(key);
+    bound
+}
+
+pub struct RedisEntry {
+    key: Vec<u8>,
+    value: Vec<u8>,
+}
+
+impl RedisEntry {
+    fn new(key: Vec<u8>, value: Vec<u8>) -> Self {
+        RedisEntry { key, value }
+    }
+}
+
+impl Into<(Vec<u8>, Vec<u8>)> for RedisEntry {
+    fn into(self) -> (Vec<u8>, Vec<u8>) {
+        (self.key, self.value)
+    }
+}
+
+impl KVEntry for RedisEntry {
+    fn key(&self) -> &[u8] {
+        &self.key
+    }
+
+    fn value(&self) -> &[u8] {
+        &self.value
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::RedisStore;
+    use redis::Connection;
+    use yrs::{Doc, GetString, ReadTxn, StateVector, Text, Transact};
+    use yrs_kvstore::DocOps;
+
+    /// Tests require a running Redis instance. Its address can be provided using `REDIS_URL`
+    /// environment variable.
+    fn init_store(namespace: &str) -> RedisStore<Connection> {
+        let url = std::env::var("REDIS_URL").unwrap_or_else(|_| "redis://127.0.0.1/".to_string());
+        let client = redis::Client::open(url).unwrap();
+        let mut conn = client.get_connection().unwrap();
+        redis::cmd("DEL")
+            .arg(format!("{}:idx", namespace))
+            .arg(format!("{}:val", namespace))
+            .query::<()>(&mut conn)
+            .unwrap();
+        RedisStore::new(conn, namespace)
+    }
+
+    #[test]
+    #[ignore]
+    fn create_get_remove() {
+        let db = init_store("redis-create_get_remove");
+
+        // insert document
+        {
+            let doc = Doc::new();
+            let text = doc.get_or_insert_text("text");
+            let mut txn = doc.transact_mut();
+            text.insert(&mut txn, 0, "hello");
+            db.insert_doc("doc", &txn).unwrap();
+        }
+
+        // retrieve document
+        {
+            let doc = Doc::new();
+            let text = doc.get_or_insert_text("text");
+            let mut txn = doc.transact_mut();
+            db.load_doc("doc", &mut txn).unwrap();
+
+            assert_eq!(text.get_string(&txn), "hello");
+
+            let (sv, completed) = db.get_state_vector("doc").unwrap();
+            assert_eq!(sv, Some(txn.state_vector()));
+            assert!(completed);
+        }
+
+        // remove document
+        {
+            db.clear_doc("doc").unwrap();
+
+            let doc = Doc::new();
+            let text = doc.get_or_insert_text("text");
+            let mut txn = doc.transact_mut();
+            db.load_doc("doc", &mut txn).unwrap();
+
+            assert_eq!(text.get_string(&txn), "");
+
+            let (sv, completed) = db.get_state_vector("doc").unwrap();
+            assert!(sv.is_none());
+            assert!(completed);
+        }
+    }
+
+    #[test]
+    #[ignore]
+    fn incremental_updates() {
+        const DOC_NAME: &str = "doc";
+        let db = init_store("redis-incremental_updates");
+
+        // store document updates
+        {
+            let doc = Doc::new();
+            let text = doc.get_or_insert_text("text");
+            for chunk in ["a", "b", "c"] {
+                let sv = doc.transact().state_vector();
+                text.push(&mut doc.transact_mut(), chunk);
+                let update = doc.transact().encode_diff_v1(&sv);
+                db.push_update(DOC_NAME, &update).unwrap();
+            }
+        }
+
+        // load document
+        {
+            let doc = Doc::new();
+            let text = doc.get_or_insert_text("text");
+            let mut txn = doc.transact_mut();
+            db.load_doc(DOC_NAME, &mut txn).unwrap();
+
+            assert_eq!(text.get_string(&txn), "abc");
+        }
+
+        // flush document
+        {
+            let doc = db.flush_doc(DOC_NAME).unwrap().unwrap();
+            let text = doc.get_or_insert_text("text");
+
+            assert_eq!(text.get_string(&doc.transact()), "abc");
+
+            let (sv, completed) = db.get_state_vector(DOC_NAME).unwrap();
+            assert_eq!(sv, Some(doc.transact().state_vector()));
+            assert!(completed);
+        }
+    }
+
+    #[test]
+    #[ignore]
+    fn doc_iter() {
+        let db = init_store("redis-doc_iter");
+
+        db.insert_meta("A", "key1", [1].as_ref()).unwrap();
+        {
+            let doc = Doc::new();
+            let text = doc.get_or_insert_text("text");
+            text.push(&mut doc.transact_mut(), "hello world");
+            db.insert_doc("B", &doc.transact()).unwrap();
+            let update = doc.transact().encode_diff_v1(&StateVector::default());
+            db.push_update("C", &update).unwrap();
+        }
+
+        let mut i = db.iter_docs().unwrap();
+        assert_eq!(i.next(), Some("A".as_bytes().into()));
+        assert_eq!(i.next(), Some("B".as_bytes().into()));
+        assert_eq!(i.next(), Some("C".as_bytes().into()));
+        assert!(i.next().is_none());
+
+        db.clear_doc("B").unwrap();
+
+        let mut i = db.iter_docs().unwrap();
+        assert_eq!(i.next(), Some("A".as_bytes().into()));
+        assert_eq!(i.next(), Some("C".as_bytes().into()));
+        assert!(i.next().is_none());
+    }
+}
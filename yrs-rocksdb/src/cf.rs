@@ -0,0 +1,320 @@
+use crate::{upper_bound, RocksDBEntry, RocksDBIter};
+use rocksdb::{
+    ColumnFamily, ColumnFamilyDescriptor, DBPinnableSlice, Direction, IteratorMode, Options,
+    ReadOptions, SliceTransform, Transaction,
+};
+use std::sync::Arc;
+use yrs_kvstore::codec::{CodecChain, ValueCodec};
+use yrs_kvstore::compression::Compression;
+use yrs_kvstore::export::JsonExport;
+use yrs_kvstore::keys::{KEYSPACE_OID, SUB_KEY_POS};
+use yrs_kvstore::metrics::{Metrics, StoreMetrics};
+use yrs_kvstore::observer::{UpdateObservers, WriteHook};
+use yrs_kvstore::options::StoreOptions;
+use yrs_kvstore::validation::UpdateValidator;
+use yrs_kvstore::{
+    CompactionPolicy, DocOps, KVStore, OidAllocation, RetentionPolicy, UpdateLimits,
+};
+
+/// Name of a column family used to store document name to OID mappings.
+pub const OID_CF: &str = "yrs-oid";
+
+/// Name of a column family used to store document states, updates and metadata.
+pub const DOC_CF: &str = "yrs-doc";
+
+/// Length of a key prefix shared by all entries belonging to the same document:
+/// `{version:1}{keyspace:1}{oid}`.
+pub const DOC_PREFIX_LEN: usize = SUB_KEY_POS;
+
+/// Returns descriptors of column families used by [RocksDBCFStore]. They should be used when
+/// opening a database, i.e. via [rocksdb::TransactionDB::open_cf_descriptors].
+pub fn column_families() -> Vec<ColumnFamilyDescriptor> {
+    let mut doc_opts = Options::default();
+    doc_opts.set_prefix_extractor(SliceTransform::create_fixed_prefix(DOC_PREFIX_LEN));
+    vec![
+        ColumnFamilyDescriptor::new(OID_CF, Options::default()),
+        ColumnFamilyDescriptor::new(DOC_CF, doc_opts),
+    ]
+}
+
+/// RocksDB store, which keeps OID and document keyspaces in separate column families (see:
+/// [column_families]). Since both keyspaces have different access patterns, this gives better
+/// compaction behavior than keeping everything in a single default column family.
+///
+/// Range iteration over entries of a single document uses prefix iterators.
+pub struct RocksDBCFStore<'a, DB> {
+    txn: Transaction<'a, DB>,
+    oid_cf: &'a ColumnFamily,
+    doc_cf: &'a ColumnFamily,
+    options: StoreOptions,
+    update_observers: UpdateObservers,
+    metrics: Metrics,
+}
+
+impl<'a, DB> RocksDBCFStore<'a, DB> {
+    pub fn new(
+        txn: Transaction<'a, DB>,
+        oid_cf: &'a ColumnFamily,
+        doc_cf: &'a ColumnFamily,
+    ) -> Self {
+        RocksDBCFStore {
+            txn,
+            oid_cf,
+            doc_cf,
+            options: StoreOptions::default(),
+            update_observers: UpdateObservers::default(),
+            metrics: Metrics::default(),
+        }
+    }
+
+    #[inline(always)]
+    pub fn commit(self) -> Result<(), rocksdb::Error> {
+        self.txn.commit()
+    }
+
+    /// Sets [StoreOptions] used to configure current store, replacing all settings set so far.
+    ///
+    /// # Panics
+    ///
+    /// Panics if options can't be applied without wrapping the store with
+    /// [yrs_kvstore::options::ConfiguredStore] (see: [StoreOptions::assert_unscoped]).
+    pub fn with_options(mut self, options: StoreOptions) -> Self {
+        options.assert_unscoped();
+        self.options = options;
+        self
+    }
+
+    /// Sets a [CompactionPolicy] used to automatically flush documents, which have accumulated
+    /// too many pending updates.
+    pub fn with_compaction_policy(mut self, policy: CompactionPolicy) -> Self {
+        self.options.compaction_policy = policy;
+        self
+    }
+
+    /// Sets an [UpdateObservers] registry notified about every persisted document update (see:
+    /// [DocOps::observe_updates]). The same registry can be shared by multiple stores.
+    pub fn with_update_observers(mut self, observers: UpdateObservers) -> Self {
+        self.update_observers = observers;
+        self
+    }
+
+    /// Sets a [Compression] applied to document states and updates written by current store.
+    pub fn with_compression(mut self, compression: Compression) -> Self {
+        self.options.compression = compression;
+        self
+    }
+
+    /// Sets an [OidAllocation] strategy used to assign OIDs to documents persisted for the first
+    /// time by current store.
+    pub fn with_oid_allocation(mut self, allocation: OidAllocation) -> Self {
+        self.options.oid_allocation = allocation;
+        self
+    }
+
+    /// Enables or disables recording document changes in a global change log (see:
+    /// [DocOps::iter_changes]).
+    pub fn with_changelog(mut self, enabled: bool) -> Self {
+        self.options.changelog = enabled;
+        self
+    }
+
+    /// Sets a [Metrics] receiver, which current store reports counts and latencies of its
+    /// operations into (see: [DocOps::metrics]).
+    pub fn with_metrics(mut self, metrics: Metrics) -> Self {
+        self.metrics = metrics;
+        self
+    }
+
+    /// Sets a number of recently pushed updates of each document, which are remembered in order to
+    /// skip persisting byte-identical updates pushed again, i.e. by retrying clients (see:
+    /// [StoreOptions::update_dedup_window]). Deduplication is disabled when set to 0.
+    pub fn with_update_dedup(mut self, window: usize) -> Self {
+        self.options.update_dedup_window = window;
+        self
+    }
+
+    /// Sets a size in bytes, starting from which document states are stored only once in
+    /// a content addressed blob keyspace and referenced from their documents (see:
+    /// [StoreOptions::blob_threshold]). Blob storage is disabled when set to 0.
+    pub fn with_blob_threshold(mut self, threshold: usize) -> Self {
+        self.options.blob_threshold = threshold;
+        self
+    }
+
+    /// Sets a [ValueCodec] used to encode document states, updates and metadata persisted by
+    /// current store (see: [StoreOptions::codecs]). It replaces a [Compression] set on the store.
+    pub fn with_value_codec(mut self, codec: Arc<dyn ValueCodec>) -> Self {
+        self.options.codecs = CodecChain::from(codec);
+        self
+    }
+
+    /// Enables or disables moving documents removed by [DocOps::clear_doc] into a trash, from
+    /// which they can be restored (see: [StoreOptions::soft_delete]).
+    pub fn with_soft_delete(mut self, enabled: bool) -> Self {
+        self.options.soft_delete = enabled;
+        self
+    }
+
+    /// Enables or disables recording timestamps of pushed updates (see:
+    /// [StoreOptions::update_timestamps]).
+    pub fn with_update_timestamps(mut self, enabled: bool) -> Self {
+        self.options.update_timestamps = enabled;
+        self
+    }
+
+    /// Sets a [RetentionPolicy] used to determine which pending updates are merged into the
+    /// document state by [DocOps::enforce_retention].
+    pub fn with_retention_policy(mut self, policy: RetentionPolicy) -> Self {
+        self.options.retention_policy = policy;
+        self
+    }
+
+    /// Sets [UpdateLimits] used to reject oversized updates before they are written (see:
+    /// [StoreOptions::update_limits]).
+    pub fn with_update_limits(mut self, limits: UpdateLimits) -> Self {
+        self.options.update_limits = limits;
+        self
+    }
+
+    /// Sets an [UpdateValidator] called with every update and document state before it's
+    /// persisted, which can veto the write (see: [StoreOptions::update_validator]).
+    pub fn with_update_validator(mut self, validator: Arc<dyn UpdateValidator>) -> Self {
+        self.options.update_validator = Some(validator);
+        self
+    }
+
+    /// Sets a [WriteHook] called with a decoded summary of every change written by current store
+    /// (see: [StoreOptions::write_hook]).
+    pub fn with_write_hook(mut self, hook: Arc<dyn WriteHook>) -> Self {
+        self.options.write_hook = Some(hook);
+        self
+    }
+
+    /// Sets a [JsonExport] used to maintain a cached JSON rendering of documents refreshed every
+    /// time they are flushed (see: [StoreOptions::json_export]).
+    pub fn with_json_export(mut self, export: JsonExport) -> Self {
+        self.options.json_export = Some(Arc::new(export));
+        self
+    }
+
+    fn cf(&self, key: &[u8]) -> &'a ColumnFamily {
+        if key.get(1) == Some(&KEYSPACE_OID) {
+            self.oid_cf
+        } else {
+            self.doc_cf
+        }
+    }
+}
+
+impl<'a, DB> DocOps<'a> for RocksDBCFStore<'a, DB> {
+    fn options(&self) -> &StoreOptions {
+        &self.options
+    }
+
+    fn update_observers(&self) -> Option<&UpdateObservers> {
+        Some(&self.update_observers)
+    }
+
+    fn metrics(&self) -> Option<&dyn StoreMetrics> {
+        self.metrics.get()
+    }
+}
+
+impl<'a, DB> KVStore<'a> for RocksDBCFStore<'a, DB> {
+    type Error = rocksdb::Error;
+    type Cursor = RocksDBIter<'a, DB>;
+    type Entry = RocksDBEntry;
+    type Return = DBPinnableSlice<'a>;
+
+    fn get(&self, key: &[u8]) -> Result<Option<Self::Return>, Self::Error> {
+        if let Some(pinned) = self.txn.get_pinned_cf(self.cf(key), key)? {
+            Ok(Some(unsafe { std::mem::transmute(pinned) }))
+        } else {
+            Ok(None)
+        }
+    }
+
+    fn upsert(&self, key: &[u8], value: &[u8]) -> Result<(), Self::Error> {
+        self.txn.put_cf(self.cf(key), key, value)
+    }
+
+    fn remove(&self, key: &[u8]) -> Result<(), Self::Error> {
+        self.txn.delete_cf(self.cf(key), key)
+    }
+
+    fn remove_range(&self, from: &[u8], to: &[u8]) -> Result<(), Self::Error> {
+        let cf = self.cf(from);
+        let opt = range_options(from, to);
+        let mut i = self
+            .txn
+            .iterator_cf_opt(cf, opt, IteratorMode::From(from, Direction::Forward));
+        while let Some(res) = i.next() {
+            let (key, _) = res?;
+            self.txn.delete_cf(cf, key)?;
+        }
+        Ok(())
+    }
+
+    fn iter_range(&self, from: &[u8], to: &[u8]) -> Result<Self::Cursor, Self::Error> {
+        let opt = range_options(from, to);
+        let raw = self.txn.iterator_cf_opt(
+            self.cf(from),
+            opt,
+            IteratorMode::From(from, Direction::Forward),
+        );
+        Ok(RocksDBIter::new(
+            unsafe { std::mem::transmute(raw) },
+            to.to_vec(),
+        ))
+    }
+
+    fn contains_range(&self, from: &[u8], to: &[u8]) -> Result<bool, Self::Error> {
+        let opt = range_options(from, to);
+        let mut raw = self.txn.raw_iterator_cf_opt(self.cf(from), opt);
+        raw.seek(from);
+        raw.status()?;
+        Ok(raw.valid())
+    }
+
+    fn peek_back(&self, key: &[u8]) -> Result<Option<Self::Entry>, Self::Error> {
+        let cf = self.cf(key);
+        let mut opt = ReadOptions::default();
+        opt.set_total_order_seek(true);
+        let mut raw = self.txn.raw_iterator_cf_opt(cf, opt);
+        raw.seek_for_prev(key);
+        if raw.key() == Some(key) {
+            // seek_for_prev positions cursor at the key itself if it exists
+            raw.prev();
+        }
+        if let Some((key, value)) = raw.item() {
+            return Ok(Some(RocksDBEntry::new(key.into(), value.into())));
+        }
+        if !std::ptr::eq(cf, self.oid_cf) {
+            // OID keyspace precedes the document keyspace
+            let mut raw = self
+                .txn
+                .raw_iterator_cf_opt(self.oid_cf, ReadOptions::default());
+            raw.seek_to_last();
+            if let Some((key, value)) = raw.item() {
+                return Ok(Some(RocksDBEntry::new(key.into(), value.into())));
+            }
+        }
+        Ok(None)
+    }
+}
+
+fn range_options(from: &[u8], to: &[u8]) -> ReadOptions {
+    let mut opt = ReadOptions::default();
+    opt.set_iterate_lower_bound(from);
+    opt.set_iterate_upper_bound(upper_bound(to));
+    if from.len() >= DOC_PREFIX_LEN
+        && to.len() >= DOC_PREFIX_LEN
+        && from[..DOC_PREFIX_LEN] == to[..DOC_PREFIX_LEN]
+    {
+        // range within a single document
+        opt.set_prefix_same_as_start(true);
+    } else {
+        opt.set_total_order_seek(true);
+    }
+    opt
+}
@@ -1,30 +1,177 @@
+pub mod cf;
+
+pub use cf::RocksDBCFStore;
 use rocksdb::{
     DBIteratorWithThreadMode, DBPinnableSlice, Direction, IteratorMode, ReadOptions, Transaction,
 };
 use std::ops::Deref;
-use yrs_kvstore::{DocOps, KVEntry, KVStore};
+use std::sync::Arc;
+use yrs_kvstore::codec::{CodecChain, ValueCodec};
+use yrs_kvstore::compression::Compression;
+use yrs_kvstore::export::JsonExport;
+use yrs_kvstore::metrics::{Metrics, StoreMetrics};
+use yrs_kvstore::observer::{UpdateObservers, WriteHook};
+use yrs_kvstore::options::StoreOptions;
+use yrs_kvstore::validation::UpdateValidator;
+use yrs_kvstore::{
+    CompactionPolicy, DocOps, KVEntry, KVStore, OidAllocation, RetentionPolicy, UpdateLimits,
+};
 
-#[repr(transparent)]
-pub struct RocksDBStore<'a, DB>(Transaction<'a, DB>);
+pub struct RocksDBStore<'a, DB> {
+    txn: Transaction<'a, DB>,
+    options: StoreOptions,
+    update_observers: UpdateObservers,
+    metrics: Metrics,
+}
 
 impl<'a, DB> RocksDBStore<'a, DB> {
     #[inline(always)]
     pub fn commit(self) -> Result<(), rocksdb::Error> {
-        self.0.commit()
+        self.txn.commit()
+    }
+
+    /// Sets [StoreOptions] used to configure current store, replacing all settings set so far.
+    ///
+    /// # Panics
+    ///
+    /// Panics if options can't be applied without wrapping the store with
+    /// [yrs_kvstore::options::ConfiguredStore] (see: [StoreOptions::assert_unscoped]).
+    pub fn with_options(mut self, options: StoreOptions) -> Self {
+        options.assert_unscoped();
+        self.options = options;
+        self
+    }
+
+    /// Sets a [CompactionPolicy] used to automatically flush documents, which have accumulated
+    /// too many pending updates.
+    pub fn with_compaction_policy(mut self, policy: CompactionPolicy) -> Self {
+        self.options.compaction_policy = policy;
+        self
+    }
+
+    /// Sets an [UpdateObservers] registry notified about every persisted document update (see:
+    /// [DocOps::observe_updates]). The same registry can be shared by multiple stores.
+    pub fn with_update_observers(mut self, observers: UpdateObservers) -> Self {
+        self.update_observers = observers;
+        self
+    }
+
+    /// Sets a [Compression] applied to document states and updates written by current store.
+    pub fn with_compression(mut self, compression: Compression) -> Self {
+        self.options.compression = compression;
+        self
+    }
+
+    /// Sets an [OidAllocation] strategy used to assign OIDs to documents persisted for the first
+    /// time by current store.
+    pub fn with_oid_allocation(mut self, allocation: OidAllocation) -> Self {
+        self.options.oid_allocation = allocation;
+        self
+    }
+
+    /// Enables or disables recording document changes in a global change log (see:
+    /// [DocOps::iter_changes]).
+    pub fn with_changelog(mut self, enabled: bool) -> Self {
+        self.options.changelog = enabled;
+        self
+    }
+
+    /// Sets a [Metrics] receiver, which current store reports counts and latencies of its
+    /// operations into (see: [DocOps::metrics]).
+    pub fn with_metrics(mut self, metrics: Metrics) -> Self {
+        self.metrics = metrics;
+        self
+    }
+
+    /// Sets a number of recently pushed updates of each document, which are remembered in order to
+    /// skip persisting byte-identical updates pushed again, i.e. by retrying clients (see:
+    /// [StoreOptions::update_dedup_window]). Deduplication is disabled when set to 0.
+    pub fn with_update_dedup(mut self, window: usize) -> Self {
+        self.options.update_dedup_window = window;
+        self
+    }
+
+    /// Sets a size in bytes, starting from which document states are stored only once in
+    /// a content addressed blob keyspace and referenced from their documents (see:
+    /// [StoreOptions::blob_threshold]). Blob storage is disabled when set to 0.
+    pub fn with_blob_threshold(mut self, threshold: usize) -> Self {
+        self.options.blob_threshold = threshold;
+        self
+    }
+
+    /// Sets a [ValueCodec] used to encode document states, updates and metadata persisted by
+    /// current store (see: [StoreOptions::codecs]). It replaces a [Compression] set on the store.
+    pub fn with_value_codec(mut self, codec: Arc<dyn ValueCodec>) -> Self {
+        self.options.codecs = CodecChain::from(codec);
+        self
+    }
+
+    /// Enables or disables moving documents removed by [DocOps::clear_doc] into a trash, from
+    /// which they can be restored (see: [StoreOptions::soft_delete]).
+    pub fn with_soft_delete(mut self, enabled: bool) -> Self {
+        self.options.soft_delete = enabled;
+        self
+    }
+
+    /// Enables or disables recording timestamps of pushed updates (see:
+    /// [StoreOptions::update_timestamps]).
+    pub fn with_update_timestamps(mut self, enabled: bool) -> Self {
+        self.options.update_timestamps = enabled;
+        self
+    }
+
+    /// Sets a [RetentionPolicy] used to determine which pending updates are merged into the
+    /// document state by [DocOps::enforce_retention].
+    pub fn with_retention_policy(mut self, policy: RetentionPolicy) -> Self {
+        self.options.retention_policy = policy;
+        self
+    }
+
+    /// Sets [UpdateLimits] used to reject oversized updates before they are written (see:
+    /// [StoreOptions::update_limits]).
+    pub fn with_update_limits(mut self, limits: UpdateLimits) -> Self {
+        self.options.update_limits = limits;
+        self
+    }
+
+    /// Sets an [UpdateValidator] called with every update and document state before it's
+    /// persisted, which can veto the write (see: [StoreOptions::update_validator]).
+    pub fn with_update_validator(mut self, validator: Arc<dyn UpdateValidator>) -> Self {
+        self.options.update_validator = Some(validator);
+        self
+    }
+
+    /// Sets a [WriteHook] called with a decoded summary of every change written by current store
+    /// (see: [StoreOptions::write_hook]).
+    pub fn with_write_hook(mut self, hook: Arc<dyn WriteHook>) -> Self {
+        self.options.write_hook = Some(hook);
+        self
+    }
+
+    /// Sets a [JsonExport] used to maintain a cached JSON rendering of documents refreshed every
+    /// time they are flushed (see: [StoreOptions::json_export]).
+    pub fn with_json_export(mut self, export: JsonExport) -> Self {
+        self.options.json_export = Some(Arc::new(export));
+        self
     }
 }
 
 impl<'a, DB> From<Transaction<'a, DB>> for RocksDBStore<'a, DB> {
     #[inline(always)]
     fn from(txn: Transaction<'a, DB>) -> Self {
-        RocksDBStore(txn)
+        RocksDBStore {
+            txn,
+            options: StoreOptions::default(),
+            update_observers: UpdateObservers::default(),
+            metrics: Metrics::default(),
+        }
     }
 }
 
 impl<'a, DB> Into<Transaction<'a, DB>> for RocksDBStore<'a, DB> {
     #[inline(always)]
     fn into(self) -> Transaction<'a, DB> {
-        self.0
+        self.txn
     }
 }
 
@@ -33,11 +180,23 @@ impl<'a, DB> Deref for RocksDBStore<'a, DB> {
 
     #[inline(always)]
     fn deref(&self) -> &Self::Target {
-        &self.0
+        &self.txn
     }
 }
 
-impl<'a, DB> DocOps<'a> for RocksDBStore<'a, DB> {}
+impl<'a, DB> DocOps<'a> for RocksDBStore<'a, DB> {
+    fn options(&self) -> &StoreOptions {
+        &self.options
+    }
+
+    fn update_observers(&self) -> Option<&UpdateObservers> {
+        Some(&self.update_observers)
+    }
+
+    fn metrics(&self) -> Option<&dyn StoreMetrics> {
+        self.metrics.get()
+    }
+}
 
 impl<'a, DB> KVStore<'a> for RocksDBStore<'a, DB> {
     type Error = rocksdb::Error;
@@ -46,7 +205,7 @@ impl<'a, DB> KVStore<'a> for RocksDBStore<'a, DB> {
     type Return = DBPinnableSlice<'a>;
 
     fn get(&self, key: &[u8]) -> Result<Option<Self::Return>, Self::Error> {
-        if let Some(pinned) = self.0.get_pinned(key)? {
+        if let Some(pinned) = self.txn.get_pinned(key)? {
             Ok(Some(unsafe { std::mem::transmute(pinned) }))
         } else {
             Ok(None)
@@ -54,25 +213,25 @@ impl<'a, DB> KVStore<'a> for RocksDBStore<'a, DB> {
     }
 
     fn upsert(&self, key: &[u8], value: &[u8]) -> Result<(), Self::Error> {
-        self.0.put(key, value)?;
+        self.txn.put(key, value)?;
         Ok(())
     }
 
     fn remove(&self, key: &[u8]) -> Result<(), Self::Error> {
-        self.0.delete(key)?;
+        self.txn.delete(key)?;
         Ok(())
     }
 
     fn remove_range(&self, from: &[u8], to: &[u8]) -> Result<(), Self::Error> {
         let mut opt = ReadOptions::default();
         opt.set_iterate_lower_bound(from);
-        opt.set_iterate_upper_bound(to);
+        opt.set_iterate_upper_bound(upper_bound(to));
         let mut i = self
             .0
             .iterator_opt(IteratorMode::From(from, Direction::Forward), opt);
         while let Some(res) = i.next() {
             let (key, _) = res?;
-            self.0.delete(key)?;
+            self.txn.delete(key)?;
         }
         Ok(())
     }
@@ -80,7 +239,7 @@ impl<'a, DB> KVStore<'a> for RocksDBStore<'a, DB> {
     fn iter_range(&self, from: &[u8], to: &[u8]) -> Result<Self::Cursor, Self::Error> {
         let mut opt = ReadOptions::default();
         opt.set_iterate_lower_bound(from);
-        opt.set_iterate_upper_bound(to);
+        opt.set_iterate_upper_bound(upper_bound(to));
         let raw = self
             .0
             .iterator_opt(IteratorMode::From(from, Direction::Forward), opt);
@@ -90,10 +249,24 @@ impl<'a, DB> KVStore<'a> for RocksDBStore<'a, DB> {
         ))
     }
 
+    fn contains_range(&self, from: &[u8], to: &[u8]) -> Result<bool, Self::Error> {
+        let mut opt = ReadOptions::default();
+        opt.set_iterate_lower_bound(from);
+        opt.set_iterate_upper_bound(upper_bound(to));
+        let mut raw = self.txn.raw_iterator_opt(opt);
+        raw.seek(from);
+        raw.status()?;
+        Ok(raw.valid())
+    }
+
     fn peek_back(&self, key: &[u8]) -> Result<Option<Self::Entry>, Self::Error> {
         let opt = ReadOptions::default();
-        let mut raw = self.0.raw_iterator_opt(opt);
+        let mut raw = self.txn.raw_iterator_opt(opt);
         raw.seek_for_prev(key);
+        if raw.key() == Some(key) {
+            // seek_for_prev positions cursor at the key itself if it exists
+            raw.prev();
+        }
         if let Some((key, value)) = raw.item() {
             Ok(Some(RocksDBEntry::new(key.into(), value.into())))
         } else {
@@ -102,6 +275,15 @@ impl<'a, DB> KVStore<'a> for RocksDBStore<'a, DB> {
     }
 }
 
+/// Returns an exclusive upper bound for RocksDB iterators, which makes the provided key `to`
+/// inclusive: the smallest key greater than `to` is `to` followed by zero byte.
+fn upper_bound(to: &[u8]) -> Vec<u8> {
+    let mut bound = Vec::with_capacity(to.len() + 1);
+    bound.extend_from_slice(to);
+    bound.push(0);
+    bound
+}
+
 pub struct RocksDBIter<'a, DB> {
     inner: DBIteratorWithThreadMode<'a, Transaction<'a, DB>>,
     to: Vec<u8>,
@@ -119,7 +301,7 @@ impl<'a, DB> Iterator for RocksDBIter<'a, DB> {
     fn next(&mut self) -> Option<Self::Item> {
         let n = self.inner.next()?;
         if let Ok((key, value)) = n {
-            if key.as_ref() >= &self.to {
+            if key.as_ref() > &self.to {
                 None
             } else {
                 Some(RocksDBEntry::new(key, value))
@@ -159,11 +341,19 @@ impl KVEntry for RocksDBEntry {
 
 #[cfg(test)]
 mod test {
-    use crate::RocksDBStore;
-    use rocksdb::TransactionDB;
-    use std::sync::Arc;
-    use yrs::{Doc, GetString, ReadTxn, Text, Transact};
-    use yrs_kvstore::DocOps;
+    use crate::cf::{column_families, DOC_CF, OID_CF};
+    use crate::{RocksDBCFStore, RocksDBStore};
+    use rocksdb::{Options, TransactionDB, TransactionDBOptions};
+    use std::sync::{Arc, Mutex};
+    use yrs::updates::decoder::Decode;
+    use yrs::updates::encoder::Encode;
+    use yrs::{Doc, GetString, Map, ReadTxn, StateVector, Text, Transact, Update};
+    use yrs_kvstore::batch::MultiDocBatch;
+    use yrs_kvstore::checksum::SEALED_HEADER_LEN;
+    use yrs_kvstore::keys::SCHEMA_VERSION;
+    use yrs_kvstore::observer::UpdateObservers;
+    use yrs_kvstore::testing::verify_kvstore_contract;
+    use yrs_kvstore::{CompactionPolicy, DocOps};
 
     struct Cleaner(&'static str);
 
@@ -327,6 +517,146 @@ mod test {
         }
     }
 
+    #[test]
+    fn mixed_encoding_updates() {
+        const DOC_NAME: &str = "doc";
+        let cleaner = Cleaner::new("rocksdb-mixed_encoding_updates");
+        let db = init_env(cleaner.dir());
+
+        let (sv, expected) = {
+            let doc = Doc::new();
+            let text = doc.get_or_insert_text("text");
+            text.push(&mut doc.transact_mut(), "a");
+
+            let db_txn = RocksDBStore::from(db.transaction());
+            {
+                let txn = doc.transact();
+                let doc_state = txn.encode_diff_v2(&StateVector::default());
+                let state_vector = txn.state_vector().encode_v2();
+                db_txn
+                    .insert_doc_raw_v2(DOC_NAME.as_bytes(), &doc_state, &state_vector)
+                    .unwrap();
+            }
+
+            let sv = doc.transact().state_vector();
+            text.push(&mut doc.transact_mut(), "b");
+            db_txn
+                .push_update(DOC_NAME, &doc.transact().encode_diff_v1(&sv))
+                .unwrap();
+            let sv2 = doc.transact().state_vector();
+            text.push(&mut doc.transact_mut(), "c");
+            db_txn
+                .push_update_v2(DOC_NAME, &doc.transact().encode_diff_v2(&sv2))
+                .unwrap();
+            db_txn.commit().unwrap();
+
+            let update = doc.transact().encode_diff_v2(&sv);
+            (sv, update)
+        };
+
+        // load document
+        {
+            let doc = Doc::new();
+            let text = doc.get_or_insert_text("text");
+            let mut txn = doc.transact_mut();
+
+            let db_txn = RocksDBStore::from(db.transaction());
+            db_txn.load_doc(DOC_NAME, &mut txn).unwrap();
+
+            assert_eq!(text.get_string(&txn), "abc");
+
+            let actual = db_txn.get_diff_v2(DOC_NAME, &sv).unwrap();
+            assert_eq!(actual, Some(expected));
+        }
+
+        // flush document
+        {
+            let db_txn = RocksDBStore::from(db.transaction());
+            let doc = db_txn.flush_doc(DOC_NAME).unwrap().unwrap();
+            db_txn.commit().unwrap();
+
+            let text = doc.get_or_insert_text("text");
+            assert_eq!(text.get_string(&doc.transact()), "abc");
+        }
+    }
+
+    #[test]
+    fn batch_updates() {
+        const DOC_NAME: &str = "doc";
+        let cleaner = Cleaner::new("rocksdb-batch_updates");
+        let db = init_env(cleaner.dir());
+
+        // buffer document updates
+        let updates = {
+            let doc = Doc::new();
+            let text = doc.get_or_insert_text("text");
+            let mut updates = Vec::new();
+            for chunk in ["a", "b", "c"] {
+                let sv = doc.transact().state_vector();
+                text.push(&mut doc.transact_mut(), chunk);
+                updates.push(doc.transact().encode_diff_v1(&sv));
+            }
+            updates
+        };
+
+        {
+            let db_txn = RocksDBStore::from(db.transaction());
+            let last = db_txn
+                .push_updates(DOC_NAME, updates.iter().map(Vec::as_slice))
+                .unwrap();
+            assert_eq!(last, 3);
+            let next = db_txn.push_update(DOC_NAME, &updates[0]).unwrap();
+            assert_eq!(next, 4);
+            db_txn.commit().unwrap();
+        }
+
+        {
+            let doc = Doc::new();
+            let text = doc.get_or_insert_text("text");
+            let mut txn = doc.transact_mut();
+
+            let db_txn = RocksDBStore::from(db.transaction());
+            db_txn.load_doc(DOC_NAME, &mut txn).unwrap();
+
+            assert_eq!(text.get_string(&txn), "abc");
+        }
+    }
+
+    #[test]
+    fn auto_compaction() {
+        const DOC_NAME: &str = "doc";
+        let cleaner = Cleaner::new("rocksdb-auto_compaction");
+        let db = init_env(cleaner.dir());
+        let policy = CompactionPolicy {
+            max_pending_updates: Some(2),
+            ..CompactionPolicy::default()
+        };
+
+        let doc = Doc::new();
+        let text = doc.get_or_insert_text("text");
+        let db_txn = RocksDBStore::from(db.transaction()).with_compaction_policy(policy);
+        for chunk in ["a", "b"] {
+            let sv = doc.transact().state_vector();
+            text.push(&mut doc.transact_mut(), chunk);
+            db_txn
+                .push_update(DOC_NAME, &doc.transact().encode_diff_v1(&sv))
+                .unwrap();
+        }
+        let (sv, completed) = db_txn.get_state_vector(DOC_NAME).unwrap();
+        assert!(sv.is_none());
+        assert!(!completed);
+
+        // 3rd update exceeds the limit of pending updates
+        let sv = doc.transact().state_vector();
+        text.push(&mut doc.transact_mut(), "c");
+        db_txn
+            .push_update(DOC_NAME, &doc.transact().encode_diff_v1(&sv))
+            .unwrap();
+        let (sv, completed) = db_txn.get_state_vector(DOC_NAME).unwrap();
+        assert_eq!(sv, Some(doc.transact().state_vector()));
+        assert!(completed);
+    }
+
     #[test]
     fn state_vector_updates_only() {
         const DOC_NAME: &str = "doc";
@@ -464,6 +794,677 @@ mod test {
         assert!(i.next().is_none());
     }
 
+    #[test]
+    fn doc_meta_range() {
+        let cleaner = Cleaner::new("rocksdb-doc_meta_range");
+        let db = init_env(cleaner.dir());
+        let db_txn = RocksDBStore::from(db.transaction());
+
+        for key in [
+            "cursor:alice",
+            "cursor:bob",
+            "cursor:carol",
+            "owner",
+            "title",
+        ] {
+            db_txn.insert_meta("A", key, key.as_bytes()).unwrap();
+        }
+        db_txn
+            .insert_meta("B", "cursor:dave", [1].as_ref())
+            .unwrap();
+
+        let keys: Vec<_> = db_txn
+            .iter_meta_prefix("A", "cursor:")
+            .unwrap()
+            .map(|(k, _)| k)
+            .collect();
+        let expected: Vec<Box<[u8]>> = vec![
+            "cursor:alice".as_bytes().into(),
+            "cursor:bob".as_bytes().into(),
+            "cursor:carol".as_bytes().into(),
+        ];
+        assert_eq!(keys, expected);
+
+        // range bounds are inclusive
+        let keys: Vec<_> = db_txn
+            .iter_meta_range("A", "cursor:bob", "owner")
+            .unwrap()
+            .map(|(k, _)| k)
+            .collect();
+        let expected: Vec<Box<[u8]>> = vec![
+            "cursor:bob".as_bytes().into(),
+            "cursor:carol".as_bytes().into(),
+            "owner".as_bytes().into(),
+        ];
+        assert_eq!(keys, expected);
+
+        assert_eq!(db_txn.iter_meta_prefix("A", "").unwrap().count(), 5);
+        assert!(db_txn.iter_meta_prefix("A", "x").unwrap().next().is_none());
+        assert!(db_txn.iter_meta_prefix("C", "").unwrap().next().is_none());
+    }
+
+    #[test]
+    fn doc_snapshots() {
+        const DOC_NAME: &str = "doc";
+        let cleaner = Cleaner::new("rocksdb-doc_snapshots");
+        let db = init_env(cleaner.dir());
+
+        {
+            let doc = Doc::new();
+            let text = doc.get_or_insert_text("text");
+            text.push(&mut doc.transact_mut(), "a");
+
+            let db_txn = RocksDBStore::from(db.transaction());
+            db_txn.insert_doc(DOC_NAME, &doc.transact()).unwrap();
+            let snapshot = db_txn.create_snapshot(DOC_NAME, "v1").unwrap();
+            assert!(snapshot.is_some());
+
+            let sv = doc.transact().state_vector();
+            text.push(&mut doc.transact_mut(), "b");
+            db_txn
+                .push_update(DOC_NAME, &doc.transact().encode_diff_v1(&sv))
+                .unwrap();
+            db_txn.commit().unwrap();
+        }
+
+        {
+            let db_txn = RocksDBStore::from(db.transaction());
+
+            let doc = Doc::new();
+            let text = doc.get_or_insert_text("text");
+            let mut txn = doc.transact_mut();
+            assert!(db_txn.load_snapshot(DOC_NAME, "v1", &mut txn).unwrap());
+            assert_eq!(text.get_string(&txn), "a");
+
+            let mut i = db_txn.iter_snapshots(DOC_NAME).unwrap();
+            assert_eq!(i.next(), Some("v1".as_bytes().into()));
+            assert!(i.next().is_none());
+        }
+
+        {
+            let db_txn = RocksDBStore::from(db.transaction());
+            db_txn.remove_snapshot(DOC_NAME, "v1").unwrap();
+            assert!(db_txn.get_snapshot(DOC_NAME, "v1").unwrap().is_none());
+
+            let doc = Doc::new();
+            let text = doc.get_or_insert_text("text");
+            let mut txn = doc.transact_mut();
+            assert!(!db_txn.load_snapshot(DOC_NAME, "v1", &mut txn).unwrap());
+            db_txn.load_doc(DOC_NAME, &mut txn).unwrap();
+            assert_eq!(text.get_string(&txn), "ab");
+        }
+    }
+
+    #[test]
+    fn kvstore_contract() {
+        let cleaner = Cleaner::new("rocksdb-kvstore_contract");
+        let db = init_env(cleaner.dir());
+        let db_txn = RocksDBStore::from(db.transaction());
+        verify_kvstore_contract(&db_txn);
+    }
+
+    #[test]
+    fn observe_updates() {
+        const DOC_NAME: &str = "doc";
+        let cleaner = Cleaner::new("rocksdb-observe_updates");
+        let db = init_env(cleaner.dir());
+        let observers = UpdateObservers::new();
+        let events = Arc::new(Mutex::new(Vec::new()));
+
+        let doc = Doc::new();
+        let text = doc.get_or_insert_text("text");
+        text.push(&mut doc.transact_mut(), "a");
+
+        // observers are shared between store instances
+        let _sub = {
+            let db_txn =
+                RocksDBStore::from(db.transaction()).with_update_observers(observers.clone());
+            let events = events.clone();
+            db_txn
+                .observe_updates(DOC_NAME, move |e| {
+                    events.lock().unwrap().push((e.seq, e.update.to_vec()));
+                })
+                .unwrap()
+        };
+
+        let mut expected = Vec::new();
+        {
+            let db_txn =
+                RocksDBStore::from(db.transaction()).with_update_observers(observers.clone());
+            db_txn.insert_doc(DOC_NAME, &doc.transact()).unwrap();
+            expected.push((None, doc.transact().encode_diff_v1(&StateVector::default())));
+            for chunk in ["b", "c"] {
+                let sv = doc.transact().state_vector();
+                text.push(&mut doc.transact_mut(), chunk);
+                let update = doc.transact().encode_diff_v1(&sv);
+                let seq = db_txn.push_update(DOC_NAME, &update).unwrap();
+                expected.push((Some(seq), update));
+            }
+            // updates of other documents are not observed
+            db_txn.push_update("other", &expected[1].1).unwrap();
+            db_txn.commit().unwrap();
+        }
+
+        assert_eq!(*events.lock().unwrap(), expected);
+    }
+
+    #[test]
+    fn update_history() {
+        const DOC_NAME: &str = "doc";
+        let cleaner = Cleaner::new("rocksdb-update_history");
+        let db = init_env(cleaner.dir());
+        let policy = CompactionPolicy {
+            retain_history: true,
+            ..CompactionPolicy::default()
+        };
+
+        let doc = Doc::new();
+        let text = doc.get_or_insert_text("text");
+        for chunks in [["a", "b"], ["c", "d"]] {
+            let db_txn = RocksDBStore::from(db.transaction()).with_compaction_policy(policy);
+            for chunk in chunks {
+                let sv = doc.transact().state_vector();
+                text.push(&mut doc.transact_mut(), chunk);
+                let update = doc.transact().encode_diff_v1(&sv);
+                db_txn.push_update(DOC_NAME, &update).unwrap();
+            }
+            db_txn.flush_doc(DOC_NAME).unwrap().unwrap();
+            db_txn.commit().unwrap();
+        }
+
+        let db_txn = RocksDBStore::from(db.transaction()).with_compaction_policy(policy);
+        let history: Vec<_> = db_txn.iter_history(DOC_NAME).unwrap().collect();
+        let seqs: Vec<_> = history.iter().map(|e| e.seq).collect();
+        assert_eq!(seqs, vec![1, 2, 3, 4]);
+
+        // reconstruct intermediate document state
+        let restored = Doc::new();
+        let restored_text = restored.get_or_insert_text("text");
+        for e in &history[..3] {
+            assert!(!e.v2);
+            let update = Update::decode_v1(&e.update).unwrap();
+            restored.transact_mut().apply_update(update).unwrap();
+        }
+        assert_eq!(restored_text.get_string(&restored.transact()), "abc");
+
+        db_txn.trim_history(DOC_NAME, 2).unwrap();
+        let seqs: Vec<_> = db_txn
+            .iter_history(DOC_NAME)
+            .unwrap()
+            .map(|e| e.seq)
+            .collect();
+        assert_eq!(seqs, vec![3, 4]);
+    }
+
+    #[test]
+    fn subdocs() {
+        let cleaner = Cleaner::new("rocksdb-subdocs");
+        let db = init_env(cleaner.dir());
+
+        // insert parent document and its subdocument
+        let guid = {
+            let doc = Doc::new();
+            let map = doc.get_or_insert_map("map");
+            let subdoc = map.insert(&mut doc.transact_mut(), "sub", Doc::new());
+            let text = subdoc.get_or_insert_text("text");
+            text.push(&mut subdoc.transact_mut(), "hello");
+
+            let db_txn = RocksDBStore::from(db.transaction());
+            db_txn.insert_doc("parent", &doc.transact()).unwrap();
+            db_txn.insert_subdoc("parent", &subdoc).unwrap();
+            db_txn.commit().unwrap();
+            subdoc.guid()
+        };
+
+        // load parent document together with its subdocuments
+        {
+            let doc = Doc::new();
+            let db_txn = RocksDBStore::from(db.transaction());
+            db_txn.load_doc("parent", &mut doc.transact_mut()).unwrap();
+            let loaded = db_txn.load_subdocs("parent", &doc.transact()).unwrap();
+            assert_eq!(loaded, 1);
+
+            let subdoc = doc.transact().subdocs().next().unwrap().clone();
+            assert_eq!(subdoc.guid(), guid);
+            let text = subdoc.get_or_insert_text("text");
+            assert_eq!(text.get_string(&subdoc.transact()), "hello");
+
+            let guids: Vec<_> = db_txn.iter_subdocs("parent").unwrap().collect();
+            assert_eq!(guids, vec![guid.as_bytes().into()]);
+        }
+
+        // removing parent document removes its subdocuments
+        {
+            let db_txn = RocksDBStore::from(db.transaction());
+            db_txn.clear_doc("parent").unwrap();
+            let doc = Doc::new();
+            assert!(!db_txn
+                .load_subdoc("parent", &guid, &mut doc.transact_mut())
+                .unwrap());
+        }
+    }
+
+    #[test]
+    fn purge_doc() {
+        const DOC_NAME: &str = "doc";
+        let cleaner = Cleaner::new("rocksdb-purge_doc");
+        let db = init_env(cleaner.dir());
+
+        let db_txn = RocksDBStore::from(db.transaction());
+        let doc = Doc::new();
+        let text = doc.get_or_insert_text("text");
+        text.push(&mut doc.transact_mut(), "a");
+        db_txn.insert_doc(DOC_NAME, &doc.transact()).unwrap();
+        for chunk in ["b", "c"] {
+            let sv = doc.transact().state_vector();
+            text.push(&mut doc.transact_mut(), chunk);
+            let update = doc.transact().encode_diff_v1(&sv);
+            db_txn.push_update(DOC_NAME, &update).unwrap();
+        }
+        db_txn.insert_meta(DOC_NAME, "key", &[1]).unwrap();
+        db_txn.create_snapshot(DOC_NAME, "s1").unwrap();
+
+        let stats = db_txn.purge_doc(DOC_NAME, true).unwrap().unwrap();
+        assert_eq!(stats.state, 2);
+        assert_eq!(stats.updates, 2);
+        assert_eq!(stats.meta, 1);
+        assert_eq!(stats.snapshots, 1);
+        assert_eq!(stats.total(), 6);
+
+        assert!(db_txn.iter_docs().unwrap().next().is_none());
+        assert!(db_txn.purge_doc(DOC_NAME, true).unwrap().is_none());
+    }
+
+    #[test]
+    fn rename_doc() {
+        let cleaner = Cleaner::new("rocksdb-rename_doc");
+        let db = init_env(cleaner.dir());
+
+        let db_txn = RocksDBStore::from(db.transaction());
+        let doc = Doc::new();
+        let text = doc.get_or_insert_text("text");
+        text.push(&mut doc.transact_mut(), "hello");
+        db_txn.insert_doc("A", &doc.transact()).unwrap();
+        db_txn.insert_meta("A", "key", &[1]).unwrap();
+        db_txn.insert_meta("B", "key", &[2]).unwrap();
+
+        assert!(db_txn.rename_doc("A", "C").unwrap());
+        assert!(!db_txn.rename_doc("A", "D").unwrap());
+        assert!(db_txn.rename_doc("C", "B").is_err());
+
+        let mut i = db_txn.iter_docs().unwrap();
+        assert_eq!(i.next(), Some("B".as_bytes().into()));
+        assert_eq!(i.next(), Some("C".as_bytes().into()));
+        assert!(i.next().is_none());
+
+        let doc = Doc::new();
+        let text = doc.get_or_insert_text("text");
+        assert!(db_txn.load_doc("C", &mut doc.transact_mut()).unwrap());
+        assert_eq!(text.get_string(&doc.transact()), "hello");
+        let meta = db_txn.get_meta("C", "key").unwrap().unwrap();
+        assert_eq!(meta, vec![1]);
+    }
+
+    #[test]
+    fn copy_doc() {
+        let cleaner = Cleaner::new("rocksdb-copy_doc");
+        let db = init_env(cleaner.dir());
+
+        let db_txn = RocksDBStore::from(db.transaction());
+        let doc = Doc::new();
+        let text = doc.get_or_insert_text("text");
+        text.push(&mut doc.transact_mut(), "hello");
+        db_txn.insert_doc("A", &doc.transact()).unwrap();
+        let sv = doc.transact().state_vector();
+        text.push(&mut doc.transact_mut(), " world");
+        let update = doc.transact().encode_diff_v1(&sv);
+        db_txn.push_update("A", &update).unwrap();
+        db_txn.insert_meta("A", "key", &[1]).unwrap();
+
+        assert!(db_txn.copy_doc("A", "B", true).unwrap());
+        assert!(db_txn.copy_doc("A", "C", false).unwrap());
+        assert!(!db_txn.copy_doc("missing", "D", false).unwrap());
+        assert!(db_txn.copy_doc("A", "C", false).is_err());
+
+        // copies are independent from the source document
+        db_txn.clear_doc("A").unwrap();
+
+        for name in ["B", "C"] {
+            let doc = Doc::new();
+            let text = doc.get_or_insert_text("text");
+            assert!(db_txn.load_doc(name, &mut doc.transact_mut()).unwrap());
+            assert_eq!(text.get_string(&doc.transact()), "hello world");
+        }
+        assert!(db_txn.get_meta("B", "key").unwrap().is_some());
+        assert!(db_txn.get_meta("C", "key").unwrap().is_none());
+    }
+
+    #[test]
+    fn flush_doc_gc() {
+        const DOC_NAME: &str = "doc";
+        let cleaner = Cleaner::new("rocksdb-flush_doc_gc");
+        let db = init_env(cleaner.dir());
+
+        let db_txn = RocksDBStore::from(db.transaction());
+        let doc = Doc::with_options(yrs::Options {
+            skip_gc: true,
+            ..yrs::Options::default()
+        });
+        let text = doc.get_or_insert_text("text");
+        text.push(&mut doc.transact_mut(), "hello world, this will be removed");
+        text.remove_range(&mut doc.transact_mut(), 5, 28);
+        db_txn.insert_doc(DOC_NAME, &doc.transact()).unwrap();
+
+        let stats = db_txn.flush_doc_gc(DOC_NAME).unwrap().unwrap();
+        assert!(stats.reclaimed() > 0);
+
+        let doc = Doc::new();
+        let text = doc.get_or_insert_text("text");
+        db_txn.load_doc(DOC_NAME, &mut doc.transact_mut()).unwrap();
+        assert_eq!(text.get_string(&doc.transact()), "hello");
+        assert!(db_txn.flush_doc_gc("missing").unwrap().is_none());
+    }
+
+    #[test]
+    fn doc_stats() {
+        const DOC_NAME: &str = "doc";
+        let cleaner = Cleaner::new("rocksdb-doc_stats");
+        let db = init_env(cleaner.dir());
+
+        let db_txn = RocksDBStore::from(db.transaction());
+        assert!(db_txn.doc_stats(DOC_NAME).unwrap().is_none());
+
+        let doc = Doc::new();
+        let text = doc.get_or_insert_text("text");
+        text.push(&mut doc.transact_mut(), "hello");
+        let doc_state = doc.transact().encode_diff_v1(&StateVector::default());
+        db_txn.insert_doc(DOC_NAME, &doc.transact()).unwrap();
+        let mut update_bytes = 0;
+        for chunk in ["a", "b"] {
+            let sv = doc.transact().state_vector();
+            text.push(&mut doc.transact_mut(), chunk);
+            let update = doc.transact().encode_diff_v1(&sv);
+            update_bytes += update.len() as u64;
+            db_txn.push_update(DOC_NAME, &update).unwrap();
+        }
+        db_txn.insert_meta(DOC_NAME, "key", &[1]).unwrap();
+
+        let stats = db_txn.doc_stats(DOC_NAME).unwrap().unwrap();
+        // stored values are sealed with a checksum
+        let overhead = SEALED_HEADER_LEN as u64;
+        assert_eq!(stats.doc_state_bytes, doc_state.len() as u64 + overhead);
+        assert_eq!(stats.pending_updates, 2);
+        assert_eq!(stats.pending_update_bytes, update_bytes + 2 * overhead);
+        assert_eq!(stats.meta_entries, 1);
+        assert_eq!(stats.last_update_seq, Some(2));
+        assert_eq!(stats.last_flush_seq, None);
+    }
+
+    #[test]
+    fn iter_updates() {
+        const DOC_NAME: &str = "doc";
+        let cleaner = Cleaner::new("rocksdb-iter_updates");
+        let db = init_env(cleaner.dir());
+
+        let db_txn = RocksDBStore::from(db.transaction());
+        let doc = Doc::new();
+        let text = doc.get_or_insert_text("text");
+        let mut expected = Vec::new();
+        for (i, chunk) in ["a", "b", "c"].iter().enumerate() {
+            let sv = doc.transact().state_vector();
+            text.push(&mut doc.transact_mut(), chunk);
+            let v2 = i == 1;
+            let update = if v2 {
+                let update = doc.transact().encode_diff_v2(&sv);
+                db_txn.push_update_v2(DOC_NAME, &update).unwrap();
+                update
+            } else {
+                let update = doc.transact().encode_diff_v1(&sv);
+                db_txn.push_update(DOC_NAME, &update).unwrap();
+                update
+            };
+            expected.push((i as u32 + 1, update, v2));
+        }
+
+        let actual: Vec<_> = db_txn
+            .iter_updates(DOC_NAME)
+            .unwrap()
+            .map(|u| (u.clock, u.update.to_vec(), u.v2))
+            .collect();
+        assert_eq!(actual, expected);
+
+        db_txn.flush_doc(DOC_NAME).unwrap();
+        assert!(db_txn.iter_updates(DOC_NAME).unwrap().next().is_none());
+    }
+
+    #[test]
+    fn trim_updates() {
+        const DOC_NAME: &str = "doc";
+        let cleaner = Cleaner::new("rocksdb-trim_updates");
+        let db = init_env(cleaner.dir());
+
+        let db_txn = RocksDBStore::from(db.transaction());
+        let doc = Doc::new();
+        let text = doc.get_or_insert_text("text");
+        for chunk in ["a", "b", "c"] {
+            let sv = doc.transact().state_vector();
+            text.push(&mut doc.transact_mut(), chunk);
+            let update = doc.transact().encode_diff_v1(&sv);
+            db_txn.push_update(DOC_NAME, &update).unwrap();
+        }
+
+        db_txn.trim_updates(DOC_NAME, 2).unwrap();
+        let clocks: Vec<_> = db_txn
+            .iter_updates(DOC_NAME)
+            .unwrap()
+            .map(|u| u.clock)
+            .collect();
+        assert_eq!(clocks, vec![3]);
+
+        let update = doc.transact().encode_diff_v1(&StateVector::default());
+        assert_eq!(db_txn.push_update(DOC_NAME, &update).unwrap(), 4);
+    }
+
+    #[test]
+    fn multi_doc_batch() {
+        let cleaner = Cleaner::new("rocksdb-multi_doc_batch");
+        let db = init_env(cleaner.dir());
+
+        let doc = Doc::new();
+        let text = doc.get_or_insert_text("text");
+        text.push(&mut doc.transact_mut(), "hello");
+        let update = doc.transact().encode_diff_v1(&StateVector::default());
+
+        let mut batch = MultiDocBatch::new();
+        batch
+            .push_update("A", &update)
+            .push_update("B", &update)
+            .push_update("A", &update)
+            .insert_meta("index", "A", &[1])
+            .insert_meta("index", "B", &[2]);
+        let db_txn = RocksDBStore::from(db.transaction());
+        db_txn.commit_docs(batch).unwrap();
+        db_txn.commit().unwrap();
+
+        let db_txn = RocksDBStore::from(db.transaction());
+        let clocks: Vec<_> = db_txn.iter_updates("A").unwrap().map(|u| u.clock).collect();
+        assert_eq!(clocks, vec![1, 2]);
+        for name in ["A", "B"] {
+            let doc = Doc::new();
+            let text = doc.get_or_insert_text("text");
+            assert!(db_txn.load_doc(name, &mut doc.transact_mut()).unwrap());
+            assert_eq!(text.get_string(&doc.transact()), "hello");
+        }
+        let index: Vec<_> = db_txn.iter_meta("index").unwrap().collect();
+        assert_eq!(index.len(), 2);
+    }
+
+    #[test]
+    fn doc_expiry() {
+        let cleaner = Cleaner::new("rocksdb-doc_expiry");
+        let db = init_env(cleaner.dir());
+
+        let db_txn = RocksDBStore::from(db.transaction());
+        let doc = Doc::new();
+        let text = doc.get_or_insert_text("text");
+        text.push(&mut doc.transact_mut(), "hello");
+        for name in ["A", "B", "C"] {
+            db_txn.insert_doc(name, &doc.transact()).unwrap();
+        }
+        db_txn.set_doc_expiry("A", 100).unwrap();
+        db_txn.set_doc_expiry("B", 100).unwrap();
+        db_txn.set_doc_expiry("C", 300).unwrap();
+        // expiry can be extended
+        db_txn.set_doc_expiry("B", 200).unwrap();
+        assert_eq!(db_txn.get_doc_expiry("B").unwrap(), Some(200));
+        // renamed documents still expire
+        db_txn.rename_doc("C", "D").unwrap();
+
+        assert_eq!(db_txn.sweep_expired(50).unwrap(), 0);
+        assert_eq!(db_txn.sweep_expired(150).unwrap(), 1);
+        let docs: Vec<_> = db_txn.iter_docs().unwrap().collect();
+        assert_eq!(docs, vec!["B".as_bytes().into(), "D".as_bytes().into()]);
+
+        db_txn.remove_doc_expiry("B").unwrap();
+        assert_eq!(db_txn.get_doc_expiry("B").unwrap(), None);
+        assert_eq!(db_txn.sweep_expired(1000).unwrap(), 1);
+
+        let docs: Vec<_> = db_txn.iter_docs().unwrap().collect();
+        assert_eq!(docs, vec!["B".as_bytes().into()]);
+        db_txn.commit().unwrap();
+    }
+
+    #[test]
+    fn schema_migration() {
+        let cleaner = Cleaner::new("rocksdb-schema_migration");
+        let db = init_env(cleaner.dir());
+
+        let db_txn = RocksDBStore::from(db.transaction());
+        assert_eq!(db_txn.schema_version().unwrap(), SCHEMA_VERSION);
+        db_txn.migrate(SCHEMA_VERSION, SCHEMA_VERSION).unwrap();
+        assert_eq!(db_txn.schema_version().unwrap(), SCHEMA_VERSION);
+
+        // there's no schema version newer than the current one
+        assert!(db_txn.migrate(SCHEMA_VERSION, SCHEMA_VERSION + 1).is_err());
+        // database doesn't use requested schema version
+        assert!(db_txn
+            .migrate(SCHEMA_VERSION + 1, SCHEMA_VERSION + 2)
+            .is_err());
+        db_txn.commit().unwrap();
+    }
+
+    #[test]
+    fn sync_step() {
+        const DOC_NAME: &str = "doc";
+        let cleaner = Cleaner::new("rocksdb-sync_step");
+        let db = init_env(cleaner.dir());
+
+        let doc = Doc::new();
+        let text = doc.get_or_insert_text("text");
+        text.push(&mut doc.transact_mut(), "a");
+        let db_txn = RocksDBStore::from(db.transaction());
+        db_txn.insert_doc(DOC_NAME, &doc.transact()).unwrap();
+
+        // remote peer knows only about the stored document state
+        let remote = Doc::new();
+        let remote_text = remote.get_or_insert_text("text");
+        let state = doc.transact().encode_diff_v1(&StateVector::default());
+        remote
+            .transact_mut()
+            .apply_update(Update::decode_v1(&state).unwrap());
+        let remote_sv = remote.transact().state_vector();
+
+        // local changes which were not merged into document state yet
+        text.push(&mut doc.transact_mut(), "b");
+        let update = doc.transact().encode_diff_v1(&remote_sv);
+        db_txn.push_update(DOC_NAME, &update).unwrap();
+
+        let (diff, local_sv) = db_txn.sync_step(DOC_NAME, &remote_sv).unwrap().unwrap();
+        assert_eq!(local_sv, doc.transact().state_vector());
+        remote
+            .transact_mut()
+            .apply_update(Update::decode_v1(&diff).unwrap());
+        assert_eq!(remote_text.get_string(&remote.transact()), "ab");
+
+        let (diff_v2, local_sv_v2) = db_txn.sync_step_v2(DOC_NAME, &remote_sv).unwrap().unwrap();
+        assert_eq!(local_sv_v2, local_sv);
+        assert_eq!(Update::decode_v2(&diff_v2).unwrap().encode_v1(), diff);
+
+        assert!(db_txn.sync_step("none", &remote_sv).unwrap().is_none());
+        db_txn.commit().unwrap();
+    }
+
+    #[test]
+    fn doc_awareness() {
+        let cleaner = Cleaner::new("rocksdb-doc_awareness");
+        let db = init_env(cleaner.dir());
+
+        let db_txn = RocksDBStore::from(db.transaction());
+        assert!(db_txn.get_awareness("doc").unwrap().is_none());
+        let doc = Doc::new();
+        db_txn.insert_doc("doc", &doc.transact()).unwrap();
+        db_txn.insert_awareness("doc", &[1, 2, 3]).unwrap();
+        db_txn.insert_awareness("doc", &[4, 5]).unwrap();
+        let awareness = db_txn.get_awareness("doc").unwrap().map(Vec::from);
+        assert_eq!(awareness.as_deref(), Some(&[4u8, 5][..]));
+
+        // awareness state is not a part of document content
+        assert!(db_txn.copy_doc("doc", "copy", true).unwrap());
+        assert!(db_txn.get_awareness("copy").unwrap().is_none());
+
+        db_txn.remove_awareness("doc").unwrap();
+        assert!(db_txn.get_awareness("doc").unwrap().is_none());
+        db_txn.insert_awareness("doc", &[6]).unwrap();
+        db_txn.clear_doc("doc").unwrap();
+        assert!(db_txn.get_awareness("doc").unwrap().is_none());
+        db_txn.commit().unwrap();
+    }
+
+    #[test]
+    fn doc_iter_paged() {
+        let cleaner = Cleaner::new("rocksdb-doc_iter_paged");
+        let db = init_env(cleaner.dir());
+        let db_txn = RocksDBStore::from(db.transaction());
+        for name in ["A", "B", "C", "D", "E"] {
+            db_txn.insert_meta(name, "key", [1].as_ref()).unwrap();
+        }
+
+        let names = |page: Vec<(Box<[u8]>, _)>| -> Vec<Box<[u8]>> {
+            page.into_iter().map(|(name, _)| name).collect()
+        };
+        let page = db_txn.iter_docs_paged(None, 2).unwrap();
+        assert_eq!(page[0].1, 1);
+        assert_eq!(
+            names(page),
+            vec!["A".as_bytes().into(), "B".as_bytes().into()]
+        );
+        let page = db_txn.iter_docs_paged(Some("B".as_bytes()), 2).unwrap();
+        assert_eq!(
+            names(page),
+            vec!["C".as_bytes().into(), "D".as_bytes().into()]
+        );
+        let page = db_txn.iter_docs_paged(Some("D".as_bytes()), 2).unwrap();
+        assert_eq!(names(page), vec!["E".as_bytes().into()]);
+        assert!(db_txn
+            .iter_docs_paged(Some("E".as_bytes()), 2)
+            .unwrap()
+            .is_empty());
+
+        // page may start after a document, which no longer exists
+        db_txn.clear_doc("B").unwrap();
+        let page = db_txn.iter_docs_paged(Some("B".as_bytes()), 10).unwrap();
+        assert_eq!(
+            names(page),
+            vec![
+                "C".as_bytes().into(),
+                "D".as_bytes().into(),
+                "E".as_bytes().into()
+            ]
+        );
+        assert!(db_txn.iter_docs_paged(None, 0).unwrap().is_empty());
+        db_txn.commit().unwrap();
+    }
+
     #[test]
     fn doc_iter() {
         let cleaner = Cleaner::new("rocksdb-doc_iter");
@@ -526,4 +1527,96 @@ mod test {
             assert!(i.next().is_none());
         }
     }
+
+    #[test]
+    fn column_families_kvstore_contract() {
+        let cleaner = Cleaner::new("rocksdb-column_families_kvstore_contract");
+        let mut opts = Options::default();
+        opts.create_if_missing(true);
+        opts.create_missing_column_families(true);
+        let db: TransactionDB = TransactionDB::open_cf_descriptors(
+            &opts,
+            &TransactionDBOptions::default(),
+            cleaner.dir(),
+            column_families(),
+        )
+        .unwrap();
+        let oid_cf = db.cf_handle(OID_CF).unwrap();
+        let doc_cf = db.cf_handle(DOC_CF).unwrap();
+        let store = RocksDBCFStore::new(db.transaction(), oid_cf, doc_cf);
+        verify_kvstore_contract(&store);
+    }
+
+    #[test]
+    fn column_families_store() {
+        let cleaner = Cleaner::new("rocksdb-column_families_store");
+        let mut opts = Options::default();
+        opts.create_if_missing(true);
+        opts.create_missing_column_families(true);
+        let db: TransactionDB = TransactionDB::open_cf_descriptors(
+            &opts,
+            &TransactionDBOptions::default(),
+            cleaner.dir(),
+            column_families(),
+        )
+        .unwrap();
+        let oid_cf = db.cf_handle(OID_CF).unwrap();
+        let doc_cf = db.cf_handle(DOC_CF).unwrap();
+
+        let doc = Doc::new();
+        let text = doc.get_or_insert_text("text");
+        text.push(&mut doc.transact_mut(), "hello");
+
+        // insert documents
+        {
+            let db_txn = RocksDBCFStore::new(db.transaction(), oid_cf, doc_cf);
+            db_txn.insert_doc("A", &doc.transact()).unwrap();
+            db_txn.insert_meta("A", "key1", [1].as_ref()).unwrap();
+            let sv = doc.transact().state_vector();
+            text.push(&mut doc.transact_mut(), " world");
+            db_txn
+                .push_update("B", &doc.transact().encode_diff_v1(&StateVector::default()))
+                .unwrap();
+            db_txn
+                .push_update("A", &doc.transact().encode_diff_v1(&sv))
+                .unwrap();
+            db_txn.commit().unwrap();
+        }
+
+        // read documents
+        {
+            let db_txn = RocksDBCFStore::new(db.transaction(), oid_cf, doc_cf);
+            for name in ["A", "B"] {
+                let doc = Doc::new();
+                let text = doc.get_or_insert_text("text");
+                let mut txn = doc.transact_mut();
+                db_txn.load_doc(name, &mut txn).unwrap();
+                assert_eq!(text.get_string(&txn), "hello world");
+            }
+
+            let mut i = db_txn.iter_docs().unwrap();
+            assert_eq!(i.next(), Some("A".as_bytes().into()));
+            assert_eq!(i.next(), Some("B".as_bytes().into()));
+            assert!(i.next().is_none());
+
+            let mut i = db_txn.iter_meta("A").unwrap();
+            assert_eq!(i.next(), Some(("key1".as_bytes().into(), [1].into())));
+            assert!(i.next().is_none());
+        }
+
+        // flush and clear documents
+        {
+            let db_txn = RocksDBCFStore::new(db.transaction(), oid_cf, doc_cf);
+            db_txn.flush_doc("A").unwrap();
+            let (sv, completed) = db_txn.get_state_vector("A").unwrap();
+            assert_eq!(sv, Some(doc.transact().state_vector()));
+            assert!(completed);
+
+            db_txn.clear_doc("A").unwrap();
+            let mut i = db_txn.iter_docs().unwrap();
+            assert_eq!(i.next(), Some("B".as_bytes().into()));
+            assert!(i.next().is_none());
+            db_txn.commit().unwrap();
+        }
+    }
 }
@@ -0,0 +1,908 @@
+use sled::{Batch, IVec, Iter, Tree};
+use std::ops::Deref;
+use std::sync::Arc;
+use yrs_kvstore::batch::{BatchOp, WriteBatch};
+use yrs_kvstore::codec::{CodecChain, ValueCodec};
+use yrs_kvstore::compression::Compression;
+use yrs_kvstore::export::JsonExport;
+use yrs_kvstore::metrics::{Metrics, StoreMetrics};
+use yrs_kvstore::observer::{UpdateObservers, WriteHook};
+use yrs_kvstore::options::StoreOptions;
+use yrs_kvstore::validation::UpdateValidator;
+use yrs_kvstore::{
+    CompactionPolicy, DocOps, KVEntry, KVStore, OidAllocation, RetentionPolicy, UpdateLimits,
+};
+
+/// Persistence layer for Yrs documents build on top of sled [Tree]. Since sled is a pure Rust
+/// embedded database, it doesn't require any C dependencies to be present.
+///
+/// Every operation is executed directly over the provided tree.
+#[derive(Debug, Clone)]
+pub struct SledStore {
+    tree: Tree,
+    options: StoreOptions,
+    update_observers: UpdateObservers,
+    metrics: Metrics,
+}
+
+impl SledStore {
+    /// Sets [StoreOptions] used to configure current store, replacing all settings set so far.
+    ///
+    /// # Panics
+    ///
+    /// Panics if options can't be applied without wrapping the store with
+    /// [yrs_kvstore::options::ConfiguredStore] (see: [StoreOptions::assert_unscoped]).
+    pub fn with_options(mut self, options: StoreOptions) -> Self {
+        options.assert_unscoped();
+        self.options = options;
+        self
+    }
+
+    /// Sets a [CompactionPolicy] used to automatically flush documents, which have accumulated
+    /// too many pending updates.
+    pub fn with_compaction_policy(mut self, policy: CompactionPolicy) -> Self {
+        self.options.compaction_policy = policy;
+        self
+    }
+
+    /// Sets an [UpdateObservers] registry notified about every persisted document update (see:
+    /// [DocOps::observe_updates]). The same registry can be shared by multiple stores.
+    pub fn with_update_observers(mut self, observers: UpdateObservers) -> Self {
+        self.update_observers = observers;
+        self
+    }
+
+    /// Sets a [Compression] applied to document states and updates written by current store.
+    pub fn with_compression(mut self, compression: Compression) -> Self {
+        self.options.compression = compression;
+        self
+    }
+
+    /// Sets an [OidAllocation] strategy used to assign OIDs to documents persisted for the first
+    /// time by current store.
+    pub fn with_oid_allocation(mut self, allocation: OidAllocation) -> Self {
+        self.options.oid_allocation = allocation;
+        self
+    }
+
+    /// Enables or disables recording document changes in a global change log (see:
+    /// [DocOps::iter_changes]).
+    pub fn with_changelog(mut self, enabled: bool) -> Self {
+        self.options.changelog = enabled;
+        self
+    }
+
+    /// Sets a [Metrics] receiver, which current store reports counts and latencies of its
+    /// operations into (see: [DocOps::metrics]).
+    pub fn with_metrics(mut self, metrics: Metrics) -> Self {
+        self.metrics = metrics;
+        self
+    }
+
+    /// Sets a number of recently pushed updates of each document, which are remembered in order to
+    /// skip persisting byte-identical updates pushed again, i.e. by retrying clients (see:
+    /// [StoreOptions::update_dedup_window]). Deduplication is disabled when set to 0.
+    pub fn with_update_dedup(mut self, window: usize) -> Self {
+        self.options.update_dedup_window = window;
+        self
+    }
+
+    /// Sets a size in bytes, starting from which document states are stored only once in
+    /// a content addressed blob keyspace and referenced from their documents (see:
+    /// [StoreOptions::blob_threshold]). Blob storage is disabled when set to 0.
+    pub fn with_blob_threshold(mut self, threshold: usize) -> Self {
+        self.options.blob_threshold = threshold;
+        self
+    }
+
+    /// Sets a [ValueCodec] used to encode document states, updates and metadata persisted by
+    /// current store (see: [StoreOptions::codecs]). It replaces a [Compression] set on the store.
+    pub fn with_value_codec(mut self, codec: Arc<dyn ValueCodec>) -> Self {
+        self.options.codecs = CodecChain::from(codec);
+        self
+    }
+
+    /// Enables or disables moving documents removed by [DocOps::clear_doc] into a trash, from
+    /// which they can be restored (see: [StoreOptions::soft_delete]).
+    pub fn with_soft_delete(mut self, enabled: bool) -> Self {
+        self.options.soft_delete = enabled;
+        self
+    }
+
+    /// Enables or disables recording timestamps of pushed updates (see:
+    /// [StoreOptions::update_timestamps]).
+    pub fn with_update_timestamps(mut self, enabled: bool) -> Self {
+        self.options.update_timestamps = enabled;
+        self
+    }
+
+    /// Sets a [RetentionPolicy] used to determine which pending updates are merged into the
+    /// document state by [DocOps::enforce_retention].
+    pub fn with_retention_policy(mut self, policy: RetentionPolicy) -> Self {
+        self.options.retention_policy = policy;
+        self
+    }
+
+    /// Sets [UpdateLimits] used to reject oversized updates before they are written (see:
+    /// [StoreOptions::update_limits]).
+    pub fn with_update_limits(mut self, limits: UpdateLimits) -> Self {
+        self.options.update_limits = limits;
+        self
+    }
+
+    /// Sets an [UpdateValidator] called with every update and document state before it's
+    /// persisted, which can veto the write (see: [StoreOptions::update_validator]).
+    pub fn with_update_validator(mut self, validator: Arc<dyn UpdateValidator>) -> Self {
+        self.options.update_validator = Some(validator);
+        self
+    }
+
+    /// Sets a [WriteHook] called with a decoded summary of every change written by current store
+    /// (see: [StoreOptions::write_hook]).
+    pub fn with_write_hook(mut self, hook: Arc<dyn WriteHook>) -> Self {
+        self.options.write_hook = Some(hook);
+        self
+    }
+
+    /// Sets a [JsonExport] used to maintain a cached JSON rendering of documents refreshed every
+    /// time they are flushed (see: [StoreOptions::json_export]).
+    pub fn with_json_export(mut self, export: JsonExport) -> Self {
+        self.options.json_export = Some(Arc::new(export));
+        self
+    }
+}
+
+impl From<Tree> for SledStore {
+    #[inline(always)]
+    fn from(tree: Tree) -> Self {
+        SledStore {
+            tree,
+            options: StoreOptions::default(),
+            update_observers: UpdateObservers::default(),
+            metrics: Metrics::default(),
+        }
+    }
+}
+
+impl Into<Tree> for SledStore {
+    #[inline(always)]
+    fn into(self) -> Tree {
+        self.tree
+    }
+}
+
+impl Deref for SledStore {
+    type Target = Tree;
+
+    #[inline(always)]
+    fn deref(&self) -> &Self::Target {
+        &self.tree
+    }
+}
+
+impl<'a> DocOps<'a> for SledStore {
+    fn options(&self) -> &StoreOptions {
+        &self.options
+    }
+
+    fn update_observers(&self) -> Option<&UpdateObservers> {
+        Some(&self.update_observers)
+    }
+
+    fn metrics(&self) -> Option<&dyn StoreMetrics> {
+        self.metrics.get()
+    }
+}
+
+impl<'a> KVStore<'a> for SledStore {
+    type Error = sled::Error;
+    type Cursor = SledRange;
+    type Entry = SledEntry;
+    type Return = IVec;
+
+    fn get(&self, key: &[u8]) -> Result<Option<Self::Return>, Self::Error> {
+        self.tree.get(key)
+    }
+
+    fn upsert(&self, key: &[u8], value: &[u8]) -> Result<(), Self::Error> {
+        self.tree.insert(key, value)?;
+        Ok(())
+    }
+
+    fn remove(&self, key: &[u8]) -> Result<(), Self::Error> {
+        self.tree.remove(key)?;
+        Ok(())
+    }
+
+    fn remove_range(&self, from: &[u8], to: &[u8]) -> Result<(), Self::Error> {
+        let mut batch = Batch::default();
+        for res in self.tree.range(from..=to) {
+            let (key, _) = res?;
+            batch.remove(key);
+        }
+        self.tree.apply_batch(batch)
+    }
+
+    fn iter_range(&self, from: &[u8], to: &[u8]) -> Result<Self::Cursor, Self::Error> {
+        let inner = self.tree.range(from..=to);
+        Ok(SledRange(inner))
+    }
+
+    fn contains_range(&self, from: &[u8], to: &[u8]) -> Result<bool, Self::Error> {
+        match self.tree.range(from..=to).next() {
+            Some(res) => {
+                res?;
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    fn peek_back(&self, key: &[u8]) -> Result<Option<Self::Entry>, Self::Error> {
+        if let Some(res) = self.tree.range(..key).next_back() {
+            let (key, value) = res?;
+            Ok(Some(SledEntry::new(key, value)))
+        } else {
+            Ok(None)
+        }
+    }
+
+    fn commit_batch(&self, batch: WriteBatch) -> Result<(), Self::Error> {
+        let mut sled_batch = Batch::default();
+        // keys inserted by current batch, they are not visible to range scans yet
+        let mut inserted: Vec<Vec<u8>> = Vec::new();
+        for op in batch {
+            match op {
+                BatchOp::Upsert(key, value) => {
+                    sled_batch.insert(key.as_slice(), value);
+                    inserted.push(key);
+                }
+                BatchOp::Remove(key) => sled_batch.remove(key),
+                BatchOp::RemoveRange(from, to) => {
+                    for res in self.tree.range(from.as_slice()..=to.as_slice()) {
+                        let (key, _) = res?;
+                        sled_batch.remove(key);
+                    }
+                    for key in inserted.iter() {
+                        if key >= &from && key <= &to {
+                            sled_batch.remove(key.as_slice());
+                        }
+                    }
+                }
+            }
+        }
+        self.tree.apply_batch(sled_batch)
+    }
+}
+
+pub struct SledRange(Iter);
+
+impl Iterator for SledRange {
+    type Item = SledEntry;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (key, value) = self.0.next()?.ok()?;
+        Some(SledEntry::new(key, value))
+    }
+}
+
+pub struct SledEntry {
+    key: IVec,
+    value: IVec,
+}
+
+impl SledEntry {
+    fn new(key: IVec, value: IVec) -> Self {
+        SledEntry { key, value }
+    }
+}
+
+impl Into<(IVec, IVec)> for SledEntry {
+    fn into(self) -> (IVec, IVec) {
+        (self.key, self.value)
+    }
+}
+
+impl KVEntry for SledEntry {
+    fn key(&self) -> &[u8] {
+        &self.key
+    }
+
+    fn value(&self) -> &[u8] {
+        &self.value
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::SledStore;
+    use sled::Tree;
+    use yrs::updates::decoder::Decode;
+    use yrs::updates::encoder::Encode;
+    use yrs::{Doc, GetString, ReadTxn, StateVector, Text, Transact, TransactionMut, Update};
+    use yrs_kvstore::testing::properties::verify_key_ranges;
+    use yrs_kvstore::testing::verify_kvstore_contract;
+    use yrs_kvstore::{CompactionPolicy, DocOps, KVEntry, KVStore};
+
+    struct Cleaner(&'static str);
+
+    impl Cleaner {
+        fn new(dir: &'static str) -> Self {
+            Self::cleanup(dir);
+            Cleaner(dir)
+        }
+
+        fn dir(&self) -> &str {
+            self.0
+        }
+
+        fn cleanup(dir: &str) {
+            if let Err(_) = std::fs::remove_dir_all(dir) {
+                // if dir doesn't exists, ignore
+            }
+        }
+    }
+
+    impl Drop for Cleaner {
+        fn drop(&mut self) {
+            Self::cleanup(self.dir());
+        }
+    }
+
+    fn init_env(dir: &str) -> Tree {
+        let db = sled::open(dir).unwrap();
+        db.open_tree("yrs").unwrap()
+    }
+
+    #[test]
+    fn create_get_remove() {
+        let cleaner = Cleaner::new("sled-create_get_remove");
+        let db = init_env(cleaner.dir());
+
+        // insert document
+        {
+            let doc = Doc::new();
+            let text = doc.get_or_insert_text("text");
+            let mut txn = doc.transact_mut();
+            text.insert(&mut txn, 0, "hello");
+
+            let store = SledStore::from(db.clone());
+            store.insert_doc("doc", &txn).unwrap();
+        }
+
+        // retrieve document
+        {
+            let doc = Doc::new();
+            let text = doc.get_or_insert_text("text");
+            let mut txn = doc.transact_mut();
+            let store = SledStore::from(db.clone());
+            store.load_doc("doc", &mut txn).unwrap();
+
+            assert_eq!(text.get_string(&txn), "hello");
+
+            let (sv, completed) = store.get_state_vector("doc").unwrap();
+            assert_eq!(sv, Some(txn.state_vector()));
+            assert!(completed);
+        }
+
+        // remove document
+        {
+            let store = SledStore::from(db.clone());
+
+            store.clear_doc("doc").unwrap();
+
+            let doc = Doc::new();
+            let text = doc.get_or_insert_text("text");
+            let mut txn = doc.transact_mut();
+            store.load_doc("doc", &mut txn).unwrap();
+
+            assert_eq!(text.get_string(&txn), "");
+
+            let (sv, completed) = store.get_state_vector("doc").unwrap();
+            assert!(sv.is_none());
+            assert!(completed);
+        }
+    }
+    #[test]
+    fn multi_insert() {
+        let cleaner = Cleaner::new("sled-multi_insert");
+        let db = init_env(cleaner.dir());
+
+        // insert document twice
+        {
+            let doc = Doc::new();
+            let text = doc.get_or_insert_text("text");
+            let mut txn = doc.transact_mut();
+            text.push(&mut txn, "hello");
+
+            let store = SledStore::from(db.clone());
+
+            store.insert_doc("doc", &txn).unwrap();
+
+            text.push(&mut txn, " world");
+
+            store.insert_doc("doc", &txn).unwrap();
+        }
+
+        // retrieve document
+        {
+            let store = SledStore::from(db.clone());
+
+            let doc = Doc::new();
+            let text = doc.get_or_insert_text("text");
+            let mut txn = doc.transact_mut();
+            store.load_doc("doc", &mut txn).unwrap();
+
+            assert_eq!(text.get_string(&txn), "hello world");
+        }
+    }
+
+    #[test]
+    fn incremental_updates() {
+        const DOC_NAME: &str = "doc";
+        let cleaner = Cleaner::new("sled-incremental_updates");
+        let db = init_env(cleaner.dir());
+
+        // store document updates
+        {
+            let doc = Doc::new();
+            let text = doc.get_or_insert_text("text");
+
+            let db = db.clone();
+            doc.observe_update_v1("persist", move |_: &TransactionMut, u| {
+                let store = SledStore::from(db.clone());
+                store.push_update(DOC_NAME, &u.update).unwrap();
+            })
+            .unwrap();
+            // generate 3 updates
+            text.push(&mut doc.transact_mut(), "a");
+            text.push(&mut doc.transact_mut(), "b");
+            text.push(&mut doc.transact_mut(), "c");
+        }
+
+        // load document
+        {
+            let doc = Doc::new();
+            let text = doc.get_or_insert_text("text");
+            let mut txn = doc.transact_mut();
+
+            let store = SledStore::from(db.clone());
+            store.load_doc(DOC_NAME, &mut txn).unwrap();
+
+            assert_eq!(text.get_string(&txn), "abc");
+        }
+
+        // flush document
+        {
+            let store = SledStore::from(db.clone());
+            let doc = store.flush_doc(DOC_NAME).unwrap().unwrap();
+
+            let text = doc.get_or_insert_text("text");
+
+            assert_eq!(text.get_string(&doc.transact()), "abc");
+        }
+    }
+
+    #[test]
+    fn mixed_encoding_updates() {
+        const DOC_NAME: &str = "doc";
+        let cleaner = Cleaner::new("sled-mixed_encoding_updates");
+        let db = init_env(cleaner.dir());
+
+        let (sv, expected) = {
+            let doc = Doc::new();
+            let text = doc.get_or_insert_text("text");
+            text.push(&mut doc.transact_mut(), "a");
+
+            let store = SledStore::from(db.clone());
+            {
+                let txn = doc.transact();
+                let doc_state = txn.encode_diff_v2(&StateVector::default());
+                let state_vector = txn.state_vector().encode_v2();
+                store
+                    .insert_doc_raw_v2(DOC_NAME.as_bytes(), &doc_state, &state_vector)
+                    .unwrap();
+            }
+
+            let sv = doc.transact().state_vector();
+            text.push(&mut doc.transact_mut(), "b");
+            store
+                .push_update(DOC_NAME, &doc.transact().encode_diff_v1(&sv))
+                .unwrap();
+            let sv2 = doc.transact().state_vector();
+            text.push(&mut doc.transact_mut(), "c");
+            store
+                .push_update_v2(DOC_NAME, &doc.transact().encode_diff_v2(&sv2))
+                .unwrap();
+
+            let update = doc.transact().encode_diff_v2(&sv);
+            (sv, update)
+        };
+
+        // load document
+        {
+            let doc = Doc::new();
+            let text = doc.get_or_insert_text("text");
+            let mut txn = doc.transact_mut();
+
+            let store = SledStore::from(db.clone());
+            store.load_doc(DOC_NAME, &mut txn).unwrap();
+
+            assert_eq!(text.get_string(&txn), "abc");
+
+            // pending updates are merged without squashing their blocks, so updates are compared
+            // by the changes they carry instead of their binary representation
+            let actual = store.get_diff_v2(DOC_NAME, &sv).unwrap().unwrap();
+            let actual = Update::decode_v2(&actual).unwrap();
+            let expected = Update::decode_v2(&expected).unwrap();
+            assert_eq!(actual.insertions(true), expected.insertions(true));
+            assert_eq!(actual.delete_set(), expected.delete_set());
+        }
+
+        // flush document
+        {
+            let store = SledStore::from(db.clone());
+            let doc = store.flush_doc(DOC_NAME).unwrap().unwrap();
+
+            let text = doc.get_or_insert_text("text");
+            assert_eq!(text.get_string(&doc.transact()), "abc");
+        }
+    }
+
+    #[test]
+    fn batch_updates() {
+        const DOC_NAME: &str = "doc";
+        let cleaner = Cleaner::new("sled-batch_updates");
+        let db = init_env(cleaner.dir());
+
+        // buffer document updates
+        let updates = {
+            let doc = Doc::new();
+            let text = doc.get_or_insert_text("text");
+            let mut updates = Vec::new();
+            for chunk in ["a", "b", "c"] {
+                let sv = doc.transact().state_vector();
+                text.push(&mut doc.transact_mut(), chunk);
+                updates.push(doc.transact().encode_diff_v1(&sv));
+            }
+            updates
+        };
+
+        {
+            let store = SledStore::from(db.clone());
+            let last = store
+                .push_updates(DOC_NAME, updates.iter().map(Vec::as_slice))
+                .unwrap();
+            assert_eq!(last, 3);
+            let next = store.push_update(DOC_NAME, &updates[0]).unwrap();
+            assert_eq!(next, 4);
+        }
+
+        {
+            let doc = Doc::new();
+            let text = doc.get_or_insert_text("text");
+            let mut txn = doc.transact_mut();
+
+            let store = SledStore::from(db.clone());
+            store.load_doc(DOC_NAME, &mut txn).unwrap();
+
+            assert_eq!(text.get_string(&txn), "abc");
+        }
+    }
+
+    #[test]
+    fn auto_compaction() {
+        const DOC_NAME: &str = "doc";
+        let cleaner = Cleaner::new("sled-auto_compaction");
+        let db = init_env(cleaner.dir());
+        let policy = CompactionPolicy {
+            max_pending_updates: Some(2),
+            ..CompactionPolicy::default()
+        };
+
+        let doc = Doc::new();
+        let text = doc.get_or_insert_text("text");
+        let store = SledStore::from(db.clone()).with_compaction_policy(policy);
+        for chunk in ["a", "b"] {
+            let sv = doc.transact().state_vector();
+            text.push(&mut doc.transact_mut(), chunk);
+            store
+                .push_update(DOC_NAME, &doc.transact().encode_diff_v1(&sv))
+                .unwrap();
+        }
+        let (sv, completed) = store.get_state_vector(DOC_NAME).unwrap();
+        assert!(sv.is_none());
+        assert!(!completed);
+
+        // 3rd update exceeds the limit of pending updates
+        let sv = doc.transact().state_vector();
+        text.push(&mut doc.transact_mut(), "c");
+        store
+            .push_update(DOC_NAME, &doc.transact().encode_diff_v1(&sv))
+            .unwrap();
+        let (sv, completed) = store.get_state_vector(DOC_NAME).unwrap();
+        assert_eq!(sv, Some(doc.transact().state_vector()));
+        assert!(completed);
+    }
+
+    #[test]
+    fn state_vector_updates_only() {
+        const DOC_NAME: &str = "doc";
+        let cleaner = Cleaner::new("sled-state_vector_updates_only");
+        let db = init_env(cleaner.dir());
+
+        // store document updates
+        {
+            let doc = Doc::new();
+            let text = doc.get_or_insert_text("text");
+            let db = db.clone();
+            doc.observe_update_v1("persist", move |_: &TransactionMut, u| {
+                let store = SledStore::from(db.clone());
+                store.push_update(DOC_NAME, &u.update).unwrap();
+            })
+            .unwrap();
+            // generate 3 updates
+            text.push(&mut doc.transact_mut(), "a");
+            text.push(&mut doc.transact_mut(), "b");
+            text.push(&mut doc.transact_mut(), "c");
+
+            let sv = doc.transact().state_vector();
+            sv
+        };
+
+        let store = SledStore::from(db.clone());
+        let (sv, completed) = store.get_state_vector(DOC_NAME).unwrap();
+        assert!(sv.is_none());
+        assert!(!completed); // since it's not completed, we should recalculate state vector from doc state
+    }
+
+    #[test]
+    fn state_diff_from_updates() {
+        const DOC_NAME: &str = "doc";
+        let cleaner = Cleaner::new("sled-state_diff_from_updates");
+        let db = init_env(cleaner.dir());
+
+        let (sv, expected) = {
+            let doc = Doc::new();
+            let text = doc.get_or_insert_text("text");
+
+            let db = db.clone();
+            doc.observe_update_v1("persist", move |_: &TransactionMut, u| {
+                let store = SledStore::from(db.clone());
+                store.push_update(DOC_NAME, &u.update).unwrap();
+            })
+            .unwrap();
+
+            // generate 3 updates
+            text.push(&mut doc.transact_mut(), "a");
+            text.push(&mut doc.transact_mut(), "b");
+            let sv = doc.transact().state_vector();
+            text.push(&mut doc.transact_mut(), "c");
+            let update = doc.transact().encode_diff_v1(&sv);
+            (sv, update)
+        };
+
+        let store = SledStore::from(db.clone());
+        let actual = store.get_diff(DOC_NAME, &sv).unwrap();
+        assert_eq!(actual, Some(expected));
+    }
+
+    #[test]
+    fn state_diff_from_doc() {
+        const DOC_NAME: &str = "doc";
+        let cleaner = Cleaner::new("sled-state_diff_from_doc");
+        let db = init_env(cleaner.dir());
+
+        let (sv, expected) = {
+            let doc = Doc::new();
+            let text = doc.get_or_insert_text("text");
+            // generate 3 updates
+            text.push(&mut doc.transact_mut(), "a");
+            text.push(&mut doc.transact_mut(), "b");
+            let sv = doc.transact().state_vector();
+            text.push(&mut doc.transact_mut(), "c");
+            let update = doc.transact().encode_diff_v1(&sv);
+
+            let store = SledStore::from(db.clone());
+            store.insert_doc(DOC_NAME, &doc.transact()).unwrap();
+
+            (sv, update)
+        };
+
+        let store = SledStore::from(db.clone());
+        let actual = store.get_diff(DOC_NAME, &sv).unwrap();
+        assert_eq!(actual, Some(expected));
+    }
+
+    #[test]
+    fn doc_meta() {
+        const DOC_NAME: &str = "doc";
+        let cleaner = Cleaner::new("lmdb-doc_meta");
+        let db = init_env(cleaner.dir());
+
+        let store = SledStore::from(db.clone());
+        let value = store.get_meta(DOC_NAME, "key").unwrap();
+        assert!(value.is_none());
+        store
+            .insert_meta(DOC_NAME, "key", "value1".as_bytes())
+            .unwrap();
+
+        let store = SledStore::from(db.clone());
+        let prev = store.get_meta(DOC_NAME, "key").unwrap();
+        store
+            .insert_meta(DOC_NAME, "key", "value2".as_bytes())
+            .unwrap();
+        assert_eq!(prev.as_deref(), Some("value1".as_bytes()));
+
+        let store = SledStore::from(db.clone());
+        let prev = store.get_meta(DOC_NAME, "key").unwrap();
+        store.remove_meta(DOC_NAME, "key").unwrap();
+        assert_eq!(prev.as_deref(), Some("value2".as_bytes()));
+        let value = store.get_meta(DOC_NAME, "key").unwrap();
+        assert!(value.is_none());
+    }
+
+    #[test]
+    fn doc_meta_iter() {
+        let cleaner = Cleaner::new("sled-doc_meta_iter");
+        let db = init_env(cleaner.dir());
+        let store = SledStore::from(db.clone());
+
+        store.insert_meta("A", "key1", [1].as_ref()).unwrap();
+        store.insert_meta("B", "key2", [2].as_ref()).unwrap();
+        store.insert_meta("B", "key3", [3].as_ref()).unwrap();
+        store.insert_meta("C", "key4", [4].as_ref()).unwrap();
+
+        let mut i = store.iter_meta("B").unwrap();
+        assert_eq!(i.next(), Some(("key2".as_bytes().into(), [2].into())));
+        assert_eq!(i.next(), Some(("key3".as_bytes().into(), [3].into())));
+        assert!(i.next().is_none());
+    }
+
+    #[test]
+    fn doc_snapshots() {
+        const DOC_NAME: &str = "doc";
+        let cleaner = Cleaner::new("sled-doc_snapshots");
+        let db = init_env(cleaner.dir());
+
+        {
+            let doc = Doc::new();
+            let text = doc.get_or_insert_text("text");
+            text.push(&mut doc.transact_mut(), "a");
+
+            let store = SledStore::from(db.clone());
+            store.insert_doc(DOC_NAME, &doc.transact()).unwrap();
+            let snapshot = store.create_snapshot(DOC_NAME, "v1").unwrap();
+            assert!(snapshot.is_some());
+
+            let sv = doc.transact().state_vector();
+            text.push(&mut doc.transact_mut(), "b");
+            store
+                .push_update(DOC_NAME, &doc.transact().encode_diff_v1(&sv))
+                .unwrap();
+        }
+
+        {
+            let store = SledStore::from(db.clone());
+
+            let doc = Doc::new();
+            let text = doc.get_or_insert_text("text");
+            let mut txn = doc.transact_mut();
+            assert!(store.load_snapshot(DOC_NAME, "v1", &mut txn).unwrap());
+            assert_eq!(text.get_string(&txn), "a");
+
+            let mut i = store.iter_snapshots(DOC_NAME).unwrap();
+            assert_eq!(i.next(), Some("v1".as_bytes().into()));
+            assert!(i.next().is_none());
+        }
+
+        {
+            let store = SledStore::from(db.clone());
+            store.remove_snapshot(DOC_NAME, "v1").unwrap();
+            assert!(store.get_snapshot(DOC_NAME, "v1").unwrap().is_none());
+
+            let doc = Doc::new();
+            let text = doc.get_or_insert_text("text");
+            let mut txn = doc.transact_mut();
+            assert!(!store.load_snapshot(DOC_NAME, "v1", &mut txn).unwrap());
+            store.load_doc(DOC_NAME, &mut txn).unwrap();
+            assert_eq!(text.get_string(&txn), "ab");
+        }
+    }
+
+    #[test]
+    fn kvstore_contract() {
+        let cleaner = Cleaner::new("sled-kvstore_contract");
+        let store = SledStore::from(init_env(cleaner.dir()));
+        verify_kvstore_contract(&store);
+    }
+
+    #[test]
+    fn key_ranges() {
+        let cleaner = Cleaner::new("sled-key_ranges");
+        let store = SledStore::from(init_env(cleaner.dir()));
+        verify_key_ranges(&store);
+    }
+
+    #[test]
+    fn commit_batch() {
+        let cleaner = Cleaner::new("sled-commit_batch");
+        let store = SledStore::from(init_env(cleaner.dir()));
+        store.upsert(&[1], &[1]).unwrap();
+        store.upsert(&[2], &[2]).unwrap();
+
+        let mut batch = store.batch();
+        batch.upsert(&[3], &[3]);
+        batch.remove(&[1]);
+        batch.upsert(&[4], &[4]);
+        batch.remove_range(&[4], &[5]);
+        store.commit_batch(batch).unwrap();
+
+        let keys: Vec<_> = store
+            .iter_range(&[0], &[255])
+            .unwrap()
+            .map(|e| e.key().to_vec())
+            .collect();
+        assert_eq!(keys, vec![vec![2], vec![3]]);
+    }
+
+    #[test]
+    fn doc_iter() {
+        let cleaner = Cleaner::new("sled-doc_iter");
+        let db = init_env(cleaner.dir());
+
+        // insert metadata
+        {
+            let store = SledStore::from(db.clone());
+            store.insert_meta("A", "key1", [1].as_ref()).unwrap();
+        }
+
+        // insert full doc state
+        {
+            let doc = Doc::new();
+            let text = doc.get_or_insert_text("text");
+            let mut txn = doc.transact_mut();
+            text.push(&mut txn, "hello world");
+
+            let store = SledStore::from(db.clone());
+            store.insert_doc("B", &txn).unwrap();
+        }
+
+        // insert update
+        {
+            let doc = Doc::new();
+            let db = db.clone();
+            doc.observe_update_v1("persist", move |_: &TransactionMut, u| {
+                let store = SledStore::from(db.clone());
+                store.push_update("C", &u.update).unwrap();
+            })
+            .unwrap();
+            let text = doc.get_or_insert_text("text");
+            let mut txn = doc.transact_mut();
+            text.push(&mut txn, "hello world");
+        }
+
+        {
+            let store = SledStore::from(db.clone());
+            let mut i = store.iter_docs().unwrap();
+            assert_eq!(i.next(), Some("A".as_bytes().into()));
+            assert_eq!(i.next(), Some("B".as_bytes().into()));
+            assert_eq!(i.next(), Some("C".as_bytes().into()));
+            assert!(i.next().is_none());
+        }
+
+        // clear doc
+        {
+            let store = SledStore::from(db.clone());
+            store.clear_doc("B").unwrap();
+        }
+
+        {
+            let store = SledStore::from(db.clone());
+            let mut i = store.iter_docs().unwrap();
+            assert_eq!(i.next(), Some("A".as_bytes().into()));
+            assert_eq!(i.next(), Some("C".as_bytes().into()));
+            assert!(i.next().is_none());
+        }
+    }
+}
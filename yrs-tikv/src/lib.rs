@@ -0,0 +1,186 @@
+use async_trait::async_trait;
+use std::collections::VecDeque;
+use tikv_client::{KvPair, RawClient};
+use yrs_kvstore::range_kv::{RangeKv, RangeKvStore};
+
+/// Maximum number of entries TiKV returns from a single raw scan request.
+const SCAN_LIMIT: u32 = 10240;
+
+/// Persistence layer for Yrs documents build on top of TiKV raw key-value API.
+///
+/// ```rust,no_run
+/// use yrs::{Doc, Transact};
+/// use yrs_kvstore::asynchronous::AsyncDocOps;
+/// use yrs_tikv::{TikvClient, TikvStore};
+///
+/// async fn save(doc: &Doc) -> Result<(), yrs_kvstore::error::Error> {
+///     let client = TikvClient::connect(vec!["127.0.0.1:2379"]).await?;
+///     let store = TikvStore::new(client);
+///     store.insert_doc("doc", &doc.transact()).await
+/// }
+/// ```
+pub type TikvStore = RangeKvStore<TikvClient>;
+
+/// [RangeKv] implementation over TiKV [RawClient].
+#[derive(Clone)]
+pub struct TikvClient(RawClient);
+
+impl TikvClient {
+    /// Connects to TiKV cluster using addresses of its placement driver (PD) nodes.
+    pub async fn connect<S: Into<String>>(
+        pd_endpoints: Vec<S>,
+    ) -> Result<Self, tikv_client::Error> {
+        let client = RawClient::new(pd_endpoints).await?;
+        Ok(TikvClient(client))
+    }
+
+    /// Returns a reference to the underlying TiKV client.
+    pub fn raw(&self) -> &RawClient {
+        &self.0
+    }
+}
+
+impl From<RawClient> for TikvClient {
+    fn from(client: RawClient) -> Self {
+        TikvClient(client)
+    }
+}
+
+fn into_pair(kv: KvPair) -> (Vec<u8>, Vec<u8>) {
+    let (key, value) = kv.into();
+    (key.into(), value)
+}
+
+#[async_trait(?Send)]
+impl RangeKv for TikvClient {
+    type Error = tikv_client::Error;
+
+    async fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>, Self::Error> {
+        self.0.get(key.to_vec()).await
+    }
+
+    async fn put(&self, key: &[u8], value: &[u8]) -> Result<(), Self::Error> {
+        self.0.put(key.to_vec(), value.to_vec()).await
+    }
+
+    async fn scan(
+        &self,
+        from: &[u8],
+        to: &[u8],
+        limit: Option<u32>,
+        reverse: bool,
+    ) -> Result<Vec<(Vec<u8>, Vec<u8>)>, Self::Error> {
+        let mut from = from.to_vec();
+        let to = to.to_vec();
+        // raw client 0.3 can only scan forward: in reverse mode the whole range is scanned and
+        // only the last `limit` entries are kept
+        let keep = limit.map(|limit| limit as usize);
+        let mut result = VecDeque::new();
+        // TiKV limits the number of entries returned by a single request, so larger ranges are
+        // scanned page by page
+        loop {
+            let page_size = match keep {
+                Some(keep) if !reverse => (keep - result.len()).min(SCAN_LIMIT as usize) as u32,
+                _ => SCAN_LIMIT,
+            };
+            if page_size == 0 {
+                break;
+            }
+            let page = self.0.scan(from.clone()..to.clone(), page_size).await?;
+            let exhausted = page.len() < page_size as usize;
+            if let Some(last) = page.last() {
+                from = last.key().clone().into();
+                from.push(0);
+            }
+            result.extend(page.into_iter().map(into_pair));
+            if let Some(keep) = keep {
+                while result.len() > keep {
+                    result.pop_front();
+                }
+            }
+            if exhausted {
+                break;
+            }
+        }
+        if reverse {
+            Ok(result.into_iter().rev().collect())
+        } else {
+            Ok(result.into())
+        }
+    }
+
+    async fn delete_range(&self, from: &[u8], to: &[u8]) -> Result<(), Self::Error> {
+        self.0.delete_range(from.to_vec()..to.to_vec()).await
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{TikvClient, TikvStore};
+    use yrs::{Doc, GetString, ReadTxn, Text, Transact};
+    use yrs_kvstore::asynchronous::{AsyncDocOps, AsyncKVStore};
+    use yrs_kvstore::KVEntry;
+
+    /// Tests require a running TiKV cluster. Comma-separated addresses of its PD nodes can be
+    /// provided using `PD_ENDPOINTS` environment variable.
+    async fn init_store() -> TikvStore {
+        let endpoints =
+            std::env::var("PD_ENDPOINTS").unwrap_or_else(|_| "127.0.0.1:2379".to_string());
+        let endpoints: Vec<_> = endpoints.split(',').map(str::to_string).collect();
+        let client = TikvClient::connect(endpoints).await.unwrap();
+        let store = TikvStore::new(client);
+        store.remove_range(&[0], &[255]).await.unwrap();
+        store
+    }
+
+    #[tokio::test]
+    #[ignore]
+    async fn scan_and_peek_back() {
+        let store = init_store().await;
+        for i in 0u8..10 {
+            store.upsert(&[1, i], &[i]).await.unwrap();
+        }
+        let values: Vec<_> = store
+            .iter_range(&[1, 2], &[1, 5])
+            .await
+            .unwrap()
+            .map(|e| e.value().to_vec())
+            .collect();
+        assert_eq!(values, vec![vec![2], vec![3], vec![4], vec![5]]);
+
+        let last = store.peek_back(&[1, 7]).await.unwrap().unwrap();
+        assert_eq!(last.key(), &[1, 6]);
+
+        store.remove_range(&[1, 0], &[1, 8]).await.unwrap();
+        let remaining = store.iter_range(&[1], &[2]).await.unwrap().count();
+        assert_eq!(remaining, 1);
+    }
+
+    #[tokio::test]
+    #[ignore]
+    async fn create_get_remove() {
+        let store = init_store().await;
+        let doc = Doc::new();
+        let text = doc.get_or_insert_text("text");
+        text.push(&mut doc.transact_mut(), "hello");
+        store.insert_doc("doc", &doc.transact()).await.unwrap();
+
+        let sv = doc.transact().state_vector();
+        text.push(&mut doc.transact_mut(), " world");
+        let update = doc.transact().encode_diff_v1(&sv);
+        store.push_update("doc", &update).await.unwrap();
+
+        let loaded = Doc::new();
+        let loaded_text = loaded.get_or_insert_text("text");
+        let found = store
+            .load_doc("doc", &mut loaded.transact_mut())
+            .await
+            .unwrap();
+        assert!(found);
+        assert_eq!(loaded_text.get_string(&loaded.transact()), "hello world");
+
+        store.clear_doc("doc").await.unwrap();
+        let (sv, _) = store.get_state_vector("doc").await.unwrap();
+        assert!(sv.is_none());
+    }
+}